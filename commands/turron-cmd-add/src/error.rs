@@ -0,0 +1,52 @@
+use dotnet_semver::Range;
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum AddError {
+    #[error("Only NuGet package specifiers can be added to a project.")]
+    #[diagnostic(code(turron::add::invalid_package_spec))]
+    InvalidPackageSpec,
+
+    #[error("Failed to find a version for {0} that satisfied {1}")]
+    #[diagnostic(
+        code(turron::add::version_not_found),
+        help("Try running `turron view <id> versions`")
+    )]
+    VersionNotFound(String, Range),
+
+    #[error("--no-resolve needs a version or range to write, like `{0}@1.0.0`.")]
+    #[diagnostic(code(turron::add::no_version))]
+    NoVersion(String),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::add::invalid_package_spec",
+        cause: "`turron add` writes a `<PackageReference>`, which can only point at a package on a NuGet source. Paths and git specs can't be referenced that way.",
+        fixes: &["Pass a package id, optionally with a version or range, e.g. `Newtonsoft.Json@13.0.1`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::add::version_not_found",
+        cause: "The package exists, but none of its published versions satisfy the requested version or range.",
+        fixes: &[
+            "Run `turron view <id> versions` to see what's available.",
+            "Widen the range, or drop it to get the latest version.",
+            "Pass `--no-resolve` to write the range as-is, without checking the source.",
+        ],
+        config: &["commands.add.source"],
+    },
+    Explanation {
+        code: "turron::add::no_version",
+        cause: "`--no-resolve` writes whatever version or range was typed into the project without asking the source, so there has to be one.",
+        fixes: &[
+            "Add a version or range to the spec, e.g. `Newtonsoft.Json@[13.0.1, )`.",
+            "Drop `--no-resolve` to use the latest version on the source.",
+        ],
+        config: &["commands.add.no_resolve"],
+    },
+];
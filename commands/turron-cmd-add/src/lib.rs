@@ -0,0 +1,336 @@
+use std::env;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use dotnet_semver::{Range, Version};
+use nuget_api::{v3::NuGetClient, SourceProtocol};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    owo_colors::{colors::*, OwoColorize},
+    render::sanitize,
+    turron_config::{SourcePolicy, TurronConfigLayer},
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json},
+    smol::{self, fs},
+    tracing,
+};
+use turron_dotnet::{DotnetError, ReferenceChange};
+use turron_package_spec::PackageSpec;
+use turron_pick_version::VersionPicker;
+use zip::ZipArchive;
+
+pub use error::{AddError, EXPLANATIONS};
+
+mod error;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "add"]
+pub struct AddCmd {
+    #[clap(about = "Package spec to add, like `Newtonsoft.Json` or `Newtonsoft.Json@[13.0.1, )`")]
+    package: String,
+    #[clap(from_global)]
+    root: Option<PathBuf>,
+    #[clap(
+        about = "Use the highest version that satisfies the range, instead of the lowest like NuGet",
+        long
+    )]
+    latest: bool,
+    #[clap(
+        about = "Write the version or range as typed, without looking anything up on the source",
+        long,
+        conflicts_with = "latest"
+    )]
+    no_resolve: bool,
+    #[clap(
+        about = "Source to resolve versions against",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Add the package even if the source's package patterns don't allow it",
+        long
+    )]
+    ignore_source_policy: bool,
+    #[clap(skip)]
+    #[config_layer(source_policy)]
+    source_policy: SourcePolicy,
+    #[clap(
+        about = "Write a PrivateAssets attribute, like `all` for analyzers and build-only packages. Pass an empty value to remove it",
+        long,
+        value_name = "ASSETS"
+    )]
+    private_assets: Option<String>,
+    #[clap(
+        about = "Write an IncludeAssets attribute, like `runtime; build`. Pass an empty value to remove it",
+        long,
+        value_name = "ASSETS"
+    )]
+    include_assets: Option<String>,
+    #[clap(
+        about = "Write an ExcludeAssets attribute, like `compile`. Pass an empty value to remove it",
+        long,
+        value_name = "ASSETS"
+    )]
+    exclude_assets: Option<String>,
+    #[clap(
+        about = "Default to `--private-assets all` for tools and analyzers, going by the package's types and contents",
+        long
+    )]
+    detect_dev: bool,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for AddCmd {
+    async fn execute(self) -> Result<()> {
+        let package = self.package.parse()?;
+        let (package_id, requested) = if let PackageSpec::NuGet { name, requested } = &package {
+            (name, requested.clone())
+        } else {
+            return Err(AddError::InvalidPackageSpec.into());
+        };
+        self.source_policy
+            .enforce(package_id, &self.source, self.ignore_source_policy)?;
+
+        let start = env::current_dir()
+            .into_diagnostic()
+            .context("Failed to get the current directory")?
+            .join(self.root.clone().unwrap_or_default());
+        let project = smol::unblock(move || turron_dotnet::find_project(&start)).await?;
+        let found = project.clone();
+        if let Some(props) =
+            smol::unblock(move || turron_dotnet::central_package_versions(&found)).await
+        {
+            return Err(DotnetError::CentrallyManaged { project, props }.into());
+        }
+
+        let client = if self.no_resolve && !self.detect_dev {
+            None
+        } else {
+            Some(
+                NuGetClient::from_source_as(
+                    self.source.clone(),
+                    self.assume_source_version.unwrap_or_default(),
+                )
+                .await?,
+            )
+        };
+        let version = match &client {
+            Some(client) if !self.no_resolve => self.resolve(client, package_id, requested).await?,
+            // The parsed range gets normalized, but people should get back
+            // exactly what they typed.
+            _ => self
+                .package
+                .splitn(2, '@')
+                .nth(1)
+                .map(|raw| raw.trim().to_string())
+                .ok_or_else(|| AddError::NoVersion(package_id.clone()))?,
+        };
+        let detected_dev = match &client {
+            Some(client) if self.detect_dev && self.private_assets.is_none() => {
+                is_dev_package(client, package_id, &version).await?
+            }
+            _ => false,
+        };
+        let private_assets = if detected_dev {
+            Some("all".to_string())
+        } else {
+            self.private_assets.clone()
+        };
+        let metadata = [
+            ("PrivateAssets", &private_assets),
+            ("IncludeAssets", &self.include_assets),
+            ("ExcludeAssets", &self.exclude_assets),
+        ]
+        .iter()
+        .filter_map(|(name, value)| {
+            value.as_deref().map(|value| {
+                let value = value.trim();
+                (*name, if value.is_empty() { None } else { Some(value) })
+            })
+        })
+        .collect::<Vec<_>>();
+
+        let contents = fs::read_to_string(&project)
+            .await
+            .into_diagnostic()
+            .with_context(|| format!("Failed to read {}", project.display()))?;
+        let (edited, change) =
+            turron_dotnet::set_package_reference(&project, &contents, package_id, &version)?;
+        let edited = if metadata.is_empty() {
+            edited
+        } else {
+            turron_dotnet::set_reference_metadata(&project, &edited, package_id, &metadata)?
+                .map(|(edited, _)| edited)
+                .expect("TURRON BUG: the reference was just written")
+        };
+        if edited != contents {
+            fs::write(&project, edited)
+                .await
+                .into_diagnostic()
+                .with_context(|| format!("Failed to write {}", project.display()))?;
+        }
+
+        if self.json && !self.quiet {
+            let (change_name, previous) = match &change {
+                ReferenceChange::Added => ("added", None),
+                ReferenceChange::Updated { from } => ("updated", Some(from.clone())),
+                ReferenceChange::Unchanged => ("unchanged", Some(version.clone())),
+            };
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({
+                    "id": package_id,
+                    "version": version,
+                    "file": project,
+                    "change": change_name,
+                    "previous": previous,
+                    "metadata": metadata
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), json!(value)))
+                        .collect::<serde_json::Map<_, _>>(),
+                    "detected_dev": detected_dev,
+                }))
+                .into_diagnostic()
+                .context("Failed to serialize add output into JSON")?
+            );
+        } else if !self.quiet {
+            let id = sanitize(package_id);
+            let file = project.display();
+            match change {
+                ReferenceChange::Added => println!(
+                    "Added {} {} to {}",
+                    id.fg::<BrightCyan>(),
+                    sanitize(&version).fg::<Green>(),
+                    file
+                ),
+                ReferenceChange::Updated { from } => println!(
+                    "Updated {} from {} to {} in {}",
+                    id.fg::<BrightCyan>(),
+                    sanitize(&from).fg::<Yellow>(),
+                    sanitize(&version).fg::<Green>(),
+                    file
+                ),
+                ReferenceChange::Unchanged => println!(
+                    "{} is already at {} in {}",
+                    id.fg::<BrightCyan>(),
+                    sanitize(&version).fg::<Green>(),
+                    file
+                ),
+            }
+            if detected_dev {
+                println!("It looks like a tool or analyzer, so its assets are kept private.");
+            }
+            for (name, value) in &metadata {
+                match value {
+                    Some(value) => println!("  {}: {}", name, sanitize(value).fg::<Green>()),
+                    None => println!("  {}: {}", name, "removed".fg::<Yellow>()),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AddCmd {
+    /// The version of `package_id` to reference: the lowest one satisfying
+    /// `requested` like NuGet picks, or the highest with `--latest`. Without
+    /// a range, that's the latest stable version either way.
+    async fn resolve(
+        &self,
+        client: &NuGetClient,
+        package_id: &str,
+        requested: Option<Range>,
+    ) -> Result<String> {
+        let requested = requested.unwrap_or_else(Range::any_floating);
+        let versions = client.versions(package_id).await?;
+        let picker = if self.latest {
+            VersionPicker::new_floating_only()
+        } else {
+            VersionPicker::new()
+        };
+        let version = picker
+            .pick_version(&requested, &versions[..])
+            .ok_or_else(|| AddError::VersionNotFound(package_id.into(), requested.clone()))?;
+        Ok(version.to_string())
+    }
+}
+
+/// Package types that mean a package is only needed while building.
+const DEV_PACKAGE_TYPES: &[&str] = &["DotnetTool", "DotnetCliTool"];
+
+/// Whether `package_id` at `version` looks like something only needed while
+/// building, like a tool or an analyzer: its nuspec says it's a tool, or
+/// one of its package types mentions analyzers, or it ships anything under
+/// `analyzers/`. Versions that aren't exact (with `--no-resolve`) can't be
+/// looked up, so they never count.
+async fn is_dev_package(client: &NuGetClient, package_id: &str, version: &str) -> Result<bool> {
+    let version = match version.parse::<Version>() {
+        Ok(version) => version,
+        Err(_) => {
+            tracing::warn!(
+                "--detect-dev needs an exact version to look at, and {} isn't one. Skipping it.",
+                version
+            );
+            return Ok(false);
+        }
+    };
+    let nuspec = client.nuspec(package_id, &version).await?;
+    let dev_type = nuspec
+        .metadata
+        .package_types
+        .iter()
+        .flat_map(|types| types.package_types.iter())
+        .any(|package_type| {
+            DEV_PACKAGE_TYPES
+                .iter()
+                .any(|dev| package_type.name.eq_ignore_ascii_case(dev))
+                || package_type.name.to_lowercase().contains("analyzer")
+        });
+    if dev_type {
+        return Ok(true);
+    }
+    let nupkg = client.nupkg(package_id, &version).await?;
+    smol::unblock(move || has_analyzers(&nupkg)).await
+}
+
+/// Whether the package in `nupkg` has anything under `analyzers/`.
+fn has_analyzers(nupkg: &[u8]) -> Result<bool> {
+    let zip = ZipArchive::new(Cursor::new(nupkg))
+        .into_diagnostic()
+        .context("Failed to read package contents")?;
+    let found = zip
+        .file_names()
+        .any(|name| name.to_lowercase().starts_with("analyzers/"));
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_testing::NupkgBuilder;
+
+    use super::*;
+
+    #[test]
+    fn finds_analyzers() {
+        let analyzer = NupkgBuilder::new("Turron.Analyzers", "1.0.0")
+            .file("analyzers/dotnet/cs/Turron.Analyzers.dll", "")
+            .build();
+        assert!(has_analyzers(&analyzer).unwrap());
+        let library = NupkgBuilder::new("Turron.Lib", "1.0.0")
+            .file("lib/net6.0/Turron.Lib.dll", "")
+            .build();
+        assert!(!has_analyzers(&library).unwrap());
+    }
+}
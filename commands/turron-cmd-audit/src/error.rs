@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use nuget_api::v3::Severity;
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Debug, Diagnostic, Error)]
+pub enum AuditError {
+    #[error("Only NuGet package specifiers are acceptable, like `Foo@1.2.3`.")]
+    #[diagnostic(code(turron::audit::invalid_package_spec))]
+    InvalidPackageSpec,
+
+    #[error("No project files found under {}.", .0.display())]
+    #[diagnostic(
+        code(turron::audit::no_projects),
+        help("Run this from a directory with .csproj, .fsproj, or .vbproj files under it, pass --root, or name packages to audit directly.")
+    )]
+    NoProjects(PathBuf),
+
+    #[error("Found {count} vulnerabilities of {severity} severity or higher.")]
+    #[diagnostic(
+        code(turron::audit::vulnerable),
+        help("Upgrade to the fixed versions listed above, or acknowledge advisories that don't affect you in turron-suppressions.kdl.")
+    )]
+    Vulnerable { count: usize, severity: Severity },
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::audit::invalid_package_spec",
+        cause: "`turron audit` only looks up NuGet packages, so specs pointing anywhere else can't be audited.",
+        fixes: &["Use `<id>` or `<id>@<version or range>`, like `Newtonsoft.Json@12.0.3`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::audit::no_projects",
+        cause: "Without any packages named on the command line, `turron audit` checks the `<PackageReference>`s in every project file under the current directory (or --root), skipping anything git ignores and `bin`/`obj` directories, and didn't find any project files.",
+        fixes: &[
+            "Run turron from your repository or solution directory.",
+            "Pass `--root` with a project file, or a directory containing some.",
+            "Name the packages to audit, like `turron audit Newtonsoft.Json@12.0.3`.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::audit::vulnerable",
+        cause: "Some audited package versions have known vulnerabilities at or above the `--severity` threshold (low, by default), and `turron-suppressions.kdl` doesn't acknowledge them. Expired suppressions don't count.",
+        fixes: &[
+            "Upgrade to the version listed as fixing each advisory.",
+            "If an advisory doesn't affect how you use the package, add an `advisory` block with a `reason` (and ideally `expires`) to turron-suppressions.kdl.",
+            "Raise `--severity` to only fail on more serious vulnerabilities.",
+        ],
+        config: &["commands.audit.severity"],
+    },
+];
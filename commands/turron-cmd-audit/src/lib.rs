@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use nuget_api::{
+    schedule::{self, Priority},
+    v3::{NuGetClient, RegistrationLeaf, Severity},
+    NuGetApiError, SourceProtocol,
+};
+use serde::Serialize;
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    owo_colors::{colors::*, OwoColorize},
+    render::sanitize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    chrono::Utc,
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json,
+    smol::{self, fs},
+    tracing,
+};
+use turron_package_spec::PackageSpec;
+use turron_suppressions::Suppressions;
+
+pub use error::{AuditError, EXPLANATIONS};
+use report::{PackageAudit, Status};
+
+mod error;
+mod report;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "audit"]
+pub struct AuditCmd {
+    #[clap(about = "Packages to audit, like `Foo@1.2.3`, instead of the project's references")]
+    specs: Vec<String>,
+    #[clap(from_global)]
+    root: Option<PathBuf>,
+    #[clap(
+        about = "Lowest severity that fails the audit: low, moderate, high, or critical",
+        default_value = "low",
+        long
+    )]
+    severity: Severity,
+    #[clap(about = "Ignore turron-suppressions.kdl", long)]
+    no_suppressions: bool,
+    #[clap(
+        about = "Source to look up vulnerability data on",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+/// A package reference to audit, along with everywhere it was referenced.
+#[derive(Debug)]
+struct Target {
+    id: String,
+    requested: Option<String>,
+    referenced_by: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditReport<'a> {
+    #[serde(serialize_with = "report::display")]
+    severity: Severity,
+    packages: &'a [PackageAudit],
+}
+
+#[async_trait]
+impl TurronCommand for AuditCmd {
+    async fn execute(self) -> Result<()> {
+        let root = env::current_dir()
+            .into_diagnostic()
+            .context("Failed to get the current directory")?
+            .join(self.root.clone().unwrap_or_default());
+        let targets = if self.specs.is_empty() {
+            project_targets(&root).await?
+        } else {
+            self.spec_targets()?
+        };
+        let mut ids: Vec<String> = Vec::new();
+        for target in &targets {
+            if !ids.iter().any(|id| id.eq_ignore_ascii_case(&target.id)) {
+                ids.push(target.id.clone());
+            }
+        }
+
+        let client = Arc::new(
+            NuGetClient::from_source_as(
+                self.source.clone(),
+                self.assume_source_version.unwrap_or_default(),
+            )
+            .await?,
+        );
+        // Sources that keep vulnerability data say so in their service
+        // index. Anywhere else, a version without any tells us nothing.
+        let tracked = client
+            .resources
+            .iter()
+            .any(|resource| resource.restype.starts_with("VulnerabilityInfo/"));
+        let leaves = fetch_leaves(client, ids).await;
+
+        let suppressions = if self.no_suppressions {
+            None
+        } else {
+            let dir = if root.is_file() {
+                root.parent().map(Path::to_path_buf).unwrap_or_default()
+            } else {
+                root.clone()
+            };
+            Some(smol::unblock(move || Suppressions::load(&dir)).await?)
+        };
+        let today = Utc::today().naive_utc();
+        let audits = targets
+            .into_iter()
+            .map(|target| {
+                let mut audit = PackageAudit::new(
+                    &target.id,
+                    target.requested.as_deref(),
+                    leaves.get(&target.id.to_lowercase()).map(|l| &l[..]),
+                    tracked,
+                );
+                audit.referenced_by = target.referenced_by;
+                if let Some(suppressions) = &suppressions {
+                    audit.suppress(suppressions, today);
+                }
+                audit
+            })
+            .collect::<Vec<_>>();
+
+        if self.json && !self.quiet {
+            let report = AuditReport {
+                severity: self.severity,
+                packages: &audits,
+            };
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report)
+                    .into_diagnostic()
+                    .context("Failed to serialize audit report into JSON")?
+            );
+        } else if !self.quiet {
+            print_report(&audits, self.severity);
+        }
+
+        let count = audits
+            .iter()
+            .flat_map(|audit| &audit.vulnerabilities)
+            .filter(|finding| finding.counts(self.severity))
+            .count();
+        if count > 0 {
+            return Err(AuditError::Vulnerable {
+                count,
+                severity: self.severity,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl AuditCmd {
+    /// Targets named on the command line. A bare id audits the latest
+    /// version.
+    fn spec_targets(&self) -> Result<Vec<Target>> {
+        let mut targets = Vec::new();
+        for spec in &self.specs {
+            let (id, requested) = match spec.parse()? {
+                PackageSpec::NuGet { name, requested } => (name, requested),
+                _ => return Err(AuditError::InvalidPackageSpec.into()),
+            };
+            let requested = requested.map_or_else(|| "*".into(), |range| range.to_string());
+            add_target(&mut targets, id, Some(requested), "command line".into());
+        }
+        Ok(targets)
+    }
+}
+
+/// Targets for every `<PackageReference>` in the projects at or under
+/// `root`.
+async fn project_targets(root: &Path) -> Result<Vec<Target>> {
+    let projects = if root.is_file() {
+        vec![root.to_path_buf()]
+    } else {
+        let root = root.to_path_buf();
+        smol::unblock(move || turron_dotnet::project_files(&root)).await
+    };
+    if projects.is_empty() {
+        return Err(AuditError::NoProjects(root.to_path_buf()).into());
+    }
+    let mut targets = Vec::new();
+    for project in projects {
+        let contents = fs::read_to_string(&project)
+            .await
+            .into_diagnostic()
+            .with_context(|| format!("Failed to read {}", project.display()))?;
+        for reference in turron_dotnet::package_references(&project, &contents)? {
+            add_target(
+                &mut targets,
+                reference.id,
+                reference.version,
+                relative(root, &project),
+            );
+        }
+    }
+    Ok(targets)
+}
+
+/// Adds a target, folding it into an existing one for the same package and
+/// request.
+fn add_target(
+    targets: &mut Vec<Target>,
+    id: String,
+    requested: Option<String>,
+    referenced_by: String,
+) {
+    let existing = targets
+        .iter_mut()
+        .find(|target| target.id.eq_ignore_ascii_case(&id) && target.requested == requested);
+    match existing {
+        Some(target) => {
+            if !target.referenced_by.contains(&referenced_by) {
+                target.referenced_by.push(referenced_by);
+            }
+        }
+        None => targets.push(Target {
+            id,
+            requested,
+            referenced_by: vec![referenced_by],
+        }),
+    }
+}
+
+/// Fetches every registration leaf of each of `ids`, keyed by lowercased
+/// id. They're all asked for at once, and the request scheduler decides how
+/// many are actually in flight. Packages that can't be found, or fail to
+/// load, are left out with a warning.
+async fn fetch_leaves(
+    client: Arc<NuGetClient>,
+    ids: Vec<String>,
+) -> HashMap<String, Vec<RegistrationLeaf>> {
+    let tasks = ids
+        .into_iter()
+        .map(|id| {
+            let client = client.clone();
+            smol::spawn(schedule::prioritized(Priority::Bulk, async move {
+                match client.registration_leaves(&id).await {
+                    Ok(leaves) => Some((id.to_lowercase(), leaves)),
+                    Err(NuGetApiError::PackageNotFound) => {
+                        tracing::warn!("{} wasn't found on the source", id);
+                        None
+                    }
+                    Err(err) => {
+                        tracing::warn!("Failed to get the registration of {}: {}", id, err);
+                        None
+                    }
+                }
+            }))
+        })
+        .collect::<Vec<_>>();
+    let mut leaves = HashMap::new();
+    for task in tasks {
+        leaves.extend(task.await);
+    }
+    leaves
+}
+
+/// `project`'s path relative to `root`, with `/` separators, or just its
+/// name when `root` is the project itself.
+fn relative(root: &Path, project: &Path) -> String {
+    let relative = project.strip_prefix(root).unwrap_or(project);
+    let relative = if relative.as_os_str().is_empty() {
+        project.file_name().map(Path::new).unwrap_or(project)
+    } else {
+        relative
+    };
+    relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Prints vulnerable packages with their advisories, then anything that
+/// couldn't be checked, then a summary.
+fn print_report(audits: &[PackageAudit], threshold: Severity) {
+    let version = |audit: &PackageAudit| {
+        audit
+            .version
+            .as_ref()
+            .map(|v| v.to_string())
+            .or_else(|| audit.requested.clone())
+            .unwrap_or_else(|| "-".into())
+    };
+    let vulnerable = audits
+        .iter()
+        .filter(|audit| audit.status == Status::Vulnerable)
+        .collect::<Vec<_>>();
+    let unknown = audits
+        .iter()
+        .filter(|audit| audit.status == Status::Unknown)
+        .collect::<Vec<_>>();
+    for audit in &vulnerable {
+        println!(
+            "{} ({})",
+            sanitize(&format!("{}@{}", audit.id, version(audit))).bold(),
+            sanitize(&audit.referenced_by.join(", "))
+        );
+        for finding in &audit.vulnerabilities {
+            let severity = format!("{:8}", finding.severity.to_string());
+            let severity = match finding.severity {
+                Severity::Critical | Severity::High => severity.fg::<Red>().to_string(),
+                Severity::Moderate => severity.fg::<Yellow>().to_string(),
+                Severity::Low => severity,
+            };
+            let fix = match &finding.fixed_in {
+                Some(version) => format!("fixed in {}", version),
+                None => "no fixed version yet".into(),
+            };
+            let mut line = format!(
+                "    {} {} ({})",
+                severity,
+                sanitize(&finding.advisory_url),
+                fix
+            );
+            if let Some(suppressed) = &finding.suppressed {
+                let note = if suppressed.expired {
+                    format!("suppression expired: {}", suppressed.reason)
+                } else {
+                    format!("suppressed: {}", suppressed.reason)
+                };
+                line.push_str(&format!(" [{}]", sanitize(&note)).dimmed().to_string());
+            } else if finding.severity < threshold {
+                line.push_str(&" [below threshold]".dimmed().to_string());
+            }
+            println!("{}", line);
+        }
+    }
+    if !unknown.is_empty() {
+        if !vulnerable.is_empty() {
+            println!();
+        }
+        println!("Couldn't check:");
+        for audit in &unknown {
+            println!(
+                "    {}: {}",
+                sanitize(&format!("{}@{}", audit.id, version(audit))),
+                audit.reason.as_deref().unwrap_or("unknown")
+            );
+        }
+    }
+    if !vulnerable.is_empty() || !unknown.is_empty() {
+        println!();
+    }
+    let clean = audits.len() - vulnerable.len() - unknown.len();
+    if vulnerable.is_empty() && unknown.is_empty() {
+        println!("No known vulnerabilities in {} packages.", audits.len());
+    } else {
+        println!(
+            "Audited {} packages: {} vulnerable, {} unknown, {} clean.",
+            audits.len(),
+            vulnerable.len().to_string().fg::<Red>(),
+            unknown.len().to_string().fg::<Yellow>(),
+            clean
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+
+use dotnet_semver::{Range, Version};
+use nuget_api::v3::{RegistrationLeaf, Severity, Vulnerability};
+use serde::{Serialize, Serializer};
+use turron_common::chrono::NaiveDate;
+use turron_pick_version::VersionPicker;
+use turron_suppressions::{FindingStatus, Suppressions};
+
+/// How one package came out of the audit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Clean,
+    Vulnerable,
+    /// There was nothing to check it against, so it didn't pass either.
+    Unknown,
+}
+
+/// One vulnerability affecting an audited package.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Finding {
+    pub(crate) advisory_url: String,
+    #[serde(serialize_with = "display")]
+    pub(crate) severity: Severity,
+    /// The first later version that isn't affected, if there is one.
+    pub(crate) fixed_in: Option<Version>,
+    /// Set when `turron-suppressions.kdl` acknowledges this advisory.
+    pub(crate) suppressed: Option<Suppressed>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Suppressed {
+    pub(crate) reason: String,
+    pub(crate) expires: Option<NaiveDate>,
+    /// Expired suppressions are reported, but don't stop the finding from
+    /// counting.
+    pub(crate) expired: bool,
+}
+
+impl Finding {
+    /// Whether this finding should fail the audit at `threshold`.
+    pub(crate) fn counts(&self, threshold: Severity) -> bool {
+        self.severity >= threshold && self.suppressed.as_ref().map_or(true, |s| s.expired)
+    }
+}
+
+/// What the audit found for one package reference.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PackageAudit {
+    pub(crate) id: String,
+    /// The version or range that was asked for.
+    pub(crate) requested: Option<String>,
+    /// What `requested` resolves to, picked the way NuGet would.
+    pub(crate) version: Option<Version>,
+    pub(crate) status: Status,
+    /// Why the status is [`Status::Unknown`].
+    pub(crate) reason: Option<String>,
+    pub(crate) vulnerabilities: Vec<Finding>,
+    /// Where the reference came from: project files, or the command line.
+    pub(crate) referenced_by: Vec<String>,
+}
+
+impl PackageAudit {
+    /// Audits `requested` of `id`. `leaves` are every version in the
+    /// package's registration, or `None` if the package wasn't found.
+    /// `tracked` is whether the source keeps vulnerability data, in which
+    /// case a leaf without any just means it has no vulnerabilities.
+    pub(crate) fn new(
+        id: &str,
+        requested: Option<&str>,
+        leaves: Option<&[RegistrationLeaf]>,
+        tracked: bool,
+    ) -> Self {
+        let mut audit = PackageAudit {
+            id: id.into(),
+            requested: requested.map(String::from),
+            version: None,
+            status: Status::Unknown,
+            reason: None,
+            vulnerabilities: Vec::new(),
+            referenced_by: Vec::new(),
+        };
+        let leaves = match leaves {
+            Some(leaves) => leaves,
+            None => return audit.unknown("the package wasn't found on the source"),
+        };
+        let range = match requested.map(str::parse::<Range>) {
+            Some(Ok(range)) => range,
+            Some(Err(_)) => return audit.unknown("the requested version isn't a valid range"),
+            None => return audit.unknown("no version was requested"),
+        };
+        let versions = leaves
+            .iter()
+            .map(|leaf| leaf.catalog_entry.version.clone())
+            .collect::<Vec<_>>();
+        let version = match VersionPicker::new().pick_version(&range, &versions) {
+            Some(version) => version,
+            None => return audit.unknown("no version on the source matches the request"),
+        };
+        let leaf = leaves
+            .iter()
+            .find(|leaf| leaf.catalog_entry.version == version)
+            .expect("TURRON BUG: picked a version without a leaf");
+        audit.version = Some(version.clone());
+        let vulnerabilities = match vulnerabilities(leaf, tracked) {
+            Some(vulnerabilities) => vulnerabilities,
+            None => return audit.unknown("the source has no vulnerability data for it"),
+        };
+        audit.vulnerabilities = vulnerabilities
+            .iter()
+            .map(|vulnerability| Finding {
+                advisory_url: vulnerability.advisory_url.clone(),
+                severity: vulnerability.severity,
+                fixed_in: fixed_in(leaves, &version, &vulnerability.advisory_url, tracked),
+                suppressed: None,
+            })
+            .collect();
+        audit.status = if audit.vulnerabilities.is_empty() {
+            Status::Clean
+        } else {
+            Status::Vulnerable
+        };
+        audit
+    }
+
+    /// Marks findings `suppressions` acknowledges.
+    pub(crate) fn suppress(&mut self, suppressions: &Suppressions, today: NaiveDate) {
+        for finding in &mut self.vulnerabilities {
+            let status = suppressions.advisory(&self.id, &[], Some(&finding.advisory_url), today);
+            finding.suppressed = status.suppression().map(|suppression| Suppressed {
+                reason: suppression.reason.clone(),
+                expires: suppression.expires,
+                expired: matches!(status, FindingStatus::Expired { .. }),
+            });
+        }
+    }
+
+    fn unknown(mut self, reason: &str) -> Self {
+        self.status = Status::Unknown;
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+/// `leaf`'s vulnerabilities, or `None` if there's no telling.
+fn vulnerabilities(leaf: &RegistrationLeaf, tracked: bool) -> Option<&[Vulnerability]> {
+    match &leaf.catalog_entry.vulnerabilities {
+        Some(vulnerabilities) => Some(vulnerabilities),
+        None if tracked => Some(&[]),
+        None => None,
+    }
+}
+
+/// The lowest version after `version` that isn't affected by `advisory_url`.
+/// Prereleases only count if `version` is one.
+fn fixed_in(
+    leaves: &[RegistrationLeaf],
+    version: &Version,
+    advisory_url: &str,
+    tracked: bool,
+) -> Option<Version> {
+    let mut later = leaves
+        .iter()
+        .filter(|leaf| &leaf.catalog_entry.version > version)
+        .filter(|leaf| version.is_prerelease() || !leaf.catalog_entry.version.is_prerelease())
+        .collect::<Vec<_>>();
+    later.sort_by(|a, b| a.catalog_entry.version.cmp(&b.catalog_entry.version));
+    later
+        .into_iter()
+        .find(|leaf| {
+            vulnerabilities(leaf, tracked).map_or(false, |vulnerabilities| {
+                !vulnerabilities
+                    .iter()
+                    .any(|v| v.advisory_url.eq_ignore_ascii_case(advisory_url))
+            })
+        })
+        .map(|leaf| leaf.catalog_entry.version.clone())
+}
+
+pub(crate) fn display<S: Serializer>(
+    value: &impl fmt::Display,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::serde_json::{self, json};
+
+    use super::*;
+
+    const ADVISORY: &str = "https://github.com/advisories/GHSA-5crp-9r3c-p9vr";
+
+    fn leaf(version: &str, vulnerabilities: Option<&[(&str, &str)]>) -> RegistrationLeaf {
+        let mut entry = json!({ "id": "Foo", "version": version });
+        if let Some(vulnerabilities) = vulnerabilities {
+            entry["vulnerabilities"] = vulnerabilities
+                .iter()
+                .map(|(url, severity)| json!({ "advisoryUrl": url, "severity": severity }))
+                .collect::<Vec<_>>()
+                .into();
+        }
+        serde_json::from_value(json!({
+            "catalogEntry": entry,
+            "packageContent": "https://example.com/foo.nupkg",
+        }))
+        .unwrap()
+    }
+
+    fn leaves() -> Vec<RegistrationLeaf> {
+        vec![
+            leaf(
+                "1.0.0",
+                Some(&[(ADVISORY, "2"), ("https://example.com/other", "0")]),
+            ),
+            leaf("1.1.0", Some(&[(ADVISORY, "2")])),
+            leaf("2.0.0-beta", Some(&[])),
+            leaf("2.0.0", None),
+        ]
+    }
+
+    #[test]
+    fn finds_vulnerabilities_and_fixes() {
+        let audit = PackageAudit::new("Foo", Some("1.0.0"), Some(&leaves()), true);
+        assert_eq!(audit.status, Status::Vulnerable);
+        assert_eq!(audit.version, Some("1.0.0".parse().unwrap()));
+        let fixes = audit
+            .vulnerabilities
+            .iter()
+            .map(|f| (f.severity, f.fixed_in.as_ref().map(|v| v.to_string())))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fixes,
+            vec![
+                (Severity::High, Some("2.0.0".into())),
+                (Severity::Low, Some("1.1.0".into())),
+            ]
+        );
+        assert!(audit.vulnerabilities[0].counts(Severity::High));
+        assert!(!audit.vulnerabilities[1].counts(Severity::Moderate));
+
+        let audit = PackageAudit::new("Foo", Some("[2.0.0, )"), Some(&leaves()), true);
+        assert_eq!(audit.status, Status::Clean);
+    }
+
+    #[test]
+    fn untracked_sources_are_unknown() {
+        let audit = PackageAudit::new("Foo", Some("[2.0.0, )"), Some(&leaves()), false);
+        assert_eq!(audit.status, Status::Unknown);
+        assert_eq!(audit.version, Some("2.0.0".parse().unwrap()));
+
+        // Without data for 2.0.0, there's no telling whether it's fixed.
+        let audit = PackageAudit::new("Foo", Some("1.1.0"), Some(&leaves()), false);
+        assert_eq!(audit.status, Status::Vulnerable);
+        assert_eq!(audit.vulnerabilities[0].fixed_in, None);
+
+        for requested in &[None, Some("not a range"), Some("[3.0.0, )")] {
+            let audit = PackageAudit::new("Foo", *requested, Some(&leaves()), true);
+            assert_eq!(audit.status, Status::Unknown, "{:?}", requested);
+            assert!(audit.reason.is_some());
+        }
+        let audit = PackageAudit::new("Foo", Some("1.0.0"), None, true);
+        assert_eq!(audit.status, Status::Unknown);
+    }
+
+    #[test]
+    fn applies_suppressions() {
+        let suppressions = Suppressions::parse(
+            r#"
+            advisory "GHSA-5crp-9r3c-p9vr" {
+                package "foo"
+                reason "Not reachable."
+            }
+            advisory "https://example.com/other" {
+                expires "2020-01-01"
+                reason "Was waiting on a fix."
+            }
+            "#,
+        )
+        .unwrap();
+        let mut audit = PackageAudit::new("Foo", Some("1.0.0"), Some(&leaves()), true);
+        audit.suppress(&suppressions, NaiveDate::from_ymd(2021, 6, 1));
+        let high = &audit.vulnerabilities[0];
+        assert_eq!(high.suppressed.as_ref().unwrap().reason, "Not reachable.");
+        assert!(!high.counts(Severity::Low));
+        let low = &audit.vulnerabilities[1];
+        assert!(low.suppressed.as_ref().unwrap().expired);
+        assert!(low.counts(Severity::Low));
+    }
+}
@@ -0,0 +1,311 @@
+//! The search itself, kept apart from projects and processes so it only
+//! needs to be told whether each version it picks is good.
+
+use dotnet_semver::Version;
+
+use crate::error::BisectError;
+
+/// What to do next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// Try this version, then [`Bisection::record`] how it went.
+    Test(Version),
+    Done(Outcome),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    /// `first_bad` is bad, and `last_good`, the version right before it, is
+    /// good.
+    FirstBad {
+        last_good: Version,
+        first_bad: Version,
+    },
+    /// Even the earliest version is bad.
+    NoGood(Version),
+    /// Even the latest version is good.
+    NoBad(Version),
+}
+
+/// A binary search for the first bad version, assuming every version after
+/// a bad one is bad too.
+///
+/// Unless they're seeded, the earliest version is checked first to make
+/// sure it's good, then the latest to make sure it's bad.
+#[derive(Debug, Clone)]
+pub(crate) struct Bisection {
+    /// Sorted, without duplicates.
+    versions: Vec<Version>,
+    /// The latest version known to be good.
+    good: Option<usize>,
+    /// The earliest version known to be bad.
+    bad: Option<usize>,
+    tested: Vec<(Version, bool)>,
+}
+
+impl Bisection {
+    /// Starts a search through `versions`, which can't be empty. `good` and
+    /// `bad` are versions already known to be good and bad, and have to be
+    /// among `versions`.
+    pub(crate) fn new(
+        mut versions: Vec<Version>,
+        good: Option<&Version>,
+        bad: Option<&Version>,
+    ) -> Result<Self, BisectError> {
+        assert!(
+            !versions.is_empty(),
+            "TURRON BUG: bisecting without any versions"
+        );
+        versions.sort();
+        versions.dedup();
+        let index = |version: &Version| {
+            versions
+                .iter()
+                .position(|v| v == version)
+                .ok_or_else(|| BisectError::UnknownVersion(version.clone()))
+        };
+        let good_index = good.map(index).transpose()?;
+        let bad_index = bad.map(index).transpose()?;
+        if let (Some(good), Some(bad), Some(good_index), Some(bad_index)) =
+            (good, bad, good_index, bad_index)
+        {
+            if good_index >= bad_index {
+                return Err(BisectError::GoodAfterBad {
+                    good: good.clone(),
+                    bad: bad.clone(),
+                });
+            }
+        }
+        Ok(Bisection {
+            versions,
+            good: good_index,
+            bad: bad_index,
+            tested: Vec::new(),
+        })
+    }
+
+    /// What to do next, given everything recorded so far.
+    pub(crate) fn next(&self) -> Step {
+        let last = self.versions.len() - 1;
+        let good = match (self.good, self.bad) {
+            (Some(good), _) => good,
+            (None, Some(0)) => return Step::Done(Outcome::NoGood(self.versions[0].clone())),
+            (None, _) => return Step::Test(self.versions[0].clone()),
+        };
+        let bad = match self.bad {
+            Some(bad) => bad,
+            None if good == last => return Step::Done(Outcome::NoBad(self.versions[last].clone())),
+            None => return Step::Test(self.versions[last].clone()),
+        };
+        if bad - good == 1 {
+            Step::Done(Outcome::FirstBad {
+                last_good: self.versions[good].clone(),
+                first_bad: self.versions[bad].clone(),
+            })
+        } else {
+            Step::Test(self.versions[good + (bad - good) / 2].clone())
+        }
+    }
+
+    /// Records whether `version`, which [`Bisection::next`] asked for, is
+    /// good.
+    pub(crate) fn record(&mut self, version: &Version, good: bool) {
+        let index = self
+            .versions
+            .iter()
+            .position(|v| v == version)
+            .expect("TURRON BUG: recorded a version that wasn't being bisected");
+        if good {
+            self.good = Some(self.good.map_or(index, |g| g.max(index)));
+        } else {
+            self.bad = Some(self.bad.map_or(index, |b| b.min(index)));
+        }
+        self.tested.push((version.clone(), good));
+    }
+
+    /// Versions that could still turn out to be the first bad one.
+    pub(crate) fn candidates(&self) -> &[Version] {
+        let start = self.good.map_or(0, |good| good + 1);
+        let end = self.bad.map_or(self.versions.len(), |bad| bad + 1);
+        &self.versions[start.min(end)..end]
+    }
+
+    /// The most versions that could still need testing.
+    pub(crate) fn steps_left(&self) -> usize {
+        let mut steps = 0;
+        let good = match self.good {
+            Some(good) => good,
+            None if self.bad == Some(0) => return 0,
+            None => {
+                steps += 1;
+                0
+            }
+        };
+        let bad = match self.bad {
+            Some(bad) => bad,
+            None if good == self.versions.len() - 1 => return steps,
+            None => {
+                steps += 1;
+                self.versions.len() - 1
+            }
+        };
+        let mut gap = bad - good;
+        while gap > 1 {
+            gap = (gap + 1) / 2;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Every version tested so far, in order, and whether it was good.
+    pub(crate) fn tested(&self) -> &[(Version, bool)] {
+        &self.tested
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(count: u64) -> Vec<Version> {
+        (1..=count)
+            .map(|major| format!("{}.0.0", major).parse().unwrap())
+            .collect()
+    }
+
+    fn v(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    /// Runs `bisection` to the end, with every version from `first_bad` on
+    /// being bad.
+    fn run(mut bisection: Bisection, first_bad: Option<&Version>) -> Outcome {
+        let budget = bisection.steps_left();
+        loop {
+            match bisection.next() {
+                Step::Test(version) => {
+                    assert!(
+                        !bisection.tested().iter().any(|(v, _)| v == &version),
+                        "tested {} twice",
+                        version
+                    );
+                    let good = first_bad.map_or(true, |bad| &version < bad);
+                    bisection.record(&version, good);
+                }
+                Step::Done(outcome) => {
+                    assert!(
+                        bisection.tested().len() <= budget,
+                        "took {} steps, expected at most {}",
+                        bisection.tested().len(),
+                        budget
+                    );
+                    return outcome;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn finds_the_first_bad_version() {
+        for count in 1..=9 {
+            let all = versions(count);
+            for (i, first_bad) in all.iter().enumerate() {
+                let outcome = run(
+                    Bisection::new(all.clone(), None, None).unwrap(),
+                    Some(first_bad),
+                );
+                let expected = if i == 0 {
+                    Outcome::NoGood(first_bad.clone())
+                } else {
+                    Outcome::FirstBad {
+                        last_good: all[i - 1].clone(),
+                        first_bad: first_bad.clone(),
+                    }
+                };
+                assert_eq!(
+                    outcome, expected,
+                    "{} versions, first bad {}",
+                    count, first_bad
+                );
+            }
+            let outcome = run(Bisection::new(all.clone(), None, None).unwrap(), None);
+            assert_eq!(outcome, Outcome::NoBad(all[all.len() - 1].clone()));
+        }
+    }
+
+    #[test]
+    fn seeds_narrow_the_search() {
+        let all = versions(16);
+        let bisection = Bisection::new(all.clone(), Some(&v("4.0.0")), Some(&v("8.0.0"))).unwrap();
+        assert_eq!(
+            bisection.candidates(),
+            &[v("5.0.0"), v("6.0.0"), v("7.0.0"), v("8.0.0")]
+        );
+        assert_eq!(bisection.steps_left(), 2);
+        assert_eq!(bisection.next(), Step::Test(v("6.0.0")));
+        assert_eq!(
+            run(bisection, Some(&v("7.0.0"))),
+            Outcome::FirstBad {
+                last_good: v("6.0.0"),
+                first_bad: v("7.0.0"),
+            }
+        );
+
+        // Seeds are never tested again, so a bad seed right after a good one
+        // is the answer already.
+        let bisection = Bisection::new(all.clone(), Some(&v("4.0.0")), Some(&v("5.0.0"))).unwrap();
+        assert_eq!(bisection.steps_left(), 0);
+        assert_eq!(
+            bisection.next(),
+            Step::Done(Outcome::FirstBad {
+                last_good: v("4.0.0"),
+                first_bad: v("5.0.0"),
+            })
+        );
+        let bisection = Bisection::new(all.clone(), None, Some(&v("1.0.0"))).unwrap();
+        assert_eq!(bisection.next(), Step::Done(Outcome::NoGood(v("1.0.0"))));
+
+        // Only the missing end gets checked.
+        let bisection = Bisection::new(all.clone(), Some(&v("1.0.0")), None).unwrap();
+        assert_eq!(bisection.next(), Step::Test(v("16.0.0")));
+        let bisection = Bisection::new(all, None, Some(&v("16.0.0"))).unwrap();
+        assert_eq!(bisection.next(), Step::Test(v("1.0.0")));
+    }
+
+    #[test]
+    fn rejects_bad_seeds() {
+        let all = versions(4);
+        assert!(matches!(
+            Bisection::new(all.clone(), Some(&v("3.0.0")), Some(&v("2.0.0"))),
+            Err(BisectError::GoodAfterBad { .. })
+        ));
+        assert!(matches!(
+            Bisection::new(all.clone(), Some(&v("2.0.0")), Some(&v("2.0.0"))),
+            Err(BisectError::GoodAfterBad { .. })
+        ));
+        assert!(matches!(
+            Bisection::new(all, Some(&v("2.5.0")), None),
+            Err(BisectError::UnknownVersion(_))
+        ));
+    }
+
+    #[test]
+    fn lists_candidates_in_order() {
+        let bisection = Bisection::new(
+            vec![v("2.0.0"), v("1.0.0"), v("1.1.0-beta"), v("1.0.0")],
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            bisection.candidates(),
+            &[v("1.0.0"), v("1.1.0-beta"), v("2.0.0")]
+        );
+        assert_eq!(bisection.steps_left(), 3);
+        let mut bisection = bisection;
+        bisection.record(&v("1.0.0"), true);
+        bisection.record(&v("2.0.0"), false);
+        assert_eq!(bisection.candidates(), &[v("1.1.0-beta"), v("2.0.0")]);
+        assert_eq!(bisection.next(), Step::Test(v("1.1.0-beta")));
+    }
+}
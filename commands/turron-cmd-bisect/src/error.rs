@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+
+use dotnet_semver::{Range, Version};
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Debug, Diagnostic, Error)]
+pub enum BisectError {
+    #[error("Only NuGet package specifiers can be bisected, like `Foo@[1.0.0, 2.0.0]`.")]
+    #[diagnostic(code(turron::bisect::invalid_package_spec))]
+    InvalidPackageSpec,
+
+    #[error("A package to bisect is required.")]
+    #[diagnostic(
+        code(turron::bisect::no_package),
+        help("Pass the package id, like `turron bisect Newtonsoft.Json --test \"dotnet test\"`.")
+    )]
+    NoPackage,
+
+    #[error("A command to test each version with is required.")]
+    #[diagnostic(
+        code(turron::bisect::no_test_command),
+        help("Pass one with --test, like `--test \"dotnet test\"`, or use --dry-run to just see the versions that would be tried.")
+    )]
+    NoTestCommand,
+
+    #[error("{} doesn't reference {0}.", .1.display())]
+    #[diagnostic(
+        code(turron::bisect::not_referenced),
+        help("Add it first with `turron add {0}`, or pass --root to point at the right project.")
+    )]
+    NotReferenced(String, PathBuf),
+
+    #[error("{0} doesn't have a version in {}, so there's nothing to change between runs.", .1.display())]
+    #[diagnostic(code(turron::bisect::no_version))]
+    NoVersion(String, PathBuf),
+
+    #[error("No published versions of {0} satisfy {1}.")]
+    #[diagnostic(
+        code(turron::bisect::no_candidates),
+        help("Try running `turron view {0} versions`.")
+    )]
+    NoCandidates(String, Range),
+
+    #[error("{0} isn't a published version of this package.")]
+    #[diagnostic(
+        code(turron::bisect::unknown_version),
+        help("--good and --bad need to name versions that exist on the source.")
+    )]
+    UnknownVersion(Version),
+
+    #[error("The good version, {good}, has to come before the bad one, {bad}.")]
+    #[diagnostic(code(turron::bisect::good_after_bad))]
+    GoodAfterBad { good: Version, bad: Version },
+
+    #[error("Even the earliest version tried, {0}@{1}, is bad.")]
+    #[diagnostic(
+        code(turron::bisect::no_good_version),
+        help("Widen the range to include an older version, or check that the test passes at all.")
+    )]
+    NoGoodVersion(String, Version),
+
+    #[error("Even the latest version tried, {0}@{1}, is good.")]
+    #[diagnostic(
+        code(turron::bisect::no_bad_version),
+        help("Widen the range to include a newer version, or check that the test fails when it should.")
+    )]
+    NoBadVersion(String, Version),
+
+    #[error("`{0}` was stopped by a signal before it finished.")]
+    #[diagnostic(code(turron::bisect::test_interrupted))]
+    TestInterrupted(String),
+
+    #[error("There's no interrupted bisect to undo in {}.", .0.display())]
+    #[diagnostic(code(turron::bisect::nothing_to_reset))]
+    NothingToReset(PathBuf),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::bisect::invalid_package_spec",
+        cause: "`turron bisect` switches a `<PackageReference>` between versions of a package on a NuGet source. Paths and git specs can't be referenced that way.",
+        fixes: &["Pass a package id, optionally with a range to search, like `Newtonsoft.Json@[12.0.1, 13.0.1]`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::no_package",
+        cause: "Only `--reset` works without a package. Everything else needs to know which package's versions to go through.",
+        fixes: &["Pass the package id, like `turron bisect Newtonsoft.Json --test \"dotnet test\"`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::no_test_command",
+        cause: "Each version is judged by running a command: exiting with 0 means the version is good, and anything else that it's bad. Without one, there's no way to tell.",
+        fixes: &[
+            "Pass the command with `--test`, quoted if it has spaces, like `--test \"dotnet test\"`.",
+            "Use `--dry-run` to only list the versions that would be tried.",
+        ],
+        config: &["commands.bisect.test"],
+    },
+    Explanation {
+        code: "turron::bisect::not_referenced",
+        cause: "Bisecting works by changing the version of the package's `<PackageReference>` in the project, and the project doesn't have one.",
+        fixes: &[
+            "Add the package with `turron add <id>` first.",
+            "Pass `--root` with the project that references the package.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::no_version",
+        cause: "The package is referenced without a version, usually because it's managed centrally in Directory.Packages.props, which `turron bisect` doesn't edit.",
+        fixes: &["Temporarily give the reference a `Version` (or `VersionOverride`) in the project while bisecting."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::no_candidates",
+        cause: "None of the package's published versions fall within the requested range. Prereleases are left out unless the range mentions one, or `--prerelease` is passed.",
+        fixes: &[
+            "Run `turron view <id> versions` to see what's available.",
+            "Widen the range, or drop it to search every version.",
+            "Pass `--prerelease` to include prereleases.",
+        ],
+        config: &["commands.bisect.prerelease", "commands.bisect.source"],
+    },
+    Explanation {
+        code: "turron::bisect::unknown_version",
+        cause: "`--good` and `--bad` mark versions already known to work or not, so the search can skip them, but the source doesn't have the version passed to one of them.",
+        fixes: &["Run `turron view <id> versions` and pick one of the versions listed."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::good_after_bad",
+        cause: "Bisecting assumes that once a version is bad, every later version is bad too, so the good version has to be older than the bad one.",
+        fixes: &["Swap `--good` and `--bad`, if that's what was meant."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::no_good_version",
+        cause: "The test failed with the oldest version in the range, so there's no good version to start from. The breakage may predate the range, or the test may fail no matter which version is used.",
+        fixes: &[
+            "Widen the range, or pass an older `--good` version.",
+            "Run the test command by hand to check that it can pass.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::no_bad_version",
+        cause: "The test passed with the newest version in the range, so nothing in it broke the test.",
+        fixes: &[
+            "Widen the range, or include prereleases with `--prerelease`.",
+            "Check that the test command fails when it should, and exits with a non-zero code when it does.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::test_interrupted",
+        cause: "The test command was killed, usually by Ctrl-C, so whether the version is good is unknown. The project was put back the way it was.",
+        fixes: &["Run the bisect again. Pass `--good` and `--bad` with versions already found to pick up where it left off."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::bisect::nothing_to_reset",
+        cause: "`--reset` puts back a project that an interrupted `turron bisect` left pointing at some other version, using the `.turron-bisect` copy saved next to it, and there isn't one.",
+        fixes: &["Nothing needs undoing. If the project still looks wrong, check `git diff`."],
+        config: &[],
+    },
+];
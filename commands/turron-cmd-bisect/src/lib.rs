@@ -0,0 +1,397 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use dotnet_semver::{Range, Version};
+use nuget_api::{v3::NuGetClient, SourceProtocol};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    owo_colors::{colors::*, OwoColorize},
+    render::sanitize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json},
+    smol::{self, fs, process::Command},
+    tracing,
+};
+use turron_package_spec::PackageSpec;
+
+use bisection::{Bisection, Outcome, Step};
+pub use error::{BisectError, EXPLANATIONS};
+
+mod bisection;
+mod error;
+
+/// Added to the project's file name for the copy kept while bisecting.
+const BACKUP_SUFFIX: &str = ".turron-bisect";
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "bisect"]
+pub struct BisectCmd {
+    #[clap(
+        about = "Package to bisect, optionally with a range to search, like `Foo@[1.0.0, 2.0.0]`",
+        required_unless_present = "reset"
+    )]
+    package: Option<String>,
+    #[clap(
+        about = "Command to run against each version: exiting with 0 means it's good, anything else that it's bad",
+        long
+    )]
+    test: Option<String>,
+    #[clap(about = "A version already known to be good", long)]
+    good: Option<Version>,
+    #[clap(about = "A version already known to be bad", long)]
+    bad: Option<Version>,
+    #[clap(
+        about = "Include prereleases, even if the range doesn't mention any",
+        long
+    )]
+    prerelease: bool,
+    #[clap(
+        about = "List the versions that could be tried, without changing or running anything",
+        long
+    )]
+    dry_run: bool,
+    #[clap(
+        about = "Put back a project left changed by an interrupted bisect",
+        long,
+        conflicts_with = "dry-run"
+    )]
+    reset: bool,
+    #[clap(from_global)]
+    root: Option<PathBuf>,
+    #[clap(
+        about = "Source to look up versions on",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for BisectCmd {
+    async fn execute(self) -> Result<()> {
+        let start = env::current_dir()
+            .into_diagnostic()
+            .context("Failed to get the current directory")?
+            .join(self.root.clone().unwrap_or_default());
+        let project = smol::unblock(move || turron_dotnet::find_project(&start)).await?;
+        let backup = backup_path(&project);
+        if self.reset {
+            if !restore_backup(&project, &backup).await? {
+                return Err(BisectError::NothingToReset(project).into());
+            }
+            if !self.quiet && !self.json {
+                println!("Restored {}", project.display());
+            }
+            return Ok(());
+        }
+        if restore_backup(&project, &backup).await? {
+            tracing::warn!(
+                "Restored {} from an earlier bisect that didn't finish",
+                project.display()
+            );
+        }
+
+        let (package_id, range) = self.spec()?;
+        let original = fs::read_to_string(&project)
+            .await
+            .into_diagnostic()
+            .with_context(|| format!("Failed to read {}", project.display()))?;
+        let reference = turron_dotnet::package_references(&project, &original)?
+            .into_iter()
+            .find(|reference| reference.id.eq_ignore_ascii_case(&package_id))
+            .ok_or_else(|| BisectError::NotReferenced(package_id.clone(), project.clone()))?;
+        if reference.version.is_none() {
+            return Err(BisectError::NoVersion(reference.id, project).into());
+        }
+
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let published = client.versions(&reference.id).await?;
+        let prerelease = self.prerelease || range.has_pre_release();
+        let mut versions = published
+            .iter()
+            .filter(|v| range.satisfies(v) && (prerelease || !v.is_prerelease()))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Seeds count even when they're outside the range.
+        for seed in self.good.iter().chain(self.bad.iter()) {
+            if published.contains(seed) {
+                versions.push(seed.clone());
+            }
+        }
+        if versions.is_empty() {
+            return Err(BisectError::NoCandidates(reference.id, range).into());
+        }
+        let mut bisection = Bisection::new(versions, self.good.as_ref(), self.bad.as_ref())?;
+
+        if self.dry_run {
+            return self.print_plan(&reference.id, &bisection);
+        }
+        let test = self.test.clone().ok_or(BisectError::NoTestCommand)?;
+        let restore = Restore::new(&project, backup, original)?;
+        let outcome = loop {
+            let version = match bisection.next() {
+                Step::Test(version) => version,
+                Step::Done(outcome) => break outcome,
+            };
+            if !self.quiet && !self.json {
+                println!(
+                    "{} {}@{} ({} left at most)",
+                    "Testing".fg::<BrightCyan>(),
+                    sanitize(&reference.id),
+                    version,
+                    bisection.steps_left()
+                );
+            }
+            let (edited, _) = turron_dotnet::set_package_reference(
+                &project,
+                &restore.original,
+                &reference.id,
+                &version.to_string(),
+            )?;
+            fs::write(&project, edited)
+                .await
+                .into_diagnostic()
+                .with_context(|| format!("Failed to write {}", project.display()))?;
+            let good = run_test(&test, &reference.id, &version, self.quiet || self.json).await;
+            restore.restore().await?;
+            let good = good?;
+            if !self.quiet && !self.json {
+                if good {
+                    println!(
+                        "{}@{} is {}",
+                        sanitize(&reference.id),
+                        version,
+                        "good".fg::<Green>()
+                    );
+                } else {
+                    println!(
+                        "{}@{} is {}",
+                        sanitize(&reference.id),
+                        version,
+                        "bad".fg::<Red>()
+                    );
+                }
+            }
+            bisection.record(&version, good);
+        };
+        drop(restore);
+
+        match outcome {
+            Outcome::FirstBad {
+                last_good,
+                first_bad,
+            } => {
+                if self.json && !self.quiet {
+                    let tested = bisection
+                        .tested()
+                        .iter()
+                        .map(|(version, good)| json!({ "version": version, "good": good }))
+                        .collect::<Vec<_>>();
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&json!({
+                            "id": reference.id,
+                            "firstBad": first_bad,
+                            "lastGood": last_good,
+                            "tested": tested,
+                        }))
+                        .into_diagnostic()
+                        .context("Failed to serialize bisect results into JSON")?
+                    );
+                } else if !self.quiet {
+                    println!();
+                    println!(
+                        "{} is the first bad version of {}. The last good one is {}.",
+                        first_bad.to_string().fg::<Red>(),
+                        sanitize(&reference.id).fg::<BrightCyan>(),
+                        last_good.to_string().fg::<Green>()
+                    );
+                }
+                Ok(())
+            }
+            Outcome::NoGood(version) => {
+                Err(BisectError::NoGoodVersion(reference.id, version).into())
+            }
+            Outcome::NoBad(version) => Err(BisectError::NoBadVersion(reference.id, version).into()),
+        }
+    }
+}
+
+impl BisectCmd {
+    /// The package id and the range of versions to search, which is every
+    /// version if the spec doesn't have one.
+    fn spec(&self) -> Result<(String, Range)> {
+        let package = self.package.as_ref().ok_or(BisectError::NoPackage)?;
+        match package.parse()? {
+            PackageSpec::NuGet { name, requested } => {
+                Ok((name, requested.unwrap_or_else(Range::any)))
+            }
+            _ => Err(BisectError::InvalidPackageSpec.into()),
+        }
+    }
+
+    /// Prints the versions the first bad one could be, in order, and which
+    /// would be tried first.
+    fn print_plan(&self, package_id: &str, bisection: &Bisection) -> Result<()> {
+        let next = match bisection.next() {
+            Step::Test(version) => Some(version),
+            Step::Done(_) => None,
+        };
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({
+                    "id": package_id,
+                    "candidates": bisection.candidates(),
+                    "next": next,
+                    "stepsLeft": bisection.steps_left(),
+                }))
+                .into_diagnostic()
+                .context("Failed to serialize bisect plan into JSON")?
+            );
+        } else if !self.quiet {
+            for version in bisection.candidates() {
+                if Some(version) == next.as_ref() {
+                    println!("{} {}", version, "(first to test)".fg::<BrightCyan>());
+                } else {
+                    println!("{}", version);
+                }
+            }
+            println!();
+            println!(
+                "Up to {} versions of {} would be tested.",
+                bisection.steps_left(),
+                sanitize(package_id)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Puts the project back the way it was. Also happens on drop, so bisecting
+/// never leaves it changed if it fails partway.
+///
+/// A copy of the original is kept next to the project until then, in case
+/// turron itself is interrupted. `--reset`, or the next bisect, puts it
+/// back.
+struct Restore {
+    project: PathBuf,
+    backup: PathBuf,
+    original: String,
+}
+
+impl Restore {
+    fn new(project: &Path, backup: PathBuf, original: String) -> Result<Self> {
+        std::fs::write(&backup, &original)
+            .into_diagnostic()
+            .with_context(|| format!("Failed to write {}", backup.display()))?;
+        Ok(Restore {
+            project: project.into(),
+            backup,
+            original,
+        })
+    }
+
+    async fn restore(&self) -> Result<()> {
+        fs::write(&self.project, &self.original)
+            .await
+            .into_diagnostic()
+            .with_context(|| format!("Failed to restore {}", self.project.display()))
+    }
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        match std::fs::write(&self.project, &self.original) {
+            Ok(()) => {
+                if let Err(err) = std::fs::remove_file(&self.backup) {
+                    tracing::warn!("Failed to remove {}: {}", self.backup.display(), err);
+                }
+            }
+            // The backup stays, so `--reset` can try again.
+            Err(err) => tracing::warn!(
+                "Failed to restore {}: {}. Run `turron bisect --reset` to try again.",
+                self.project.display(),
+                err
+            ),
+        }
+    }
+}
+
+fn backup_path(project: &Path) -> PathBuf {
+    let mut path = OsString::from(project.as_os_str());
+    path.push(BACKUP_SUFFIX);
+    path.into()
+}
+
+/// Copies a backup left by an interrupted bisect back over the project.
+/// Returns whether there was one.
+async fn restore_backup(project: &Path, backup: &Path) -> Result<bool> {
+    let original = match fs::read_to_string(backup).await {
+        Ok(original) => original,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => {
+            return Err(err)
+                .into_diagnostic()
+                .with_context(|| format!("Failed to read {}", backup.display()))
+        }
+    };
+    fs::write(project, original)
+        .await
+        .into_diagnostic()
+        .with_context(|| format!("Failed to restore {}", project.display()))?;
+    fs::remove_file(backup)
+        .await
+        .into_diagnostic()
+        .with_context(|| format!("Failed to remove {}", backup.display()))?;
+    Ok(true)
+}
+
+/// Runs `test` through the shell, and returns whether it passed. The
+/// package and version being tested are in `TURRON_BISECT_PACKAGE` and
+/// `TURRON_BISECT_VERSION`, for tests that need them.
+async fn run_test(test: &str, package_id: &str, version: &Version, quiet: bool) -> Result<bool> {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(test)
+        .env("TURRON_BISECT_PACKAGE", package_id)
+        .env("TURRON_BISECT_VERSION", version.to_string());
+    if quiet {
+        // Keeps --json output parseable. Errors still show up on stderr.
+        cmd.stdout(Stdio::null());
+    }
+    let status = cmd
+        .status()
+        .await
+        .into_diagnostic()
+        .with_context(|| format!("Failed to run `{}`", test))?;
+    if status.code().is_none() {
+        return Err(BisectError::TestInterrupted(test.into()).into());
+    }
+    Ok(status.success())
+}
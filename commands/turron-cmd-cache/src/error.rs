@@ -0,0 +1,25 @@
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum CacheError {
+    #[error("Couldn't find NuGet's global packages folder.")]
+    #[diagnostic(
+        code(turron::cache::no_global_packages),
+        help("Set NUGET_PACKAGES, or pass the folder to import.")
+    )]
+    NoGlobalPackages,
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::cache::no_global_packages",
+    cause: "`turron cache import-global-packages` imports from NuGet's global packages folder unless told otherwise. That's `NUGET_PACKAGES` if it's set, or `.nuget/packages` in the home directory, and there was neither.",
+    fixes: &[
+        "Set NUGET_PACKAGES to the folder `dotnet restore` uses.",
+        "Pass the folder to import as an argument.",
+    ],
+    config: &[],
+}];
@@ -0,0 +1,87 @@
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, ArgMatches, Clap},
+    turron_config::{TurronConfig, TurronConfigLayer},
+    TurronCommand,
+};
+use turron_common::{miette::Result, tracing};
+
+pub use error::{CacheError, EXPLANATIONS};
+use subcommands::{ClearCmd, DirCmd, ImportGlobalPackagesCmd, LsCmd};
+
+mod error;
+mod subcommands;
+
+#[derive(Debug, Clap)]
+pub enum CacheSubCmd {
+    #[clap(
+        about = "Print where the HTTP cache lives",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Dir(DirCmd),
+    #[clap(
+        about = "List cached URLs",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Ls(LsCmd),
+    #[clap(
+        about = "Remove everything from the HTTP cache",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Clear(ClearCmd),
+    #[clap(
+        about = "Add the packages in NuGet's global packages folder to the HTTP cache, without copying them",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    ImportGlobalPackages(ImportGlobalPackagesCmd),
+}
+
+#[derive(Debug, Clap)]
+#[clap(
+    setting = clap::AppSettings::InferSubcommands,
+)]
+pub struct CacheCmd {
+    #[clap(subcommand)]
+    subcommand: CacheSubCmd,
+}
+
+#[async_trait]
+impl TurronCommand for CacheCmd {
+    async fn execute(self) -> Result<()> {
+        tracing::debug!("Running command: {:#?}", self.subcommand);
+        match self.subcommand {
+            CacheSubCmd::Dir(dir) => dir.execute().await,
+            CacheSubCmd::Ls(ls) => ls.execute().await,
+            CacheSubCmd::Clear(clear) => clear.execute().await,
+            CacheSubCmd::ImportGlobalPackages(import) => import.execute().await,
+        }
+    }
+}
+
+impl TurronConfigLayer for CacheCmd {
+    fn layer_config(&mut self, args: &ArgMatches, conf: &TurronConfig) -> Result<()> {
+        match self.subcommand {
+            CacheSubCmd::Dir(ref mut dir) => {
+                dir.layer_config(args.subcommand_matches("dir").unwrap(), conf)
+            }
+            CacheSubCmd::Ls(ref mut ls) => {
+                ls.layer_config(args.subcommand_matches("ls").unwrap(), conf)
+            }
+            CacheSubCmd::Clear(ref mut clear) => {
+                clear.layer_config(args.subcommand_matches("clear").unwrap(), conf)
+            }
+            CacheSubCmd::ImportGlobalPackages(ref mut import) => import.layer_config(
+                args.subcommand_matches("import-global-packages").unwrap(),
+                conf,
+            ),
+        }
+    }
+}
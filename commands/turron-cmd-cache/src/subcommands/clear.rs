@@ -0,0 +1,47 @@
+use nuget_api::cache::HttpCache;
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::json,
+    smol,
+};
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "cache.clear"]
+pub struct ClearCmd {
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for ClearCmd {
+    async fn execute(self) -> Result<()> {
+        let cache = HttpCache::new(HttpCache::default_dir()?);
+        let dir = cache.dir().to_owned();
+        let cleared = smol::unblock(move || cache.clear())
+            .await
+            .into_diagnostic()
+            .with_context(|| format!("Failed to clear the HTTP cache at {}", dir.display()))?;
+        if self.json && !self.quiet {
+            println!("{}", json!({ "cleared": cleared }));
+        } else if !self.quiet {
+            println!(
+                "Removed {} cached {}.",
+                cleared,
+                if cleared == 1 {
+                    "response"
+                } else {
+                    "responses"
+                }
+            );
+        }
+        Ok(())
+    }
+}
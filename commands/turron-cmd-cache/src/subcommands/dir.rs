@@ -0,0 +1,30 @@
+use nuget_api::cache::HttpCache;
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{miette::Result, serde_json::json};
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "cache.dir"]
+pub struct DirCmd {
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for DirCmd {
+    async fn execute(self) -> Result<()> {
+        let dir = HttpCache::default_dir()?;
+        if self.json && !self.quiet {
+            println!("{}", json!({ "dir": dir }));
+        } else if !self.quiet {
+            println!("{}", dir.display());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dotnet_semver::Version;
+use nuget_api::{
+    cache::{HttpCache, ImportMode},
+    sidecar,
+    v3::NuGetClient,
+    SourceProtocol,
+};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    dirs,
+    miette::{Context, IntoDiagnostic, Result},
+    paths::{join_checked, sanitize_filename},
+    resume::{ResumableJob, ResumeError},
+    serde_json::json,
+    smol, tracing,
+};
+
+use crate::error::CacheError;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "cache.import-global-packages"]
+pub struct ImportGlobalPackagesCmd {
+    #[clap(
+        about = "Global packages folder to import. Defaults to NUGET_PACKAGES, or ~/.nuget/packages"
+    )]
+    dir: Option<PathBuf>,
+    #[clap(
+        about = "Source the packages came from. They're cached as that source's .nupkg URLs",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "How to import packages without copying them: `link` hard-links them into the cache, `reference` points at them where they are",
+        long,
+        default_value = "link",
+        possible_values = &["link", "reference"]
+    )]
+    mode: String,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for ImportGlobalPackagesCmd {
+    async fn execute(self) -> Result<()> {
+        let root = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => dirs::nuget_global_packages().ok_or(CacheError::NoGlobalPackages)?,
+        };
+        let mode = if self.mode == "reference" {
+            ImportMode::Reference
+        } else {
+            ImportMode::Link
+        };
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let scan_root = root.clone();
+        let packages = smol::unblock(move || scan(&scan_root))
+            .await
+            .into_diagnostic()
+            .with_context(|| format!("Failed to read {}", root.display()))?;
+
+        let cache = HttpCache::new(HttpCache::default_dir()?);
+        let mut jobs = Vec::new();
+        for package in packages {
+            let url = client.nupkg_url(&package.id, &package.version)?;
+            jobs.push((package.nupkg.display().to_string(), url, package));
+        }
+        // Big global packages folders take a while, so an interrupted import
+        // picks up where it left off instead of starting over.
+        let spec = json!({
+            "job": "cache import-global-packages",
+            "dir": root.display().to_string(),
+            "source": self.source,
+            "mode": self.mode,
+        })
+        .to_string();
+        let jobs_dir = dirs::cache_dir()?;
+        let (linked, referenced, resumed) = smol::unblock(move || {
+            let items = jobs
+                .iter()
+                .map(|(item, ..)| item.clone())
+                .collect::<Vec<_>>();
+            let mut job = match ResumableJob::resume(&jobs_dir, &spec, items.clone()) {
+                // Importing again is harmless, so a state file that can't be
+                // read just means starting over.
+                Err(ResumeError::BadState(path, err)) => {
+                    tracing::warn!(
+                        "Starting over, since {} couldn't be read: {}",
+                        path.display(),
+                        err
+                    );
+                    ResumableJob::start(&jobs_dir, &spec, items)?
+                }
+                job => job?,
+            };
+            let pending = job.pending().iter().cloned().collect::<HashSet<_>>();
+            let resumed = jobs.len().saturating_sub(pending.len());
+            let (mut linked, mut referenced) = (0usize, 0usize);
+            for (item, url, package) in jobs {
+                if !pending.contains(&item) {
+                    continue;
+                }
+                match cache.import(&url, &package.nupkg, &package.sha512, mode) {
+                    Ok(imported) => {
+                        match imported {
+                            ImportMode::Link => linked += 1,
+                            ImportMode::Reference => referenced += 1,
+                        }
+                        job.complete(&item)?;
+                    }
+                    Err(err) => {
+                        tracing::warn!("Failed to import {}: {}", package.nupkg.display(), err);
+                        job.fail(&item, err)?;
+                    }
+                }
+            }
+            job.finish()?;
+            Ok::<_, ResumeError>((linked, referenced, resumed))
+        })
+        .await?;
+
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                json!({ "linked": linked, "referenced": referenced, "resumed": resumed })
+            );
+        } else if !self.quiet {
+            println!(
+                "Imported {} {} from {} ({} linked, {} referenced).",
+                linked + referenced,
+                if linked + referenced == 1 {
+                    "package"
+                } else {
+                    "packages"
+                },
+                root.display(),
+                linked,
+                referenced
+            );
+            if resumed > 0 {
+                println!(
+                    "{} more were already imported by an earlier, interrupted run.",
+                    resumed
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A fully extracted package in a global packages folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Found {
+    id: String,
+    version: Version,
+    nupkg: PathBuf,
+    /// The base64 SHA-512 restore recorded for it.
+    sha512: String,
+}
+
+/// Every package under `root`, laid out as `<id>/<version>/<id>.<version>.nupkg`.
+/// Packages restore hasn't finished extracting, which have no recorded
+/// hash yet, are skipped, since the .nupkg might not be all there.
+fn scan(root: &Path) -> std::io::Result<Vec<Found>> {
+    let mut found = Vec::new();
+    for id_dir in fs::read_dir(root)? {
+        let id_dir = id_dir?.path();
+        if !id_dir.is_dir() {
+            continue;
+        }
+        let id = file_name(&id_dir);
+        for version_dir in fs::read_dir(&id_dir)? {
+            let version_dir = version_dir?.path();
+            let raw_version = file_name(&version_dir);
+            let version = match Version::parse(&raw_version) {
+                Ok(version) => version,
+                Err(_) => continue,
+            };
+            let name = sanitize_filename(&format!("{}.{}.nupkg", id, raw_version));
+            // Symlinked packages can point anywhere, and anything they point
+            // to would end up linked or referenced from the cache.
+            let nupkg = match join_checked(root, Path::new(&id).join(&raw_version).join(&name)) {
+                Ok(nupkg) => nupkg,
+                Err(err) => {
+                    tracing::warn!("Skipping {}: {}", version_dir.display(), err);
+                    continue;
+                }
+            };
+            if !nupkg.is_file() {
+                continue;
+            }
+            match sidecar::recorded_hash(&version_dir, &name) {
+                Some(sha512) => found.push(Found {
+                    id: id.clone(),
+                    version,
+                    nupkg,
+                    sha512,
+                }),
+                None => tracing::debug!("Skipping {}, which has no recorded hash", nupkg.display()),
+            }
+        }
+    }
+    found.sort_by(|a, b| a.id.cmp(&b.id).then_with(|| a.version.cmp(&b.version)));
+    Ok(found)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use turron_testing::fixtures;
+
+    use super::*;
+
+    fn restored(root: &Path, id: &str, version: &str, sidecars: bool) -> PathBuf {
+        let dir = root.join(id).join(version);
+        fs::create_dir_all(&dir).unwrap();
+        let name = format!("{}.{}.nupkg", id, version);
+        let nupkg = fixtures::nupkg_packed();
+        fs::write(dir.join(&name), &nupkg).unwrap();
+        if sidecars {
+            sidecar::write(&dir, &name, &nupkg, None).unwrap();
+        }
+        dir.join(name)
+    }
+
+    #[test]
+    fn finds_fully_restored_packages() {
+        let root = tempdir().unwrap();
+        let done = restored(root.path(), "turron.test", "1.0.0", true);
+        let beta = restored(root.path(), "turron.test", "2.0.0-beta", true);
+        restored(root.path(), "half.done", "1.0.0", false);
+        fs::create_dir_all(root.path().join("turron.test").join("not-a-version")).unwrap();
+        fs::write(root.path().join("stray.txt"), "").unwrap();
+
+        let found = scan(root.path()).unwrap();
+        assert_eq!(
+            found
+                .iter()
+                .map(|f| (f.id.as_str(), f.version.to_string(), &f.nupkg))
+                .collect::<Vec<_>>(),
+            vec![
+                ("turron.test", "1.0.0".to_string(), &done),
+                ("turron.test", "2.0.0-beta".to_string(), &beta),
+            ]
+        );
+        assert_eq!(
+            found[0].sha512,
+            sidecar::content_hash(&fixtures::nupkg_packed())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_packages_linked_from_elsewhere() {
+        let root = tempdir().unwrap();
+        let elsewhere = tempdir().unwrap();
+        restored(root.path(), "turron.test", "1.0.0", true);
+        restored(elsewhere.path(), "turron.linked", "1.0.0", true);
+        std::os::unix::fs::symlink(
+            elsewhere.path().join("turron.linked"),
+            root.path().join("turron.linked"),
+        )
+        .unwrap();
+
+        let found = scan(root.path()).unwrap();
+        assert_eq!(
+            found.iter().map(|f| f.id.as_str()).collect::<Vec<_>>(),
+            vec!["turron.test"]
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use nuget_api::cache::HttpCache;
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    fmt::Numbers,
+    owo_colors::{colors::*, OwoColorize},
+    render::sanitize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    chrono_humanize::HumanTime,
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json, smol,
+};
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "cache.ls"]
+pub struct LsCmd {
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for LsCmd {
+    async fn execute(self) -> Result<()> {
+        let cache = HttpCache::new(HttpCache::default_dir()?);
+        let dir = cache.dir().to_owned();
+        let entries = smol::unblock(move || cache.entries())
+            .await
+            .into_diagnostic()
+            .with_context(|| format!("Failed to read the HTTP cache at {}", dir.display()))?;
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&entries)
+                    .into_diagnostic()
+                    .context("Failed to serialize cache entries to JSON")?
+            );
+        } else if !self.quiet {
+            let numbers = Numbers::current();
+            for entry in &entries {
+                println!(
+                    "{} {} {}",
+                    sanitize(&entry.url),
+                    format!("({})", numbers.bytes(entry.size)).fg::<Yellow>(),
+                    format!("stored {}", HumanTime::from(entry.stored_at)).fg::<BrightBlack>()
+                );
+            }
+            if entries.is_empty() {
+                println!("Nothing is cached.");
+            }
+        }
+        Ok(())
+    }
+}
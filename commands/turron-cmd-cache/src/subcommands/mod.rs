@@ -0,0 +1,9 @@
+pub use clear::ClearCmd;
+pub use dir::DirCmd;
+pub use import::ImportGlobalPackagesCmd;
+pub use ls::LsCmd;
+
+mod clear;
+mod dir;
+mod import;
+mod ls;
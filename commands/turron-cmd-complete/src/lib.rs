@@ -0,0 +1,51 @@
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, ArgMatches, Clap},
+    turron_config::{TurronConfig, TurronConfigLayer},
+    TurronCommand,
+};
+use turron_common::{miette::Result, tracing};
+
+pub use version::{complete_versions, VersionCompleteCmd};
+
+mod version;
+
+#[derive(Debug, Clap)]
+pub enum CompleteSubCmd {
+    #[clap(
+        about = "Complete versions for a package ID",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Version(VersionCompleteCmd),
+}
+
+/// Hidden helper that shell completion scripts call to get dynamic
+/// candidates, one per line. It never fails loudly: if candidates can't be
+/// found, it prints nothing.
+#[derive(Debug, Clap)]
+pub struct CompleteCmd {
+    #[clap(subcommand)]
+    subcommand: CompleteSubCmd,
+}
+
+#[async_trait]
+impl TurronCommand for CompleteCmd {
+    async fn execute(self) -> Result<()> {
+        tracing::debug!("Running command: {:#?}", self.subcommand);
+        match self.subcommand {
+            CompleteSubCmd::Version(version) => version.execute().await,
+        }
+    }
+}
+
+impl TurronConfigLayer for CompleteCmd {
+    fn layer_config(&mut self, args: &ArgMatches, conf: &TurronConfig) -> Result<()> {
+        match self.subcommand {
+            CompleteSubCmd::Version(ref mut version) => {
+                version.layer_config(args.subcommand_matches("version").unwrap(), conf)
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use dotnet_semver::Version;
+use nuget_api::v3::NuGetClient;
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::Result,
+    smol::{future, Timer},
+    tracing,
+};
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "complete.version"]
+pub struct VersionCompleteCmd {
+    #[clap(about = "Package ID to complete versions for")]
+    id: String,
+    #[clap(about = "Only show versions starting with this", default_value = "")]
+    prefix: String,
+    #[clap(
+        about = "Source to look versions up from",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(
+        about = "Milliseconds to wait for the source before giving up",
+        default_value = "2000",
+        long
+    )]
+    timeout: u64,
+}
+
+#[async_trait]
+impl TurronCommand for VersionCompleteCmd {
+    async fn execute(self) -> Result<()> {
+        let lookup = async {
+            let client = NuGetClient::from_source(self.source.clone()).await?;
+            client.versions(&self.id).await
+        };
+        let timeout = async {
+            Timer::after(Duration::from_millis(self.timeout)).await;
+            Ok(Vec::new())
+        };
+        // Completion should never get in the user's way, so errors and
+        // timeouts just mean there's nothing to offer.
+        match future::or(lookup, timeout).await {
+            Ok(versions) => {
+                for version in complete_versions(&versions, &self.prefix) {
+                    println!("{}", version);
+                }
+            }
+            Err(err) => tracing::debug!("Version completion failed: {}", err),
+        }
+        Ok(())
+    }
+}
+
+/// Returns the versions that start with `prefix` (case-insensitively),
+/// newest first.
+pub fn complete_versions(versions: &[Version], prefix: &str) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut versions = versions.to_vec();
+    versions.sort_unstable_by(|a, b| b.cmp(a));
+    versions
+        .into_iter()
+        .map(|v| v.to_string())
+        .filter(|v| v.to_lowercase().starts_with(&prefix))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(versions: &[&str]) -> Vec<Version> {
+        versions.iter().map(|v| v.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn newest_first() {
+        let all = versions(&["1.0.0", "2.0.0-beta.1", "1.10.0", "1.2.0", "2.0.0"]);
+        assert_eq!(
+            complete_versions(&all, ""),
+            vec!["2.0.0", "2.0.0-beta.1", "1.10.0", "1.2.0", "1.0.0"]
+        );
+    }
+
+    #[test]
+    fn filters_by_prefix() {
+        let all = versions(&["1.0.0", "1.10.0", "1.2.0", "2.0.0", "10.0.0"]);
+        assert_eq!(
+            complete_versions(&all, "1."),
+            vec!["1.10.0", "1.2.0", "1.0.0"]
+        );
+        assert_eq!(
+            complete_versions(&all, "1"),
+            vec!["10.0.0", "1.10.0", "1.2.0", "1.0.0"]
+        );
+        assert!(complete_versions(&all, "3").is_empty());
+    }
+
+    #[test]
+    fn prefix_is_case_insensitive() {
+        let all = versions(&["1.0.0-Beta", "1.0.0-alpha"]);
+        assert_eq!(complete_versions(&all, "1.0.0-b"), vec!["1.0.0-Beta"]);
+    }
+}
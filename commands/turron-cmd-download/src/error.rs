@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+use dotnet_semver::Range;
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum DownloadError {
+    #[error("Only NuGet package specifiers can be downloaded.")]
+    #[diagnostic(code(turron::download::invalid_package_spec))]
+    InvalidPackageSpec,
+
+    #[error("Failed to find a version for {0} that satisfied {1}")]
+    #[diagnostic(
+        code(turron::download::version_not_found),
+        help("Try running `turron view <id> versions`")
+    )]
+    VersionNotFound(String, Range),
+
+    #[error("{} already exists.", .0.display())]
+    #[diagnostic(
+        code(turron::download::already_exists),
+        help("Use --force to overwrite it.")
+    )]
+    AlreadyExists(PathBuf),
+
+    #[error("Unknown package layout: {0}")]
+    #[diagnostic(
+        code(turron::download::unknown_layout),
+        help("Use `flat` or `global-packages`.")
+    )]
+    UnknownLayout(String),
+
+    #[error("Couldn't find NuGet's global packages folder.")]
+    #[diagnostic(
+        code(turron::download::no_global_packages),
+        help("Set NUGET_PACKAGES, or pass --out.")
+    )]
+    NoGlobalPackages,
+
+    #[error("The downloaded package has no nuspec.")]
+    #[diagnostic(code(turron::download::missing_nuspec))]
+    MissingNuspec,
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::download::invalid_package_spec",
+        cause: "`turron download` only fetches packages from a NuGet source. Paths and git specs point at things that aren't on a source.",
+        fixes: &["Pass a package id, optionally with a version, e.g. `Newtonsoft.Json@13.0.1`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::download::version_not_found",
+        cause: "The package exists, but none of its published versions satisfy the requested version or range.",
+        fixes: &[
+            "Run `turron view <id> versions` to see what's available.",
+            "Widen the range, or drop it to get the latest version.",
+        ],
+        config: &["commands.download.source"],
+    },
+    Explanation {
+        code: "turron::download::already_exists",
+        cause: "The output file is already there, and turron won't overwrite it unless asked to.",
+        fixes: &["Pass `--force`, or pick another path with `--out`."],
+        config: &["commands.download.force", "commands.download.out"],
+    },
+    Explanation {
+        code: "turron::download::unknown_layout",
+        cause: "`--layout` only knows how to lay out packages like a packages.config `packages/` folder (`flat`) or like NuGet's global packages folder (`global-packages`).",
+        fixes: &["Pass `--layout flat` or `--layout global-packages`."],
+        config: &["commands.download.layout"],
+    },
+    Explanation {
+        code: "turron::download::no_global_packages",
+        cause: "`--layout global-packages` expands packages into NuGet's global packages folder unless told otherwise. That's `NUGET_PACKAGES` if it's set, or `.nuget/packages` in the home directory, and there was neither.",
+        fixes: &[
+            "Set NUGET_PACKAGES to the folder `dotnet restore` uses.",
+            "Pass `--out` to expand the package somewhere else.",
+        ],
+        config: &["commands.download.out"],
+    },
+    Explanation {
+        code: "turron::download::missing_nuspec",
+        cause: "Every .nupkg is supposed to have a nuspec at its root, and NuGet tooling won't use an expanded package without one. The source served something that isn't a valid package.",
+        fixes: &["Report the package to the source's maintainers."],
+        config: &[],
+    },
+];
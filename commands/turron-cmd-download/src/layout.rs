@@ -0,0 +1,306 @@
+//! Expanding downloaded packages the way NuGet tooling expects to find them.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use dotnet_semver::Version;
+use nuget_api::{sidecar, NuGetApiError};
+use percent_encoding::percent_decode_str;
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    paths::{join_checked, sanitize_filename},
+};
+use zip::ZipArchive;
+
+use crate::error::DownloadError;
+
+/// How `--unzip` lays a package out on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// `<Id>.<version>/`, with the .nupkg and its contents, like the
+    /// `packages/` folder of a packages.config project.
+    Flat,
+    /// `<id>/<version>/`, lowercased, with the .nupkg, its contents, a
+    /// `<id>.nuspec`, and NuGet's `.nupkg.sha512` and `.nupkg.metadata`
+    /// sidecars, like NuGet's global packages folder.
+    GlobalPackages,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Flat
+    }
+}
+
+impl FromStr for Layout {
+    type Err = DownloadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.trim().to_lowercase()[..] {
+            "flat" => Ok(Layout::Flat),
+            "global-packages" => Ok(Layout::GlobalPackages),
+            _ => Err(DownloadError::UnknownLayout(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Flat => write!(f, "flat"),
+            Layout::GlobalPackages => write!(f, "global-packages"),
+        }
+    }
+}
+
+impl Layout {
+    /// Where the package gets expanded to, under `root`.
+    pub fn package_dir(&self, root: &Path, package_id: &str, version: &Version) -> PathBuf {
+        match self {
+            Layout::Flat => root.join(sanitize_filename(&format!("{}.{}", package_id, version))),
+            Layout::GlobalPackages => root
+                .join(sanitize_filename(&package_id.to_lowercase()))
+                .join(sanitize_filename(&normalized(version))),
+        }
+    }
+
+    /// What the .nupkg is called inside [`Layout::package_dir`].
+    pub fn nupkg_name(&self, package_id: &str, version: &Version) -> String {
+        sanitize_filename(&match self {
+            Layout::Flat => format!("{}.{}.nupkg", package_id, version),
+            Layout::GlobalPackages => format!(
+                "{}.{}.nupkg",
+                package_id.to_lowercase(),
+                normalized(version)
+            ),
+        })
+    }
+}
+
+/// Extracts the .nupkg already downloaded into `dir` next to it, along with
+/// whatever else `layout` calls for. `source` is recorded in the
+/// `.nupkg.metadata` of global packages.
+///
+/// Like NuGet, this leaves out the zip's packaging files (`_rels/`,
+/// `package/`, and `[Content_Types].xml`) and unescapes entry names. Entries
+/// that would land outside of `dir` fail the whole extraction.
+pub fn expand(
+    layout: Layout,
+    dir: &Path,
+    package_id: &str,
+    version: &Version,
+    source: &str,
+) -> Result<()> {
+    let nupkg = fs::read(dir.join(layout.nupkg_name(package_id, version)))
+        .into_diagnostic()
+        .context("Failed to read downloaded .nupkg")?;
+    let mut zip = ZipArchive::new(Cursor::new(&nupkg[..])).map_err(NuGetApiError::from)?;
+    let mut found_nuspec = false;
+    let mut nuspec = Vec::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).map_err(NuGetApiError::from)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = unescape(file.name());
+        if is_packaging_file(&name) {
+            continue;
+        }
+        let is_nuspec = !name.contains('/') && name.to_lowercase().ends_with(".nuspec");
+        found_nuspec |= is_nuspec;
+        if is_nuspec && layout == Layout::GlobalPackages {
+            // Written out below, under the name restore gives it.
+            file.read_to_end(&mut nuspec)
+                .into_diagnostic()
+                .context("Failed to read the package's nuspec")?;
+            continue;
+        }
+        let dest = join_checked(dir, &*name)?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)
+                .into_diagnostic()
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let mut out = fs::File::create(&dest)
+            .into_diagnostic()
+            .with_context(|| format!("Failed to create {}", dest.display()))?;
+        io::copy(&mut file, &mut out)
+            .into_diagnostic()
+            .with_context(|| format!("Failed to extract {}", name))?;
+    }
+    if !found_nuspec {
+        return Err(DownloadError::MissingNuspec.into());
+    }
+    if layout == Layout::GlobalPackages {
+        let id = package_id.to_lowercase();
+        fs::write(
+            dir.join(sanitize_filename(&format!("{}.nuspec", id))),
+            nuspec,
+        )
+        .into_diagnostic()
+        .context("Failed to write nuspec")?;
+        // Restore writes .nupkg.metadata last, since it's what says the
+        // package is all there.
+        sidecar::write(
+            dir,
+            &layout.nupkg_name(package_id, version),
+            &nupkg,
+            Some(source),
+        )
+        .into_diagnostic()
+        .context("Failed to write the package's .nupkg.sha512 and .nupkg.metadata")?;
+    }
+    Ok(())
+}
+
+/// Lowercased, without build metadata, the way NuGet names version
+/// directories.
+fn normalized(version: &Version) -> String {
+    let mut version = version.clone();
+    version.build.clear();
+    version.to_string().to_lowercase()
+}
+
+/// Zip entry names are URI-escaped, so `Read Me.txt` is stored as
+/// `Read%20Me.txt`.
+fn unescape(name: &str) -> Cow<'_, str> {
+    percent_decode_str(name)
+        .decode_utf8()
+        .unwrap_or(Cow::Borrowed(name))
+}
+
+/// Files that are part of the zip's OPC packaging, not the package.
+fn is_packaging_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name == "[content_types].xml" || name.starts_with("_rels/") || name.starts_with("package/")
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use turron_testing::fixtures;
+
+    use super::*;
+
+    /// Every file under `dir`, relative to it, with `/` separators.
+    fn listing(dir: &Path) -> Vec<String> {
+        fn walk(root: &Path, dir: &Path, files: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    walk(root, &path, files);
+                } else {
+                    let relative = path.strip_prefix(root).unwrap();
+                    let parts = relative
+                        .components()
+                        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+                        .collect::<Vec<_>>();
+                    files.push(parts.join("/"));
+                }
+            }
+        }
+        let mut files = Vec::new();
+        walk(dir, dir, &mut files);
+        files.sort();
+        files
+    }
+
+    fn download(layout: Layout, root: &Path, version: &Version) -> PathBuf {
+        let dir = layout.package_dir(root, "Turron.Test", version);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(layout.nupkg_name("Turron.Test", version)),
+            fixtures::nupkg_packed(),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn matches_what_restore_produces() {
+        let root = tempdir().unwrap();
+        let version = Version::parse("1.0.0").unwrap();
+        let dir = download(Layout::GlobalPackages, root.path(), &version);
+        expand(
+            Layout::GlobalPackages,
+            &dir,
+            "Turron.Test",
+            &version,
+            "https://api.nuget.org/v3/index.json",
+        )
+        .unwrap();
+
+        let expected = fixtures::global_packages_listing()
+            .lines()
+            .collect::<Vec<_>>();
+        assert_eq!(listing(root.path()), expected);
+
+        let sha512 = fs::read_to_string(dir.join("turron.test.1.0.0.nupkg.sha512")).unwrap();
+        assert_eq!(sha512, sidecar::content_hash(&fixtures::nupkg_packed()));
+        let metadata = sidecar::read_metadata(&dir).unwrap();
+        assert_eq!(metadata.content_hash, sha512);
+        assert_eq!(
+            metadata.source.as_deref(),
+            Some("https://api.nuget.org/v3/index.json")
+        );
+        let nuspec = fs::read_to_string(dir.join("turron.test.nuspec")).unwrap();
+        assert!(nuspec.contains("<id>Turron.Test</id>"));
+        assert_eq!(
+            fs::read_to_string(dir.join("content").join("Read Me.txt")).unwrap(),
+            "Hello, world.\n"
+        );
+    }
+
+    #[test]
+    fn names_things_like_nuget() {
+        let version = Version::parse("1.0.0-Beta+abc").unwrap();
+        let layout = Layout::GlobalPackages;
+        assert_eq!(
+            layout.package_dir(Path::new("root"), "Turron.Test", &version),
+            Path::new("root").join("turron.test").join("1.0.0-beta")
+        );
+        assert_eq!(
+            layout.nupkg_name("Turron.Test", &version),
+            "turron.test.1.0.0-beta.nupkg"
+        );
+        assert_eq!(
+            Layout::Flat.package_dir(Path::new("root"), "Turron.Test", &version),
+            Path::new("root").join("Turron.Test.1.0.0-Beta+abc")
+        );
+        assert_eq!(
+            "global-packages".parse::<Layout>().unwrap(),
+            Layout::GlobalPackages
+        );
+        assert!("nested".parse::<Layout>().is_err());
+    }
+
+    #[test]
+    fn flat_keeps_original_names() {
+        let root = tempdir().unwrap();
+        let version = Version::parse("1.0.0").unwrap();
+        let dir = download(Layout::Flat, root.path(), &version);
+        expand(Layout::Flat, &dir, "Turron.Test", &version, "").unwrap();
+        assert_eq!(
+            listing(&dir),
+            vec![
+                "README.md",
+                "Turron.Test.1.0.0.nupkg",
+                "content/Read Me.txt",
+                "lib/netstandard2.0/Turron.Test.dll",
+                "turron.test.nuspec",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_packaging_files() {
+        assert_eq!(unescape("content/Read%20Me.txt"), "content/Read Me.txt");
+        assert!(is_packaging_file("_rels/.rels"));
+        assert!(is_packaging_file("[Content_Types].xml"));
+        assert!(!is_packaging_file("lib/_rels.dll"));
+    }
+}
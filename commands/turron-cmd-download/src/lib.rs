@@ -0,0 +1,240 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use dotnet_semver::Range;
+use nuget_api::{v3::NuGetClient, SourceProtocol};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    fmt::Numbers,
+    indicatif::{ProgressBar, ProgressStyle},
+    turron_config::{SourcePolicy, TurronConfigLayer},
+    TurronCommand,
+};
+use turron_common::{
+    dirs,
+    miette::{Context, IntoDiagnostic, Result},
+    paths::sanitize_filename,
+    serde_json::{self, json},
+    smol::{self, fs, Timer},
+};
+use turron_package_spec::PackageSpec;
+
+pub use error::{DownloadError, EXPLANATIONS};
+pub use layout::Layout;
+
+mod error;
+mod layout;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "download"]
+pub struct DownloadCmd {
+    #[clap(about = "Package spec to download")]
+    package: String,
+    #[clap(
+        about = "File or directory to write the .nupkg to. With --unzip, the directory to expand it under",
+        long,
+        short
+    )]
+    out: Option<PathBuf>,
+    #[clap(about = "Overwrite the destination if it already exists", long, short)]
+    force: bool,
+    #[clap(
+        about = "Expand the package into a directory, next to the .nupkg",
+        long
+    )]
+    unzip: bool,
+    #[clap(
+        about = "How --unzip lays the package out: `flat` (<id>.<version>/), or `global-packages` (<id>/<version>/, like `dotnet restore`, under the global packages folder by default)",
+        long,
+        requires = "unzip",
+        possible_values = &["flat", "global-packages"]
+    )]
+    layout: Option<Layout>,
+    #[clap(
+        about = "Source to download from",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Download even if the source's package patterns don't allow it",
+        long
+    )]
+    ignore_source_policy: bool,
+    #[clap(skip)]
+    #[config_layer(source_policy)]
+    source_policy: SourcePolicy,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for DownloadCmd {
+    async fn execute(self) -> Result<()> {
+        let package = self.package.parse()?;
+        let (package_id, requested) = if let PackageSpec::NuGet { name, requested } = &package {
+            (name, requested.clone().unwrap_or_else(Range::any_floating))
+        } else {
+            return Err(DownloadError::InvalidPackageSpec.into());
+        };
+        self.source_policy
+            .enforce(package_id, &self.source, self.ignore_source_policy)?;
+
+        let spinner = if self.quiet || self.json {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new_spinner()
+        };
+        let spin_clone = spinner.clone();
+        let spin_fut = smol::spawn(async move {
+            while !spin_clone.is_finished() {
+                spin_clone.tick();
+                Timer::after(Duration::from_millis(20)).await;
+            }
+        });
+
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let versions = client.versions(package_id).await?;
+        let version = turron_pick_version::pick_version(&requested, &versions[..])
+            .ok_or_else(|| DownloadError::VersionNotFound(package_id.into(), requested.clone()))?;
+
+        let layout = self.layout.unwrap_or_default();
+        let path = if self.unzip {
+            let root = match (&self.out, layout) {
+                (Some(out), _) => out.clone(),
+                (None, Layout::GlobalPackages) => {
+                    dirs::nuget_global_packages().ok_or(DownloadError::NoGlobalPackages)?
+                }
+                (None, Layout::Flat) => PathBuf::new(),
+            };
+            layout.package_dir(&root, package_id, &version)
+        } else {
+            let filename = sanitize_filename(&format!("{}.{}.nupkg", package_id, version));
+            match &self.out {
+                Some(out) if out.is_dir() => out.join(filename),
+                Some(out) => out.clone(),
+                None => PathBuf::from(filename),
+            }
+        };
+        if !self.force && path.exists() {
+            spinner.finish_and_clear();
+            spin_fut.await;
+            return Err(DownloadError::AlreadyExists(path).into());
+        }
+
+        spinner.set_message(format!("Downloading {}@{}...", package_id, version));
+        // Write to a sibling file first so a failed download doesn't leave a
+        // truncated .nupkg behind. Expanded packages get a whole sibling
+        // directory, so nothing sees a half-extracted one either.
+        let staging = if self.unzip {
+            let parent = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            fs::create_dir_all(parent)
+                .await
+                .into_diagnostic()
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+            Some(
+                tempfile::Builder::new()
+                    .prefix(".turron-")
+                    .tempdir_in(parent)
+                    .into_diagnostic()
+                    .context("Failed to create a directory to expand the package in")?,
+            )
+        } else {
+            None
+        };
+        let partial = match &staging {
+            Some(staging) => staging.path().join(layout.nupkg_name(package_id, &version)),
+            None => {
+                let mut partial = path.clone().into_os_string();
+                partial.push(".part");
+                PathBuf::from(partial)
+            }
+        };
+        let file = fs::File::create(&partial)
+            .await
+            .into_diagnostic()
+            .context("Failed to create .nupkg file")?;
+        let bar = spinner.clone();
+        let mut sized = false;
+        let written = client
+            .nupkg_to_writer(package_id, &version, file, move |done, total| {
+                if let (Some(total), false) = (total, sized) {
+                    sized = true;
+                    bar.set_style(ProgressStyle::default_bar().template(&format!(
+                        "{{spinner}} {{msg}} [{{bar:30}}] {}",
+                        Numbers::current().transfer_template()
+                    )));
+                    bar.set_length(total);
+                }
+                bar.set_position(done);
+            })
+            .await;
+        let bytes = match written {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                let _ = fs::remove_file(&partial).await;
+                spinner.finish_and_clear();
+                spin_fut.await;
+                return Err(err.into());
+            }
+        };
+        if let Some(staging) = &staging {
+            spinner.set_message(format!("Expanding {}@{}...", package_id, version));
+            let (dir, id, v, source) = (
+                staging.path().to_owned(),
+                package_id.clone(),
+                version.clone(),
+                self.source.clone(),
+            );
+            smol::unblock(move || layout::expand(layout, &dir, &id, &v, &source)).await?;
+            if path.exists() {
+                fs::remove_dir_all(&path)
+                    .await
+                    .into_diagnostic()
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            fs::rename(staging.path(), &path)
+                .await
+                .into_diagnostic()
+                .context("Failed to move expanded package into place")?;
+        } else {
+            fs::rename(&partial, &path)
+                .await
+                .into_diagnostic()
+                .context("Failed to move downloaded .nupkg into place")?;
+        }
+        spinner.finish_and_clear();
+        spin_fut.await;
+
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({
+                    "id": package_id,
+                    "version": version.to_string(),
+                    "path": path,
+                    "bytes": bytes,
+                    "layout": if self.unzip { Some(layout.to_string()) } else { None },
+                }))
+                .into_diagnostic()
+                .context("Failed to serialize download output into JSON")?
+            );
+        } else if !self.quiet {
+            println!("{}", path.display());
+        }
+        Ok(())
+    }
+}
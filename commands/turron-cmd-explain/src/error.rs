@@ -0,0 +1,29 @@
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum ExplainError {
+    #[error("No explanation found for `{0}`.")]
+    #[diagnostic(
+        code(turron::explain::unknown_code),
+        help("Diagnostic codes are printed next to error messages, and look like `turron::api::bad_json`.")
+    )]
+    UnknownCode(String),
+
+    #[error("No explanation found for `{code}`. Did you mean `{suggestion}`?")]
+    #[diagnostic(code(turron::explain::unknown_code))]
+    DidYouMean {
+        code: String,
+        suggestion: &'static str,
+    },
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::explain::unknown_code",
+    cause: "`turron explain` was given a code it has no documentation for. Codes are matched exactly, including the `turron::` prefix.",
+    fixes: &["Copy the code exactly as it appears next to the error message."],
+    config: &[],
+}];
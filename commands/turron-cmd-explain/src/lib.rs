@@ -0,0 +1,76 @@
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    owo_colors::OwoColorize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    explain::Explanation,
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json},
+};
+
+pub use error::{ExplainError, EXPLANATIONS};
+pub use registry::{explanations, find, suggest};
+
+mod error;
+mod registry;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "explain"]
+pub struct ExplainCmd {
+    #[clap(about = "Diagnostic code to explain, e.g. turron::api::bad_json")]
+    code: String,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for ExplainCmd {
+    async fn execute(self) -> Result<()> {
+        let explanation = find(&self.code).ok_or_else(|| match suggest(&self.code) {
+            Some(suggestion) => ExplainError::DidYouMean {
+                code: self.code.clone(),
+                suggestion,
+            },
+            None => ExplainError::UnknownCode(self.code.clone()),
+        })?;
+        if self.quiet {
+            return Ok(());
+        }
+        if self.json {
+            let output = serde_json::to_string_pretty(&json!({
+                "code": explanation.code,
+                "cause": explanation.cause,
+                "fixes": explanation.fixes,
+                "config": explanation.config,
+            }))
+            .into_diagnostic()
+            .context("Failed to serialize JSON explain output.")?;
+            println!("{}", output);
+        } else {
+            print!("{}", render(explanation));
+        }
+        Ok(())
+    }
+}
+
+fn render(explanation: &Explanation) -> String {
+    let mut out = format!("{}\n\n{}\n", explanation.code.bold(), explanation.cause);
+    if !explanation.fixes.is_empty() {
+        out.push_str(&format!("\n{}\n", "Common fixes:".bold()));
+        for fix in explanation.fixes {
+            out.push_str(&format!("  - {}\n", fix));
+        }
+    }
+    if !explanation.config.is_empty() {
+        out.push_str(&format!("\n{}\n", "Related config keys:".bold()));
+        for key in explanation.config {
+            out.push_str(&format!("  - {}\n", key));
+        }
+    }
+    out
+}
@@ -0,0 +1,168 @@
+use turron_common::explain::Explanation;
+
+/// Every diagnostic explanation in the workspace. Crates that define new
+/// diagnostics need to be added here; the tests below will complain if one
+/// is missed.
+pub fn explanations() -> Vec<&'static Explanation> {
+    let lists: [&'static [Explanation]; 27] = [
+        turron_common::dirs::EXPLANATIONS,
+        turron_common::paths::EXPLANATIONS,
+        turron_common::resume::EXPLANATIONS,
+        turron_command::turron_config::EXPLANATIONS,
+        dotnet_semver::EXPLANATIONS,
+        turron_package_spec::EXPLANATIONS,
+        nuget_api::EXPLANATIONS,
+        turron_dotnet::EXPLANATIONS,
+        turron_nupkg::EXPLANATIONS,
+        turron_suppressions::EXPLANATIONS,
+        turron_cmd_add::EXPLANATIONS,
+        turron_cmd_audit::EXPLANATIONS,
+        turron_cmd_bisect::EXPLANATIONS,
+        turron_cmd_cache::EXPLANATIONS,
+        turron_cmd_download::EXPLANATIONS,
+        turron_cmd_login::EXPLANATIONS,
+        turron_cmd_outdated::EXPLANATIONS,
+        turron_cmd_publish::EXPLANATIONS,
+        turron_cmd_remove::EXPLANATIONS,
+        turron_cmd_search::EXPLANATIONS,
+        turron_cmd_source::EXPLANATIONS,
+        turron_cmd_stats::EXPLANATIONS,
+        turron_cmd_unlist::EXPLANATIONS,
+        turron_cmd_unpublish_check::EXPLANATIONS,
+        turron_cmd_verify::EXPLANATIONS,
+        turron_cmd_view::EXPLANATIONS,
+        crate::error::EXPLANATIONS,
+    ];
+    lists.iter().copied().flat_map(|list| list.iter()).collect()
+}
+
+/// Looks up the explanation for `code`. Surrounding whitespace and casing
+/// are ignored, since codes often get copied out of terminal output.
+pub fn find(code: &str) -> Option<&'static Explanation> {
+    let code = code.trim().to_lowercase();
+    explanations().into_iter().find(|exp| exp.code == code)
+}
+
+/// Finds the known code closest to `code`, if any is close enough to be a
+/// plausible typo. Input without a `::` is also compared against the last
+/// segment of each code, so `bad_jsn` suggests `turron::api::bad_json`.
+pub fn suggest(code: &str) -> Option<&'static str> {
+    let code = code.trim().to_lowercase();
+    let threshold = std::cmp::max(2, code.chars().count() / 3);
+    explanations()
+        .into_iter()
+        .map(|exp| {
+            let full = strsim::levenshtein(&code, exp.code);
+            let short = if code.contains("::") {
+                full
+            } else {
+                exp.code
+                    .rsplit("::")
+                    .next()
+                    .map(|last| strsim::levenshtein(&code, last))
+                    .unwrap_or(full)
+            };
+            (std::cmp::min(full, short), exp.code)
+        })
+        .filter(|(dist, _)| *dist <= threshold)
+        .min_by_key(|(dist, _)| *dist)
+        .map(|(_, code)| code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use turron_common::regex::Regex;
+
+    fn rust_files(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                if path.file_name().map_or(false, |name| name != "target") {
+                    rust_files(&path, files);
+                }
+            } else if path.extension().map_or(false, |ext| ext == "rs") {
+                files.push(path);
+            }
+        }
+    }
+
+    fn workspace_codes() -> HashSet<String> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let mut files = Vec::new();
+        for dir in &["src", "crates", "commands"] {
+            rust_files(&root.join(dir), &mut files);
+        }
+        let re = Regex::new(r"code\(\s*([a-z0-9_]+(?:::[a-z0-9_]+)+)\s*\)").unwrap();
+        let mut codes = HashSet::new();
+        for file in files {
+            let src = fs::read_to_string(&file).unwrap();
+            for cap in re.captures_iter(&src) {
+                codes.insert(cap[1].to_string());
+            }
+        }
+        codes
+    }
+
+    #[test]
+    fn every_diagnostic_code_is_explained() {
+        let codes = workspace_codes();
+        assert!(codes.contains("turron::api::bad_json"));
+        let known = explanations()
+            .into_iter()
+            .map(|exp| exp.code.to_string())
+            .collect::<HashSet<_>>();
+        let mut missing = codes.difference(&known).collect::<Vec<_>>();
+        missing.sort();
+        assert!(
+            missing.is_empty(),
+            "These diagnostic codes need an entry in their crate's EXPLANATIONS: {:#?}",
+            missing
+        );
+        let mut stale = known.difference(&codes).collect::<Vec<_>>();
+        stale.sort();
+        assert!(
+            stale.is_empty(),
+            "These explanations don't match any diagnostic code: {:#?}",
+            stale
+        );
+    }
+
+    #[test]
+    fn explanations_are_unique_and_filled_in() {
+        let mut seen = HashSet::new();
+        for exp in explanations() {
+            assert!(seen.insert(exp.code), "{} is explained twice", exp.code);
+            assert!(!exp.cause.is_empty(), "{} has no cause", exp.code);
+            assert!(!exp.fixes.is_empty(), "{} has no fixes", exp.code);
+        }
+    }
+
+    #[test]
+    fn finds_exact_codes() {
+        assert_eq!(
+            find(" Turron::API::bad_json\n").map(|exp| exp.code),
+            Some("turron::api::bad_json")
+        );
+        assert!(find("turron::api::nope").is_none());
+    }
+
+    #[test]
+    fn suggests_close_matches() {
+        assert_eq!(
+            suggest("turron::api::bad_jsno"),
+            Some("turron::api::bad_json")
+        );
+        assert_eq!(suggest("needs_api_key"), Some("turron::api::needs_api_key"));
+        assert_eq!(
+            suggest("turon::view::icon_not_found"),
+            Some("turron::view::icon_not_found")
+        );
+        assert_eq!(suggest("something::else::entirely"), None);
+    }
+}
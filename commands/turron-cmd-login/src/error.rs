@@ -0,0 +1,26 @@
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Debug, Diagnostic, Error)]
+pub enum LoginError {
+    #[error("{url} rejected the API key ({status}), so it wasn't saved.")]
+    #[diagnostic(
+        code(turron::login::key_rejected),
+        help("Check that the key was pasted in full and hasn't expired. Pass --no-verify to save it anyway.")
+    )]
+    KeyRejected { url: String, status: u16 },
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::login::key_rejected",
+    cause: "Before saving a key, `turron login` sends the source's publish endpoint an authenticated push with a deliberately invalid package. The source answered with 401 or 403, which means it turned the key down. Nothing was published, and the key wasn't saved.",
+    fixes: &[
+        "Copy the key again, making sure none of it got cut off.",
+        "Generate a new key with push rights for the packages you publish.",
+        "Pass `--no-verify` if the source checks keys in some other way.",
+    ],
+    config: &["commands.login.no_verify"],
+}];
@@ -1,25 +1,53 @@
+use nuget_api::{v3::NuGetClient, SourceProtocol};
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
     dialoguer::{Confirm, Input},
-    directories::ProjectDirs,
-    turron_config::TurronConfigLayer,
+    turron_config::{keychain, save_api_key, save_keychain_marker, TurronConfigLayer},
     TurronCommand,
 };
 use turron_common::{
-    miette::{miette, Context, IntoDiagnostic, Result},
-    smol::{
-        self,
-        fs::{self, OpenOptions},
-        io::AsyncWriteExt,
-    },
+    dirs,
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::json,
+    smol,
+    surf::StatusCode,
+    tracing,
 };
 
+pub use error::{LoginError, EXPLANATIONS};
+
+mod error;
+
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "login"]
 pub struct LoginCmd {
+    #[clap(
+        about = "Source to save the API key for",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Save the key without checking that the source accepts it",
+        long
+    )]
+    no_verify: bool,
+    #[clap(about = "Store the key in the OS keychain instead of turron.kdl", long)]
+    keychain: bool,
+    #[clap(skip)]
+    #[config_layer(credential_store_for = "source")]
+    credential_store: Option<String>,
     #[clap(from_global)]
+    #[config_layer(api_key_for = "source")]
     api_key: Option<String>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
 }
 
 #[async_trait]
@@ -28,7 +56,9 @@ impl TurronCommand for LoginCmd {
         if self.api_key.is_some() {
             let confirm = smol::unblock(|| -> Result<bool> {
                 Confirm::new()
-                    .with_prompt("You already have an API key configured. Continue?")
+                    .with_prompt(
+                        "You already have an API key configured for this source. Continue?",
+                    )
                     .default(true)
                     .interact()
                     .into_diagnostic()
@@ -47,28 +77,131 @@ impl TurronCommand for LoginCmd {
                 .context("Failed to read api key")
         }).await?;
 
-        let config = ProjectDirs::from("", "", "turron")
-            .map(|d| d.config_dir().to_owned().join("turron.kdl"))
-            .ok_or_else(|| miette!("Failed to calculate config file location."))?;
+        if !self.no_verify {
+            let client = NuGetClient::from_source_as(
+                self.source.clone(),
+                self.assume_source_version.unwrap_or_default(),
+            )
+            .await?
+            .with_key(Some(&key));
+            verify_key(&client).await?;
+        }
 
-        fs::create_dir_all(config.parent().unwrap())
-            .await
-            .into_diagnostic()
-            .context("Failed to create directories for config file location")?;
+        let config = dirs::config_file()?;
 
-        OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&config)
-            .await
-            .into_diagnostic()
-            .context("Failed to open turron config file")?
-            .write_all(format!("\napi_key \"{}\"\n", key).as_bytes())
-            .await
-            .into_diagnostic()
-            .context("Failed to append key to config file")?;
+        let use_keychain =
+            self.keychain || self.credential_store.as_deref() == Some(keychain::KEYRING);
+        let backup = if use_keychain {
+            let source = self.source.clone();
+            smol::unblock(move || keychain::store_api_key(&source, &key)).await?;
+            let source = self.source.clone();
+            let file = config.clone();
+            smol::unblock(move || save_keychain_marker(&file, &source))
+                .await
+                .context("Failed to save keychain setting to config file")?
+        } else {
+            let source = self.source.clone();
+            let file = config.clone();
+            smol::unblock(move || save_api_key(&file, &source, &key))
+                .await
+                .context("Failed to save API key to config file")?
+        };
 
-        println!("API Key written to {}.", config.display());
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                json!({
+                    "source": self.source,
+                    "stored_in": if use_keychain { "keychain" } else { "config" },
+                    "config": config,
+                    "backup": backup,
+                })
+            );
+        } else if !self.quiet {
+            if use_keychain {
+                println!("API Key for {} saved to the OS keychain.", self.source);
+            } else {
+                println!(
+                    "API Key for {} written to {}.",
+                    self.source,
+                    config.display()
+                );
+            }
+            // Lets users know where their hand-written config went, the
+            // first time saving a key rewrites it.
+            if let Some(backup) = backup {
+                println!(
+                    "Rewriting the config file drops its comments and formatting, so the original was saved to {}.",
+                    backup.display()
+                );
+            }
+        }
         Ok(())
     }
 }
+
+/// Checks the client's key against the source's publish endpoint, without
+/// publishing anything (see [`NuGetClient::probe_publish`]). A rejected key
+/// is an error. Sources that answer some other way only get a warning,
+/// since not every server checks keys in the same order nuget.org does.
+/// Returns whether the key was definitely accepted.
+async fn verify_key(client: &NuGetClient) -> Result<bool> {
+    let status = client.probe_publish().await?;
+    let url = client
+        .endpoints
+        .publish
+        .as_ref()
+        .map(|url| url.to_string())
+        .unwrap_or_default();
+    match status {
+        StatusCode::BadRequest => Ok(true),
+        StatusCode::Unauthorized | StatusCode::Forbidden => Err(LoginError::KeyRejected {
+            url,
+            status: status.into(),
+        }
+        .into()),
+        status => {
+            tracing::warn!(
+                "Couldn't tell whether {} accepts this key: it answered {}. Saving it anyway.",
+                url,
+                status
+            );
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_testing::TestServer;
+
+    use super::*;
+
+    const PUBLISH: &str = "/api/v2/package";
+
+    #[test]
+    fn verifies_keys_before_saving() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .respond_once(PUBLISH, 400, "")
+                .respond_once(PUBLISH, 403, "");
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_key(Some("key"));
+            assert!(verify_key(&client).await.unwrap());
+            let err = verify_key(&client).await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<LoginError>(),
+                Some(LoginError::KeyRejected { status: 403, .. })
+            ));
+            // Nothing at the endpoint: can't tell, so it's only a warning.
+            assert!(!verify_key(&client).await.unwrap());
+            assert_eq!(
+                server.request_headers(PUBLISH, "X-NuGet-ApiKey"),
+                vec![Some("key".into()); 3]
+            );
+        });
+    }
+}
@@ -0,0 +1,71 @@
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    turron_config::{forget_api_key, keychain, TurronConfigLayer},
+    TurronCommand,
+};
+use turron_common::{
+    dirs,
+    miette::{Context, Result},
+    serde_json::json,
+    smol, tracing,
+};
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "logout"]
+pub struct LogoutCmd {
+    #[clap(
+        about = "Source to forget the API key for",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(skip)]
+    #[config_layer(credential_store_for = "source")]
+    credential_store: Option<String>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for LogoutCmd {
+    async fn execute(self) -> Result<()> {
+        let source = self.source.clone();
+        let deleted = smol::unblock(move || keychain::delete_api_key(&source)).await;
+        match deleted {
+            Ok(_) => {}
+            // If the config says the key is in the keychain, not being able
+            // to remove it is worth stopping for. Otherwise there's probably
+            // no key there to begin with.
+            Err(err) if self.credential_store.as_deref() == Some(keychain::KEYRING) => {
+                return Err(err.into());
+            }
+            Err(err) => tracing::debug!("Skipping the keychain: {}", err),
+        }
+
+        let config = dirs::config_file()?;
+        let mut backup = None;
+        if config.exists() {
+            let source = self.source.clone();
+            let file = config.clone();
+            backup = smol::unblock(move || forget_api_key(&file, &source))
+                .await
+                .context("Failed to remove API key from config file")?;
+        }
+
+        if self.json && !self.quiet {
+            println!("{}", json!({ "source": self.source, "backup": backup }));
+        } else if !self.quiet {
+            println!("Logged out of {}.", self.source);
+            if let Some(backup) = backup {
+                println!(
+                    "Rewriting the config file drops its comments and formatting, so the original was saved to {}.",
+                    backup.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum OutdatedError {
+    #[error("No project files found under {}.", .0.display())]
+    #[diagnostic(
+        code(turron::outdated::no_projects),
+        help("Run this from a directory with .csproj, .fsproj, or .vbproj files under it, or pass --root.")
+    )]
+    NoProjects(PathBuf),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::outdated::no_projects",
+    cause: "`turron outdated` checks the `<PackageReference>`s in every project file under the current directory (or --root), skipping anything git ignores and `bin`/`obj` directories, and didn't find any project files.",
+    fixes: &[
+        "Run turron from your repository or solution directory.",
+        "Pass `--root` with a project file, or a directory containing some.",
+    ],
+    config: &[],
+}];
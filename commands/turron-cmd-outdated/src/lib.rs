@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use dotnet_semver::Version;
+use nuget_api::{
+    schedule::{self, Priority},
+    v3::NuGetClient,
+    NuGetApiError, SourceProtocol,
+};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    owo_colors::{colors::*, OwoColorize},
+    render::{sanitize_cell, DEFAULT_MAX_CELL_WIDTH},
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, Value},
+    smol::{self, fs},
+    tracing,
+};
+
+pub use error::{OutdatedError, EXPLANATIONS};
+use report::{Bump, Outdated, Policy};
+
+mod error;
+mod report;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "outdated"]
+pub struct OutdatedCmd {
+    #[clap(from_global)]
+    root: Option<PathBuf>,
+    #[clap(about = "Consider prereleases when looking for upgrades", long)]
+    prerelease: bool,
+    #[clap(about = "Only report upgrades to a new major version", long)]
+    major: bool,
+    #[clap(
+        about = "Only report upgrades to a new minor version of the same major version",
+        long
+    )]
+    minor: bool,
+    #[clap(
+        about = "Only report upgrades within the same major and minor version",
+        long
+    )]
+    patch: bool,
+    #[clap(
+        about = "Source to check for newer versions",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for OutdatedCmd {
+    async fn execute(self) -> Result<()> {
+        let root = env::current_dir()
+            .into_diagnostic()
+            .context("Failed to get the current directory")?
+            .join(self.root.clone().unwrap_or_default());
+        let projects = if root.is_file() {
+            vec![root.clone()]
+        } else {
+            let root = root.clone();
+            smol::unblock(move || turron_dotnet::project_files(&root)).await
+        };
+        if projects.is_empty() {
+            return Err(OutdatedError::NoProjects(root).into());
+        }
+
+        let mut references = Vec::new();
+        for project in projects {
+            let contents = fs::read_to_string(&project)
+                .await
+                .into_diagnostic()
+                .with_context(|| format!("Failed to read {}", project.display()))?;
+            for reference in turron_dotnet::package_references(&project, &contents)? {
+                references.push((project.clone(), reference));
+            }
+        }
+        let mut ids: Vec<String> = Vec::new();
+        for (_, reference) in &references {
+            if !ids.iter().any(|id| id.eq_ignore_ascii_case(&reference.id)) {
+                ids.push(reference.id.clone());
+            }
+        }
+
+        let client = Arc::new(
+            NuGetClient::from_source_as(
+                self.source.clone(),
+                self.assume_source_version.unwrap_or_default(),
+            )
+            .await?,
+        );
+        let versions = fetch_versions(client, ids).await;
+        let policy = self.policy();
+        let rows = references
+            .iter()
+            .map(|(project, reference)| {
+                let available = versions
+                    .get(&reference.id.to_lowercase())
+                    .map(|versions| &versions[..])
+                    .unwrap_or_default();
+                let outdated = Outdated::new(
+                    &reference.id,
+                    reference.version.as_deref(),
+                    available,
+                    &policy,
+                );
+                (relative(&root, project), outdated)
+            })
+            .collect::<Vec<_>>();
+
+        if self.json && !self.quiet {
+            let rows = rows
+                .iter()
+                .map(|(project, outdated)| -> Result<Value> {
+                    let mut row = serde_json::to_value(outdated)
+                        .into_diagnostic()
+                        .context("Failed to serialize outdated output into JSON")?;
+                    row["project"] = project.clone().into();
+                    Ok(row)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&rows)
+                    .into_diagnostic()
+                    .context("Failed to serialize outdated output into JSON")?
+            );
+        } else if !self.quiet {
+            let several_projects = rows.iter().any(|(project, _)| project != &rows[0].0);
+            print_table(&rows, several_projects);
+            let upgradable = rows.iter().filter(|(_, o)| o.upgrade.is_some()).count();
+            println!();
+            if upgradable == 0 {
+                println!("Everything is up to date.");
+            } else {
+                println!(
+                    "{} of {} package references can be upgraded.",
+                    upgradable.to_string().fg::<Yellow>(),
+                    rows.len()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OutdatedCmd {
+    fn policy(&self) -> Policy {
+        let mut bumps = Vec::new();
+        if self.major {
+            bumps.push(Bump::Major);
+        }
+        if self.minor {
+            bumps.push(Bump::Minor);
+        }
+        if self.patch {
+            bumps.push(Bump::Patch);
+        }
+        Policy {
+            prerelease: self.prerelease,
+            bumps,
+        }
+    }
+}
+
+/// Fetches every version of each of `ids`, keyed by lowercased id. They're
+/// all asked for at once, and the request scheduler decides how many are
+/// actually in flight. Packages that can't be found, or fail to load, are
+/// left out with a warning.
+async fn fetch_versions(
+    client: Arc<NuGetClient>,
+    ids: Vec<String>,
+) -> HashMap<String, Vec<Version>> {
+    let tasks = ids
+        .into_iter()
+        .map(|id| {
+            let client = client.clone();
+            smol::spawn(schedule::prioritized(Priority::Bulk, async move {
+                match client.versions(&id).await {
+                    Ok(versions) => Some((id.to_lowercase(), versions)),
+                    Err(NuGetApiError::PackageNotFound) => {
+                        tracing::warn!("{} wasn't found on the source", id);
+                        None
+                    }
+                    Err(err) => {
+                        tracing::warn!("Failed to get versions of {}: {}", id, err);
+                        None
+                    }
+                }
+            }))
+        })
+        .collect::<Vec<_>>();
+    let mut versions = HashMap::new();
+    for task in tasks {
+        versions.extend(task.await);
+    }
+    versions
+}
+
+/// `project`'s path relative to `root`, with `/` separators, or just its
+/// name when `root` is the project itself.
+fn relative(root: &Path, project: &Path) -> String {
+    let relative = project.strip_prefix(root).unwrap_or(project);
+    let relative = if relative.as_os_str().is_empty() {
+        project.file_name().map(Path::new).unwrap_or(project)
+    } else {
+        relative
+    };
+    relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Prints `rows` lined up in columns, with the ones that can be upgraded
+/// highlighted.
+fn print_table(rows: &[(String, Outdated)], with_project: bool) {
+    let version = |version: &Option<Version>| {
+        version
+            .as_ref()
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "-".into())
+    };
+    let mut headers = vec![
+        "package",
+        "requested",
+        "current",
+        "latest",
+        "prerelease",
+        "upgrade",
+    ];
+    if with_project {
+        headers.push("project");
+    }
+    let cells = rows
+        .iter()
+        .map(|(project, outdated)| {
+            let mut cells = vec![
+                outdated.id.clone(),
+                outdated.requested.clone().unwrap_or_else(|| "-".into()),
+                version(&outdated.current),
+                version(&outdated.latest_stable),
+                version(&outdated.latest_prerelease),
+                version(&outdated.upgrade),
+            ];
+            if with_project {
+                cells.push(project.clone());
+            }
+            cells
+                .iter()
+                .map(|cell| sanitize_cell(cell, DEFAULT_MAX_CELL_WIDTH))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let widths = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(header.len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let line = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    let headers = headers.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    println!("{}", line(&headers).bold());
+    for ((_, outdated), row) in rows.iter().zip(&cells) {
+        if outdated.upgrade.is_some() {
+            println!("{}", line(row).fg::<Yellow>());
+        } else {
+            println!("{}", line(row));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use dotnet_semver::{Range, Version};
+use serde::Serialize;
+use turron_pick_version::VersionPicker;
+
+/// Which part of a version an upgrade changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Bump {
+    Major,
+    Minor,
+    /// Anything smaller than a minor version, including revisions and
+    /// prerelease tags.
+    Patch,
+}
+
+impl Bump {
+    pub(crate) fn between(from: &Version, to: &Version) -> Self {
+        if from.major != to.major {
+            Bump::Major
+        } else if from.minor != to.minor {
+            Bump::Minor
+        } else {
+            Bump::Patch
+        }
+    }
+}
+
+/// What counts as an upgrade.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Policy {
+    /// Whether prereleases can be upgrades.
+    pub(crate) prerelease: bool,
+    /// The kinds of bumps to report. Empty means all of them.
+    pub(crate) bumps: Vec<Bump>,
+}
+
+impl Policy {
+    fn allows(&self, bump: Bump) -> bool {
+        self.bumps.is_empty() || self.bumps.contains(&bump)
+    }
+}
+
+/// How one package reference compares to what's on the source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Outdated {
+    pub(crate) id: String,
+    /// The version or range the project asks for. Centrally managed
+    /// versions don't have one.
+    pub(crate) requested: Option<String>,
+    /// What `requested` resolves to, picked the way NuGet would.
+    pub(crate) current: Option<Version>,
+    pub(crate) latest_stable: Option<Version>,
+    /// Only set when it's newer than `latest_stable`.
+    pub(crate) latest_prerelease: Option<Version>,
+    /// The newest version past `current` that `Policy` allows.
+    pub(crate) upgrade: Option<Version>,
+    pub(crate) bump: Option<Bump>,
+}
+
+impl Outdated {
+    pub(crate) fn new(
+        id: &str,
+        requested: Option<&str>,
+        versions: &[Version],
+        policy: &Policy,
+    ) -> Self {
+        let current = requested
+            .and_then(|requested| requested.parse::<Range>().ok())
+            .and_then(|range| VersionPicker::new().pick_version(&range, versions));
+        let latest_stable = versions.iter().filter(|v| !v.is_prerelease()).max();
+        let latest_prerelease = versions
+            .iter()
+            .filter(|v| v.is_prerelease())
+            .max()
+            .filter(|pre| latest_stable.map_or(true, |stable| *pre > stable));
+        let upgrade = current.as_ref().and_then(|current| {
+            versions
+                .iter()
+                .filter(|v| *v > current)
+                .filter(|v| policy.prerelease || !v.is_prerelease())
+                .filter(|v| policy.allows(Bump::between(current, v)))
+                .max()
+                .cloned()
+        });
+        let bump = match (&current, &upgrade) {
+            (Some(current), Some(upgrade)) => Some(Bump::between(current, upgrade)),
+            _ => None,
+        };
+        Outdated {
+            id: id.into(),
+            requested: requested.map(String::from),
+            current,
+            latest_stable: latest_stable.cloned(),
+            latest_prerelease: latest_prerelease.cloned(),
+            upgrade,
+            bump,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions(versions: &[&str]) -> Vec<Version> {
+        versions.iter().map(|v| v.parse().unwrap()).collect()
+    }
+
+    fn policy(prerelease: bool, bumps: &[Bump]) -> Policy {
+        Policy {
+            prerelease,
+            bumps: bumps.to_vec(),
+        }
+    }
+
+    #[test]
+    fn finds_upgrades() {
+        let available = versions(&["1.0.0", "1.0.1", "1.1.0", "2.0.0", "2.1.0-beta.1"]);
+        let outdated = Outdated::new("Foo", Some("1.0.0"), &available, &Policy::default());
+        assert_eq!(outdated.current, Some("1.0.0".parse().unwrap()));
+        assert_eq!(outdated.latest_stable, Some("2.0.0".parse().unwrap()));
+        assert_eq!(
+            outdated.latest_prerelease,
+            Some("2.1.0-beta.1".parse().unwrap())
+        );
+        assert_eq!(outdated.upgrade, Some("2.0.0".parse().unwrap()));
+        assert_eq!(outdated.bump, Some(Bump::Major));
+
+        let outdated = Outdated::new("Foo", Some("1.0.0"), &available, &policy(true, &[]));
+        assert_eq!(outdated.upgrade, Some("2.1.0-beta.1".parse().unwrap()));
+
+        let outdated = Outdated::new("Foo", Some("[2.0.0, )"), &available, &Policy::default());
+        assert_eq!(outdated.upgrade, None);
+        assert_eq!(outdated.bump, None);
+    }
+
+    #[test]
+    fn restricts_bumps() {
+        let available = versions(&["1.0.0", "1.0.1", "1.1.0", "2.0.0"]);
+        let upgrade = |bumps: &[Bump]| {
+            Outdated::new("Foo", Some("1.0.0"), &available, &policy(false, bumps))
+                .upgrade
+                .map(|v| v.to_string())
+        };
+        assert_eq!(upgrade(&[Bump::Patch]).as_deref(), Some("1.0.1"));
+        assert_eq!(upgrade(&[Bump::Minor]).as_deref(), Some("1.1.0"));
+        assert_eq!(upgrade(&[Bump::Major]).as_deref(), Some("2.0.0"));
+        assert_eq!(
+            upgrade(&[Bump::Minor, Bump::Patch]).as_deref(),
+            Some("1.1.0")
+        );
+    }
+
+    #[test]
+    fn handles_missing_versions() {
+        let available = versions(&["1.0.0-alpha", "1.0.0-beta"]);
+        let outdated = Outdated::new("Foo", None, &available, &Policy::default());
+        assert_eq!(outdated.current, None);
+        assert_eq!(outdated.latest_stable, None);
+        assert_eq!(
+            outdated.latest_prerelease,
+            Some("1.0.0-beta".parse().unwrap())
+        );
+        assert_eq!(outdated.upgrade, None);
+
+        let outdated = Outdated::new("Foo", Some("not a range"), &[], &Policy::default());
+        assert_eq!(outdated.current, None);
+    }
+}
@@ -1,24 +1,289 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use dotnet_semver::Version;
+use nuget_api::{v3::NuGetClient, SourceProtocol};
+use turron_cmd_publish::PushOptions;
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
     turron_config::TurronConfigLayer,
     TurronCommand,
 };
-use turron_common::miette::Result;
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Report, Result},
+    serde_json::{self, json, Value},
+    smol, tracing,
+};
+use turron_dotnet::{DotnetError, PackOptions, PackReport};
+use turron_nupkg::NupkgError;
 
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "pack"]
 pub struct PackCmd {
+    #[clap(about = "Project or solution file to pack (defaults to the current directory)")]
+    project: Option<PathBuf>,
+    #[clap(
+        about = "Build the package straight from this nuspec, without the dotnet CLI",
+        long,
+        value_name = "PATH",
+        conflicts_with = "project",
+        conflicts_with = "all"
+    )]
+    nuspec: Option<PathBuf>,
+    #[clap(
+        about = "Pack every packable project under --root, in project reference order",
+        long,
+        conflicts_with = "project"
+    )]
+    all: bool,
+    #[clap(
+        about = "With --all, skip projects whose path or name matches this glob",
+        long,
+        value_name = "GLOB",
+        number_of_values = 1,
+        requires = "all"
+    )]
+    exclude: Vec<glob::Pattern>,
+    #[clap(from_global)]
+    root: Option<PathBuf>,
+    #[clap(about = "Build configuration to pack, like `Release`", long, short)]
+    configuration: Option<String>,
+    #[clap(about = "Directory to write the produced packages to", long, short)]
+    output: Option<PathBuf>,
+    #[clap(
+        about = "Version to stamp on the produced package",
+        long,
+        alias = "version-override"
+    )]
+    set_version: Option<Version>,
+    #[clap(
+        about = "Derive the package version from `git describe --tags`",
+        long,
+        conflicts_with = "set-version"
+    )]
+    version_from_git: bool,
+    #[clap(
+        about = "Normalize the produced packages so identical inputs give identical bytes",
+        long
+    )]
+    deterministic: bool,
+    #[clap(
+        about = "Pack a second time and fail if the results differ",
+        long,
+        requires = "deterministic"
+    )]
+    pack_twice: bool,
+    #[clap(about = "Publish the produced packages once packing succeeds", long)]
+    publish: bool,
+    #[clap(
+        about = "Source to publish to",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Treat packages that already exist on the source as successfully published",
+        long,
+        requires = "publish"
+    )]
+    skip_duplicate: bool,
     #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
+    #[clap(from_global)]
+    #[config_layer(api_key_for = "source")]
+    api_key: Option<String>,
 }
 
 #[async_trait]
 impl TurronCommand for PackCmd {
     async fn execute(self) -> Result<()> {
-        turron_dotnet::pack().await?;
+        let version = if self.version_from_git {
+            let version = turron_dotnet::version_from_git().await?;
+            tracing::info!("Using version {} from git", version);
+            Some(version)
+        } else {
+            self.set_version.clone()
+        };
+        let reports = match &self.nuspec {
+            Some(nuspec) => {
+                let mut report = self.pack_nuspec(nuspec, version).await?;
+                self.print_report(None, &mut report);
+                vec![(None, report)]
+            }
+            None => self.pack_projects(version).await?,
+        };
+        let nupkgs = reports
+            .iter()
+            .flat_map(|(_, report)| report.nupkgs.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let published = if self.publish {
+            let client = NuGetClient::from_source_as(
+                self.source.clone(),
+                self.assume_source_version.unwrap_or_default(),
+            )
+            .await?
+            .with_key(self.api_key.clone());
+            let results = turron_cmd_publish::push_packages(
+                Arc::new(client),
+                &nupkgs,
+                &PushOptions {
+                    source: self.source.clone(),
+                    parallel: 1,
+                    skip_duplicate: self.skip_duplicate,
+                    quiet: self.quiet || self.json,
+                },
+            )
+            .await;
+            Some(results)
+        } else {
+            None
+        };
+
+        if self.json && !self.quiet {
+            let mut output = if self.all {
+                json!({
+                    "projects": reports
+                        .iter()
+                        .map(|(project, report)| {
+                            let mut json = report_json(report);
+                            json["project"] = json!(project);
+                            json
+                        })
+                        .collect::<Vec<_>>(),
+                })
+            } else {
+                report_json(&reports[0].1)
+            };
+            if let Some(results) = &published {
+                output["published"] = serde_json::to_value(results)
+                    .into_diagnostic()
+                    .context("Failed to serialize publish results into JSON")?;
+            }
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&output)
+                    .into_diagnostic()
+                    .context("Failed to serialize pack report into JSON")?
+            );
+        }
+        if let Some(results) = &published {
+            turron_cmd_publish::check_results(results)?;
+        }
         Ok(())
     }
 }
+
+impl PackCmd {
+    /// Packs the project, or with `--all`, every packable project, through
+    /// `dotnet pack`.
+    async fn pack_projects(
+        &self,
+        version: Option<Version>,
+    ) -> Result<Vec<(Option<PathBuf>, PackReport)>> {
+        let projects = if self.all {
+            let root = self.root.clone().unwrap_or_else(|| PathBuf::from("."));
+            let exclude = self.exclude.clone();
+            let (root, found) = smol::unblock(move || {
+                let found = turron_dotnet::discover(&root, &exclude);
+                (root, found)
+            })
+            .await;
+            let found = found?;
+            if found.is_empty() {
+                return Err(DotnetError::NoProjects(root).into());
+            }
+            turron_dotnet::pack_order(&found)
+                .into_iter()
+                .map(|project| Some(project.path.clone()))
+                .collect()
+        } else {
+            vec![self.project.clone()]
+        };
+
+        let mut reports = Vec::new();
+        for project in projects {
+            let opts = PackOptions {
+                project,
+                configuration: self.configuration.clone(),
+                output: self.output.clone(),
+                version: version.clone(),
+                extra_props: Vec::new(),
+            };
+            tracing::debug!("Packing with {:?}", opts);
+            let mut report = if self.deterministic {
+                turron_dotnet::pack_deterministic(&opts, self.pack_twice).await?
+            } else {
+                turron_dotnet::pack(&opts).await?
+            };
+            self.print_report(opts.project.as_deref(), &mut report);
+            reports.push((opts.project, report));
+        }
+        Ok(reports)
+    }
+
+    /// Builds the package described by `--nuspec` without going through
+    /// the dotnet CLI. Files are looked up relative to the nuspec, and the
+    /// package goes in `--output`, or the current directory.
+    async fn pack_nuspec(&self, path: &Path, version: Option<Version>) -> Result<PackReport> {
+        let path = path.to_path_buf();
+        let output = self.output.clone().unwrap_or_else(|| PathBuf::from("."));
+        let (nupkg, version) = smol::unblock(move || -> Result<_, NupkgError> {
+            let mut nuspec = turron_nupkg::read_nuspec(&path)?;
+            if let Some(version) = version {
+                nuspec.metadata.version = version;
+            }
+            let base = path.parent().unwrap_or_else(|| Path::new("."));
+            let files = turron_nupkg::resolve_files(&nuspec, base)?;
+            fs::create_dir_all(&output).map_err(|e| NupkgError::WriteFailed(output.clone(), e))?;
+            let nupkg = output.join(turron_nupkg::nupkg_file_name(&nuspec));
+            turron_nupkg::build_nupkg(&nuspec, &files, &nupkg)?;
+            Ok((nupkg, nuspec.metadata.version))
+        })
+        .await?;
+        Ok(PackReport {
+            nupkgs: vec![nupkg],
+            version: Some(version),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Prints what got packed, and any warnings along the way.
+    fn print_report(&self, project: Option<&Path>, report: &mut PackReport) {
+        if self.json || self.quiet {
+            return;
+        }
+        for warning in std::mem::take(&mut report.warnings) {
+            eprintln!("{:?}", Report::new(warning));
+        }
+        match (project, &report.version) {
+            (Some(project), Some(version)) if self.all => {
+                println!("Packed {} version {}.", project.display(), version)
+            }
+            (_, Some(version)) => println!("Packed version {}.", version),
+            _ => {}
+        }
+        for nupkg in &report.nupkgs {
+            println!("{}", nupkg.display());
+        }
+    }
+}
+
+fn report_json(report: &PackReport) -> Value {
+    json!({
+        "version": report.version.as_ref().map(|v| v.to_string()),
+        "nupkgs": report.nupkgs,
+        "warnings": report
+            .warnings
+            .iter()
+            .map(|w| json!({ "code": w.code, "message": w.message }))
+            .collect::<Vec<_>>(),
+    })
+}
@@ -1,10 +1,16 @@
+use std::io::{self, Write};
 use std::time::{Duration, Instant};
 
-use nuget_api::v3::NuGetClient;
+use nuget_api::{
+    v3::{IndexResource, NuGetClient},
+    SourceProtocol,
+};
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
     indicatif::ProgressBar,
+    owo_colors::OwoColorize,
+    render::sanitize,
     turron_config::TurronConfigLayer,
     TurronCommand,
 };
@@ -24,6 +30,9 @@ pub struct PingCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
@@ -46,21 +55,73 @@ impl TurronCommand for PingCmd {
                 Timer::after(Duration::from_millis(20)).await;
             }
         });
-        let client = NuGetClient::from_source(self.source.clone()).await?;
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
         let time = start.elapsed().as_micros() as f32 / 1000.0;
         if !self.quiet && self.json {
             let output = serde_json::to_string_pretty(&json!({
                 "source": self.source.to_string(),
                 "time": time,
                 "endpoints": client.endpoints,
+                "resources": client.resources,
             }))
             .into_diagnostic()
             .context("Failed to serialize JSON ping output.")?;
             println!("{}", output);
         }
-        spinner.println(format!("pong: {}ms", time));
+        let mut report = Vec::new();
+        write_pong(&mut report, time, &client.resources)
+            .into_diagnostic()
+            .context("Failed to write ping output.")?;
+        for line in String::from_utf8_lossy(&report).lines() {
+            spinner.println(line);
+        }
         spinner.finish();
         fut.await;
         Ok(())
     }
 }
+
+/// Writes how long the source took to answer, and warnings for any of its
+/// resources whose comments look important.
+fn write_pong(out: &mut impl Write, time: f32, resources: &[IndexResource]) -> io::Result<()> {
+    writeln!(out, "pong: {}ms", time)?;
+    for resource in resources.iter().filter(|r| r.has_notable_comment()) {
+        writeln!(
+            out,
+            "{} {}: {}",
+            "warning:".yellow(),
+            sanitize(&resource.restype),
+            sanitize(resource.comment.as_deref().unwrap_or_default()),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nuget_api::v3::Index;
+    use turron_testing::{assert_snapshot, fixtures, snapshot};
+
+    use super::*;
+
+    #[test]
+    fn renders_pong_and_warnings() {
+        let mut resources = serde_json::from_value::<Index>(fixtures::service_index())
+            .unwrap()
+            .resources;
+        resources.push(
+            serde_json::from_value(json!({
+                "@id": "https://api.nuget.org/v3/catalog0/index.json",
+                "@type": "Catalog/3.0.0",
+                "comment": "Deprecated. Rate limited to 100 requests a minute.",
+            }))
+            .unwrap(),
+        );
+        let out = snapshot::render(|out, _| write_pong(out, 123.456, &resources));
+        assert_snapshot!("ping", out);
+    }
+}
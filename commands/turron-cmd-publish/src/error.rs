@@ -0,0 +1,71 @@
+use std::path::PathBuf;
+
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Debug, Diagnostic, Error)]
+pub enum PublishError {
+    #[error("No packages {}.", if .0.is_empty() { "were given".into() } else { format!("matched {}", .0) })]
+    #[diagnostic(
+        code(turron::publish::no_packages),
+        help("Pass the .nupkg files to publish, or a glob like `bin/Release/*.nupkg`. Symbol packages (.snupkg) are only included with --symbols.")
+    )]
+    NoPackages(String),
+
+    #[error("`{0}` is not a valid glob pattern.")]
+    #[diagnostic(code(turron::publish::invalid_pattern))]
+    InvalidPattern(String, #[source] glob::PatternError),
+
+    #[error("{} hasn't been packed: there's no {expected} under its bin directory.", .project.display())]
+    #[diagnostic(
+        code(turron::publish::not_packed),
+        help("Run `turron pack --all` first. Packages left over from older versions aren't published.")
+    )]
+    NotPacked { project: PathBuf, expected: String },
+
+    #[error("{failed} of {total} packages failed to publish.")]
+    #[diagnostic(code(turron::publish::push_failed))]
+    PushFailed { failed: usize, total: usize },
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::publish::no_packages",
+        cause: "None of the paths or glob patterns given to `turron publish` matched a package, or none were given at all. turron doesn't guess which packages to publish.",
+        fixes: &[
+            "Pack first, then point `turron publish` at the output, e.g. `bin/Release/*.nupkg`.",
+            "Pass `--all` to publish the package built by each project under --root.",
+            "Pass `--symbols` if you meant to publish .snupkg files.",
+            "With `--all`, run `turron pack --all` first.",
+        ],
+        config: &["commands.publish.nupkgs"],
+    },
+    Explanation {
+        code: "turron::publish::invalid_pattern",
+        cause: "An argument to `turron publish` looked like a glob pattern, but couldn't be parsed as one. Unclosed `[` brackets are the usual culprit.",
+        fixes: &["Quote or fix the pattern, or pass the file path directly."],
+        config: &["commands.publish.nupkgs"],
+    },
+    Explanation {
+        code: "turron::publish::not_packed",
+        cause: "`turron publish --all` only publishes the package built from each project's current package id and version, and one of the projects doesn't have one under its bin directory. Packages with another version, like ones from an earlier build, don't count.",
+        fixes: &[
+            "Run `turron pack --all` before publishing.",
+            "If the project sets <PackageId> or <Version> somewhere turron can't see, like a Directory.Build.props, publish its packages by path instead.",
+            "Pass `--exclude` to skip projects that aren't meant to be published.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::publish::push_failed",
+        cause: "At least one package failed to upload. The per-package output above says what went wrong with each one; the rest were published.",
+        fixes: &[
+            "Fix the reported problems and publish the failed packages again.",
+            "Pass `--skip-duplicate` if some packages were already published by an earlier run.",
+        ],
+        config: &[],
+    },
+];
@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use turron_common::miette;
+use turron_dotnet::{DotnetError, Project};
+
+use crate::error::PublishError;
+
+/// Turns the paths and glob patterns passed to `turron publish` into the
+/// list of packages to push. Symbol packages are dropped unless `symbols` is
+/// set. Nothing is published without at least one pattern, so a stray
+/// `turron publish` can't push whatever happens to be lying around.
+pub(crate) fn expand(patterns: &[String], symbols: bool) -> Result<Vec<PathBuf>, PublishError> {
+    if patterns.is_empty() {
+        return Err(PublishError::NoPackages(String::new()));
+    }
+    let mut packages = Vec::new();
+    for pattern in patterns {
+        if is_glob(pattern) {
+            let mut matches = glob::glob(pattern)
+                .map_err(|e| PublishError::InvalidPattern(pattern.clone(), e))?
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+            matches.sort();
+            packages.extend(matches);
+        } else {
+            packages.push(PathBuf::from(pattern));
+        }
+    }
+    packages.retain(|path| symbols || !is_symbol_package(path));
+    let mut seen = std::collections::HashSet::new();
+    packages.retain(|path| seen.insert(path.clone()));
+    if packages.is_empty() {
+        return Err(PublishError::NoPackages(patterns.join(", ")));
+    }
+    Ok(packages)
+}
+
+/// Finds the package built for each packable project under `root`, for
+/// `turron publish --all`. A project without one is an error, rather than
+/// something to skip, so nothing gets half-published.
+pub(crate) fn from_projects(
+    root: &Path,
+    exclude: &[glob::Pattern],
+) -> miette::Result<Vec<PathBuf>> {
+    let projects = turron_dotnet::discover(root, exclude)?;
+    if projects.is_empty() {
+        return Err(DotnetError::NoProjects(root.into()).into());
+    }
+    let mut packages = Vec::new();
+    for project in &projects {
+        match turron_dotnet::built_nupkg(project) {
+            Some(nupkg) => packages.push(nupkg),
+            None => {
+                return Err(PublishError::NotPacked {
+                    project: project.path.clone(),
+                    expected: expected_name(project),
+                }
+                .into())
+            }
+        }
+    }
+    Ok(packages)
+}
+
+/// The file name `dotnet pack` gives `project`'s package.
+fn expected_name(project: &Project) -> String {
+    match &project.version {
+        Some(version) => format!("{}.{}.nupkg", project.package_id, version),
+        None => format!("{}.<version>.nupkg", project.package_id),
+    }
+}
+
+pub(crate) fn is_symbol_package(path: &std::path::Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("snupkg"))
+        .unwrap_or(false)
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(|c| matches!(c, '*' | '?' | '['))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn touch(dir: &std::path::Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"").unwrap();
+        path
+    }
+
+    #[test]
+    fn expands_globs() {
+        let dir = tempdir().unwrap();
+        let b = touch(dir.path(), "bin/Release/B.1.0.0.nupkg");
+        let a = touch(dir.path(), "bin/Release/A.1.0.0.nupkg");
+        let sym = touch(dir.path(), "bin/Release/A.1.0.0.snupkg");
+        touch(dir.path(), "bin/Release/A.dll");
+        let pattern = format!("{}/bin/Release/*", dir.path().display());
+
+        assert_eq!(
+            expand(&[pattern.clone()], false).unwrap(),
+            vec![a.clone(), b.clone()]
+        );
+        assert_eq!(
+            expand(&[pattern.clone()], true).unwrap(),
+            vec![a.clone(), sym, b.clone()]
+        );
+        // Overlapping patterns don't push anything twice.
+        assert_eq!(
+            expand(&[pattern, a.display().to_string()], false).unwrap(),
+            vec![a, b]
+        );
+    }
+
+    #[test]
+    fn passes_plain_paths_through() {
+        assert_eq!(
+            expand(&["does/not/exist.nupkg".into()], false).unwrap(),
+            vec![PathBuf::from("does/not/exist.nupkg")]
+        );
+    }
+
+    #[test]
+    fn empty_matches_are_an_error() {
+        let dir = tempdir().unwrap();
+        touch(dir.path(), "Foo.1.0.0.snupkg");
+        let pattern = format!("{}/*.nupkg", dir.path().display());
+        assert!(matches!(
+            expand(&[pattern], false),
+            Err(PublishError::NoPackages(_))
+        ));
+        let pattern = format!("{}/*", dir.path().display());
+        assert!(matches!(
+            expand(&[pattern], false),
+            Err(PublishError::NoPackages(_))
+        ));
+        assert!(matches!(
+            expand(&[], true),
+            Err(PublishError::NoPackages(patterns)) if patterns.is_empty()
+        ));
+        assert!(matches!(
+            expand(&["[oops".into()], false),
+            Err(PublishError::InvalidPattern(..))
+        ));
+    }
+}
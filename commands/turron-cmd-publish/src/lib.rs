@@ -1,24 +1,55 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use nuget_api::v3::{Body, NuGetClient};
+use nuget_api::{v3::NuGetClient, NuGetApiError, SourceProtocol};
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
-    indicatif::ProgressBar,
+    fmt::Numbers,
+    indicatif::{ProgressBar, ProgressStyle},
     turron_config::TurronConfigLayer,
     TurronCommand,
 };
 use turron_common::{
     miette::{Context, IntoDiagnostic, Result},
-    smol::{self, Timer},
+    serde::Serialize,
+    serde_json,
+    smol::{self, channel, Timer},
     tracing,
 };
 
+pub use error::{PublishError, EXPLANATIONS};
+
+mod error;
+mod files;
+
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "publish"]
 pub struct PublishCmd {
-    #[clap(about = "Specific packages to publish, if not the current path")]
-    nupkgs: Vec<PathBuf>,
+    #[clap(about = "Packages or glob patterns to publish, like `bin/Release/*.nupkg`")]
+    nupkgs: Vec<String>,
+    #[clap(
+        about = "Publish the latest package built by each packable project under --root",
+        long,
+        conflicts_with = "nupkgs"
+    )]
+    all: bool,
+    #[clap(
+        about = "With --all, skip projects whose path or name matches this glob",
+        long,
+        value_name = "GLOB",
+        number_of_values = 1,
+        requires = "all"
+    )]
+    exclude: Vec<glob::Pattern>,
+    #[clap(from_global)]
+    root: Option<PathBuf>,
     #[clap(
         about = "Source to ping",
         default_value = "https://api.nuget.org/v3/index.json",
@@ -26,46 +57,248 @@ pub struct PublishCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(about = "Also publish symbol packages (.snupkg)", long)]
+    symbols: bool,
+    #[clap(
+        about = "Number of packages to push at the same time",
+        default_value = "1",
+        long
+    )]
+    parallel: usize,
+    #[clap(
+        about = "Treat packages that already exist on the source as successfully published",
+        long
+    )]
+    skip_duplicate: bool,
+    #[clap(from_global)]
     verbosity: tracing::Level,
     #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
     #[clap(from_global)]
+    #[config_layer(api_key_for = "source")]
     api_key: Option<String>,
 }
 
+/// How publishing a single package went.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PushStatus {
+    Published,
+    SkippedDuplicate,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PushResult {
+    pub path: PathBuf,
+    pub status: PushStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How to push packages. Shared with `turron pack --publish`.
+#[derive(Debug, Clone)]
+pub struct PushOptions {
+    /// The source being pushed to, for display.
+    pub source: String,
+    /// Number of packages to push at the same time.
+    pub parallel: usize,
+    pub skip_duplicate: bool,
+    /// Hide the progress spinner and per-package lines.
+    pub quiet: bool,
+}
+
 #[async_trait]
 impl TurronCommand for PublishCmd {
     async fn execute(self) -> Result<()> {
-        let spinner = if self.quiet || self.json {
-            ProgressBar::hidden()
+        let packages = if self.all {
+            let root = self.root.clone().unwrap_or_else(|| PathBuf::from("."));
+            let exclude = self.exclude.clone();
+            smol::unblock(move || files::from_projects(&root, &exclude)).await?
         } else {
-            ProgressBar::new_spinner()
+            files::expand(&self.nupkgs, self.symbols)?
         };
-        let spin_clone = spinner.clone();
-        let spin_fut = smol::spawn(async move {
-            while !spin_clone.is_finished() {
-                spin_clone.tick();
-                Timer::after(Duration::from_millis(20)).await;
-            }
-        });
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?
+        .with_key(self.api_key.clone());
+        let results = push_packages(
+            Arc::new(client),
+            &packages,
+            &PushOptions {
+                source: self.source.clone(),
+                parallel: self.parallel,
+                skip_duplicate: self.skip_duplicate,
+                quiet: self.quiet || self.json,
+            },
+        )
+        .await;
+
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&results)
+                    .into_diagnostic()
+                    .context("Failed to serialize publish results into JSON")?
+            );
+        }
+        check_results(&results)?;
+        Ok(())
+    }
+}
 
-        let client = NuGetClient::from_source(self.source.clone())
-            .await?
-            .with_key(self.api_key);
-        let body = Body::from_file(&self.nupkgs[0])
-            .await
-            .into_diagnostic()
-            .context("Failed to open provided nupkg")?;
+/// Pushes `packages` with `client`, showing progress as it goes. Failures
+/// are reported per package rather than stopping the rest; pass the results
+/// to [`check_results`] to turn them into an error.
+pub async fn push_packages(
+    client: Arc<NuGetClient>,
+    packages: &[PathBuf],
+    opts: &PushOptions,
+) -> Vec<PushResult> {
+    let spinner = if opts.quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    // One bar for every byte being pushed, whichever package it's from.
+    spinner.set_style(ProgressStyle::default_bar().template(&format!(
+        "{{spinner}} [{{bar:30}}] {}",
+        Numbers::current().transfer_template()
+    )));
+    spinner.set_length(
+        packages
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|meta| meta.len())
+            .sum(),
+    );
+    let spin_clone = spinner.clone();
+    let spin_fut = smol::spawn(async move {
+        while !spin_clone.is_finished() {
+            spin_clone.tick();
+            Timer::after(Duration::from_millis(20)).await;
+        }
+    });
 
-        spinner.println(format!("Uploading nupkg to {}...", self.source));
+    spinner.println(format!(
+        "Uploading {} package(s) to {}...",
+        packages.len(),
+        opts.source
+    ));
 
-        client.push(body).await?;
+    // Workers pull packages off a shared queue, so at most `parallel`
+    // pushes are in flight at once.
+    let (tx, rx) = channel::unbounded();
+    for (i, path) in packages.iter().cloned().enumerate() {
+        tx.try_send((i, path))
+            .expect("TURRON BUG: unbounded channel refused a package");
+    }
+    drop(tx);
+    let workers = (0..opts.parallel.max(1))
+        .map(|_| {
+            let rx = rx.clone();
+            let client = client.clone();
+            let skip_duplicate = opts.skip_duplicate;
+            let spinner = spinner.clone();
+            smol::spawn(async move {
+                let mut results = Vec::new();
+                while let Ok((i, path)) = rx.recv().await {
+                    let res = push_one(&client, &path, skip_duplicate, &spinner).await;
+                    match &res {
+                        Ok(PushStatus::SkippedDuplicate) => spinner
+                            .println(format!("- {}: already exists, skipping", path.display())),
+                        Ok(_) => spinner.println(format!("✓ {}", path.display())),
+                        Err(err) => spinner.println(format!("✗ {}: {}", path.display(), err)),
+                    }
+                    results.push((i, path, res));
+                }
+                results
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut results = Vec::new();
+    for worker in workers {
+        results.extend(worker.await);
+    }
+    results.sort_by_key(|(i, _, _)| *i);
+    let results = results
+        .into_iter()
+        .map(|(_, path, res)| match res {
+            Ok(status) => PushResult {
+                path,
+                status,
+                error: None,
+            },
+            Err(err) => PushResult {
+                path,
+                status: PushStatus::Failed,
+                error: Some(err.to_string()),
+            },
+        })
+        .collect::<Vec<_>>();
 
-        spinner.println("...package upload succeeded.");
-        spinner.finish();
-        spin_fut.await;
-        Ok(())
+    let failed = count(&results, |s| matches!(s, PushStatus::Failed));
+    let skipped = count(&results, |s| matches!(s, PushStatus::SkippedDuplicate));
+    spinner.println(format!(
+        "...{} published, {} skipped, {} failed.",
+        results.len() - failed - skipped,
+        skipped,
+        failed
+    ));
+    spinner.finish();
+    spin_fut.await;
+    results
+}
+
+/// Fails if any package in `results` failed to push.
+pub fn check_results(results: &[PushResult]) -> Result<(), PublishError> {
+    let failed = count(results, |s| matches!(s, PushStatus::Failed));
+    if failed > 0 {
+        return Err(PublishError::PushFailed {
+            failed,
+            total: results.len(),
+        });
+    }
+    Ok(())
+}
+
+fn count(results: &[PushResult], status: fn(&PushStatus) -> bool) -> usize {
+    results.iter().filter(|res| status(&res.status)).count()
+}
+
+async fn push_one(
+    client: &NuGetClient,
+    path: &PathBuf,
+    skip_duplicate: bool,
+    bar: &ProgressBar,
+) -> Result<PushStatus> {
+    let (bar, pushed) = (bar.clone(), AtomicU64::new(0));
+    let progress = move |sent: u64, _: u64| {
+        // A retried push starts over, so take back what it had sent.
+        let before = pushed.swap(sent, Ordering::Relaxed);
+        if sent >= before {
+            bar.inc(sent - before);
+        } else {
+            bar.set_position(bar.position().saturating_sub(before - sent));
+        }
+    };
+    let res = if files::is_symbol_package(path) {
+        client.push_symbols(path, progress).await
+    } else {
+        client.push(path, progress).await
+    };
+    match res {
+        Ok(()) => Ok(PushStatus::Published),
+        Err(NuGetApiError::PackageAlreadyExists) if skip_duplicate => {
+            tracing::info!("{} already exists, skipping", path.display());
+            Ok(PushStatus::SkippedDuplicate)
+        }
+        Err(err) => Err(err.into()),
     }
 }
@@ -1,4 +1,4 @@
-use nuget_api::v3::NuGetClient;
+use nuget_api::{v3::NuGetClient, SourceProtocol};
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
@@ -12,8 +12,8 @@ use turron_common::{miette::Result, thiserror::Error};
 pub struct RelistCmd {
     #[clap(about = "ID of package to relist")]
     id: String,
-    #[clap(about = "Version of package to relist")]
-    version: String,
+    #[clap(about = "Versions of package to relist", required = true)]
+    versions: Vec<String>,
     #[clap(
         about = "Source for package",
         default_value = "https://api.nuget.org/v3/index.json",
@@ -21,22 +21,36 @@ pub struct RelistCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
     #[clap(from_global)]
+    #[config_layer(api_key_for = "source")]
     api_key: Option<String>,
 }
 
 #[async_trait]
 impl TurronCommand for RelistCmd {
     async fn execute(self) -> Result<()> {
-        let client = NuGetClient::from_source(self.source.clone())
-            .await?
-            .with_key(self.api_key);
-        client.relist(self.id.clone(), self.version.clone()).await?;
-        if !self.quiet {
-            println!("{}@{} has been relisted. This may take several hours to process.", self.id, self.version);
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?
+        .with_key(self.api_key);
+        // One client for every version, so the service index is only
+        // fetched once.
+        for version in &self.versions {
+            client.relist(&self.id, version).await?;
+            if !self.quiet {
+                println!(
+                    "{}@{} has been relisted. This may take several hours to process.",
+                    self.id, version
+                );
+            }
         }
         Ok(())
     }
@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum RemoveError {
+    #[error("{} doesn't reference {id}.{}", .location.display(), did_you_mean(.candidates))]
+    #[diagnostic(
+        code(turron::remove::not_referenced),
+        help("Check the spelling of the package id. Package ids are matched ignoring case.")
+    )]
+    NotReferenced {
+        id: String,
+        /// The project, or with `--all-projects`, the solution.
+        location: PathBuf,
+        candidates: Vec<String>,
+    },
+}
+
+fn did_you_mean(candidates: &[String]) -> String {
+    if candidates.is_empty() {
+        String::new()
+    } else {
+        format!(" Did you mean one of these? {}", candidates.join(", "))
+    }
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::remove::not_referenced",
+    cause: "`turron remove` only removes `<PackageReference>`s written directly in the project file, and none of them were for this package. References that come from Directory.Build.props, Directory.Packages.props, or other imports aren't touched.",
+    fixes: &[
+        "Check the spelling of the package id against the suggestions, if there are any.",
+        "Pass `--all-projects` to look through every project in the solution.",
+        "Remove references from imported props files by hand.",
+    ],
+    config: &["commands.remove.all_projects"],
+}];
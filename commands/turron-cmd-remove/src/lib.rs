@@ -0,0 +1,207 @@
+use std::env;
+use std::path::PathBuf;
+
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    owo_colors::{colors::*, OwoColorize},
+    render::sanitize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json},
+    smol::{self, fs},
+};
+use turron_dotnet::{DotnetError, RemovedReference};
+
+pub use error::{RemoveError, EXPLANATIONS};
+
+mod error;
+
+/// Most close matches suggested for a package that isn't referenced.
+const MAX_CANDIDATES: usize = 5;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "remove"]
+pub struct RemoveCmd {
+    #[clap(about = "Id of the package to remove, like `Newtonsoft.Json`")]
+    package: String,
+    #[clap(from_global)]
+    root: Option<PathBuf>,
+    #[clap(
+        about = "Remove the package from every project in the closest solution (.sln)",
+        long
+    )]
+    all_projects: bool,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for RemoveCmd {
+    async fn execute(self) -> Result<()> {
+        let package_id = self.package.trim().to_string();
+        let start = env::current_dir()
+            .into_diagnostic()
+            .context("Failed to get the current directory")?
+            .join(self.root.clone().unwrap_or_default());
+        let all_projects = self.all_projects;
+        let (location, projects) = smol::unblock(move || -> Result<_, DotnetError> {
+            if all_projects {
+                let solution = turron_dotnet::find_solution(&start)?;
+                let projects = turron_dotnet::solution_projects(&solution)?;
+                Ok((solution, projects))
+            } else {
+                let project = turron_dotnet::find_project(&start)?;
+                Ok((project.clone(), vec![project]))
+            }
+        })
+        .await?;
+
+        let mut removed = Vec::new();
+        let mut referenced = Vec::new();
+        for project in projects {
+            let contents = fs::read_to_string(&project)
+                .await
+                .into_diagnostic()
+                .with_context(|| format!("Failed to read {}", project.display()))?;
+            match turron_dotnet::remove_package_reference(&project, &contents, &package_id)? {
+                Some((edited, references)) => {
+                    fs::write(&project, edited)
+                        .await
+                        .into_diagnostic()
+                        .with_context(|| format!("Failed to write {}", project.display()))?;
+                    removed.push((project, references));
+                }
+                None => referenced.extend(
+                    turron_dotnet::package_references(&project, &contents)?
+                        .into_iter()
+                        .map(|reference| reference.id),
+                ),
+            }
+        }
+        if removed.is_empty() {
+            return Err(RemoveError::NotReferenced {
+                candidates: close_matches(&package_id, &referenced),
+                id: package_id,
+                location,
+            }
+            .into());
+        }
+
+        if self.json && !self.quiet {
+            let removed = removed
+                .iter()
+                .flat_map(|(project, references)| {
+                    references.iter().map(move |reference| {
+                        json!({
+                            "id": reference.id,
+                            "version": reference.version,
+                            "file": project,
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&removed)
+                    .into_diagnostic()
+                    .context("Failed to serialize remove output into JSON")?
+            );
+        } else if !self.quiet {
+            for (project, references) in &removed {
+                if let [RemovedReference { id, version }] = &references[..] {
+                    match version {
+                        Some(version) => println!(
+                            "Removed {} {} from {}",
+                            sanitize(id).fg::<BrightCyan>(),
+                            sanitize(version).fg::<Yellow>(),
+                            project.display()
+                        ),
+                        None => println!(
+                            "Removed {} from {}",
+                            sanitize(id).fg::<BrightCyan>(),
+                            project.display()
+                        ),
+                    }
+                    continue;
+                }
+                // Usually one per target framework, each in its own
+                // conditional <ItemGroup>.
+                let versions = references
+                    .iter()
+                    .map(|reference| sanitize(reference.version.as_deref().unwrap_or("no version")))
+                    .collect::<Vec<_>>();
+                println!(
+                    "Removed {} references to {} ({}) from {}",
+                    references.len(),
+                    sanitize(&references[0].id).fg::<BrightCyan>(),
+                    versions.join(", ").fg::<Yellow>(),
+                    project.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Referenced ids that look like typos of `id`, or contain it, closest
+/// first.
+fn close_matches(id: &str, referenced: &[String]) -> Vec<String> {
+    let id = id.to_lowercase();
+    let threshold = std::cmp::max(2, id.chars().count() / 3);
+    let mut matches = referenced
+        .iter()
+        .filter_map(|candidate| {
+            let lower = candidate.to_lowercase();
+            let distance = strsim::levenshtein(&id, &lower);
+            if distance <= threshold || lower.contains(&id) {
+                Some((distance, candidate.clone()))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    // Stable, so equally close ids keep the order the projects have them in.
+    matches.sort_by_key(|(distance, _)| *distance);
+    let mut candidates: Vec<String> = Vec::new();
+    for (_, candidate) in matches {
+        if !candidates
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(&candidate))
+        {
+            candidates.push(candidate);
+        }
+    }
+    candidates.truncate(MAX_CANDIDATES);
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_close_matches() {
+        let referenced = vec![
+            "Newtonsoft.Json".to_string(),
+            "Serilog".into(),
+            "Serilog.Sinks.Console".into(),
+            "NEWTONSOFT.JSON".into(),
+            "Polly".into(),
+        ];
+        assert_eq!(
+            close_matches("Newtonsoft.Jsn", &referenced),
+            vec!["Newtonsoft.Json"]
+        );
+        assert_eq!(
+            close_matches("serilog.sinks", &referenced),
+            vec!["Serilog.Sinks.Console"]
+        );
+        assert!(close_matches("Dapper", &referenced).is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum SearchError {
+    #[error("No package has the id `{0}`.")]
+    #[diagnostic(
+        code(turron::search::no_exact_match),
+        help("Drop --exact to see packages whose ids only partly match.")
+    )]
+    NoExactMatch(String),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::search::no_exact_match",
+    cause: "`--exact` only keeps results whose id is the whole query, ignoring case. None of the results on the requested page had that id.",
+    fixes: &[
+        "Check the spelling of the package id.",
+        "Search again without --exact to see similarly named packages.",
+        "If the package is a pre-release only, add `--prerelease true`.",
+    ],
+    config: &[],
+}];
@@ -1,11 +1,17 @@
-use std::{collections::HashMap, time::Duration};
+use std::io::{self, Write};
+use std::time::Duration;
 
-use nu_table::{draw_table, StyledString, Table, TextStyle, Theme};
-use nuget_api::v3::{NuGetClient, SearchQuery};
+use dotnet_semver::Version;
+use nuget_api::{
+    v3::{LenientVersion, NuGetClient, SearchQuery, SearchResponse, SearchResult},
+    SourceProtocol,
+};
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
+    fmt::Numbers,
     indicatif::ProgressBar,
+    render::{self, DEFAULT_MAX_CELL_WIDTH},
     turron_config::TurronConfigLayer,
     TurronCommand,
 };
@@ -15,6 +21,21 @@ use turron_common::{
     smol::{self, Timer},
 };
 
+pub use error::{SearchError, EXPLANATIONS};
+
+mod error;
+
+/// Columns `--columns` can pick from.
+const COLUMNS: &[&str] = &[
+    "id",
+    "verified",
+    "version",
+    "downloads",
+    "authors",
+    "description",
+];
+const DEFAULT_COLUMNS: &[&str] = &["id", "verified", "version", "downloads", "description"];
+
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "search"]
 pub struct SearchCmd {
@@ -27,6 +48,9 @@ pub struct SearchCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
@@ -34,10 +58,62 @@ pub struct SearchCmd {
     take: Option<usize>,
     #[clap(about = "Number of results to skip.", long)]
     skip: Option<usize>,
+    #[clap(
+        about = "Fetch every page of results instead of just the first. --take limits the total",
+        long
+    )]
+    all: bool,
     #[clap(about = "Include pre-releases", long)]
     prerelease: Option<bool>,
     #[clap(about = "Package type to filter by", long = "type")]
     package_type: Option<String>,
+    #[clap(
+        about = "Only show packages usable from this target framework (e.g. net6.0)",
+        long
+    )]
+    framework: Option<String>,
+    #[clap(
+        about = "Show full descriptions, one result at a time, instead of a table",
+        long
+    )]
+    full_descriptions: bool,
+    #[clap(about = "Maximum width of a table cell before it gets truncated", long)]
+    max_cell_width: Option<usize>,
+    #[clap(
+        about = "Only show packages whose latest version is this or newer",
+        long
+    )]
+    min_version: Option<Version>,
+    #[clap(
+        about = "Only show packages whose latest version is this or older",
+        long
+    )]
+    max_version: Option<Version>,
+    #[clap(
+        about = "Keep packages whose version can't be parsed when filtering by version",
+        long
+    )]
+    show_unparseable: bool,
+    #[clap(
+        about = "Only show the package whose id is exactly the query, ignoring case",
+        long
+    )]
+    exact: bool,
+    #[clap(
+        about = "How to order results",
+        long,
+        default_value = "relevance",
+        possible_values = &["relevance", "downloads", "id"]
+    )]
+    sort: String,
+    #[clap(
+        about = "Comma-separated table columns to show",
+        long,
+        use_delimiter = true,
+        require_delimiter = true,
+        possible_values = COLUMNS
+    )]
+    columns: Vec<String>,
 }
 
 #[async_trait]
@@ -56,21 +132,56 @@ impl TurronCommand for SearchCmd {
             }
         });
 
-        let client = NuGetClient::from_source(self.source.clone()).await?;
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
 
-        let query = SearchQuery {
+        let mut query = SearchQuery {
             query: Some(self.query.join(" ")),
             skip: self.skip,
             take: self.take,
             prerelease: self.prerelease,
             package_type: self.package_type,
+            framework: self.framework.clone(),
         };
 
-        let response = client.search(query).await?;
+        let mut response = if self.all {
+            query.take = None;
+            let progress = spinner.clone();
+            client
+                .search_all(query, self.take, move |fetched, total| {
+                    progress.set_message(format!("fetched {} / {}", fetched, total))
+                })
+                .await?
+        } else {
+            client.search(query).await?
+        };
+        let version_filtered = self.min_version.is_some() || self.max_version.is_some();
+        if version_filtered {
+            let (min, max) = (self.min_version.as_ref(), self.max_version.as_ref());
+            let show_unparseable = self.show_unparseable;
+            response
+                .data
+                .retain(|result| in_version_range(&result.version, min, max, show_unparseable));
+        }
+
+        if self.exact {
+            let id = self.query.join(" ");
+            response
+                .data
+                .retain(|result| result.id.eq_ignore_ascii_case(&id));
+        }
+        sort_results(&mut response.data, &self.sort);
 
         spinner.finish();
         spin_fut.await;
 
+        if self.exact && response.data.is_empty() {
+            return Err(SearchError::NoExactMatch(self.query.join(" ")).into());
+        }
+
         if !self.quiet && self.json {
             println!(
                 "{}",
@@ -79,36 +190,286 @@ impl TurronCommand for SearchCmd {
                     .context("Failed to serialize response back into JSON")?
             );
         } else if !self.quiet {
-            let headers = vec!["id", "version", "description"]
-                .iter()
-                .map(|h| StyledString::new(h.to_string(), TextStyle::default_header()))
-                .collect::<Vec<StyledString>>();
-            let rows = response
-                .data
-                .iter()
-                .map(|row| {
-                    vec![
-                        StyledString::new(row.id.clone(), TextStyle::basic_left()),
-                        StyledString::new(row.version.clone(), TextStyle::basic_left()),
-                        StyledString::new(
-                            row.description.clone().unwrap_or_else(|| "".into()),
-                            TextStyle::basic_left(),
-                        ),
-                    ]
-                })
-                .collect::<Vec<Vec<StyledString>>>();
             let width = if let Some((w, _)) = term_size::dimensions() {
                 w
             } else {
                 80
             };
-            let table = Table::new(headers, rows, Theme::rounded());
-            let color_hm: HashMap<String, nu_ansi_term::Style> = HashMap::new();
-            let output_table = draw_table(&table, width, &color_hm);
-            // Draw the table
-            println!("{}", output_table);
-            println!("Total hits: {}", response.total_hits);
+            let stdout = io::stdout();
+            self.write_results(
+                &mut stdout.lock(),
+                width,
+                &response,
+                Numbers::current(),
+                version_filtered,
+            )
+            .into_diagnostic()
+            .context("Failed to write search results")?;
+        }
+        Ok(())
+    }
+}
+
+impl SearchCmd {
+    /// Writes the results table (or records, with `--full-descriptions`)
+    /// and the notes that go under it.
+    fn write_results(
+        &self,
+        out: &mut impl Write,
+        width: usize,
+        response: &SearchResponse,
+        numbers: Numbers,
+        version_filtered: bool,
+    ) -> io::Result<()> {
+        if self.full_descriptions {
+            for row in &response.data {
+                writeln!(
+                    out,
+                    "{}",
+                    render::record(
+                        &[
+                            ("id", row.id.clone()),
+                            ("version", row.version.to_string()),
+                            (
+                                "description",
+                                row.description.clone().unwrap_or_else(|| "".into())
+                            ),
+                        ],
+                        width
+                    )
+                )?;
+            }
+        } else {
+            let max_width = self.max_cell_width.unwrap_or(DEFAULT_MAX_CELL_WIDTH);
+            let columns = if self.columns.is_empty() {
+                DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect()
+            } else {
+                self.columns.clone()
+            };
+            let rows = response
+                .data
+                .iter()
+                .map(|row| {
+                    columns
+                        .iter()
+                        .map(|column| render::sanitize_cell(&cell(row, column, numbers), max_width))
+                        .collect()
+                })
+                .collect::<Vec<Vec<String>>>();
+            writeln!(out, "{}", render::table(&columns, &rows, width))?;
+        }
+        writeln!(out, "Total hits: {}", response.total_hits)?;
+        if response.client_side_filtered {
+            writeln!(
+                out,
+                "{} can't filter by framework, so only packages from this page compatible with {} are shown. Later pages may have more.",
+                self.source,
+                self.framework.as_deref().unwrap_or_default()
+            )?;
+        }
+        if version_filtered {
+            writeln!(
+                out,
+                "Only packages from this page within the requested versions are shown."
+            )?;
         }
         Ok(())
     }
 }
+
+/// Reorders results for `--sort`. `relevance` keeps the order the source
+/// sent them in. Packages without a download count go last.
+fn sort_results(results: &mut [SearchResult], sort: &str) {
+    match sort {
+        "downloads" => results.sort_by(|a, b| b.total_downloads.cmp(&a.total_downloads)),
+        "id" => results.sort_by_key(|result| result.id.to_lowercase()),
+        _ => {}
+    }
+}
+
+/// The text for one of [`COLUMNS`] in `result`'s row.
+fn cell(result: &SearchResult, column: &str, numbers: Numbers) -> String {
+    match column {
+        "id" => result.id.clone(),
+        "verified" if result.verified == Some(true) => "✓".into(),
+        "verified" => String::new(),
+        "version" => result.version.to_string(),
+        "downloads" => result
+            .total_downloads
+            .map(|downloads| numbers.count(downloads))
+            .unwrap_or_default(),
+        "authors" => result
+            .authors
+            .as_ref()
+            .map(|authors| authors.to_string())
+            .unwrap_or_default(),
+        _ => result.description.clone().unwrap_or_default(),
+    }
+}
+
+/// Whether `version` is between `min` and `max`, inclusive. Versions that
+/// couldn't be parsed can't be compared, so they only get through if asked
+/// for.
+fn in_version_range(
+    version: &LenientVersion,
+    min: Option<&Version>,
+    max: Option<&Version>,
+    show_unparseable: bool,
+) -> bool {
+    match version.parsed() {
+        Some(version) => {
+            min.map_or(true, |min| min <= version) && max.map_or(true, |max| version <= max)
+        }
+        None => show_unparseable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use turron_common::serde_json::json;
+    use turron_testing::{assert_snapshot, fixtures, snapshot};
+
+    fn cmd(columns: &[&str], full_descriptions: bool) -> SearchCmd {
+        SearchCmd {
+            query: vec!["newtonsoft.json".into()],
+            source: "https://api.nuget.org/v3/index.json".into(),
+            assume_source_version: None,
+            quiet: false,
+            json: false,
+            take: Some(2),
+            skip: None,
+            all: false,
+            prerelease: None,
+            package_type: None,
+            framework: None,
+            full_descriptions,
+            max_cell_width: None,
+            min_version: None,
+            max_version: None,
+            show_unparseable: false,
+            exact: false,
+            sort: "relevance".into(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    fn response() -> SearchResponse {
+        serde_json::from_value(fixtures::search()).unwrap()
+    }
+
+    fn results() -> Vec<SearchResult> {
+        serde_json::from_value(json!([
+            { "id": "b.two", "version": "1.0.0", "totalDownloads": 20 },
+            { "id": "A.One", "version": "1.0.0", "authors": ["Kat", "Ferris"] },
+            {
+                "id": "c.three",
+                "version": "1.0.0",
+                "totalDownloads": 300,
+                "authors": "Kat",
+                "verified": true,
+            },
+        ]))
+        .unwrap()
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|result| &result.id[..]).collect()
+    }
+
+    #[test]
+    fn sorts_results() {
+        let mut data = results();
+        sort_results(&mut data, "relevance");
+        assert_eq!(ids(&data), vec!["b.two", "A.One", "c.three"]);
+        sort_results(&mut data, "downloads");
+        assert_eq!(ids(&data), vec!["c.three", "b.two", "A.One"]);
+        sort_results(&mut data, "id");
+        assert_eq!(ids(&data), vec!["A.One", "b.two", "c.three"]);
+    }
+
+    #[test]
+    fn fills_in_columns() {
+        let data = results();
+        assert_eq!(cell(&data[0], "downloads", Numbers::Human), "20");
+        assert_eq!(cell(&data[0], "authors", Numbers::Human), "");
+        assert_eq!(cell(&data[1], "authors", Numbers::Human), "Kat, Ferris");
+        assert_eq!(cell(&data[1], "downloads", Numbers::Human), "");
+        assert_eq!(cell(&data[2], "authors", Numbers::Human), "Kat");
+        assert_eq!(cell(&data[2], "verified", Numbers::Human), "✓");
+        assert_eq!(cell(&data[0], "verified", Numbers::Human), "");
+        assert_eq!(cell(&data[2], "version", Numbers::Human), "1.0.0");
+        assert_eq!(cell(&data[2], "description", Numbers::Human), "");
+    }
+
+    #[test]
+    fn formats_downloads_in_both_modes() {
+        let data: Vec<SearchResult> = serde_json::from_value(json!([
+            { "id": "big", "version": "1.0.0", "totalDownloads": 1_234_567 },
+            { "id": "huge", "version": "1.0.0", "totalDownloads": 4_200_000_000u64 },
+        ]))
+        .unwrap();
+        assert_eq!(cell(&data[0], "downloads", Numbers::Human), "1.2M");
+        assert_eq!(cell(&data[1], "downloads", Numbers::Human), "4.2B");
+        assert_eq!(cell(&data[0], "downloads", Numbers::Raw), "1234567");
+        assert_eq!(cell(&data[1], "downloads", Numbers::Raw), "4200000000");
+    }
+
+    fn v(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    fn allowed(version: &str, min: Option<&str>, max: Option<&str>) -> bool {
+        in_version_range(
+            &LenientVersion::parse(version),
+            min.map(v).as_ref(),
+            max.map(v).as_ref(),
+            false,
+        )
+    }
+
+    #[test]
+    fn version_bounds_are_inclusive() {
+        assert!(allowed("8.0.0", Some("8.0.0"), None));
+        assert!(allowed("8.0.1", Some("8.0.0"), None));
+        assert!(!allowed("7.0.9", Some("8.0.0"), None));
+        assert!(!allowed("8.0.0-rc.2", Some("8.0.0"), None));
+
+        assert!(allowed("9.0.0", None, Some("9.0.0")));
+        assert!(allowed("9.0.0-preview.1", None, Some("9.0.0")));
+        assert!(!allowed("9.0.0.1", None, Some("9.0.0")));
+
+        assert!(allowed("8.5.0", Some("8.0.0"), Some("9.0.0")));
+        assert!(allowed("8.0", Some("8.0.0"), Some("8.0.0")));
+        assert!(!allowed("9.1.0", Some("8.0.0"), Some("9.0.0")));
+        assert!(allowed("1.0.0", None, None));
+    }
+
+    #[test]
+    fn unparseable_versions_need_asking_for() {
+        let weird = LenientVersion::parse("latest");
+        let min = v("1.0.0");
+        assert!(!in_version_range(&weird, Some(&min), None, false));
+        assert!(in_version_range(&weird, Some(&min), None, true));
+    }
+
+    #[test]
+    fn renders_a_table() {
+        let response = response();
+        let search = cmd(&["id", "verified", "version", "downloads"], false);
+        let out = snapshot::render(|out, width| {
+            search.write_results(out, width, &response, Numbers::Human, false)
+        });
+        assert_snapshot!("search_table", out);
+    }
+
+    #[test]
+    fn renders_full_descriptions() {
+        let response = response();
+        let out = snapshot::render(|out, width| {
+            cmd(&[], true).write_results(out, width, &response, Numbers::Human, true)
+        });
+        assert_snapshot!("search_full_descriptions", out);
+    }
+}
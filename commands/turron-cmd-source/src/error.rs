@@ -0,0 +1,89 @@
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Debug, Diagnostic, Error)]
+pub enum SourceError {
+    #[error("Not sending a test publish request without confirmation.")]
+    #[diagnostic(
+        code(turron::source::needs_confirmation),
+        help("Pass --i-understand-this-sends-a-request to run this when nobody's around to answer the prompt.")
+    )]
+    NeedsConfirmation,
+
+    #[error("{url} rejected the API key ({status}).")]
+    #[diagnostic(
+        code(turron::source::publish_unauthorized),
+        help("Check that the key hasn't expired, and that it's allowed to push to this source.")
+    )]
+    PublishUnauthorized { url: String, status: u16 },
+
+    #[error("{url} doesn't seem to accept pushes ({status}).")]
+    #[diagnostic(
+        code(turron::source::publish_endpoint_missing),
+        help("Run `turron ping --source <url>` to see the endpoints the source advertises.")
+    )]
+    PublishEndpointMissing { url: String, status: u16 },
+
+    #[error("{url} answered the test publish with {status}, which doesn't say whether the API key works.")]
+    #[diagnostic(code(turron::source::publish_inconclusive))]
+    PublishInconclusive { url: String, status: u16 },
+
+    #[error("{url} doesn't conform to the NuGet protocol: {failed} checks failed, and {violations} protocol violations were found.")]
+    #[diagnostic(
+        code(turron::source::not_conformant),
+        help("Each failure and violation is listed above. Pass --json for the full report.")
+    )]
+    NotConformant {
+        url: String,
+        failed: usize,
+        violations: usize,
+    },
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::source::needs_confirmation",
+        cause: "`turron source test-publish` sends a real, authenticated request to the source's publish endpoint. It asks before doing that, and there was no terminal to ask on.",
+        fixes: &["Pass `--i-understand-this-sends-a-request` in scripts and CI."],
+        config: &["commands.source.test-publish.i_understand_this_sends_a_request"],
+    },
+    Explanation {
+        code: "turron::source::publish_unauthorized",
+        cause: "The source answered the test publish with 401 or 403, so it turned the API key down before even looking at the upload.",
+        fixes: &[
+            "Generate a new key with push rights for the packages you publish.",
+            "Check which key is configured for this source, or pass `--api-key` explicitly.",
+        ],
+        config: &["api_key", "sources"],
+    },
+    Explanation {
+        code: "turron::source::publish_endpoint_missing",
+        cause: "The source answered the test publish with 404 or 405. The publish URL from its service index doesn't accept pushes, or isn't there at all.",
+        fixes: &[
+            "Double-check the source URL. Some servers use a separate URL for publishing.",
+            "Ask the source's administrators whether pushing is enabled.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::source::publish_inconclusive",
+        cause: "The test publish relies on sources rejecting a deliberately invalid package with 400 once the API key checks out. This source answered with something else, so turron can't tell whether the key works.",
+        fixes: &[
+            "Look the status up in the source's documentation.",
+            "Run again with `--verbosity debug` to see more about the request.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::source::not_conformant",
+        cause: "`turron source conformance` sends a source the same requests turron itself makes, and checks the responses against the NuGet protocol docs: required fields, URLs, counts, normalized versions, and fields the protocol doesn't mention. Some requests failed outright, or some responses broke those rules.",
+        fixes: &[
+            "Fix the source so it follows https://docs.microsoft.com/en-us/nuget/api/overview.",
+            "Run `turron --validate-responses` with any other command to check the responses it gets, without failing it.",
+        ],
+        config: &[],
+    },
+];
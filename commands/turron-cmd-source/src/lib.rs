@@ -0,0 +1,64 @@
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, ArgMatches, Clap},
+    turron_config::{TurronConfig, TurronConfigLayer},
+    TurronCommand,
+};
+use turron_common::{miette::Result, tracing};
+
+pub use error::{SourceError, EXPLANATIONS};
+use subcommands::{ConformanceCmd, TestPublishCmd};
+
+mod error;
+mod subcommands;
+
+#[derive(Debug, Clap)]
+pub enum SourceSubCmd {
+    #[clap(
+        about = "Check that a source's responses follow the NuGet protocol",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Conformance(ConformanceCmd),
+    #[clap(
+        about = "Check that an API key can publish to a source, without publishing anything",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    TestPublish(TestPublishCmd),
+}
+
+#[derive(Debug, Clap)]
+#[clap(
+    setting = clap::AppSettings::InferSubcommands,
+)]
+pub struct SourceCmd {
+    #[clap(subcommand)]
+    subcommand: SourceSubCmd,
+}
+
+#[async_trait]
+impl TurronCommand for SourceCmd {
+    async fn execute(self) -> Result<()> {
+        tracing::debug!("Running command: {:#?}", self.subcommand);
+        match self.subcommand {
+            SourceSubCmd::Conformance(conformance) => conformance.execute().await,
+            SourceSubCmd::TestPublish(test_publish) => test_publish.execute().await,
+        }
+    }
+}
+
+impl TurronConfigLayer for SourceCmd {
+    fn layer_config(&mut self, args: &ArgMatches, conf: &TurronConfig) -> Result<()> {
+        match self.subcommand {
+            SourceSubCmd::Conformance(ref mut conformance) => {
+                conformance.layer_config(args.subcommand_matches("conformance").unwrap(), conf)
+            }
+            SourceSubCmd::TestPublish(ref mut test_publish) => {
+                test_publish.layer_config(args.subcommand_matches("test-publish").unwrap(), conf)
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use nuget_api::{
+    v3::{AutocompleteQuery, NuGetClient, SearchQuery},
+    validate::{self, Violation},
+    NuGetApiError, SourceProtocol,
+};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    owo_colors::OwoColorize,
+    render::sanitize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde::Serialize,
+    serde_json,
+};
+
+use crate::error::SourceError;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "source.conformance"]
+pub struct ConformanceCmd {
+    #[clap(about = "Id of a package on the source to run the checks against")]
+    package: String,
+    #[clap(
+        about = "Source to check",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "outcome", content = "reason")]
+enum Outcome {
+    Passed,
+    /// The source doesn't advertise the resource this check needs.
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    name: &'static str,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Debug, Serialize)]
+struct ConformanceReport {
+    source: String,
+    package: String,
+    checks: Vec<Check>,
+    violations: Vec<Violation>,
+}
+
+#[async_trait]
+impl TurronCommand for ConformanceCmd {
+    async fn execute(self) -> Result<()> {
+        let report = conformance(
+            &self.source,
+            self.assume_source_version.unwrap_or_default(),
+            &self.package,
+        )
+        .await?;
+
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report)
+                    .into_diagnostic()
+                    .context("Failed to serialize conformance report into JSON")?
+            );
+        } else if !self.quiet {
+            for check in &report.checks {
+                match &check.outcome {
+                    Outcome::Passed => println!("{} {}", "ok:".green(), check.name),
+                    Outcome::Skipped(reason) => println!(
+                        "{} {} ({})",
+                        "skipped:".yellow(),
+                        check.name,
+                        sanitize(reason)
+                    ),
+                    Outcome::Failed(err) => {
+                        println!("{} {}: {}", "failed:".red(), check.name, sanitize(err))
+                    }
+                }
+            }
+            if !report.violations.is_empty() {
+                println!();
+                println!("Protocol violations:");
+                for violation in &report.violations {
+                    println!("    {}", sanitize(&violation.to_string()));
+                }
+            }
+        }
+
+        let failed = report
+            .checks
+            .iter()
+            .filter(|check| matches!(check.outcome, Outcome::Failed(_)))
+            .count();
+        if failed > 0 || !report.violations.is_empty() {
+            return Err(SourceError::NotConformant {
+                url: report.source,
+                failed,
+                violations: report.violations.len(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Runs every check against `source`, using `package` for the ones that
+/// need a package, with response validation turned on.
+async fn conformance(
+    source: &str,
+    protocol: SourceProtocol,
+    package: &str,
+) -> Result<ConformanceReport> {
+    let log = validate::start();
+    // Only what this run turned up, in case validation was already on.
+    let seen = log.len();
+    let client = NuGetClient::from_source_as(source, protocol).await?;
+    let checks = run_checks(&client, package).await;
+    Ok(ConformanceReport {
+        source: source.into(),
+        package: package.into(),
+        checks,
+        violations: log.violations().split_off(seen),
+    })
+}
+
+/// The battery of requests. The service index has already been fetched
+/// (and checked) by the time these run.
+async fn run_checks(client: &NuGetClient, package: &str) -> Vec<Check> {
+    let mut checks = vec![Check {
+        name: "service index",
+        outcome: Outcome::Passed,
+    }];
+    checks.push(Check {
+        name: "flat container versions",
+        outcome: outcome(client.versions(package).await),
+    });
+    let registration = client.registration(package).await;
+    let pages = match &registration {
+        Ok(index) => index
+            .items
+            .iter()
+            .filter(|page| page.items.is_none())
+            .map(|page| page.id.clone())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    checks.push(Check {
+        name: "registration index",
+        outcome: outcome(registration),
+    });
+    let mut paged = Ok(());
+    for page in pages {
+        if let Err(err) = client.registration_page(&page).await {
+            paged = Err(err);
+            break;
+        }
+    }
+    checks.push(Check {
+        name: "registration pages",
+        outcome: outcome(paged),
+    });
+    checks.push(Check {
+        name: "search",
+        outcome: outcome(client.search(SearchQuery::from_query(package)).await),
+    });
+    checks.push(Check {
+        name: "autocomplete ids",
+        outcome: outcome(
+            client
+                .autocomplete(AutocompleteQuery::from_query(package))
+                .await,
+        ),
+    });
+    checks.push(Check {
+        name: "autocomplete versions",
+        outcome: outcome(
+            client
+                .autocomplete(AutocompleteQuery::versions_of(package))
+                .await,
+        ),
+    });
+    checks
+}
+
+fn outcome<T>(result: Result<T, NuGetApiError>) -> Outcome {
+    match result {
+        Ok(_) => Outcome::Passed,
+        Err(NuGetApiError::UnsupportedEndpoint(resource)) => {
+            Outcome::Skipped(format!("the source doesn't advertise {}", resource))
+        }
+        Err(err) => Outcome::Failed(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nuget_api::validate::Rule;
+    use turron_common::{serde_json::json, smol};
+    use turron_testing::{fixtures, RegistrationBuilder, TestServer};
+
+    use super::*;
+
+    #[test]
+    fn runs_every_check() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let reg = RegistrationBuilder::new("Foo")
+                .base_url(server.url("/v3/registration5-gz-semver2/"))
+                .versions(vec!["1.0.0", "2.0.0-beta"])
+                .page_size(1)
+                .inline_leaves(false)
+                .build();
+            for (url, body) in reg.documents() {
+                server.json(&url[server.base().len()..], &body);
+            }
+            server
+                .json(
+                    "/v3-flatcontainer/foo/index.json",
+                    &json!({ "versions": ["1.0.0", "2.0.0-BETA"] }),
+                )
+                .json("/query", &fixtures::search())
+                .respond("/autocomplete", 404, "");
+
+            let report = conformance(&server.index_url(), SourceProtocol::Auto, "Foo")
+                .await
+                .unwrap();
+            let outcomes = report
+                .checks
+                .iter()
+                .map(|check| (check.name, &check.outcome))
+                .collect::<Vec<_>>();
+            assert_eq!(outcomes.len(), 7);
+            for (name, outcome) in &outcomes[..5] {
+                assert_eq!(*outcome, &Outcome::Passed, "{}", name);
+            }
+            // Failing requests don't stop the rest.
+            for (name, outcome) in &outcomes[5..] {
+                assert!(matches!(outcome, Outcome::Failed(_)), "{}", name);
+            }
+            assert_eq!(
+                server.hits("/v3/registration5-gz-semver2/foo/page/2.0.0-beta/2.0.0-beta.json"),
+                1
+            );
+
+            assert_eq!(report.violations.len(), 1);
+            let violation = &report.violations[0];
+            assert_eq!(violation.rule, Rule::NormalizedVersion);
+            assert_eq!(violation.path, "$.versions[1]");
+            assert!(violation.url.ends_with("/v3-flatcontainer/foo/index.json"));
+        });
+    }
+}
@@ -0,0 +1,5 @@
+pub use conformance::ConformanceCmd;
+pub use test_publish::TestPublishCmd;
+
+mod conformance;
+mod test_publish;
@@ -0,0 +1,220 @@
+use nuget_api::{v3::NuGetClient, NuGetApiError, SourceProtocol};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    dialoguer::{console, Confirm},
+    owo_colors::OwoColorize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde::Serialize,
+    serde_json, smol,
+    surf::StatusCode,
+};
+
+use crate::error::SourceError;
+
+/// How the verdict is reached, shown alongside it so nobody mistakes it for
+/// more than it is.
+const HEURISTIC: &str = "turron sent the publish endpoint an authenticated push containing a deliberately invalid package. Sources check the API key before validating uploads, so 400 means the key was accepted (and nothing was published), 401/403 mean it was rejected, and 404/405 mean the endpoint doesn't take pushes. Servers that validate in a different order can fool this, so treat it as a strong hint rather than a guarantee.";
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "source.test-publish"]
+pub struct TestPublishCmd {
+    #[clap(
+        about = "Source to test publishing to",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Send the test request without asking first. Required when not running interactively",
+        long
+    )]
+    i_understand_this_sends_a_request: bool,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+    #[clap(from_global)]
+    #[config_layer(api_key_for = "source")]
+    api_key: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Verdict {
+    /// The key got past authentication.
+    Authorized,
+    Unauthorized,
+    EndpointMissing,
+    Inconclusive,
+}
+
+impl Verdict {
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BadRequest => Verdict::Authorized,
+            StatusCode::Unauthorized | StatusCode::Forbidden => Verdict::Unauthorized,
+            StatusCode::NotFound | StatusCode::MethodNotAllowed => Verdict::EndpointMissing,
+            _ => Verdict::Inconclusive,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TestPublishReport {
+    source: String,
+    endpoint: String,
+    status: u16,
+    verdict: Verdict,
+    heuristic: &'static str,
+}
+
+#[async_trait]
+impl TurronCommand for TestPublishCmd {
+    async fn execute(self) -> Result<()> {
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?
+        .with_key(self.api_key.as_ref());
+        let endpoint = publish_endpoint(&client)?;
+        client.get_key()?;
+
+        if !self.i_understand_this_sends_a_request {
+            if !console::user_attended() {
+                return Err(SourceError::NeedsConfirmation.into());
+            }
+            let prompt = format!(
+                "This sends an authenticated (but invalid) publish request to {}. Continue?",
+                endpoint
+            );
+            let confirm = smol::unblock(move || -> Result<bool> {
+                Confirm::new()
+                    .with_prompt(prompt)
+                    .default(false)
+                    .interact()
+                    .into_diagnostic()
+            })
+            .await?;
+            if !confirm {
+                return Ok(());
+            }
+        }
+
+        let report = test_publish(&client, &self.source).await?;
+
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report)
+                    .into_diagnostic()
+                    .context("Failed to serialize test-publish report into JSON")?
+            );
+        } else if !self.quiet {
+            match report.verdict {
+                Verdict::Authorized => println!(
+                    "{} {} accepted the API key ({}).",
+                    "ok:".green(),
+                    report.endpoint,
+                    report.status
+                ),
+                _ => println!(
+                    "{} {} answered with {}.",
+                    "failed:".red(),
+                    report.endpoint,
+                    report.status
+                ),
+            }
+            println!("{} {}", "note:".cyan(), HEURISTIC);
+        }
+
+        let (url, status) = (report.endpoint, report.status);
+        match report.verdict {
+            Verdict::Authorized => Ok(()),
+            Verdict::Unauthorized => Err(SourceError::PublishUnauthorized { url, status }.into()),
+            Verdict::EndpointMissing => {
+                Err(SourceError::PublishEndpointMissing { url, status }.into())
+            }
+            Verdict::Inconclusive => Err(SourceError::PublishInconclusive { url, status }.into()),
+        }
+    }
+}
+
+fn publish_endpoint(client: &NuGetClient) -> Result<String, NuGetApiError> {
+    client
+        .endpoints
+        .publish
+        .as_ref()
+        .map(|url| url.to_string())
+        .ok_or_else(|| NuGetApiError::UnsupportedEndpoint("PackagePublish/2.0.0".into()))
+}
+
+async fn test_publish(client: &NuGetClient, source: &str) -> Result<TestPublishReport> {
+    let status = client.probe_publish().await?;
+    Ok(TestPublishReport {
+        source: source.into(),
+        endpoint: publish_endpoint(client)?,
+        status: status.into(),
+        verdict: Verdict::from_status(status),
+        heuristic: HEURISTIC,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_testing::TestServer;
+
+    use super::*;
+
+    const PUBLISH: &str = "/api/v2/package";
+
+    #[test]
+    fn reads_statuses() {
+        use Verdict::*;
+        for &(status, verdict) in &[
+            (StatusCode::BadRequest, &Authorized),
+            (StatusCode::Unauthorized, &Unauthorized),
+            (StatusCode::Forbidden, &Unauthorized),
+            (StatusCode::NotFound, &EndpointMissing),
+            (StatusCode::MethodNotAllowed, &EndpointMissing),
+            (StatusCode::Ok, &Inconclusive),
+            (StatusCode::InternalServerError, &Inconclusive),
+        ] {
+            assert_eq!(&Verdict::from_status(status), verdict, "{}", status);
+        }
+    }
+
+    #[test]
+    fn reports_what_the_source_said() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .respond_once(PUBLISH, 400, "")
+                .respond_once(PUBLISH, 403, "");
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_key(Some("key"));
+
+            let report = test_publish(&client, "src").await.unwrap();
+            assert_eq!(report.verdict, Verdict::Authorized);
+            assert_eq!(report.endpoint, server.url(PUBLISH));
+            assert_eq!(report.status, 400);
+
+            let report = test_publish(&client, "src").await.unwrap();
+            assert_eq!(report.verdict, Verdict::Unauthorized);
+
+            let report = test_publish(&client, "src").await.unwrap();
+            assert_eq!(report.verdict, Verdict::EndpointMissing);
+            assert_eq!(server.hits(PUBLISH), 3);
+        });
+    }
+}
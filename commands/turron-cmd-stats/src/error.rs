@@ -0,0 +1,25 @@
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum StatsError {
+    #[error("No packages on the source have ids starting with `{0}`.")]
+    #[diagnostic(
+        code(turron::stats::empty_ecosystem),
+        help("Check the prefix's spelling. Only listed packages show up in search, so a family that's entirely unlisted can't be found this way.")
+    )]
+    EmptyEcosystem(String),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::stats::empty_ecosystem",
+    cause: "`turron stats --ecosystem` finds a package family by searching the source and keeping the results whose ids start with the prefix. None of them did.",
+    fixes: &[
+        "Check the prefix, e.g. `Contoso.` or `Contoso.*`. It's matched case-insensitively.",
+        "Make sure the source has a search endpoint and indexes the packages, and that at least one version of each is listed.",
+    ],
+    config: &[],
+}];
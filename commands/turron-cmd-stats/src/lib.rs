@@ -0,0 +1,471 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use nuget_api::{
+    schedule::{self, Priority},
+    v3::{NuGetClient, SearchQuery},
+    NuGetApiError, SourceProtocol,
+};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    fmt::Numbers,
+    indicatif::{ProgressBar, ProgressStyle},
+    owo_colors::{colors::*, OwoColorize},
+    render::{self, sanitize_cell, DEFAULT_MAX_CELL_WIDTH},
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    chrono::{DateTime, Utc},
+    chrono_humanize::HumanTime,
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json},
+    smol::{self, Timer},
+    tracing,
+};
+
+pub use error::{StatsError, EXPLANATIONS};
+use stats::{sort_by_staleness, EcosystemStats, PackageStats, RECENT_DAYS};
+
+mod error;
+mod stats;
+
+/// How many search results to ask for at a time when looking for a
+/// package family.
+const SEARCH_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "stats"]
+pub struct StatsCmd {
+    #[clap(
+        about = "Package to show stats for",
+        required_unless_present = "ecosystem"
+    )]
+    package: Option<String>,
+    #[clap(
+        about = "Add up stats for every package whose id starts with this prefix (e.g. `Contoso.*`)",
+        long,
+        conflicts_with = "package"
+    )]
+    ecosystem: Option<String>,
+    #[clap(
+        about = "Stop after finding this many packages in the ecosystem",
+        long,
+        default_value = "200"
+    )]
+    max_packages: usize,
+    #[clap(
+        about = "Source to get stats from",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for StatsCmd {
+    async fn execute(self) -> Result<()> {
+        let client = Arc::new(
+            NuGetClient::from_source_as(
+                self.source.clone(),
+                self.assume_source_version.unwrap_or_default(),
+            )
+            .await?,
+        );
+        match (&self.ecosystem, &self.package) {
+            (Some(prefix), _) => self.ecosystem_stats(client, prefix).await,
+            (None, Some(id)) => self.package_stats(&client, id).await,
+            (None, None) => unreachable!("clap requires one of these"),
+        }
+    }
+}
+
+impl StatsCmd {
+    async fn package_stats(&self, client: &NuGetClient, id: &str) -> Result<()> {
+        let stats = package_stats(client, id, downloads(client, id).await, Utc::now()).await?;
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&stats)
+                    .into_diagnostic()
+                    .context("Failed to serialize stats into JSON")?
+            );
+        } else if !self.quiet {
+            let numbers = Numbers::current();
+            let fields = vec![
+                ("package", stats.id.clone()),
+                (
+                    "latest",
+                    stats
+                        .latest
+                        .as_ref()
+                        .map(|v| v.to_string())
+                        .unwrap_or_else(|| "nothing listed".into()),
+                ),
+                ("versions", numbers.count(stats.versions as u64)),
+                (
+                    "recent versions",
+                    format!(
+                        "{} in the last {} days",
+                        numbers.count(stats.recent_versions as u64),
+                        RECENT_DAYS
+                    ),
+                ),
+                ("downloads", downloads_cell(stats.downloads, numbers)),
+                ("last published", published_cell(stats.last_published)),
+                ("flags", flags_cell(&stats)),
+            ];
+            print!("{}", render::record(&fields, terminal_width()));
+        }
+        Ok(())
+    }
+
+    async fn ecosystem_stats(&self, client: Arc<NuGetClient>, prefix: &str) -> Result<()> {
+        let spinner = if self.quiet || self.json {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new_spinner()
+        };
+        let spin_clone = spinner.clone();
+        let spin_fut = smol::spawn(async move {
+            while !spin_clone.is_finished() {
+                spin_clone.tick();
+                Timer::after(Duration::from_millis(20)).await;
+            }
+        });
+
+        spinner.set_message(format!("Looking for {} packages...", prefix));
+        let progress = spinner.clone();
+        let family = find_family(&client, prefix, self.max_packages, move |found| {
+            progress.set_message(format!(
+                "Looking for {} packages... {} found",
+                prefix, found
+            ))
+        })
+        .await;
+        let family = match family {
+            Ok(family) if !family.is_empty() => family,
+            Ok(_) => {
+                spinner.finish_and_clear();
+                spin_fut.await;
+                return Err(StatsError::EmptyEcosystem(prefix.into()).into());
+            }
+            Err(err) => {
+                spinner.finish_and_clear();
+                spin_fut.await;
+                return Err(err.into());
+            }
+        };
+
+        spinner.set_style(
+            ProgressStyle::default_bar().template("{spinner} {msg} [{bar:30}] {pos}/{len}"),
+        );
+        spinner.set_message("Fetching registrations");
+        spinner.set_length(family.len() as u64);
+        let mut packages = fetch_stats(client, family, Utc::now(), spinner.clone()).await;
+        spinner.finish_and_clear();
+        spin_fut.await;
+        sort_by_staleness(&mut packages);
+        let summary = EcosystemStats::new(&packages);
+
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({
+                    "ecosystem": prefix,
+                    "summary": summary,
+                    "packages": packages,
+                }))
+                .into_diagnostic()
+                .context("Failed to serialize stats into JSON")?
+            );
+        } else if !self.quiet {
+            print_ecosystem(prefix, &summary, &packages);
+        }
+        Ok(())
+    }
+}
+
+/// Pages through search for packages whose ids start with `prefix`, until
+/// the source runs out of results or `max` have turned up. Search matches
+/// far more than prefixes, so everything else is dropped along the way.
+/// Returns each id with its download count, if the source has one.
+async fn find_family(
+    client: &NuGetClient,
+    prefix: &str,
+    max: usize,
+    mut on_page: impl FnMut(usize),
+) -> Result<Vec<(String, Option<u64>)>, NuGetApiError> {
+    let prefix = prefix.trim_end_matches('*').to_lowercase();
+    let mut query = SearchQuery::from_query(prefix.trim_end_matches('.'));
+    query.prerelease = Some(true);
+    query.take = Some(SEARCH_PAGE_SIZE);
+    let mut family: Vec<(String, Option<u64>)> = Vec::new();
+    let mut skip = 0;
+    while family.len() < max {
+        let page = client
+            .search(SearchQuery {
+                skip: Some(skip),
+                ..query.clone()
+            })
+            .await?;
+        if page.data.is_empty() {
+            break;
+        }
+        skip += page.data.len();
+        for result in page.data {
+            let id = result.id.to_lowercase();
+            if id.starts_with(&prefix) && !family.iter().any(|(f, _)| f.eq_ignore_ascii_case(&id)) {
+                family.push((result.id, result.total_downloads));
+            }
+        }
+        on_page(family.len().min(max));
+        if skip >= page.total_hits {
+            break;
+        }
+    }
+    family.truncate(max);
+    Ok(family)
+}
+
+/// `id`'s total downloads, if search knows about them.
+async fn downloads(client: &NuGetClient, id: &str) -> Option<u64> {
+    let mut query = SearchQuery::from_query(format!("packageid:{}", id));
+    query.prerelease = Some(true);
+    match client.search(query).await {
+        Ok(response) => response
+            .data
+            .into_iter()
+            .find(|result| result.id.eq_ignore_ascii_case(id))
+            .and_then(|result| result.total_downloads),
+        Err(err) => {
+            tracing::debug!("Couldn't get download counts for {}: {}", id, err);
+            None
+        }
+    }
+}
+
+async fn package_stats(
+    client: &NuGetClient,
+    id: &str,
+    downloads: Option<u64>,
+    now: DateTime<Utc>,
+) -> Result<PackageStats, NuGetApiError> {
+    let leaves = client.registration_leaves(id).await?;
+    Ok(PackageStats::new(id, downloads, &leaves, now))
+}
+
+/// Gets [`PackageStats`] for each of `family`, ticking `progress` as each
+/// one finishes. They're all asked for at once, and the request scheduler
+/// decides how many are actually in flight. Packages that fail to load are
+/// left out with a warning.
+async fn fetch_stats(
+    client: Arc<NuGetClient>,
+    family: Vec<(String, Option<u64>)>,
+    now: DateTime<Utc>,
+    progress: ProgressBar,
+) -> Vec<PackageStats> {
+    let tasks = family
+        .into_iter()
+        .map(|(id, downloads)| {
+            let client = client.clone();
+            let progress = progress.clone();
+            smol::spawn(schedule::prioritized(Priority::Bulk, async move {
+                let stats = match package_stats(&client, &id, downloads, now).await {
+                    Ok(stats) => Some(stats),
+                    Err(err) => {
+                        tracing::warn!("Failed to get stats for {}: {}", id, err);
+                        None
+                    }
+                };
+                progress.inc(1);
+                stats
+            }))
+        })
+        .collect::<Vec<_>>();
+    let mut packages = Vec::new();
+    for task in tasks {
+        packages.extend(task.await);
+    }
+    packages
+}
+
+fn terminal_width() -> usize {
+    term_size::dimensions().map(|(w, _)| w).unwrap_or(80)
+}
+
+fn downloads_cell(downloads: Option<u64>, numbers: Numbers) -> String {
+    downloads
+        .map(|downloads| numbers.count(downloads))
+        .unwrap_or_else(|| "-".into())
+}
+
+fn published_cell(published: Option<DateTime<Utc>>) -> String {
+    published
+        .map(|published| HumanTime::from(published).to_string())
+        .unwrap_or_else(|| "never".into())
+}
+
+fn flags_cell(stats: &PackageStats) -> String {
+    let mut flags = Vec::new();
+    if stats.vulnerable {
+        flags.push("vulnerable");
+    }
+    if stats.deprecated {
+        flags.push("deprecated");
+    }
+    flags.join(", ")
+}
+
+/// Lists up to a few ids, and how many more there are.
+fn id_list(ids: &[String]) -> String {
+    const SHOWN: usize = 5;
+    let mut list = ids
+        .iter()
+        .take(SHOWN)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if ids.len() > SHOWN {
+        list.push_str(&format!(", and {} more", ids.len() - SHOWN));
+    }
+    list
+}
+
+/// Prints the summary card, then a table of every package, stalest first.
+fn print_ecosystem(prefix: &str, summary: &EcosystemStats, packages: &[PackageStats]) {
+    let numbers = Numbers::current();
+    let width = terminal_width();
+    let with_ids = |count: usize, ids: &[String]| {
+        if count == 0 {
+            "none".to_string()
+        } else {
+            format!("{} ({})", numbers.count(count as u64), id_list(ids))
+        }
+    };
+    println!("{}", prefix.fg::<BrightGreen>().underline());
+    let fields = vec![
+        ("packages", numbers.count(summary.packages as u64)),
+        ("downloads", numbers.count(summary.downloads)),
+        ("versions", numbers.count(summary.versions as u64)),
+        (
+            "recent versions",
+            format!(
+                "{} in the last {} days",
+                numbers.count(summary.recent_versions as u64),
+                RECENT_DAYS
+            ),
+        ),
+        (
+            "vulnerable",
+            with_ids(summary.vulnerable.len(), &summary.vulnerable),
+        ),
+        (
+            "deprecated",
+            with_ids(summary.deprecated.len(), &summary.deprecated),
+        ),
+        (
+            "stalest",
+            summary
+                .stalest
+                .as_ref()
+                .map(|stalest| {
+                    format!(
+                        "{} ({})",
+                        stalest.id,
+                        HumanTime::from(stalest.last_published)
+                    )
+                })
+                .unwrap_or_else(|| "-".into()),
+        ),
+    ];
+    print!("{}", render::record(&fields, width));
+    println!();
+
+    let headers = [
+        "package",
+        "latest",
+        "versions",
+        "recent",
+        "downloads",
+        "last published",
+        "flags",
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect::<Vec<_>>();
+    let rows = packages
+        .iter()
+        .map(|stats| {
+            vec![
+                stats.id.clone(),
+                stats
+                    .latest
+                    .as_ref()
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "-".into()),
+                numbers.count(stats.versions as u64),
+                numbers.count(stats.recent_versions as u64),
+                downloads_cell(stats.downloads, numbers),
+                published_cell(stats.last_published),
+                flags_cell(stats),
+            ]
+            .iter()
+            .map(|cell| sanitize_cell(cell, DEFAULT_MAX_CELL_WIDTH))
+            .collect()
+        })
+        .collect::<Vec<Vec<String>>>();
+    println!("{}", render::table(&headers, &rows, width));
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::serde_json::json;
+    use turron_testing::TestServer;
+
+    use super::*;
+
+    #[test]
+    fn finds_a_package_family_through_search() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.json(
+                "/query",
+                &json!({
+                    "totalHits": 4,
+                    "data": [
+                        { "id": "Contoso.Core", "version": "1.0.0", "totalDownloads": 10 },
+                        { "id": "NotContoso", "version": "1.0.0" },
+                        { "id": "contoso.web", "version": "2.0.0" },
+                        { "id": "Contoso", "version": "1.0.0" },
+                    ],
+                }),
+            );
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            let mut pages = 0;
+            let family = find_family(&client, "Contoso.*", 10, |_| pages += 1)
+                .await
+                .unwrap();
+            assert_eq!(
+                family,
+                vec![
+                    ("Contoso.Core".to_string(), Some(10)),
+                    ("contoso.web".to_string(), None),
+                ]
+            );
+            assert_eq!(pages, 1);
+
+            let family = find_family(&client, "contoso.", 1, |_| {}).await.unwrap();
+            assert_eq!(family.len(), 1);
+        });
+    }
+}
@@ -0,0 +1,269 @@
+use dotnet_semver::Version;
+use nuget_api::v3::{CatalogEntry, RegistrationLeaf};
+use serde::Serialize;
+use turron_common::chrono::{DateTime, Datelike, Duration, Utc};
+
+/// How far back a version counts as recently published.
+pub(crate) const RECENT_DAYS: i64 = 90;
+
+/// What one package's registration says about it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PackageStats {
+    pub(crate) id: String,
+    /// Total downloads, on sources that count them.
+    pub(crate) downloads: Option<u64>,
+    /// Listed versions.
+    pub(crate) versions: usize,
+    /// Listed versions published in the last [`RECENT_DAYS`] days.
+    pub(crate) recent_versions: usize,
+    /// The newest listed version.
+    pub(crate) latest: Option<Version>,
+    pub(crate) last_published: Option<DateTime<Utc>>,
+    /// Whether `latest` is deprecated. Older versions being deprecated
+    /// doesn't say much about the package as a whole.
+    pub(crate) deprecated: bool,
+    /// Whether `latest` has known vulnerabilities.
+    pub(crate) vulnerable: bool,
+}
+
+impl PackageStats {
+    /// Tallies `leaves`, as of `now`. Unlisted versions are left out.
+    pub(crate) fn new(
+        id: &str,
+        downloads: Option<u64>,
+        leaves: &[RegistrationLeaf],
+        now: DateTime<Utc>,
+    ) -> Self {
+        let listed = leaves
+            .iter()
+            .map(|leaf| &leaf.catalog_entry)
+            .filter(|entry| is_listed(entry))
+            .collect::<Vec<_>>();
+        let recent = now - Duration::days(RECENT_DAYS);
+        let latest = listed.iter().max_by(|a, b| a.version.cmp(&b.version));
+        PackageStats {
+            id: latest.map_or_else(|| id.to_string(), |entry| entry.id.clone()),
+            downloads,
+            versions: listed.len(),
+            recent_versions: listed
+                .iter()
+                .filter(|entry| entry.published.map_or(false, |p| p >= recent))
+                .count(),
+            latest: latest.map(|entry| entry.version.clone()),
+            last_published: listed.iter().filter_map(|entry| entry.published).max(),
+            deprecated: latest.map_or(false, |entry| entry.deprecation.is_some()),
+            vulnerable: latest.map_or(false, |entry| {
+                entry
+                    .vulnerabilities
+                    .as_ref()
+                    .map_or(false, |vulns| !vulns.is_empty())
+            }),
+        }
+    }
+}
+
+/// nuget.org unlists by backdating `published` to 1900, and other sources
+/// set `listed: false`.
+fn is_listed(entry: &CatalogEntry) -> bool {
+    entry.listed != Some(false) && entry.published.map_or(true, |p| p.year() > 1900)
+}
+
+/// The package that's gone longest without a release.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Stalest {
+    pub(crate) id: String,
+    pub(crate) last_published: DateTime<Utc>,
+}
+
+/// A package family's [`PackageStats`], added up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EcosystemStats {
+    pub(crate) packages: usize,
+    /// Downloads across every package the source counts them for.
+    pub(crate) downloads: u64,
+    pub(crate) versions: usize,
+    pub(crate) recent_versions: usize,
+    /// Ids of packages whose latest version is vulnerable.
+    pub(crate) vulnerable: Vec<String>,
+    /// Ids of packages whose latest version is deprecated.
+    pub(crate) deprecated: Vec<String>,
+    /// Packages with no listed versions have no publish date, so they're
+    /// never the stalest.
+    pub(crate) stalest: Option<Stalest>,
+}
+
+impl EcosystemStats {
+    pub(crate) fn new(packages: &[PackageStats]) -> Self {
+        let ids = |pred: fn(&PackageStats) -> bool| {
+            packages
+                .iter()
+                .filter(|stats| pred(stats))
+                .map(|stats| stats.id.clone())
+                .collect()
+        };
+        EcosystemStats {
+            packages: packages.len(),
+            downloads: packages.iter().filter_map(|stats| stats.downloads).sum(),
+            versions: packages.iter().map(|stats| stats.versions).sum(),
+            recent_versions: packages.iter().map(|stats| stats.recent_versions).sum(),
+            vulnerable: ids(|stats| stats.vulnerable),
+            deprecated: ids(|stats| stats.deprecated),
+            stalest: packages
+                .iter()
+                .filter_map(|stats| Some((stats, stats.last_published?)))
+                .min_by_key(|(_, published)| *published)
+                .map(|(stats, last_published)| Stalest {
+                    id: stats.id.clone(),
+                    last_published,
+                }),
+        }
+    }
+}
+
+/// Sorts `packages` stalest first. Ones that were never published (or have
+/// nothing listed) come before all of them, then ties go by id.
+pub(crate) fn sort_by_staleness(packages: &mut [PackageStats]) {
+    packages.sort_by(|a, b| {
+        a.last_published
+            .cmp(&b.last_published)
+            .then_with(|| a.id.to_lowercase().cmp(&b.id.to_lowercase()))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::{
+        chrono::TimeZone,
+        serde_json::{self, json},
+    };
+
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2021, 6, 1).and_hms(0, 0, 0)
+    }
+
+    fn days_ago(days: i64) -> DateTime<Utc> {
+        now() - Duration::days(days)
+    }
+
+    fn leaf(version: &str, published: &str, extra: serde_json::Value) -> RegistrationLeaf {
+        let mut entry = json!({
+            "id": "Contoso.Core",
+            "version": version,
+            "published": published,
+        });
+        entry
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(json!({
+            "catalogEntry": entry,
+            "packageContent": "https://example.com/contoso.core.nupkg",
+        }))
+        .unwrap()
+    }
+
+    fn package(id: &str, downloads: Option<u64>, last_published: Option<i64>) -> PackageStats {
+        PackageStats {
+            id: id.into(),
+            downloads,
+            versions: 3,
+            recent_versions: 1,
+            latest: Some("1.0.0".parse().unwrap()),
+            last_published: last_published.map(days_ago),
+            deprecated: false,
+            vulnerable: false,
+        }
+    }
+
+    #[test]
+    fn tallies_listed_versions() {
+        let leaves = vec![
+            leaf("1.0.0", &days_ago(400).to_rfc3339(), json!({})),
+            leaf("1.1.0", &days_ago(30).to_rfc3339(), json!({})),
+            // Unlisted, both ways.
+            leaf("1.2.0", "1900-01-01T00:00:00+00:00", json!({})),
+            leaf(
+                "1.3.0",
+                &days_ago(1).to_rfc3339(),
+                json!({ "listed": false }),
+            ),
+            leaf(
+                "2.0.0-beta",
+                &days_ago(10).to_rfc3339(),
+                json!({
+                    "deprecation": { "reasons": ["Legacy"] },
+                    "vulnerabilities": [{ "advisoryUrl": "https://example.com/a", "severity": "2" }],
+                }),
+            ),
+        ];
+        let stats = PackageStats::new("contoso.core", Some(42), &leaves, now());
+        assert_eq!(stats.id, "Contoso.Core");
+        assert_eq!(stats.versions, 3);
+        assert_eq!(stats.recent_versions, 2);
+        assert_eq!(stats.latest, Some("2.0.0-beta".parse().unwrap()));
+        assert_eq!(stats.last_published, Some(days_ago(10)));
+        assert!(stats.deprecated);
+        assert!(stats.vulnerable);
+
+        let nothing_listed = PackageStats::new("Gone", None, &leaves[2..4], now());
+        assert_eq!(nothing_listed.id, "Gone");
+        assert_eq!(nothing_listed.versions, 0);
+        assert_eq!(nothing_listed.latest, None);
+        assert_eq!(nothing_listed.last_published, None);
+        assert!(!nothing_listed.deprecated);
+    }
+
+    #[test]
+    fn adds_up_a_family() {
+        let mut old = package("Contoso.Old", Some(1_000), Some(800));
+        old.vulnerable = true;
+        old.deprecated = true;
+        let mut legacy = package("Contoso.Legacy", None, Some(300));
+        legacy.deprecated = true;
+        let packages = vec![
+            package("Contoso.Core", Some(5_000), Some(2)),
+            old,
+            legacy,
+            package("Contoso.Empty", Some(7), None),
+        ];
+        let stats = EcosystemStats::new(&packages);
+        assert_eq!(stats.packages, 4);
+        // Packages without a download count don't count as zero, they just
+        // don't add anything.
+        assert_eq!(stats.downloads, 6_007);
+        assert_eq!(stats.versions, 12);
+        assert_eq!(stats.recent_versions, 4);
+        assert_eq!(stats.vulnerable, vec!["Contoso.Old"]);
+        assert_eq!(stats.deprecated, vec!["Contoso.Old", "Contoso.Legacy"]);
+        assert_eq!(
+            stats.stalest,
+            Some(Stalest {
+                id: "Contoso.Old".into(),
+                last_published: days_ago(800),
+            })
+        );
+
+        let empty = EcosystemStats::new(&[]);
+        assert_eq!(empty.packages, 0);
+        assert_eq!(empty.downloads, 0);
+        assert_eq!(empty.stalest, None);
+    }
+
+    #[test]
+    fn sorts_stalest_first() {
+        let mut packages = vec![
+            package("b", None, Some(10)),
+            package("c", None, Some(500)),
+            package("a", None, Some(10)),
+            package("never", None, None),
+        ];
+        sort_by_staleness(&mut packages);
+        let ids = packages.iter().map(|p| p.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["never", "c", "a", "b"]);
+    }
+}
@@ -0,0 +1,91 @@
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Debug, Diagnostic, Error)]
+pub enum UnlistError {
+    /// Api Key is missing.
+    #[error("Missing API key")]
+    #[diagnostic(code(turron::unlist::missing_api_key))]
+    MissingApiKey,
+
+    #[error("Version {version} of {id} not found.{}", did_you_mean(.suggestion))]
+    #[diagnostic(
+        code(turron::unlist::version_not_found),
+        help("Run `turron view {id} versions` to see what's published.")
+    )]
+    VersionNotFound {
+        id: String,
+        version: String,
+        suggestion: Option<String>,
+    },
+
+    #[error("Not unlisting without confirmation.")]
+    #[diagnostic(
+        code(turron::unlist::needs_confirmation),
+        help("Pass --yes to unlist when nobody's around to answer the prompt.")
+    )]
+    NeedsConfirmation,
+
+    #[error("No published versions of {0} match {1}.")]
+    #[diagnostic(
+        code(turron::unlist::no_matches),
+        help("Try running `turron view {0} versions`.")
+    )]
+    NoMatches(String, String),
+
+    #[error("{failed} of {total} versions failed to unlist.")]
+    #[diagnostic(code(turron::unlist::unlist_failed))]
+    UnlistFailed { failed: usize, total: usize },
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(suggestion) => format!(" Did you mean {}?", suggestion),
+        None => String::new(),
+    }
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::unlist::missing_api_key",
+        cause: "Unlisting needs an API key for the source, and none was configured or passed.",
+        fixes: &[
+            "Run `turron login` for the source, or pass --api-key.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::unlist::version_not_found",
+        cause: "`turron unlist` checks that every version it's given is published before unlisting any of them, and one of them isn't. Versions are compared the way NuGet compares them, so `1.0` matches `1.0.0`.",
+        fixes: &[
+            "Check the version for typos against the suggestion, if there is one.",
+            "Run `turron view <id> versions` to see what's published.",
+        ],
+        config: &["commands.unlist.source"],
+    },
+    Explanation {
+        code: "turron::unlist::needs_confirmation",
+        cause: "`turron unlist` asks before unlisting anything, and there was no terminal to ask on.",
+        fixes: &["Pass `--yes` in scripts and CI."],
+        config: &["commands.unlist.yes"],
+    },
+    Explanation {
+        code: "turron::unlist::no_matches",
+        cause: "None of the package's published versions fall within --range. Prereleases are left out unless the range mentions one, or `--all-prereleases` is passed.",
+        fixes: &[
+            "Run `turron view <id> versions` to see what's available.",
+            "Widen the range.",
+            "Pass `--all-prereleases` to include prereleases.",
+        ],
+        config: &["commands.unlist.source"],
+    },
+    Explanation {
+        code: "turron::unlist::unlist_failed",
+        cause: "At least one version failed to unlist. The per-version output above says what went wrong with each one; the rest were unlisted.",
+        fixes: &["Fix the reported problems and unlist the failed versions again."],
+        config: &[],
+    },
+];
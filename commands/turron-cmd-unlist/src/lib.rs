@@ -1,19 +1,50 @@
-use nuget_api::v3::NuGetClient;
+use dotnet_semver::{Range, Version};
+use nuget_api::{v3::NuGetClient, SourceProtocol};
+use turron_cmd_unpublish_check::{assess, print_advisory, version_facts};
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
+    dialoguer::{console, Confirm},
     turron_config::TurronConfigLayer,
     TurronCommand,
 };
-use turron_common::{miette::Result, thiserror::Error};
+use turron_common::{
+    chrono::Utc,
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json},
+    smol,
+};
+
+pub use error::{UnlistError, EXPLANATIONS};
+
+mod error;
 
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "unlist"]
 pub struct UnlistCmd {
     #[clap(about = "ID of package to unlist")]
     id: String,
-    #[clap(about = "Version of package to unlist")]
-    version: String,
+    #[clap(
+        about = "Versions of package to unlist",
+        required_unless_present_any = &["range", "all-prereleases"],
+        conflicts_with_all = &["range", "all-prereleases"]
+    )]
+    versions: Vec<String>,
+    #[clap(
+        about = "Unlist every published version in this range, like `[1.0.0-0, 1.0.0)`",
+        long
+    )]
+    range: Option<Range>,
+    #[clap(
+        about = "Unlist every published prerelease. With --range, include prereleases even if the range doesn't mention any",
+        long
+    )]
+    all_prereleases: bool,
+    #[clap(
+        about = "List the versions that would be unlisted, without unlisting anything",
+        long
+    )]
+    dry_run: bool,
     #[clap(
         about = "Source for package",
         default_value = "https://api.nuget.org/v3/index.json",
@@ -21,30 +52,308 @@ pub struct UnlistCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Show how risky unlisting these versions is before going ahead",
+        long
+    )]
+    advise: bool,
+    #[clap(
+        about = "Don't prompt for confirmation. Needed when there's no terminal to prompt on",
+        long,
+        short
+    )]
+    yes: bool,
+    #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
     #[clap(from_global)]
+    #[config_layer(api_key_for = "source")]
     api_key: Option<String>,
 }
 
 #[async_trait]
 impl TurronCommand for UnlistCmd {
     async fn execute(self) -> Result<()> {
-        let client = NuGetClient::from_source(self.source.clone())
-            .await?
-            .with_key(self.api_key);
-        client.unlist(self.id.clone(), self.version.clone()).await?;
-        if !self.quiet {
-            println!("{}@{} has been unlisted. This may take several hours to process.", self.id, self.version);
+        // One client for every version, so the service index is only
+        // fetched once.
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?
+        .with_key(self.api_key.clone());
+        let batch = self.range.is_some() || self.all_prereleases;
+        let published = client.versions(&self.id).await?;
+        let versions = if batch {
+            let matched = select(&published, self.range.as_ref(), self.all_prereleases);
+            if matched.is_empty() {
+                let filter = match &self.range {
+                    Some(range) => range.to_string(),
+                    None => "--all-prereleases".into(),
+                };
+                return Err(UnlistError::NoMatches(self.id.clone(), filter).into());
+            }
+            matched.iter().map(|v| v.to_string()).collect::<Vec<_>>()
+        } else {
+            // Check every version first, so a typo fails before anything
+            // gets unlisted.
+            self.versions
+                .iter()
+                .map(|version| resolve(&self.id, version, &published).map(|v| v.to_string()))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        if batch || self.dry_run || versions.len() > 1 {
+            self.print_candidates(&versions)?;
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+        if self.advise {
+            for version in &versions {
+                let version = version.parse()?;
+                let facts = version_facts(&client, &self.id, &version).await?;
+                let advisory = assess(&facts, Utc::now());
+                if !self.quiet && !self.json {
+                    print_advisory(&facts, &advisory);
+                }
+            }
+        }
+        if !self.yes {
+            if !console::user_attended() {
+                return Err(UnlistError::NeedsConfirmation.into());
+            }
+            if !confirm(&self.id, &versions).await? {
+                return Ok(());
+            }
+        }
+        self.unlist_all(&client, &versions).await
+    }
+}
+
+impl UnlistCmd {
+    /// Shows which versions are about to be unlisted. With `--json`, this is
+    /// only output for `--dry-run`, since a real run reports its outcomes
+    /// instead.
+    fn print_candidates(&self, versions: &[String]) -> Result<()> {
+        if self.quiet {
+            return Ok(());
+        }
+        if self.json {
+            if self.dry_run {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&json!({
+                        "id": self.id,
+                        "candidates": versions,
+                    }))
+                    .into_diagnostic()
+                    .context("Failed to serialize unlist candidates into JSON")?
+                );
+            }
+            return Ok(());
+        }
+        println!("Versions of {} to unlist:", self.id);
+        for version in versions {
+            println!("  {}", version);
+        }
+        if self.dry_run {
+            println!();
+            println!("{} version(s) would be unlisted.", versions.len());
         }
         Ok(())
     }
+
+    /// Unlists each of `versions`, carrying on past failures so one bad
+    /// version doesn't leave the rest listed.
+    async fn unlist_all(&self, client: &NuGetClient, versions: &[String]) -> Result<()> {
+        let human = !self.quiet && !self.json;
+        let mut outcomes = Vec::new();
+        let mut errors = Vec::new();
+        for version in versions {
+            match client.unlist(&self.id, version).await {
+                Ok(()) => {
+                    if human && versions.len() > 1 {
+                        println!("✓ {}@{}", self.id, version);
+                    }
+                    outcomes.push(json!({
+                        "id": self.id,
+                        "version": version,
+                        "status": "unlisted",
+                    }));
+                }
+                Err(err) => {
+                    if human && versions.len() > 1 {
+                        println!("✗ {}@{}: {}", self.id, version, err);
+                    }
+                    outcomes.push(json!({
+                        "id": self.id,
+                        "version": version,
+                        "status": "failed",
+                        "error": err.to_string(),
+                    }));
+                    errors.push(err);
+                }
+            }
+        }
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&outcomes)
+                    .into_diagnostic()
+                    .context("Failed to serialize unlist results into JSON")?
+            );
+        }
+        if versions.len() == 1 {
+            // Just one version: fail with whatever went wrong, like always.
+            if let Some(err) = errors.pop() {
+                return Err(err.into());
+            }
+            if human {
+                println!(
+                    "{}@{} has been unlisted. This may take several hours to process.",
+                    self.id, versions[0]
+                );
+            }
+            return Ok(());
+        }
+        if human {
+            println!(
+                "...{} unlisted, {} failed. This may take several hours to process.",
+                versions.len() - errors.len(),
+                errors.len()
+            );
+        }
+        if !errors.is_empty() {
+            return Err(UnlistError::UnlistFailed {
+                failed: errors.len(),
+                total: versions.len(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+async fn confirm(id: &str, versions: &[String]) -> Result<bool> {
+    let prompt = match versions {
+        [version] => format!("Unlist {}@{}?", id, version),
+        _ => format!("Unlist {} versions of {}?", versions.len(), id),
+    };
+    smol::unblock(move || -> Result<bool> {
+        Confirm::new()
+            .with_prompt(prompt)
+            .default(false)
+            .interact()
+            .into_diagnostic()
+    })
+    .await
+}
+
+/// Finds `version` among the `published` versions of `id`. If it isn't
+/// there, the error suggests the closest one that is.
+fn resolve(id: &str, version: &str, published: &[Version]) -> Result<Version, UnlistError> {
+    let found = version
+        .parse::<Version>()
+        .ok()
+        .and_then(|parsed| published.iter().find(|v| **v == parsed));
+    match found {
+        Some(found) => Ok(found.clone()),
+        None => Err(UnlistError::VersionNotFound {
+            id: id.into(),
+            version: version.into(),
+            suggestion: closest(version, published),
+        }),
+    }
+}
+
+/// The published version spelled most like `version`, if any is close
+/// enough to be a plausible typo.
+fn closest(version: &str, published: &[Version]) -> Option<String> {
+    let threshold = std::cmp::max(2, version.chars().count() / 3);
+    published
+        .iter()
+        .map(|v| v.to_string())
+        .map(|v| (strsim::levenshtein(version, &v), v))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, v)| v)
 }
 
-#[derive(Debug, Error)]
-pub enum UnlistError {
-    /// Api Key is missing.
-    #[error("Missing API key")]
-    MissingApiKey,
+/// The versions in `published` to unlist for `--range` and
+/// `--all-prereleases`. Prereleases only count when the range mentions one,
+/// or `all_prereleases` is set. Without a range, that's every prerelease.
+fn select(published: &[Version], range: Option<&Range>, all_prereleases: bool) -> Vec<Version> {
+    published
+        .iter()
+        .filter(|v| match range {
+            Some(range) => {
+                range.satisfies(v)
+                    && (!v.is_prerelease() || all_prereleases || range.has_pre_release())
+            }
+            None => all_prereleases && v.is_prerelease(),
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn published() -> Vec<Version> {
+        ["0.9.0", "1.0.0-alpha", "1.0.0-beta", "1.0.0", "1.1.0-rc.1"]
+            .iter()
+            .map(|v| v.parse().unwrap())
+            .collect()
+    }
+
+    fn selected(range: Option<&str>, all_prereleases: bool) -> Vec<String> {
+        let range = range.map(|range| range.parse::<Range>().unwrap());
+        select(&published(), range.as_ref(), all_prereleases)
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn selects_versions_in_a_range() {
+        assert_eq!(
+            selected(Some("[1.0.0-0, 1.0.0)"), false),
+            vec!["1.0.0-alpha", "1.0.0-beta"]
+        );
+        assert_eq!(selected(Some("[1.0.0, 2.0.0)"), false), vec!["1.0.0"]);
+        assert_eq!(
+            selected(Some("[1.0.0, 2.0.0)"), true),
+            vec!["1.0.0", "1.1.0-rc.1"]
+        );
+    }
+
+    #[test]
+    fn resolves_published_versions() {
+        assert_eq!(
+            resolve("Foo", "1.0", &published()).unwrap().to_string(),
+            "1.0.0"
+        );
+        match resolve("Foo", "1.0.0-alhpa", &published()) {
+            Err(UnlistError::VersionNotFound { suggestion, .. }) => {
+                assert_eq!(suggestion.as_deref(), Some("1.0.0-alpha"))
+            }
+            res => panic!("expected VersionNotFound, got {:?}", res),
+        }
+        match resolve("Foo", "7.3.2", &published()) {
+            Err(UnlistError::VersionNotFound { suggestion, .. }) => assert_eq!(suggestion, None),
+            res => panic!("expected VersionNotFound, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn selects_every_prerelease() {
+        assert_eq!(
+            selected(None, true),
+            vec!["1.0.0-alpha", "1.0.0-beta", "1.1.0-rc.1"]
+        );
+    }
 }
@@ -0,0 +1,231 @@
+use dotnet_semver::{Range, Version};
+use turron_common::chrono::{DateTime, Utc};
+
+/// Download count at or above which unlisting is considered risky.
+const HIGH_DOWNLOADS: u64 = 100_000;
+/// Download count at or above which unlisting deserves a second look.
+const MEDIUM_DOWNLOADS: u64 = 1_000;
+/// Versions younger than this have probably not been picked up by many
+/// consumers yet.
+const FRESH_DAYS: i64 = 1;
+
+/// Everything we know about the version someone wants to unlist.
+#[derive(Debug, Clone)]
+pub struct VersionFacts {
+    pub id: String,
+    pub version: Version,
+    pub published: Option<DateTime<Utc>>,
+    pub downloads: Option<u64>,
+    /// Every currently-listed version of the package, including the target.
+    pub listed: Vec<Version>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use RiskLevel::*;
+        match self {
+            Low => write!(f, "low"),
+            Medium => write!(f, "medium"),
+            High => write!(f, "high"),
+        }
+    }
+}
+
+/// What a range that a consumer would have used to depend on the target
+/// version resolves to once the target is gone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeShift {
+    pub range: Range,
+    pub replacement: Option<Version>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Advisory {
+    pub age_days: Option<i64>,
+    pub downloads: Option<u64>,
+    pub latest_stable: bool,
+    pub range_shifts: Vec<RangeShift>,
+    pub risk: RiskLevel,
+    pub reasons: Vec<String>,
+}
+
+/// Whether `version` is the newest listed, non-prerelease version.
+pub fn is_latest_stable(version: &Version, listed: &[Version]) -> bool {
+    version.pre_release.is_empty()
+        && listed
+            .iter()
+            .filter(|v| v.pre_release.is_empty())
+            .max()
+            .map(|latest| latest == version)
+            .unwrap_or(false)
+}
+
+/// Ranges consumers commonly use to pick up `version`: a minimum version
+/// (`[1.2.3, )`), a patch float (`1.2.*`), and a minor float (`1.*`).
+pub fn common_ranges(version: &Version) -> Vec<Range> {
+    vec![
+        format!("[{},)", version),
+        format!("{}.{}.*", version.major, version.minor),
+        format!("{}.*", version.major),
+    ]
+    .into_iter()
+    .filter_map(|r| r.parse().ok())
+    .collect()
+}
+
+/// For each of the [`common_ranges`], which version would get picked instead
+/// of `version` once it's unlisted.
+pub fn range_shifts(version: &Version, listed: &[Version]) -> Vec<RangeShift> {
+    let remaining = listed
+        .iter()
+        .filter(|v| *v != version)
+        .cloned()
+        .collect::<Vec<_>>();
+    common_ranges(version)
+        .into_iter()
+        .filter(|range| range.satisfies(version))
+        .map(|range| {
+            let replacement = turron_pick_version::pick_version(&range, &remaining);
+            RangeShift { range, replacement }
+        })
+        .collect()
+}
+
+/// Assess how risky unlisting a version would be, as of `now`.
+pub fn assess(facts: &VersionFacts, now: DateTime<Utc>) -> Advisory {
+    let mut risk = RiskLevel::Low;
+    let mut reasons = Vec::new();
+
+    let age_days = facts
+        .published
+        .map(|published| now.signed_duration_since(published).num_days());
+    if let Some(age) = age_days {
+        if age < FRESH_DAYS {
+            reasons.push("Version was published less than a day ago.".into());
+        }
+    }
+
+    if let Some(downloads) = facts.downloads {
+        if downloads >= HIGH_DOWNLOADS {
+            risk = risk.max(RiskLevel::High);
+            reasons.push(format!("Version has been downloaded {} times.", downloads));
+        } else if downloads >= MEDIUM_DOWNLOADS {
+            risk = risk.max(RiskLevel::Medium);
+            reasons.push(format!("Version has been downloaded {} times.", downloads));
+        }
+    }
+
+    let latest_stable = is_latest_stable(&facts.version, &facts.listed);
+    if latest_stable {
+        risk = risk.max(RiskLevel::High);
+        reasons.push(
+            "Version is the latest stable release. Floating consumers will silently shift to another version."
+                .into(),
+        );
+    }
+
+    let range_shifts = range_shifts(&facts.version, &facts.listed);
+    for shift in &range_shifts {
+        if shift.replacement.is_none() {
+            risk = risk.max(RiskLevel::Medium);
+            reasons.push(format!(
+                "No other listed version satisfies `{}`.",
+                shift.range
+            ));
+        }
+    }
+
+    Advisory {
+        age_days,
+        downloads: facts.downloads,
+        latest_stable,
+        range_shifts,
+        risk,
+        reasons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use turron_common::chrono::{Duration, TimeZone};
+
+    fn v(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    fn vs(versions: &[&str]) -> Vec<Version> {
+        versions.iter().map(|x| v(x)).collect()
+    }
+
+    fn facts(version: &str, listed: &[&str], downloads: Option<u64>) -> VersionFacts {
+        VersionFacts {
+            id: "Foo".into(),
+            version: v(version),
+            published: Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)),
+            downloads,
+            listed: vs(listed),
+        }
+    }
+
+    #[test]
+    fn latest_stable() {
+        let listed = vs(&["1.0.0", "1.1.0", "2.0.0-beta"]);
+        assert!(is_latest_stable(&v("1.1.0"), &listed));
+        assert!(!is_latest_stable(&v("1.0.0"), &listed));
+        assert!(!is_latest_stable(&v("2.0.0-beta"), &listed));
+    }
+
+    #[test]
+    fn shifts_to_other_versions() {
+        let shifts = range_shifts(&v("1.2.3"), &vs(&["1.2.3", "1.2.4", "1.3.0"]));
+        assert_eq!(shifts.len(), 3);
+        assert_eq!(shifts[0].replacement, Some(v("1.2.4")));
+        assert_eq!(shifts[1].replacement, Some(v("1.2.4")));
+        assert_eq!(shifts[2].replacement, Some(v("1.3.0")));
+    }
+
+    #[test]
+    fn shifts_without_alternatives() {
+        let shifts = range_shifts(&v("2.0.0"), &vs(&["1.0.0", "2.0.0"]));
+        assert!(shifts.iter().all(|s| s.replacement.is_none()));
+    }
+
+    #[test]
+    fn risk_levels() {
+        let now = Utc.ymd(2021, 6, 1).and_hms(0, 0, 0);
+
+        let advisory = assess(&facts("1.0.0", &["1.0.0", "1.0.1", "1.1.0"], Some(10)), now);
+        assert_eq!(advisory.risk, RiskLevel::Low);
+        assert_eq!(advisory.age_days, Some(151));
+
+        let advisory = assess(&facts("1.0.0", &["1.0.0", "1.0.1"], Some(5_000)), now);
+        assert_eq!(advisory.risk, RiskLevel::Medium);
+
+        let advisory = assess(&facts("1.0.1", &["1.0.0", "1.0.1"], Some(10)), now);
+        assert!(advisory.latest_stable);
+        assert_eq!(advisory.risk, RiskLevel::High);
+
+        let advisory = assess(&facts("1.0.0", &["1.0.0", "1.0.1"], Some(500_000)), now);
+        assert_eq!(advisory.risk, RiskLevel::High);
+    }
+
+    #[test]
+    fn fresh_versions() {
+        let published = Utc.ymd(2021, 1, 1).and_hms(0, 0, 0);
+        let advisory = assess(
+            &facts("1.0.0", &["1.0.0", "1.0.1"], None),
+            published + Duration::hours(2),
+        );
+        assert_eq!(advisory.age_days, Some(0));
+        assert!(advisory.reasons[0].contains("less than a day"));
+    }
+}
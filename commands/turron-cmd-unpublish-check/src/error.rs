@@ -0,0 +1,23 @@
+use dotnet_semver::Version;
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum UnpublishCheckError {
+    #[error("{0}@{1} does not exist in this source")]
+    #[diagnostic(
+        code(turron::unpublish_check::version_not_found),
+        help("Try running `turron view versions <id>`")
+    )]
+    VersionNotFound(String, Version),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::unpublish_check::version_not_found",
+    cause: "The version you asked about has never been published to this source, so there's nothing to unlist.",
+    fixes: &["Run `turron view versions <id>` to see the published versions."],
+    config: &["commands.unpublish-check.source"],
+}];
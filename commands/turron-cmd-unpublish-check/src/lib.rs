@@ -0,0 +1,179 @@
+use dotnet_semver::Version;
+use nuget_api::{
+    v3::{NuGetClient, SearchQuery},
+    SourceProtocol,
+};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    chrono::{Datelike, Utc},
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json},
+};
+
+pub use advisory::*;
+pub use error::{UnpublishCheckError, EXPLANATIONS};
+
+mod advisory;
+mod error;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "unpublish-check"]
+pub struct UnpublishCheckCmd {
+    #[clap(about = "ID of package to check")]
+    id: String,
+    #[clap(about = "Version of package to check")]
+    version: String,
+    #[clap(
+        about = "Source for package",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for UnpublishCheckCmd {
+    async fn execute(self) -> Result<()> {
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let version = self.version.parse()?;
+        let facts = version_facts(&client, &self.id, &version).await?;
+        let advisory = assess(&facts, Utc::now());
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&advisory_json(&facts, &advisory))
+                    .into_diagnostic()
+                    .context("Failed to serialize advisory into JSON")?
+            );
+        } else if !self.quiet {
+            print_advisory(&facts, &advisory);
+        }
+        Ok(())
+    }
+}
+
+/// Collects what we need to know about a package version from the
+/// registration and search endpoints. Download counts are best-effort, since
+/// not every source supports search.
+pub async fn version_facts(
+    client: &NuGetClient,
+    id: &str,
+    version: &Version,
+) -> Result<VersionFacts> {
+    let leaves = client.registration_leaves(id).await?;
+    let mut listed = Vec::new();
+    let mut published = None;
+    let mut found = false;
+    for leaf in leaves {
+        let entry = leaf.catalog_entry;
+        // nuget.org marks unlisted versions with a 1900 publish date
+        // rather than with `listed`.
+        let is_listed = entry.listed.unwrap_or(true)
+            && entry.published.map(|p| p.year() > 1900).unwrap_or(true);
+        if &entry.version == version {
+            found = true;
+            published = entry.published.filter(|p| p.year() > 1900);
+        }
+        if is_listed {
+            listed.push(entry.version);
+        }
+    }
+    if !found {
+        return Err(UnpublishCheckError::VersionNotFound(id.into(), version.clone()).into());
+    }
+
+    let mut query = SearchQuery::from_query(format!("packageid:{}", id));
+    query.prerelease = Some(true);
+    let downloads = client.search(query).await.ok().and_then(|res| {
+        let target = version.to_string().to_lowercase();
+        res.data
+            .into_iter()
+            .find(|r| r.id.to_lowercase() == id.to_lowercase())
+            .and_then(|r| {
+                r.versions
+                    .into_iter()
+                    .find(|v| v.version.to_lowercase() == target)
+            })
+            .and_then(|v| v.downloads)
+    });
+
+    Ok(VersionFacts {
+        id: id.into(),
+        version: version.clone(),
+        published,
+        downloads,
+        listed,
+    })
+}
+
+pub fn advisory_json(facts: &VersionFacts, advisory: &Advisory) -> serde_json::Value {
+    json!({
+        "id": facts.id,
+        "version": facts.version.to_string(),
+        "risk": advisory.risk.to_string(),
+        "ageDays": advisory.age_days,
+        "downloads": advisory.downloads,
+        "latestStable": advisory.latest_stable,
+        "rangeShifts": advisory.range_shifts.iter().map(|shift| json!({
+            "range": shift.range.to_string(),
+            "replacement": shift.replacement.as_ref().map(|v| v.to_string()),
+        })).collect::<Vec<_>>(),
+        "reasons": advisory.reasons,
+    })
+}
+
+pub fn print_advisory(facts: &VersionFacts, advisory: &Advisory) {
+    println!("{}@{}", facts.id, facts.version);
+    println!("  risk: {}", advisory.risk);
+    println!(
+        "  age: {}",
+        advisory
+            .age_days
+            .map(|d| format!("{} days", d))
+            .unwrap_or_else(|| "unknown".into())
+    );
+    println!(
+        "  downloads: {}",
+        advisory
+            .downloads
+            .map(|d| d.to_string())
+            .unwrap_or_else(|| "unknown".into())
+    );
+    println!(
+        "  latest stable: {}",
+        if advisory.latest_stable { "yes" } else { "no" }
+    );
+    if !advisory.range_shifts.is_empty() {
+        println!("  consumers using these ranges would get:");
+        for shift in &advisory.range_shifts {
+            println!(
+                "    {} => {}",
+                shift.range,
+                shift
+                    .replacement
+                    .as_ref()
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "nothing".into())
+            );
+        }
+    }
+    for reason in &advisory.reasons {
+        println!("  - {}", reason);
+    }
+}
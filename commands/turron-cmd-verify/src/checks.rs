@@ -0,0 +1,328 @@
+//! The individual checks. Each works on an archive that's already open, so
+//! they can be tried out on packages built in memory.
+
+use std::collections::HashSet;
+use std::io::{self, Read, Seek};
+
+use nuget_api::v3::{NuSpec, NuSpecMetadata};
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha256, Sha512};
+use turron_common::quick_xml;
+use zip::{result::ZipError, ZipArchive};
+
+use crate::report::Finding;
+
+pub(crate) const ZIP: &str = "zip";
+pub(crate) const NUSPEC: &str = "nuspec";
+pub(crate) const METADATA: &str = "metadata";
+pub(crate) const FILES: &str = "files";
+pub(crate) const HASH: &str = "hash";
+pub(crate) const SIGNATURE: &str = "signature";
+
+/// Every check's name, in the order they run.
+pub(crate) const ALL: &[&str] = &[ZIP, NUSPEC, METADATA, FILES, HASH, SIGNATURE];
+
+/// Where package signatures live, per the NuGet signing spec.
+const SIGNATURE_FILE: &str = ".signature.p7s";
+
+/// Reads every entry to the end, which is when their CRCs get checked.
+pub(crate) fn zip_integrity<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Finding {
+    for i in 0..zip.len() {
+        let mut file = match zip.by_index(i) {
+            Ok(file) => file,
+            Err(err) => return Finding::fail(format!("Entry {} can't be read: {}", i, err)),
+        };
+        let name = file.name().to_string();
+        if let Err(err) = io::copy(&mut file, &mut io::sink()) {
+            return Finding::fail(format!("{} is corrupt: {}", name, err));
+        }
+    }
+    Finding::pass(format!("All {} entries read cleanly", zip.len()))
+}
+
+/// The package's nuspec: the one `.nuspec` file at the root of the archive.
+pub(crate) fn read_nuspec<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<NuSpec, String> {
+    let names = zip
+        .file_names()
+        .filter(|name| !name.contains('/') && name.to_lowercase().ends_with(".nuspec"))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let name = match &names[..] {
+        [name] => name,
+        [] => return Err("There's no .nuspec at the root of the package".into()),
+        _ => {
+            return Err(format!(
+                "There's more than one .nuspec at the root of the package: {}",
+                names.join(", ")
+            ))
+        }
+    };
+    let mut contents = String::new();
+    zip.by_name(name)
+        .map_err(|err| format!("{} can't be read: {}", name, err))?
+        .read_to_string(&mut contents)
+        .map_err(|err| format!("{} can't be read: {}", name, err))?;
+    quick_xml::de::from_str(&contents).map_err(|err| format!("{} isn't valid: {}", name, err))
+}
+
+pub(crate) fn nuspec<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Finding {
+    match read_nuspec(zip) {
+        Ok(nuspec) => Finding::pass(format!(
+            "{} {}",
+            nuspec.metadata.id, nuspec.metadata.version
+        )),
+        Err(err) => Finding::fail(err),
+    }
+}
+
+/// Whether the fields every package needs are filled in. The version
+/// always is, or the nuspec wouldn't have parsed.
+pub(crate) fn metadata(metadata: &NuSpecMetadata) -> Finding {
+    let blank = [
+        ("id", &metadata.id),
+        ("description", &metadata.description),
+        ("authors", &metadata.authors),
+    ]
+    .iter()
+    .filter(|(_, value)| value.trim().is_empty())
+    .map(|(field, _)| format!("<{}>", field))
+    .collect::<Vec<_>>();
+    if blank.is_empty() {
+        Finding::pass("id, version, description, and authors are all set")
+    } else {
+        Finding::fail(format!("Required metadata is blank: {}", blank.join(", ")))
+    }
+}
+
+/// Whether the files `<icon>`, `<readme>`, and a file `<license>` point at
+/// are in the package. Nuspecs can use either kind of slash, and paths are
+/// compared ignoring case.
+pub(crate) fn referenced_files<R: Read + Seek>(
+    zip: &ZipArchive<R>,
+    metadata: &NuSpecMetadata,
+) -> Finding {
+    let entries = zip
+        .file_names()
+        .map(|name| percent_decode_str(name).decode_utf8_lossy().to_lowercase())
+        .collect::<HashSet<_>>();
+    let referenced = [
+        ("icon", metadata.icon.as_deref()),
+        ("readme", metadata.readme.as_deref()),
+        ("license", metadata.license_file()),
+    ];
+    let mut found = Vec::new();
+    let mut missing = Vec::new();
+    for &(field, path) in referenced.iter() {
+        if let Some(path) = path {
+            let normalized = path.replace('\\', "/").to_lowercase();
+            if entries.contains(normalized.trim_start_matches("./")) {
+                found.push(format!("<{}>", field));
+            } else {
+                missing.push(format!("<{}> {}", field, path));
+            }
+        }
+    }
+    if !missing.is_empty() {
+        Finding::fail(format!("Not in the package: {}", missing.join(", ")))
+    } else if found.is_empty() {
+        Finding::pass("The nuspec doesn't point at any files")
+    } else {
+        Finding::pass(format!("Found the files for {}", found.join(", ")))
+    }
+}
+
+/// Compares the package's SHA-512 to `expected`, which is base64-encoded
+/// like in `.nupkg.sha512` files.
+pub(crate) fn hash(nupkg: &[u8], expected: Option<&str>) -> Finding {
+    let actual = base64::encode(Sha512::digest(nupkg));
+    match expected.map(str::trim) {
+        None => Finding::skip(format!("Nothing to compare to. SHA-512: {}", actual)),
+        Some(expected) if expected == actual => Finding::pass(format!("SHA-512: {}", actual)),
+        Some(expected) => Finding::fail(format!(
+            "Expected SHA-512 {}, but the package's is {}",
+            expected, actual
+        )),
+    }
+}
+
+/// Whether the package has a signature. The signature itself isn't
+/// validated, but its SHA-256 is reported so it can be compared.
+pub(crate) fn signature<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Finding {
+    let mut file = match zip.by_name(SIGNATURE_FILE) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => {
+            return Finding::warn(
+                "The package isn't signed. Sources like nuget.org sign packages themselves when they're pushed.",
+            )
+        }
+        Err(err) => return Finding::fail(format!("{} can't be read: {}", SIGNATURE_FILE, err)),
+    };
+    let mut signature = Vec::new();
+    if let Err(err) = file.read_to_end(&mut signature) {
+        return Finding::fail(format!("{} can't be read: {}", SIGNATURE_FILE, err));
+    }
+    if signature.is_empty() {
+        return Finding::fail(format!("{} is empty", SIGNATURE_FILE));
+    }
+    let fingerprint = Sha256::digest(&signature)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    Finding::pass(format!("Signed. Signature SHA-256: {}", fingerprint))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use turron_testing::NupkgBuilder;
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    use super::*;
+    use crate::report::Status;
+
+    fn open(nupkg: Vec<u8>) -> ZipArchive<Cursor<Vec<u8>>> {
+        ZipArchive::new(Cursor::new(nupkg)).unwrap()
+    }
+
+    /// A zip of uncompressed files, so tests can find and mangle them.
+    fn stored(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            zip.start_file(
+                *name,
+                FileOptions::default().compression_method(CompressionMethod::Stored),
+            )
+            .unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn checks_archive_integrity() {
+        let mut zip = open(NupkgBuilder::new("Foo", "1.0.0").build());
+        assert_eq!(zip_integrity(&mut zip).status, Status::Pass);
+
+        let mut nupkg = stored(&[("foo.txt", "hello, world")]);
+        let start = nupkg
+            .windows(5)
+            .position(|window| window == b"hello")
+            .unwrap();
+        nupkg[start] = b'j';
+        let finding = zip_integrity(&mut open(nupkg));
+        assert_eq!(finding.status, Status::Fail);
+        assert!(finding.details.starts_with("foo.txt is corrupt"));
+    }
+
+    #[test]
+    fn checks_the_nuspec() {
+        let mut zip = open(NupkgBuilder::new("Foo", "1.0.0").build());
+        assert_eq!(nuspec(&mut zip), Finding::pass("Foo 1.0.0"));
+        assert_eq!(read_nuspec(&mut zip).unwrap().metadata.authors, "turron");
+
+        let finding = nuspec(&mut open(stored(&[("lib/foo.dll", "")])));
+        assert_eq!(finding.status, Status::Fail);
+        assert!(finding.details.contains("no .nuspec"));
+
+        // Nested nuspecs aren't the package's own.
+        let nuspec_xml = NupkgBuilder::new("Foo", "1.0.0").nuspec();
+        let finding = nuspec(&mut open(stored(&[
+            ("foo.nuspec", &nuspec_xml),
+            ("content/bar.nuspec", "<package />"),
+        ])));
+        assert_eq!(finding.status, Status::Pass);
+
+        let finding = nuspec(&mut open(stored(&[
+            ("foo.nuspec", &nuspec_xml),
+            ("bar.nuspec", &nuspec_xml),
+        ])));
+        assert_eq!(finding.status, Status::Fail);
+        assert!(finding.details.contains("more than one"));
+
+        let finding = nuspec(&mut open(stored(&[("foo.nuspec", "<package>")])));
+        assert_eq!(finding.status, Status::Fail);
+        assert!(finding.details.starts_with("foo.nuspec isn't valid"));
+    }
+
+    #[test]
+    fn checks_required_metadata() {
+        let mut zip = open(NupkgBuilder::new("Foo", "1.0.0").build());
+        let mut fields = read_nuspec(&mut zip).unwrap().metadata;
+        assert_eq!(metadata(&fields).status, Status::Pass);
+
+        fields.description = " ".into();
+        fields.authors = String::new();
+        assert_eq!(
+            metadata(&fields),
+            Finding::fail("Required metadata is blank: <description>, <authors>")
+        );
+    }
+
+    #[test]
+    fn finds_referenced_files() {
+        let builder = NupkgBuilder::new("Foo", "1.0.0");
+        let mut zip = open(builder.build());
+        let nuspec = read_nuspec(&mut zip).unwrap();
+        assert_eq!(
+            referenced_files(&zip, &nuspec.metadata),
+            Finding::pass("The nuspec doesn't point at any files")
+        );
+
+        let builder = builder.readme("docs/README.md", "hi");
+        let mut zip = open(builder.build());
+        let mut metadata = read_nuspec(&mut zip).unwrap().metadata;
+        metadata.readme = Some("Docs\\readme.md".into());
+        assert_eq!(
+            referenced_files(&zip, &metadata),
+            Finding::pass("Found the files for <readme>")
+        );
+
+        metadata.icon = Some("icon.png".into());
+        metadata.license = Some("LICENSE.txt".into());
+        assert_eq!(
+            referenced_files(&zip, &metadata),
+            Finding::fail("Not in the package: <icon> icon.png, <license> LICENSE.txt")
+        );
+
+        // Without the file, the nuspec's <readme> points at nothing.
+        let zip = open(stored(&[("foo.nuspec", &builder.nuspec())]));
+        assert_eq!(referenced_files(&zip, &metadata).status, Status::Fail);
+    }
+
+    #[test]
+    fn compares_hashes() {
+        let nupkg = NupkgBuilder::new("Foo", "1.0.0").build();
+        let expected = base64::encode(Sha512::digest(&nupkg));
+        assert_eq!(hash(&nupkg, None).status, Status::Skip);
+        assert_eq!(
+            hash(&nupkg, Some(&format!("{}\n", expected))).status,
+            Status::Pass
+        );
+        let finding = hash(b"something else", Some(&expected));
+        assert_eq!(finding.status, Status::Fail);
+        assert!(finding.details.contains(&expected));
+    }
+
+    #[test]
+    fn reports_signatures() {
+        let mut zip = open(NupkgBuilder::new("Foo", "1.0.0").build());
+        assert_eq!(signature(&mut zip).status, Status::Warn);
+
+        let mut zip = open(
+            NupkgBuilder::new("Foo", "1.0.0")
+                .file(SIGNATURE_FILE, &b"not really pkcs7"[..])
+                .build(),
+        );
+        let finding = signature(&mut zip);
+        assert_eq!(finding.status, Status::Pass);
+        assert!(finding.details.starts_with("Signed. Signature SHA-256: "));
+
+        let mut zip = open(
+            NupkgBuilder::new("Foo", "1.0.0")
+                .file(SIGNATURE_FILE, Vec::new())
+                .build(),
+        );
+        assert_eq!(signature(&mut zip).status, Status::Fail);
+    }
+}
@@ -0,0 +1,73 @@
+use dotnet_semver::Range;
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Clone, Debug, Diagnostic, Error)]
+pub enum VerifyError {
+    #[error("{0} verification check(s) failed")]
+    #[diagnostic(
+        code(turron::verify::failed),
+        help("Fix the problems listed above, or pass `--allow <check>` to let a check fail without failing verification.")
+    )]
+    Failed(usize),
+
+    #[error("There's no verification check called `{0}`")]
+    #[diagnostic(
+        code(turron::verify::unknown_check),
+        help("The checks are zip, nuspec, metadata, files, hash, and signature.")
+    )]
+    UnknownCheck(String),
+
+    #[error("`{0}` isn't a path to a .nupkg or a NuGet package spec")]
+    #[diagnostic(
+        code(turron::verify::invalid_package_spec),
+        help("Pass a local .nupkg, or a spec like `Foo@1.2.3` to download one.")
+    )]
+    InvalidPackageSpec(String),
+
+    #[error("Failed to find a version for {0} that satisfied {1}")]
+    #[diagnostic(
+        code(turron::verify::version_not_found),
+        help("Try running `turron view versions <id>`")
+    )]
+    VersionNotFound(String, Range),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::verify::failed",
+        cause: "At least one check found a problem with the package that would likely stop it from being published or installed.",
+        fixes: &[
+            "Read the details next to each failed check, fix the package, and verify it again.",
+            "If a failure is expected, pass `--allow <check>` to report it as a warning instead.",
+        ],
+        config: &["commands.verify.allow"],
+    },
+    Explanation {
+        code: "turron::verify::unknown_check",
+        cause: "`--allow` was given a name that isn't one of the checks `turron verify` runs.",
+        fixes: &["Use one of: zip, nuspec, metadata, files, hash, signature."],
+        config: &["commands.verify.allow"],
+    },
+    Explanation {
+        code: "turron::verify::invalid_package_spec",
+        cause: "`turron verify` takes either a local .nupkg or a NuGet package spec, and got something that's neither, like a git URL or a directory.",
+        fixes: &[
+            "Pass the path to a .nupkg file.",
+            "Pass a spec like `Foo` or `Foo@1.2.3` to download that package and verify it.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::verify::version_not_found",
+        cause: "The package exists on the source, but none of its versions match the requested range.",
+        fixes: &[
+            "Check the versions the source has with `turron view versions <id>`.",
+            "Check that --source points at the feed the package was published to.",
+        ],
+        config: &["commands.verify.source"],
+    },
+];
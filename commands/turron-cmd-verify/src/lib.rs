@@ -0,0 +1,311 @@
+use std::ffi::OsString;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use dotnet_semver::Range;
+use nuget_api::{v3::NuGetClient, SourceProtocol};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    owo_colors::{colors::*, OwoColorize},
+    render::sanitize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json,
+    smol::{self, fs},
+};
+use turron_package_spec::PackageSpec;
+use zip::ZipArchive;
+
+pub use error::{VerifyError, EXPLANATIONS};
+use report::{Finding, Status, VerificationReport};
+
+mod checks;
+mod error;
+mod report;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "verify"]
+pub struct VerifyCmd {
+    #[clap(
+        about = "Path to the .nupkg to verify, or a package spec (like `Foo@1.2.3`) to download and verify"
+    )]
+    package: String,
+    #[clap(
+        about = "Base64 SHA-512 the package should have. Defaults to the contents of a `.sha512` file next to a local .nupkg",
+        long
+    )]
+    sha512: Option<String>,
+    #[clap(
+        about = "Report failures of this check as warnings: zip, nuspec, metadata, files, hash, or signature",
+        long,
+        value_name = "CHECK",
+        number_of_values = 1
+    )]
+    allow: Vec<String>,
+    #[clap(
+        about = "Source to download packages from, when verifying a package spec",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for VerifyCmd {
+    async fn execute(self) -> Result<()> {
+        if let Some(name) = self.allow.iter().find(|name| {
+            !checks::ALL
+                .iter()
+                .any(|check| check.eq_ignore_ascii_case(name))
+        }) {
+            return Err(VerifyError::UnknownCheck(name.clone()).into());
+        }
+        let local = if is_local(&self.package) {
+            Some(PathBuf::from(&self.package))
+        } else {
+            None
+        };
+        let nupkg = match &local {
+            Some(path) => fs::read(path)
+                .await
+                .into_diagnostic()
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+            None => self.download().await?,
+        };
+        let expected = match (&self.sha512, &local) {
+            (Some(sha512), _) => Some(sha512.clone()),
+            (None, Some(path)) => fs::read_to_string(sidecar_path(path)).await.ok(),
+            (None, None) => None,
+        };
+        let mut report = smol::unblock(move || verify(&nupkg, expected.as_deref())).await;
+        report.allow(&self.allow);
+
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report)
+                    .into_diagnostic()
+                    .context("Failed to serialize verification report into JSON")?
+            );
+        } else if !self.quiet {
+            print_report(&report);
+        }
+        report.result()?;
+        Ok(())
+    }
+}
+
+impl VerifyCmd {
+    /// Fetches the version of the package spec'd by `self.package` that
+    /// the range picks.
+    async fn download(&self) -> Result<Vec<u8>> {
+        let (package_id, requested) = match self.package.parse::<PackageSpec>()? {
+            PackageSpec::NuGet { name, requested } => {
+                (name, requested.unwrap_or_else(Range::any_floating))
+            }
+            _ => return Err(VerifyError::InvalidPackageSpec(self.package.clone()).into()),
+        };
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let versions = client.versions(&package_id).await?;
+        let version = turron_pick_version::pick_version(&requested, &versions[..])
+            .ok_or_else(|| VerifyError::VersionNotFound(package_id.clone(), requested.clone()))?;
+        Ok(client.nupkg(&package_id, &version).await?)
+    }
+}
+
+/// Runs every check against `nupkg`. Checks that need to look inside the
+/// archive are skipped if it can't be opened at all, and ones that need the
+/// nuspec are skipped if it can't be read.
+fn verify(nupkg: &[u8], expected_sha512: Option<&str>) -> VerificationReport {
+    let mut report = VerificationReport::default();
+    let mut zip = None;
+    report.run(checks::ZIP, || match ZipArchive::new(Cursor::new(nupkg)) {
+        Ok(mut archive) => {
+            let finding = checks::zip_integrity(&mut archive);
+            zip = Some(archive);
+            finding
+        }
+        Err(err) => Finding::fail(format!(
+            "The package can't be opened as a zip file: {}",
+            err
+        )),
+    });
+    match &mut zip {
+        Some(zip) => report.run(checks::NUSPEC, || checks::nuspec(zip)),
+        None => report.run(checks::NUSPEC, || {
+            Finding::skip("The package couldn't be opened")
+        }),
+    }
+    let nuspec = zip.as_mut().and_then(|zip| checks::read_nuspec(zip).ok());
+    match &nuspec {
+        Some(nuspec) => report.run(checks::METADATA, || checks::metadata(&nuspec.metadata)),
+        None => report.run(checks::METADATA, || {
+            Finding::skip("The nuspec couldn't be read")
+        }),
+    }
+    match (&zip, &nuspec) {
+        (Some(zip), Some(nuspec)) => report.run(checks::FILES, || {
+            checks::referenced_files(zip, &nuspec.metadata)
+        }),
+        _ => report.run(checks::FILES, || {
+            Finding::skip("The nuspec couldn't be read")
+        }),
+    }
+    report.run(checks::HASH, || checks::hash(nupkg, expected_sha512));
+    match &mut zip {
+        Some(zip) => report.run(checks::SIGNATURE, || checks::signature(zip)),
+        None => report.run(checks::SIGNATURE, || {
+            Finding::skip("The package couldn't be opened")
+        }),
+    }
+    report
+}
+
+/// Whether `package` is a local file rather than a package spec. Anything
+/// ending in `.nupkg` counts, even if it doesn't exist, since no package is
+/// named that.
+fn is_local(package: &str) -> bool {
+    package.to_lowercase().ends_with(".nupkg") || Path::new(package).is_file()
+}
+
+/// `foo.nupkg.sha512`, like the ones `turron download` writes.
+fn sidecar_path(nupkg: &Path) -> PathBuf {
+    let mut path = OsString::from(nupkg.as_os_str());
+    path.push(".sha512");
+    path.into()
+}
+
+fn print_report(report: &VerificationReport) {
+    let width = report
+        .checks
+        .iter()
+        .map(|check| check.name.len())
+        .max()
+        .unwrap_or(0);
+    for check in &report.checks {
+        let symbol = match check.status {
+            Status::Pass => "✓".fg::<Green>().to_string(),
+            Status::Skip => "-".dimmed().to_string(),
+            Status::Warn => "!".fg::<Yellow>().to_string(),
+            Status::Fail => "✗".fg::<Red>().to_string(),
+        };
+        println!(
+            "{} {:width$}  {}",
+            symbol,
+            check.name,
+            sanitize(&check.details),
+            width = width
+        );
+    }
+    println!();
+    println!(
+        "{} passed, {} warned, {} failed, {} skipped",
+        report.count(Status::Pass),
+        report.count(Status::Warn),
+        report.count(Status::Fail),
+        report.count(Status::Skip)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use sha2::{Digest, Sha512};
+    use turron_testing::NupkgBuilder;
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    fn statuses(report: &VerificationReport) -> Vec<(&str, Status)> {
+        report
+            .checks
+            .iter()
+            .map(|check| (&check.name[..], check.status))
+            .collect()
+    }
+
+    #[test]
+    fn runs_every_check() {
+        let nupkg = NupkgBuilder::new("Foo", "1.0.0").build();
+        let expected = base64::encode(Sha512::digest(&nupkg));
+        let report = verify(&nupkg, Some(&expected));
+        assert_eq!(
+            statuses(&report),
+            vec![
+                ("zip", Status::Pass),
+                ("nuspec", Status::Pass),
+                ("metadata", Status::Pass),
+                ("files", Status::Pass),
+                ("hash", Status::Pass),
+                ("signature", Status::Warn),
+            ]
+        );
+        assert!(report.result().is_ok());
+    }
+
+    #[test]
+    fn skips_checks_that_need_the_nuspec() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("foo.nuspec", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"<package>").unwrap();
+        let nupkg = zip.finish().unwrap().into_inner();
+        let report = verify(&nupkg, None);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                ("zip", Status::Pass),
+                ("nuspec", Status::Fail),
+                ("metadata", Status::Skip),
+                ("files", Status::Skip),
+                ("hash", Status::Skip),
+                ("signature", Status::Warn),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_checks_when_the_archive_is_unreadable() {
+        let report = verify(b"not a zip", None);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                ("zip", Status::Fail),
+                ("nuspec", Status::Skip),
+                ("metadata", Status::Skip),
+                ("files", Status::Skip),
+                ("hash", Status::Skip),
+                ("signature", Status::Skip),
+            ]
+        );
+        assert!(matches!(report.result(), Err(VerifyError::Failed(1))));
+    }
+
+    #[test]
+    fn tells_paths_from_specs() {
+        assert!(is_local("Foo.1.0.0.nupkg"));
+        assert!(is_local("out/Foo.1.0.0.NUPKG"));
+        assert!(!is_local("Foo@1.0.0"));
+        assert!(!is_local("Newtonsoft.Json"));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package");
+        std::fs::write(&path, b"").unwrap();
+        assert!(is_local(path.to_str().unwrap()));
+    }
+}
@@ -0,0 +1,214 @@
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Serializer};
+
+use crate::error::VerifyError;
+
+/// How a check came out, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Pass,
+    /// The check couldn't run, usually because an earlier one failed.
+    Skip,
+    Warn,
+    Fail,
+}
+
+/// What a single check found, before it's timed and named.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Finding {
+    pub(crate) status: Status,
+    pub(crate) details: String,
+}
+
+impl Finding {
+    pub(crate) fn pass(details: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Pass,
+            details: details.into(),
+        }
+    }
+
+    pub(crate) fn skip(details: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Skip,
+            details: details.into(),
+        }
+    }
+
+    pub(crate) fn warn(details: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Warn,
+            details: details.into(),
+        }
+    }
+
+    pub(crate) fn fail(details: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Fail,
+            details: details.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct CheckResult {
+    pub(crate) name: String,
+    pub(crate) status: Status,
+    pub(crate) details: String,
+    /// In milliseconds, in JSON.
+    #[serde(serialize_with = "millis")]
+    pub(crate) duration: Duration,
+}
+
+/// Everything `turron verify` checked. Both the text and JSON output come
+/// from this.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub(crate) struct VerificationReport {
+    pub(crate) checks: Vec<CheckResult>,
+}
+
+impl VerificationReport {
+    /// Runs one check, timing it.
+    pub(crate) fn run(&mut self, name: &str, check: impl FnOnce() -> Finding) {
+        let start = Instant::now();
+        let finding = check();
+        self.checks.push(CheckResult {
+            name: name.into(),
+            status: finding.status,
+            details: finding.details,
+            duration: start.elapsed(),
+        });
+    }
+
+    /// Downgrades failures of the checks named in `allowed` to warnings.
+    pub(crate) fn allow(&mut self, allowed: &[String]) {
+        for check in &mut self.checks {
+            if check.status == Status::Fail
+                && allowed
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(&check.name))
+            {
+                check.status = Status::Warn;
+                check.details.push_str(" (allowed)");
+            }
+        }
+    }
+
+    /// The worst status of any check. An empty report passes.
+    pub(crate) fn worst(&self) -> Status {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(Status::Pass)
+    }
+
+    pub(crate) fn count(&self, status: Status) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == status)
+            .count()
+    }
+
+    /// Fails if any check did. Warnings and skipped checks don't count.
+    pub(crate) fn result(&self) -> Result<(), VerifyError> {
+        match self.worst() {
+            Status::Fail => Err(VerifyError::Failed(self.count(Status::Fail))),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::serde_json::{self, json};
+
+    use super::*;
+
+    fn report(statuses: &[(&str, Status)]) -> VerificationReport {
+        VerificationReport {
+            checks: statuses
+                .iter()
+                .map(|(name, status)| CheckResult {
+                    name: name.to_string(),
+                    status: *status,
+                    details: "details".into(),
+                    duration: Duration::from_millis(12),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn worst_status_sets_the_result() {
+        assert_eq!(VerificationReport::default().worst(), Status::Pass);
+        assert!(VerificationReport::default().result().is_ok());
+
+        let passing = report(&[("zip", Status::Pass), ("signature", Status::Warn)]);
+        assert_eq!(passing.worst(), Status::Warn);
+        assert!(passing.result().is_ok());
+
+        let skipped = report(&[("zip", Status::Pass), ("hash", Status::Skip)]);
+        assert_eq!(skipped.worst(), Status::Skip);
+        assert!(skipped.result().is_ok());
+
+        let failing = report(&[
+            ("zip", Status::Fail),
+            ("nuspec", Status::Fail),
+            ("hash", Status::Skip),
+        ]);
+        assert_eq!(failing.worst(), Status::Fail);
+        assert!(matches!(failing.result(), Err(VerifyError::Failed(2))));
+    }
+
+    #[test]
+    fn allowed_failures_become_warnings() {
+        let mut failing = report(&[
+            ("hash", Status::Fail),
+            ("nuspec", Status::Fail),
+            ("zip", Status::Pass),
+        ]);
+        failing.allow(&["HASH".into(), "zip".into()]);
+        let statuses = failing
+            .checks
+            .iter()
+            .map(|check| check.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, vec![Status::Warn, Status::Fail, Status::Pass]);
+        assert_eq!(failing.checks[0].details, "details (allowed)");
+        assert_eq!(failing.checks[2].details, "details");
+        assert!(matches!(failing.result(), Err(VerifyError::Failed(1))));
+
+        failing.allow(&["nuspec".into()]);
+        assert_eq!(failing.worst(), Status::Warn);
+        assert!(failing.result().is_ok());
+    }
+
+    #[test]
+    fn serializes_checks() {
+        let mut report = report(&[("zip", Status::Pass)]);
+        report.run("signature", || Finding::warn("The package isn't signed."));
+        let mut json = serde_json::to_value(&report).unwrap();
+        json["checks"][1]["duration"] = json!(0);
+        assert_eq!(
+            json,
+            json!({
+                "checks": [
+                    { "name": "zip", "status": "pass", "details": "details", "duration": 12 },
+                    {
+                        "name": "signature",
+                        "status": "warn",
+                        "details": "The package isn't signed.",
+                        "duration": 0,
+                    },
+                ]
+            })
+        );
+    }
+}
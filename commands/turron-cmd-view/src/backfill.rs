@@ -0,0 +1,211 @@
+use std::fmt;
+
+use nuget_api::v3::{Authors, CatalogEntry, NuSpecMetadata, Tags};
+
+/// Catalog entry fields `view summary` will fill in from the nuspec when the
+/// registration leaves them out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Field {
+    Description,
+    Authors,
+    License,
+    ProjectUrl,
+    Tags,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Named after the `CatalogEntry` fields they fill, as they're spelled
+        // in JSON.
+        match self {
+            Field::Description => write!(f, "description"),
+            Field::Authors => write!(f, "authors"),
+            Field::License => write!(f, "licenseExpression"),
+            Field::ProjectUrl => write!(f, "projectUrl"),
+            Field::Tags => write!(f, "tags"),
+        }
+    }
+}
+
+/// A catalog entry with gaps filled in from the package's own nuspec.
+#[derive(Debug, Clone)]
+pub(crate) struct Backfilled {
+    pub(crate) entry: CatalogEntry,
+    /// Which fields came from the nuspec, in [`Field`] order.
+    pub(crate) fields: Vec<Field>,
+}
+
+impl Backfilled {
+    /// Merges `metadata` into `entry`. Whatever the registration has wins,
+    /// and a field only counts as missing if it's absent or blank.
+    ///
+    /// Nuspecs don't say whether `<license>` is an expression or a file, so
+    /// it's only used when the registration has no license URL either.
+    pub(crate) fn new(entry: &CatalogEntry, metadata: &NuSpecMetadata) -> Self {
+        let mut entry = entry.clone();
+        let mut fields = Vec::new();
+        if is_blank(entry.description.as_deref()) && !metadata.description.trim().is_empty() {
+            entry.description = Some(metadata.description.clone());
+            fields.push(Field::Description);
+        }
+        if entry.authors.as_ref().map_or(true, authors_blank) {
+            let authors = split(&metadata.authors, |c| c == ',');
+            if !authors.is_empty() {
+                entry.authors = Some(Authors::Many(authors));
+                fields.push(Field::Authors);
+            }
+        }
+        if is_blank(entry.license_expression.as_deref())
+            && is_blank(entry.license_url.as_deref())
+            && !is_blank(metadata.license.as_deref())
+        {
+            entry.license_expression = metadata.license.as_ref().map(|l| l.trim().to_string());
+            fields.push(Field::License);
+        }
+        if is_blank(entry.project_url.as_deref()) {
+            if let Some(url) = &metadata.project_url {
+                entry.project_url = Some(url.to_string());
+                fields.push(Field::ProjectUrl);
+            }
+        }
+        if entry.tags.as_ref().map_or(true, tags_blank) {
+            let tags = split(
+                metadata.tags.as_deref().unwrap_or_default(),
+                char::is_whitespace,
+            );
+            if !tags.is_empty() {
+                entry.tags = Some(Tags::Many(tags));
+                fields.push(Field::Tags);
+            }
+        }
+        Backfilled { entry, fields }
+    }
+
+    pub(crate) fn contains(&self, field: Field) -> bool {
+        self.fields.contains(&field)
+    }
+
+    /// What to put after a field's value to show it came from the nuspec.
+    pub(crate) fn marker(&self, field: Field) -> &'static str {
+        if self.contains(field) {
+            "*"
+        } else {
+            ""
+        }
+    }
+}
+
+fn is_blank(value: Option<&str>) -> bool {
+    value.map_or(true, |value| value.trim().is_empty())
+}
+
+fn authors_blank(authors: &Authors) -> bool {
+    match authors {
+        Authors::One(author) => author.trim().is_empty(),
+        Authors::Many(authors) => authors.iter().all(|a| a.trim().is_empty()),
+    }
+}
+
+fn tags_blank(tags: &Tags) -> bool {
+    match tags {
+        Tags::One(tag) => tag.trim().is_empty(),
+        Tags::Many(tags) => tags.iter().all(|t| t.trim().is_empty()),
+    }
+}
+
+fn split(list: &str, separator: fn(char) -> bool) -> Vec<String> {
+    list.split(separator)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use nuget_api::v3::NuSpec;
+    use turron_common::{
+        quick_xml,
+        serde_json::{self, json},
+    };
+    use turron_testing::fixtures;
+
+    use super::*;
+
+    fn metadata() -> NuSpecMetadata {
+        quick_xml::de::from_str::<NuSpec>(fixtures::nuspec())
+            .unwrap()
+            .metadata
+    }
+
+    fn entry(json: serde_json::Value) -> CatalogEntry {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn fills_in_missing_fields() {
+        let merged = Backfilled::new(
+            &entry(json!({
+                "id": "Newtonsoft.Json",
+                "version": "13.0.1",
+                "description": "  ",
+                "tags": [""],
+            })),
+            &metadata(),
+        );
+        assert_eq!(
+            merged.fields,
+            vec![
+                Field::Description,
+                Field::Authors,
+                Field::License,
+                Field::ProjectUrl,
+                Field::Tags
+            ]
+        );
+        let entry = serde_json::to_value(&merged.entry).unwrap();
+        assert_eq!(
+            entry["description"],
+            "Json.NET is a popular high-performance JSON framework for .NET"
+        );
+        assert_eq!(entry["authors"], json!(["James Newton-King"]));
+        assert_eq!(entry["licenseExpression"], "MIT");
+        assert_eq!(entry["projectUrl"], "https://www.newtonsoft.com/json");
+        assert_eq!(entry["tags"], json!(["json"]));
+        assert_eq!(merged.marker(Field::Tags), "*");
+    }
+
+    #[test]
+    fn registration_wins() {
+        let original = entry(json!({
+            "id": "Newtonsoft.Json",
+            "version": "13.0.1",
+            "authors": "Someone Else",
+            "description": "From the registration",
+            "licenseExpression": "Apache-2.0",
+            "projectUrl": "https://example.com",
+            "tags": "registration",
+        }));
+        let merged = Backfilled::new(&original, &metadata());
+        assert!(merged.fields.is_empty());
+        assert_eq!(merged.marker(Field::Description), "");
+        assert_eq!(
+            serde_json::to_value(&merged.entry).unwrap(),
+            serde_json::to_value(&original).unwrap()
+        );
+    }
+
+    #[test]
+    fn license_urls_count_as_licenses() {
+        let merged = Backfilled::new(
+            &entry(json!({
+                "id": "Newtonsoft.Json",
+                "version": "13.0.1",
+                "licenseUrl": "https://example.com/LICENSE",
+            })),
+            &metadata(),
+        );
+        assert!(!merged.contains(Field::License));
+        assert!(merged.entry.license_expression.is_none());
+    }
+}
@@ -1,22 +1,30 @@
 use dotnet_semver::{Range, Version};
 use turron_common::{
+    explain::Explanation,
     miette::{self, Diagnostic},
     thiserror::{self, Error},
 };
 
 #[derive(Clone, Debug, Diagnostic, Error)]
 pub enum ViewError {
-    #[error("Invalid utf8 text")]
-    #[diagnostic(
-        code(turron::view::invalid_utf8),
-        help("turron only supports text files which are valid UTF-8 text.")
-    )]
-    InvalidUtf8(#[from] std::string::FromUtf8Error),
-
     #[error("Only NuGet package specifiers are acceptable. Directories and git repositories are not supported... yet ��")]
     #[diagnostic(code(turron::view::invalid_package_spec))]
     InvalidPackageSpec,
 
+    #[error("{id} wasn't found on the source.{}", did_you_mean(.candidates))]
+    #[diagnostic(
+        code(turron::view::package_not_found),
+        help("Check the spelling of the package id, or search for it with `turron search`.")
+    )]
+    PackageNotFound { id: String, candidates: Vec<String> },
+
+    #[error("`{spec}` already includes a version, so `{version}` can't be used too.")]
+    #[diagnostic(
+        code(turron::view::conflicting_versions),
+        help("Pass either `<id>@<version>` or `<id> <version>`, but not both.")
+    )]
+    ConflictingVersions { spec: String, version: String },
+
     #[error("Failed to find a version for {0} that satisfied {1}")]
     #[diagnostic(
         code(turron::view::version_not_found),
@@ -24,6 +32,13 @@ pub enum ViewError {
     )]
     VersionNotFound(String, Range),
 
+    #[error("{0} exists, but version {1} wasn't found.")]
+    #[diagnostic(
+        code(turron::view::version_not_indexed),
+        help("If this version was just published, the source may still be indexing it, which can take several minutes on nuget.org. Pass `--wait 10m` to keep checking until it shows up.")
+    )]
+    VersionNotIndexed(String, Version),
+
     #[error("{0}@{1} does not have a readme")]
     #[diagnostic(code(turron::view::readme_not_found), help("turron only supports READMEs included in the package itself, which is not commonly used."))]
     ReadmeNotFound(String, Version),
@@ -31,7 +46,67 @@ pub enum ViewError {
     #[error("{0}@{1} does not have an icon")]
     #[diagnostic(
         code(turron::view::icon_not_found),
-        help("turron only supports icons included in the package itself, not iconUrl.")
+        help("Its nuspec has neither an embedded icon nor an iconUrl.")
     )]
     IconNotFound(String, Version),
 }
+
+fn did_you_mean(candidates: &[String]) -> String {
+    if candidates.is_empty() {
+        String::new()
+    } else {
+        format!(" Did you mean one of these? {}", candidates.join(", "))
+    }
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::view::invalid_package_spec",
+        cause: "`turron view` only shows packages from a NuGet source. Paths and git specs aren't supported yet.",
+        fixes: &["Pass a package id, optionally with a version, e.g. `Newtonsoft.Json@13.0.1`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::view::package_not_found",
+        cause: "The source has no package with the requested id. When turron can't ask which package was meant, it lists similarly named packages from a search instead.",
+        fixes: &[
+            "Use one of the suggested ids, if any were listed.",
+            "Run `turron search <term>` to look for the package.",
+            "Make sure --source points at the source the package was published to.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::view::conflicting_versions",
+        cause: "A version was given twice: once in the spec after `@`, and once as a separate argument.",
+        fixes: &["Use either `turron view <id>@<version>` or `turron view <id> <version>`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::view::version_not_found",
+        cause: "The package exists, but none of its published versions satisfy the requested version or range.",
+        fixes: &["Run `turron view <id> versions` to see what's available."],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::view::version_not_indexed",
+        cause: "The package is on the source, but the requested version isn't listed yet. Sources index new pushes asynchronously, so a version can be missing for a while right after it's published.",
+        fixes: &[
+            "Pass `--wait <duration>` to `turron view summary` or `turron view versions` to keep checking, e.g. `--wait 10m`.",
+            "Double-check the version number with `turron view <id> versions`.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::view::readme_not_found",
+        cause: "The package doesn't embed a readme. turron only shows readmes packed inside the .nupkg, not ones hosted on a project site.",
+        fixes: &["Check the package's project URL with `turron view <id>`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::view::icon_not_found",
+        cause: "The package doesn't embed an icon, and its nuspec has no `iconUrl` to download one from either.",
+        fixes: &["Check the package's project URL with `turron view <id>`."],
+        config: &[],
+    },
+];
@@ -0,0 +1,544 @@
+//! The `view summary --health` score: a 0-100 number built from a handful
+//! of factors, each with its own weight and explanation so the total can
+//! be checked by hand. Weights and thresholds can be tuned with a `health`
+//! block:
+//!
+//! ```kdl
+//! health {
+//!     weights {
+//!         recency 25
+//!         cadence 15
+//!         metadata 15
+//!         status 25
+//!         downloads 10
+//!         dependencies 10
+//!     }
+//!     fresh_days 180
+//!     stale_days 730
+//!     releases_per_year 4
+//!     few_dependencies 5
+//!     many_dependencies 30
+//! }
+//! ```
+//!
+//! A factor there's no data for (download counts, on sources without
+//! search) is left out, and the total is taken over the rest.
+
+use turron_command::turron_config::{config_value, TurronConfig, TurronConfigError};
+use turron_common::{
+    chrono::{DateTime, Duration, Utc},
+    serde_json::{json, Value},
+};
+
+/// What the score is computed from. Everything here comes out of the
+/// registration, nuspec, and search data `view summary` already has.
+#[derive(Clone, Debug)]
+pub(crate) struct PackageFacts {
+    pub(crate) now: DateTime<Utc>,
+    /// When each listed version was published.
+    pub(crate) releases: Vec<DateTime<Utc>>,
+    pub(crate) has_license: bool,
+    pub(crate) has_readme: bool,
+    pub(crate) has_repository: bool,
+    pub(crate) deprecated: bool,
+    pub(crate) vulnerabilities: usize,
+    /// `None` if the source couldn't say how often the package gets
+    /// downloaded.
+    pub(crate) downloads: Option<Downloads>,
+    /// Distinct packages the viewed version depends on.
+    pub(crate) dependencies: usize,
+}
+
+/// Download counts, for comparing how fast the newest release is picked up
+/// against the package's lifetime average.
+#[derive(Clone, Debug)]
+pub(crate) struct Downloads {
+    pub(crate) total: u64,
+    pub(crate) latest: u64,
+    pub(crate) latest_published: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Factor {
+    Recency,
+    Cadence,
+    Metadata,
+    Status,
+    Downloads,
+    Dependencies,
+}
+
+impl Factor {
+    pub(crate) const ALL: [Factor; 6] = [
+        Factor::Recency,
+        Factor::Cadence,
+        Factor::Metadata,
+        Factor::Status,
+        Factor::Downloads,
+        Factor::Dependencies,
+    ];
+
+    /// The factor's name in the `health.weights` block and in JSON output.
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            Factor::Recency => "recency",
+            Factor::Cadence => "cadence",
+            Factor::Metadata => "metadata",
+            Factor::Status => "status",
+            Factor::Downloads => "downloads",
+            Factor::Dependencies => "dependencies",
+        }
+    }
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            Factor::Recency => "Last release",
+            Factor::Cadence => "Release cadence",
+            Factor::Metadata => "License, readme, repository",
+            Factor::Status => "Deprecation, vulnerabilities",
+            Factor::Downloads => "Download trend",
+            Factor::Dependencies => "Dependencies",
+        }
+    }
+
+    fn default_weight(&self) -> u32 {
+        match self {
+            Factor::Recency => 25,
+            Factor::Cadence => 15,
+            Factor::Metadata => 15,
+            Factor::Status => 25,
+            Factor::Downloads => 10,
+            Factor::Dependencies => 10,
+        }
+    }
+}
+
+/// Weights and thresholds for [`score`], from the `health` config block.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HealthConfig {
+    /// One weight per factor, in [`Factor::ALL`] order.
+    pub(crate) weights: Vec<(Factor, u32)>,
+    /// A last release at most this old scores full marks...
+    pub(crate) fresh_days: i64,
+    /// ...and one at least this old scores nothing.
+    pub(crate) stale_days: i64,
+    /// Releases in the past year needed for full marks on cadence.
+    pub(crate) releases_per_year: u32,
+    /// Up to this many dependencies scores full marks...
+    pub(crate) few_dependencies: usize,
+    /// ...and this many or more scores nothing.
+    pub(crate) many_dependencies: usize,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            weights: Factor::ALL
+                .iter()
+                .map(|factor| (*factor, factor.default_weight()))
+                .collect(),
+            fresh_days: 180,
+            stale_days: 730,
+            releases_per_year: 4,
+            few_dependencies: 5,
+            many_dependencies: 30,
+        }
+    }
+}
+
+impl HealthConfig {
+    /// The defaults, with anything set in the `health` block layered on
+    /// top.
+    pub(crate) fn from_config(config: &TurronConfig) -> Result<Self, TurronConfigError> {
+        let mut health = Self::default();
+        for (factor, weight) in &mut health.weights {
+            let key = format!("health.weights.{}", factor.key());
+            if let Some(val) = config_value(config, &[&key])? {
+                *weight = val;
+            }
+        }
+        if let Some(val) = config_value(config, &["health.fresh_days"])? {
+            health.fresh_days = val;
+        }
+        if let Some(val) = config_value(config, &["health.stale_days"])? {
+            health.stale_days = val;
+        }
+        if let Some(val) = config_value(config, &["health.releases_per_year"])? {
+            health.releases_per_year = val;
+        }
+        if let Some(val) = config_value(config, &["health.few_dependencies"])? {
+            health.few_dependencies = val;
+        }
+        if let Some(val) = config_value(config, &["health.many_dependencies"])? {
+            health.many_dependencies = val;
+        }
+        Ok(health)
+    }
+}
+
+/// A package's health score and how it got there.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Health {
+    /// The weighted average of the factors' scores, from 0 to 100.
+    pub(crate) score: u8,
+    pub(crate) factors: Vec<FactorScore>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FactorScore {
+    pub(crate) factor: Factor,
+    pub(crate) weight: u32,
+    /// From 0 to 100, or `None` if there was nothing to score it on.
+    pub(crate) score: Option<u8>,
+    /// Why it got that score.
+    pub(crate) detail: String,
+}
+
+impl Health {
+    /// `{ "score": 87, "factors": [{ "factor", "weight", "score", "detail" }] }`
+    pub(crate) fn to_json(&self) -> Value {
+        let factors = self
+            .factors
+            .iter()
+            .map(|factor| {
+                json!({
+                    "factor": factor.factor.key(),
+                    "weight": factor.weight,
+                    "score": factor.score,
+                    "detail": factor.detail,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "score": self.score, "factors": factors })
+    }
+}
+
+/// Scores `facts`. Factors without data don't count towards the total, and
+/// if nothing counts at all, the score is 0.
+pub(crate) fn score(facts: &PackageFacts, config: &HealthConfig) -> Health {
+    let factors = config
+        .weights
+        .iter()
+        .map(|(factor, weight)| {
+            let (score, detail) = score_factor(*factor, facts, config);
+            FactorScore {
+                factor: *factor,
+                weight: *weight,
+                score,
+                detail,
+            }
+        })
+        .collect::<Vec<_>>();
+    let (total, weights) = factors
+        .iter()
+        .filter_map(|factor| factor.score.map(|score| (score, factor.weight)))
+        .fold((0u64, 0u64), |(total, weights), (score, weight)| {
+            (
+                total + u64::from(score) * u64::from(weight),
+                weights + u64::from(weight),
+            )
+        });
+    let score = if weights == 0 {
+        0
+    } else {
+        ((total + weights / 2) / weights) as u8
+    };
+    Health { score, factors }
+}
+
+fn score_factor(
+    factor: Factor,
+    facts: &PackageFacts,
+    config: &HealthConfig,
+) -> (Option<u8>, String) {
+    match factor {
+        Factor::Recency => match facts.releases.iter().max() {
+            Some(latest) => {
+                let days = days_between(*latest, facts.now);
+                (
+                    Some(falloff(days, config.fresh_days, config.stale_days)),
+                    format!(
+                        "last listed release was {} ago",
+                        plural(days, "day", "days")
+                    ),
+                )
+            }
+            None => (Some(0), "no listed releases".into()),
+        },
+        Factor::Cadence => {
+            let recent = facts
+                .releases
+                .iter()
+                .filter(|published| days_between(**published, facts.now) <= 365)
+                .count() as u32;
+            let score = if recent >= config.releases_per_year {
+                100
+            } else {
+                recent * 100 / config.releases_per_year
+            };
+            (
+                Some(score as u8),
+                format!(
+                    "{} in the past year (aiming for {})",
+                    plural(recent.into(), "release", "releases"),
+                    config.releases_per_year
+                ),
+            )
+        }
+        Factor::Metadata => {
+            let fields = [
+                ("license", facts.has_license),
+                ("readme", facts.has_readme),
+                ("repository", facts.has_repository),
+            ];
+            let present = fields.iter().filter(|(_, present)| *present).count();
+            let missing = fields
+                .iter()
+                .filter(|(_, present)| !present)
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>();
+            let detail = if missing.is_empty() {
+                "has all three".into()
+            } else {
+                format!("missing {}", missing.join(", "))
+            };
+            (Some(((present * 100 + 1) / fields.len()) as u8), detail)
+        }
+        Factor::Status => {
+            let mut problems = Vec::new();
+            if facts.deprecated {
+                problems.push("deprecated".to_string());
+            }
+            if facts.vulnerabilities > 0 {
+                problems.push(format!(
+                    "{} known",
+                    plural(
+                        facts.vulnerabilities as i64,
+                        "vulnerability",
+                        "vulnerabilities",
+                    )
+                ));
+            }
+            if problems.is_empty() {
+                (Some(100), "not deprecated, no known vulnerabilities".into())
+            } else {
+                (Some(0), problems.join(", "))
+            }
+        }
+        Factor::Downloads => match (&facts.downloads, facts.releases.iter().min()) {
+            (Some(downloads), Some(first)) => {
+                let lifetime =
+                    downloads.total as f64 / days_between(*first, facts.now).max(1) as f64;
+                let latest = downloads.latest as f64
+                    / days_between(downloads.latest_published, facts.now).max(1) as f64;
+                if lifetime == 0.0 {
+                    (Some(0), "never downloaded".into())
+                } else {
+                    let ratio = latest / lifetime;
+                    (
+                        Some((ratio.min(1.0) * 100.0).round() as u8),
+                        format!(
+                            "newest release gets {:.0}% of the lifetime average downloads per day",
+                            ratio * 100.0
+                        ),
+                    )
+                }
+            }
+            _ => (None, "download counts unavailable".into()),
+        },
+        Factor::Dependencies => (
+            Some(falloff(
+                facts.dependencies as i64,
+                config.few_dependencies as i64,
+                config.many_dependencies as i64,
+            )),
+            plural(facts.dependencies as i64, "dependency", "dependencies"),
+        ),
+    }
+}
+
+/// 100 at or below `good`, 0 at or above `bad`, and a straight line in
+/// between.
+fn falloff(value: i64, good: i64, bad: i64) -> u8 {
+    if value <= good {
+        100
+    } else if value >= bad {
+        0
+    } else {
+        (100 - (value - good) * 100 / (bad - good)) as u8
+    }
+}
+
+/// Whole days from `from` to `to`, never negative.
+fn days_between(from: DateTime<Utc>, to: DateTime<Utc>) -> i64 {
+    (to - from).max(Duration::zero()).num_days()
+}
+
+fn plural(count: i64, one: &str, many: &str) -> String {
+    if count == 1 {
+        format!("1 {}", one)
+    } else {
+        format!("{} {}", count, many)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::chrono::TimeZone;
+
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2021, 6, 1).and_hms(0, 0, 0)
+    }
+
+    fn days_ago(days: i64) -> DateTime<Utc> {
+        now() - Duration::days(days)
+    }
+
+    fn healthy() -> PackageFacts {
+        PackageFacts {
+            now: now(),
+            releases: vec![
+                days_ago(400),
+                days_ago(200),
+                days_ago(100),
+                days_ago(30),
+                days_ago(10),
+            ],
+            has_license: true,
+            has_readme: true,
+            has_repository: true,
+            deprecated: false,
+            vulnerabilities: 0,
+            downloads: Some(Downloads {
+                total: 4_000,
+                latest: 1_000,
+                latest_published: days_ago(10),
+            }),
+            dependencies: 2,
+        }
+    }
+
+    fn factor(health: &Health, factor: Factor) -> &FactorScore {
+        health.factors.iter().find(|f| f.factor == factor).unwrap()
+    }
+
+    #[test]
+    fn scores_a_healthy_package_full_marks() {
+        let health = score(&healthy(), &HealthConfig::default());
+        assert_eq!(health.score, 100);
+        assert_eq!(health.factors.len(), Factor::ALL.len());
+        for f in &health.factors {
+            assert_eq!(f.score, Some(100), "{:?}", f);
+        }
+        assert_eq!(
+            factor(&health, Factor::Cadence).detail,
+            "4 releases in the past year (aiming for 4)"
+        );
+    }
+
+    #[test]
+    fn explains_each_factor() {
+        let facts = PackageFacts {
+            releases: vec![days_ago(1000), days_ago(455)],
+            has_readme: false,
+            has_repository: false,
+            deprecated: true,
+            vulnerabilities: 2,
+            downloads: Some(Downloads {
+                total: 1_000,
+                latest: 100,
+                latest_published: days_ago(455),
+            }),
+            dependencies: 30,
+            ..healthy()
+        };
+        let health = score(&facts, &HealthConfig::default());
+
+        let recency = factor(&health, Factor::Recency);
+        assert_eq!(recency.score, Some(50));
+        assert_eq!(recency.detail, "last listed release was 455 days ago");
+        assert_eq!(factor(&health, Factor::Cadence).score, Some(0));
+        let metadata = factor(&health, Factor::Metadata);
+        assert_eq!(metadata.score, Some(33));
+        assert_eq!(metadata.detail, "missing readme, repository");
+        let status = factor(&health, Factor::Status);
+        assert_eq!(status.score, Some(0));
+        assert_eq!(status.detail, "deprecated, 2 vulnerabilities known");
+        // 100/455 a day against 1000/1000.
+        assert_eq!(factor(&health, Factor::Downloads).score, Some(22));
+        let deps = factor(&health, Factor::Dependencies);
+        assert_eq!(deps.score, Some(0));
+        assert_eq!(deps.detail, "30 dependencies");
+
+        // (25*50 + 15*0 + 15*33 + 25*0 + 10*22 + 10*0) / 100
+        assert_eq!(health.score, 20);
+    }
+
+    #[test]
+    fn leaves_out_factors_without_data() {
+        let facts = PackageFacts {
+            downloads: None,
+            dependencies: 30,
+            ..healthy()
+        };
+        let health = score(&facts, &HealthConfig::default());
+        let downloads = factor(&health, Factor::Downloads);
+        assert_eq!(downloads.score, None);
+        assert_eq!(downloads.weight, 10);
+        // Everything else is perfect except dependencies: 80 of 90.
+        assert_eq!(health.score, 89);
+    }
+
+    #[test]
+    fn uses_configured_weights_and_thresholds() {
+        let config = HealthConfig {
+            weights: Factor::ALL
+                .iter()
+                .map(|f| (*f, if *f == Factor::Dependencies { 1 } else { 0 }))
+                .collect(),
+            few_dependencies: 0,
+            many_dependencies: 4,
+            ..HealthConfig::default()
+        };
+        let health = score(&healthy(), &config);
+        assert_eq!(factor(&health, Factor::Recency).weight, 0);
+        assert_eq!(health.score, 50);
+
+        let nothing = HealthConfig {
+            weights: Factor::ALL.iter().map(|f| (*f, 0)).collect(),
+            ..HealthConfig::default()
+        };
+        assert_eq!(score(&healthy(), &nothing).score, 0);
+    }
+
+    #[test]
+    fn scores_packages_with_no_listed_releases() {
+        let facts = PackageFacts {
+            releases: Vec::new(),
+            ..healthy()
+        };
+        let health = score(&facts, &HealthConfig::default());
+        assert_eq!(factor(&health, Factor::Recency).score, Some(0));
+        assert_eq!(
+            factor(&health, Factor::Recency).detail,
+            "no listed releases"
+        );
+        assert_eq!(factor(&health, Factor::Downloads).score, None);
+    }
+
+    #[test]
+    fn writes_factors_to_json() {
+        let health = score(&healthy(), &HealthConfig::default());
+        let json = health.to_json();
+        assert_eq!(json["score"], 100);
+        assert_eq!(json["factors"][0]["factor"], "recency");
+        assert_eq!(json["factors"][0]["weight"], 25);
+        assert_eq!(json["factors"][0]["score"], 100);
+        assert_eq!(
+            json["factors"][0]["detail"],
+            "last listed release was 10 days ago"
+        );
+    }
+}
@@ -6,10 +6,19 @@ use turron_command::{
 };
 use turron_common::{miette::Result, tracing};
 
-use subcommands::{IconCmd, ReadmeCmd, SummaryCmd, VersionsCmd};
+pub use error::EXPLANATIONS;
+use health::HealthConfig;
+use subcommands::{DepsCmd, FilesCmd, IconCmd, ReadmeCmd, RuntimesCmd, SummaryCmd, VersionsCmd};
 
+mod backfill;
 mod error;
+mod health;
+mod markdown;
+mod matrix;
+mod spec;
 mod subcommands;
+mod suggest;
+mod wait;
 
 #[derive(Debug, Clap)]
 pub enum ViewSubCmd {
@@ -41,6 +50,27 @@ pub enum ViewSubCmd {
         setting = clap::AppSettings::DeriveDisplayOrder,
     )]
     Icon(IconCmd),
+    #[clap(
+        about = "Show the full dependency tree of a package",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Deps(DepsCmd),
+    #[clap(
+        about = "List the files inside a package",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Files(FilesCmd),
+    #[clap(
+        about = "List the runtime identifiers a package ships native assets for",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Runtimes(RuntimesCmd),
 }
 
 #[derive(Debug, Clap)]
@@ -61,6 +91,9 @@ impl TurronCommand for ViewCmd {
             ViewSubCmd::Readme(readme) => readme.execute().await,
             ViewSubCmd::Icon(icon) => icon.execute().await,
             ViewSubCmd::Versions(versions) => versions.execute().await,
+            ViewSubCmd::Deps(deps) => deps.execute().await,
+            ViewSubCmd::Files(files) => files.execute().await,
+            ViewSubCmd::Runtimes(runtimes) => runtimes.execute().await,
         }
     }
 }
@@ -78,8 +111,57 @@ impl TurronConfigLayer for ViewCmd {
                 versions.layer_config(args.subcommand_matches("versions").unwrap(), conf)
             }
             ViewSubCmd::Summary(ref mut summary) => {
-                summary.layer_config(args.subcommand_matches("summary").unwrap(), conf)
+                summary.layer_config(args.subcommand_matches("summary").unwrap(), conf)?;
+                summary.health_config = HealthConfig::from_config(conf)?;
+                Ok(())
             }
+            ViewSubCmd::Deps(ref mut deps) => {
+                deps.layer_config(args.subcommand_matches("deps").unwrap(), conf)
+            }
+            ViewSubCmd::Files(ref mut files) => {
+                files.layer_config(args.subcommand_matches("files").unwrap(), conf)
+            }
+            ViewSubCmd::Runtimes(ref mut runtimes) => {
+                runtimes.layer_config(args.subcommand_matches("runtimes").unwrap(), conf)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_command::clap::{FromArgMatches, IntoApp};
+
+    use super::*;
+
+    fn spec(args: &[&str]) -> Result<(String, Option<dotnet_semver::Range>)> {
+        let matches = ViewCmd::into_app().get_matches_from(args);
+        match ViewCmd::from_arg_matches(&matches).subcommand {
+            ViewSubCmd::Summary(cmd) => cmd.spec(),
+            ViewSubCmd::Versions(cmd) => cmd.spec(),
+            ViewSubCmd::Readme(cmd) => cmd.spec(),
+            ViewSubCmd::Icon(cmd) => cmd.spec(),
+            ViewSubCmd::Deps(cmd) => cmd.spec(),
+            ViewSubCmd::Files(cmd) => cmd.spec(),
+            ViewSubCmd::Runtimes(cmd) => cmd.spec(),
         }
     }
+
+    #[test]
+    fn version_positional_matches_spec() -> Result<()> {
+        for subcommand in &[
+            "summary", "versions", "readme", "icon", "deps", "files", "runtimes",
+        ] {
+            let combined = spec(&["view", subcommand, "Newtonsoft.Json@12.0.3"])?;
+            let separate = spec(&["view", subcommand, "Newtonsoft.Json", "12.0.3"])?;
+            assert_eq!(combined, separate);
+            assert_eq!(combined.1, Some("12.0.3".parse()?));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn version_positional_conflicts_with_spec() {
+        assert!(spec(&["view", "summary", "Newtonsoft.Json@12.0.3", "13.0.1"]).is_err());
+    }
 }
@@ -0,0 +1,518 @@
+//! Renders Markdown, like package readmes, for the terminal.
+//!
+//! Readmes come from publishers, so every bit of text is [`sanitize`]d
+//! before any styling goes on. Links become OSC 8 hyperlinks when the
+//! terminal supports them, and otherwise get their targets printed after
+//! them. Inline HTML is dropped.
+
+use std::mem;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use turron_command::{
+    owo_colors::{colors::*, OwoColorize},
+    render::{self, sanitize, sanitize_cell, DEFAULT_MAX_CELL_WIDTH},
+};
+
+/// What the output can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RenderOptions {
+    /// Columns to wrap text at.
+    pub(crate) width: usize,
+    /// Whether styles like bold and colors can be used.
+    pub(crate) color: bool,
+    /// Whether links can be OSC 8 hyperlinks.
+    pub(crate) hyperlinks: bool,
+    /// Whether box-drawing characters and bullets can be used.
+    pub(crate) unicode: bool,
+}
+
+/// Renders `markdown` as lines of text, each ending in a newline.
+pub(crate) fn render(markdown: &str, opts: RenderOptions) -> String {
+    let mut renderer = Renderer {
+        opts,
+        out: String::new(),
+        words: Vec::new(),
+        space: false,
+        format: Format::default(),
+        link_text: String::new(),
+        prefixes: Vec::new(),
+        marker: None,
+        lists: Vec::new(),
+        pending_blank: false,
+        code: None,
+        table: None,
+    };
+    let parser = Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS,
+    );
+    for event in parser {
+        renderer.event(event);
+    }
+    renderer.flush();
+    renderer.out
+}
+
+/// Inline styles in effect.
+#[derive(Debug, Clone, Default)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    strikethrough: bool,
+    code: bool,
+    heading: Option<u32>,
+    link: Option<String>,
+}
+
+#[derive(Debug)]
+struct Word {
+    text: String,
+    format: Format,
+    /// Whether it's stuck to the word before it, like punctuation after
+    /// bold text.
+    glue: bool,
+}
+
+#[derive(Debug, Default)]
+struct TableState {
+    rows: Vec<Vec<String>>,
+    row: Vec<String>,
+    cell: String,
+}
+
+struct Renderer {
+    opts: RenderOptions,
+    out: String,
+    /// The current block's text, waiting to be wrapped.
+    words: Vec<Word>,
+    /// Whether the next word has a space before it.
+    space: bool,
+    format: Format,
+    /// The text of the current link, to tell whether its target is worth
+    /// printing.
+    link_text: String,
+    /// What each enclosing block puts at the start of its lines: quote bars,
+    /// and list item indents.
+    prefixes: Vec<String>,
+    /// The current list item's bullet or number, which replaces its indent
+    /// on the item's first line.
+    marker: Option<String>,
+    /// The next number of each enclosing list, or `None` for bulleted ones.
+    lists: Vec<Option<u64>>,
+    /// Blank lines are only written once something comes after them, so
+    /// they get the prefixes of whatever that is.
+    pending_blank: bool,
+    code: Option<String>,
+    table: Option<TableState>,
+}
+
+impl Renderer {
+    fn event(&mut self, event: Event) {
+        if let Some(code) = &mut self.code {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(Tag::CodeBlock(_)) => self.end_code_block(),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(table) = &mut self.table {
+            match event {
+                Event::Text(text) | Event::Code(text) => table.cell.push_str(&text),
+                Event::SoftBreak | Event::HardBreak => table.cell.push(' '),
+                Event::End(Tag::TableCell) => {
+                    let cell = sanitize_cell(&mem::take(&mut table.cell), DEFAULT_MAX_CELL_WIDTH);
+                    table.row.push(cell);
+                }
+                Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => {
+                    let row = mem::take(&mut table.row);
+                    table.rows.push(row);
+                }
+                Event::End(Tag::Table(_)) => self.end_table(),
+                _ => {}
+            }
+            return;
+        }
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(&text),
+            Event::Code(text) => {
+                self.format.code = true;
+                self.text(&text);
+                self.format.code = false;
+            }
+            Event::Html(_) => {}
+            Event::FootnoteReference(name) => self.text(&format!("[^{}]", name)),
+            Event::SoftBreak => self.space = true,
+            Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.flush();
+                let rule = if self.opts.unicode { "─" } else { "-" };
+                let rule = rule.repeat(self.text_width());
+                self.line(&self.dim(&rule));
+                self.pending_blank = true;
+            }
+            Event::TaskListMarker(done) => self.marker_word(if done { "[x]" } else { "[ ]" }),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.flush(),
+            Tag::Heading(level) => {
+                self.flush();
+                self.format.heading = Some(level);
+                if !self.opts.color {
+                    self.marker_word(&"#".repeat(level as usize));
+                }
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                // Whatever came before the quote doesn't get its bar.
+                self.blank();
+                let bar = if self.opts.unicode { "│ " } else { "> " };
+                self.prefixes.push(bar.into());
+            }
+            Tag::CodeBlock(_) => {
+                self.flush();
+                self.code = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        let marker = format!("{}. ", number);
+                        *number += 1;
+                        marker
+                    }
+                    _ if self.opts.unicode => "• ".into(),
+                    _ => "- ".into(),
+                };
+                self.prefixes.push(" ".repeat(marker.chars().count()));
+                self.marker = Some(marker);
+            }
+            Tag::FootnoteDefinition(name) => {
+                self.flush();
+                self.marker_word(&format!("[^{}]:", sanitize(&name)));
+            }
+            Tag::Table(_) => {
+                self.flush();
+                self.table = Some(TableState::default());
+            }
+            Tag::TableHead | Tag::TableRow | Tag::TableCell => {}
+            Tag::Emphasis => self.format.italic = true,
+            Tag::Strong => self.format.bold = true,
+            Tag::Strikethrough => self.format.strikethrough = true,
+            Tag::Link(_, url, _) | Tag::Image(_, url, _) => {
+                self.format.link = Some(sanitize(&url));
+                self.link_text.clear();
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::FootnoteDefinition(_) => {
+                self.flush();
+                self.pending_blank = true;
+            }
+            Tag::Heading(_) => {
+                self.flush();
+                self.format.heading = None;
+                self.pending_blank = true;
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.prefixes.pop();
+                self.pending_blank = true;
+            }
+            Tag::List(_) => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.pending_blank = true;
+                }
+            }
+            Tag::Item => {
+                self.flush();
+                if self.marker.is_some() {
+                    // An empty item still gets its bullet.
+                    self.line("");
+                }
+                self.prefixes.pop();
+            }
+            Tag::Emphasis => self.format.italic = false,
+            Tag::Strong => self.format.bold = false,
+            Tag::Strikethrough => self.format.strikethrough = false,
+            Tag::Link(..) | Tag::Image(..) => {
+                let url = self.format.link.take().unwrap_or_default();
+                let link_text = mem::take(&mut self.link_text);
+                // Autolinks and in-page anchors aren't worth spelling out.
+                if !self.opts.hyperlinks
+                    && !url.is_empty()
+                    && url != link_text.trim()
+                    && !url.starts_with('#')
+                {
+                    self.words.push(Word {
+                        text: format!("({})", url),
+                        format: Format::default(),
+                        glue: false,
+                    });
+                }
+            }
+            Tag::CodeBlock(_) | Tag::Table(_) | Tag::TableHead | Tag::TableRow | Tag::TableCell => {
+            }
+        }
+    }
+
+    /// Adds inline text to the current block.
+    fn text(&mut self, text: &str) {
+        let text = sanitize(text);
+        if self.format.link.is_some() {
+            self.link_text.push_str(&text);
+        }
+        let glue = !self.space && !text.starts_with(char::is_whitespace);
+        for (i, piece) in text.split_whitespace().enumerate() {
+            self.words.push(Word {
+                text: piece.into(),
+                format: self.format.clone(),
+                glue: i == 0 && glue && !self.words.is_empty(),
+            });
+        }
+        if !text.is_empty() {
+            self.space = text.ends_with(char::is_whitespace);
+        }
+    }
+
+    /// Adds something like a heading's `#`s, that always has a space after
+    /// it.
+    fn marker_word(&mut self, text: &str) {
+        self.words.push(Word {
+            text: text.into(),
+            format: Format::default(),
+            glue: false,
+        });
+        self.space = true;
+    }
+
+    /// Wraps the current block's text into lines.
+    fn flush(&mut self) {
+        self.space = false;
+        if self.words.is_empty() {
+            return;
+        }
+        let width = self.text_width();
+        // Glued words wrap as one.
+        let mut units: Vec<(String, usize)> = Vec::new();
+        for word in mem::take(&mut self.words) {
+            let styled = self.styled(&word);
+            let len = word.text.chars().count();
+            match units.last_mut() {
+                Some(unit) if word.glue => {
+                    unit.0.push_str(&styled);
+                    unit.1 += len;
+                }
+                _ => units.push((styled, len)),
+            }
+        }
+        let mut line = String::new();
+        let mut len = 0;
+        for (text, text_len) in units {
+            if len > 0 && len + 1 + text_len > width {
+                self.line(&line);
+                line.clear();
+                len = 0;
+            }
+            if len > 0 {
+                line.push(' ');
+                len += 1;
+            }
+            line.push_str(&text);
+            len += text_len;
+        }
+        if len > 0 {
+            self.line(&line);
+        }
+    }
+
+    fn end_code_block(&mut self) {
+        let code = self.code.take().unwrap_or_default();
+        for line in code.trim_end_matches('\n').lines() {
+            let line = sanitize(line).replace('\t', "    ");
+            let line = if self.opts.color {
+                line.fg::<Yellow>().to_string()
+            } else {
+                line
+            };
+            self.line(&format!("  {}", line));
+        }
+        self.pending_blank = true;
+    }
+
+    fn end_table(&mut self) {
+        let mut rows = self
+            .table
+            .take()
+            .map(|table| table.rows)
+            .unwrap_or_default();
+        if rows.is_empty() {
+            return;
+        }
+        let headers = rows.remove(0);
+        let table = render::table(&headers, &rows, self.text_width());
+        for line in table.lines() {
+            self.line(line);
+        }
+        self.pending_blank = true;
+    }
+
+    /// Writes a pending blank line, if there is one.
+    fn blank(&mut self) {
+        if mem::take(&mut self.pending_blank) && !self.out.is_empty() {
+            self.out.push_str(self.prefixes.concat().trim_end());
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes one line, with the prefixes of every block it's in.
+    fn line(&mut self, content: &str) {
+        self.blank();
+        let mut prefix = self.prefixes.concat();
+        if let Some(marker) = self.marker.take() {
+            let indent = self.prefixes.last().map_or(0, String::len);
+            prefix.truncate(prefix.len() - indent);
+            prefix.push_str(&if self.opts.color {
+                marker.fg::<BrightCyan>().to_string()
+            } else {
+                marker
+            });
+        }
+        self.out
+            .push_str(format!("{}{}", prefix, content).trim_end());
+        self.out.push('\n');
+    }
+
+    /// How many columns text has, after prefixes.
+    fn text_width(&self) -> usize {
+        let prefix = self
+            .prefixes
+            .iter()
+            .map(|p| p.chars().count())
+            .sum::<usize>();
+        self.opts.width.saturating_sub(prefix).max(20)
+    }
+
+    fn styled(&self, word: &Word) -> String {
+        let format = &word.format;
+        let mut text = word.text.clone();
+        if self.opts.color {
+            if format.code {
+                text = text.fg::<Yellow>().to_string();
+            }
+            if format.link.is_some() {
+                text = text.fg::<Blue>().underline().to_string();
+            }
+            if format.bold || format.heading.is_some() {
+                text = text.bold().to_string();
+            }
+            if format.heading == Some(1) {
+                text = text.underline().to_string();
+            }
+            if format.italic {
+                text = text.italic().to_string();
+            }
+            if format.strikethrough {
+                text = text.strikethrough().to_string();
+            }
+        }
+        match &format.link {
+            Some(url) if self.opts.hyperlinks && !url.is_empty() => {
+                format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+            }
+            _ => text,
+        }
+    }
+
+    fn dim(&self, text: &str) -> String {
+        if self.opts.color {
+            text.dimmed().to_string()
+        } else {
+            text.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(width: usize) -> RenderOptions {
+        RenderOptions {
+            width,
+            color: false,
+            hyperlinks: false,
+            unicode: true,
+        }
+    }
+
+    #[test]
+    fn renders_blocks() {
+        let markdown = "# Turron\n\
+            \n\
+            Manage your **NuGet** packages,\n\
+            from the `terminal`.\n\
+            \n\
+            - one\n\
+            - two\n\
+            \x20 1. nested\n\
+            \n\
+            > quoted\n\
+            \n\
+            ```sh\n\
+            turron view readme\n\
+            ```\n";
+        assert_eq!(
+            render(markdown, plain(40)),
+            "# Turron\n\
+             \n\
+             Manage your NuGet packages, from the\n\
+             terminal.\n\
+             \n\
+             • one\n\
+             • two\n\
+             \x20 1. nested\n\
+             \n\
+             │ quoted\n\
+             \n\
+             \x20 turron view readme\n"
+        );
+    }
+
+    #[test]
+    fn renders_links() {
+        let markdown = "See [the docs](https://example.com/docs) or <https://turron.dev>.\n\
+            \n\
+            Not \u{9b}31mred.";
+        assert_eq!(
+            render(markdown, plain(80)),
+            "See the docs (https://example.com/docs) or https://turron.dev.\n\
+             \n\
+             Not red.\n"
+        );
+
+        let linked = render(
+            markdown,
+            RenderOptions {
+                hyperlinks: true,
+                ..plain(80)
+            },
+        );
+        assert!(linked.contains("\x1b]8;;https://example.com/docs\x1b\\docs\x1b]8;;\x1b\\"));
+        assert!(!linked.contains("(https://example.com/docs)"));
+    }
+}
@@ -0,0 +1,140 @@
+use nuget_api::v3::DependencyGroup;
+use turron_common::serde_json::{Map, Value};
+
+/// What a dependency group with no target framework applies to.
+const ANY_FRAMEWORK: &str = "any";
+
+/// Every dependency of a package against every target framework it
+/// declares dependencies for, so differences between frameworks line up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DependencyMatrix {
+    pub(crate) frameworks: Vec<String>,
+    /// One row per dependency id, sorted, with a cell for each framework.
+    /// Cells are `None` when that framework doesn't have the dependency.
+    pub(crate) rows: Vec<(String, Vec<Option<String>>)>,
+}
+
+impl DependencyMatrix {
+    /// Pivots `groups` around dependency ids. Frameworks keep the order the
+    /// package lists them in. Ids are compared ignoring case, with the first
+    /// spelling winning. A dependency without a range shows up as `*`, and
+    /// one whose range doesn't parse shows up the way the source sent it.
+    pub(crate) fn new(groups: &[DependencyGroup]) -> Self {
+        let mut frameworks: Vec<String> = Vec::new();
+        let mut rows: Vec<(String, Vec<Option<String>>)> = Vec::new();
+        for group in groups {
+            let framework = group
+                .target_framework
+                .clone()
+                .unwrap_or_else(|| ANY_FRAMEWORK.into());
+            let column = match frameworks
+                .iter()
+                .position(|f| f.eq_ignore_ascii_case(&framework))
+            {
+                Some(column) => column,
+                None => {
+                    frameworks.push(framework);
+                    for (_, cells) in &mut rows {
+                        cells.push(None);
+                    }
+                    frameworks.len() - 1
+                }
+            };
+            for dep in group.dependencies.iter().flatten() {
+                let row = match rows
+                    .iter()
+                    .position(|(id, _)| id.eq_ignore_ascii_case(&dep.id))
+                {
+                    Some(row) => row,
+                    None => {
+                        rows.push((dep.id.clone(), vec![None; frameworks.len()]));
+                        rows.len() - 1
+                    }
+                };
+                rows[row].1[column] = Some(dep.range_label().unwrap_or_else(|| "*".into()));
+            }
+        }
+        rows.sort_by_key(|(id, _)| id.to_lowercase());
+        DependencyMatrix { frameworks, rows }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// `{ "<id>": { "<framework>": "<range>" | null } }`
+    pub(crate) fn to_json(&self) -> Value {
+        let rows = self
+            .rows
+            .iter()
+            .map(|(id, cells)| {
+                let cells = self
+                    .frameworks
+                    .iter()
+                    .zip(cells)
+                    .map(|(framework, cell)| (framework.clone(), cell.clone().into()))
+                    .collect::<Map<_, _>>();
+                (id.clone(), Value::Object(cells))
+            })
+            .collect::<Map<_, _>>();
+        Value::Object(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::serde_json::{self, json};
+
+    use super::*;
+
+    fn groups(json: Value) -> Vec<DependencyGroup> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn pivots_dependency_groups() {
+        let matrix = DependencyMatrix::new(&groups(json!([
+            {
+                "targetFramework": "net6.0",
+                "dependencies": [
+                    { "id": "Zed", "range": "[1.0.0, )" },
+                    { "id": "alpha", "range": "[2.0.0, )" },
+                ],
+            },
+            {
+                "targetFramework": "netstandard2.0",
+                "dependencies": [
+                    { "id": "Alpha", "range": "[1.0.0, )" },
+                    { "id": "Legacy" },
+                ],
+            },
+            { "targetFramework": "net48" },
+        ])));
+        assert_eq!(matrix.frameworks, vec!["net6.0", "netstandard2.0", "net48"]);
+        assert_eq!(
+            matrix.rows,
+            vec![
+                (
+                    "alpha".to_string(),
+                    vec![Some("[2.0.0,)".into()), Some("[1.0.0,)".into()), None]
+                ),
+                ("Legacy".to_string(), vec![None, Some("*".into()), None]),
+                ("Zed".to_string(), vec![Some("[1.0.0,)".into()), None, None]),
+            ]
+        );
+        assert_eq!(
+            matrix.to_json()["Legacy"],
+            json!({ "net6.0": null, "netstandard2.0": "*", "net48": null })
+        );
+    }
+
+    #[test]
+    fn groups_without_a_framework() {
+        let matrix = DependencyMatrix::new(&groups(json!([
+            { "dependencies": [{ "id": "A", "range": "[1.0.0, )" }] },
+        ])));
+        assert_eq!(matrix.frameworks, vec!["any"]);
+        assert!(!matrix.is_empty());
+        assert!(DependencyMatrix::new(&[]).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use dotnet_semver::Range;
+use turron_common::miette::Result;
+use turron_package_spec::PackageSpec;
+
+use crate::error::ViewError;
+
+/// Works out the package ID and requested range from a package spec and an
+/// optional separate version argument, so `Foo@1.2.3` and `Foo 1.2.3` mean
+/// the same thing.
+pub(crate) fn resolve_spec(
+    package: &str,
+    version: Option<&str>,
+) -> Result<(String, Option<Range>)> {
+    let (name, requested) = match package.parse()? {
+        PackageSpec::NuGet { name, requested } => (name, requested),
+        _ => return Err(ViewError::InvalidPackageSpec.into()),
+    };
+    match (requested, version) {
+        (Some(_), Some(version)) => Err(ViewError::ConflictingVersions {
+            spec: package.into(),
+            version: version.into(),
+        }
+        .into()),
+        (Some(requested), None) => Ok((name, Some(requested))),
+        (None, Some(version)) => Ok((name, Some(version.parse()?))),
+        (None, None) => Ok((name, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separate_version_matches_spec() {
+        for (version, range) in &[("12.0.3", "12.0.3"), ("[12.0.0,13.0.0)", "[12.0.0,13.0.0)")] {
+            let separate = resolve_spec("Newtonsoft.Json", Some(version)).unwrap();
+            let combined = resolve_spec(&format!("Newtonsoft.Json@{}", range), None).unwrap();
+            assert_eq!(separate, combined);
+            assert_eq!(separate.1, Some(range.parse().unwrap()));
+        }
+        assert_eq!(
+            resolve_spec("Newtonsoft.Json", None).unwrap(),
+            ("Newtonsoft.Json".into(), None)
+        );
+    }
+
+    #[test]
+    fn rejects_both_forms_at_once() {
+        let err = resolve_spec("Newtonsoft.Json@12.0.3", Some("13.0.1")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ViewError>(),
+            Some(ViewError::ConflictingVersions { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_versions() {
+        assert!(resolve_spec("Newtonsoft.Json", Some("not a version")).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+
+use dotnet_semver::{Range, Version};
+use nuget_api::{
+    v3::{Dependency, NuGetClient, RegistrationLeaf},
+    NuGetApiError, SourceProtocol,
+};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    render::sanitize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json, Value},
+};
+
+use crate::error::ViewError;
+use crate::spec::resolve_spec;
+use crate::suggest::find_package;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "view.deps"]
+pub struct DepsCmd {
+    #[clap(about = "Package spec to look up")]
+    package: String,
+    #[clap(about = "Version or range to look up, if the package spec doesn't have one")]
+    version: Option<String>,
+    #[clap(
+        about = "Only follow dependencies declared for this target framework (e.g. netstandard2.0)",
+        long
+    )]
+    framework: Option<String>,
+    #[clap(
+        about = "How many levels of dependencies to show",
+        long,
+        default_value = "10"
+    )]
+    depth: usize,
+    #[clap(
+        about = "Source to view packages from",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Don't look for similarly named packages if this one isn't found",
+        long
+    )]
+    no_suggest: bool,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for DepsCmd {
+    async fn execute(self) -> Result<()> {
+        let (package_id, requested) = self.spec()?;
+        let requested = requested.unwrap_or_else(Range::any_floating);
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let package_id = find_package(&client, &package_id, self.no_suggest).await?;
+        let mut resolver = Resolver::new(&client, self.framework.clone(), self.depth);
+        let tree = resolver
+            .resolve(package_id.clone(), Some(requested.clone()), 0)
+            .await?;
+        if tree.status == DepStatus::Unresolved {
+            return Err(ViewError::VersionNotFound(package_id, requested).into());
+        }
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&tree.to_json())
+                    .into_diagnostic()
+                    .context("Failed to serialize dependency tree to JSON")?
+            );
+        } else if !self.quiet {
+            print!("{}", tree.render());
+        }
+        Ok(())
+    }
+}
+
+impl DepsCmd {
+    /// The package ID and requested range, from either `<id>@<version>` or
+    /// `<id> <version>`.
+    pub(crate) fn spec(&self) -> Result<(String, Option<Range>)> {
+        resolve_spec(&self.package, self.version.as_deref())
+    }
+}
+
+/// Why a node in the tree does or doesn't have children.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum DepStatus {
+    /// Resolved, with its dependencies listed below it.
+    Resolved,
+    /// Already expanded elsewhere in the tree.
+    Repeated,
+    /// Depends on one of its own ancestors.
+    Cycle,
+    /// Has dependencies, but is past the depth limit.
+    Truncated,
+    /// No published version satisfies the range, or the package is missing.
+    Unresolved,
+}
+
+impl DepStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DepStatus::Resolved => "resolved",
+            DepStatus::Repeated => "repeated",
+            DepStatus::Cycle => "cycle",
+            DepStatus::Truncated => "truncated",
+            DepStatus::Unresolved => "unresolved",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct DepNode {
+    pub(crate) id: String,
+    pub(crate) range: Option<Range>,
+    pub(crate) version: Option<Version>,
+    pub(crate) status: DepStatus,
+    pub(crate) dependencies: Vec<DepNode>,
+}
+
+impl DepNode {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "version": self.version.as_ref().map(|v| v.to_string()),
+            "range": self.range.as_ref().map(|r| r.to_string()),
+            "status": self.status.as_str(),
+            "dependencies": self.dependencies.iter().map(|dep| dep.to_json()).collect::<Vec<_>>(),
+        })
+    }
+
+    fn label(&self) -> String {
+        let mut label = match &self.version {
+            Some(version) => format!("{}@{}", sanitize(&self.id), version),
+            None => sanitize(&self.id),
+        };
+        if let Some(range) = &self.range {
+            if self.version.is_none() {
+                label.push_str(&format!(" {}", range));
+            }
+        }
+        match self.status {
+            DepStatus::Resolved => {}
+            DepStatus::Repeated => label.push_str(" (repeated)"),
+            DepStatus::Cycle => label.push_str(" (cycle)"),
+            DepStatus::Truncated => label.push_str(" (...)"),
+            DepStatus::Unresolved => label.push_str(" (unresolved)"),
+        }
+        label
+    }
+
+    /// Renders the tree with box-drawing guides, one package per line.
+    pub(crate) fn render(&self) -> String {
+        let mut out = format!("{}\n", self.label());
+        self.render_children("", &mut out);
+        out
+    }
+
+    fn render_children(&self, prefix: &str, out: &mut String) {
+        for (i, dep) in self.dependencies.iter().enumerate() {
+            let last = i + 1 == self.dependencies.len();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            out.push_str(&format!("{}{}{}\n", prefix, branch, dep.label()));
+            dep.render_children(&format!("{}{}", prefix, indent), out);
+        }
+    }
+}
+
+/// Walks registration data to build a dependency tree. Each `(id, version)`
+/// pair is only expanded once; later appearances are marked as repeats.
+pub(crate) struct Resolver<'a> {
+    client: &'a NuGetClient,
+    framework: Option<String>,
+    max_depth: usize,
+    leaves: HashMap<String, Option<Vec<RegistrationLeaf>>>,
+    expanded: HashSet<(String, Version)>,
+    path: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(
+        client: &'a NuGetClient,
+        framework: Option<String>,
+        max_depth: usize,
+    ) -> Self {
+        Self {
+            client,
+            framework,
+            max_depth,
+            leaves: HashMap::new(),
+            expanded: HashSet::new(),
+            path: Vec::new(),
+        }
+    }
+
+    pub(crate) fn resolve<'b>(
+        &'b mut self,
+        id: String,
+        range: Option<Range>,
+        depth: usize,
+    ) -> Pin<Box<dyn Future<Output = Result<DepNode>> + Send + 'b>> {
+        Box::pin(async move {
+            let leaf = match self.pick(&id, range.as_ref()).await? {
+                Some(leaf) => leaf,
+                None => {
+                    return Ok(DepNode {
+                        id,
+                        range,
+                        version: None,
+                        status: DepStatus::Unresolved,
+                        dependencies: Vec::new(),
+                    })
+                }
+            };
+            let key = id.to_lowercase();
+            let version = leaf.catalog_entry.version.clone();
+            let deps = self.dependencies(&leaf);
+            let mut node = DepNode {
+                id: leaf.catalog_entry.id,
+                range,
+                version: Some(version.clone()),
+                status: DepStatus::Resolved,
+                dependencies: Vec::new(),
+            };
+            if self.path.contains(&key) {
+                node.status = DepStatus::Cycle;
+            } else if !self.expanded.insert((key.clone(), version)) {
+                node.status = DepStatus::Repeated;
+            } else if depth >= self.max_depth {
+                if !deps.is_empty() {
+                    node.status = DepStatus::Truncated;
+                }
+            } else {
+                self.path.push(key);
+                for dep in deps {
+                    let child = self.resolve(dep.id, dep.range, depth + 1).await?;
+                    node.dependencies.push(child);
+                }
+                self.path.pop();
+            }
+            Ok(node)
+        })
+    }
+
+    /// Picks the best version of `id` for `range`, preferring listed
+    /// versions. Returns `None` if the package doesn't exist or nothing
+    /// matches.
+    async fn pick(&mut self, id: &str, range: Option<&Range>) -> Result<Option<RegistrationLeaf>> {
+        let key = id.to_lowercase();
+        if !self.leaves.contains_key(&key) {
+            let leaves = match self.client.registration_leaves(id).await {
+                Ok(leaves) => Some(leaves),
+                Err(NuGetApiError::PackageNotFound) => None,
+                Err(err) => return Err(err.into()),
+            };
+            self.leaves.insert(key.clone(), leaves);
+        }
+        let leaves = match &self.leaves[&key] {
+            Some(leaves) => leaves,
+            None => return Ok(None),
+        };
+        let any = Range::any();
+        let range = range.unwrap_or(&any);
+        let listed = leaves
+            .iter()
+            .filter(|leaf| leaf.catalog_entry.listed != Some(false))
+            .map(|leaf| leaf.catalog_entry.version.clone())
+            .collect::<Vec<_>>();
+        let all = leaves
+            .iter()
+            .map(|leaf| leaf.catalog_entry.version.clone())
+            .collect::<Vec<_>>();
+        let version = turron_pick_version::pick_version(range, &listed)
+            .or_else(|| turron_pick_version::pick_version(range, &all));
+        Ok(version.and_then(|version| {
+            leaves
+                .iter()
+                .find(|leaf| leaf.catalog_entry.version == version)
+                .cloned()
+        }))
+    }
+
+    /// The dependencies to follow for `leaf`. With a framework filter, that's
+    /// the group for that framework, falling back to the framework-agnostic
+    /// group. Without one, it's every group, deduplicated by ID.
+    fn dependencies(&self, leaf: &RegistrationLeaf) -> Vec<Dependency> {
+        let groups = leaf
+            .catalog_entry
+            .dependency_groups
+            .as_deref()
+            .unwrap_or_default();
+        let selected = match &self.framework {
+            Some(framework) => {
+                let exact = groups
+                    .iter()
+                    .filter(|group| {
+                        group
+                            .target_framework
+                            .as_deref()
+                            .map_or(false, |tfm| same_framework(tfm, framework))
+                    })
+                    .collect::<Vec<_>>();
+                if exact.is_empty() {
+                    groups
+                        .iter()
+                        .filter(|group| {
+                            group
+                                .target_framework
+                                .as_deref()
+                                .map_or(true, str::is_empty)
+                        })
+                        .collect()
+                } else {
+                    exact
+                }
+            }
+            None => groups.iter().collect(),
+        };
+        let mut deps: Vec<Dependency> = Vec::new();
+        for dep in selected
+            .into_iter()
+            .flat_map(|group| group.dependencies.iter().flatten())
+        {
+            if !deps.iter().any(|d| d.id.eq_ignore_ascii_case(&dep.id)) {
+                deps.push(dep.clone());
+            }
+        }
+        deps
+    }
+}
+
+/// Registration data spells frameworks like `.NETStandard2.0`, while users
+/// tend to type `netstandard2.0`.
+fn same_framework(a: &str, b: &str) -> bool {
+    a.trim_start_matches('.')
+        .eq_ignore_ascii_case(b.trim_start_matches('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::smol;
+    use turron_testing::{RegistrationBuilder, TestServer};
+
+    use super::*;
+
+    fn serve(server: &TestServer, builder: RegistrationBuilder) {
+        let reg = builder
+            .base_url(server.url("/v3/registration5-gz-semver2/"))
+            .build();
+        for (url, body) in reg.documents() {
+            server.json(&url[server.base().len()..], &body);
+        }
+    }
+
+    #[test]
+    fn marks_repeats_and_cycles() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            serve(
+                &server,
+                RegistrationBuilder::new("A")
+                    .versions(vec!["1.0.0", "2.0.0"])
+                    .dependency(None, "B", "[1.0.0, )")
+                    .dependency(None, "C", "[1.0.0, )"),
+            );
+            serve(
+                &server,
+                RegistrationBuilder::new("B")
+                    .versions(vec!["1.0.0", "1.1.0"])
+                    .dependency(None, "A", "[2.0.0, )"),
+            );
+            serve(
+                &server,
+                RegistrationBuilder::new("C")
+                    .versions(vec!["1.0.0"])
+                    .dependency(None, "B", "[1.0.0, )")
+                    .dependency(None, "Missing", "[1.0.0, )"),
+            );
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let mut resolver = Resolver::new(&client, None, 10);
+            let tree = resolver
+                .resolve("A".into(), Some(Range::any_floating()), 0)
+                .await
+                .unwrap();
+
+            let expected = [
+                "A@2.0.0",
+                "├── B@1.0.0",
+                "│   └── A@2.0.0 (cycle)",
+                "└── C@1.0.0",
+                "    ├── B@1.0.0 (repeated)",
+                "    └── Missing [1.0.0,) (unresolved)",
+                "",
+            ];
+            assert_eq!(tree.render(), expected.join("\n"));
+            let json = tree.to_json();
+            assert_eq!(
+                json["dependencies"][0]["dependencies"][0]["status"],
+                "cycle"
+            );
+            assert_eq!(json["dependencies"][1]["version"], "1.0.0");
+            // Each registration is only fetched once, however often it shows up.
+            assert_eq!(server.hits("/v3/registration5-gz-semver2/b/index.json"), 1);
+        });
+    }
+
+    #[test]
+    fn filters_by_framework_and_depth() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            serve(
+                &server,
+                RegistrationBuilder::new("A")
+                    .versions(vec!["1.0.0"])
+                    .dependency(Some(".NETStandard2.0"), "B", "[1.0.0, )")
+                    .dependency(Some("net6.0"), "C", "[1.0.0, )"),
+            );
+            serve(
+                &server,
+                RegistrationBuilder::new("B")
+                    .versions(vec!["1.0.0"])
+                    .dependency(None, "C", "[1.0.0, )"),
+            );
+            serve(
+                &server,
+                RegistrationBuilder::new("C").versions(vec!["1.0.0"]),
+            );
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            let mut resolver = Resolver::new(&client, Some("netstandard2.0".into()), 10);
+            let tree = resolver.resolve("A".into(), None, 0).await.unwrap();
+            assert_eq!(tree.render(), "A@1.0.0\n└── B@1.0.0\n    └── C@1.0.0\n");
+
+            let mut resolver = Resolver::new(&client, Some("netstandard2.0".into()), 1);
+            let tree = resolver.resolve("A".into(), None, 0).await.unwrap();
+            assert_eq!(tree.render(), "A@1.0.0\n└── B@1.0.0 (...)\n");
+        });
+    }
+}
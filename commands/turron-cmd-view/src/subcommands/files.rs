@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read, Seek};
+
+use dotnet_semver::Range;
+use nuget_api::{v3::NuGetClient, NuGetApiError, SourceProtocol};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    fmt::Numbers,
+    owo_colors::{colors::*, OwoColorize},
+    render::sanitize,
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde::Serialize,
+    serde_json, smol,
+};
+use zip::ZipArchive;
+
+use crate::spec::resolve_spec;
+use crate::suggest::find_package;
+use crate::wait::version_missing;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "view.files"]
+pub struct FilesCmd {
+    #[clap(about = "Package spec to look up")]
+    package: String,
+    #[clap(about = "Version or range to look up, if the package spec doesn't have one")]
+    version: Option<String>,
+    #[clap(
+        about = "Only list files whose path matches this pattern (e.g. `lib/**/*.dll`)",
+        long
+    )]
+    glob: Option<glob::Pattern>,
+    #[clap(
+        about = "Source to view packages from",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Don't look for similarly named packages if this one isn't found",
+        long
+    )]
+    no_suggest: bool,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for FilesCmd {
+    async fn execute(self) -> Result<()> {
+        let (package_id, requested) = self.spec()?;
+        let requested = requested.unwrap_or_else(Range::any_floating);
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let package_id = find_package(&client, &package_id, self.no_suggest).await?;
+        self.print_files(&client, &package_id, &requested).await
+    }
+}
+
+impl FilesCmd {
+    /// The package ID and requested range, from either `<id>@<version>` or
+    /// `<id> <version>`.
+    pub(crate) fn spec(&self) -> Result<(String, Option<Range>)> {
+        resolve_spec(&self.package, self.version.as_deref())
+    }
+
+    async fn print_files(
+        &self,
+        client: &NuGetClient,
+        package_id: &str,
+        requested: &Range,
+    ) -> Result<()> {
+        let versions = client.versions(&package_id).await?;
+        let version = turron_pick_version::pick_version(requested, &versions[..])
+            .ok_or_else(|| version_missing(package_id, requested))?;
+        let nupkg = client.nupkg(package_id, &version).await?;
+        let glob = self.glob.clone();
+        let entries =
+            smol::unblock(move || list_entries(Cursor::new(nupkg), glob.as_ref())).await?;
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&entries)
+                    .into_diagnostic()
+                    .context("Failed to serialize file list to JSON")?
+            );
+        } else if !self.quiet {
+            for (framework, entries) in group_by_framework(&entries) {
+                match framework {
+                    Some(framework) => println!(
+                        "{}",
+                        format!("lib/{}/", sanitize(framework)).fg::<BrightCyan>()
+                    ),
+                    None => println!("{}", "Other files".fg::<BrightCyan>()),
+                }
+                for entry in entries {
+                    println!(
+                        "  {} {}",
+                        sanitize(&entry.path),
+                        format!("({})", Numbers::current().bytes(entry.size)).fg::<Yellow>()
+                    );
+                }
+            }
+            if entries.is_empty() {
+                println!("No files matched.");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A file inside a .nupkg.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct Entry {
+    pub(crate) path: String,
+    pub(crate) size: u64,
+    pub(crate) compressed_size: u64,
+}
+
+/// Every file in `nupkg` whose path matches `glob`, in the order the zip
+/// lists them. Directory entries are skipped, since some zip writers add
+/// them and some don't.
+pub(crate) fn list_entries<R: Read + Seek>(
+    nupkg: R,
+    glob: Option<&glob::Pattern>,
+) -> Result<Vec<Entry>, NuGetApiError> {
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    let mut zip = ZipArchive::new(nupkg)?;
+    let mut entries = Vec::new();
+    for i in 0..zip.len() {
+        let file = zip.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = file.name().to_string();
+        if glob.map_or(true, |glob| glob.matches_with(&path, options)) {
+            entries.push(Entry {
+                path,
+                size: file.size(),
+                compressed_size: file.compressed_size(),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Groups `lib/<tfm>/` entries by target framework, in order, followed by
+/// everything else under `None`.
+pub(crate) fn group_by_framework(entries: &[Entry]) -> Vec<(Option<&str>, Vec<&Entry>)> {
+    let mut frameworks: BTreeMap<String, (&str, Vec<&Entry>)> = BTreeMap::new();
+    let mut other = Vec::new();
+    for entry in entries {
+        let mut parts = entry.path.splitn(3, '/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(lib), Some(tfm), Some(_))
+                if lib.eq_ignore_ascii_case("lib") && !tfm.is_empty() =>
+            {
+                frameworks
+                    .entry(tfm.to_lowercase())
+                    .or_insert_with(|| (tfm, Vec::new()))
+                    .1
+                    .push(entry)
+            }
+            _ => other.push(entry),
+        }
+    }
+    let mut groups = frameworks
+        .into_iter()
+        .map(|(_, (tfm, entries))| (Some(tfm), entries))
+        .collect::<Vec<_>>();
+    if !other.is_empty() {
+        groups.push((None, other));
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    fn nupkg() -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("Turron.Test.nuspec", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"<package />").unwrap();
+        zip.add_directory("lib/", FileOptions::default()).unwrap();
+        zip.add_directory("lib/net6.0/", FileOptions::default())
+            .unwrap();
+        zip.start_file("lib/net6.0/Turron.Test.dll", FileOptions::default())
+            .unwrap();
+        zip.write_all(&[0; 64]).unwrap();
+        zip.start_file("lib/netstandard2.0/_._", FileOptions::default())
+            .unwrap();
+        zip.start_file("lib/Legacy.dll", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"old").unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn paths(entries: &[&Entry]) -> Vec<String> {
+        entries.iter().map(|e| e.path.clone()).collect()
+    }
+
+    #[test]
+    fn lists_files_but_not_directories() {
+        let entries = list_entries(Cursor::new(nupkg()), None).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| &e.path[..]).collect::<Vec<_>>(),
+            vec![
+                "Turron.Test.nuspec",
+                "lib/net6.0/Turron.Test.dll",
+                "lib/netstandard2.0/_._",
+                "lib/Legacy.dll",
+            ]
+        );
+        assert_eq!(entries[1].size, 64);
+        assert_eq!(entries[2].size, 0);
+        assert_eq!(
+            serde_json::to_value(&entries[2]).unwrap(),
+            serde_json::json!({
+                "path": "lib/netstandard2.0/_._",
+                "size": 0,
+                "compressed_size": entries[2].compressed_size,
+            })
+        );
+    }
+
+    #[test]
+    fn filters_by_glob() {
+        let glob = glob::Pattern::new("LIB/**/*.dll").unwrap();
+        let entries = list_entries(Cursor::new(nupkg()), Some(&glob)).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| &e.path[..]).collect::<Vec<_>>(),
+            vec!["lib/net6.0/Turron.Test.dll", "lib/Legacy.dll"]
+        );
+    }
+
+    #[test]
+    fn groups_lib_folders_by_framework() {
+        let entries = list_entries(Cursor::new(nupkg()), None).unwrap();
+        let groups = group_by_framework(&entries);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].0, Some("net6.0"));
+        assert_eq!(paths(&groups[0].1), vec!["lib/net6.0/Turron.Test.dll"]);
+        assert_eq!(groups[1].0, Some("netstandard2.0"));
+        assert_eq!(groups[2].0, None);
+        assert_eq!(
+            paths(&groups[2].1),
+            vec!["Turron.Test.nuspec", "lib/Legacy.dll"]
+        );
+        assert!(group_by_framework(&[]).is_empty());
+    }
+}
@@ -1,27 +1,51 @@
+use std::path::PathBuf;
+
 use dotnet_semver::Range;
-use nuget_api::{v3::NuGetClient, NuGetApiError};
+use image::GenericImageView;
+use nuget_api::{v3::NuGetClient, SourceProtocol};
 use turron_command::{
     async_trait::async_trait,
+    capabilities::{self, ColorDepth, Graphics, Probes},
     clap::{self, Clap},
+    render::sanitize,
     turron_config::TurronConfigLayer,
     TurronCommand,
 };
-use turron_common::miette::{Context, IntoDiagnostic, Report, Result};
-use turron_package_spec::PackageSpec;
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    smol::{self, fs},
+};
 
 use crate::error::ViewError;
+use crate::spec::resolve_spec;
+use crate::suggest::find_package;
+use crate::wait::version_missing;
 
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "view.icon"]
 pub struct IconCmd {
     #[clap(about = "Package spec to look up")]
     package: String,
+    #[clap(about = "Version or range to look up, if the package spec doesn't have one")]
+    version: Option<String>,
     #[clap(
         about = "Height, in pixels, that the image should be rendered at",
         long,
         default_value = "15"
     )]
     height: u32,
+    #[clap(
+        about = "Write the icon's bytes to this file instead of showing it",
+        long,
+        value_name = "PATH"
+    )]
+    out: Option<PathBuf>,
+    #[clap(
+        about = "Only check that the icon can be fetched and read as an image",
+        long,
+        conflicts_with = "out"
+    )]
+    no_render: bool,
     #[clap(
         about = "Source to view packages from",
         default_value = "https://api.nuget.org/v3/index.json",
@@ -29,6 +53,16 @@ pub struct IconCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(from_global)]
+    refresh_capabilities: bool,
+    #[clap(
+        about = "Don't look for similarly named packages if this one isn't found",
+        long
+    )]
+    no_suggest: bool,
+    #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
@@ -37,18 +71,25 @@ pub struct IconCmd {
 #[async_trait]
 impl TurronCommand for IconCmd {
     async fn execute(self) -> Result<()> {
-        let package = self.package.parse()?;
-        let client = NuGetClient::from_source(self.source.clone()).await?;
-        let (package_id, requested) = if let PackageSpec::NuGet { name, requested } = &package {
-            (name, requested.clone().unwrap_or_else(Range::any_floating))
-        } else {
-            return Err(ViewError::InvalidPackageSpec.into());
-        };
-        self.print_icon(&client, package_id, &requested).await
+        let (package_id, requested) = self.spec()?;
+        let requested = requested.unwrap_or_else(Range::any_floating);
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let package_id = find_package(&client, &package_id, self.no_suggest).await?;
+        self.print_icon(&client, &package_id, &requested).await
     }
 }
 
 impl IconCmd {
+    /// The package ID and requested range, from either `<id>@<version>` or
+    /// `<id> <version>`.
+    pub(crate) fn spec(&self) -> Result<(String, Option<Range>)> {
+        resolve_spec(&self.package, self.version.as_deref())
+    }
+
     async fn print_icon(
         &self,
         client: &NuGetClient,
@@ -57,36 +98,78 @@ impl IconCmd {
     ) -> Result<()> {
         let versions = client.versions(&package_id).await?;
         let version = turron_pick_version::pick_version(requested, &versions[..])
-            .ok_or_else(|| ViewError::VersionNotFound(package_id.into(), requested.clone()))?;
-        let nuspec = client.nuspec(package_id, &version).await?;
-        if let Some(icon) = &nuspec.metadata.icon {
-            let icon = icon.to_lowercase();
-            let data = client
-                .get_from_nupkg(package_id, &version, &icon)
+            .ok_or_else(|| version_missing(package_id, requested))?;
+        let data = match client.icon(package_id, &version).await? {
+            Some(data) => data,
+            // Packages without an embedded icon may still have the
+            // deprecated iconUrl.
+            None => match client.nuspec(package_id, &version).await?.metadata.icon_url {
+                Some(url) => client.icon_from_url(&url).await?,
+                None => {
+                    return Err(ViewError::IconNotFound(package_id.into(), version.clone()).into())
+                }
+            },
+        };
+        if let Some(out) = &self.out {
+            fs::write(out, &data)
                 .await
-                .map_err(|err| -> Report {
-                    match err {
-                        NuGetApiError::FileNotFound(_, _, _) => {
-                            ViewError::IconNotFound(nuspec.metadata.id, version).into()
-                        }
-                        _ => err.into(),
-                    }
-                })?;
-            let conf = viuer::Config {
-                transparent: true,
-                absolute_offset: false,
-                height: Some(self.height),
-                ..Default::default()
-            };
-            let img = image::load_from_memory(&data)
-                .into_diagnostic()
-                .context("Failed to load image into memory")?;
-            viuer::print(&img, &conf)
                 .into_diagnostic()
-                .context("Failed to print image to terminal")?;
-            Ok(())
-        } else {
-            Err(ViewError::IconNotFound(nuspec.metadata.id, version).into())
+                .with_context(|| format!("Failed to write {}", out.display()))?;
+            if !self.quiet {
+                println!(
+                    "Saved {}@{}'s icon to {}",
+                    sanitize(package_id),
+                    version,
+                    out.display()
+                );
+            }
+            return Ok(());
         }
+        let img = image::load_from_memory(&data)
+            .into_diagnostic()
+            .context("Failed to load image into memory")?;
+        if self.no_render {
+            if !self.quiet {
+                println!(
+                    "{}@{}'s icon is a {}x{} image",
+                    sanitize(package_id),
+                    version,
+                    img.width(),
+                    img.height()
+                );
+            }
+            return Ok(());
+        }
+        let refresh = self.refresh_capabilities;
+        let caps = smol::unblock(move || {
+            capabilities::detect(&Probes::default().with_graphics(probe_graphics), refresh)
+        })
+        .await;
+        let conf = viuer::Config {
+            transparent: true,
+            absolute_offset: false,
+            height: Some(self.height),
+            truecolor: caps.color == ColorDepth::TrueColor,
+            use_kitty: caps.graphics == Graphics::Kitty,
+            use_iterm: caps.graphics == Graphics::Iterm,
+            ..Default::default()
+        };
+        viuer::print(&img, &conf)
+            .into_diagnostic()
+            .context("Failed to print image to terminal")?;
+        Ok(())
+    }
+}
+
+/// Asks the terminal which image protocols it speaks. The kitty check waits
+/// on a reply that some terminals never send, which is why results get
+/// cached.
+fn probe_graphics() -> Graphics {
+    if viuer::get_kitty_support() != viuer::KittySupport::None {
+        Graphics::Kitty
+    } else if viuer::is_iterm_supported() {
+        Graphics::Iterm
+    } else {
+        Graphics::Blocks
     }
 }
@@ -1,9 +1,15 @@
+pub use deps::DepsCmd;
+pub use files::FilesCmd;
 pub use icon::IconCmd;
 pub use readme::ReadmeCmd;
+pub use runtimes::RuntimesCmd;
 pub use summary::SummaryCmd;
 pub use versions::VersionsCmd;
 
+mod deps;
+pub(crate) mod files;
 mod icon;
 mod readme;
+mod runtimes;
 mod summary;
 mod versions;
@@ -1,21 +1,41 @@
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
 use dotnet_semver::Range;
-use nuget_api::{v3::NuGetClient, NuGetApiError};
+use nuget_api::{v3::NuGetClient, SourceProtocol};
+use supports_hyperlinks::Stream;
 use turron_command::{
     async_trait::async_trait,
+    capabilities::{self, ColorDepth, Probes},
     clap::{self, Clap},
     turron_config::TurronConfigLayer,
     TurronCommand,
 };
-use turron_common::miette::{Report, Result};
-use turron_package_spec::PackageSpec;
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, json},
+    smol, tracing,
+};
 
 use crate::error::ViewError;
+use crate::markdown::{self, RenderOptions};
+use crate::spec::resolve_spec;
+use crate::suggest::find_package;
+use crate::wait::version_missing;
+
+/// Printed on stderr before a `--raw` readme.
+const RAW_BANNER: &str = "warning: Printing the readme exactly as the publisher wrote it. It may \
+contain terminal escape sequences, which your terminal will act on. Leave out --raw to \
+render it safely.";
 
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "view.readme"]
 pub struct ReadmeCmd {
     #[clap(about = "Package spec to look up")]
     package: String,
+    #[clap(about = "Version or range to look up, if the package spec doesn't have one")]
+    version: Option<String>,
     #[clap(
         about = "Source to view packages from",
         default_value = "https://api.nuget.org/v3/index.json",
@@ -23,6 +43,26 @@ pub struct ReadmeCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Print the readme as-is, without rendering its Markdown or stripping escape sequences",
+        long
+    )]
+    raw: bool,
+    #[clap(
+        about = "Show the readme in $PAGER if it doesn't fit in the terminal",
+        long
+    )]
+    pager: bool,
+    #[clap(from_global)]
+    refresh_capabilities: bool,
+    #[clap(
+        about = "Don't look for similarly named packages if this one isn't found",
+        long
+    )]
+    no_suggest: bool,
+    #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
@@ -31,18 +71,25 @@ pub struct ReadmeCmd {
 #[async_trait]
 impl TurronCommand for ReadmeCmd {
     async fn execute(self) -> Result<()> {
-        let package = self.package.parse()?;
-        let client = NuGetClient::from_source(self.source.clone()).await?;
-        let (package_id, requested) = if let PackageSpec::NuGet { name, requested } = &package {
-            (name, requested.clone().unwrap_or_else(Range::any_floating))
-        } else {
-            return Err(ViewError::InvalidPackageSpec.into());
-        };
-        self.print_readme(&client, package_id, &requested).await
+        let (package_id, requested) = self.spec()?;
+        let requested = requested.unwrap_or_else(Range::any_floating);
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let package_id = find_package(&client, &package_id, self.no_suggest).await?;
+        self.print_readme(&client, &package_id, &requested).await
     }
 }
 
 impl ReadmeCmd {
+    /// The package ID and requested range, from either `<id>@<version>` or
+    /// `<id> <version>`.
+    pub(crate) fn spec(&self) -> Result<(String, Option<Range>)> {
+        resolve_spec(&self.package, self.version.as_deref())
+    }
+
     async fn print_readme(
         &self,
         client: &NuGetClient,
@@ -51,26 +98,101 @@ impl ReadmeCmd {
     ) -> Result<()> {
         let versions = client.versions(&package_id).await?;
         let version = turron_pick_version::pick_version(requested, &versions[..])
-            .ok_or_else(|| ViewError::VersionNotFound(package_id.into(), requested.clone()))?;
-        let nuspec = client.nuspec(package_id, &version).await?;
-        if let Some(readme) = &nuspec.metadata.readme {
-            let readme = readme.to_lowercase();
-            let data = client
-                .get_from_nupkg(package_id, &version, &readme)
-                .await
-                .map_err(|err| -> Report {
-                    match err {
-                        NuGetApiError::FileNotFound(_, _, _) => {
-                            ViewError::ReadmeNotFound(nuspec.metadata.id, version).into()
-                        }
-                        _ => err.into(),
-                    }
-                })?;
-            let readme_str = String::from_utf8(data).map_err(ViewError::InvalidUtf8)?;
-            termimad::print_text(&readme_str);
-            Ok(())
+            .ok_or_else(|| version_missing(package_id, requested))?;
+        let readme = client
+            .readme(package_id, &version)
+            .await?
+            .ok_or_else(|| ViewError::ReadmeNotFound(package_id.into(), version))?;
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({ "readme": readme }))
+                    .into_diagnostic()
+                    .context("Failed to serialize readme into JSON")?
+            );
+            return Ok(());
+        } else if self.quiet {
+            return Ok(());
+        }
+
+        let text = if self.raw {
+            // Raw means raw, escape sequences and all, so warn about them
+            // instead of stripping them. The banner goes to stderr to keep
+            // stdout byte-for-byte what the publisher wrote.
+            eprintln!("{}", RAW_BANNER);
+            let mut text = readme;
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text
         } else {
-            Err(ViewError::ReadmeNotFound(nuspec.metadata.id, version).into())
+            let refresh = self.refresh_capabilities;
+            let caps =
+                smol::unblock(move || capabilities::detect(&Probes::default(), refresh)).await;
+            let opts = RenderOptions {
+                width: term_size::dimensions().map(|(w, _)| w).unwrap_or(80),
+                color: caps.color != ColorDepth::None,
+                hyperlinks: supports_hyperlinks::on(Stream::Stdout),
+                unicode: caps.unicode,
+            };
+            markdown::render(&readme, opts)
+        };
+        if self.pager {
+            let text = text.clone();
+            if smol::unblock(move || page(&text)).await? {
+                return Ok(());
+            }
+        }
+        print!("{}", text);
+        Ok(())
+    }
+}
+
+/// Shows `text` in `$PAGER` (or `less`) if it's taller than the terminal.
+/// Returns whether it did. If the pager can't be started, `text` is left
+/// for the caller to print.
+fn page(text: &str) -> Result<bool> {
+    let height = match term_size::dimensions() {
+        Some((_, height)) => height,
+        // Not a terminal, so there's nothing to page.
+        None => return Ok(false),
+    };
+    if text.lines().count() < height {
+        return Ok(false);
+    }
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "more" } else { "less" }.into());
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(&pager).stdin(Stdio::piped());
+    // Like git: pass colors through, and quit right away if it all fits
+    // after all.
+    if env::var_os("LESS").is_none() {
+        cmd.env("LESS", "FRX");
+    }
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            tracing::warn!("Failed to start pager `{}`: {}", pager, err);
+            return Ok(false);
         }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe, which is fine.
+        let _ = stdin.write_all(text.as_bytes());
     }
+    child
+        .wait()
+        .into_diagnostic()
+        .with_context(|| format!("Failed to run pager `{}`", pager))?;
+    Ok(true)
 }
@@ -0,0 +1,381 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read, Seek};
+
+use dotnet_semver::Range;
+use nuget_api::{v3::NuGetClient, NuGetApiError, SourceProtocol};
+use turron_command::{
+    async_trait::async_trait,
+    clap::{self, Clap},
+    fmt::Numbers,
+    owo_colors::{colors::*, OwoColorize},
+    render::{self, sanitize, sanitize_cell, DEFAULT_MAX_CELL_WIDTH},
+    turron_config::TurronConfigLayer,
+    TurronCommand,
+};
+use turron_common::{
+    miette::{Context, IntoDiagnostic, Result},
+    serde::Serialize,
+    serde_json::{self, Value},
+    smol, tracing,
+};
+use zip::ZipArchive;
+
+use crate::spec::resolve_spec;
+use crate::subcommands::files::{list_entries, Entry};
+use crate::suggest::find_package;
+use crate::wait::version_missing;
+
+/// Where a package declares its RID-specific dependency packages.
+const RUNTIME_JSON: &str = "runtime.json";
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "view.runtimes"]
+pub struct RuntimesCmd {
+    #[clap(about = "Package spec to look up")]
+    package: String,
+    #[clap(about = "Version or range to look up, if the package spec doesn't have one")]
+    version: Option<String>,
+    #[clap(
+        about = "Source to view packages from",
+        default_value = "https://api.nuget.org/v3/index.json",
+        long
+    )]
+    source: String,
+    #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "Don't look for similarly named packages if this one isn't found",
+        long
+    )]
+    no_suggest: bool,
+    #[clap(from_global)]
+    quiet: bool,
+    #[clap(from_global)]
+    json: bool,
+}
+
+#[async_trait]
+impl TurronCommand for RuntimesCmd {
+    async fn execute(self) -> Result<()> {
+        let (package_id, requested) = self.spec()?;
+        let requested = requested.unwrap_or_else(Range::any_floating);
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        let package_id = find_package(&client, &package_id, self.no_suggest).await?;
+        self.print_runtimes(&client, &package_id, &requested).await
+    }
+}
+
+impl RuntimesCmd {
+    /// The package ID and requested range, from either `<id>@<version>` or
+    /// `<id> <version>`.
+    pub(crate) fn spec(&self) -> Result<(String, Option<Range>)> {
+        resolve_spec(&self.package, self.version.as_deref())
+    }
+
+    async fn print_runtimes(
+        &self,
+        client: &NuGetClient,
+        package_id: &str,
+        requested: &Range,
+    ) -> Result<()> {
+        let versions = client.versions(&package_id).await?;
+        let version = turron_pick_version::pick_version(requested, &versions[..])
+            .ok_or_else(|| version_missing(package_id, requested))?;
+        let nupkg = client.nupkg(package_id, &version).await?;
+        let (entries, runtime_json) = smol::unblock(move || -> Result<_, NuGetApiError> {
+            let entries = list_entries(Cursor::new(&nupkg), None)?;
+            let runtime_json = read_runtime_json(Cursor::new(&nupkg))?;
+            Ok((entries, runtime_json))
+        })
+        .await?;
+        let expanded = runtime_json.and_then(|json| match runtime_json_rids(&json) {
+            Ok(rids) => Some(rids),
+            Err(err) => {
+                tracing::warn!(
+                    "Ignoring {}'s unreadable {}: {}",
+                    package_id,
+                    RUNTIME_JSON,
+                    err
+                );
+                None
+            }
+        });
+        let runtimes = group_by_runtime(&entries, expanded.as_deref());
+
+        if self.json && !self.quiet {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&runtimes)
+                    .into_diagnostic()
+                    .context("Failed to serialize runtimes to JSON")?
+            );
+        } else if !self.quiet {
+            print_grid(&runtimes);
+        }
+        Ok(())
+    }
+}
+
+/// The assets a package ships for one runtime identifier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct Runtime {
+    pub(crate) rid: String,
+    /// Everything under `runtimes/<rid>/`, including `native/` and `lib/`.
+    pub(crate) files: Vec<Entry>,
+    /// Whether the package has a `runtime.json` that doesn't mention this
+    /// RID, so restoring for it won't pull in any RID-specific dependencies.
+    /// Always `false` for packages without a `runtime.json`.
+    pub(crate) missing_from_runtime_json: bool,
+}
+
+/// Groups `runtimes/<rid>/` entries by RID, sorted. RIDs are compared
+/// ignoring case, with the first spelling winning. `expanded` is the RIDs
+/// the package's `runtime.json` lists, if it has one.
+pub(crate) fn group_by_runtime(entries: &[Entry], expanded: Option<&[String]>) -> Vec<Runtime> {
+    let mut runtimes: BTreeMap<String, Runtime> = BTreeMap::new();
+    for entry in entries {
+        let mut parts = entry.path.splitn(3, '/');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(dir), Some(rid), Some(rest))
+                if dir.eq_ignore_ascii_case("runtimes") && !rid.is_empty() && !rest.is_empty() =>
+            {
+                runtimes
+                    .entry(rid.to_lowercase())
+                    .or_insert_with(|| Runtime {
+                        rid: rid.into(),
+                        files: Vec::new(),
+                        missing_from_runtime_json: expanded.map_or(false, |expanded| {
+                            !expanded.iter().any(|r| r.eq_ignore_ascii_case(rid))
+                        }),
+                    })
+                    .files
+                    .push(entry.clone())
+            }
+            _ => {}
+        }
+    }
+    runtimes.into_iter().map(|(_, runtime)| runtime).collect()
+}
+
+/// The RIDs a `runtime.json` expands dependencies for: the keys of its
+/// `runtimes` object.
+pub(crate) fn runtime_json_rids(json: &str) -> serde_json::Result<Vec<String>> {
+    let json: Value = serde_json::from_str(json)?;
+    Ok(json
+        .get("runtimes")
+        .and_then(Value::as_object)
+        .map(|runtimes| runtimes.keys().cloned().collect())
+        .unwrap_or_default())
+}
+
+/// The contents of the `runtime.json` at the root of `nupkg`, if any.
+fn read_runtime_json<R: Read + Seek>(nupkg: R) -> Result<Option<String>, NuGetApiError> {
+    let mut zip = ZipArchive::new(nupkg)?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if file.name().eq_ignore_ascii_case(RUNTIME_JSON) {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            return Ok(Some(contents));
+        }
+    }
+    Ok(None)
+}
+
+/// One table row per RID, with its total size and files.
+fn grid_rows(runtimes: &[Runtime], numbers: Numbers) -> Vec<Vec<String>> {
+    runtimes
+        .iter()
+        .map(|runtime| {
+            let mut rid = sanitize_cell(&runtime.rid, DEFAULT_MAX_CELL_WIDTH);
+            if runtime.missing_from_runtime_json {
+                rid.push('*');
+            }
+            let size = runtime.files.iter().map(|entry| entry.size).sum::<u64>();
+            vec![rid, numbers.bytes(size), runtime_files(runtime).join(", ")]
+        })
+        .collect()
+}
+
+/// A runtime's files, relative to its `runtimes/<rid>/` folder.
+fn runtime_files(runtime: &Runtime) -> Vec<String> {
+    runtime
+        .files
+        .iter()
+        .map(|entry| sanitize(entry.path.splitn(3, '/').nth(2).unwrap_or(&entry.path)))
+        .collect()
+}
+
+/// Prints one row per RID with its files, falling back to a list per RID if
+/// the terminal is too narrow for the table.
+fn print_grid(runtimes: &[Runtime]) {
+    if runtimes.is_empty() {
+        println!("This package has no runtime-specific assets.");
+        return;
+    }
+    let width = term_size::dimensions().map(|(w, _)| w).unwrap_or(80);
+    let numbers = Numbers::current();
+    let headers = ["runtime", "size", "files"]
+        .iter()
+        .map(|h| h.to_string())
+        .collect::<Vec<_>>();
+    let rows = grid_rows(runtimes, numbers);
+    if render::table_width(&headers, &rows) <= width {
+        println!("{}", render::table(&headers, &rows, width));
+    } else {
+        tracing::debug!("Runtime table is too wide for the terminal");
+        for runtime in runtimes {
+            println!(
+                "{}",
+                format!("runtimes/{}/", sanitize(&runtime.rid)).fg::<BrightCyan>()
+            );
+            for (file, entry) in runtime_files(runtime).iter().zip(&runtime.files) {
+                println!(
+                    "  {} {}",
+                    file,
+                    format!("({})", numbers.bytes(entry.size)).fg::<Yellow>()
+                );
+            }
+        }
+    }
+    if runtimes
+        .iter()
+        .any(|runtime| runtime.missing_from_runtime_json)
+    {
+        println!();
+        println!(
+            "{}",
+            format!(
+                "* Not in the package's {}, so restoring for it won't pull in RID-specific dependencies.",
+                RUNTIME_JSON
+            )
+            .fg::<Yellow>()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(paths: &[&str]) -> Vec<Entry> {
+        paths
+            .iter()
+            .map(|path| Entry {
+                path: path.to_string(),
+                size: 8,
+                compressed_size: 4,
+            })
+            .collect()
+    }
+
+    fn layout() -> Vec<Entry> {
+        entries(&[
+            "Turron.Native.nuspec",
+            "lib/netstandard2.0/Turron.Native.dll",
+            "runtimes/win-x64/native/turron.dll",
+            "runtimes/linux-x64/native/libturron.so",
+            "runtimes/osx-arm64/native/libturron.dylib",
+            "runtimes/Win-x64/lib/net6.0/Turron.Native.Windows.dll",
+            "runtimes/",
+            "runtimes/linux-x64/",
+        ])
+    }
+
+    fn summary(runtimes: &[Runtime]) -> Vec<(&str, Vec<&str>, bool)> {
+        runtimes
+            .iter()
+            .map(|r| {
+                (
+                    &r.rid[..],
+                    r.files.iter().map(|e| &e.path[..]).collect(),
+                    r.missing_from_runtime_json,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn groups_files_by_rid() {
+        let runtimes = group_by_runtime(&layout(), None);
+        assert_eq!(
+            summary(&runtimes),
+            vec![
+                (
+                    "linux-x64",
+                    vec!["runtimes/linux-x64/native/libturron.so"],
+                    false
+                ),
+                (
+                    "osx-arm64",
+                    vec!["runtimes/osx-arm64/native/libturron.dylib"],
+                    false
+                ),
+                (
+                    "win-x64",
+                    vec![
+                        "runtimes/win-x64/native/turron.dll",
+                        "runtimes/Win-x64/lib/net6.0/Turron.Native.Windows.dll",
+                    ],
+                    false
+                ),
+            ]
+        );
+        assert!(group_by_runtime(&entries(&["lib/net6.0/A.dll"]), None).is_empty());
+    }
+
+    #[test]
+    fn formats_sizes_in_both_modes() {
+        let mut native = entries(&[
+            "runtimes/linux-x64/native/libturron.so",
+            "runtimes/linux-x64/native/libturron.so.dbg",
+        ]);
+        native[0].size = 18_000_000;
+        native[1].size = 1_189_350;
+        let runtimes = group_by_runtime(&native, None);
+        let files = "native/libturron.so, native/libturron.so.dbg";
+        assert_eq!(
+            grid_rows(&runtimes, Numbers::Human),
+            vec![vec!["linux-x64", "18.30MiB", files]]
+        );
+        assert_eq!(
+            grid_rows(&runtimes, Numbers::Raw),
+            vec![vec!["linux-x64", "19189350", files]]
+        );
+    }
+
+    #[test]
+    fn flags_rids_missing_from_runtime_json() {
+        let rids = runtime_json_rids(
+            r#"{
+                "runtimes": {
+                    "linux-x64": { "Turron.Native": { "runtime.linux-x64.Turron.Native": "1.0.0" } },
+                    "WIN-X64": { "Turron.Native": { "runtime.win-x64.Turron.Native": "1.0.0" } }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(rids, vec!["WIN-X64", "linux-x64"]);
+        let runtimes = group_by_runtime(&layout(), Some(&rids));
+        let flagged = summary(&runtimes)
+            .into_iter()
+            .map(|(rid, _, missing)| (rid, missing))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            flagged,
+            vec![
+                ("linux-x64", false),
+                ("osx-arm64", true),
+                ("win-x64", false)
+            ]
+        );
+
+        assert_eq!(runtime_json_rids("{}").unwrap(), Vec::<String>::new());
+        assert!(runtime_json_rids("not json").is_err());
+    }
+}
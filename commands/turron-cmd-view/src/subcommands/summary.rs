@@ -1,30 +1,49 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Cursor, Write};
+use std::sync::Arc;
+
 use dotnet_semver::{Range, Version};
 use nuget_api::{
-    v3::{NuGetClient, NuSpec, RegistrationIndex, RegistrationLeaf, Tags},
-    NuGetApiError,
+    schedule::{self, Priority},
+    v3::{
+        Dependency, NuGetClient, NuSpec, RegistrationIndex, RegistrationLeaf, SearchQuery,
+        SearchResult, Tags,
+    },
+    NuGetApiError, SourceProtocol,
 };
 use term_grid::{Cell, Direction, Filling, Grid, GridOptions};
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
     owo_colors::{colors::*, OwoColorize},
+    render::{self, sanitize, sanitize_cell, DEFAULT_MAX_CELL_WIDTH},
     turron_config::TurronConfigLayer,
     TurronCommand,
 };
 use turron_common::{
+    chrono::{Datelike, Utc},
     chrono_humanize::HumanTime,
-    miette::{Context, IntoDiagnostic, Report, Result},
-    serde_json,
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json::{self, Value},
+    smol, tracing,
 };
-use turron_package_spec::PackageSpec;
 
+use crate::backfill::{Backfilled, Field};
 use crate::error::ViewError;
+use crate::health::{self, Downloads, Health, HealthConfig, PackageFacts};
+use crate::matrix::DependencyMatrix;
+use crate::spec::resolve_spec;
+use crate::subcommands::files::{group_by_framework, list_entries};
+use crate::suggest::find_package;
+use crate::wait::{version_missing, wait_for_requested};
 
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "view.summary"]
 pub struct SummaryCmd {
     #[clap(about = "Package spec to look up")]
     package: String,
+    #[clap(about = "Version or range to look up, if the package spec doesn't have one")]
+    version: Option<String>,
     #[clap(
         about = "Source to view packages from",
         default_value = "https://api.nuget.org/v3/index.json",
@@ -32,6 +51,54 @@ pub struct SummaryCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "If the requested version isn't on the source yet, keep checking for this long (e.g. `10m`)",
+        long
+    )]
+    wait: Option<humantime::Duration>,
+    #[clap(
+        about = "Show which version each dependency's range currently resolves to",
+        long
+    )]
+    resolve_deps: bool,
+    #[clap(
+        about = "Skip --resolve-deps for packages with more dependencies than this",
+        long,
+        default_value = "50"
+    )]
+    resolve_limit: usize,
+    #[clap(
+        about = "Resolve dependencies even past --resolve-limit",
+        long,
+        requires = "resolve-deps"
+    )]
+    force: bool,
+    #[clap(
+        about = "Show dependencies as a table of ids against every target framework",
+        long
+    )]
+    all_frameworks: bool,
+    #[clap(
+        about = "Download the package and check its target frameworks against its lib/ folders",
+        long
+    )]
+    check_frameworks: bool,
+    #[clap(
+        about = "Don't look for similarly named packages if this one isn't found",
+        long
+    )]
+    no_suggest: bool,
+    #[clap(
+        about = "Score the package's health from 0 to 100, showing what went into the score",
+        long
+    )]
+    health: bool,
+    /// Weights and thresholds for --health, from the `health` config block.
+    #[clap(skip)]
+    pub(crate) health_config: HealthConfig,
+    #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
@@ -40,125 +107,280 @@ pub struct SummaryCmd {
 #[async_trait]
 impl TurronCommand for SummaryCmd {
     async fn execute(self) -> Result<()> {
-        let package = self.package.parse()?;
-        let client = NuGetClient::from_source(self.source.clone()).await?;
-        let (package_id, requested) = if let PackageSpec::NuGet { name, requested } = &package {
-            (name, requested.clone().unwrap_or_else(Range::any_floating))
+        let (package_id, requested) = self.spec()?;
+        let requested = requested.unwrap_or_else(Range::any_floating);
+        let client = Arc::new(
+            NuGetClient::from_source_as(
+                self.source.clone(),
+                self.assume_source_version.unwrap_or_default(),
+            )
+            .await?,
+        );
+        // A package that's still being indexed would look missing, so
+        // --wait gets a chance first.
+        let package_id = if self.wait.is_some() {
+            package_id
         } else {
-            return Err(ViewError::InvalidPackageSpec.into());
+            find_package(&client, &package_id, self.no_suggest).await?
         };
-        self.print_version_details(&client, package_id, &requested)
+        self.print_version_details(&client, &package_id, &requested)
             .await
     }
 }
 
 impl SummaryCmd {
+    /// The package ID and requested range, from either `<id>@<version>` or
+    /// `<id> <version>`.
+    pub(crate) fn spec(&self) -> Result<(String, Option<Range>)> {
+        resolve_spec(&self.package, self.version.as_deref())
+    }
+
     async fn print_version_details(
         &self,
-        client: &NuGetClient,
+        client: &Arc<NuGetClient>,
         package_id: &str,
         requested: &Range,
     ) -> Result<()> {
+        if let Some(timeout) = &self.wait {
+            wait_for_requested(client, package_id, Some(requested), timeout).await?;
+        }
         let versions = client.versions(&package_id).await?;
         let version = turron_pick_version::pick_version(requested, &versions[..])
-            .ok_or_else(|| ViewError::VersionNotFound(package_id.into(), requested.clone()))?;
-        let (index, leaf) = self
-            .find_version(client, package_id, requested, &version)
+            .ok_or_else(|| version_missing(package_id, requested))?;
+        let (index, mut leaf) = self
+            .find_version(client, package_id, &version)
             .await
             .context("Failed to find desired version")?;
         let nuspec = client.nuspec(package_id, &version).await?;
+        // Some sources leave most of the catalog entry out, but the nuspec
+        // always has it.
+        let backfilled = Backfilled::new(&leaf.catalog_entry, &nuspec.metadata);
+        leaf.catalog_entry = backfilled.entry.clone();
+        let resolved = if self.resolve_deps {
+            self.resolve_dependencies(client, &leaf).await
+        } else {
+            None
+        };
+        let frameworks = Frameworks {
+            declared: target_frameworks(&leaf),
+            lib: if self.check_frameworks {
+                Some(lib_frameworks(client, package_id, &version).await?)
+            } else {
+                None
+            },
+        };
+        let health = if self.health {
+            Some(self.score_health(client, &index, &leaf, &nuspec).await?)
+        } else {
+            None
+        };
+        let mut data = SummaryData {
+            index,
+            leaf,
+            nuspec,
+            backfilled,
+            icon: None,
+            resolved,
+            frameworks,
+            health,
+        };
         if self.json && !self.quiet {
             // Just print the whole thing tbh
+            let mut json = serde_json::to_value(&data.leaf)
+                .into_diagnostic()
+                .context("Failed to stringify package data back to JSON")?;
+            if let Some(resolved) = &data.resolved {
+                annotate_json(&mut json, &data.leaf, resolved);
+            }
+            if self.all_frameworks {
+                let groups = data.leaf.catalog_entry.dependency_groups.as_deref();
+                json["dependencyMatrix"] =
+                    DependencyMatrix::new(groups.unwrap_or_default()).to_json();
+            }
+            data.frameworks.annotate_json(&mut json);
+            if let Some(health) = &data.health {
+                json["health"] = health.to_json();
+            }
+            if !data.backfilled.fields.is_empty() {
+                json["backfilledFromNuspec"] = data
+                    .backfilled
+                    .fields
+                    .iter()
+                    .map(|field| field.to_string())
+                    .collect::<Vec<_>>()
+                    .into();
+            }
             println!(
                 "{}",
-                serde_json::to_string_pretty(&leaf)
+                serde_json::to_string_pretty(&json)
                     .into_diagnostic()
                     .context("Failed to stringify package data back to JSON")?
             );
         } else if !self.quiet {
-            let icon = if let Some(icon) = &nuspec.metadata.icon {
-                let icon = icon.to_lowercase();
-                let data = client
-                    .get_from_nupkg(package_id, &version, &icon)
-                    .await
-                    .map_err(|err| -> Report {
-                        match err {
-                            NuGetApiError::FileNotFound(_, _, _) => {
-                                ViewError::IconNotFound(nuspec.metadata.id.clone(), version).into()
-                            }
-                            _ => err.into(),
-                        }
-                    })?;
-                Some(data)
-            } else {
-                None
-            };
-            self.print_package_details(&index, &leaf, &nuspec, icon.as_deref())?;
+            if data.nuspec.metadata.icon.is_some() {
+                data.icon = client.icon(package_id, &version).await?;
+            }
+            let width = term_size::dimensions().map(|(w, _)| w).unwrap_or(80);
+            let stdout = io::stdout();
+            self.write_package_details(&mut stdout.lock(), width, &data)?;
         }
         Ok(())
     }
 
+    /// Picks versions for `leaf`'s dependencies, unless there are too many
+    /// of them to be worth the requests.
+    async fn resolve_dependencies(
+        &self,
+        client: &Arc<NuGetClient>,
+        leaf: &RegistrationLeaf,
+    ) -> Option<Resolved> {
+        let mut deps: Vec<Dependency> = Vec::new();
+        for dep in all_dependencies(leaf) {
+            if !deps.iter().any(|d| dep_key(d) == dep_key(dep)) {
+                deps.push(dep.clone());
+            }
+        }
+        if deps.len() > self.resolve_limit && !self.force {
+            tracing::warn!(
+                "Not resolving {} dependencies, since that's more than --resolve-limit ({}). Pass --force to resolve them anyway.",
+                deps.len(),
+                self.resolve_limit
+            );
+            return None;
+        }
+        Some(resolve_deps(client.clone(), deps).await)
+    }
+
     async fn find_version(
         &self,
         client: &NuGetClient,
         package_id: &str,
-        req: &Range,
         version: &Version,
     ) -> Result<(RegistrationIndex, RegistrationLeaf)> {
+        if !client.endpoints.registration_supports_semver2() {
+            tracing::warn!("{} does not support SemVer 2.0.0 package registrations. Some versions may be missing.", self.source);
+        }
         let index = client.registration(package_id).await?;
-        for page in &index.items {
-            let page_range: Range = format!("[{}, {}]", page.lower, page.upper).parse()?;
-            if page_range.satisfies(version) {
-                let page = if page.items.is_some() {
-                    page.clone()
-                } else {
-                    client.registration_page(&page.id).await?
-                };
-                for leaf in page
-                    .items
-                    .expect("RegistrationPage endpoints must have items!")
-                    .into_iter()
-                {
-                    if version == &leaf.catalog_entry.version {
-                        return Ok((index, leaf));
-                    }
-                }
-            }
+        if let Some(leaf) = client.find_leaf_in(&index, version).await? {
+            return Ok((index, leaf));
         }
-        Err(ViewError::VersionNotFound(package_id.into(), req.clone()).into())
+        // The flat container already listed it, so the registration just
+        // hasn't caught up yet.
+        Err(ViewError::VersionNotIndexed(package_id.into(), version.clone()).into())
     }
 
-    fn print_package_details(
+    /// Gathers [`PackageFacts`] for `leaf` and scores them. This needs
+    /// every version's registration leaf and the package's download counts,
+    /// so it can take a few more requests.
+    async fn score_health(
         &self,
+        client: &NuGetClient,
         index: &RegistrationIndex,
         leaf: &RegistrationLeaf,
         nuspec: &NuSpec,
-        icon: Option<&[u8]>,
+    ) -> Result<Health> {
+        let leaves = client.leaves_in(index).await?;
+        let entry = &leaf.catalog_entry;
+        let facts = PackageFacts {
+            now: Utc::now(),
+            releases: leaves
+                .iter()
+                .filter(|leaf| leaf.catalog_entry.listed != Some(false))
+                .filter_map(|leaf| leaf.catalog_entry.published)
+                // nuget.org dates unlisted versions to 1900.
+                .filter(|published| published.year() > 1900)
+                .collect(),
+            has_license: entry.license_expression.is_some()
+                || entry.license_url.is_some()
+                || nuspec.metadata.license.is_some(),
+            has_readme: nuspec.metadata.readme.is_some(),
+            has_repository: nuspec.metadata.repository.is_some(),
+            deprecated: entry.deprecation.is_some(),
+            vulnerabilities: entry.vulnerabilities.as_ref().map_or(0, Vec::len),
+            downloads: search_result(client, &entry.id)
+                .await
+                .and_then(|result| downloads(&result, &leaves)),
+            dependencies: dependency_count(leaf),
+        };
+        Ok(health::score(&facts, &self.health_config))
+    }
+
+    /// Writes everything but `--json`'s output. The icon, if there is one,
+    /// is drawn straight to the terminal, since it's not text.
+    fn write_package_details(
+        &self,
+        out: &mut impl Write,
+        width: usize,
+        data: &SummaryData,
     ) -> Result<()> {
-        self.print_header(index, leaf, icon)?;
-        self.print_tags(leaf);
-        self.print_nupkg_details(leaf);
-        self.print_dependencies(leaf);
-        self.print_readme_info(nuspec);
-        self.print_publish_time(leaf);
+        self.write_header(out, &data.index, &data.leaf, &data.backfilled)
+            .into_diagnostic()
+            .context("Failed to write package summary")?;
+        if let Some(icon_data) = &data.icon {
+            out.flush()
+                .into_diagnostic()
+                .context("Failed to write package summary")?;
+            let conf = viuer::Config {
+                transparent: true,
+                absolute_offset: false,
+                height: Some(5),
+                ..Default::default()
+            };
+            let img = image::load_from_memory(icon_data)
+                .into_diagnostic()
+                .context("Failed to load image into memory")?;
+            viuer::print(&img, &conf)
+                .into_diagnostic()
+                .context("Failed to print image to terminal")?;
+        }
+        self.write_details(out, width, data)
+            .into_diagnostic()
+            .context("Failed to write package summary")
+    }
+
+    /// Everything after the header.
+    fn write_details(
+        &self,
+        out: &mut impl Write,
+        width: usize,
+        data: &SummaryData,
+    ) -> io::Result<()> {
+        let leaf = &data.leaf;
+        self.write_tags(out, leaf, &data.backfilled)?;
+        self.write_frameworks(out, &data.frameworks)?;
+        self.write_nupkg_details(out, leaf)?;
+        self.write_dependencies(out, width, leaf, data.resolved.as_ref())?;
+        self.write_readme_info(out, &data.nuspec)?;
+        self.write_publish_time(out, leaf)?;
+        if let Some(health) = &data.health {
+            self.write_health(out, width, health)?;
+        }
+        if !data.backfilled.fields.is_empty() {
+            writeln!(out, "{}", "* from nuspec".dimmed())?;
+        }
         Ok(())
     }
 
-    fn print_header(
+    fn write_header(
         &self,
+        out: &mut impl Write,
         index: &RegistrationIndex,
         leaf: &RegistrationLeaf,
-        icon: Option<&[u8]>,
-    ) -> Result<()> {
+        backfilled: &Backfilled,
+    ) -> io::Result<()> {
         let mut total_versions = 0usize;
         for page in &index.items {
             total_versions += page.count;
         }
         let entry = &leaf.catalog_entry;
-        let total_deps = 0;
-        println!(
-            "{}@{} | {} | deps: {} | versions: {}",
-            entry.id.fg::<BrightGreen>().underline(),
+        let total_deps = match dependency_count(leaf) {
+            0 => "none".into(),
+            count => count.to_string(),
+        };
+        writeln!(
+            out,
+            "{}@{} | {}{} | deps: {} | versions: {}",
+            sanitize(&entry.id).fg::<BrightGreen>().underline(),
             entry.version.to_string().fg::<BrightGreen>().underline(),
             entry
                 .license_expression
@@ -166,139 +388,745 @@ impl SummaryCmd {
                 .and_then(|l| if l.is_empty() {
                     None
                 } else {
-                    Some(l.fg::<Green>().to_string())
+                    Some(sanitize(&l).fg::<Green>().to_string())
                 })
                 .unwrap_or_else(|| "No License".fg::<Red>().to_string()),
-            total_deps.to_string().fg::<Yellow>(),
+            backfilled.marker(Field::License).dimmed(),
+            total_deps.fg::<Yellow>(),
             total_versions.to_string().fg::<Yellow>(),
-        );
+        )?;
         if let Some(desc) = &entry.description {
-            println!("{}", desc);
+            writeln!(
+                out,
+                "{}{}",
+                sanitize(desc),
+                backfilled.marker(Field::Description).dimmed()
+            )?;
         }
         if let Some(url) = &entry.project_url {
-            println!("{}", url.fg::<Cyan>());
+            writeln!(
+                out,
+                "{}{}",
+                sanitize(url).fg::<Cyan>(),
+                backfilled.marker(Field::ProjectUrl).dimmed()
+            )?;
         }
         if let Some(depr) = &entry.deprecation {
-            print!("⚠ {}", "DEPRECATED".bright_red());
+            write!(out, "⚠ {}", "DEPRECATED".bright_red())?;
             if let Some(msg) = &depr.message {
-                print!(" - {}", msg);
+                write!(out, " - {}", sanitize(msg))?;
             }
-            println!()
-        }
-        if let Some(icon_data) = icon {
-            let conf = viuer::Config {
-                transparent: true,
-                absolute_offset: false,
-                height: Some(5),
-                ..Default::default()
-            };
-            let img = image::load_from_memory(icon_data)
-                .into_diagnostic()
-                .context("Failed to load image into memory")?;
-            viuer::print(&img, &conf)
-                .into_diagnostic()
-                .context("Failed to print image to terminal")?;
+            writeln!(out)?;
         }
         Ok(())
     }
 
-    fn print_tags(&self, leaf: &RegistrationLeaf) {
-        println!();
+    fn write_tags(
+        &self,
+        out: &mut impl Write,
+        leaf: &RegistrationLeaf,
+        backfilled: &Backfilled,
+    ) -> io::Result<()> {
+        writeln!(out)?;
         let entry = &leaf.catalog_entry;
+        if let Some(authors) = &entry.authors {
+            writeln!(
+                out,
+                "Authors: {}{}",
+                sanitize(&authors.to_string()),
+                backfilled.marker(Field::Authors).dimmed()
+            )?;
+        }
+        let marker = backfilled.marker(Field::Tags).dimmed();
         match &entry.tags {
             Some(Tags::One(tag)) => {
-                println!("Tags: {}", tag.fg::<Yellow>());
+                writeln!(out, "Tags: {}{}", sanitize(tag).fg::<Yellow>(), marker)?;
             }
             Some(Tags::Many(tags)) => {
-                println!(
-                    "Tags: {}",
+                writeln!(
+                    out,
+                    "Tags: {}{}",
                     tags.iter()
-                        .map(|t| t.fg::<Yellow>().to_string())
+                        .map(|t| sanitize(t).fg::<Yellow>().to_string())
                         .collect::<Vec<_>>()
-                        .join(", ")
-                );
+                        .join(", "),
+                    marker
+                )?;
             }
             None => {}
         }
+        Ok(())
+    }
+
+    fn write_frameworks(&self, out: &mut impl Write, frameworks: &Frameworks) -> io::Result<()> {
+        if frameworks.declared.is_empty() {
+            writeln!(out, "Frameworks: {}", "none declared".dimmed())?;
+        } else {
+            writeln!(
+                out,
+                "Frameworks: {}",
+                frameworks
+                    .declared
+                    .iter()
+                    .map(|f| sanitize(f).fg::<BrightCyan>().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        for framework in frameworks.missing_lib() {
+            writeln!(
+                out,
+                "⚠ {} has a dependency group, but no lib/{}/",
+                sanitize(framework).fg::<Yellow>(),
+                sanitize(framework)
+            )?;
+        }
+        for framework in frameworks.missing_group() {
+            writeln!(
+                out,
+                "⚠ {} has no dependency group",
+                format!("lib/{}/", sanitize(framework)).fg::<Yellow>()
+            )?;
+        }
+        Ok(())
     }
 
-    fn print_nupkg_details(&self, leaf: &RegistrationLeaf) {
-        println!();
-        println!("Nupkg: {}", leaf.package_content.fg::<Cyan>());
+    fn write_nupkg_details(&self, out: &mut impl Write, leaf: &RegistrationLeaf) -> io::Result<()> {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "Nupkg: {}",
+            sanitize(&leaf.package_content).fg::<Cyan>()
+        )?;
         // TODO: How tf do I get the nupkg hash?...
+        Ok(())
     }
 
-    fn print_dependencies(&self, leaf: &RegistrationLeaf) {
+    fn write_dependencies(
+        &self,
+        out: &mut impl Write,
+        width: usize,
+        leaf: &RegistrationLeaf,
+        resolved: Option<&Resolved>,
+    ) -> io::Result<()> {
         let entry = &leaf.catalog_entry;
+        if self.all_frameworks {
+            let groups = entry.dependency_groups.as_deref().unwrap_or_default();
+            if self.write_dependency_matrix(out, width, &DependencyMatrix::new(groups))? {
+                return Ok(());
+            }
+        }
         if let Some(groups) = &entry.dependency_groups {
             for group in groups {
                 if let Some(deps) = &group.dependencies {
                     if !deps.is_empty() {
-                        println!(
-                            "\nDependencies for {}:",
+                        writeln!(
+                            out,
+                            "\nDependencies for {} ({}):",
                             group
                                 .target_framework
-                                .clone()
+                                .as_deref()
+                                .map(sanitize)
                                 .unwrap_or_else(|| "this package".into())
-                                .fg::<BrightCyan>()
-                        );
+                                .fg::<BrightCyan>(),
+                            deps.len()
+                        )?;
                         let max_deps = 25_usize;
                         let mut grid = Grid::new(GridOptions {
                             filling: Filling::Spaces(3),
                             direction: Direction::TopToBottom,
                         });
-                        let width = term_size::dimensions().map(|(w, _)| w).unwrap_or(80);
                         let mut deps = deps.clone();
                         deps.sort();
                         let mut vals = Vec::new();
                         for dep in deps.iter().take(max_deps) {
-                            let mut val = dep.id.clone().fg::<Yellow>().to_string();
-                            if let Some(range) = &dep.range {
-                                val.push_str(&format!(": {}", range));
-                            }
+                            let val = dep_label(dep, resolved);
                             vals.push(val.clone());
                             grid.add(Cell::from(val));
                         }
-                        if let Some(out) = grid.fit_into_width(width) {
-                            print!("{}", out);
+                        if let Some(grid) = grid.fit_into_width(width) {
+                            write!(out, "{}", grid)?;
                         } else {
                             // Too wide. Print one per line.
                             for val in &vals {
-                                println!("{}", val);
+                                writeln!(out, "{}", val)?;
                             }
                         }
                         let count = deps.len();
                         if count > max_deps {
-                            println!("(...and {} more)", count - max_deps);
+                            writeln!(out, "(...and {} more)", count - max_deps)?;
                         }
                     }
                 }
             }
         }
+        Ok(())
     }
 
-    fn print_readme_info(&self, nuspec: &NuSpec) {
-        println!();
+    /// Writes `--all-frameworks`' table. Returns `false` without writing
+    /// anything if `width` is too narrow for it, so the usual per-framework
+    /// lists can be shown instead.
+    fn write_dependency_matrix(
+        &self,
+        out: &mut impl Write,
+        width: usize,
+        matrix: &DependencyMatrix,
+    ) -> io::Result<bool> {
+        if matrix.is_empty() {
+            return Ok(true);
+        }
+        let headers = std::iter::once("dependency".to_string())
+            .chain(
+                matrix
+                    .frameworks
+                    .iter()
+                    .map(|f| sanitize_cell(f, DEFAULT_MAX_CELL_WIDTH)),
+            )
+            .collect::<Vec<_>>();
+        let rows = matrix
+            .rows
+            .iter()
+            .map(|(id, cells)| {
+                std::iter::once(sanitize_cell(id, DEFAULT_MAX_CELL_WIDTH))
+                    .chain(cells.iter().map(|cell| match cell {
+                        Some(range) => sanitize_cell(range, DEFAULT_MAX_CELL_WIDTH),
+                        None => "—".into(),
+                    }))
+                    .collect()
+            })
+            .collect::<Vec<Vec<String>>>();
+        if render::table_width(&headers, &rows) > width {
+            tracing::debug!("Dependency matrix is too wide for the terminal");
+            return Ok(false);
+        }
+        writeln!(out, "\nDependencies:")?;
+        writeln!(out, "{}", render::table(&headers, &rows, width))?;
+        Ok(true)
+    }
+
+    fn write_readme_info(&self, out: &mut impl Write, nuspec: &NuSpec) -> io::Result<()> {
+        writeln!(out)?;
         if nuspec.metadata.readme.is_some() {
-            println!(
+            writeln!(
+                out,
                 "This package includes a readme.\nUse `turron view readme {}@{} to read it",
-                nuspec.metadata.id, nuspec.metadata.version
-            );
-            println!();
+                sanitize(&nuspec.metadata.id),
+                nuspec.metadata.version
+            )?;
+            writeln!(out)?;
         } else {
-            println!("This package does not publish a readme.");
-            println!();
+            writeln!(out, "This package does not publish a readme.")?;
+            writeln!(out)?;
         }
+        Ok(())
     }
 
-    fn print_publish_time(&self, leaf: &RegistrationLeaf) {
+    fn write_publish_time(&self, out: &mut impl Write, leaf: &RegistrationLeaf) -> io::Result<()> {
         let entry = &leaf.catalog_entry;
         if let Some(published) = &entry.published {
-            println!(
+            writeln!(
+                out,
                 "Published to {} {}",
                 self.source.fg::<Cyan>(),
                 HumanTime::from(*published).to_string().fg::<Yellow>()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_health(&self, out: &mut impl Write, width: usize, health: &Health) -> io::Result<()> {
+        let score = format!("{}/100", health.score);
+        let score = match health.score {
+            80..=100 => score.fg::<Green>().to_string(),
+            50..=79 => score.fg::<Yellow>().to_string(),
+            _ => score.fg::<Red>().to_string(),
+        };
+        writeln!(out, "\nHealth: {}", score)?;
+        let headers = ["factor", "weight", "score", "why"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect::<Vec<_>>();
+        let rows = health
+            .factors
+            .iter()
+            .map(|factor| {
+                vec![
+                    factor.factor.label().into(),
+                    factor.weight.to_string(),
+                    factor
+                        .score
+                        .map(|score| score.to_string())
+                        .unwrap_or_else(|| "—".into()),
+                    factor.detail.clone(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", render::table(&headers, &rows, width))
+    }
+}
+
+/// `id`'s search result, which is where download counts come from. Not
+/// every source has search, so failing just means going without.
+async fn search_result(client: &NuGetClient, id: &str) -> Option<SearchResult> {
+    let mut query = SearchQuery::from_query(format!("packageid:{}", id));
+    query.prerelease = Some(true);
+    match client.search(query).await {
+        Ok(response) => response
+            .data
+            .into_iter()
+            .find(|result| result.id.eq_ignore_ascii_case(id)),
+        Err(err) => {
+            tracing::debug!("Couldn't get download counts for {}: {}", id, err);
+            None
+        }
+    }
+}
+
+/// Total downloads, plus those of the most recently published version
+/// search has a count for.
+fn downloads(result: &SearchResult, leaves: &[RegistrationLeaf]) -> Option<Downloads> {
+    let (latest_published, latest) = result
+        .versions
+        .iter()
+        .filter_map(|found| {
+            let version = Version::parse(&found.version).ok()?;
+            let leaf = leaves
+                .iter()
+                .find(|leaf| leaf.catalog_entry.version == version)?;
+            Some((leaf.catalog_entry.published?, found.downloads?))
+        })
+        .max_by_key(|(published, _)| *published)?;
+    Some(Downloads {
+        total: result.total_downloads?,
+        latest,
+        latest_published,
+    })
+}
+
+/// Everything `view summary` knows about the version it's showing.
+struct SummaryData {
+    index: RegistrationIndex,
+    leaf: RegistrationLeaf,
+    nuspec: NuSpec,
+    backfilled: Backfilled,
+    /// The package's icon, when there's a terminal to draw it on.
+    icon: Option<Vec<u8>>,
+    resolved: Option<Resolved>,
+    frameworks: Frameworks,
+    health: Option<Health>,
+}
+
+/// Picked versions, keyed by [`dep_key`]. `None` means nothing matched.
+type Resolved = HashMap<(String, String), Option<Version>>;
+
+/// Dependencies are resolved per ID and range, since different framework
+/// groups can ask for different ranges of the same package.
+fn dep_key(dep: &Dependency) -> (String, String) {
+    (
+        dep.id.to_lowercase(),
+        dep.range
+            .as_ref()
+            .map(|r| r.to_string())
+            .unwrap_or_default(),
+    )
+}
+
+fn all_dependencies(leaf: &RegistrationLeaf) -> impl Iterator<Item = &Dependency> {
+    leaf.catalog_entry
+        .dependency_groups
+        .iter()
+        .flatten()
+        .flat_map(|group| group.dependencies.iter().flatten())
+}
+
+/// How many different packages `leaf` depends on. A package that shows up
+/// in several framework groups only counts once.
+fn dependency_count(leaf: &RegistrationLeaf) -> usize {
+    all_dependencies(leaf)
+        .map(|dep| dep.id.to_lowercase())
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// The target frameworks a package says it supports, and, with
+/// `--check-frameworks`, the ones its `lib/` folders actually have.
+#[derive(Debug)]
+struct Frameworks {
+    declared: Vec<String>,
+    lib: Option<Vec<String>>,
+}
+
+impl Frameworks {
+    /// Declared frameworks with no `lib/<tfm>/` folder.
+    fn missing_lib(&self) -> Vec<&str> {
+        match &self.lib {
+            Some(lib) => missing_from(&self.declared, lib),
+            None => Vec::new(),
+        }
+    }
+
+    /// `lib/<tfm>/` folders with no dependency group.
+    fn missing_group(&self) -> Vec<&str> {
+        match &self.lib {
+            Some(lib) => missing_from(lib, &self.declared),
+            None => Vec::new(),
+        }
+    }
+
+    fn annotate_json(&self, json: &mut Value) {
+        json["frameworks"] = self.declared.clone().into();
+        if let Some(lib) = &self.lib {
+            json["libFrameworks"] = lib.clone().into();
+            json["frameworkMismatches"] = serde_json::json!({
+                "missingLib": self.missing_lib(),
+                "missingDependencyGroup": self.missing_group(),
+            });
+        }
+    }
+}
+
+/// The distinct target frameworks of `leaf`'s dependency groups, sorted.
+fn target_frameworks(leaf: &RegistrationLeaf) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut frameworks = leaf
+        .catalog_entry
+        .dependency_groups
+        .iter()
+        .flatten()
+        .filter_map(|group| group.target_framework.as_deref())
+        .filter(|framework| !framework.is_empty() && seen.insert(framework.to_lowercase()))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    frameworks.sort_by_key(|framework| framework.to_lowercase());
+    frameworks
+}
+
+/// The `lib/<tfm>/` folders in the package's .nupkg.
+async fn lib_frameworks(
+    client: &NuGetClient,
+    package_id: &str,
+    version: &Version,
+) -> Result<Vec<String>> {
+    let nupkg = client.nupkg(package_id, version).await?;
+    let entries = smol::unblock(move || list_entries(Cursor::new(nupkg), None)).await?;
+    Ok(group_by_framework(&entries)
+        .into_iter()
+        .filter_map(|(framework, _)| framework.map(String::from))
+        .collect())
+}
+
+/// Frameworks in `frameworks` that aren't in `other`, ignoring case.
+fn missing_from<'a>(frameworks: &'a [String], other: &[String]) -> Vec<&'a str> {
+    frameworks
+        .iter()
+        .filter(|framework| !other.iter().any(|o| o.eq_ignore_ascii_case(framework)))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Looks up the versions of each of `deps` and picks the one its range
+/// would get today. They're all asked for at once, and the request
+/// scheduler decides how many are actually in flight.
+async fn resolve_deps(client: Arc<NuGetClient>, deps: Vec<Dependency>) -> Resolved {
+    let tasks = deps
+        .into_iter()
+        .map(|dep| {
+            let client = client.clone();
+            smol::spawn(schedule::prioritized(Priority::Bulk, async move {
+                let picked = match client.versions(&dep.id).await {
+                    Ok(versions) => turron_pick_version::pick_version(
+                        dep.range.as_ref().unwrap_or(&Range::any()),
+                        &versions,
+                    ),
+                    Err(NuGetApiError::PackageNotFound) => None,
+                    Err(err) => {
+                        tracing::warn!("Failed to resolve {}: {}", dep.id, err);
+                        None
+                    }
+                };
+                (dep_key(&dep), picked)
+            }))
+        })
+        .collect::<Vec<_>>();
+    let mut resolved = HashMap::new();
+    for task in tasks {
+        let (key, picked) = task.await;
+        resolved.insert(key, picked);
+    }
+    resolved
+}
+
+fn dep_label(dep: &Dependency, resolved: Option<&Resolved>) -> String {
+    let mut val = sanitize(&dep.id).fg::<Yellow>().to_string();
+    if let Some(range) = dep.range_label() {
+        val.push_str(&format!(": {}", sanitize(&range)));
+    }
+    if let Some(picked) = resolved.and_then(|resolved| resolved.get(&dep_key(dep))) {
+        match picked {
+            Some(version) => val.push_str(&format!(" → {}", version.to_string().fg::<Green>())),
+            None => val.push_str(&format!(" → {}", "(unresolved)".fg::<Red>())),
+        }
+    }
+    val
+}
+
+/// Adds a `resolved` field to each dependency in the JSON form of `leaf`.
+fn annotate_json(json: &mut Value, leaf: &RegistrationLeaf, resolved: &Resolved) {
+    let groups = leaf.catalog_entry.dependency_groups.iter().flatten();
+    for (i, group) in groups.enumerate() {
+        for (j, dep) in group.dependencies.iter().flatten().enumerate() {
+            let picked = resolved
+                .get(&dep_key(dep))
+                .cloned()
+                .flatten()
+                .map(|v| v.to_string());
+            if let Some(obj) =
+                json["catalogEntry"]["dependencyGroups"][i]["dependencies"][j].as_object_mut()
+            {
+                obj.insert("resolved".into(), picked.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::{quick_xml, serde_json::json};
+    use turron_testing::{assert_snapshot, fixtures, snapshot, RegistrationBuilder, TestServer};
+
+    use super::*;
+
+    fn leaf(builder: RegistrationBuilder) -> RegistrationLeaf {
+        serde_json::from_value(builder.build().index["items"][0]["items"][0].clone()).unwrap()
+    }
+
+    fn cmd(resolve_limit: usize, force: bool) -> SummaryCmd {
+        SummaryCmd {
+            package: "A".into(),
+            version: None,
+            source: "https://api.nuget.org/v3/index.json".into(),
+            assume_source_version: None,
+            wait: None,
+            resolve_deps: true,
+            resolve_limit,
+            force,
+            all_frameworks: false,
+            check_frameworks: false,
+            no_suggest: false,
+            health: false,
+            health_config: HealthConfig::default(),
+            quiet: false,
+            json: false,
+        }
+    }
+
+    #[test]
+    fn counts_distinct_dependencies() {
+        let overlapping = leaf(
+            RegistrationBuilder::new("A")
+                .versions(vec!["1.0.0"])
+                .dependency(Some("net6.0"), "B", "[1.0.0, )")
+                .dependency(Some("net6.0"), "C", "[1.0.0, )")
+                .dependency(Some("netstandard2.0"), "b", "[0.9.0, )")
+                .dependency(Some("netstandard2.0"), "C", "[1.0.0, )")
+                .dependency(Some("netstandard2.0"), "D", "[1.0.0, )"),
+        );
+        assert_eq!(dependency_count(&overlapping), 3);
+
+        // Both empty and missing groups mean "deps: none".
+        let mut none = leaf(RegistrationBuilder::new("A").versions(vec!["1.0.0"]));
+        assert_eq!(dependency_count(&none), 0);
+        none.catalog_entry.dependency_groups = None;
+        assert_eq!(dependency_count(&none), 0);
+    }
+
+    #[test]
+    fn lists_and_checks_target_frameworks() {
+        let leaf = leaf(
+            RegistrationBuilder::new("A")
+                .versions(vec!["1.0.0"])
+                .dependency(Some("netstandard2.0"), "B", "[1.0.0, )")
+                .dependency(Some("net6.0"), "B", "[1.0.0, )")
+                .dependency(Some("NET6.0"), "C", "[1.0.0, )")
+                .dependency(None, "D", "[1.0.0, )"),
+        );
+        let mut frameworks = Frameworks {
+            declared: target_frameworks(&leaf),
+            lib: None,
+        };
+        assert_eq!(frameworks.declared, vec!["net6.0", "netstandard2.0"]);
+        assert!(frameworks.missing_lib().is_empty());
+
+        frameworks.lib = Some(vec!["NET6.0".into(), "net48".into()]);
+        assert_eq!(frameworks.missing_lib(), vec!["netstandard2.0"]);
+        assert_eq!(frameworks.missing_group(), vec!["net48"]);
+
+        let mut json = json!({});
+        frameworks.annotate_json(&mut json);
+        assert_eq!(json["frameworks"], json!(["net6.0", "netstandard2.0"]));
+        assert_eq!(
+            json["frameworkMismatches"],
+            json!({ "missingLib": ["netstandard2.0"], "missingDependencyGroup": ["net48"] })
+        );
+    }
+
+    #[test]
+    fn annotates_dependencies_with_picked_versions() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.json(
+                "/v3-flatcontainer/b/index.json",
+                &json!({ "versions": ["0.9.0", "1.0.0", "1.2.0"] }),
+            );
+            server.json(
+                "/v3-flatcontainer/c/index.json",
+                &json!({ "versions": ["1.0.0"] }),
+            );
+            let leaf = leaf(
+                RegistrationBuilder::new("A")
+                    .versions(vec!["1.0.0"])
+                    .dependency(Some("net6.0"), "B", "[1.0.0, )")
+                    .dependency(Some("netstandard2.0"), "B", "[1.0.0, )")
+                    .dependency(Some("netstandard2.0"), "C", "[2.0.0, )")
+                    .dependency(Some("netstandard2.0"), "Missing", "[1.0.0, )"),
+            );
+            let client = Arc::new(NuGetClient::from_source(server.index_url()).await.unwrap());
+            let resolved = cmd(50, false)
+                .resolve_dependencies(&client, &leaf)
+                .await
+                .unwrap();
+
+            let b = Dependency {
+                id: "b".into(),
+                range: Some("[1.0.0, )".parse().unwrap()),
+                raw_range: None,
+            };
+            assert_eq!(resolved[&dep_key(&b)], Some("1.0.0".parse().unwrap()));
+            assert!(
+                dep_label(&b, Some(&resolved)).ends_with(&format!(" → {}", "1.0.0".fg::<Green>()))
+            );
+            // The same range in another framework group is only looked up once.
+            assert_eq!(server.hits("/v3-flatcontainer/b/index.json"), 1);
+
+            let mut json = serde_json::to_value(&leaf).unwrap();
+            annotate_json(&mut json, &leaf, &resolved);
+            let groups = &json["catalogEntry"]["dependencyGroups"];
+            assert_eq!(groups[0]["dependencies"][0]["resolved"], "1.0.0");
+            assert_eq!(groups[1]["dependencies"][0]["resolved"], "1.0.0");
+            assert_eq!(groups[1]["dependencies"][1]["resolved"], Value::Null);
+            assert_eq!(groups[1]["dependencies"][2]["resolved"], Value::Null);
+        });
+    }
+
+    #[test]
+    fn skips_resolution_past_the_limit_unless_forced() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.json(
+                "/v3-flatcontainer/b/index.json",
+                &json!({ "versions": ["1.0.0"] }),
+            );
+            server.json(
+                "/v3-flatcontainer/c/index.json",
+                &json!({ "versions": ["1.0.0"] }),
+            );
+            let leaf = leaf(
+                RegistrationBuilder::new("A")
+                    .versions(vec!["1.0.0"])
+                    .dependency(None, "B", "[1.0.0, )")
+                    .dependency(None, "C", "[1.0.0, )"),
             );
+            let client = Arc::new(NuGetClient::from_source(server.index_url()).await.unwrap());
+
+            assert!(cmd(1, false)
+                .resolve_dependencies(&client, &leaf)
+                .await
+                .is_none());
+            assert!(server
+                .requests()
+                .iter()
+                .all(|req| !req.contains("flatcontainer")));
+
+            let resolved = cmd(1, true)
+                .resolve_dependencies(&client, &leaf)
+                .await
+                .unwrap();
+            assert_eq!(resolved.len(), 2);
+        });
+    }
+
+    #[test]
+    fn compares_downloads_of_the_newest_release() {
+        let mut leaves = (0..3)
+            .map(|i| {
+                let version = format!("1.{}.0", i);
+                leaf(RegistrationBuilder::new("A").versions(vec![version.as_str()]))
+            })
+            .collect::<Vec<_>>();
+        // 1.2.0 has the highest version, but 1.1.0 came out last.
+        let dates = [
+            "2021-01-01T00:00:00Z",
+            "2021-03-01T00:00:00Z",
+            "2021-02-01T00:00:00Z",
+        ];
+        for (leaf, date) in leaves.iter_mut().zip(dates.iter()) {
+            leaf.catalog_entry.published = Some(date.parse().unwrap());
         }
+        let result: SearchResult = serde_json::from_value(json!({
+            "id": "A",
+            "version": "1.2.0",
+            "totalDownloads": 600,
+            "versions": [
+                { "version": "1.0.0", "downloads": 300 },
+                { "version": "1.1.0", "downloads": 100 },
+                { "version": "1.2.0", "downloads": 200 },
+                { "version": "9.9.9", "downloads": 5 },
+            ],
+        }))
+        .unwrap();
+        let found = downloads(&result, &leaves).unwrap();
+        assert_eq!(found.total, 600);
+        assert_eq!(found.latest, 100);
+        assert_eq!(
+            found.latest_published,
+            leaves[1].catalog_entry.published.unwrap()
+        );
+
+        let no_total: SearchResult =
+            serde_json::from_value(json!({ "id": "A", "version": "1.2.0" })).unwrap();
+        assert!(downloads(&no_total, &leaves).is_none());
+    }
+
+    #[test]
+    fn renders_a_summary() {
+        let registration = RegistrationBuilder::new("Newtonsoft.Json")
+            .versions(vec!["13.0.1"])
+            .dependency(Some(".NETStandard1.0"), "Microsoft.CSharp", "[4.3.0, )")
+            .build();
+        let index: RegistrationIndex = serde_json::from_value(registration.index).unwrap();
+        let mut leaf = index.items[0].items.as_ref().unwrap()[0].clone();
+        leaf.catalog_entry.published = Some("2021-03-22T20:13:32.123Z".parse().unwrap());
+        // Most of the metadata comes from the nuspec, like on sources with
+        // sparse registrations.
+        let nuspec: NuSpec = quick_xml::de::from_str(fixtures::nuspec()).unwrap();
+        let backfilled = Backfilled::new(&leaf.catalog_entry, &nuspec.metadata);
+        leaf.catalog_entry = backfilled.entry.clone();
+        let frameworks = Frameworks {
+            declared: target_frameworks(&leaf),
+            lib: None,
+        };
+        let data = SummaryData {
+            index,
+            leaf,
+            nuspec,
+            backfilled,
+            icon: None,
+            resolved: None,
+            frameworks,
+            health: None,
+        };
+        let summary = cmd(50, false);
+        let out = snapshot::render(|out, width| summary.write_package_details(out, width, &data));
+        assert_snapshot!("summary", out);
     }
 }
@@ -1,7 +1,8 @@
-use std::collections::HashMap;
+use std::io::{self, Write};
 
-use nu_table::{draw_table, StyledString, Table, TextStyle, Theme};
-use nuget_api::v3::NuGetClient;
+use dotnet_semver::{Range, Version};
+use nuget_api::{v3::NuGetClient, SourceProtocol};
+use term_grid::{Cell, Direction, Filling, Grid, GridOptions};
 use turron_command::{
     async_trait::async_trait,
     clap::{self, Clap},
@@ -10,19 +11,48 @@ use turron_command::{
 };
 use turron_common::{
     chrono::Datelike,
-    chrono_humanize::HumanTime,
     miette::{Context, IntoDiagnostic, Result},
-    serde_json,
+    serde_json, tracing,
 };
-use turron_package_spec::PackageSpec;
 
-use crate::error::ViewError;
+use crate::spec::resolve_spec;
+use crate::suggest::find_package;
+use crate::wait::{version_missing, wait_for_requested};
 
 #[derive(Debug, Clap, TurronConfigLayer)]
 #[config_layer = "view.versions"]
 pub struct VersionsCmd {
     #[clap(about = "Package spec to look up")]
     package: String,
+    #[clap(about = "Only list versions in this range, if the package spec doesn't have one")]
+    version: Option<String>,
+    #[clap(
+        about = "Only list versions in this NuGet range. Same as the positional version",
+        long,
+        conflicts_with = "version"
+    )]
+    range: Option<String>,
+    #[clap(
+        about = "Include prerelease versions (the default)",
+        long,
+        overrides_with = "no-prerelease"
+    )]
+    prerelease: bool,
+    #[clap(
+        about = "Leave out prerelease versions",
+        long,
+        overrides_with = "prerelease"
+    )]
+    no_prerelease: bool,
+    #[clap(about = "Only list the newest N versions", long, value_name = "N")]
+    latest: Option<usize>,
+    #[clap(
+        about = "Order to list versions in",
+        long,
+        default_value = "asc",
+        possible_values = &["asc", "desc"]
+    )]
+    sort: String,
     #[clap(
         about = "Source to view packages from",
         default_value = "https://api.nuget.org/v3/index.json",
@@ -30,6 +60,19 @@ pub struct VersionsCmd {
     )]
     source: String,
     #[clap(from_global)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<SourceProtocol>,
+    #[clap(
+        about = "If the requested version isn't on the source yet, keep checking for this long (e.g. `10m`)",
+        long
+    )]
+    wait: Option<humantime::Duration>,
+    #[clap(
+        about = "Don't look for similarly named packages if this one isn't found",
+        long
+    )]
+    no_suggest: bool,
+    #[clap(from_global)]
     quiet: bool,
     #[clap(from_global)]
     json: bool,
@@ -38,82 +81,280 @@ pub struct VersionsCmd {
 #[async_trait]
 impl TurronCommand for VersionsCmd {
     async fn execute(self) -> Result<()> {
-        let package = self.package.parse()?;
-        let client = NuGetClient::from_source(self.source.clone()).await?;
-        let package_id = if let PackageSpec::NuGet { name, .. } = &package {
-            name
+        let (package_id, requested) = self.spec()?;
+        let client = NuGetClient::from_source_as(
+            self.source.clone(),
+            self.assume_source_version.unwrap_or_default(),
+        )
+        .await?;
+        // A package that's still being indexed would look missing, so
+        // --wait gets a chance first.
+        let package_id = if self.wait.is_some() {
+            package_id
         } else {
-            return Err(ViewError::InvalidPackageSpec.into());
+            find_package(&client, &package_id, self.no_suggest).await?
         };
-        self.print_versions(&client, package_id).await
+        self.print_versions(&client, &package_id, requested.as_ref())
+            .await
     }
 }
 
 impl VersionsCmd {
-    async fn print_versions(&self, client: &NuGetClient, package_id: &str) -> Result<()> {
-        let index = client.registration(package_id).await?;
-        let mut versions = Vec::new();
-        for page in index.items {
-            let page = if page.items.is_some() {
-                page
-            } else {
-                client.registration_page(&page.id).await?
-            };
-            for leaf in page
-                .items
-                .expect("RegistrationPage endpoints must have items!")
-                .into_iter()
-            {
-                versions.push((leaf.catalog_entry.version, leaf.catalog_entry.published));
+    /// The package ID and requested range, from either `<id>@<version>` or
+    /// `<id> <version>`.
+    pub(crate) fn spec(&self) -> Result<(String, Option<Range>)> {
+        resolve_spec(
+            &self.package,
+            self.version.as_deref().or_else(|| self.range.as_deref()),
+        )
+    }
+
+    async fn print_versions(
+        &self,
+        client: &NuGetClient,
+        package_id: &str,
+        requested: Option<&Range>,
+    ) -> Result<()> {
+        if let Some(warning) = semver2_warning(client, &self.source) {
+            tracing::warn!("{}", warning);
+        }
+        if let Some(timeout) = &self.wait {
+            wait_for_requested(client, package_id, requested, timeout).await?;
+        }
+        let mut versions = list_versions(client, package_id).await?;
+        if let Some(requested) = requested {
+            versions.retain(|(version, _)| requested.satisfies(version));
+            if versions.is_empty() && requested.plain_version().is_some() {
+                return Err(version_missing(package_id, requested).into());
             }
         }
-        versions.sort_unstable();
+        let versions = select(
+            versions,
+            self.no_prerelease && !self.prerelease,
+            self.latest,
+            self.sort == "desc",
+        );
         if self.json && !self.quiet {
-            let mut map = HashMap::new();
-            for (version, published) in versions {
-                map.insert(version, published);
-            }
+            let versions = versions
+                .iter()
+                .map(|(version, _)| version.to_string())
+                .collect::<Vec<_>>();
             println!(
                 "{}",
-                serde_json::to_string_pretty(&map)
+                serde_json::to_string_pretty(&versions)
                     .into_diagnostic()
                     .context("Failed to serialize versions back into JSON")?
             );
         } else if !self.quiet {
-            let headers = vec!["version", "published_at"]
-                .iter()
-                .map(|h| StyledString::new(h.to_string(), TextStyle::default_header()))
-                .collect::<Vec<StyledString>>();
-            let rows = versions
-                .iter()
-                .map(|(v, p)| {
-                    vec![
-                        StyledString::new(v.to_string(), TextStyle::basic_left()),
-                        StyledString::new(
-                            p.map(|p| {
-                                if p.year() > 1900 {
-                                    HumanTime::from(p).to_string()
-                                } else {
-                                    "unlisted".into()
-                                }
-                            })
-                            .unwrap_or_else(|| "unlisted".into()),
-                            TextStyle::basic_left(),
-                        ),
-                    ]
-                })
-                .collect::<Vec<Vec<StyledString>>>();
-            let width = if let Some((w, _)) = term_size::dimensions() {
-                w
-            } else {
-                80
-            };
-            let table = Table::new(headers, rows, Theme::rounded());
-            let color_hm: HashMap<String, nu_ansi_term::Style> = HashMap::new();
-            let output_table = draw_table(&table, width, &color_hm);
-            // Draw the table
-            println!("{}", output_table);
+            let width = term_size::dimensions().map(|(w, _)| w).unwrap_or(80);
+            let stdout = io::stdout();
+            write_versions(&mut stdout.lock(), &versions, width)
+                .into_diagnostic()
+                .context("Failed to write versions")?;
         }
         Ok(())
     }
 }
+
+/// A warning for sources whose registration endpoint leaves out SemVer
+/// 2.0.0 packages, if `client`'s does.
+fn semver2_warning(client: &NuGetClient, source: &str) -> Option<String> {
+    if client.endpoints.registration_supports_semver2() {
+        None
+    } else {
+        Some(format!(
+            "{} does not support SemVer 2.0.0 package registrations. Some versions may be missing.",
+            source
+        ))
+    }
+}
+
+/// Every version of `package_id` the source has, and whether it's listed.
+async fn list_versions(client: &NuGetClient, package_id: &str) -> Result<Vec<(Version, bool)>> {
+    Ok(client
+        .registration_leaves(package_id)
+        .await?
+        .into_iter()
+        .map(|leaf| {
+            let listed = leaf
+                .catalog_entry
+                .published
+                .map(|p| p.year() > 1900)
+                .unwrap_or(false);
+            (leaf.catalog_entry.version, listed)
+        })
+        .collect())
+}
+
+/// Writes `versions` as a grid, marking the unlisted ones, or one per line
+/// if they don't fit in `width`.
+fn write_versions(
+    out: &mut impl Write,
+    versions: &[(Version, bool)],
+    width: usize,
+) -> io::Result<()> {
+    let mut grid = Grid::new(GridOptions {
+        filling: Filling::Spaces(3),
+        direction: Direction::TopToBottom,
+    });
+    let vals = versions
+        .iter()
+        .map(|(version, listed)| {
+            if *listed {
+                version.to_string()
+            } else {
+                format!("{} (unlisted)", version)
+            }
+        })
+        .collect::<Vec<_>>();
+    for val in &vals {
+        grid.add(Cell::from(val.as_str()));
+    }
+    if let Some(grid) = grid.fit_into_width(width) {
+        write!(out, "{}", grid)?;
+    } else {
+        // Too wide. Print one per line.
+        for val in &vals {
+            writeln!(out, "{}", val)?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the prerelease filter, `--latest`, and `--sort`. "Newest" goes by
+/// [`Version`] ordering, so `1.0.0.1` comes after `1.0.0`, and `1.10.0`
+/// after `1.9.0`.
+fn select<T>(
+    mut versions: Vec<(Version, T)>,
+    no_prerelease: bool,
+    latest: Option<usize>,
+    descending: bool,
+) -> Vec<(Version, T)> {
+    if no_prerelease {
+        versions.retain(|(version, _)| version.pre_release.is_empty());
+    }
+    versions.sort_by(|(a, _), (b, _)| a.cmp(b));
+    if let Some(latest) = latest {
+        versions.drain(..versions.len().saturating_sub(latest));
+    }
+    if descending {
+        versions.reverse();
+    }
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::{serde_json::json, smol};
+    use turron_testing::{assert_snapshot, fixtures, snapshot, RegistrationBuilder, TestServer};
+
+    use super::*;
+
+    fn versions(vs: &[&str]) -> Vec<(Version, ())> {
+        vs.iter().map(|v| (v.parse().unwrap(), ())).collect()
+    }
+
+    fn strings(vs: Vec<(Version, ())>) -> Vec<String> {
+        vs.into_iter().map(|(v, _)| v.to_string()).collect()
+    }
+
+    #[test]
+    fn sorts_by_version_not_string() {
+        let all = versions(&["1.10.0", "1.0.0.1", "1.9.0", "1.0.0", "2.0.0-beta.1"]);
+        assert_eq!(
+            strings(select(all.clone(), false, None, false)),
+            vec!["1.0.0", "1.0.0.1", "1.9.0", "1.10.0", "2.0.0-beta.1"]
+        );
+        assert_eq!(
+            strings(select(all, false, None, true)),
+            vec!["2.0.0-beta.1", "1.10.0", "1.9.0", "1.0.0.1", "1.0.0"]
+        );
+    }
+
+    #[test]
+    fn filters_prereleases_and_keeps_latest() {
+        let all = versions(&["1.0.0", "1.0.0.1", "1.1.0", "2.0.0-beta.1"]);
+        assert_eq!(
+            strings(select(all.clone(), true, Some(2), false)),
+            vec!["1.0.0.1", "1.1.0"]
+        );
+        assert_eq!(
+            strings(select(all.clone(), false, Some(2), true)),
+            vec!["2.0.0-beta.1", "1.1.0"]
+        );
+        assert_eq!(select(all, false, Some(10), false).len(), 4);
+    }
+
+    #[test]
+    fn renders_a_grid() {
+        let mut listed = fixtures::flatcontainer_versions()["versions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| (v.as_str().unwrap().parse().unwrap(), true))
+            .collect::<Vec<(Version, bool)>>();
+        // 13.0.2-beta1 was pulled.
+        listed[4].1 = false;
+        let out = snapshot::render(|out, width| write_versions(out, &listed, width));
+        assert_snapshot!("versions", out);
+
+        let narrow = snapshot::render(|out, _| write_versions(out, &listed, 10));
+        assert_snapshot!("versions_narrow", narrow);
+    }
+
+    /// Serves a service index with a registration resource for each of
+    /// `restypes`, each listing `Foo` at the versions it's paired with.
+    fn serve_registrations(server: &TestServer, index: &str, restypes: &[(&str, &[&str])]) {
+        let mut resources = Vec::new();
+        for (restype, versions) in restypes {
+            let path = format!("/{}/", restype.replace('/', "-"));
+            resources.push(json!({ "@id": server.url(&path), "@type": restype }));
+            let reg = RegistrationBuilder::new("Foo")
+                .base_url(server.url(&path))
+                .versions(versions.iter().copied())
+                .build();
+            for (url, body) in reg.documents() {
+                server.json(&url[server.base().len()..], &body);
+            }
+        }
+        server.json(
+            index,
+            &json!({ "version": "3.0.0", "resources": resources }),
+        );
+    }
+
+    #[test]
+    fn lists_semver2_only_versions() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            // Older registration resources hide SemVer 2.0.0 versions, like
+            // nuget.org's do.
+            let older: &[(&str, &[&str])] = &[
+                ("RegistrationsBaseUrl/3.4.0", &["1.0.0"]),
+                ("RegistrationsBaseUrl/3.0.0-rc", &["1.0.0"]),
+            ];
+            serve_registrations(&server, "/older/index.json", older);
+            let mut all = older.to_vec();
+            all.push((
+                "RegistrationsBaseUrl/3.6.0",
+                &["1.0.0", "2.0.0-beta.1+sha.abc"],
+            ));
+            serve_registrations(&server, "/semver2/index.json", &all);
+
+            let source = server.url("/semver2/index.json");
+            let client = NuGetClient::from_source(&source).await.unwrap();
+            assert_eq!(semver2_warning(&client, &source), None);
+            let versions = list_versions(&client, "Foo").await.unwrap();
+            let latest = select(versions, false, Some(1), false);
+            assert_eq!(latest[0].0.to_string(), "2.0.0-beta.1+sha.abc");
+
+            let source = server.url("/older/index.json");
+            let client = NuGetClient::from_source(&source).await.unwrap();
+            let warning = semver2_warning(&client, &source).unwrap();
+            assert!(warning.contains("does not support SemVer 2.0.0"));
+            let versions = list_versions(&client, "Foo").await.unwrap();
+            assert_eq!(versions.len(), 1);
+            assert_eq!(server.hits("/RegistrationsBaseUrl-3.4.0/foo/index.json"), 1);
+        });
+    }
+}
@@ -0,0 +1,192 @@
+use std::cmp::Reverse;
+
+use nuget_api::{
+    v3::{NuGetClient, SearchQuery},
+    NuGetApiError,
+};
+use turron_command::{
+    dialoguer::{console, Select},
+    fmt::Numbers,
+};
+use turron_common::{
+    miette::{IntoDiagnostic, Result},
+    smol, tracing,
+};
+
+use crate::error::ViewError;
+
+/// Most candidates offered for an id that doesn't exist.
+const MAX_CANDIDATES: usize = 10;
+
+/// A package id that might be what someone meant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Candidate {
+    pub(crate) id: String,
+    pub(crate) downloads: Option<u64>,
+}
+
+/// Makes sure `package_id` exists on the source. If it doesn't, searches for
+/// similar ids and either asks which one was meant or, when nobody's there
+/// to ask, fails with them listed. `no_suggest` skips all that and fails
+/// straight away.
+pub(crate) async fn find_package(
+    client: &NuGetClient,
+    package_id: &str,
+    no_suggest: bool,
+) -> Result<String> {
+    match client.versions(package_id).await {
+        Ok(_) => return Ok(package_id.into()),
+        Err(NuGetApiError::PackageNotFound) if !no_suggest => {}
+        Err(err) => return Err(err.into()),
+    }
+    let candidates = candidates(client, package_id).await;
+    if candidates.is_empty() || !console::user_attended() {
+        return Err(ViewError::PackageNotFound {
+            id: package_id.into(),
+            candidates: candidates.into_iter().map(|c| c.id).collect(),
+        }
+        .into());
+    }
+    let items = candidates
+        .iter()
+        .map(|candidate| match candidate.downloads {
+            Some(downloads) => format!(
+                "{} ({} downloads)",
+                candidate.id,
+                Numbers::current().count(downloads)
+            ),
+            None => candidate.id.clone(),
+        })
+        .collect::<Vec<_>>();
+    let prompt = format!("{} wasn't found. Did you mean", package_id);
+    let picked = smol::unblock(move || {
+        Select::new()
+            .with_prompt(prompt)
+            .items(&items)
+            .default(0)
+            .interact_opt()
+            .into_diagnostic()
+    })
+    .await?;
+    match picked {
+        Some(index) => Ok(candidates[index].id.clone()),
+        None => Err(ViewError::PackageNotFound {
+            id: package_id.into(),
+            candidates: Vec::new(),
+        }
+        .into()),
+    }
+}
+
+/// Searches the source for ids like `package_id`. A failed search just
+/// means no suggestions.
+async fn candidates(client: &NuGetClient, package_id: &str) -> Vec<Candidate> {
+    let query = SearchQuery {
+        take: Some(MAX_CANDIDATES * 2),
+        ..SearchQuery::from_query(package_id)
+    };
+    match client.search(query).await {
+        Ok(response) => rank(
+            package_id,
+            response
+                .data
+                .into_iter()
+                .map(|result| Candidate {
+                    id: result.id,
+                    downloads: result.total_downloads,
+                })
+                .collect(),
+        ),
+        Err(err) => {
+            tracing::debug!("Couldn't search for packages like {}: {}", package_id, err);
+            Vec::new()
+        }
+    }
+}
+
+/// Orders `candidates` for someone who typed `wanted`: ids equal to it
+/// (ignoring case) first, then ids starting with it, then everything else.
+/// Within each group, more downloads come first. Keeps the first
+/// [`MAX_CANDIDATES`] distinct ids.
+pub(crate) fn rank(wanted: &str, mut candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let wanted = wanted.to_lowercase();
+    candidates.sort_by_key(|candidate| {
+        let id = candidate.id.to_lowercase();
+        let group = if id == wanted {
+            0
+        } else if id.starts_with(&wanted) {
+            1
+        } else {
+            2
+        };
+        (group, Reverse(candidate.downloads))
+    });
+    let mut seen = Vec::new();
+    candidates.retain(|candidate| {
+        let id = candidate.id.to_lowercase();
+        if seen.contains(&id) {
+            false
+        } else {
+            seen.push(id);
+            true
+        }
+    });
+    candidates.truncate(MAX_CANDIDATES);
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: &str, downloads: Option<u64>) -> Candidate {
+        Candidate {
+            id: id.into(),
+            downloads,
+        }
+    }
+
+    fn ids(candidates: &[Candidate]) -> Vec<&str> {
+        candidates.iter().map(|c| &c.id[..]).collect()
+    }
+
+    #[test]
+    fn ranks_exact_then_prefix_then_downloads() {
+        let ranked = rank(
+            "newtonsoft",
+            vec![
+                candidate("Json.Net.Thing", Some(1_000_000)),
+                candidate("Newtonsoft.Json.Bson", Some(10)),
+                candidate("NewtonSoft", Some(1)),
+                candidate("Newtonsoft.Json", Some(100)),
+                candidate("Something.Newtonsoft", None),
+                candidate("Other", Some(5)),
+            ],
+        );
+        assert_eq!(
+            ids(&ranked),
+            vec![
+                "NewtonSoft",
+                "Newtonsoft.Json",
+                "Newtonsoft.Json.Bson",
+                "Json.Net.Thing",
+                "Other",
+                "Something.Newtonsoft",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_ten_distinct_ids() {
+        let many = (0..30)
+            .map(|n| candidate(&format!("Pkg{}", n % 15), Some(n)))
+            .collect::<Vec<_>>();
+        let ranked = rank("pkg", many);
+        assert_eq!(ranked.len(), MAX_CANDIDATES);
+        assert_eq!(ranked[0], candidate("Pkg14", Some(29)));
+        let mut unique = ids(&ranked);
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), MAX_CANDIDATES);
+    }
+}
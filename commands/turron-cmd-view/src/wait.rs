@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use dotnet_semver::Range;
+use nuget_api::v3::NuGetClient;
+use turron_common::{miette::Result, tracing};
+
+use crate::error::ViewError;
+
+/// How often `--wait` checks the source again.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Implements `--wait`: holds off until the version in `requested` shows up
+/// on the source, or `timeout` passes. Giving up isn't an error, since the
+/// lookup that follows will complain about the missing version anyway.
+pub(crate) async fn wait_for_requested(
+    client: &NuGetClient,
+    package_id: &str,
+    requested: Option<&Range>,
+    timeout: &humantime::Duration,
+) -> Result<()> {
+    let version = match requested.and_then(Range::plain_version) {
+        Some(version) => version,
+        None => {
+            tracing::warn!("--wait only applies when asking for a specific version. Ignoring it.");
+            return Ok(());
+        }
+    };
+    if !client
+        .wait_for_version(package_id, version, **timeout, POLL_INTERVAL)
+        .await?
+    {
+        tracing::warn!(
+            "Gave up waiting for {}@{} after {}.",
+            package_id,
+            version,
+            timeout
+        );
+    }
+    Ok(())
+}
+
+/// The error for when `package_id` exists but nothing matches `requested`.
+/// Asking for one specific version usually means it was just published, so
+/// that gets a hint about indexing delays.
+pub(crate) fn version_missing(package_id: &str, requested: &Range) -> ViewError {
+    match requested.plain_version() {
+        Some(version) => ViewError::VersionNotIndexed(package_id.into(), version.clone()),
+        None => ViewError::VersionNotFound(package_id.into(), requested.clone()),
+    }
+}
@@ -14,7 +14,8 @@ use nom::sequence::{preceded, tuple};
 use nom::{Err, IResult};
 
 use turron_common::{
-    miette::{self, Diagnostic},
+    explain::Explanation,
+    miette::{self, Diagnostic, LabeledSpan, SourceCode},
     serde::de::{self, Deserialize, Deserializer, Visitor},
     serde::ser::{Serialize, Serializer},
     thiserror::{self, Error},
@@ -23,10 +24,13 @@ use turron_common::{
 pub use range::Range;
 
 mod range;
+pub mod range_cache;
 
 // from JavaScript: https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Global_Objects/Number/MAX_SAFE_INTEGER
 const MAX_SAFE_INTEGER: u64 = 900_719_925_474_099;
 const MAX_LENGTH: usize = 256;
+/// How much of the input to show on either side of a parse error.
+const SNIPPET_CONTEXT: usize = 40;
 
 #[derive(Debug, Error, Eq, PartialEq)]
 #[error("Error parsing semver string. {kind}")]
@@ -34,9 +38,56 @@ pub struct SemverError {
     input: String,
     offset: usize,
     kind: SemverErrorKind,
+    /// The bit of `input` around the error that gets shown with it.
+    snippet: String,
+    /// Where `snippet` starts in `input`.
+    snippet_start: usize,
 }
 
 impl SemverError {
+    pub(crate) fn new(input: &str, offset: usize, kind: SemverErrorKind) -> Self {
+        let mut offset = cmp::min(offset, input.len());
+        while !input.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let mut err = SemverError {
+            input: input.into(),
+            offset,
+            kind,
+            snippet: String::new(),
+            snippet_start: 0,
+        };
+        // Long inputs get cut down to the error's line, and to a window
+        // around the error within that.
+        let (_, column) = err.location();
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |len| offset + len);
+        let mut start = cmp::max(offset - column, offset.saturating_sub(SNIPPET_CONTEXT));
+        while !input.is_char_boundary(start) {
+            start += 1;
+        }
+        let mut end = cmp::min(line_end, offset + err.token_len() + SNIPPET_CONTEXT);
+        while !input.is_char_boundary(end) {
+            end += 1;
+        }
+        err.snippet = input[start..end].into();
+        err.snippet_start = start;
+        err
+    }
+
+    /// Length of the token the parser tripped over: everything up to the
+    /// next separator, or just the separator if that's where it stopped.
+    fn token_len(&self) -> usize {
+        let is_separator = |c: char| ".-+,[]()|*".contains(c) || c.is_whitespace();
+        let rest = &self.input[self.offset..];
+        match rest.chars().next() {
+            None => 0,
+            Some(c) if is_separator(c) => c.len_utf8(),
+            Some(_) => rest.find(is_separator).unwrap_or(rest.len()),
+        }
+    }
+
     pub fn location(&self) -> (usize, usize) {
         // Taken partially from nom.
         let prefix = &self.input.as_bytes()[..self.offset];
@@ -94,6 +145,62 @@ pub enum SemverErrorKind {
     Other,
 }
 
+impl SemverErrorKind {
+    /// What to point at the error location with.
+    fn label(&self) -> String {
+        match self {
+            SemverErrorKind::MaxLengthError => "too long".into(),
+            SemverErrorKind::IncompleteInput => "input ends here".into(),
+            SemverErrorKind::ParseIntError(_) => "expected a number here".into(),
+            SemverErrorKind::MaxIntError(_) => "number too large".into(),
+            SemverErrorKind::Context(ctx) => format!("invalid {}", ctx),
+            SemverErrorKind::Other => "unexpected input".into(),
+        }
+    }
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::semver::input_too_long",
+        cause: "The version or version range was longer than 256 characters. Nothing that long is a real version, so the parser gives up early.",
+        fixes: &["Check for a stray value pasted into the version argument."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::semver::incomplete_input",
+        cause: "The version string ended before the parser expected it to, like `1.2.` or `[1.0,`.",
+        fixes: &["Finish the version, or close the range bracket."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::semver::integer_parse_error",
+        cause: "One of the numeric components of the version could not be read as an integer.",
+        fixes: &["Versions are dot-separated numbers, like `1.2.3` or `1.2.3.4`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::semver::integer_too_large",
+        cause: "A numeric component of the version is too big to handle safely. NuGet itself would reject it too.",
+        fixes: &["Use a smaller number. Date-like versions should be split across components, e.g. `2021.10.17`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::semver::component_parse_error",
+        cause: "A specific part of the version, such as the pre-release tag or a range bracket, was malformed. The error names the part that failed.",
+        fixes: &[
+            "Pre-release tags may only contain ASCII letters, digits, `-`, and `.`.",
+            "Ranges use NuGet's interval notation, e.g. `[1.0,2.0)`.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::semver::other",
+        cause: "The version parser failed without saying why.",
+        fixes: &["Please report the input that caused it, since this error should be more specific."],
+        config: &[],
+    },
+];
+
 #[derive(Debug)]
 struct SemverParseError<I> {
     input: I,
@@ -110,8 +217,20 @@ impl Diagnostic for SemverError {
         self.kind.help()
     }
 
-    fn labels(&self) -> Option<Box<dyn Iterator<Item = turron_common::miette::LabeledSpan> + '_>> {
-        self.kind.labels()
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.snippet)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let len = match self.kind {
+            SemverErrorKind::MaxLengthError => self.snippet.len(),
+            _ => self.token_len(),
+        };
+        Some(Box::new(std::iter::once(LabeledSpan::new(
+            Some(self.kind.label()),
+            self.offset - self.snippet_start,
+            len,
+        ))))
     }
 }
 
@@ -228,35 +347,81 @@ impl Version {
         let input = input.as_ref();
 
         if input.len() > MAX_LENGTH {
-            return Err(SemverError {
-                input: input.into(),
-                offset: 0,
-                kind: SemverErrorKind::MaxLengthError,
-            });
+            return Err(SemverError::new(input, 0, SemverErrorKind::MaxLengthError));
         }
 
         match all_consuming(version)(input) {
             Ok((_, arg)) => Ok(arg),
             Err(err) => Err(match err {
-                Err::Error(e) | Err::Failure(e) => SemverError {
-                    input: input.into(),
-                    offset: e.input.as_ptr() as usize - input.as_ptr() as usize,
-                    kind: if let Some(kind) = e.kind {
+                Err::Error(e) | Err::Failure(e) => SemverError::new(
+                    input,
+                    e.input.as_ptr() as usize - input.as_ptr() as usize,
+                    if let Some(kind) = e.kind {
                         kind
                     } else if let Some(ctx) = e.context {
                         SemverErrorKind::Context(ctx)
                     } else {
                         SemverErrorKind::Other
                     },
-                },
-                Err::Incomplete(_) => SemverError {
-                    input: input.into(),
-                    offset: input.len() - 1,
-                    kind: SemverErrorKind::IncompleteInput,
-                },
+                ),
+                Err::Incomplete(_) => SemverError::new(
+                    input,
+                    input.len().saturating_sub(1),
+                    SemverErrorKind::IncompleteInput,
+                ),
             }),
         }
     }
+
+    /// Whether this version has a pre-release tag, like `1.0.0-beta`.
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre_release.is_empty()
+    }
+
+    /// Just the numbers: this version without its pre-release tag or build
+    /// metadata.
+    pub fn core(&self) -> Version {
+        (self.major, self.minor, self.patch, self.revision).into()
+    }
+
+    /// The next major version. Everything after the major number is reset,
+    /// so `1.2.3-beta+5` becomes `2.0.0`.
+    pub fn increment_major(&self) -> Version {
+        (self.major + 1, 0, 0, 0).into()
+    }
+
+    /// The next minor version, with patch and revision reset and no
+    /// pre-release tag or build metadata.
+    pub fn increment_minor(&self) -> Version {
+        (self.major, self.minor + 1, 0, 0).into()
+    }
+
+    /// The next patch version, with revision reset and no pre-release tag or
+    /// build metadata.
+    pub fn increment_patch(&self) -> Version {
+        (self.major, self.minor, self.patch + 1, 0).into()
+    }
+
+    /// The next revision, with no pre-release tag or build metadata.
+    pub fn increment_revision(&self) -> Version {
+        (self.major, self.minor, self.patch, self.revision + 1).into()
+    }
+
+    /// This version with its pre-release tag replaced by `ids`. Build
+    /// metadata describes a particular build of the old version, so it's
+    /// dropped.
+    pub fn with_pre_release(&self, ids: Vec<Identifier>) -> Version {
+        Version {
+            pre_release: ids,
+            ..self.core()
+        }
+    }
+
+    /// The release this version is a pre-release of, without build metadata.
+    /// Same as [`Version::core`].
+    pub fn without_pre_release(&self) -> Version {
+        self.core()
+    }
 }
 
 impl PartialEq for Version {
@@ -580,6 +745,7 @@ mod tests {
 
     use pretty_assertions::assert_eq;
     use serde_derive::{Deserialize, Serialize};
+    use turron_common::miette::{GraphicalReportHandler, GraphicalTheme};
 
     #[test]
     fn trivial_version_number() {
@@ -845,11 +1011,104 @@ mod tests {
         assert!(v.is_ok());
     }
 
+    /// Renders `err` like a terminal would, and checks that the highlight
+    /// starts right under the character `offset` bytes into the input.
+    fn assert_highlighted_at(err: &SemverError, offset: usize, label: &str) {
+        assert_eq!(err.offset, offset);
+        let mut out = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut out, err)
+            .unwrap();
+        assert!(out.contains(label), "missing {:?} in:\n{}", label, out);
+
+        let lines = out.lines().collect::<Vec<_>>();
+        let source = lines
+            .iter()
+            .position(|line| line.trim_end().ends_with(&err.snippet))
+            .expect("the snippet should be in the output");
+        let snippet_column = lines[source].trim_end().chars().count() - err.snippet.chars().count();
+        let column = snippet_column + err.input[err.snippet_start..offset].chars().count();
+        let highlight = lines[source + 1].chars().collect::<Vec<_>>();
+        assert!(
+            !highlight[column].is_whitespace() && highlight[column - 1].is_whitespace(),
+            "highlight should start at column {} in:\n{}",
+            column,
+            out
+        );
+    }
+
+    #[test]
+    fn error_snippets_point_at_the_problem() {
+        let err = Version::parse("1.2.x").unwrap_err();
+        assert_eq!(err.snippet, "1.2.x");
+        assert_highlighted_at(&err, 4, "invalid version");
+
+        let err = Version::parse("1.2.900719925474100").unwrap_err();
+        assert_eq!(err.token_len(), 15);
+        assert_highlighted_at(&err, 4, "number too large");
+
+        let err = Version::parse("1.0.0-beta.$").unwrap_err();
+        assert_highlighted_at(&err, 11, "unexpected input");
+    }
+
+    #[test]
+    fn error_snippets_are_windowed() {
+        let input = format!("1.2.3-{}.$", "a".repeat(60));
+        let err = Version::parse(&input).unwrap_err();
+        assert_eq!(err.snippet_start, input.len() - 1 - SNIPPET_CONTEXT);
+        assert_eq!(
+            err.snippet,
+            format!("{}.$", "a".repeat(SNIPPET_CONTEXT - 1))
+        );
+        assert_highlighted_at(&err, input.len() - 1, "unexpected input");
+
+        let input = "[1.0.0,2.0.0)||[3.0.0,4.0.0)||[5.0.0,6.0.0)||[7.0.0,8.0.0)||?";
+        let err = Range::parse(input).unwrap_err();
+        assert!(err.snippet.len() < input.len());
+        assert_highlighted_at(&err, err.offset, &err.kind.label());
+    }
+
     #[derive(Serialize, Deserialize, Eq, PartialEq)]
     struct Versioned {
         version: Version,
     }
 
+    #[test]
+    fn bumping_versions() {
+        let bump = |v: &str, f: fn(&Version) -> Version| f(&Version::parse(v).unwrap()).to_string();
+
+        assert_eq!(bump("1.2.3-beta+5", Version::increment_major), "2.0.0");
+        assert_eq!(bump("1.2.3.4", Version::increment_major), "2.0.0");
+        assert_eq!(bump("1.2.3.4", Version::increment_minor), "1.3.0");
+        assert_eq!(bump("1.2.3.4", Version::increment_patch), "1.2.4");
+        assert_eq!(bump("1.2.3", Version::increment_revision), "1.2.3.1");
+        assert_eq!(bump("1.2.3.4-rc.1", Version::increment_revision), "1.2.3.5");
+        assert_eq!(bump("1.2.3-rc.1+abc", Version::increment_patch), "1.2.4");
+    }
+
+    #[test]
+    fn changing_pre_releases() {
+        let v = Version::parse("1.2.3.4-alpha.1+build.5").unwrap();
+        assert!(v.is_prerelease());
+        assert_eq!(v.core().to_string(), "1.2.3.4");
+        assert!(!v.core().is_prerelease());
+        assert!(v.core().build.is_empty());
+        assert_eq!(v.without_pre_release(), v.core());
+        assert_eq!(
+            v.with_pre_release(vec![AlphaNumeric("beta".into()), Numeric(2)])
+                .to_string(),
+            "1.2.3.4-beta.2"
+        );
+        assert_eq!(
+            Version::parse("1.0.0")
+                .unwrap()
+                .with_pre_release(vec![AlphaNumeric("rc".into())])
+                .to_string(),
+            "1.0.0-rc"
+        );
+        assert!(!Version::parse("1.0.0+build").unwrap().is_prerelease());
+    }
+
     #[test]
     fn read_version_from_string() {
         let v: Versioned = serde_json::from_str(r#"{"version":"1.2.34-abc.213+2"}"#).unwrap();
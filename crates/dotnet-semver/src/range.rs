@@ -12,7 +12,7 @@ use nom::{Err, IResult};
 use serde::de::{self, Deserialize, Deserializer, Visitor};
 use serde::ser::{Serialize, Serializer};
 
-use crate::{extras, number, SemverError, SemverErrorKind, SemverParseError, Version};
+use crate::{extras, number, Identifier, SemverError, SemverErrorKind, SemverParseError, Version};
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 struct ComparatorSet {
@@ -173,6 +173,28 @@ impl ComparatorSet {
             Some(vec![self.clone()])
         }
     }
+
+    /// Merges `other` into `self` if the two sets overlap or are directly
+    /// adjacent. Expects `self.lower <= other.lower`.
+    fn union(&self, other: &Self) -> Option<Self> {
+        use Bound::*;
+        use Predicate::*;
+
+        let touching = match (&self.upper, &other.lower) {
+            (Upper(Excluding(v1)), Lower(Including(v2))) => v1 == v2,
+            _ => false,
+        };
+
+        if other.lower <= self.upper || touching {
+            ComparatorSet::new(
+                self.lower.clone(),
+                std::cmp::max(&self.upper, &other.upper).clone(),
+                self.floating || other.floating,
+            )
+        } else {
+            None
+        }
+    }
 }
 
 impl fmt::Display for ComparatorSet {
@@ -313,24 +335,25 @@ impl Range {
         match all_consuming(range)(input) {
             Ok((_, predicates)) => Ok(Range {
                 comparators: predicates,
-            }),
+            }
+            .normalize()),
             Err(err) => Err(match err {
-                Err::Error(e) | Err::Failure(e) => SemverError {
-                    input: input.into(),
-                    offset: e.input.as_ptr() as usize - input.as_ptr() as usize,
-                    kind: if let Some(kind) = e.kind {
+                Err::Error(e) | Err::Failure(e) => SemverError::new(
+                    input,
+                    e.input.as_ptr() as usize - input.as_ptr() as usize,
+                    if let Some(kind) = e.kind {
                         kind
                     } else if let Some(ctx) = e.context {
                         SemverErrorKind::Context(ctx)
                     } else {
                         SemverErrorKind::Other
                     },
-                },
-                Err::Incomplete(_) => SemverError {
-                    input: input.into(),
-                    offset: input.len() - 1,
-                    kind: SemverErrorKind::IncompleteInput,
-                },
+                ),
+                Err::Incomplete(_) => SemverError::new(
+                    input,
+                    input.len().saturating_sub(1),
+                    SemverErrorKind::IncompleteInput,
+                ),
             }),
         }
     }
@@ -355,6 +378,64 @@ impl Range {
         self.comparators.iter().any(|pred| pred.has_pre())
     }
 
+    /// The version in a range written as a plain version (`1.2.3`, meaning
+    /// at least 1.2.3) or an exact one (`[1.2.3]`). Anything else gives
+    /// `None`.
+    pub fn plain_version(&self) -> Option<&Version> {
+        use Bound::*;
+        use Predicate::*;
+        match &self.comparators[..] {
+            [ComparatorSet {
+                floating: false,
+                lower: Lower(Including(v)),
+                upper: Upper(Unbounded),
+            }] => Some(v),
+            [ComparatorSet {
+                floating: false,
+                lower: Lower(Including(v)),
+                upper: Upper(Including(v2)),
+            }] if v == v2 => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The lowest version this range accepts, which is what NuGet resolves
+    /// to. An exclusive bound is stepped past by the smallest amount that
+    /// still reads like a version: a `.0` on the end of a pre-release, or
+    /// the next revision otherwise. `None` if the range has no lower bound.
+    pub fn min_version(&self) -> Option<Version> {
+        use Bound::*;
+        use Predicate::*;
+        self.comparators
+            .iter()
+            .map(|set| match &set.lower {
+                Lower(Including(v)) => Some(v.clone()),
+                Lower(Excluding(v)) => Some(step_up(v)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min()
+    }
+
+    /// The highest version this range accepts. An exclusive bound is
+    /// stepped under with a pre-release, so `(,2.0.0)` gives `2.0.0-0`.
+    /// `None` if the range has no upper bound.
+    pub fn max_version(&self) -> Option<Version> {
+        use Bound::*;
+        use Predicate::*;
+        self.comparators
+            .iter()
+            .map(|set| match &set.upper {
+                Upper(Including(v)) => Some(v.clone()),
+                Upper(Excluding(v)) => Some(step_down(v)),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .max()
+    }
+
     pub fn satisfies(&self, version: &Version) -> bool {
         for range in &self.comparators {
             if range.satisfies(version) {
@@ -389,6 +470,29 @@ impl Range {
         false
     }
 
+    /// Puts this range in canonical form: empty comparator sets are dropped,
+    /// overlapping or adjacent ones are merged, and the rest are sorted by
+    /// their bounds. Semantically equal ranges normalize to equal values.
+    pub fn normalize(self) -> Self {
+        let mut sets = self
+            .comparators
+            .into_iter()
+            .filter_map(|set| ComparatorSet::new(set.lower, set.upper, set.floating))
+            .collect::<Vec<_>>();
+        sets.sort_by(|a, b| a.lower.cmp(&b.lower).then_with(|| a.upper.cmp(&b.upper)));
+
+        let mut comparators: Vec<ComparatorSet> = Vec::with_capacity(sets.len());
+        for set in sets {
+            if let Some(merged) = comparators.last().and_then(|last| last.union(&set)) {
+                *comparators.last_mut().unwrap() = merged;
+            } else {
+                comparators.push(set);
+            }
+        }
+
+        Self { comparators }
+    }
+
     pub fn intersect(&self, other: &Self) -> Option<Self> {
         let mut predicates = Vec::new();
 
@@ -403,9 +507,12 @@ impl Range {
         if predicates.is_empty() {
             None
         } else {
-            Some(Self {
-                comparators: predicates,
-            })
+            Some(
+                Self {
+                    comparators: predicates,
+                }
+                .normalize(),
+            )
         }
     }
 
@@ -423,13 +530,79 @@ impl Range {
         if predicates.is_empty() {
             None
         } else {
-            Some(Self {
-                comparators: predicates,
-            })
+            Some(
+                Self {
+                    comparators: predicates,
+                }
+                .normalize(),
+            )
+        }
+    }
+
+    /// Every version allowed by either range. Overlapping and adjacent sets
+    /// are merged, so the result is as few `||` alternatives as possible. A
+    /// merged set is floating if anything that went into it was.
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            comparators: self
+                .comparators
+                .iter()
+                .chain(&other.comparators)
+                .cloned()
+                .collect(),
         }
+        .normalize()
     }
 }
 
+/// The closest version above `version` worth naming.
+fn step_up(version: &Version) -> Version {
+    let mut above = Version {
+        build: Vec::new(),
+        ..version.clone()
+    };
+    if above.pre_release.is_empty() {
+        above.revision += 1;
+    } else {
+        above.pre_release.push(Identifier::Numeric(0));
+    }
+    above
+}
+
+/// The closest version below `version` worth naming.
+fn step_down(version: &Version) -> Version {
+    let mut below = Version {
+        build: Vec::new(),
+        ..version.clone()
+    };
+    match below.pre_release.last().cloned() {
+        None => below.pre_release.push(Identifier::Numeric(0)),
+        Some(Identifier::Numeric(n)) if n > 0 => {
+            below.pre_release.pop();
+            below.pre_release.push(Identifier::Numeric(n - 1));
+        }
+        Some(_) if below.pre_release.len() > 1 => {
+            below.pre_release.pop();
+        }
+        Some(Identifier::AlphaNumeric(_)) => below.pre_release = vec![Identifier::Numeric(0)],
+        // `-0` is as low as pre-releases go, so fall back to the release
+        // before this one.
+        Some(Identifier::Numeric(_)) => {
+            below.pre_release.clear();
+            if below.revision > 0 {
+                below.revision -= 1;
+            } else if below.patch > 0 {
+                below.patch -= 1;
+            } else if below.minor > 0 {
+                below.minor -= 1;
+            } else {
+                below.major = below.major.saturating_sub(1);
+            }
+        }
+    }
+    below
+}
+
 impl std::str::FromStr for Range {
     type Err = SemverError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -466,7 +639,7 @@ impl<'de> Deserialize<'de> for Range {
             where
                 E: de::Error,
             {
-                Range::parse(v).map_err(de::Error::custom)
+                crate::range_cache::parse(v).map_err(de::Error::custom)
             }
         }
 
@@ -851,6 +1024,298 @@ mod parser_tests {
         assert!(range.satisfies(&version));
         Ok(())
     }
+
+    #[test]
+    fn plain_version() -> Result<(), SemverError> {
+        let version: Version = "1.2.3".parse()?;
+        for plain in &["1.2.3", "[1.2.3]", "[1.2.3,)"] {
+            assert_eq!(plain.parse::<Range>()?.plain_version(), Some(&version));
+        }
+        for other in &[
+            "1.2.*",
+            "(1.2.3,)",
+            "[1.2.3,2.0.0)",
+            "*",
+            "[1.0.0] || [1.2.3]",
+        ] {
+            assert_eq!(other.parse::<Range>()?.plain_version(), None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn min_and_max_versions() -> Result<(), SemverError> {
+        let bounds = |range: &str| -> Result<(Option<String>, Option<String>), SemverError> {
+            let range: Range = range.parse()?;
+            let min = range.min_version();
+            let max = range.max_version();
+            for v in min.iter().chain(max.iter()) {
+                assert!(range.satisfies(v), "{} should satisfy {}", v, range);
+            }
+            Ok((min.map(|v| v.to_string()), max.map(|v| v.to_string())))
+        };
+        let some = |v: &str| Some(v.to_string());
+
+        assert_eq!(bounds("[1.2.3,)")?, (some("1.2.3"), None));
+        assert_eq!(bounds("1.2.3")?, (some("1.2.3"), None));
+        assert_eq!(bounds("(1.2.3,2.0.0)")?, (some("1.2.3.1"), some("2.0.0-0")));
+        assert_eq!(bounds("[1.2.3]")?, (some("1.2.3"), some("1.2.3")));
+        assert_eq!(bounds("*")?, (None, None));
+        assert_eq!(bounds("(,1.5]")?, (None, some("1.5.0")));
+        assert_eq!(
+            bounds("(1.0.0-beta,2.0.0-rc.2)")?,
+            (some("1.0.0-beta.0"), some("2.0.0-rc.1"))
+        );
+        assert_eq!(bounds("(,2.0.0-rc)")?, (None, some("2.0.0-0")));
+        assert_eq!(bounds("(,2.0.0-rc.0)")?, (None, some("2.0.0-rc")));
+        assert_eq!(bounds("(,2.0.0-0)")?, (None, some("1.0.0")));
+
+        // Multiple sets give the lowest and highest of all of them.
+        assert_eq!(
+            bounds("[3.0,4.0] || [1.0,1.5)")?,
+            (some("1.0.0"), some("4.0.0"))
+        );
+        assert_eq!(bounds("[1.0,1.5) || (2.0,)")?, (some("1.0.0"), None));
+        assert_eq!(bounds("(,1.0] || [2.0,3.0)")?, (None, some("3.0.0-0")));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod normalization {
+    use super::*;
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    const RANGES: &[&str] = &[
+        "*",
+        "1.0",
+        "1.*",
+        "[1.0,2.0)",
+        "[1.5,2.0)",
+        "[1.0,1.5)",
+        "(1.0,2.0]",
+        "[2.0]",
+        "(,1.5]",
+        "[3.0,)",
+        "[1.0,2.0) || [1.5,2.0)",
+        "[1.0,1.5) || [3.0,4.0)",
+        "(,1.0) || (1.0,)",
+        "[1.0.0-alpha,1.0.0]",
+    ];
+
+    const VERSIONS: &[&str] = &[
+        "0.0.0",
+        "0.9.0",
+        "1.0.0-alpha",
+        "1.0.0-beta",
+        "1.0.0",
+        "1.0.1",
+        "1.4.9",
+        "1.5.0",
+        "1.9.9",
+        "2.0.0-rc.1",
+        "2.0.0",
+        "2.0.1",
+        "2.5.0",
+        "3.0.0",
+        "3.9.9",
+        "4.0.0",
+        "10.0.0",
+    ];
+
+    fn r(range: &str) -> Range {
+        range.parse().unwrap()
+    }
+
+    fn versions() -> Vec<Version> {
+        VERSIONS.iter().map(|v| v.parse().unwrap()).collect()
+    }
+
+    fn hash(range: &Range) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        range.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn merges_overlapping_sets() {
+        assert_eq!(r("[1.0,2.0) || [1.5,2.0)").to_string(), "[1.0.0,2.0.0)");
+        assert_eq!(r("[1.0,1.8) || [1.5,2.0]").to_string(), "[1.0.0,2.0.0]");
+        assert_eq!(r("[1.0,2.0) || [1.0,2.0)").to_string(), "[1.0.0,2.0.0)");
+    }
+
+    #[test]
+    fn merges_adjacent_sets() {
+        assert_eq!(r("[1.0,1.5) || [1.5,2.0)").to_string(), "[1.0.0,2.0.0)");
+        assert_eq!(r("[1.0,1.5] || (1.5,2.0)").to_string(), "[1.0.0,2.0.0)");
+        assert_eq!(r("(,1.0) || [1.0]").to_string(), "(,1.0.0]");
+        assert_eq!(r("(,1.0) || (1.0,)").to_string(), "(,1.0.0)||(1.0.0,)");
+    }
+
+    #[test]
+    fn sorts_disjoint_sets() {
+        assert_eq!(
+            r("[3.0,4.0) || [1.0,2.0)").to_string(),
+            "[1.0.0,2.0.0)||[3.0.0,4.0.0)"
+        );
+    }
+
+    #[test]
+    fn equal_ranges_compare_and_hash_equal() {
+        let a = r("[1.5,2.0) || [1.0,1.5)");
+        let b = r("[1.0,2.0)");
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+    }
+
+    #[test]
+    fn intersection_does_not_duplicate_sets() {
+        let a = r("[1.0,2.0) || [1.5,3.0)");
+        let b = r("[1.2,2.5) || [1.8,2.2)");
+        assert_eq!(a.intersect(&b).unwrap().to_string(), "[1.2.0,2.5.0)");
+
+        let a = r("[1.0,2.0) || [3.0,4.0)");
+        let b = r("[1.5,3.5)");
+        assert_eq!(
+            a.intersect(&b).unwrap().to_string(),
+            "[1.5.0,2.0.0)||[3.0.0,3.5.0)"
+        );
+    }
+
+    #[test]
+    fn intersection_satisfies_both_inputs() {
+        let versions = versions();
+        for a in RANGES.iter().map(|a| r(a)) {
+            for b in RANGES.iter().map(|b| r(b)) {
+                // The raw pairwise intersections, as they were before
+                // normalization got a chance to touch them.
+                let raw = Range {
+                    comparators: a
+                        .comparators
+                        .iter()
+                        .flat_map(|x| b.comparators.iter().filter_map(move |y| x.intersect(y)))
+                        .collect(),
+                };
+                let normalized = a.intersect(&b);
+                for version in &versions {
+                    let expected = a.satisfies(version) && b.satisfies(version);
+                    assert_eq!(
+                        raw.satisfies(version),
+                        expected,
+                        "raw {} ∩ {} on {}",
+                        a,
+                        b,
+                        version
+                    );
+                    assert_eq!(
+                        normalized.as_ref().map_or(false, |n| n.satisfies(version)),
+                        expected,
+                        "{} ∩ {} on {}",
+                        a,
+                        b,
+                        version
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normalization_preserves_satisfies() {
+        let versions = versions();
+        for a in RANGES {
+            for b in RANGES {
+                let input = format!("{} || {}", a, b);
+                let raw = Range {
+                    comparators: all_consuming(range)(&input[..]).unwrap().1,
+                };
+                let normalized = raw.clone().normalize();
+                assert_eq!(normalized.clone().normalize(), normalized);
+                for version in &versions {
+                    assert_eq!(
+                        raw.satisfies(version),
+                        normalized.satisfies(version),
+                        "{} vs {} on {}",
+                        raw,
+                        normalized,
+                        version
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn union_merges_overlapping_and_adjacent_sets() {
+        assert_eq!(
+            r("[1.0,2.0)").union(&r("[1.5,3.0)")).to_string(),
+            "[1.0.0,3.0.0)"
+        );
+        assert_eq!(
+            r("[1.0,2.0)").union(&r("[2.0,3.0)")).to_string(),
+            "[1.0.0,3.0.0)"
+        );
+        assert_eq!(
+            r("[3.0,4.0)").union(&r("[1.0,2.0)")).to_string(),
+            "[1.0.0,2.0.0)||[3.0.0,4.0.0)"
+        );
+        assert_eq!(
+            r("[1.0,1.5) || [3.0,4.0)")
+                .union(&r("[1.2,3.5)"))
+                .to_string(),
+            "[1.0.0,4.0.0)"
+        );
+    }
+
+    #[test]
+    fn union_collapsing_to_any() {
+        assert_eq!(r("(,1.0]").union(&r("(1.0,)")).to_string(), "*");
+        assert_eq!(r("[1.0,2.0)").union(&r("*")).to_string(), "*");
+        assert_eq!(r("(,1.0) || (1.0,)").union(&r("[1.0]")).to_string(), "*");
+    }
+
+    #[test]
+    fn union_of_exact_versions() {
+        assert_eq!(r("[1.0]").union(&r("[1.0]")).to_string(), "[1.0.0]");
+        assert_eq!(
+            r("[2.0]").union(&r("[1.0]")).to_string(),
+            "[1.0.0]||[2.0.0]"
+        );
+        assert_eq!(
+            r("[1.0]").union(&r("(1.0,2.0)")).to_string(),
+            "[1.0.0,2.0.0)"
+        );
+    }
+
+    #[test]
+    fn union_keeps_floating() {
+        let merged = r("1.*").union(&r("[1.5,3.0)"));
+        assert!(merged.is_floating());
+        assert!(!r("[1.0,2.0)").union(&r("[1.5,3.0)")).is_floating());
+    }
+
+    #[test]
+    fn union_satisfies_either_input() {
+        let versions = versions();
+        for a in RANGES.iter().map(|a| r(a)) {
+            for b in RANGES.iter().map(|b| r(b)) {
+                let union = a.union(&b);
+                assert_eq!(union, b.union(&a), "{} ∪ {} is commutative", a, b);
+                for version in &versions {
+                    assert_eq!(
+                        union.satisfies(version),
+                        a.satisfies(version) || b.satisfies(version),
+                        "{} ∪ {} on {}",
+                        a,
+                        b,
+                        version
+                    );
+                }
+            }
+        }
+    }
 }
 
 /*
@@ -0,0 +1,145 @@
+//! Remembers recently parsed [`Range`]s, so deserializing the same range
+//! string over and over (every leaf of a registration tends to depend on
+//! `[1.0.0, )` or similar) only parses it once.
+//!
+//! Each thread has its own small LRU cache, so deserializing on several
+//! executor threads at once never contends on a lock. Set
+//! `TURRON_NO_RANGE_CACHE` to turn caching off when debugging the parser.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+
+use crate::{Range, SemverError};
+
+/// How many distinct range strings each thread remembers.
+pub const CAPACITY: usize = 512;
+
+/// Turns the cache off for every thread when set to anything but empty.
+pub const DISABLE_VAR: &str = "TURRON_NO_RANGE_CACHE";
+
+thread_local! {
+    static CACHE: RefCell<Option<RangeCache>> = RefCell::new(
+        if env::var_os(DISABLE_VAR).map_or(true, |val| val.is_empty()) {
+            Some(RangeCache::new(CAPACITY))
+        } else {
+            None
+        }
+    );
+}
+
+/// Turns caching on or off for the current thread. It starts out on unless
+/// [`DISABLE_VAR`] is set.
+pub fn set_enabled(enabled: bool) {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        match (enabled, cache.is_some()) {
+            (true, false) => *cache = Some(RangeCache::new(CAPACITY)),
+            (false, true) => *cache = None,
+            _ => {}
+        }
+    })
+}
+
+/// [`Range::parse`], but reusing the result of parsing `input` before, if
+/// this thread did so recently. Errors aren't cached.
+///
+/// Use this instead of [`Range::parse`] anywhere the same ranges come up
+/// over and over, like dependencies in registration leaves.
+pub fn parse(input: &str) -> Result<Range, SemverError> {
+    CACHE.with(|cache| match &mut *cache.borrow_mut() {
+        Some(cache) => cache.get_or_parse(input),
+        None => Range::parse(input),
+    })
+}
+
+#[derive(Debug)]
+struct RangeCache {
+    capacity: usize,
+    /// Bumped on every lookup, to tell which entry was used longest ago.
+    clock: u64,
+    entries: HashMap<Box<str>, (Range, u64)>,
+}
+
+impl RangeCache {
+    fn new(capacity: usize) -> Self {
+        RangeCache {
+            capacity: capacity.max(1),
+            clock: 0,
+            entries: HashMap::with_capacity(capacity),
+        }
+    }
+
+    fn get_or_parse(&mut self, input: &str) -> Result<Range, SemverError> {
+        self.clock += 1;
+        if let Some((range, used)) = self.entries.get_mut(input) {
+            *used = self.clock;
+            return Ok(range.clone());
+        }
+        let range = Range::parse(input)?;
+        if self.entries.len() >= self.capacity {
+            // Finding the oldest entry is linear, but it only happens on a
+            // miss, next to a parse that costs far more.
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries
+            .insert(input.into(), (range.clone(), self.clock));
+        Ok(range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_parsed_ranges() {
+        let mut cache = RangeCache::new(2);
+        let range = cache.get_or_parse("[1.0.0, )").unwrap();
+        assert_eq!(range, Range::parse("[1.0.0, )").unwrap());
+        assert_eq!(cache.get_or_parse("[1.0.0, )").unwrap(), range);
+        assert_eq!(cache.entries.len(), 1);
+        assert!(cache.get_or_parse("nope").is_err());
+        assert_eq!(cache.entries.len(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = RangeCache::new(2);
+        cache.get_or_parse("1.0").unwrap();
+        cache.get_or_parse("2.0").unwrap();
+        cache.get_or_parse("1.0").unwrap();
+        cache.get_or_parse("3.0").unwrap();
+        assert_eq!(cache.entries.len(), 2);
+        assert!(cache.entries.contains_key("1.0"));
+        assert!(cache.entries.contains_key("3.0"));
+    }
+
+    #[test]
+    fn can_be_turned_off() {
+        // Tests run on their own threads, so this doesn't leak into others.
+        set_enabled(false);
+        assert_eq!(
+            parse("[1.0.0, )").unwrap(),
+            Range::parse("[1.0.0, )").unwrap()
+        );
+        CACHE.with(|cache| assert!(cache.borrow().is_none()));
+        set_enabled(true);
+        parse("[1.0.0, )").unwrap();
+        CACHE.with(|cache| {
+            assert!(cache
+                .borrow()
+                .as_ref()
+                .unwrap()
+                .entries
+                .contains_key("[1.0.0, )"))
+        });
+    }
+}
@@ -0,0 +1,46 @@
+//! How long it takes to deserialize a big registration index, with and
+//! without [`dotnet_semver::range_cache`].
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nuget_api::v3::RegistrationIndex;
+use turron_common::serde_json;
+use turron_testing::RegistrationBuilder;
+
+/// Roughly the shape of a long-lived package on nuget.org: lots of versions,
+/// several target frameworks, and the same handful of dependency ranges on
+/// every leaf.
+fn big_registration() -> String {
+    let mut builder = RegistrationBuilder::new("Turron.Bench")
+        .leaves(2_000)
+        .page_size(2_000);
+    for framework in &["net6.0", "netstandard2.0", "net48"] {
+        for (id, range) in &[
+            ("Microsoft.Extensions.Logging", "[6.0.0, )"),
+            ("Microsoft.Extensions.Options", "[6.0.0, )"),
+            ("Newtonsoft.Json", "[13.0.1, )"),
+            ("System.Memory", "[4.5.4, )"),
+            ("System.Text.Json", "[6.0.0, 7.0.0)"),
+        ] {
+            builder = builder.dependency(Some(framework), *id, *range);
+        }
+    }
+    serde_json::to_string(&builder.build().index).unwrap()
+}
+
+fn deserialize(c: &mut Criterion) {
+    let json = big_registration();
+    let mut group = c.benchmark_group("registration index");
+    group.sample_size(20);
+    dotnet_semver::range_cache::set_enabled(true);
+    group.bench_function("cached ranges", |b| {
+        b.iter(|| serde_json::from_str::<RegistrationIndex>(black_box(&json)).unwrap())
+    });
+    dotnet_semver::range_cache::set_enabled(false);
+    group.bench_function("uncached ranges", |b| {
+        b.iter(|| serde_json::from_str::<RegistrationIndex>(black_box(&json)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, deserialize);
+criterion_main!(benches);
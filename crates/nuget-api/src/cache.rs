@@ -0,0 +1,519 @@
+//! An opt-in, on-disk cache for GET responses.
+//!
+//! Bodies are stored by the SHA-256 of their contents, under `content/`, and
+//! each cached URL gets a small JSON entry under `index/` pointing at one.
+//! Versioned .nupkg and .nuspec URLs never change, so they're reused as-is.
+//! Everything else is revalidated with `If-None-Match` when the source sent
+//! an `ETag`, and fetched again when it didn't.
+//!
+//! Packages imported from NuGet's global packages folder are the exception:
+//! they're hard-linked into `content/` or referenced where they are, and
+//! their entries carry the `sha512-` hash NuGet recorded for them.
+//!
+//! Anything odd about the cache (missing files, bodies that don't match
+//! their hash, entries that don't parse) just means a miss.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256, Sha512};
+use tempfile::NamedTempFile;
+use turron_common::{
+    chrono::{DateTime, Utc},
+    dirs::{self, DirsError},
+    serde::{Deserialize, Serialize},
+    serde_json, smol,
+    surf::Url,
+    tracing,
+};
+
+/// Name of the HTTP cache, inside turron's cache directory.
+pub const CACHE_DIR: &str = "http";
+
+static CONFIGURED: OnceCell<HttpCache> = OnceCell::new();
+
+/// Makes every [`NuGetClient`] created from here on use `cache`, for the
+/// rest of the process. Only the first call does anything.
+///
+/// [`NuGetClient`]: crate::v3::NuGetClient
+pub fn enable(cache: HttpCache) {
+    let _ = CONFIGURED.set(cache);
+}
+
+/// The cache passed to [`enable`], if any.
+pub(crate) fn configured() -> Option<HttpCache> {
+    CONFIGURED.get().cloned()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpCache {
+    dir: PathBuf,
+    offline: bool,
+}
+
+/// What the cache knows about a URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    pub url: String,
+    /// Hex SHA-256 of the body, or `sha512-` and a hex SHA-512 for imported
+    /// packages.
+    pub integrity: String,
+    pub etag: Option<String>,
+    pub size: u64,
+    pub stored_at: DateTime<Utc>,
+    /// Where the body is, for packages imported by reference. Otherwise
+    /// it's under `content/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+/// How [`HttpCache::import`] gets a package into the cache without copying
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Hard-link the .nupkg into the cache. It keeps working if the
+    /// original is deleted, but has to be on the same filesystem.
+    Link,
+    /// Point the cache entry at the .nupkg where it is.
+    Reference,
+}
+
+/// A cached response, body and all.
+#[derive(Debug, Clone)]
+pub(crate) struct Hit {
+    pub(crate) entry: CacheEntry,
+    pub(crate) body: Vec<u8>,
+}
+
+impl HttpCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        HttpCache {
+            dir: dir.into(),
+            offline: false,
+        }
+    }
+
+    /// `http` inside turron's cache directory.
+    pub fn default_dir() -> Result<PathBuf, DirsError> {
+        Ok(dirs::cache_dir()?.join(CACHE_DIR))
+    }
+
+    /// Whether to answer exclusively from the cache, failing with
+    /// [`NuGetApiError::Offline`](crate::NuGetApiError::Offline) on a miss.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Every cached URL, sorted.
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        let index = match fs::read_dir(self.dir.join("index")) {
+            Ok(index) => index,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(entries),
+            Err(err) => return Err(err),
+        };
+        for file in index {
+            let path = file?.path();
+            match read_entry(&path) {
+                Some(entry) => entries.push(entry),
+                None => tracing::debug!("Skipping unreadable cache entry {}", path.display()),
+            }
+        }
+        entries.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(entries)
+    }
+
+    /// Removes everything in the cache. Returns how many URLs were cached.
+    pub fn clear(&self) -> io::Result<usize> {
+        let count = self.entries()?.len();
+        match fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(count),
+        }
+    }
+
+    /// The cached response for `url`, if there's a good one.
+    pub(crate) fn get(&self, url: &Url) -> Option<Hit> {
+        let (entry, path) = self.body_path(url)?;
+        let body = fs::read(path).ok()?;
+        if !matches(&body, &entry.integrity) {
+            tracing::debug!("Cached body for {} is corrupt. Ignoring it.", url);
+            return None;
+        }
+        Some(Hit { entry, body })
+    }
+
+    /// Like [`HttpCache::get`], but only says where the body is, for bodies
+    /// too big to hold in memory. It's still checked against its hash first.
+    pub(crate) fn get_path(&self, url: &Url) -> Option<(CacheEntry, PathBuf)> {
+        let (entry, path) = self.body_path(url)?;
+        if !file_matches(&path, &entry.integrity).ok()? {
+            tracing::debug!("Cached body for {} is corrupt. Ignoring it.", url);
+            return None;
+        }
+        Some((entry, path))
+    }
+
+    /// Caches `body` as the response for `url`.
+    pub(crate) fn put(&self, url: &Url, etag: Option<String>, body: &[u8]) -> io::Result<()> {
+        let mut writer = self.writer(url, etag)?;
+        writer.write_all(body)?;
+        writer.commit()
+    }
+
+    /// Caches the .nupkg at `nupkg` as the response for `url`, trusting
+    /// `sha512` (base64, like NuGet's `.nupkg.sha512`) instead of reading
+    /// it. The hash is still checked whenever the cached body gets used, so
+    /// a wrong one is just a miss.
+    ///
+    /// Hard links can't cross filesystems, so [`ImportMode::Link`] falls
+    /// back to a reference. Returns the mode that was actually used.
+    pub fn import(
+        &self,
+        url: &Url,
+        nupkg: &Path,
+        sha512: &str,
+        mode: ImportMode,
+    ) -> io::Result<ImportMode> {
+        let digest = base64::decode(sha512.trim())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let integrity = format!("sha512-{}", hex(&digest));
+        let size = fs::metadata(nupkg)?.len();
+        let mut path = None;
+        let mut used = mode;
+        if mode == ImportMode::Link {
+            let content = self.content_path(&integrity);
+            if let Some(parent) = content.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match fs::hard_link(nupkg, &content) {
+                Err(err) if err.kind() != io::ErrorKind::AlreadyExists => {
+                    tracing::debug!(
+                        "Couldn't link {} into the cache, so referencing it instead: {}",
+                        nupkg.display(),
+                        err
+                    );
+                    used = ImportMode::Reference;
+                }
+                _ => {}
+            }
+        }
+        if used == ImportMode::Reference {
+            path = Some(fs::canonicalize(nupkg)?);
+        }
+        self.write_entry(
+            url,
+            &CacheEntry {
+                url: url.to_string(),
+                integrity,
+                etag: None,
+                size,
+                stored_at: Utc::now(),
+                path,
+            },
+        )?;
+        Ok(used)
+    }
+
+    /// Starts caching a response for `url` that's too big to hold in memory.
+    /// Nothing's cached until [`CacheWriter::commit`] is called.
+    pub(crate) fn writer(&self, url: &Url, etag: Option<String>) -> io::Result<CacheWriter> {
+        let tmp = self.dir.join("tmp");
+        fs::create_dir_all(&tmp)?;
+        Ok(CacheWriter {
+            cache: self.clone(),
+            url: url.clone(),
+            etag,
+            file: NamedTempFile::new_in(tmp)?,
+            hasher: Sha256::new(),
+            size: 0,
+        })
+    }
+
+    /// [`HttpCache::get`], off the async executor.
+    pub(crate) async fn load(&self, url: &Url) -> Option<Hit> {
+        let (cache, url) = (self.clone(), url.clone());
+        smol::unblock(move || cache.get(&url)).await
+    }
+
+    /// [`HttpCache::put`], off the async executor. Failing to cache isn't
+    /// worth failing a request over, so errors are only logged.
+    pub(crate) async fn store(&self, url: &Url, etag: Option<String>, body: Vec<u8>) {
+        let (cache, url) = (self.clone(), url.clone());
+        smol::unblock(move || {
+            if let Err(err) = cache.put(&url, etag, &body) {
+                tracing::debug!("Failed to cache {}: {}", url, err);
+            }
+        })
+        .await
+    }
+
+    /// [`HttpCache::get_path`], off the async executor.
+    pub(crate) async fn load_path(&self, url: &Url) -> Option<(CacheEntry, PathBuf)> {
+        let (cache, url) = (self.clone(), url.clone());
+        smol::unblock(move || cache.get_path(&url)).await
+    }
+
+    /// The entry for `url`, and where its body should be, without checking
+    /// the body at all.
+    fn body_path(&self, url: &Url) -> Option<(CacheEntry, PathBuf)> {
+        let entry = read_entry(&self.entry_path(url))?;
+        if entry.url != url.as_str() {
+            return None;
+        }
+        let path = entry
+            .path
+            .clone()
+            .unwrap_or_else(|| self.content_path(&entry.integrity));
+        Some((entry, path))
+    }
+
+    fn entry_path(&self, url: &Url) -> PathBuf {
+        self.dir
+            .join("index")
+            .join(format!("{}.json", hash(url.as_str().as_bytes())))
+    }
+
+    fn content_path(&self, integrity: &str) -> PathBuf {
+        // Shard by the digest itself, not the `sha512-` in front of it.
+        let digest = integrity.rsplit('-').next().unwrap_or(integrity);
+        self.dir
+            .join("content")
+            .join(&digest[..2.min(digest.len())])
+            .join(integrity)
+    }
+
+    /// Points `url` at `entry`.
+    fn write_entry(&self, url: &Url, entry: &CacheEntry) -> io::Result<()> {
+        let path = self.entry_path(url);
+        let index = path
+            .parent()
+            .expect("TURRON BUG: cache entries always have a parent directory");
+        fs::create_dir_all(index)?;
+        // Write the entry next to where it goes and rename it, so readers
+        // never see half of one.
+        let mut tmp = NamedTempFile::new_in(index)?;
+        serde_json::to_writer(&mut tmp, entry)?;
+        tmp.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
+/// Writes a response body into the cache as it arrives.
+pub(crate) struct CacheWriter {
+    cache: HttpCache,
+    url: Url,
+    etag: Option<String>,
+    file: NamedTempFile,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl Write for CacheWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl CacheWriter {
+    /// Moves the body into place and points the URL's entry at it.
+    pub(crate) fn commit(self) -> io::Result<()> {
+        let integrity = format!("{:x}", self.hasher.finalize());
+        let content = self.cache.content_path(&integrity);
+        if let Some(parent) = content.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.file.persist(&content).map_err(|err| err.error)?;
+        self.cache.write_entry(
+            &self.url,
+            &CacheEntry {
+                url: self.url.to_string(),
+                integrity,
+                etag: self.etag,
+                size: self.size,
+                stored_at: Utc::now(),
+                path: None,
+            },
+        )
+    }
+}
+
+/// Whether whatever's at `url` can't change once it's published: versioned
+/// .nupkg and .nuspec files in a flat container.
+pub(crate) fn is_immutable(url: &Url) -> bool {
+    let path = url.path().to_lowercase();
+    path.ends_with(".nupkg") || path.ends_with(".nuspec")
+}
+
+fn hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Whether `body` is what `integrity` says it should be.
+fn matches(body: &[u8], integrity: &str) -> bool {
+    match integrity.strip_prefix("sha512-") {
+        Some(digest) => hex(&Sha512::digest(body)) == digest,
+        None => hash(body) == integrity,
+    }
+}
+
+/// Like [`matches`], for a body in a file, read a bit at a time.
+fn file_matches(path: &Path, integrity: &str) -> io::Result<bool> {
+    let mut file = fs::File::open(path)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut sha256 = Sha256::new();
+    let mut sha512 = Sha512::new();
+    let wants_sha512 = integrity.starts_with("sha512-");
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        if wants_sha512 {
+            sha512.update(&buf[..read]);
+        } else {
+            sha256.update(&buf[..read]);
+        }
+    }
+    Ok(match integrity.strip_prefix("sha512-") {
+        Some(digest) => hex(&sha512.finalize()) == digest,
+        None => format!("{:x}", sha256.finalize()) == integrity,
+    })
+}
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    let contents = fs::read(path).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn url(path: &str) -> Url {
+        format!("https://example.com{}", path).parse().unwrap()
+    }
+
+    #[test]
+    fn stores_bodies_by_content() {
+        let dir = tempdir().unwrap();
+        let cache = HttpCache::new(dir.path());
+        let (a, b) = (url("/a.json"), url("/b.json"));
+        assert!(cache.get(&a).is_none());
+
+        cache.put(&a, Some("\"1\"".into()), b"same").unwrap();
+        cache.put(&b, None, b"same").unwrap();
+        let hit = cache.get(&a).unwrap();
+        assert_eq!(hit.body, b"same");
+        let (_, path) = cache.get_path(&a).unwrap();
+        assert_eq!(fs::read(path).unwrap(), b"same");
+        assert_eq!(hit.entry.etag.as_deref(), Some("\"1\""));
+        assert_eq!(cache.get(&b).unwrap().entry.integrity, hit.entry.integrity);
+        // Both URLs share one copy of the body.
+        let blobs = fs::read_dir(dir.path().join("content"))
+            .unwrap()
+            .flat_map(|shard| fs::read_dir(shard.unwrap().path()).unwrap())
+            .count();
+        assert_eq!(blobs, 1);
+
+        let urls = cache
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.url)
+            .collect::<Vec<_>>();
+        assert_eq!(urls, vec![a.to_string(), b.to_string()]);
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert!(cache.get(&a).is_none());
+        assert!(cache.entries().unwrap().is_empty());
+        assert_eq!(cache.clear().unwrap(), 0);
+    }
+
+    #[test]
+    fn corrupt_bodies_are_misses() {
+        let dir = tempdir().unwrap();
+        let cache = HttpCache::new(dir.path());
+        let a = url("/a.json");
+        cache.put(&a, None, b"hello").unwrap();
+        let integrity = cache.get(&a).unwrap().entry.integrity;
+        fs::write(cache.content_path(&integrity), b"jello").unwrap();
+        assert!(cache.get(&a).is_none());
+        assert!(cache.get_path(&a).is_none());
+    }
+
+    #[test]
+    fn imports_packages_without_copying_them() {
+        let dir = tempdir().unwrap();
+        let cache = HttpCache::new(dir.path().join("cache"));
+        let nupkg = dir.path().join("foo.1.0.0.nupkg");
+        fs::write(&nupkg, b"not really a nupkg").unwrap();
+        let sha512 = base64::encode(Sha512::digest(b"not really a nupkg"));
+        let (linked, referenced) = (url("/foo.1.0.0.nupkg"), url("/bar.1.0.0.nupkg"));
+
+        assert_eq!(
+            cache
+                .import(&linked, &nupkg, &sha512, ImportMode::Link)
+                .unwrap(),
+            ImportMode::Link
+        );
+        assert_eq!(
+            cache
+                .import(&referenced, &nupkg, &sha512, ImportMode::Reference)
+                .unwrap(),
+            ImportMode::Reference
+        );
+        let hit = cache.get(&referenced).unwrap();
+        assert_eq!(hit.body, b"not really a nupkg");
+        assert_eq!(hit.entry.path, Some(fs::canonicalize(&nupkg).unwrap()));
+        assert!(hit.entry.integrity.starts_with("sha512-"));
+
+        // The hash is only checked once the body's needed. The link is its
+        // own copy, but the reference sees whatever's there now.
+        fs::remove_file(&nupkg).unwrap();
+        fs::write(&nupkg, b"something else entirely").unwrap();
+        assert_eq!(cache.get(&linked).unwrap().body, b"not really a nupkg");
+        assert!(cache.get(&referenced).is_none());
+
+        let bad = url("/bad.1.0.0.nupkg");
+        assert!(cache
+            .import(&bad, &nupkg, "not base64!", ImportMode::Link)
+            .is_err());
+    }
+
+    #[test]
+    fn knows_what_never_changes() {
+        assert!(is_immutable(&url(
+            "/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg"
+        )));
+        assert!(is_immutable(&url("/v3-flatcontainer/foo/1.0.0/foo.nuspec")));
+        assert!(!is_immutable(&url("/v3-flatcontainer/foo/index.json")));
+        assert!(!is_immutable(&url(
+            "/v3/registration5-gz-semver2/foo/index.json"
+        )));
+    }
+}
@@ -0,0 +1,382 @@
+//! Records every HTTP request made during a run, for `--capture-http`.
+//!
+//! Captures are meant to end up attached to bug reports, so anything that
+//! looks like a credential is redacted before it's recorded: API key, auth,
+//! and cookie headers, passwords in URLs, and query parameters with
+//! secret-sounding names. Request bodies are never recorded, and only the
+//! start of textual response bodies is.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+use turron_common::{
+    chrono::{DateTime, Utc},
+    serde::Serialize,
+    serde_json::{self, json},
+    surf::{
+        self,
+        http::headers::{HeaderName, HeaderValues, CONTENT_TYPE},
+        middleware::{Middleware, Next},
+        Body, Client, Request, Response, Url,
+    },
+};
+
+/// How much of each textual response body gets recorded.
+pub const BODY_PREVIEW_LEN: usize = 2048;
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "REDACTED";
+
+static ACTIVE: OnceCell<Arc<CaptureLog>> = OnceCell::new();
+
+/// Starts capturing the requests of every [`NuGetClient`] created from here
+/// on, for the rest of the process. Calling it again returns the same log.
+///
+/// [`NuGetClient`]: crate::v3::NuGetClient
+pub fn start() -> Arc<CaptureLog> {
+    ACTIVE
+        .get_or_init(|| Arc::new(CaptureLog::default()))
+        .clone()
+}
+
+/// The log [`start`] started, if it's been called.
+pub(crate) fn active() -> Option<Arc<CaptureLog>> {
+    ACTIVE.get().cloned()
+}
+
+/// Captured requests, in the order they finished.
+#[derive(Debug, Default)]
+pub struct CaptureLog {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl CaptureLog {
+    /// Everything captured so far, as a HAR-like JSON document.
+    pub fn to_json(&self) -> serde_json::Value {
+        let entries = self.entries.lock().unwrap();
+        json!({
+            "log": {
+                "version": "1.2",
+                "creator": { "name": "turron" },
+                "entries": *entries,
+            }
+        })
+    }
+
+    /// Writes everything captured so far to `path`, replacing it.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.to_json())?;
+        fs::write(path, json)
+    }
+
+    fn push(&self, entry: Entry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: DateTime<Utc>,
+    /// Milliseconds from sending the request to having the whole response.
+    time: f64,
+    request: CapturedRequest,
+    response: Option<CapturedResponse>,
+    timings: Timings,
+    /// Why there's no response, if there isn't one.
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CapturedRequest {
+    method: String,
+    url: String,
+    headers: Vec<Header>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CapturedResponse {
+    status: u16,
+    headers: Vec<Header>,
+    /// `None` if the body wasn't read and the server didn't say how long it
+    /// was.
+    body_size: Option<usize>,
+    content: Option<Content>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    mime_type: Option<String>,
+    /// The first [`BODY_PREVIEW_LEN`] bytes of the body, at most.
+    text: String,
+    truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Header {
+    name: String,
+    value: String,
+}
+
+/// In milliseconds, like the rest of the log.
+#[derive(Debug, Serialize)]
+struct Timings {
+    /// Until the response headers arrived.
+    wait: f64,
+    /// Reading the body, when it was read here.
+    receive: f64,
+}
+
+/// Adds every request that passes through it to a [`CaptureLog`]. Goes
+/// outside decompression, so the bodies it sees are readable.
+#[derive(Debug)]
+pub(crate) struct Capture {
+    log: Arc<CaptureLog>,
+}
+
+impl Capture {
+    pub(crate) fn new(log: Arc<CaptureLog>) -> Self {
+        Capture { log }
+    }
+}
+
+#[surf::utils::async_trait]
+impl Middleware for Capture {
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> surf::Result<Response> {
+        let started_date_time = Utc::now();
+        let timer = Instant::now();
+        let request = CapturedRequest {
+            method: req.method().to_string(),
+            url: sanitize_url(req.url()),
+            headers: sanitize_headers(req.iter()),
+        };
+        let result = next.run(req, client).await;
+        let wait = timer.elapsed();
+        let mut res = match result {
+            Ok(res) => res,
+            Err(err) => {
+                self.log.push(Entry {
+                    started_date_time,
+                    time: millis(wait),
+                    request,
+                    response: None,
+                    timings: Timings {
+                        wait: millis(wait),
+                        receive: 0.0,
+                    },
+                    error: Some(err.to_string()),
+                });
+                return Err(err);
+            }
+        };
+        let mime_type = res.content_type().map(|mime| mime.essence().to_string());
+        let textual = mime_type.as_deref().map(is_textual);
+        // Binary bodies (like .nupkgs) are left alone. Unlabeled ones get
+        // read so they can be sniffed.
+        let (body_size, content) = if textual.unwrap_or(true) {
+            let body = res.body_bytes().await?;
+            let content = preview(&body, textual.is_some()).map(|(text, truncated)| Content {
+                mime_type: mime_type.clone(),
+                text,
+                truncated,
+            });
+            let size = body.len();
+            res.set_body(Body::from_bytes(body));
+            if mime_type.is_none() {
+                // Setting a body labels the response if it wasn't already.
+                res.remove_header(CONTENT_TYPE);
+            }
+            (Some(size), content)
+        } else {
+            (res.len(), None)
+        };
+        let time = timer.elapsed();
+        self.log.push(Entry {
+            started_date_time,
+            time: millis(time),
+            request,
+            response: Some(CapturedResponse {
+                status: res.status().into(),
+                headers: sanitize_headers(res.iter()),
+                body_size,
+                content,
+            }),
+            timings: Timings {
+                wait: millis(wait),
+                receive: millis(time - wait),
+            },
+            error: None,
+        });
+        Ok(res)
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Whether a header or query parameter called `name` probably holds a
+/// credential.
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name == "sig"
+        || [
+            "key", "token", "secret", "auth", "password", "cookie", "session",
+        ]
+        .iter()
+        .any(|word| name.contains(word))
+}
+
+fn sanitize_headers<'a>(
+    headers: impl Iterator<Item = (&'a HeaderName, &'a HeaderValues)>,
+) -> Vec<Header> {
+    let mut sanitized = headers
+        .flat_map(|(name, values)| {
+            let secret = is_secret(name.as_str());
+            values.iter().map(move |value| Header {
+                name: name.to_string(),
+                value: if secret {
+                    REDACTED.into()
+                } else {
+                    value.to_string()
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    // Headers come out of a hash map, in no particular order.
+    sanitized.sort_by(|a, b| a.name.cmp(&b.name));
+    sanitized
+}
+
+/// `url` without its password or the values of secret-looking query
+/// parameters.
+fn sanitize_url(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.password().is_some() {
+        let _ = url.set_password(Some(REDACTED));
+    }
+    if url.query_pairs().any(|(name, _)| is_secret(&name)) {
+        let pairs = url
+            .query_pairs()
+            .map(|(name, value)| {
+                let value = if is_secret(&name) {
+                    REDACTED.into()
+                } else {
+                    value.into_owned()
+                };
+                (name.into_owned(), value)
+            })
+            .collect::<Vec<_>>();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+fn is_textual(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || mime_type.contains("json")
+        || mime_type.contains("xml")
+        || mime_type == "application/javascript"
+}
+
+/// The start of `body` as text, and whether any of it was cut off. Bodies
+/// that aren't `labeled` as text only get a preview if they look like
+/// UTF-8.
+fn preview(body: &[u8], labeled: bool) -> Option<(String, bool)> {
+    let prefix = &body[..body.len().min(BODY_PREVIEW_LEN)];
+    let valid = match str::from_utf8(prefix) {
+        Ok(_) => prefix.len(),
+        // Cut off partway through a character.
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) if labeled => prefix.len(),
+        Err(_) => return None,
+    };
+    Some((
+        String::from_utf8_lossy(&prefix[..valid]).into_owned(),
+        valid < body.len(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+    use turron_common::{serde_json::Value, smol};
+    use turron_testing::TestServer;
+
+    #[test]
+    fn never_records_secrets() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.json("/v3/thing", &json!({ "hello": "world" }));
+            let log = Arc::new(CaptureLog::default());
+            let client = Client::new().with(Capture::new(log.clone()));
+
+            let mut url: Url = server
+                .url("/v3/thing?q=newtonsoft&api_key=hunter2-query")
+                .parse()
+                .unwrap();
+            url.set_username("turron").unwrap();
+            url.set_password(Some("hunter2-password")).unwrap();
+            let mut res = client
+                .get(url)
+                .header("X-NuGet-ApiKey", "hunter2-key")
+                .header("Authorization", "Bearer hunter2-token")
+                .header("Cookie", "session=hunter2-cookie")
+                .header("User-Agent", "turron-test")
+                .await
+                .unwrap();
+            // Whoever made the request still gets the whole body.
+            assert_eq!(res.body_string().await.unwrap(), r#"{"hello":"world"}"#);
+            client.get(server.url("/missing")).await.unwrap();
+
+            let dir = tempdir().unwrap();
+            let file = dir.path().join("capture.json");
+            log.write(&file).unwrap();
+            let written = fs::read_to_string(&file).unwrap();
+            assert!(
+                !written.contains("hunter2"),
+                "secret leaked into capture: {}",
+                written
+            );
+
+            let har: Value = serde_json::from_str(&written).unwrap();
+            let entries = har["log"]["entries"].as_array().unwrap();
+            assert_eq!(entries.len(), 2);
+            let entry = &entries[0];
+            assert_eq!(entry["request"]["method"], "GET");
+            let url = entry["request"]["url"].as_str().unwrap();
+            assert!(url.contains("turron:REDACTED@"));
+            assert!(url.contains("q=newtonsoft&api_key=REDACTED"));
+            let headers = entry["request"]["headers"].as_array().unwrap();
+            assert!(headers.contains(&json!({ "name": "x-nuget-apikey", "value": "REDACTED" })));
+            assert!(headers.contains(&json!({ "name": "user-agent", "value": "turron-test" })));
+            assert_eq!(entry["response"]["status"], 200);
+            assert_eq!(entry["response"]["bodySize"], 17);
+            assert_eq!(entry["response"]["content"]["text"], r#"{"hello":"world"}"#);
+            assert_eq!(entries[1]["response"]["status"], 404);
+        })
+    }
+
+    #[test]
+    fn previews_the_start_of_bodies() {
+        assert_eq!(preview(b"hello", true), Some(("hello".into(), false)));
+        let long = "é".repeat(BODY_PREVIEW_LEN);
+        let (text, truncated) = preview(long.as_bytes(), false).unwrap();
+        assert_eq!(text.len(), BODY_PREVIEW_LEN);
+        assert!(truncated);
+        assert_eq!(preview(&[0xff, 0xfe, 0x00], false), None);
+        assert_eq!(
+            preview(&[b'a', 0xff], true),
+            Some(("a\u{fffd}".into(), false))
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! Credentials for sources that need more than an API key: private feeds
+//! with a username and password, or Azure Artifacts, whose credential
+//! provider mints short-lived tokens.
+//!
+//! Providers are registered per source with [`register`], before any
+//! [`NuGetClient`] for that source is created, and are asked in the order
+//! they were registered. The first one with an answer wins, and its
+//! credentials are sent as HTTP Basic auth and reused until they expire. A
+//! `401` asks the providers again, once, with `is_retry` set.
+//!
+//! [`NuGetClient`]: crate::v3::NuGetClient
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::OnceCell;
+use turron_common::{
+    chrono::{DateTime, Duration, Utc},
+    smol::lock,
+    surf::{
+        self,
+        http::headers::AUTHORIZATION,
+        middleware::{Middleware, Next},
+        Client, Request, Response, StatusCode, Url,
+    },
+    tracing,
+};
+
+#[cfg(feature = "credential-plugins")]
+pub use plugin::PluginProvider;
+
+#[cfg(feature = "credential-plugins")]
+mod plugin;
+
+/// How long before they expire cached credentials stop being handed out, so
+/// they don't run out mid-request.
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+static REGISTERED: OnceCell<Mutex<Vec<Registered>>> = OnceCell::new();
+
+/// A username and password for a source.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+    /// When these stop working. `None` means they're good for as long as
+    /// turron runs.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Credentials {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Credentials {
+            username: username.into(),
+            password: password.into(),
+            expires: None,
+        }
+    }
+
+    pub fn expiring(mut self, expires: DateTime<Utc>) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Whether these are still worth sending as of `now`.
+    fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        self.expires
+            .map_or(true, |at| now + Duration::seconds(EXPIRY_MARGIN_SECS) < at)
+    }
+
+    fn authorization(&self) -> String {
+        format!(
+            "Basic {}",
+            base64::encode(format!("{}:{}", self.username, self.password))
+        )
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"REDACTED")
+            .field("expires", &self.expires)
+            .finish()
+    }
+}
+
+/// Something that can come up with credentials for a source.
+///
+/// Implementations are written with `turron_common::surf::utils::async_trait`.
+#[surf::utils::async_trait]
+pub trait CredentialProvider: fmt::Debug + Send + Sync {
+    /// Credentials for a request to `url`, or `None` to leave it to the next
+    /// provider. `is_retry` means the source just turned down the last
+    /// credentials for this URL's host, so those shouldn't come back again.
+    async fn get_credentials(&self, url: &Url, is_retry: bool) -> Option<Credentials>;
+}
+
+/// Credentials that never change, like a `username` and `password` from a
+/// `source` block. They're only sent to the source's own host.
+#[derive(Debug, Clone)]
+pub struct StaticCredentials {
+    origin: String,
+    credentials: Credentials,
+}
+
+impl StaticCredentials {
+    pub fn new(source: &Url, credentials: Credentials) -> Self {
+        StaticCredentials {
+            origin: origin(source),
+            credentials,
+        }
+    }
+}
+
+#[surf::utils::async_trait]
+impl CredentialProvider for StaticCredentials {
+    async fn get_credentials(&self, url: &Url, is_retry: bool) -> Option<Credentials> {
+        // These are the ones that just got turned down.
+        if is_retry || origin(url) != self.origin {
+            return None;
+        }
+        Some(self.credentials.clone())
+    }
+}
+
+/// Has every [`NuGetClient`] created for `source` from here on ask
+/// `provider` for credentials, after any providers already registered for
+/// it.
+///
+/// [`NuGetClient`]: crate::v3::NuGetClient
+pub fn register(source: impl AsRef<str>, provider: Arc<dyn CredentialProvider>) {
+    let mut registered = REGISTERED
+        .get_or_init(Default::default)
+        .lock()
+        .expect("credential registry lock poisoned");
+    let source = source.as_ref();
+    match registered
+        .iter_mut()
+        .find(|reg| same_source(&reg.source, source))
+    {
+        Some(reg) => reg.providers.push(provider),
+        None => registered.push(Registered {
+            source: source.into(),
+            providers: vec![provider],
+            tokens: Arc::new(TokenCache::default()),
+        }),
+    }
+}
+
+/// Middleware that authenticates requests for `source`, if anything was
+/// registered for it.
+pub(crate) fn authenticator(source: &str) -> Option<Authenticate> {
+    let registered = REGISTERED
+        .get()?
+        .lock()
+        .expect("credential registry lock poisoned");
+    registered
+        .iter()
+        .find(|reg| same_source(&reg.source, source))
+        .map(|reg| Authenticate::new(reg.providers.clone(), reg.tokens.clone()))
+}
+
+struct Registered {
+    source: String,
+    providers: Vec<Arc<dyn CredentialProvider>>,
+    /// Shared by every client for the source, so each one doesn't have to
+    /// ask the providers all over again.
+    tokens: Arc<TokenCache>,
+}
+
+/// Matches how `source` blocks compare URLs: ignoring case and trailing
+/// slashes.
+fn same_source(a: &str, b: &str) -> bool {
+    let normalize = |url: &str| url.trim().trim_end_matches('/').to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+/// Scheme, host, and port. Credentials are cached, and only ever sent, per
+/// origin.
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// Credentials providers handed out, by origin.
+#[derive(Debug, Default)]
+pub(crate) struct TokenCache {
+    tokens: Mutex<HashMap<String, Credentials>>,
+}
+
+impl TokenCache {
+    fn get(&self, origin: &str, now: DateTime<Utc>) -> Option<Credentials> {
+        let mut tokens = self.tokens.lock().expect("token cache lock poisoned");
+        match tokens.get(origin) {
+            Some(creds) if creds.is_fresh(now) => Some(creds.clone()),
+            Some(_) => {
+                tokens.remove(origin);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, origin: String, credentials: Credentials) {
+        self.tokens
+            .lock()
+            .expect("token cache lock poisoned")
+            .insert(origin, credentials);
+    }
+
+    fn forget(&self, origin: &str) {
+        self.tokens
+            .lock()
+            .expect("token cache lock poisoned")
+            .remove(origin);
+    }
+}
+
+/// Adds credentials to requests, and retries a `401` once with fresh ones.
+#[derive(Debug, Clone)]
+pub(crate) struct Authenticate {
+    providers: Arc<Vec<Arc<dyn CredentialProvider>>>,
+    tokens: Arc<TokenCache>,
+    /// Held while asking providers, so a burst of concurrent requests
+    /// doesn't start a credential plugin for each one.
+    asking: Arc<lock::Mutex<()>>,
+}
+
+impl Authenticate {
+    pub(crate) fn new(
+        providers: Vec<Arc<dyn CredentialProvider>>,
+        tokens: Arc<TokenCache>,
+    ) -> Self {
+        Authenticate {
+            providers: Arc::new(providers),
+            tokens,
+            asking: Arc::new(lock::Mutex::new(())),
+        }
+    }
+
+    async fn credentials(&self, url: &Url, is_retry: bool) -> Option<Credentials> {
+        let origin = origin(url);
+        if is_retry {
+            self.tokens.forget(&origin);
+        } else if let Some(creds) = self.tokens.get(&origin, Utc::now()) {
+            return Some(creds);
+        }
+        let _asking = self.asking.lock().await;
+        // Someone else might've asked while this was waiting.
+        if !is_retry {
+            if let Some(creds) = self.tokens.get(&origin, Utc::now()) {
+                return Some(creds);
+            }
+        }
+        for provider in self.providers.iter() {
+            if let Some(creds) = provider.get_credentials(url, is_retry).await {
+                self.tokens.insert(origin, creds.clone());
+                return Some(creds);
+            }
+        }
+        None
+    }
+}
+
+#[surf::utils::async_trait]
+impl Middleware for Authenticate {
+    async fn handle(
+        &self,
+        mut req: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> surf::Result<Response> {
+        // Requests that bring their own credentials keep them.
+        if req.header(AUTHORIZATION).is_some() {
+            return next.run(req, client).await;
+        }
+        let url = req.url().clone();
+        let sent = self.credentials(&url, false).await;
+        if let Some(creds) = &sent {
+            req.insert_header(AUTHORIZATION, creds.authorization());
+        }
+        // A body can only be sent once, so only requests without one (which
+        // is everything but pushes) can be tried again.
+        let retry = if req.is_empty() == Some(true) {
+            Some(req.clone())
+        } else {
+            None
+        };
+        let res = next.run(req, client.clone()).await?;
+        if res.status() != StatusCode::Unauthorized {
+            return Ok(res);
+        }
+        let mut retry = match retry {
+            Some(retry) => retry,
+            None => return Ok(res),
+        };
+        match self.credentials(&url, true).await {
+            Some(fresh) if sent.as_ref() != Some(&fresh) => {
+                tracing::debug!("{} turned down our credentials. Trying new ones.", url);
+                retry.insert_header(AUTHORIZATION, fresh.authorization());
+                next.run(retry, client).await
+            }
+            _ => Ok(res),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::smol;
+    use turron_testing::TestServer;
+
+    use super::*;
+    use crate::v3::NuGetClient;
+
+    /// Hands out `answers` in order, one per call, and remembers how it was
+    /// asked.
+    #[derive(Debug, Default)]
+    struct FakeProvider {
+        answers: Mutex<Vec<Option<Credentials>>>,
+        calls: Mutex<Vec<(String, bool)>>,
+    }
+
+    impl FakeProvider {
+        fn new(answers: Vec<Option<Credentials>>) -> Arc<Self> {
+            Arc::new(FakeProvider {
+                answers: Mutex::new(answers),
+                calls: Mutex::default(),
+            })
+        }
+
+        fn calls(&self) -> Vec<(String, bool)> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[surf::utils::async_trait]
+    impl CredentialProvider for FakeProvider {
+        async fn get_credentials(&self, url: &Url, is_retry: bool) -> Option<Credentials> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((url.path().to_string(), is_retry));
+            let mut answers = self.answers.lock().unwrap();
+            if answers.is_empty() {
+                None
+            } else {
+                answers.remove(0)
+            }
+        }
+    }
+
+    fn basic(username: &str, password: &str) -> Option<String> {
+        Some(Credentials::new(username, password).authorization())
+    }
+
+    #[test]
+    fn retries_unauthorized_once() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let versions = "/v3-flatcontainer/foo/index.json";
+            let denied = "/v3-flatcontainer/bar/index.json";
+            server
+                .respond_once("/v3/index.json", 401, "")
+                .route(versions, r#"{"versions": ["1.0.0"]}"#)
+                .respond(denied, 401, "");
+            let provider = FakeProvider::new(vec![
+                Some(Credentials::new("me", "stale")),
+                Some(Credentials::new("me", "fresh")),
+                Some(Credentials::new("me", "also-bad")),
+            ]);
+            register(server.index_url(), provider.clone());
+
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            assert_eq!(
+                server.request_headers("/v3/index.json", "Authorization"),
+                vec![basic("me", "stale"), basic("me", "fresh")]
+            );
+            // Fresh credentials get reused without asking again.
+            client.versions("Foo").await.unwrap();
+            assert_eq!(
+                server.request_headers(versions, "Authorization"),
+                vec![basic("me", "fresh")]
+            );
+            assert_eq!(
+                provider.calls(),
+                vec![
+                    ("/v3/index.json".to_string(), false),
+                    ("/v3/index.json".to_string(), true),
+                ]
+            );
+
+            // A second 401 is the source's final answer.
+            assert!(client.versions("Bar").await.is_err());
+            assert_eq!(
+                server.request_headers(denied, "Authorization"),
+                vec![basic("me", "fresh"), basic("me", "also-bad")]
+            );
+        });
+    }
+
+    #[test]
+    fn asks_providers_in_order() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let nothing = FakeProvider::new(vec![None, None]);
+            let second = FakeProvider::new(vec![Some(Credentials::new("second", "pw"))]);
+            let never = FakeProvider::new(vec![Some(Credentials::new("third", "pw"))]);
+            let source = server.index_url();
+            register(&source, nothing.clone());
+            register(format!("{}/", source.to_uppercase()), second.clone());
+            register(&source, never.clone());
+
+            NuGetClient::from_source(&source).await.unwrap();
+            assert_eq!(
+                server.request_headers("/v3/index.json", "Authorization"),
+                vec![basic("second", "pw")]
+            );
+            assert_eq!(nothing.calls().len(), 1);
+            assert_eq!(second.calls().len(), 1);
+            assert!(never.calls().is_empty());
+
+            // Unrelated sources don't get anything.
+            assert!(authenticator("https://api.nuget.org/v3/index.json").is_none());
+        });
+    }
+
+    #[test]
+    fn cached_credentials_expire() {
+        let cache = TokenCache::default();
+        let now = Utc::now();
+        let origin = "https://pkgs.dev.azure.com";
+        cache.insert(
+            origin.into(),
+            Credentials::new("me", "token").expiring(now + Duration::minutes(10)),
+        );
+        assert!(cache.get(origin, now).is_some());
+        // Credentials about to expire count as expired already.
+        assert!(cache.get(origin, now + Duration::seconds(570)).is_none());
+        assert!(cache.get(origin, now).is_none());
+
+        cache.insert(origin.into(), Credentials::new("me", "forever"));
+        assert!(cache.get(origin, now + Duration::days(365)).is_some());
+        cache.forget(origin);
+        assert!(cache.get(origin, now).is_none());
+    }
+
+    #[test]
+    fn static_credentials_stay_on_their_host() {
+        smol::block_on(async {
+            let source: Url = "https://example.com/v3/index.json".parse().unwrap();
+            let provider = StaticCredentials::new(&source, Credentials::new("me", "pw"));
+            let same_host = "https://example.com/v3/flatcontainer/foo/index.json"
+                .parse()
+                .unwrap();
+            let cdn = "https://cdn.example.com/foo.1.0.0.nupkg".parse().unwrap();
+            assert_eq!(
+                provider.get_credentials(&same_host, false).await,
+                Some(Credentials::new("me", "pw"))
+            );
+            assert_eq!(provider.get_credentials(&same_host, true).await, None);
+            assert_eq!(provider.get_credentials(&cdn, false).await, None);
+            assert!(!format!("{:?}", Credentials::new("me", "pw")).contains("\"pw\""));
+        });
+    }
+}
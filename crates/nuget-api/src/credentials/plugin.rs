@@ -0,0 +1,510 @@
+//! Credential provider plugins, like the one Azure Artifacts ships. These
+//! are executables that speak NuGet's cross-platform plugin protocol:
+//! line-delimited JSON messages over stdin and stdout. See
+//! https://docs.microsoft.com/en-us/nuget/reference/extensibility/nuget-cross-platform-authentication-plugin
+//!
+//! Each time credentials are needed, the plugin is started with `-Plugin`,
+//! handshaken with, initialized, asked once, and killed.
+
+use std::io;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use turron_common::{
+    chrono::{self, Utc},
+    serde::{Deserialize, Serialize},
+    serde_json::{self, json, Value},
+    smol::{
+        future,
+        io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+        process::Command,
+        Timer,
+    },
+    surf::{self, Url},
+    tracing,
+};
+
+use crate::credentials::{origin, CredentialProvider, Credentials};
+
+/// The plugin protocol versions turron speaks.
+const PROTOCOL_VERSION: &str = "2.0.0";
+const MIN_PROTOCOL_VERSION: &str = "1.0.0";
+
+/// The NuGet client version turron claims to be. Plugins use it to decide
+/// what they can ask for.
+const CLIENT_VERSION: &str = "6.0.0";
+
+/// How long a plugin gets to answer, when it's not allowed to prompt.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a plugin gets to answer when it can prompt, like with Azure's
+/// device code flow, since that means waiting on a person.
+const INTERACTIVE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// The protocol has no way to say when credentials expire, so they're
+/// trusted for this long before the plugin gets asked again.
+const CREDENTIAL_LIFETIME_MINS: i64 = 15;
+
+/// Gets credentials for one source from a credential provider plugin.
+#[derive(Debug, Clone)]
+pub struct PluginProvider {
+    path: PathBuf,
+    source: Url,
+    interactive: bool,
+}
+
+impl PluginProvider {
+    /// The plugin at `path`, for `source`. Plugins that are .NET assemblies
+    /// (`.dll`s) are run with `dotnet`.
+    pub fn new(path: impl Into<PathBuf>, source: Url) -> Self {
+        PluginProvider {
+            path: path.into(),
+            source,
+            interactive: false,
+        }
+    }
+
+    /// Lets the plugin prompt for credentials, e.g. by printing a device
+    /// code to sign in with. Off by default.
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    fn command(&self) -> Command {
+        let is_dll = self
+            .path
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("dll"));
+        let mut cmd = if is_dll {
+            let mut cmd = Command::new("dotnet");
+            cmd.arg(&self.path);
+            cmd
+        } else {
+            Command::new(&self.path)
+        };
+        cmd.arg("-Plugin")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Prompts sometimes go straight to stderr instead of through
+            // log messages.
+            .stderr(if self.interactive {
+                Stdio::inherit()
+            } else {
+                Stdio::null()
+            })
+            .kill_on_drop(true);
+        cmd
+    }
+
+    async fn run(&self, is_retry: bool) -> io::Result<Option<Credentials>> {
+        let mut child = self.command().spawn()?;
+        let stdin = child
+            .stdin
+            .take()
+            .expect("TURRON BUG: plugin stdin wasn't piped");
+        let stdout = child
+            .stdout
+            .take()
+            .expect("TURRON BUG: plugin stdout wasn't piped");
+        let mut session = Session::new(BufReader::new(stdout), stdin);
+        let credentials = session
+            .get_credentials(&self.source, is_retry, !self.interactive)
+            .await?;
+        // Dropping the child kills it, which is how NuGet ends sessions with
+        // plugins it's done with, too.
+        drop(child);
+        Ok(credentials.map(|creds| {
+            creds.expiring(Utc::now() + chrono::Duration::minutes(CREDENTIAL_LIFETIME_MINS))
+        }))
+    }
+}
+
+#[surf::utils::async_trait]
+impl CredentialProvider for PluginProvider {
+    async fn get_credentials(&self, url: &Url, is_retry: bool) -> Option<Credentials> {
+        if origin(url) != origin(&self.source) {
+            return None;
+        }
+        let timeout = if self.interactive {
+            INTERACTIVE_TIMEOUT
+        } else {
+            TIMEOUT
+        };
+        let result = future::or(async { Some(self.run(is_retry).await) }, async {
+            Timer::after(timeout).await;
+            None
+        })
+        .await;
+        match result {
+            Some(Ok(creds)) => creds,
+            Some(Err(err)) => {
+                tracing::warn!(
+                    "Credential provider {} failed: {}",
+                    self.path.display(),
+                    err
+                );
+                None
+            }
+            None => {
+                tracing::warn!(
+                    "Credential provider {} didn't answer within {:?}.",
+                    self.path.display(),
+                    timeout
+                );
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Message {
+    request_id: String,
+    #[serde(rename = "Type")]
+    kind: String,
+    method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payload: Option<Value>,
+}
+
+/// One conversation with a running plugin.
+struct Session<R, W> {
+    reader: R,
+    writer: W,
+    next_id: u32,
+}
+
+impl<R, W> Session<R, W>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    fn new(reader: R, writer: W) -> Self {
+        Session {
+            reader,
+            writer,
+            next_id: 1,
+        }
+    }
+
+    async fn get_credentials(
+        &mut self,
+        source: &Url,
+        is_retry: bool,
+        non_interactive: bool,
+    ) -> io::Result<Option<Credentials>> {
+        let handshake = self
+            .request(
+                "Handshake",
+                json!({
+                    "ProtocolVersion": PROTOCOL_VERSION,
+                    "MinimumProtocolVersion": MIN_PROTOCOL_VERSION,
+                }),
+            )
+            .await?;
+        expect_success("Handshake", &handshake)?;
+        let initialize = self
+            .request(
+                "Initialize",
+                json!({
+                    "ClientVersion": CLIENT_VERSION,
+                    "Culture": "en-US",
+                    "RequestTimeout": "00:00:30",
+                }),
+            )
+            .await?;
+        expect_success("Initialize", &initialize)?;
+        let res = self
+            .request(
+                "GetAuthenticationCredentials",
+                json!({
+                    "Uri": source.as_str(),
+                    "IsRetry": is_retry,
+                    "IsNonInteractive": non_interactive,
+                    "CanShowDialog": false,
+                }),
+            )
+            .await?;
+        match response_code(&res) {
+            Some("Success") => {
+                let field = |name: &str| res.get(name).and_then(Value::as_str).map(String::from);
+                match (field("Username"), field("Password")) {
+                    (Some(username), Some(password)) => {
+                        Ok(Some(Credentials::new(username, password)))
+                    }
+                    _ => Err(bad_data("answered without a username and password")),
+                }
+            }
+            code => {
+                tracing::debug!(
+                    "Credential provider had no credentials for {} ({}): {}",
+                    source,
+                    code.unwrap_or("no response code"),
+                    res.get("Message").and_then(Value::as_str).unwrap_or("")
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Sends a request and waits for its response's payload, answering
+    /// whatever the plugin asks in the meantime.
+    async fn request(&mut self, method: &str, payload: Value) -> io::Result<Value> {
+        let id = self.next_id.to_string();
+        self.next_id += 1;
+        self.send(&Message {
+            request_id: id.clone(),
+            kind: "Request".into(),
+            method: method.into(),
+            payload: Some(payload),
+        })
+        .await?;
+        loop {
+            let msg = self.receive().await?;
+            match msg.kind.clone().as_str() {
+                "Response" if msg.request_id == id => return Ok(msg.payload.unwrap_or_default()),
+                "Request" => self.answer(msg).await?,
+                "Fault" => {
+                    return Err(bad_data(
+                        msg.payload
+                            .as_ref()
+                            .and_then(|p| p.get("Message"))
+                            .and_then(Value::as_str)
+                            .unwrap_or("sent a fault"),
+                    ))
+                }
+                // Progress, cancellations, and responses to requests that
+                // were already given up on.
+                _ => {}
+            }
+        }
+    }
+
+    async fn answer(&mut self, req: Message) -> io::Result<()> {
+        let payload = match &req.method[..] {
+            "Handshake" => json!({
+                "ResponseCode": "Success",
+                "ProtocolVersion": PROTOCOL_VERSION,
+            }),
+            "Log" => {
+                if let Some(message) = req
+                    .payload
+                    .as_ref()
+                    .and_then(|p| p.get("Message"))
+                    .and_then(Value::as_str)
+                {
+                    // This is how device codes and other instructions get
+                    // shown.
+                    tracing::warn!("{}", message);
+                }
+                json!({ "ResponseCode": "Success" })
+            }
+            _ => json!({ "ResponseCode": "NotFound" }),
+        };
+        self.send(&Message {
+            request_id: req.request_id,
+            kind: "Response".into(),
+            method: req.method,
+            payload: Some(payload),
+        })
+        .await
+    }
+
+    async fn send(&mut self, msg: &Message) -> io::Result<()> {
+        let mut line = serde_json::to_string(msg)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.flush().await
+    }
+
+    async fn receive(&mut self) -> io::Result<Message> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line).await? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the plugin exited before answering",
+                ));
+            }
+            let trimmed = line.trim_start_matches('\u{feff}').trim();
+            if !trimmed.is_empty() {
+                return serde_json::from_str(trimmed).map_err(|err| {
+                    bad_data(&format!("sent something that isn't a message: {}", err))
+                });
+            }
+        }
+    }
+}
+
+fn response_code(payload: &Value) -> Option<&str> {
+    payload.get("ResponseCode").and_then(Value::as_str)
+}
+
+fn expect_success(method: &str, payload: &Value) -> io::Result<()> {
+    match response_code(payload) {
+        Some("Success") => Ok(()),
+        code => Err(bad_data(&format!(
+            "answered {} with {}",
+            method,
+            code.unwrap_or("no response code")
+        ))),
+    }
+}
+
+fn bad_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::smol::{self, io::Cursor};
+
+    use super::*;
+
+    /// What a plugin would say, given requests numbered from 1.
+    fn transcript(lines: &[Value]) -> Cursor<Vec<u8>> {
+        let mut out = String::new();
+        for line in lines {
+            out.push_str(&line.to_string());
+            out.push('\n');
+        }
+        Cursor::new(out.into_bytes())
+    }
+
+    fn response(id: &str, method: &str, payload: Value) -> Value {
+        json!({ "RequestId": id, "Type": "Response", "Method": method, "Payload": payload })
+    }
+
+    fn sent(writer: &[u8]) -> Vec<Value> {
+        std::str::from_utf8(writer)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn speaks_the_plugin_protocol() {
+        smol::block_on(async {
+            let source: Url = "https://pkgs.dev.azure.com/me/_packaging/feed/nuget/v3/index.json"
+                .parse()
+                .unwrap();
+            let reader = transcript(&[
+                // Plugins handshake the other way, too.
+                json!({
+                    "RequestId": "p1", "Type": "Request", "Method": "Handshake",
+                    "Payload": { "ProtocolVersion": "2.0.0", "MinimumProtocolVersion": "1.0.0" },
+                }),
+                response(
+                    "1",
+                    "Handshake",
+                    json!({ "ResponseCode": "Success", "ProtocolVersion": "2.0.0" }),
+                ),
+                response("2", "Initialize", json!({ "ResponseCode": "Success" })),
+                json!({
+                    "RequestId": "p2", "Type": "Request", "Method": "Log",
+                    "Payload": { "LogLevel": "Minimal", "Message": "Go sign in." },
+                }),
+                json!({ "RequestId": "3", "Type": "Progress", "Method": "GetAuthenticationCredentials" }),
+                response(
+                    "3",
+                    "GetAuthenticationCredentials",
+                    json!({
+                        "ResponseCode": "Success",
+                        "Username": "VssSessionToken",
+                        "Password": "hunter2",
+                        "AuthenticationTypes": ["Basic"],
+                    }),
+                ),
+            ]);
+            let mut writer = Vec::new();
+            let creds = Session::new(reader, &mut writer)
+                .get_credentials(&source, true, true)
+                .await
+                .unwrap();
+            assert_eq!(creds, Some(Credentials::new("VssSessionToken", "hunter2")));
+
+            let sent = sent(&writer);
+            let methods = sent
+                .iter()
+                .map(|msg| {
+                    format!(
+                        "{} {} {}",
+                        msg["Type"].as_str().unwrap(),
+                        msg["Method"].as_str().unwrap(),
+                        msg["RequestId"].as_str().unwrap()
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                methods,
+                vec![
+                    "Request Handshake 1",
+                    "Response Handshake p1",
+                    "Request Initialize 2",
+                    "Response Log p2",
+                    "Request GetAuthenticationCredentials 3",
+                ]
+            );
+            assert_eq!(
+                sent[4]["Payload"],
+                json!({
+                    "Uri": source.as_str(),
+                    "IsRetry": true,
+                    "IsNonInteractive": true,
+                    "CanShowDialog": false,
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn plugins_without_credentials() {
+        smol::block_on(async {
+            let source: Url = "https://example.com/v3/index.json".parse().unwrap();
+            let handshake = response(
+                "1",
+                "Handshake",
+                json!({ "ResponseCode": "Success", "ProtocolVersion": "2.0.0" }),
+            );
+            let initialize = response("2", "Initialize", json!({ "ResponseCode": "Success" }));
+
+            let reader = transcript(&[
+                handshake.clone(),
+                initialize.clone(),
+                response(
+                    "3",
+                    "GetAuthenticationCredentials",
+                    json!({ "ResponseCode": "NotFound", "Message": "Not an Azure feed." }),
+                ),
+            ]);
+            let creds = Session::new(reader, Vec::new())
+                .get_credentials(&source, false, true)
+                .await
+                .unwrap();
+            assert_eq!(creds, None);
+
+            // Quitting partway through is an error, not "no credentials".
+            let reader = transcript(&[handshake]);
+            let err = Session::new(reader, Vec::new())
+                .get_credentials(&source, false, true)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+            let reader = transcript(&[response(
+                "1",
+                "Handshake",
+                json!({ "ResponseCode": "Error" }),
+            )]);
+            assert!(Session::new(reader, Vec::new())
+                .get_credentials(&source, false, true)
+                .await
+                .is_err());
+        });
+    }
+}
@@ -1,11 +1,14 @@
-use std::{cmp, io, sync::Arc};
+use std::{cmp, io, sync::Arc, time::Duration};
 
 use turron_common::{
+    explain::Explanation,
     miette::{self, Diagnostic, NamedSource, SourceOffset},
     quick_xml, serde_json, surf,
     thiserror::{self, Error},
 };
 
+use crate::SourceProtocol;
+
 #[derive(Error, Debug, Diagnostic)]
 pub enum NuGetApiError {
     /// Returned when a generic http client-related error has occurred.
@@ -120,17 +123,384 @@ pub enum NuGetApiError {
     )]
     BadResponse(surf::StatusCode),
 
+    /// The source is throttling requests.
+    #[error("{url} is rate limiting requests. {}", wait_hint(.retry_after))]
+    #[diagnostic(
+        code(turron::api::rate_limited),
+        help("Wait as long as the source asked before trying again. Running fewer turron commands against this source at once also helps.")
+    )]
+    RateLimited {
+        retry_after: Option<Duration>,
+        url: String,
+    },
+
+    /// The source won't serve something for legal reasons.
+    #[error("{url} is unavailable for legal reasons.{}", blocked_by_hint(.blocked_by))]
+    #[diagnostic(
+        code(turron::api::legally_unavailable),
+        help("The source answered with 451 Unavailable For Legal Reasons, usually because of a takedown or a regional restriction. Another source or mirror might still have it.")
+    )]
+    LegallyUnavailable {
+        url: String,
+        blocked_by: Option<String>,
+    },
+
+    /// A mirror sent a web page where a .nupkg should have been.
+    #[error("{url} returned an error page ({content_type}) instead of a .nupkg.")]
+    #[diagnostic(
+        code(turron::api::error_page),
+        help("Some mirrors and CDNs answer with an HTML error page, but a 200 status, when they can't serve a package. Try again later, or use a different source.")
+    )]
+    ErrorPage { url: String, content_type: String },
+
+    /// `--assume-source-version` or a source's `protocol` wasn't one we know.
+    #[error("Unknown source protocol `{0}`. Expected v3, v2, or auto.")]
+    #[diagnostic(code(turron::api::unknown_protocol))]
+    UnknownProtocol(String),
+
+    /// A vulnerability severity, like `--severity`'s, wasn't one we know.
+    #[error("Unknown vulnerability severity `{0}`. Expected low, moderate, high, or critical.")]
+    #[diagnostic(code(turron::api::unknown_severity))]
+    UnknownSeverity(String),
+
+    /// The source was pinned to a protocol it doesn't actually speak.
+    #[error(
+        "{url} was pinned to the {pinned} protocol, but it doesn't serve a {pinned} service index."
+    )]
+    #[diagnostic(
+        code(turron::api::protocol_mismatch),
+        help("Check the source URL, or let turron detect the protocol with `--assume-source-version auto`.")
+    )]
+    ProtocolMismatch { url: String, pinned: SourceProtocol },
+
+    /// The source was pinned to a protocol turron can't talk to.
+    #[error("turron doesn't support the NuGet {0} protocol yet.")]
+    #[diagnostic(
+        code(turron::api::unsupported_protocol),
+        help("Only v3 sources work for now. `v2` is accepted as a pin so configs can say what a source is, but turron can't talk to it. Use the source's v3 endpoint instead, if it has one.")
+    )]
+    UnsupportedProtocol(SourceProtocol),
+
+    /// A target framework moniker turron doesn't know how to compare.
+    #[error("Unrecognized target framework: {0}")]
+    #[diagnostic(
+        code(turron::api::unknown_framework),
+        help("Use a short target framework moniker, like net6.0, netstandard2.0, or net472.")
+    )]
+    UnknownFramework(String),
+
+    /// Paging through search results stopped getting anywhere.
+    #[error("{0} returned the same search results for skip={1} as for the page before it.")]
+    #[diagnostic(
+        code(turron::api::search_stalled),
+        help("The source may not support `skip`. Search without --all instead.")
+    )]
+    SearchStalled(String, usize),
+
+    /// Running with `--offline`, and the response wasn't cached.
+    #[error("{0} isn't cached, and turron is running offline.")]
+    #[diagnostic(
+        code(turron::api::offline),
+        help("Run the command once without --offline, with --cache, so the response gets cached.")
+    )]
+    Offline(String),
+
     /// File was not found in nupkg.
     #[error("File not found in .nupkg")]
     #[diagnostic(code(turron::api::file_not_found))]
     FileNotFound(String, dotnet_semver::Version, String),
 
+    /// A package's readme wasn't UTF-8 text.
+    #[error("{0}@{1}'s readme isn't valid UTF-8 text.")]
+    #[diagnostic(
+        code(turron::api::invalid_utf8),
+        help("turron only supports text files which are valid UTF-8 text.")
+    )]
+    InvalidUtf8(
+        String,
+        dotnet_semver::Version,
+        #[source] std::string::FromUtf8Error,
+    ),
+
+    /// A package's deprecated `iconUrl` didn't serve an icon.
+    #[error("{url} responded with {status} instead of an icon.")]
+    #[diagnostic(
+        code(turron::api::icon_url_failed),
+        help("The package's iconUrl may be out of date. It's set by the package's author, not the source.")
+    )]
+    IconUrlFailed {
+        url: String,
+        status: surf::StatusCode,
+    },
+
+    /// A nuspec couldn't be written out as XML.
+    #[error("Can't write this nuspec: {0}")]
+    #[diagnostic(
+        code(turron::api::invalid_nuspec),
+        help("Fix the field named above. Every nuspec needs an id, a version, a description, and authors.")
+    )]
+    InvalidNuSpec(String),
+
     /// Something went wrong while reading/writing a .nupkg
     #[error(transparent)]
     #[diagnostic(code(turron::api::zip_error))]
     ZipError(#[from] zip::result::ZipError),
 }
 
+fn blocked_by_hint(blocked_by: &Option<String>) -> String {
+    match blocked_by {
+        Some(by) => format!(" Blocked by: {}", by),
+        None => String::new(),
+    }
+}
+
+fn wait_hint(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(wait) if wait.as_secs() == 0 => "Try again now.".into(),
+        Some(wait) if wait.as_secs() == 1 => "Try again in 1 second.".into(),
+        Some(wait) => format!("Try again in {} seconds.", wait.as_secs()),
+        None => "Try again in a little while.".into(),
+    }
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::api::generic_http",
+        cause: "The HTTP request itself failed: DNS lookup, connecting, TLS, or reading the response. The source never got a chance to answer.",
+        fixes: &[
+            "Check your network connection and any proxy settings.",
+            "Run `turron ping --source <url>` to see whether the source is reachable at all.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::api::io_error",
+        cause: "A local I/O operation failed while talking to a source, usually while writing a download to disk or spooling a .nupkg to a temporary file.",
+        fixes: &["Make sure there is free disk space and that the temp directory is writable."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::invalid_source",
+        cause: "The configured source responded, but not with a NuGet v3 service index. turron only speaks the v3 protocol, and the URL must point at the index itself.",
+        fixes: &[
+            "Point at the service index, which usually ends in `/v3/index.json`.",
+            "If this is a v2 feed, check whether the server also exposes a v3 endpoint.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::api::invalid_url",
+        cause: "A URL could not be parsed. Either the source URL itself is malformed, or the service index advertised an endpoint that is not a valid URL.",
+        fixes: &["Make sure the source includes its scheme, e.g. `https://`."],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::api::unsupported_endpoint",
+        cause: "The operation needs a resource that this source's service index doesn't list. Some private feeds only implement part of the v3 protocol.",
+        fixes: &[
+            "Run `turron ping --source <url>` to list the resources the source supports.",
+            "Use a different source for this operation.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::api::unknown_protocol",
+        cause: "The protocol given to `--assume-source-version`, or set as a source's `protocol`, isn't one turron knows about.",
+        fixes: &["Use `v3`, `v2`, or `auto` (the default, which detects the protocol)."],
+        config: &["assume_source_version", "sources"],
+    },
+    Explanation {
+        code: "turron::api::unknown_severity",
+        cause: "Vulnerability severities are the ones NuGet uses: low, moderate, high, and critical. The one given wasn't any of those.",
+        fixes: &["Use `low`, `moderate`, `high`, or `critical`."],
+        config: &["commands.audit.severity"],
+    },
+    Explanation {
+        code: "turron::api::protocol_mismatch",
+        cause: "The source was pinned to a protocol, so turron skipped detection, but what the source served doesn't match it. For v3, that means the URL didn't return a JSON service index.",
+        fixes: &[
+            "Double-check the source URL. v3 sources usually end in `/v3/index.json`.",
+            "Remove the pin, or pass `--assume-source-version auto`, to let turron detect the protocol.",
+        ],
+        config: &["assume_source_version", "sources"],
+    },
+    Explanation {
+        code: "turron::api::unsupported_protocol",
+        cause: "The source was pinned to a NuGet protocol turron can't talk to yet. Only v3 sources are supported right now: `v2` is accepted by `--assume-source-version` and a source's `protocol` setting, but always ends in this error, and `auto` doesn't detect v2 sources either.",
+        fixes: &[
+            "Point turron at the source's v3 endpoint, if it has one.",
+            "Remove the `v2` pin from `--assume-source-version` or the source's config.",
+        ],
+        config: &["assume_source_version", "sources"],
+    },
+    Explanation {
+        code: "turron::api::unknown_framework",
+        cause: "This source can't filter search results by framework itself, so turron has to do it, and it didn't recognize the framework. Client-side filtering only understands .NET Framework, .NET Core/.NET 5+, and .NET Standard monikers.",
+        fixes: &[
+            "Spell the framework as a short moniker, e.g. `net6.0`, `netcoreapp3.1`, `netstandard2.0`, or `net472`.",
+            "Search without `--framework` and check the results' frameworks by hand.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::search_stalled",
+        cause: "While fetching every page of search results, the source answered a request for the next page with the page turron already had. Sources that ignore `skip` do this, and paging through them would never end.",
+        fixes: &[
+            "Search without `--all` and page by hand with `--skip` and `--take`.",
+            "Use a more specific query, so the first page has what you're after.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::api::offline",
+        cause: "turron was run with `--offline`, so it could only answer from its HTTP cache, and nothing was cached for this URL. Writes like publish and unlist always need the network.",
+        fixes: &[
+            "Run the same command once with `--cache` while online, so its responses get cached.",
+            "Drop `--offline` if the network is available.",
+            "Run `turron cache ls` to see what's cached.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::needs_api_key",
+        cause: "The source rejected a write operation (publish, unlist, relist) because no API key was sent.",
+        fixes: &[
+            "Pass `--api-key <key>`.",
+            "Run `turron login --source <url>` to save a key for this source.",
+        ],
+        config: &["api_key", "sources"],
+    },
+    Explanation {
+        code: "turron::api::invalid_api_key",
+        cause: "The source rejected the API key. It may have expired, been revoked, or not be scoped to this package.",
+        fixes: &[
+            "Generate a new key with push/unlist rights for this package.",
+            "Check that the key saved for this source is the one you expect.",
+        ],
+        config: &["api_key", "sources"],
+    },
+    Explanation {
+        code: "turron::api::invalid_package",
+        cause: "The source refused the uploaded .nupkg as invalid. The NuGet API doesn't say which part it objected to.",
+        fixes: &[
+            "Re-pack the project and try again.",
+            "Check the .nuspec for a missing id, version, description, or authors.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::package_exists",
+        cause: "A package with this id and version already exists in the source. NuGet versions are immutable once published.",
+        fixes: &[
+            "Bump the version and publish again.",
+            "If re-running a publish that already went through, pass `--skip-duplicate` to `turron publish`.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::package_not_found",
+        cause: "The source has no package with this id and version. Sources also answer this way when the API key isn't allowed to see the package.",
+        fixes: &[
+            "Check the id and version with `turron view <id> versions`.",
+            "Make sure your API key is scoped to this package.",
+        ],
+        config: &["api_key", "sources"],
+    },
+    Explanation {
+        code: "turron::api::registration_page_not_found",
+        cause: "The registration index pointed at a page that the source then couldn't find. This usually means the source is mid-update or its metadata is inconsistent.",
+        fixes: &["Try again in a few minutes."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::bad_json",
+        cause: "The source sent JSON that doesn't match the shape turron expects from the NuGet v3 API. The error shows the part of the document that failed to parse.",
+        fixes: &[
+            "Check whether the source documents any deviations from the v3 API.",
+            "Report it, along with the URL in the error, so the parser can be fixed.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::bad_xml",
+        cause: "The source returned XML, usually a .nuspec, that turron could not parse.",
+        fixes: &["Report it, along with the package id and version."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::rate_limited",
+        cause: "The source answered with `429 Too Many Requests`. turron already retried, but either the source kept refusing or asked for a longer wait than turron is willing to sit through on its own. The error says how long the source asked you to wait, if it said.",
+        fixes: &[
+            "Wait the amount of time in the error, then run the command again.",
+            "Avoid running many turron commands against the same source at once.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::legally_unavailable",
+        cause: "The source answered with `451 Unavailable For Legal Reasons`. The package, or this version of it, has been taken down, or is blocked where you are. When the source says who asked for the block, the error includes it.",
+        fixes: &[
+            "Follow the \"Blocked by\" link, if there is one, for details.",
+            "Use another source or mirror that still carries the package, if you're allowed to.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::api::error_page",
+        cause: "A .nupkg download came back as an HTML page with a `200` status. Some mirrors and CDNs do this instead of returning an error status when they can't serve a file. turron doesn't save or cache the page.",
+        fixes: &[
+            "Open the URL in a browser to read the page.",
+            "Try again later, or switch to a different source.",
+        ],
+        config: &["source"],
+    },
+    Explanation {
+        code: "turron::api::unexpected_response",
+        cause: "The source answered with an HTTP status that the NuGet API docs don't mention for this operation.",
+        fixes: &[
+            "Check the source's status page, since 5xx codes usually mean it is having trouble.",
+            "Run with `--verbosity debug` to see the request that was made.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::file_not_found",
+        cause: "The requested file is not inside the package's .nupkg, for example a readme or icon that the .nuspec mentions but that was never packed.",
+        fixes: &["Check the package contents, or ask its author to include the file."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::invalid_utf8",
+        cause: "The package's readme isn't valid UTF-8 text, so turron can't show it.",
+        fixes: &["Use `turron download` and open the readme with a tool that understands its encoding."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::icon_url_failed",
+        cause: "The package doesn't embed an icon, so turron tried the deprecated `iconUrl` from its .nuspec, but that server didn't return one. These URLs point wherever the package's author chose, and often go stale.",
+        fixes: &[
+            "Open the URL in a browser to see whether it still works.",
+            "Ask the package's author to embed the icon in the package instead.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::invalid_nuspec",
+        cause: "turron was asked to write a .nuspec that NuGet wouldn't accept: a required field (id, description, or authors) is blank, or a value contains a control character that XML can't represent at all.",
+        fixes: &[
+            "Fill in the field named in the error.",
+            "Remove control characters, often pasted in from a terminal, from the value named in the error.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::api::zip_error",
+        cause: "A downloaded .nupkg could not be read as a zip archive. The download may have been truncated or the package may be corrupt.",
+        fixes: &["Try the download again.", "If it keeps failing, report it to the source."],
+        config: &[],
+    },
+];
+
 impl NuGetApiError {
     pub fn from_json_err(err: serde_json::Error, url: String, json: String) -> Self {
         // These json strings can get VERY LONG and miette doesn't (yet?)
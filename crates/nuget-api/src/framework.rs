@@ -0,0 +1,241 @@
+//! Target framework monikers (TFMs), and which ones can consume which.
+//!
+//! This only knows about the three framework families packages actually
+//! target these days (.NET Framework, .NET Core/.NET 5+, and .NET
+//! Standard), which covers the overwhelming majority of nuget.org. Anything
+//! else (Xamarin, UWP, PCL profiles, ...) fails to parse, and callers should
+//! treat it as unknown.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameworkFamily {
+    /// `net45`, `net472`, `.NETFramework,Version=v4.8`...
+    NetFramework,
+    /// `netcoreapp3.1`, `net5.0`, `net6.0-windows`...
+    NetCore,
+    /// `netstandard2.0`...
+    NetStandard,
+}
+
+/// A parsed target framework, like `netstandard2.0` or `net6.0-windows`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Framework {
+    pub family: FrameworkFamily,
+    pub version: (u32, u32, u32),
+    /// OS-specific frameworks, like the `windows` in `net6.0-windows`.
+    pub platform: Option<String>,
+}
+
+impl Framework {
+    /// Parses both short TFMs (`net472`, `netstandard2.0`, `net6.0-ios`)
+    /// and the long names nuspecs sometimes use (`.NETStandard2.0`,
+    /// `.NETFramework,Version=v4.7.2`).
+    pub fn parse(tfm: impl AsRef<str>) -> Option<Self> {
+        use FrameworkFamily::*;
+        let tfm = tfm.as_ref().trim().trim_start_matches('.').to_lowercase();
+        let (tfm, platform) = match tfm.find('-') {
+            Some(idx) => (&tfm[..idx], Some(&tfm[idx + 1..])),
+            None => (&tfm[..], None),
+        };
+        let platform = platform
+            .map(|p| p.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+            .filter(|p| !p.is_empty())
+            .map(String::from);
+        let (family, version) = if let Some(version) = tfm.strip_prefix("netstandard") {
+            (NetStandard, parse_version(version)?)
+        } else if let Some(version) = tfm.strip_prefix("netcoreapp") {
+            (NetCore, parse_version(version)?)
+        } else if let Some(version) = tfm.strip_prefix("netframework") {
+            (NetFramework, parse_version(version)?)
+        } else if let Some(version) = tfm.strip_prefix("net") {
+            if version.contains('.') {
+                // net5.0 and up. `net4.8` isn't a thing, but be lenient.
+                let version = parse_version(version)?;
+                (
+                    if version.0 >= 5 {
+                        NetCore
+                    } else {
+                        NetFramework
+                    },
+                    version,
+                )
+            } else {
+                // net45, net472: one digit per version component.
+                let digits = version
+                    .chars()
+                    .map(|c| c.to_digit(10))
+                    .collect::<Option<Vec<_>>>()?;
+                match digits[..] {
+                    [major] => (NetFramework, (major, 0, 0)),
+                    [major, minor] => (NetFramework, (major, minor, 0)),
+                    [major, minor, patch] => (NetFramework, (major, minor, patch)),
+                    _ => return None,
+                }
+            }
+        } else {
+            return None;
+        };
+        if platform.is_some() && family != NetCore {
+            return None;
+        }
+        Some(Framework {
+            family,
+            version,
+            platform,
+        })
+    }
+
+    /// Whether a project targeting `self` can use a package built for
+    /// `package`.
+    pub fn supports(&self, package: &Framework) -> bool {
+        use FrameworkFamily::*;
+        if package.platform.is_some() && package.platform != self.platform {
+            return false;
+        }
+        match (self.family, package.family) {
+            (a, b) if a == b => package.version <= self.version,
+            (NetCore, NetStandard) => netstandard_floor(package.version, NETSTANDARD_NETCORE)
+                .map_or(false, |floor| floor <= self.version),
+            (NetFramework, NetStandard) => {
+                netstandard_floor(package.version, NETSTANDARD_NETFRAMEWORK)
+                    .map_or(false, |floor| floor <= self.version)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a project targeting `self` can use a package that ships
+    /// assets for any of `frameworks`. Unparseable entries are ignored.
+    pub fn supports_any<I, S>(&self, frameworks: I) -> bool
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        frameworks
+            .into_iter()
+            .filter_map(Framework::parse)
+            .any(|fw| self.supports(&fw))
+    }
+}
+
+impl fmt::Display for Framework {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FrameworkFamily::*;
+        let (major, minor, patch) = self.version;
+        match self.family {
+            NetFramework if patch > 0 => write!(f, "net{}{}{}", major, minor, patch)?,
+            NetFramework => write!(f, "net{}{}", major, minor)?,
+            NetCore if major >= 5 => write!(f, "net{}.{}", major, minor)?,
+            NetCore => write!(f, "netcoreapp{}.{}", major, minor)?,
+            NetStandard => write!(f, "netstandard{}.{}", major, minor)?,
+        }
+        if let Some(platform) = &self.platform {
+            write!(f, "-{}", platform)?;
+        }
+        Ok(())
+    }
+}
+
+/// The first .NET Core version that implements each .NET Standard version.
+const NETSTANDARD_NETCORE: &[((u32, u32), (u32, u32, u32))] = &[
+    ((1, 0), (1, 0, 0)),
+    ((1, 1), (1, 0, 0)),
+    ((1, 2), (1, 0, 0)),
+    ((1, 3), (1, 0, 0)),
+    ((1, 4), (1, 0, 0)),
+    ((1, 5), (1, 0, 0)),
+    ((1, 6), (1, 0, 0)),
+    ((2, 0), (2, 0, 0)),
+    ((2, 1), (3, 0, 0)),
+];
+
+/// The first .NET Framework version that implements each .NET Standard
+/// version. Nothing implements 2.1.
+const NETSTANDARD_NETFRAMEWORK: &[((u32, u32), (u32, u32, u32))] = &[
+    ((1, 0), (4, 5, 0)),
+    ((1, 1), (4, 5, 0)),
+    ((1, 2), (4, 5, 1)),
+    ((1, 3), (4, 6, 0)),
+    ((1, 4), (4, 6, 1)),
+    ((1, 5), (4, 6, 1)),
+    ((1, 6), (4, 6, 1)),
+    ((2, 0), (4, 6, 1)),
+];
+
+fn netstandard_floor(
+    (major, minor, _): (u32, u32, u32),
+    table: &[((u32, u32), (u32, u32, u32))],
+) -> Option<(u32, u32, u32)> {
+    table
+        .iter()
+        .find(|(ns, _)| *ns == (major, minor))
+        .map(|(_, floor)| *floor)
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let version = version
+        .trim_start_matches(",version=")
+        .trim_start_matches('v');
+    let mut parts = version.split('.').map(|part| part.parse::<u32>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fw(tfm: &str) -> Framework {
+        Framework::parse(tfm).unwrap_or_else(|| panic!("failed to parse {}", tfm))
+    }
+
+    #[test]
+    fn parses_short_and_long_names() {
+        use FrameworkFamily::*;
+        assert_eq!(fw("net472").family, NetFramework);
+        assert_eq!(fw("net472").version, (4, 7, 2));
+        assert_eq!(fw("net45"), fw(".NETFramework,Version=v4.5"));
+        assert_eq!(fw("netstandard2.0"), fw(".NETStandard2.0"));
+        assert_eq!(fw("netcoreapp3.1"), fw(".NETCoreApp,Version=v3.1"));
+        assert_eq!(fw("net6.0").family, NetCore);
+        assert_eq!(fw("net6.0-windows7.0").platform.as_deref(), Some("windows"));
+        assert_eq!(Framework::parse("portable-net45+win8"), None);
+        assert_eq!(Framework::parse("monoandroid10"), None);
+    }
+
+    #[test]
+    fn round_trips_short_names() {
+        for tfm in &[
+            "net45",
+            "net472",
+            "netcoreapp3.1",
+            "net6.0",
+            "net6.0-ios",
+            "netstandard2.0",
+        ] {
+            assert_eq!(fw(tfm).to_string(), *tfm);
+        }
+    }
+
+    #[test]
+    fn compatibility() {
+        assert!(fw("net6.0").supports(&fw("netstandard2.1")));
+        assert!(fw("net6.0").supports(&fw("netcoreapp3.1")));
+        assert!(fw("netcoreapp2.1").supports(&fw("netstandard2.0")));
+        assert!(!fw("netcoreapp2.1").supports(&fw("netstandard2.1")));
+        assert!(fw("net472").supports(&fw("netstandard2.0")));
+        assert!(!fw("net472").supports(&fw("netstandard2.1")));
+        assert!(!fw("net45").supports(&fw("netstandard1.3")));
+        assert!(fw("net48").supports(&fw("net45")));
+        assert!(!fw("net45").supports(&fw("net48")));
+        assert!(!fw("net6.0").supports(&fw("net48")));
+        assert!(!fw("netstandard2.0").supports(&fw("net6.0")));
+        assert!(fw("net6.0-windows").supports(&fw("net6.0")));
+        assert!(!fw("net6.0").supports(&fw("net6.0-windows")));
+        assert!(fw("net6.0").supports_any(&["monoandroid10", "netstandard2.0"]));
+        assert!(!fw("net6.0").supports_any(&["monoandroid10", "net48"]));
+    }
+}
@@ -1,6 +1,15 @@
 #![feature(macro_attributes_in_derive_output)]
 
+pub mod cache;
+pub mod capture;
+pub mod credentials;
 mod errors;
+pub mod framework;
+mod protocol;
+pub mod schedule;
+pub mod sidecar;
 pub mod v3;
+pub mod validate;
 
-pub use errors::NuGetApiError;
+pub use errors::{NuGetApiError, EXPLANATIONS};
+pub use protocol::SourceProtocol;
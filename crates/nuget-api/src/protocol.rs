@@ -0,0 +1,72 @@
+use std::fmt;
+use std::str::FromStr;
+
+use turron_common::serde::Serialize;
+
+use crate::errors::NuGetApiError;
+
+/// Which NuGet protocol a source is assumed to speak.
+///
+/// `Auto` looks at what the source serves. Pinning a protocol skips that,
+/// and turns a source that turns out to speak something else into an error
+/// instead of a guess.
+///
+/// Only v3 is actually spoken so far. `V2` is accepted so configs can
+/// already say what a source is, but connecting to one is always an
+/// [`NuGetApiError::UnsupportedProtocol`] error, and `Auto` can only tell a
+/// v3 service index from something that isn't one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceProtocol {
+    Auto,
+    V3,
+    V2,
+}
+
+impl Default for SourceProtocol {
+    fn default() -> Self {
+        SourceProtocol::Auto
+    }
+}
+
+impl FromStr for SourceProtocol {
+    type Err = NuGetApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.trim().to_lowercase()[..] {
+            "auto" => Ok(SourceProtocol::Auto),
+            "v3" | "3" => Ok(SourceProtocol::V3),
+            "v2" | "2" => Ok(SourceProtocol::V2),
+            _ => Err(NuGetApiError::UnknownProtocol(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for SourceProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceProtocol::Auto => write!(f, "auto"),
+            SourceProtocol::V3 => write!(f, "v3"),
+            SourceProtocol::V2 => write!(f, "v2"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_protocols() {
+        assert_eq!(
+            "auto".parse::<SourceProtocol>().unwrap(),
+            SourceProtocol::Auto
+        );
+        assert_eq!("V3".parse::<SourceProtocol>().unwrap(), SourceProtocol::V3);
+        assert_eq!("2".parse::<SourceProtocol>().unwrap(), SourceProtocol::V2);
+        assert!(matches!(
+            "v4".parse::<SourceProtocol>(),
+            Err(NuGetApiError::UnknownProtocol(p)) if p == "v4"
+        ));
+    }
+}
@@ -0,0 +1,482 @@
+//! One queue for every request turron makes, so commands that fan out don't
+//! trip over each other, or over the sources they're talking to.
+//!
+//! Each request waits for a slot before it's sent. There's a cap on how many
+//! can be in flight at once overall, and a lower one per host, as a courtesy
+//! to sources. When a slot frees up, it goes to the oldest waiting request of
+//! the highest [`Priority`] whose host still has room, so a pile of bulk
+//! lookups can't hold up the one thing a user is actually waiting on.
+//!
+//! Requests are [`Priority::Interactive`] unless the future making them was
+//! wrapped in [`prioritized`]. Slots are held from sending a request until
+//! its response headers arrive.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use once_cell::sync::OnceCell;
+use turron_common::surf::{
+    self,
+    middleware::{Middleware, Next},
+    Client, Request, Response,
+};
+
+/// How requests are ordered when they have to wait. Higher goes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Work nobody is waiting on yet, like warming caches.
+    Background,
+    /// Lots of lookups on behalf of one command, like resolving every
+    /// dependency of a package.
+    Bulk,
+    /// What a command needs to show the user anything at all.
+    Interactive,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Interactive
+    }
+}
+
+/// How many requests can be in flight at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_requests: usize,
+    pub max_requests_per_host: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_requests: 16,
+            max_requests_per_host: 8,
+        }
+    }
+}
+
+static GLOBAL: OnceCell<Arc<Scheduler>> = OnceCell::new();
+
+/// Sets the limits every [`NuGetClient`] shares. Only the first call, before
+/// any client is created, has any effect. Returns whether it did.
+///
+/// [`NuGetClient`]: crate::v3::NuGetClient
+pub fn configure(limits: Limits) -> bool {
+    GLOBAL.set(Arc::new(Scheduler::new(limits))).is_ok()
+}
+
+/// The scheduler every [`NuGetClient`](crate::v3::NuGetClient) shares.
+pub(crate) fn global() -> Arc<Scheduler> {
+    GLOBAL
+        .get_or_init(|| Arc::new(Scheduler::new(Limits::default())))
+        .clone()
+}
+
+thread_local! {
+    static CURRENT: Cell<Priority> = Cell::new(Priority::Interactive);
+}
+
+/// The priority of the task that's running right now.
+pub(crate) fn current() -> Priority {
+    CURRENT.with(Cell::get)
+}
+
+/// Runs `fut` with every request it makes at `priority`. Tasks spawned
+/// from inside it don't inherit it, so wrap them too.
+pub fn prioritized<F: Future>(priority: Priority, fut: F) -> Prioritized<F> {
+    Prioritized {
+        priority,
+        fut: Box::pin(fut),
+    }
+}
+
+/// See [`prioritized`].
+#[derive(Debug)]
+pub struct Prioritized<F> {
+    priority: Priority,
+    fut: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Prioritized<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let outer = CURRENT.with(|current| current.replace(self.priority));
+        let result = self.fut.as_mut().poll(cx);
+        CURRENT.with(|current| current.set(outer));
+        result
+    }
+}
+
+#[derive(Debug)]
+struct Waiter {
+    host: String,
+    granted: bool,
+    waker: Option<Waker>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    running: usize,
+    per_host: HashMap<String, usize>,
+    /// Keyed so iterating goes from the highest priority down, oldest first
+    /// within a priority.
+    waiting: BTreeMap<(std::cmp::Reverse<Priority>, u64), Waiter>,
+    next_id: u64,
+}
+
+/// Hands out request slots. See the [module docs](self).
+#[derive(Debug)]
+pub struct Scheduler {
+    limits: Limits,
+    state: Mutex<State>,
+}
+
+impl Scheduler {
+    pub fn new(limits: Limits) -> Self {
+        Scheduler {
+            limits: Limits {
+                max_requests: limits.max_requests.max(1),
+                max_requests_per_host: limits.max_requests_per_host.max(1),
+            },
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Waits for a slot for a request to `host`. The slot is given back when
+    /// the [`Permit`] is dropped.
+    pub fn acquire(self: &Arc<Self>, priority: Priority, host: impl Into<String>) -> Acquire {
+        let mut state = self.state.lock().expect("scheduler lock poisoned");
+        let key = (std::cmp::Reverse(priority), state.next_id);
+        state.next_id += 1;
+        state.waiting.insert(
+            key,
+            Waiter {
+                host: host.into(),
+                granted: false,
+                waker: None,
+            },
+        );
+        self.dispatch(&mut state);
+        Acquire {
+            scheduler: self.clone(),
+            key: Some(key),
+        }
+    }
+
+    /// How many requests are waiting for a slot.
+    pub fn waiting(&self) -> usize {
+        let state = self.state.lock().expect("scheduler lock poisoned");
+        state.waiting.values().filter(|w| !w.granted).count()
+    }
+
+    /// Grants slots to as many waiters as the limits allow, best first.
+    fn dispatch(&self, state: &mut State) {
+        let State {
+            running,
+            per_host,
+            waiting,
+            ..
+        } = state;
+        for waiter in waiting.values_mut().filter(|w| !w.granted) {
+            if *running >= self.limits.max_requests {
+                break;
+            }
+            let host = per_host.entry(waiter.host.clone()).or_default();
+            if *host >= self.limits.max_requests_per_host {
+                continue;
+            }
+            *host += 1;
+            *running += 1;
+            waiter.granted = true;
+            if let Some(waker) = waiter.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn release(&self, host: &str) {
+        let mut state = self.state.lock().expect("scheduler lock poisoned");
+        state.running -= 1;
+        if let Some(count) = state.per_host.get_mut(host) {
+            *count -= 1;
+            if *count == 0 {
+                state.per_host.remove(host);
+            }
+        }
+        self.dispatch(&mut state);
+    }
+}
+
+/// A request waiting for a slot. See [`Scheduler::acquire`].
+#[derive(Debug)]
+pub struct Acquire {
+    scheduler: Arc<Scheduler>,
+    key: Option<(std::cmp::Reverse<Priority>, u64)>,
+}
+
+impl Future for Acquire {
+    type Output = Permit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit> {
+        let key = self
+            .key
+            .expect("TURRON BUG: polled a finished scheduler acquire");
+        let mut state = self
+            .scheduler
+            .state
+            .lock()
+            .expect("scheduler lock poisoned");
+        let waiter = state
+            .waiting
+            .get_mut(&key)
+            .expect("TURRON BUG: scheduler lost a waiter");
+        if !waiter.granted {
+            waiter.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let host = state
+            .waiting
+            .remove(&key)
+            .expect("TURRON BUG: scheduler lost a waiter")
+            .host;
+        drop(state);
+        self.key = None;
+        Poll::Ready(Permit {
+            scheduler: self.scheduler.clone(),
+            host,
+        })
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut state = self
+                .scheduler
+                .state
+                .lock()
+                .expect("scheduler lock poisoned");
+            if let Some(waiter) = state.waiting.remove(&key) {
+                if waiter.granted {
+                    drop(state);
+                    self.scheduler.release(&waiter.host);
+                }
+            }
+        }
+    }
+}
+
+/// A request slot. Dropping it lets the next request go.
+#[derive(Debug)]
+pub struct Permit {
+    scheduler: Arc<Scheduler>,
+    host: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release(&self.host);
+    }
+}
+
+/// Makes every request wait its turn with a [`Scheduler`].
+#[derive(Debug)]
+pub(crate) struct Schedule {
+    scheduler: Arc<Scheduler>,
+}
+
+impl Schedule {
+    pub(crate) fn new(scheduler: Arc<Scheduler>) -> Self {
+        Schedule { scheduler }
+    }
+}
+
+#[surf::utils::async_trait]
+impl Middleware for Schedule {
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> surf::Result<Response> {
+        let host = req.url().host_str().unwrap_or_default().to_lowercase();
+        let _permit = self.scheduler.acquire(current(), host).await;
+        next.run(req, client).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::smol::{self, future};
+    use turron_testing::TestServer;
+
+    use super::*;
+
+    fn scheduler(max_requests: usize, max_requests_per_host: usize) -> Arc<Scheduler> {
+        Arc::new(Scheduler::new(Limits {
+            max_requests,
+            max_requests_per_host,
+        }))
+    }
+
+    /// Polls `acquire` once, keeping the permit if it got one.
+    fn try_acquire(acquire: &mut Acquire) -> Option<Permit> {
+        future::block_on(future::poll_once(acquire))
+    }
+
+    #[test]
+    fn interactive_requests_jump_the_queue() {
+        let scheduler = scheduler(2, 1);
+        let mut a = scheduler.acquire(Priority::Bulk, "a");
+        let a = try_acquire(&mut a).unwrap();
+        let mut bulk = scheduler.acquire(Priority::Bulk, "a");
+        let mut interactive = scheduler.acquire(Priority::Interactive, "a");
+        let mut background = scheduler.acquire(Priority::Background, "b");
+        // Host `a` is full, but `b` isn't, so it doesn't have to wait for
+        // anything ahead of it.
+        assert!(try_acquire(&mut bulk).is_none());
+        assert!(try_acquire(&mut interactive).is_none());
+        let b = try_acquire(&mut background).unwrap();
+        assert_eq!(scheduler.waiting(), 2);
+
+        drop(a);
+        let a = try_acquire(&mut interactive).unwrap();
+        assert!(try_acquire(&mut bulk).is_none());
+
+        // Freeing up `b` doesn't help anything waiting on `a`.
+        drop(b);
+        assert!(try_acquire(&mut bulk).is_none());
+        drop(a);
+        assert!(try_acquire(&mut bulk).is_some());
+        assert_eq!(scheduler.waiting(), 0);
+    }
+
+    #[test]
+    fn cancelled_waiters_give_slots_back() {
+        let scheduler = scheduler(1, 1);
+        let mut first = scheduler.acquire(Priority::Interactive, "a");
+        let first = try_acquire(&mut first).unwrap();
+        let second = scheduler.acquire(Priority::Interactive, "a");
+        let mut third = scheduler.acquire(Priority::Bulk, "a");
+        drop(first);
+        // `second` was granted the slot, but never picked it up.
+        drop(second);
+        assert!(try_acquire(&mut third).is_some());
+    }
+
+    /// Records the order requests get past the scheduler in, and how many
+    /// are in flight at once.
+    #[derive(Debug, Clone, Default)]
+    struct Recorder {
+        order: Arc<Mutex<Vec<String>>>,
+        running: Arc<Mutex<(usize, usize)>>,
+    }
+
+    impl Recorder {
+        fn peak(&self) -> usize {
+            self.running.lock().unwrap().1
+        }
+    }
+
+    #[surf::utils::async_trait]
+    impl Middleware for Recorder {
+        async fn handle(
+            &self,
+            req: Request,
+            client: Client,
+            next: Next<'_>,
+        ) -> surf::Result<Response> {
+            self.order.lock().unwrap().push(req.url().path().into());
+            {
+                let mut running = self.running.lock().unwrap();
+                running.0 += 1;
+                running.1 = running.1.max(running.0);
+            }
+            let res = next.run(req, client).await;
+            self.running.lock().unwrap().0 -= 1;
+            res
+        }
+    }
+
+    fn client(scheduler: &Arc<Scheduler>, recorder: &Recorder) -> Client {
+        Client::new()
+            .with(Schedule::new(scheduler.clone()))
+            .with(recorder.clone())
+    }
+
+    #[test]
+    fn schedules_requests_by_priority() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let scheduler = scheduler(4, 1);
+            let recorder = Recorder::default();
+            let client = client(&scheduler, &recorder);
+
+            // Hold the server's only slot, so everything queues up behind it.
+            let mut acquire = scheduler.acquire(Priority::Interactive, "127.0.0.1");
+            let held = try_acquire(&mut acquire).unwrap();
+            let tasks = [
+                ("/bulk/1", Priority::Bulk),
+                ("/background", Priority::Background),
+                ("/bulk/2", Priority::Bulk),
+                ("/interactive/1", Priority::Interactive),
+                ("/bulk/3", Priority::Bulk),
+                ("/interactive/2", Priority::Interactive),
+            ]
+            .iter()
+            .map(|(path, priority)| {
+                let client = client.clone();
+                let url = server.url(path);
+                smol::spawn(prioritized(*priority, async move {
+                    client.get(url).await.unwrap().status()
+                }))
+            })
+            .collect::<Vec<_>>();
+            while scheduler.waiting() < tasks.len() {
+                future::yield_now().await;
+            }
+            drop(held);
+            for task in tasks {
+                task.await;
+            }
+
+            assert_eq!(
+                *recorder.order.lock().unwrap(),
+                vec![
+                    "/interactive/1",
+                    "/interactive/2",
+                    "/bulk/1",
+                    "/bulk/2",
+                    "/bulk/3",
+                    "/background",
+                ]
+            );
+            assert_eq!(recorder.peak(), 1);
+        });
+    }
+
+    #[test]
+    fn never_exceeds_the_host_limit() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let scheduler = scheduler(8, 2);
+            let recorder = Recorder::default();
+            let client = client(&scheduler, &recorder);
+            let tasks = (0..12)
+                .map(|i| {
+                    let client = client.clone();
+                    let url = server.url(&format!("/bulk/{}", i));
+                    smol::spawn(prioritized(Priority::Bulk, async move {
+                        client.get(url).await.unwrap().status()
+                    }))
+                })
+                .collect::<Vec<_>>();
+            for task in tasks {
+                task.await;
+            }
+            assert_eq!(recorder.order.lock().unwrap().len(), 12);
+            assert!(recorder.peak() <= 2);
+            assert_eq!(scheduler.waiting(), 0);
+        });
+    }
+}
@@ -0,0 +1,164 @@
+//! The files NuGet keeps next to each .nupkg in its global packages folder:
+//! `<id>.<version>.nupkg.sha512`, with the base64 SHA-512 of the .nupkg, and
+//! `.nupkg.metadata`, which restore writes last to say the package is fully
+//! extracted.
+
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+use sha2::{Digest, Sha512};
+use turron_common::{
+    paths::sanitize_filename,
+    serde::{Deserialize, Serialize},
+    serde_json,
+};
+use zip::ZipArchive;
+
+/// Name of the metadata file, in the package's directory.
+pub const METADATA_FILE: &str = ".nupkg.metadata";
+
+/// The version of `.nupkg.metadata` that current NuGet writes.
+pub const METADATA_VERSION: u32 = 2;
+
+/// What's in a `.nupkg.metadata`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NupkgMetadata {
+    pub version: u32,
+    /// Base64 SHA-512 of the package. For signed packages, NuGet leaves the
+    /// signature out of this hash, so it won't match the file's.
+    pub content_hash: String,
+    /// The source the package came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// The base64 SHA-512 of `nupkg`, as NuGet writes it.
+pub fn content_hash(nupkg: &[u8]) -> String {
+    base64::encode(Sha512::digest(nupkg))
+}
+
+/// Writes both sidecars for `nupkg_name` into `dir`, where the .nupkg
+/// itself should already be.
+///
+/// Signed packages only get the `.sha512`. NuGet hashes them without their
+/// signature for `.nupkg.metadata`, and restore just re-extracts packages
+/// that don't have one, which beats handing it a hash it won't agree with.
+pub fn write(dir: &Path, nupkg_name: &str, nupkg: &[u8], source: Option<&str>) -> io::Result<()> {
+    let hash = content_hash(nupkg);
+    fs::write(dir.join(hash_file_name(nupkg_name)), &hash)?;
+    if !is_signed(nupkg) {
+        let metadata = NupkgMetadata {
+            version: METADATA_VERSION,
+            content_hash: hash,
+            source: source.map(String::from),
+        };
+        fs::write(
+            dir.join(METADATA_FILE),
+            serde_json::to_string_pretty(&metadata)?,
+        )?;
+    }
+    Ok(())
+}
+
+/// The SHA-512 recorded for `nupkg_name` in `dir`, without reading the
+/// .nupkg. The `.sha512` is preferred, since it's always the whole file's
+/// hash.
+pub fn recorded_hash(dir: &Path, nupkg_name: &str) -> Option<String> {
+    if let Ok(hash) = fs::read_to_string(dir.join(hash_file_name(nupkg_name))) {
+        let hash = hash.trim();
+        if !hash.is_empty() {
+            return Some(hash.into());
+        }
+    }
+    read_metadata(dir).map(|metadata| metadata.content_hash)
+}
+
+/// `dir`'s `.nupkg.metadata`, if it has a readable one.
+pub fn read_metadata(dir: &Path) -> Option<NupkgMetadata> {
+    let contents = fs::read(dir.join(METADATA_FILE)).ok()?;
+    // Restore on Windows sometimes leaves a BOM.
+    let contents = contents
+        .strip_prefix(b"\xEF\xBB\xBF")
+        .unwrap_or(&contents[..]);
+    serde_json::from_slice(contents).ok()
+}
+
+fn is_signed(nupkg: &[u8]) -> bool {
+    ZipArchive::new(Cursor::new(nupkg))
+        .map(|mut zip| zip.by_name(".signature.p7s").is_ok())
+        .unwrap_or(false)
+}
+
+/// What the `.sha512` next to `nupkg_name` is called.
+fn hash_file_name(nupkg_name: &str) -> String {
+    sanitize_filename(&format!("{}.sha512", nupkg_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use turron_testing::{fixtures, NupkgBuilder};
+
+    use super::*;
+
+    #[test]
+    fn writes_what_restore_writes() {
+        let dir = tempdir().unwrap();
+        let nupkg = fixtures::nupkg_packed();
+        write(
+            dir.path(),
+            "turron.test.1.0.0.nupkg",
+            &nupkg,
+            Some("https://api.nuget.org/v3/index.json"),
+        )
+        .unwrap();
+
+        let hash = fs::read_to_string(dir.path().join("turron.test.1.0.0.nupkg.sha512")).unwrap();
+        assert_eq!(hash, base64::encode(Sha512::digest(&nupkg)));
+        let metadata: serde_json::Value =
+            serde_json::from_slice(&fs::read(dir.path().join(METADATA_FILE)).unwrap()).unwrap();
+        assert_eq!(
+            metadata,
+            serde_json::json!({
+                "version": 2,
+                "contentHash": hash,
+                "source": "https://api.nuget.org/v3/index.json",
+            })
+        );
+        assert_eq!(
+            recorded_hash(dir.path(), "turron.test.1.0.0.nupkg").as_deref(),
+            Some(&hash[..])
+        );
+    }
+
+    #[test]
+    fn reads_restores_metadata() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(METADATA_FILE), fixtures::nupkg_metadata()).unwrap();
+        let metadata = read_metadata(dir.path()).unwrap();
+        assert_eq!(metadata.version, 2);
+        assert_eq!(
+            metadata.source.as_deref(),
+            Some("https://api.nuget.org/v3/index.json")
+        );
+        // No .sha512, so the metadata's hash is all there is.
+        assert_eq!(
+            recorded_hash(dir.path(), "turron.test.1.0.0.nupkg"),
+            Some(metadata.content_hash)
+        );
+        assert_eq!(recorded_hash(&dir.path().join("nope"), "x.nupkg"), None);
+    }
+
+    #[test]
+    fn signed_packages_only_get_a_sha512() {
+        let dir = tempdir().unwrap();
+        let nupkg = NupkgBuilder::new("Turron.Test", "1.0.0")
+            .file(".signature.p7s", vec![0u8; 16])
+            .build();
+        write(dir.path(), "turron.test.1.0.0.nupkg", &nupkg, None).unwrap();
+        assert!(dir.path().join("turron.test.1.0.0.nupkg.sha512").exists());
+        assert!(!dir.path().join(METADATA_FILE).exists());
+    }
+}
@@ -0,0 +1,182 @@
+use turron_common::{
+    serde::{Deserialize, Serialize},
+    surf::StatusCode,
+};
+
+use crate::errors::NuGetApiError;
+use crate::v3::{encoding::from_json_response, NuGetClient};
+
+impl NuGetClient {
+    /// Autocompletes package IDs matching `query.query`, or, if `query.id` is
+    /// set, lists all versions of that package.
+    pub async fn autocomplete(
+        &self,
+        query: AutocompleteQuery,
+    ) -> Result<AutocompleteResponse, NuGetApiError> {
+        use NuGetApiError::*;
+        let mut url = self
+            .endpoints
+            .autocomplete
+            .clone()
+            .ok_or_else(|| UnsupportedEndpoint("SearchAutocompleteService/3.5.0".into()))?;
+        {
+            let mut pairs = url.query_pairs_mut();
+            pairs.append_pair("semVerLevel", "2.0.0");
+            if let Some(id) = query.id {
+                pairs.append_pair("id", &id);
+            } else if let Some(query) = query.query {
+                pairs.append_pair("q", &query);
+            }
+            if let Some(skip) = query.skip {
+                pairs.append_pair("skip", &skip.to_string());
+            }
+            if let Some(take) = query.take {
+                pairs.append_pair("take", &take.to_string());
+            }
+            if let Some(prerelease) = query.prerelease {
+                pairs.append_pair("prerelease", &prerelease.to_string());
+            }
+            if let Some(package_type) = query.package_type {
+                pairs.append_pair("packageType", &package_type);
+            }
+        }
+
+        let (status, body) = self.get_shared(&url).await?;
+
+        match status {
+            StatusCode::Ok => from_json_response(&body, url.as_str()),
+            StatusCode::NotFound => Err(PackageNotFound),
+            code => Err(BadResponse(code)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AutocompleteQuery {
+    /// Package ID prefix to autocomplete.
+    pub query: Option<String>,
+    /// Package ID to enumerate versions for. Takes precedence over `query`.
+    pub id: Option<String>,
+    pub skip: Option<usize>,
+    pub take: Option<usize>,
+    pub prerelease: Option<bool>,
+    pub package_type: Option<String>,
+}
+
+impl AutocompleteQuery {
+    pub fn from_query(query: impl AsRef<str>) -> Self {
+        Self {
+            query: Some(query.as_ref().to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn versions_of(id: impl AsRef<str>) -> Self {
+        Self {
+            id: Some(id.as_ref().to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutocompleteResponse {
+    /// Only present for package ID autocompletion.
+    pub total_hits: Option<usize>,
+    /// Package IDs, or versions if enumerating versions.
+    pub data: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::{serde_json::json, smol};
+    use turron_testing::TestServer;
+
+    use super::*;
+
+    fn autocomplete_requests(server: &TestServer) -> Vec<String> {
+        server
+            .requests()
+            .into_iter()
+            .filter(|req| req.starts_with("/autocomplete?"))
+            .collect()
+    }
+
+    #[test]
+    fn autocompletes_ids() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.json(
+                "/autocomplete",
+                &json!({ "totalHits": 2, "data": ["Newtonsoft.Json", "Newtonsoft.Json.Bson"] }),
+            );
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let response = client
+                .autocomplete(AutocompleteQuery {
+                    take: Some(2),
+                    prerelease: Some(true),
+                    ..AutocompleteQuery::from_query("newtonsoft")
+                })
+                .await
+                .unwrap();
+            assert_eq!(response.total_hits, Some(2));
+            assert_eq!(
+                response.data,
+                vec!["Newtonsoft.Json", "Newtonsoft.Json.Bson"]
+            );
+            assert_eq!(
+                autocomplete_requests(&server),
+                vec!["/autocomplete?semVerLevel=2.0.0&q=newtonsoft&take=2&prerelease=true"]
+            );
+        });
+    }
+
+    #[test]
+    fn lists_versions_of_an_id() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.json(
+                "/autocomplete",
+                &json!({ "data": ["1.0.0", "2.0.0-beta.1"] }),
+            );
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            // `id` wins over `q` when both are set.
+            let response = client
+                .autocomplete(AutocompleteQuery {
+                    query: Some("ignored".into()),
+                    ..AutocompleteQuery::versions_of("Foo")
+                })
+                .await
+                .unwrap();
+            assert_eq!(response.total_hits, None);
+            assert_eq!(response.data, vec!["1.0.0", "2.0.0-beta.1"]);
+            assert_eq!(
+                autocomplete_requests(&server),
+                vec!["/autocomplete?semVerLevel=2.0.0&id=Foo"]
+            );
+        });
+    }
+
+    #[test]
+    fn maps_error_statuses() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            assert!(matches!(
+                client
+                    .autocomplete(AutocompleteQuery::versions_of("Missing"))
+                    .await,
+                Err(NuGetApiError::PackageNotFound)
+            ));
+
+            server.respond("/autocomplete", 400, "bad query");
+            assert!(matches!(
+                client
+                    .autocomplete(AutocompleteQuery::from_query("foo"))
+                    .await,
+                Err(NuGetApiError::BadResponse(StatusCode::BadRequest))
+            ));
+        });
+    }
+}
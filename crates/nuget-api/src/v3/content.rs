@@ -1,18 +1,24 @@
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use dotnet_semver::Version;
+use dotnet_semver::{Range, Version};
 pub use turron_common::surf::Body;
 use turron_common::{
     quick_xml,
-    serde::{Deserialize, Serialize},
-    serde_json, smol,
+    serde::{de, Deserialize, Deserializer, Serialize},
+    smol::{
+        self,
+        io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+        Timer, Unblock,
+    },
     surf::{self, StatusCode, Url},
+    tracing,
 };
 use zip::ZipArchive;
 
 use crate::errors::NuGetApiError;
-use crate::v3::NuGetClient;
+use crate::v3::{encoding::from_json_response, retry::rate_limited, unavailable, NuGetClient};
 
 impl NuGetClient {
     pub async fn versions(
@@ -30,74 +36,295 @@ impl NuGetClient {
                 &package_id.as_ref().to_lowercase()
             ))?;
 
-        let req = surf::get(url.clone());
+        let (status, body) = self.get_shared(&url).await?;
+
+        match status {
+            StatusCode::Ok => {
+                Ok(from_json_response::<PackageVersions>(&body, url.as_str())?.versions)
+            }
+            StatusCode::NotFound => Err(PackageNotFound),
+            code => Err(BadResponse(code)),
+        }
+    }
+
+    /// The highest version of `package_id`, or `None` if it has nothing to
+    /// pick from. Prereleases only count with `include_prerelease`. Like
+    /// everything else from the flat container, this includes unlisted
+    /// versions.
+    pub async fn latest_version(
+        &self,
+        package_id: impl AsRef<str>,
+        include_prerelease: bool,
+    ) -> Result<Option<Version>, NuGetApiError> {
+        Ok(self
+            .versions(package_id)
+            .await?
+            .into_iter()
+            .filter(|version| include_prerelease || !version.is_prerelease())
+            .max())
+    }
+
+    /// Whether `version` of `package_id` can be downloaded, without
+    /// downloading it. This asks for its .nupkg with a `HEAD`, or, on hosts
+    /// that don't do those, a `GET` whose body is never read.
+    pub async fn exists(
+        &self,
+        package_id: impl AsRef<str>,
+        version: &Version,
+    ) -> Result<bool, NuGetApiError> {
+        use NuGetApiError::*;
+        let url = self.nupkg_url(package_id.as_ref(), version)?;
+
+        if let Some(cache) = &self.cache {
+            match cache.load(&url).await {
+                Some(_) => return Ok(true),
+                None if cache.is_offline() => return Err(Offline(url.to_string())),
+                None => {}
+            }
+        }
 
         let mut res = self
-            .client
-            .send(req)
+            .retries
+            .run(&url, || self.client.send(surf::head(&url)))
             .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
+            .map_err(|e| SurfError(e, url.clone().into()))?;
+        if matches!(
+            res.status(),
+            StatusCode::MethodNotAllowed | StatusCode::NotImplemented
+        ) {
+            res = self
+                .retries
+                .run(&url, || self.client.send(surf::get(&url)))
+                .await
+                .map_err(|e| SurfError(e, url.clone().into()))?;
+        }
 
         match res.status() {
-            StatusCode::Ok => {
-                let body = res
-                    .body_string()
-                    .await
-                    .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
-                Ok(serde_json::from_str::<PackageVersions>(&body)
-                    .map_err(|e| NuGetApiError::from_json_err(e, url.into(), body))?
-                    .versions)
+            StatusCode::Ok if unavailable::is_error_page(&res, &url) => {
+                Err(unavailable::error_page(&res, &url))
+            }
+            StatusCode::Ok => Ok(true),
+            StatusCode::NotFound => Ok(false),
+            StatusCode::TooManyRequests => Err(rate_limited(&res, &url)),
+            StatusCode::UnavailableForLegalReasons => {
+                Err(unavailable::legally_unavailable(&res, &url))
             }
-            StatusCode::NotFound => Err(PackageNotFound),
             code => Err(BadResponse(code)),
         }
     }
 
+    /// Checks the flat container every `interval` until `version` of
+    /// `package_id` is listed, or `timeout` runs out. Returns whether it
+    /// showed up.
+    ///
+    /// Sources like nuget.org take a few minutes to index freshly pushed
+    /// packages, so a package that isn't there at all yet is waited on too.
+    pub async fn wait_for_version(
+        &self,
+        package_id: impl AsRef<str>,
+        version: &Version,
+        timeout: Duration,
+        interval: Duration,
+    ) -> Result<bool, NuGetApiError> {
+        let package_id = package_id.as_ref();
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.versions(package_id).await {
+                Ok(versions) if versions.contains(version) => return Ok(true),
+                Ok(_) | Err(NuGetApiError::PackageNotFound) => {}
+                Err(err) => return Err(err),
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            let wait = interval.min(deadline - now);
+            tracing::info!(
+                "{}@{} isn't available yet. Checking again in {:?}.",
+                package_id,
+                version,
+                wait
+            );
+            Timer::after(wait).await;
+        }
+    }
+
     pub async fn nupkg(
         &self,
         package_id: impl AsRef<str>,
         version: &Version,
     ) -> Result<Vec<u8>, NuGetApiError> {
+        Ok(self
+            .shared_nupkg(package_id.as_ref(), version)
+            .await?
+            .to_vec())
+    }
+
+    /// Streams a package's .nupkg into `writer` in chunks, without holding
+    /// the whole thing in memory. `progress` is called with the number of
+    /// bytes written so far and, if the source sent a `Content-Length`, the
+    /// total size. Returns the number of bytes written.
+    pub async fn nupkg_to_writer<W, P>(
+        &self,
+        package_id: impl AsRef<str>,
+        version: &Version,
+        mut writer: W,
+        mut progress: P,
+    ) -> Result<u64, NuGetApiError>
+    where
+        W: AsyncWrite + Unpin,
+        P: FnMut(u64, Option<u64>),
+    {
+        use NuGetApiError::*;
+        let url = self.nupkg_url(package_id.as_ref(), version)?;
+
+        if let Some(cache) = &self.cache {
+            match cache.load_path(&url).await {
+                // Streamed from the file too, so a cache hit doesn't pull
+                // the whole package into memory either.
+                Some((entry, path)) => {
+                    let total = Some(entry.size);
+                    let mut file = Unblock::new(std::fs::File::open(path)?);
+                    let mut buf = vec![0u8; NUPKG_CHUNK_SIZE];
+                    let mut written = 0;
+                    progress(written, total);
+                    loop {
+                        let read = file.read(&mut buf).await?;
+                        if read == 0 {
+                            break;
+                        }
+                        writer.write_all(&buf[..read]).await?;
+                        written += read as u64;
+                        progress(written, total);
+                    }
+                    writer.flush().await?;
+                    return Ok(written);
+                }
+                None if cache.is_offline() => return Err(Offline(url.to_string())),
+                None => {}
+            }
+        }
+
+        let mut res = self
+            .retries
+            .run(&url, || self.client.send(surf::get(&url)))
+            .await
+            .map_err(|e| SurfError(e, url.clone().into()))?;
+
+        match res.status() {
+            StatusCode::Ok if unavailable::is_error_page(&res, &url) => {
+                return Err(unavailable::error_page(&res, &url))
+            }
+            StatusCode::Ok => {}
+            StatusCode::NotFound => return Err(PackageNotFound),
+            StatusCode::TooManyRequests => return Err(rate_limited(&res, &url)),
+            StatusCode::UnavailableForLegalReasons => {
+                return Err(unavailable::legally_unavailable(&res, &url))
+            }
+            code => return Err(BadResponse(code)),
+        }
+
+        let total = res.len().map(|len| len as u64);
+        let mut body = res.take_body();
+        let mut buf = vec![0u8; NUPKG_CHUNK_SIZE];
+        let mut written = 0;
+        // Versioned nupkgs never change, so they're cached as they stream
+        // by. A cache that can't keep up just stops caching.
+        let mut cached = self.cache.as_ref().and_then(|cache| {
+            cache
+                .writer(&url, None)
+                .map_err(|err| tracing::debug!("Failed to cache {}: {}", url, err))
+                .ok()
+        });
+        progress(written, total);
+        loop {
+            let read = body.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buf[..read]).await?;
+            if let Some(cache_writer) = &mut cached {
+                if let Err(err) = cache_writer.write_all(&buf[..read]) {
+                    tracing::debug!("Failed to cache {}: {}", url, err);
+                    cached = None;
+                }
+            }
+            written += read as u64;
+            progress(written, total);
+        }
+        writer.flush().await?;
+        if let Some(Err(err)) = cached.map(|cache_writer| cache_writer.commit()) {
+            tracing::debug!("Failed to cache {}: {}", url, err);
+        }
+        Ok(written)
+    }
+
+    /// The whole .nupkg, from the per-client memo if an earlier call (like
+    /// reading a readme out of it) already downloaded it. Downloads are
+    /// memoized too, so files read out of it later don't download it again.
+    async fn shared_nupkg(
+        &self,
+        package_id: &str,
+        version: &Version,
+    ) -> Result<Arc<[u8]>, NuGetApiError> {
         use NuGetApiError::*;
 
+        if let Some(data) = self.memoized_nupkg(package_id, version) {
+            return Ok(data);
+        }
+
+        let url = self.nupkg_url(package_id, version)?;
+        let (status, body) = self.get_shared(&url).await?;
+
+        match status {
+            StatusCode::Ok => {
+                self.memoize_nupkg(package_id, version, body.clone());
+                Ok(body)
+            }
+            StatusCode::NotFound => Err(PackageNotFound),
+            code => Err(BadResponse(code)),
+        }
+    }
+
+    /// The memoized .nupkg for `version` of `package_id`, if there is one.
+    fn memoized_nupkg(&self, package_id: &str, version: &Version) -> Option<Arc<[u8]>> {
+        let data = self
+            .nupkg_memo
+            .lock()
+            .expect("nupkg memo lock poisoned")
+            .get(package_id, version)?;
+        tracing::debug!("Reusing memoized nupkg for {}@{}", package_id, version);
+        Some(data)
+    }
+
+    /// Memoizes `data` as the .nupkg for `version` of `package_id`, if the
+    /// memo has room for it.
+    fn memoize_nupkg(&self, package_id: &str, version: &Version, data: Arc<[u8]>) {
+        let mut memo = self.nupkg_memo.lock().expect("nupkg memo lock poisoned");
+        if memo.would_keep(data.len()) {
+            memo.insert(package_id, version, data);
+        }
+    }
+
+    /// Where the flat container keeps `version` of `package_id`.
+    pub fn nupkg_url(&self, package_id: &str, version: &Version) -> Result<Url, NuGetApiError> {
         // Version needs to undergo "normalization", which means lower-casing
         // and blowing away build.
         let mut version = version.clone();
         version.build.clear();
 
-        let url = self
+        Ok(self
             .endpoints
             .package_content
             .clone()
-            .ok_or_else(|| UnsupportedEndpoint("PackageBaseAddress/3.0.0".into()))?
+            .ok_or_else(|| NuGetApiError::UnsupportedEndpoint("PackageBaseAddress/3.0.0".into()))?
             .join(&format!(
                 "{}/{}/{}.{}.nupkg",
-                &package_id.as_ref().to_lowercase(),
+                &package_id.to_lowercase(),
                 version.to_string().to_lowercase(),
-                &package_id.as_ref().to_lowercase(),
+                &package_id.to_lowercase(),
                 version.to_string().to_lowercase(),
-            ))?;
-
-        let req = surf::get(url.clone());
-
-        let mut res = self
-            .client
-            .send(req)
-            .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
-
-        match res.status() {
-            StatusCode::Ok => {
-                let body = res
-                    .body_bytes()
-                    .await
-                    .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
-                // TODO: I'm so sorry. The zip parser is sync :(
-                Ok(body)
-            }
-            StatusCode::NotFound => Err(PackageNotFound),
-            code => Err(BadResponse(code)),
-        }
+            ))?)
     }
 
     pub async fn nuspec(
@@ -124,20 +351,11 @@ impl NuGetClient {
                 &package_id.as_ref().to_lowercase(),
             ))?;
 
-        let req = surf::get(url.clone());
-
-        let mut res = self
-            .client
-            .send(req)
-            .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
+        let (status, body) = self.get_shared(&url).await?;
 
-        match res.status() {
+        match status {
             StatusCode::Ok => {
-                let body = res
-                    .body_string()
-                    .await
-                    .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
+                let body = String::from_utf8_lossy(&body).into_owned();
                 Ok(
                     quick_xml::de::from_str(&body).map_err(|e| NuGetApiError::BadXml {
                         source: e,
@@ -160,25 +378,204 @@ impl NuGetClient {
         let package_id = package_id.as_ref().to_string();
         let filename = filename.as_ref().to_lowercase();
         let version = version.clone();
-        let nupkg = Cursor::new(self.nupkg(&package_id, &version).await?);
-        smol::unblock(move || {
-            let mut zip = ZipArchive::new(nupkg)?;
-            for i in 0..zip.len() {
-                let mut file = zip.by_index(i)?;
-                if file.is_file() && file.name().to_lowercase() == filename {
-                    let mut buf = Vec::new();
-                    file.read_to_end(&mut buf)?;
-                    return Ok(buf);
-                }
+
+        if let Some(data) = self.memoized_nupkg(&package_id, &version) {
+            return smol::unblock(move || {
+                find_in_nupkg(Cursor::new(data), package_id, version, filename)
+            })
+            .await;
+        }
+
+        // Spool the package to disk rather than memory, since the zip
+        // parser needs to seek around in it.
+        let mut spool = Unblock::new(smol::unblock(tempfile::tempfile).await?);
+        let len = self
+            .nupkg_to_writer(&package_id, &version, &mut spool, |_, _| {})
+            .await?;
+        let mut file = spool.into_inner().await;
+        let keep = self
+            .nupkg_memo
+            .lock()
+            .expect("nupkg memo lock poisoned")
+            .would_keep(len as usize);
+        let (id, ver) = (package_id.clone(), version.clone());
+        let (found, data) = smol::unblock(move || {
+            file.seek(SeekFrom::Start(0))?;
+            if keep {
+                let mut data = Vec::with_capacity(len as usize);
+                file.read_to_end(&mut data)?;
+                let data: Arc<[u8]> = data.into();
+                let found = find_in_nupkg(Cursor::new(data.clone()), id, ver, filename);
+                Ok::<_, NuGetApiError>((found, Some(data)))
+            } else {
+                Ok((find_in_nupkg(file, id, ver, filename), None))
             }
-            Err(NuGetApiError::FileNotFound(
-                package_id,
-                version.clone(),
-                filename,
-            ))
         })
-        .await
+        .await?;
+        if let Some(data) = data {
+            self.memoize_nupkg(&package_id, &version, data);
+        }
+        found
+    }
+
+    /// The package's readme, if it has one. Sources that advertise
+    /// `ReadmeUriTemplate` get asked for it directly. Otherwise, it comes out
+    /// of the .nupkg, wherever the nuspec says it is.
+    pub async fn readme(
+        &self,
+        package_id: impl AsRef<str>,
+        version: &Version,
+    ) -> Result<Option<String>, NuGetApiError> {
+        let package_id = package_id.as_ref();
+        let data = match self.readme_from_template(package_id, version).await? {
+            Some(data) => data,
+            None => {
+                let nuspec = self.nuspec(package_id, version).await?;
+                match &nuspec.metadata.readme {
+                    Some(path) => match self.file_from_nupkg(package_id, version, path).await? {
+                        Some(data) => data,
+                        None => return Ok(None),
+                    },
+                    None => return Ok(None),
+                }
+            }
+        };
+        let data = match data.strip_prefix(UTF8_BOM) {
+            Some(data) => data.to_vec(),
+            None => data,
+        };
+        String::from_utf8(data)
+            .map(Some)
+            .map_err(|err| NuGetApiError::InvalidUtf8(package_id.into(), version.clone(), err))
+    }
+
+    /// The package's embedded icon, if it has one, out of the .nupkg. The
+    /// deprecated `iconUrl` isn't followed here, since it can point
+    /// anywhere; see [`NuGetClient::icon_from_url`].
+    pub async fn icon(
+        &self,
+        package_id: impl AsRef<str>,
+        version: &Version,
+    ) -> Result<Option<Vec<u8>>, NuGetApiError> {
+        let package_id = package_id.as_ref();
+        let nuspec = self.nuspec(package_id, version).await?;
+        match &nuspec.metadata.icon {
+            Some(path) => self.file_from_nupkg(package_id, version, path).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Downloads an icon from a nuspec's `iconUrl`, which can be on any host
+    /// at all.
+    pub async fn icon_from_url(&self, url: &Url) -> Result<Vec<u8>, NuGetApiError> {
+        let (status, body) = self.get_shared(url).await?;
+        match status {
+            StatusCode::Ok => Ok(body.to_vec()),
+            status => Err(NuGetApiError::IconUrlFailed {
+                url: url.to_string(),
+                status,
+            }),
+        }
     }
+
+    /// Reads a file the nuspec points at out of the .nupkg. `None` if it
+    /// isn't actually there.
+    async fn file_from_nupkg(
+        &self,
+        package_id: &str,
+        version: &Version,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>, NuGetApiError> {
+        match self
+            .nupkg_entry_ranged(package_id, version, nupkg_path(path))
+            .await
+        {
+            Ok(data) => Ok(Some(data)),
+            Err(NuGetApiError::FileNotFound(..)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Fetches the readme from the source's `ReadmeUriTemplate`, if it has
+    /// one. Anything but a 200 falls back to the .nupkg, since not every
+    /// source that advertises the template fills it in for every package.
+    async fn readme_from_template(
+        &self,
+        package_id: &str,
+        version: &Version,
+    ) -> Result<Option<Vec<u8>>, NuGetApiError> {
+        let template = match &self.endpoints.readme {
+            Some(template) => template,
+            None => return Ok(None),
+        };
+        let url = fill_template(template, package_id, version)?;
+        let (status, body) = self.get_shared(&url).await?;
+        match status {
+            StatusCode::Ok => Ok(Some(body.to_vec())),
+            code => {
+                tracing::debug!(
+                    "{} responded with {}. Looking for the readme in the .nupkg instead.",
+                    url,
+                    code
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Turns a path from a nuspec into the one its file has in the .nupkg.
+/// Nuspecs written on Windows sometimes use backslashes, and file lookups
+/// ignore case.
+fn nupkg_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_lowercase()
+}
+
+/// Fills in a `{lower_id}`/`{lower_version}` URI template, like
+/// `ReadmeUriTemplate`'s. The braces may have been percent-encoded when the
+/// template was parsed as a URL.
+fn fill_template(
+    template: &Url,
+    package_id: &str,
+    version: &Version,
+) -> Result<Url, NuGetApiError> {
+    let mut version = version.clone();
+    version.build.clear();
+    let id = package_id.to_lowercase();
+    let version = version.to_string().to_lowercase();
+    let url = template
+        .as_str()
+        .replace("{lower_id}", &id)
+        .replace("%7Blower_id%7D", &id)
+        .replace("{lower_version}", &version)
+        .replace("%7Blower_version%7D", &version);
+    Ok(Url::parse(&url)?)
+}
+
+/// How much of a .nupkg to read from the network at a time.
+const NUPKG_CHUNK_SIZE: usize = 64 * 1024;
+
+fn find_in_nupkg<R: Read + Seek>(
+    nupkg: R,
+    package_id: String,
+    version: Version,
+    filename: String,
+) -> Result<Vec<u8>, NuGetApiError> {
+    let mut zip = ZipArchive::new(nupkg)?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if file.is_file() && file.name().to_lowercase() == filename {
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            return Ok(buf);
+        }
+    }
+    Err(NuGetApiError::FileNotFound(package_id, version, filename))
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -186,15 +583,16 @@ pub struct PackageVersions {
     pub versions: Vec<Version>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename = "package")]
 pub struct NuSpec {
     pub metadata: NuSpecMetadata,
-    #[serde(default)]
-    pub files: Vec<NuSpecFile>,
+    /// What to pack, in a nuspec that's being packed. Nuspecs inside
+    /// .nupkgs don't have this.
+    pub files: Option<NuSpecFiles>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NuSpecMetadata {
     // Required fields
     #[serde(rename = "$unflatten=id", default)]
@@ -212,6 +610,8 @@ pub struct NuSpecMetadata {
     pub min_client_version: Option<Version>,
 
     // Optional fields
+    #[serde(rename = "$unflatten=title")]
+    pub title: Option<String>,
     // TODO: comma-separated
     #[serde(rename = "$unflatten=owners")]
     pub owners: Option<String>,
@@ -247,16 +647,16 @@ pub struct NuSpecMetadata {
     #[serde(rename = "$unflatten=dependencies")]
     pub dependencies: Option<NuSpecDependencies>,
     #[serde(rename = "$unflatten=frameworkAssemblies")]
-    pub framework_assemblies: Option<Vec<NuSpecFrameworkAssembly>>,
+    pub framework_assemblies: Option<NuSpecFrameworkAssemblies>,
     #[serde(rename = "$unflatten=packageTypes")]
-    pub package_types: Option<Vec<NuSpecPackageType>>,
+    pub package_types: Option<NuSpecPackageTypes>,
     #[serde(rename = "$unflatten=references")]
-    pub references: Option<Vec<NuSpecReference>>,
+    pub references: Option<NuSpecReferences>,
     #[serde(rename = "$unflatten=contentFiles")]
-    pub content_files: Option<Vec<NuSpecContentFiles>>,
+    pub content_files: Option<NuSpecContentFiles>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NuSpecRepository {
     #[serde(rename = "type")]
     pub repo_type: Option<String>,
@@ -265,71 +665,118 @@ pub struct NuSpecRepository {
     pub commit: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NuSpecFiles {
+    #[serde(rename = "file", default)]
+    pub files: Vec<NuSpecFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NuSpecFile {
     pub src: String,
-    pub target: String,
-    pub exclude: String,
+    pub target: Option<String>,
+    pub exclude: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NuSpecDependencies {
     #[serde(rename = "$unflatten=group", default)]
-    groups: Vec<NuSpecDependencyGroup>,
+    pub groups: Vec<NuSpecDependencyGroup>,
+    /// Dependencies outside of any group, from nuspecs that don't target
+    /// particular frameworks.
     #[serde(rename = "$unflatten=dependency", default)]
-    dependencies: Vec<NuSpecDependency>,
+    pub dependencies: Vec<NuSpecDependency>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NuSpecDependencyGroup {
-    target_framework: Option<String>,
+    pub target_framework: Option<String>,
     #[serde(rename = "dependency", default)]
-    dependencies: Vec<NuSpecDependency>,
+    pub dependencies: Vec<NuSpecDependency>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NuSpecDependency {
     pub id: String,
-    pub version: Version,
+    /// A NuGet range, like `[13.0.1, )`. A plain `1.2.3` means `>= 1.2.3`,
+    /// and no version at all means any.
+    #[serde(default = "Range::any", deserialize_with = "nuspec_range")]
+    pub version: Range,
     pub exclude: Option<String>,
     pub include: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Reads a dependency's `version` attribute as a range. An empty one means
+/// any version, like a missing one does.
+fn nuspec_range<'de, D>(deserializer: D) -> Result<Range, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    if text.trim().is_empty() {
+        return Ok(Range::any());
+    }
+    text.parse().map_err(de::Error::custom)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NuSpecFrameworkAssemblies {
+    #[serde(rename = "frameworkAssembly", default)]
+    pub assemblies: Vec<NuSpecFrameworkAssembly>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NuSpecFrameworkAssembly {
     pub assembly_name: Option<String>,
     pub target_framework: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub enum NuSpecPackageType {
-    Dependency,
-    DotnetTool,
-    Template,
-    #[serde(other)]
-    Unknown,
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NuSpecPackageTypes {
+    #[serde(rename = "packageType", default)]
+    pub package_types: Vec<NuSpecPackageType>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub enum NuSpecReferenceOrGroup {
-    Group {
-        #[serde(rename = "targetFramework")]
-        target_framework: String,
-        #[serde(rename = "reference", default)]
-        references: Vec<NuSpecReference>,
-    },
-    Reference(NuSpecReference),
+/// Like `Dependency`, `DotnetTool`, or `Template`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NuSpecPackageType {
+    pub name: String,
+    pub version: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NuSpecReferences {
+    #[serde(rename = "group", default)]
+    pub groups: Vec<NuSpecReferenceGroup>,
+    /// References outside of any group, which apply to every framework.
+    #[serde(rename = "reference", default)]
+    pub references: Vec<NuSpecReference>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NuSpecReferenceGroup {
+    #[serde(rename = "targetFramework")]
+    pub target_framework: Option<String>,
+    #[serde(rename = "reference", default)]
+    pub references: Vec<NuSpecReference>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NuSpecReference {
     pub file: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NuSpecContentFiles {
+    #[serde(rename = "files", default)]
+    pub files: Vec<NuSpecContentFile>,
+}
+
+/// One `<files>` element in `<contentFiles>`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NuSpecContentFile {
     pub include: String,
     pub exclude: Option<String>,
     #[serde(rename = "buildAction")]
@@ -338,3 +785,399 @@ pub struct NuSpecContentFiles {
     pub copy_to_output: Option<bool>,
     pub flatten: Option<bool>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use turron_common::{serde_json::json, smol::io};
+    use turron_testing::{fixtures, NupkgBuilder, TestServer};
+
+    use super::*;
+
+    /// Counts writes instead of keeping what's written.
+    #[derive(Default)]
+    struct CountingWriter {
+        writes: usize,
+        largest: usize,
+        total: usize,
+    }
+
+    impl AsyncWrite for CountingWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.writes += 1;
+            self.largest = self.largest.max(buf.len());
+            self.total += buf.len();
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// A nuspec for Foo with an `iconUrl` and no embedded icon.
+    fn icon_url_nuspec(version: &str, icon_url: &str) -> String {
+        format!(
+            concat!(
+                "<package><metadata>",
+                "<id>Foo</id><version>{}</version>",
+                "<authors>turron</authors><description>Test package.</description>",
+                "<iconUrl>{}</iconUrl>",
+                "</metadata></package>",
+            ),
+            version, icon_url
+        )
+    }
+
+    #[test]
+    fn waits_for_new_versions() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let path = "/v3-flatcontainer/foo/index.json";
+            server
+                .respond_once(path, 404, "")
+                .respond_once(path, 200, r#"{"versions": ["1.0.0"]}"#)
+                .route(path, r#"{"versions": ["1.0.0", "1.1.0"]}"#);
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let version = "1.1.0".parse().unwrap();
+            let interval = Duration::from_millis(10);
+
+            assert!(client
+                .wait_for_version("Foo", &version, Duration::from_secs(5), interval)
+                .await
+                .unwrap());
+            assert_eq!(server.hits(path), 3);
+
+            let missing = "2.0.0".parse().unwrap();
+            assert!(!client
+                .wait_for_version("Foo", &missing, Duration::from_millis(50), interval)
+                .await
+                .unwrap());
+        });
+    }
+
+    #[test]
+    fn parses_dependency_ranges() {
+        let range = |text: &str| text.parse::<Range>().unwrap();
+        let versions = |deps: &[NuSpecDependency]| {
+            deps.iter()
+                .map(|dep| (dep.id.clone(), dep.version.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let xunit = quick_xml::de::from_str::<NuSpec>(fixtures::xunit_nuspec()).unwrap();
+        let deps = xunit.metadata.dependencies.unwrap();
+        assert!(deps.groups.is_empty());
+        assert_eq!(
+            versions(&deps.dependencies),
+            vec![
+                ("xunit.analyzers".into(), range("0.10.0")),
+                ("xunit.assert".into(), range("[2.4.1]")),
+                ("xunit.core".into(), range("[2.4.1]")),
+            ]
+        );
+        assert!(deps.dependencies[0]
+            .version
+            .satisfies(&"0.11.0".parse().unwrap()));
+        assert!(!deps.dependencies[1]
+            .version
+            .satisfies(&"2.4.2".parse().unwrap()));
+
+        let ranges = quick_xml::de::from_str::<NuSpec>(fixtures::ranges_nuspec()).unwrap();
+        let groups = ranges.metadata.dependencies.unwrap().groups;
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].target_framework.as_deref(), Some("net6.0"));
+        assert_eq!(
+            versions(&groups[0].dependencies),
+            vec![
+                ("Newtonsoft.Json".into(), range("[13.0.1, )")),
+                ("Serilog".into(), range("2.*")),
+                ("System.Memory".into(), range("4.5.4")),
+                ("Polly".into(), range("(7.0.0,8.0.0]")),
+                ("Anything.Goes".into(), Range::any()),
+                ("Also.Anything".into(), Range::any()),
+            ]
+        );
+        assert!(groups[0].dependencies[1].version.is_floating());
+        assert_eq!(
+            groups[0].dependencies[2].exclude.as_deref(),
+            Some("Build,Analyzers")
+        );
+        assert!(groups[1].dependencies.is_empty());
+
+        let newtonsoft = quick_xml::de::from_str::<NuSpec>(fixtures::nuspec()).unwrap();
+        let groups = newtonsoft.metadata.dependencies.unwrap().groups;
+        assert_eq!(groups[1].dependencies[1].id, "NETStandard.Library");
+        assert_eq!(groups[1].dependencies[1].version, range("1.6.1"));
+
+        let bad = fixtures::ranges_nuspec().replace("2.*", "not a range");
+        assert!(quick_xml::de::from_str::<NuSpec>(&bad).is_err());
+    }
+
+    #[test]
+    fn finds_latest_versions() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.route(
+                "/v3-flatcontainer/foo/index.json",
+                r#"{"versions": ["1.0.0", "1.10.0", "1.9.0", "2.0.0-beta.2", "2.0.0-beta.10"]}"#,
+            );
+            server.route(
+                "/v3-flatcontainer/bar/index.json",
+                r#"{"versions": ["0.1.0-alpha"]}"#,
+            );
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            assert_eq!(
+                client.latest_version("Foo", false).await.unwrap(),
+                Some("1.10.0".parse().unwrap())
+            );
+            assert_eq!(
+                client.latest_version("Foo", true).await.unwrap(),
+                Some("2.0.0-beta.10".parse().unwrap())
+            );
+            assert_eq!(client.latest_version("Bar", false).await.unwrap(), None);
+            assert!(matches!(
+                client.latest_version("Baz", true).await,
+                Err(NuGetApiError::PackageNotFound)
+            ));
+        });
+    }
+
+    #[test]
+    fn checks_existence_without_downloading() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let nupkg = NupkgBuilder::new("Foo", "1.0.0").build();
+            server.route("/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg", nupkg);
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let served = server.bytes_served();
+
+            assert!(client
+                .exists("Foo", &"1.0.0".parse().unwrap())
+                .await
+                .unwrap());
+            assert!(!client
+                .exists("Foo", &"2.0.0".parse().unwrap())
+                .await
+                .unwrap());
+            assert_eq!(server.bytes_served(), served);
+
+            server.respond("/v3-flatcontainer/foo/3.0.0/foo.3.0.0.nupkg", 451, "");
+            assert!(matches!(
+                client.exists("Foo", &"3.0.0".parse().unwrap()).await,
+                Err(NuGetApiError::LegallyUnavailable { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn streams_nupkg_in_chunks() {
+        smol::block_on(async {
+            let size = 8 * 1024 * 1024;
+            let server = TestServer::start().await;
+            server.route(
+                "/v3-flatcontainer/big/1.0.0/big.1.0.0.nupkg",
+                vec![7u8; size],
+            );
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let version = "1.0.0".parse().unwrap();
+
+            let mut writer = CountingWriter::default();
+            let mut reports = Vec::new();
+            let written = client
+                .nupkg_to_writer("Big", &version, &mut writer, |done, total| {
+                    reports.push((done, total))
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(written, size as u64);
+            assert_eq!(writer.total, size);
+            assert!(writer.writes >= size / NUPKG_CHUNK_SIZE);
+            assert!(writer.largest <= NUPKG_CHUNK_SIZE);
+            assert_eq!(reports.first(), Some(&(0, Some(size as u64))));
+            assert_eq!(reports.last(), Some(&(size as u64, Some(size as u64))));
+            assert_eq!(reports.len(), writer.writes + 1);
+
+            assert!(matches!(
+                client
+                    .nupkg_to_writer("missing", &version, CountingWriter::default(), |_, _| {})
+                    .await,
+                Err(NuGetApiError::PackageNotFound)
+            ));
+        });
+    }
+
+    #[test]
+    fn gets_files_from_streamed_nupkg() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.route(
+                "/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg",
+                NupkgBuilder::new("Foo", "1.0.0")
+                    .file("README.md", "# hello")
+                    .build(),
+            );
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let version = "1.0.0".parse().unwrap();
+
+            let readme = client
+                .get_from_nupkg("Foo", &version, "readme.md")
+                .await
+                .unwrap();
+            assert_eq!(readme, b"# hello");
+            // The second lookup comes from the memo.
+            client
+                .get_from_nupkg("Foo", &version, "readme.md")
+                .await
+                .unwrap();
+            assert_eq!(
+                server.hits("/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg"),
+                1
+            );
+            assert!(matches!(
+                client.get_from_nupkg("Foo", &version, "nope.txt").await,
+                Err(NuGetApiError::FileNotFound(..))
+            ));
+        });
+    }
+
+    #[test]
+    fn downloads_nupkgs_once_per_client() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .route(
+                    "/v3-flatcontainer/foo/1.0.0/foo.nuspec",
+                    concat!(
+                        "<package><metadata>",
+                        "<id>Foo</id><version>1.0.0</version>",
+                        "<authors>turron</authors><description>Test package.</description>",
+                        "<readme>README.md</readme><icon>icon.png</icon>",
+                        "</metadata></package>",
+                    ),
+                )
+                .route(
+                    "/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg",
+                    NupkgBuilder::new("Foo", "1.0.0")
+                        .file("README.md", "# Foo")
+                        .file("icon.png", &b"not really a png"[..])
+                        .file("lib/net6.0/Foo.dll", "")
+                        .build(),
+                )
+                // Every file read is a full download, unless it's memoized.
+                .ignore_ranges();
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let version = "1.0.0".parse().unwrap();
+
+            // `view summary --check-frameworks`, then `view readme`, then
+            // `view icon`.
+            client.nupkg("Foo", &version).await.unwrap();
+            let readme = client.readme("Foo", &version).await.unwrap();
+            assert_eq!(readme.as_deref(), Some("# Foo"));
+            let icon = client.icon("Foo", &version).await.unwrap();
+            assert_eq!(icon.as_deref(), Some(&b"not really a png"[..]));
+            assert_eq!(
+                server.hits("/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg"),
+                1
+            );
+        });
+    }
+
+    #[test]
+    fn reads_readmes_and_icons() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let mut index = fixtures::service_index_at(server.base());
+            index["resources"].as_array_mut().unwrap().push(json!({
+                "@id": server.url("/v3-flatcontainer/{lower_id}/{lower_version}/readme"),
+                "@type": "ReadmeUriTemplate/6.13.0",
+            }));
+            server
+                .json("/v3/index.json", &index)
+                .route(
+                    "/v3-flatcontainer/foo/1.0.0/foo.nuspec",
+                    concat!(
+                        "<package><metadata>",
+                        "<id>Foo</id><version>1.0.0</version>",
+                        "<authors>turron</authors><description>Test package.</description>",
+                        "<readme>docs\\README.md</readme><icon>images\\icon.png</icon>",
+                        "</metadata></package>",
+                    ),
+                )
+                .route(
+                    "/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg",
+                    NupkgBuilder::new("Foo", "1.0.0")
+                        .file("docs/README.md", b"\xEF\xBB\xBF# Foo".to_vec())
+                        .build(),
+                )
+                .route(
+                    "/v3-flatcontainer/foo/2.0.0/foo.nuspec",
+                    icon_url_nuspec("2.0.0", &server.url("/icons/foo.png")),
+                )
+                .route("/icons/foo.png", &b"not really a png"[..])
+                .route(
+                    "/v3-flatcontainer/foo/3.0.0/foo.nuspec",
+                    icon_url_nuspec("3.0.0", &server.url("/icons/gone.png")),
+                )
+                .route("/v3-flatcontainer/bar/1.0.0-beta/readme", "# Bar");
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            // The template 404s for Foo, so its readme comes out of the
+            // nupkg, with the nuspec's backslashes and the BOM sorted out.
+            let version = "1.0.0".parse().unwrap();
+            let readme = client.readme("Foo", &version).await.unwrap();
+            assert_eq!(readme.as_deref(), Some("# Foo"));
+            // The nuspec points at an icon that was never packed.
+            assert_eq!(client.icon("Foo", &version).await.unwrap(), None);
+
+            // iconUrls are only downloaded when asked for.
+            let version = "2.0.0".parse().unwrap();
+            assert_eq!(client.icon("Foo", &version).await.unwrap(), None);
+            assert_eq!(server.hits("/icons/foo.png"), 0);
+            let url = client
+                .nuspec("Foo", &version)
+                .await
+                .unwrap()
+                .metadata
+                .icon_url;
+            assert_eq!(
+                client.icon_from_url(&url.unwrap()).await.unwrap(),
+                b"not really a png".to_vec()
+            );
+            let version = "3.0.0".parse().unwrap();
+            let url = client
+                .nuspec("Foo", &version)
+                .await
+                .unwrap()
+                .metadata
+                .icon_url;
+            assert!(matches!(
+                client.icon_from_url(&url.unwrap()).await,
+                Err(NuGetApiError::IconUrlFailed {
+                    status: StatusCode::NotFound,
+                    ..
+                })
+            ));
+
+            let version = "1.0.0-BETA+build".parse().unwrap();
+            let readme = client.readme("Bar", &version).await.unwrap();
+            assert_eq!(readme.as_deref(), Some("# Bar"));
+            assert_eq!(
+                server.hits("/v3-flatcontainer/bar/1.0.0-beta/bar.nuspec"),
+                0
+            );
+        });
+    }
+}
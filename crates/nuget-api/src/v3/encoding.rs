@@ -0,0 +1,247 @@
+use std::io::{self, Read};
+
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use turron_common::{
+    serde::{de::DeserializeOwned, Serialize},
+    serde_json,
+    smol::{
+        io::{AsyncBufReadExt, BlockOn, BufReader},
+        Unblock,
+    },
+    surf::{
+        self,
+        http::headers::{ACCEPT_ENCODING, CONTENT_ENCODING},
+        middleware::{Middleware, Next},
+        Body, Client, Request, Response,
+    },
+    tracing,
+};
+
+use crate::errors::NuGetApiError;
+use crate::validate::{self, Validate};
+
+/// Asks sources for compressed responses, and decompresses labeled responses
+/// if the HTTP backend hasn't already done so. Bodies are decompressed as
+/// they're read, so big downloads don't have to fit in memory.
+#[derive(Debug)]
+pub(crate) struct Decompression;
+
+#[surf::utils::async_trait]
+impl Middleware for Decompression {
+    async fn handle(
+        &self,
+        mut req: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> surf::Result<Response> {
+        if req.header(ACCEPT_ENCODING).is_none() {
+            req.insert_header(ACCEPT_ENCODING, "gzip, deflate");
+        }
+        let mut res = next.run(req, client).await?;
+        let encoding = res
+            .header(CONTENT_ENCODING)
+            .map(|values| values.last().as_str().to_lowercase());
+        if let Some(encoding) = encoding {
+            let body = decode_labeled(&encoding, res.take_body()).await?;
+            res.set_body(body);
+            res.remove_header(CONTENT_ENCODING);
+        }
+        Ok(res)
+    }
+}
+
+/// Decodes a body according to its `Content-Encoding`, as it's read. Bodies
+/// that don't actually look compressed are passed through untouched, since
+/// some backends decompress on their own but leave the header in place.
+pub(crate) async fn decode_labeled(encoding: &str, body: Body) -> io::Result<Body> {
+    let mut reader = body.into_reader();
+    // Only peeks at the start of the body. Nothing's consumed yet.
+    let head = reader.fill_buf().await?;
+    let (gzip, zlib) = (is_gzip(head), is_zlib(head));
+    // The decoders want blocking reads, so they run on the blocking thread
+    // pool, blocking on the body there.
+    Ok(match encoding.trim() {
+        "gzip" | "x-gzip" if gzip => decoding(MultiGzDecoder::new(BlockOn::new(reader))),
+        "deflate" if zlib => decoding(ZlibDecoder::new(BlockOn::new(reader))),
+        _ => Body::from_reader(reader, None),
+    })
+}
+
+/// A body that reads from `decoder` without blocking the executor.
+fn decoding(decoder: impl Read + Send + Sync + 'static) -> Body {
+    Body::from_reader(
+        BufReader::new(Unblock::with_capacity(DECODE_BUFFER_SIZE, decoder)),
+        None,
+    )
+}
+
+/// How much decoded data can pile up before reading the body waits for it
+/// to be used.
+const DECODE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Parses a JSON response body. If parsing fails and the body turns out to be
+/// gzip or zlib data the server forgot to label, decompresses and tries again.
+pub(crate) fn from_json_body<T: DeserializeOwned>(
+    body: &[u8],
+    url: &str,
+) -> Result<T, NuGetApiError> {
+    match serde_json::from_slice(body) {
+        Ok(parsed) => Ok(parsed),
+        Err(err) => {
+            let sniffed = if is_gzip(body) {
+                gunzip(body).ok()
+            } else if is_zlib(body) {
+                inflate(body).ok()
+            } else {
+                None
+            };
+            if let Some(decoded) = sniffed {
+                tracing::warn!(
+                    "{} sent a compressed response without a Content-Encoding header. This is a bug in the source.",
+                    url
+                );
+                serde_json::from_slice(&decoded).map_err(|e| {
+                    NuGetApiError::from_json_err(
+                        e,
+                        url.into(),
+                        String::from_utf8_lossy(&decoded).into(),
+                    )
+                })
+            } else {
+                Err(NuGetApiError::from_json_err(
+                    err,
+                    url.into(),
+                    String::from_utf8_lossy(body).into(),
+                ))
+            }
+        }
+    }
+}
+
+/// [`from_json_body`], for API responses. These also get checked against the
+/// protocol when [`validate::start`] has been called.
+pub(crate) fn from_json_response<T: DeserializeOwned + Serialize + Validate>(
+    body: &[u8],
+    url: &str,
+) -> Result<T, NuGetApiError> {
+    let parsed = from_json_body(body, url)?;
+    validate::check(url, body, &parsed);
+    Ok(parsed)
+}
+
+fn is_gzip(body: &[u8]) -> bool {
+    body.starts_with(&[0x1f, 0x8b])
+}
+
+fn is_zlib(body: &[u8]) -> bool {
+    match body {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+fn gunzip(body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    MultiGzDecoder::new(body).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+fn inflate(body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    ZlibDecoder::new(body).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{
+        write::{GzEncoder, ZlibEncoder},
+        Compression,
+    };
+
+    use turron_common::smol;
+
+    use super::*;
+    use crate::v3::RegistrationIndex;
+
+    const REGISTRATION: &str = r#"{
+        "count": 1,
+        "items": [{
+            "@id": "https://example.com/v3/registration/foo/index.json#page/1.0.0/1.0.0",
+            "count": 1,
+            "lower": "1.0.0",
+            "upper": "1.0.0"
+        }]
+    }"#;
+
+    fn gzipped(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zlibbed(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decode(encoding: &str, body: Vec<u8>) -> Vec<u8> {
+        smol::block_on(async {
+            decode_labeled(encoding, Body::from_bytes(body))
+                .await
+                .unwrap()
+                .into_bytes()
+                .await
+                .unwrap()
+        })
+    }
+
+    #[test]
+    fn labeled_gzip() {
+        let body = decode("gzip", gzipped(REGISTRATION.as_bytes()));
+        let index: RegistrationIndex = from_json_body(&body, "https://example.com").unwrap();
+        assert_eq!(index.count, 1);
+    }
+
+    #[test]
+    fn labeled_deflate() {
+        let body = decode("deflate", zlibbed(REGISTRATION.as_bytes()));
+        assert_eq!(body, REGISTRATION.as_bytes());
+    }
+
+    #[test]
+    fn labeled_but_already_decoded() {
+        let body = decode("gzip", REGISTRATION.as_bytes().to_vec());
+        assert_eq!(body, REGISTRATION.as_bytes());
+    }
+
+    #[test]
+    fn decodes_big_bodies() {
+        let big = REGISTRATION.repeat(10_000);
+        assert_eq!(decode("gzip", gzipped(big.as_bytes())), big.as_bytes());
+    }
+
+    #[test]
+    fn unlabeled_gzip() {
+        let index: RegistrationIndex =
+            from_json_body(&gzipped(REGISTRATION.as_bytes()), "https://example.com").unwrap();
+        assert_eq!(index.count, 1);
+    }
+
+    #[test]
+    fn unlabeled_zlib() {
+        let index: RegistrationIndex =
+            from_json_body(&zlibbed(REGISTRATION.as_bytes()), "https://example.com").unwrap();
+        assert_eq!(index.items.len(), 1);
+    }
+
+    #[test]
+    fn bad_json_is_still_bad() {
+        let res: Result<RegistrationIndex, _> =
+            from_json_body(b"{\"count\": nope}", "https://example.com");
+        assert!(matches!(res, Err(NuGetApiError::BadJson { .. })));
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use turron_common::{
+    smol::{self, channel},
+    surf::{
+        self,
+        http::headers::{ETAG, IF_NONE_MATCH},
+        Client, StatusCode, Url,
+    },
+};
+
+use crate::schedule;
+use crate::v3::retry::{Attempt, RetryPolicy};
+
+/// A finished request, shareable between everyone who asked for it.
+pub(crate) type SharedResponse = Result<Fetched, Arc<surf::Error>>;
+
+/// The parts of a response anyone needs once the body's been read.
+#[derive(Clone, Debug)]
+pub(crate) struct Fetched {
+    pub(crate) status: StatusCode,
+    pub(crate) body: Arc<[u8]>,
+    pub(crate) retry_after: Option<Duration>,
+    pub(crate) etag: Option<String>,
+    pub(crate) link: Option<String>,
+    pub(crate) content_type: Option<String>,
+}
+
+impl Attempt for Fetched {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    fn link(&self) -> Option<String> {
+        self.link.clone()
+    }
+
+    fn content_type(&self) -> Option<String> {
+        self.content_type.clone()
+    }
+}
+
+/// Tracks requests that are currently in flight, so concurrent requests for
+/// the same thing can share a single underlying request.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InFlight {
+    waiters: Arc<Mutex<HashMap<String, Vec<channel::Sender<SharedResponse>>>>>,
+}
+
+impl InFlight {
+    /// Runs `fetch` for `key`, unless a request for `key` is already in
+    /// flight, in which case this waits for that one instead.
+    ///
+    /// The request itself runs on its own task, so dropping one of the
+    /// futures waiting on it doesn't cancel it for anyone else. Entries are
+    /// removed as soon as the request completes: this only deduplicates
+    /// concurrent requests, it doesn't cache.
+    pub(crate) async fn run<F, Fut>(&self, key: String, fetch: F) -> SharedResponse
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = SharedResponse> + Send + 'static,
+    {
+        let (tx, rx) = channel::bounded(1);
+        let leader = {
+            let mut waiters = self.waiters.lock().expect("in-flight lock poisoned");
+            if let Some(existing) = waiters.get_mut(&key) {
+                existing.push(tx);
+                false
+            } else {
+                waiters.insert(key.clone(), vec![tx]);
+                true
+            }
+        };
+        if leader {
+            let fut = fetch();
+            let waiters = self.waiters.clone();
+            // The fetch runs as its own task, so it has to be told whose
+            // priority it's running at.
+            smol::spawn(schedule::prioritized(schedule::current(), async move {
+                let res = fut.await;
+                let senders = waiters
+                    .lock()
+                    .expect("in-flight lock poisoned")
+                    .remove(&key)
+                    .unwrap_or_default();
+                for sender in senders {
+                    // Receivers that went away were cancelled. That's fine.
+                    let _ = sender.try_send(res.clone());
+                }
+            }))
+            .detach();
+        }
+        rx.recv()
+            .await
+            .expect("TURRON BUG: in-flight request finished without a response")
+    }
+}
+
+/// GETs `url` and reads the whole body, retrying according to `retries`.
+/// With an `etag`, the request is conditional, and an unchanged resource
+/// comes back as an empty `304`.
+pub(crate) async fn fetch(
+    client: Client,
+    url: Url,
+    retries: RetryPolicy,
+    etag: Option<String>,
+) -> SharedResponse {
+    let (client, req_url, etag) = (&client, &url, &etag);
+    let fetched = retries
+        .run(&url, move || async move {
+            let mut req = surf::get(req_url);
+            if let Some(etag) = etag {
+                req = req.header(IF_NONE_MATCH, etag.as_str());
+            }
+            let mut res = client.send(req).await?;
+            let body = res.body_bytes().await?;
+            Ok::<_, surf::Error>(Fetched {
+                status: res.status(),
+                body: body.into(),
+                retry_after: res.retry_after(),
+                etag: res.header(ETAG).map(|values| values.last().to_string()),
+                link: res.link(),
+                content_type: res.content_type(),
+            })
+        })
+        .await?;
+    Ok(fetched)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tempfile::tempdir;
+    use turron_common::smol::{future, Timer};
+    use turron_testing::TestServer;
+
+    use super::*;
+    use crate::cache::HttpCache;
+    use crate::v3::NuGetClient;
+    use crate::SourceProtocol;
+
+    fn fetch(count: Arc<AtomicUsize>) -> impl Future<Output = SharedResponse> + Send + 'static {
+        async move {
+            count.fetch_add(1, Ordering::SeqCst);
+            Timer::after(Duration::from_millis(50)).await;
+            Ok(Fetched {
+                status: StatusCode::Ok,
+                body: Arc::from(&b"hello"[..]),
+                retry_after: None,
+                etag: None,
+                link: None,
+                content_type: None,
+            })
+        }
+    }
+
+    #[test]
+    fn dedupes_concurrent_requests() {
+        smol::block_on(async {
+            let inflight = InFlight::default();
+            let count = Arc::new(AtomicUsize::new(0));
+            let tasks = (0..10)
+                .map(|_| {
+                    let inflight = inflight.clone();
+                    let count = count.clone();
+                    smol::spawn(async move {
+                        inflight
+                            .run("https://example.com/foo".into(), || fetch(count))
+                            .await
+                    })
+                })
+                .collect::<Vec<_>>();
+            for task in tasks {
+                let fetched = task.await.unwrap();
+                assert_eq!(fetched.status, StatusCode::Ok);
+                assert_eq!(&fetched.body[..], b"hello");
+            }
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+            assert!(inflight.waiters.lock().unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn sequential_requests_are_not_cached() {
+        smol::block_on(async {
+            let inflight = InFlight::default();
+            let count = Arc::new(AtomicUsize::new(0));
+            for _ in 0..3 {
+                let count = count.clone();
+                inflight.run("key".into(), || fetch(count)).await.unwrap();
+            }
+            assert_eq!(count.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn cancelled_waiter_does_not_cancel_request() {
+        smol::block_on(async {
+            let inflight = InFlight::default();
+            let count = Arc::new(AtomicUsize::new(0));
+            let first = {
+                let count = count.clone();
+                inflight.run("key".into(), || fetch(count))
+            };
+            // Poll the leader once so the request starts, then drop it.
+            let mut first = Box::pin(first);
+            assert!(future::poll_once(&mut first).await.is_none());
+            drop(first);
+
+            let second = {
+                let count = count.clone();
+                inflight.run("key".into(), || fetch(count)).await
+            };
+            assert!(second.is_ok());
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    /// GETs `path` from `server` with ten requests at once, and checks they
+    /// all got the same body.
+    async fn get_concurrently(client: &Arc<NuGetClient>, server: &TestServer, path: &str) {
+        let url: Url = server.url(path).parse().unwrap();
+        let tasks = (0..10)
+            .map(|_| {
+                let client = client.clone();
+                let url = url.clone();
+                smol::spawn(async move { client.get_shared(&url).await })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            let (status, body) = task.await.unwrap();
+            assert_eq!(status, StatusCode::Ok);
+            assert_eq!(&body[..], b"hello");
+        }
+    }
+
+    #[test]
+    fn shares_concurrent_gets() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .route("/hello", "hello")
+                .delay("/hello", Duration::from_millis(100));
+            let client = Arc::new(
+                NuGetClient::connect(server.index_url(), SourceProtocol::Auto, None)
+                    .await
+                    .unwrap(),
+            );
+            get_concurrently(&client, &server, "/hello").await;
+            assert_eq!(server.hits("/hello"), 1);
+        });
+    }
+
+    #[test]
+    fn shares_concurrent_gets_through_the_cache() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .route("/hello", "hello")
+                .delay("/hello", Duration::from_millis(100));
+            let dir = tempdir().unwrap();
+            let client = Arc::new(
+                NuGetClient::connect(
+                    server.index_url(),
+                    SourceProtocol::Auto,
+                    Some(HttpCache::new(dir.path())),
+                )
+                .await
+                .unwrap(),
+            );
+            get_concurrently(&client, &server, "/hello").await;
+            assert_eq!(server.hits("/hello"), 1);
+            // Revalidating the cached copy is shared too.
+            get_concurrently(&client, &server, "/hello").await;
+            assert_eq!(server.hits("/hello"), 2);
+        });
+    }
+}
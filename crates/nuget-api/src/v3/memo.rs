@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use dotnet_semver::Version;
+
+/// Default cap for the in-memory nupkg memo: 128MiB.
+pub(crate) const DEFAULT_NUPKG_MEMO_SIZE: usize = 128 * 1024 * 1024;
+
+/// Per-client memo of downloaded .nupkg bytes, so pulling several files out
+/// of the same package (icon, readme, etc) only downloads it once.
+#[derive(Debug)]
+pub(crate) struct NupkgMemo {
+    max_size: usize,
+    size: usize,
+    entries: HashMap<(String, String), Arc<[u8]>>,
+}
+
+impl NupkgMemo {
+    pub(crate) fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            size: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict_until(0);
+    }
+
+    /// Keys are the lower-cased package ID and the normalized version
+    /// (lower-cased, without build metadata).
+    pub(crate) fn key(package_id: &str, version: &Version) -> (String, String) {
+        let mut version = version.clone();
+        version.build.clear();
+        (
+            package_id.to_lowercase(),
+            version.to_string().to_lowercase(),
+        )
+    }
+
+    pub(crate) fn get(&self, package_id: &str, version: &Version) -> Option<Arc<[u8]>> {
+        self.entries.get(&Self::key(package_id, version)).cloned()
+    }
+
+    /// Whether a package of `len` bytes is small enough to be remembered.
+    pub(crate) fn would_keep(&self, len: usize) -> bool {
+        len <= self.max_size
+    }
+
+    /// Remembers a package's bytes. Packages bigger than the memo itself are
+    /// never stored. Otherwise, the largest entries are evicted first until
+    /// the new entry fits.
+    pub(crate) fn insert(&mut self, package_id: &str, version: &Version, data: Arc<[u8]>) {
+        if !self.would_keep(data.len()) {
+            return;
+        }
+        let key = Self::key(package_id, version);
+        if let Some(old) = self.entries.remove(&key) {
+            self.size -= old.len();
+        }
+        self.evict_until(data.len());
+        self.size += data.len();
+        self.entries.insert(key, data);
+    }
+
+    fn evict_until(&mut self, incoming: usize) {
+        while self.size + incoming > self.max_size {
+            let largest = self
+                .entries
+                .iter()
+                .max_by_key(|(_, data)| data.len())
+                .map(|(key, _)| key.clone());
+            if let Some(key) = largest {
+                if let Some(data) = self.entries.remove(&key) {
+                    self.size -= data.len();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    fn bytes(len: usize) -> Arc<[u8]> {
+        vec![0u8; len].into()
+    }
+
+    #[test]
+    fn normalized_keys() {
+        let mut memo = NupkgMemo::new(100);
+        memo.insert("Foo.Bar", &v("1.0.0-Beta+abc"), bytes(10));
+        assert!(memo.get("foo.bar", &v("1.0.0-beta")).is_some());
+        assert!(memo.get("foo.bar", &v("1.0.0")).is_none());
+    }
+
+    #[test]
+    fn evicts_largest_first() {
+        let mut memo = NupkgMemo::new(100);
+        memo.insert("small", &v("1.0.0"), bytes(20));
+        memo.insert("big", &v("1.0.0"), bytes(60));
+        memo.insert("new", &v("1.0.0"), bytes(30));
+        assert!(memo.get("big", &v("1.0.0")).is_none());
+        assert!(memo.get("small", &v("1.0.0")).is_some());
+        assert!(memo.get("new", &v("1.0.0")).is_some());
+        assert_eq!(memo.size, 50);
+    }
+
+    #[test]
+    fn skips_oversized() {
+        let mut memo = NupkgMemo::new(100);
+        memo.insert("small", &v("1.0.0"), bytes(20));
+        memo.insert("huge", &v("1.0.0"), bytes(101));
+        assert!(memo.get("huge", &v("1.0.0")).is_none());
+        assert!(memo.get("small", &v("1.0.0")).is_some());
+    }
+}
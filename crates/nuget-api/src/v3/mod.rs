@@ -1,22 +1,43 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
 use dotnet_semver::Version;
 pub use turron_common::surf::Body;
 use turron_common::{
     serde::{Deserialize, Serialize},
-    serde_json,
-    surf::{self, Client, Url},
+    surf::{self, Client, StatusCode, Url},
+    tracing,
 };
 
+use crate::cache::{self, HttpCache};
+use crate::capture::{self, Capture};
+use crate::credentials;
 use crate::errors::NuGetApiError;
+use crate::schedule::{self, Schedule};
+use crate::SourceProtocol;
+use encoding::{from_json_response, Decompression};
+use inflight::InFlight;
+use memo::{NupkgMemo, DEFAULT_NUPKG_MEMO_SIZE};
 
+pub use autocomplete::*;
 pub use content::*;
 pub use registration::*;
+pub use retry::RetryPolicy;
 pub use search::*;
 
+mod autocomplete;
 mod content;
+mod encoding;
+mod inflight;
+mod memo;
+mod nuspec;
 mod push;
+mod ranged;
 mod registration;
 mod relist;
+mod retry;
 mod search;
+mod unavailable;
 mod unlist;
 
 #[derive(Debug)]
@@ -24,6 +45,13 @@ pub struct NuGetClient {
     client: Client,
     pub key: Option<String>,
     pub endpoints: NuGetEndpoints,
+    /// Every resource the source's service index advertised, including ones
+    /// turron doesn't use.
+    pub resources: Vec<IndexResource>,
+    nupkg_memo: Mutex<NupkgMemo>,
+    inflight: InFlight,
+    retries: RetryPolicy,
+    cache: Option<HttpCache>,
 }
 
 #[derive(Debug, Serialize)]
@@ -36,69 +64,180 @@ pub struct NuGetEndpoints {
     pub signatures: Option<Url>,
     pub autocomplete: Option<Url>,
     pub symbol_publish: Option<Url>,
+    /// A `{lower_id}`/`{lower_version}` template for package readmes.
+    pub readme: Option<Url>,
+    /// The index resource each endpoint above was taken from, keyed by
+    /// endpoint name.
+    pub provenance: BTreeMap<String, IndexResource>,
 }
 
-impl NuGetEndpoints {
-    fn find_endpoint(resources: &[IndexResource], restype: &str) -> Option<Url> {
-        resources
-            .iter()
-            .find(|res| res.restype == restype)
-            .map(|res| res.id.clone())
-    }
+const REGISTRATION_SEMVER2: &str = "RegistrationsBaseUrl/3.6.0";
+const SEARCH_FRAMEWORKS: &str = "SearchQueryService/3.6.0";
 
-    fn from_resources(resources: Vec<IndexResource>) -> Self {
-        let r = |res| Self::find_endpoint(&resources, res);
+impl NuGetEndpoints {
+    fn from_resources(resources: &[IndexResource]) -> Self {
+        let mut provenance = BTreeMap::new();
+        // Takes the first of `restypes` that the source offers, so list them
+        // in order of preference.
+        let mut r = |name: &str, restypes: &[&str]| {
+            restypes
+                .iter()
+                .find_map(|restype| resources.iter().find(|res| &res.restype == restype))
+                .map(|res| {
+                    provenance.insert(name.into(), res.clone());
+                    res.id.clone()
+                })
+        };
         NuGetEndpoints {
-            package_content: r("PackageBaseAddress/3.0.0"),
-            publish: r("PackagePublish/2.0.0"),
-            registration: r("RegistrationsBaseUrl/3.6.0"),
-            search: r("SearchQueryService/3.5.0"),
-            catalog: r("Catalog/3.0.0"),
-            signatures: r("RepositorySignatures/5.0.0"),
-            autocomplete: r("SearchAutocompleteService/3.5.0"),
-            symbol_publish: r("SymbolPackagePublish/4.9.0"),
+            package_content: r("package_content", &["PackageBaseAddress/3.0.0"]),
+            publish: r("publish", &["PackagePublish/2.0.0"]),
+            // Only 3.6.0 includes SemVer 2.0.0 packages. The rest are
+            // fallbacks, newest first.
+            registration: r(
+                "registration",
+                &[
+                    REGISTRATION_SEMVER2,
+                    "RegistrationsBaseUrl/3.4.0",
+                    "RegistrationsBaseUrl/3.0.0-rc",
+                    "RegistrationsBaseUrl",
+                ],
+            ),
+            search: r("search", &[SEARCH_FRAMEWORKS, "SearchQueryService/3.5.0"]),
+            catalog: r("catalog", &["Catalog/3.0.0"]),
+            signatures: r("signatures", &["RepositorySignatures/5.0.0"]),
+            autocomplete: r("autocomplete", &["SearchAutocompleteService/3.5.0"]),
+            symbol_publish: r("symbol_publish", &["SymbolPackagePublish/4.9.0"]),
+            readme: r("readme", &["ReadmeUriTemplate/6.13.0"]),
+            provenance,
         }
     }
+
+    /// Whether the registration endpoint in use lists SemVer 2.0.0 packages.
+    /// Older registration resources silently leave them out.
+    pub fn registration_supports_semver2(&self) -> bool {
+        self.provenance
+            .get("registration")
+            .map(|res| res.restype == REGISTRATION_SEMVER2)
+            .unwrap_or(false)
+    }
+
+    /// Whether the search endpoint in use can filter by target framework
+    /// itself (the `supportedFramework` parameter). Older search resources
+    /// ignore it.
+    pub fn search_supports_frameworks(&self) -> bool {
+        self.provenance
+            .get("search")
+            .map(|res| res.restype == SEARCH_FRAMEWORKS)
+            .unwrap_or(false)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Index {
-    version: Version,
-    resources: Vec<IndexResource>,
+    pub version: Version,
+    pub resources: Vec<IndexResource>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct IndexResource {
     #[serde(rename = "@id")]
-    id: Url,
+    pub id: Url,
     #[serde(rename = "@type")]
-    restype: String,
-    comment: Option<String>,
+    pub restype: String,
+    pub comment: Option<String>,
+}
+
+/// Phrases that make a resource comment worth pointing out. Whole phrases,
+/// so words like "generate" or "accurate" don't count as rate limits.
+const NOTABLE_PHRASES: &[&str] = &["deprecat", "rate limit", "rate-limit"];
+
+impl IndexResource {
+    /// Whether this resource's comment says something users probably want to
+    /// know about, like deprecations or rate limits.
+    pub fn has_notable_comment(&self) -> bool {
+        self.comment
+            .as_deref()
+            .map(|c| {
+                let c = c.to_lowercase();
+                NOTABLE_PHRASES.iter().any(|phrase| c.contains(phrase))
+            })
+            .unwrap_or(false)
+    }
 }
 
 impl NuGetClient {
     pub async fn from_source(source: impl AsRef<str>) -> Result<Self, NuGetApiError> {
-        let client = Client::new();
+        Self::from_source_as(source, SourceProtocol::Auto).await
+    }
+
+    /// Like [`NuGetClient::from_source`], but assumes the source speaks
+    /// `protocol` instead of working it out. Sources that don't are an
+    /// error.
+    pub async fn from_source_as(
+        source: impl AsRef<str>,
+        protocol: SourceProtocol,
+    ) -> Result<Self, NuGetApiError> {
+        Self::connect(source, protocol, cache::configured()).await
+    }
+
+    /// Like [`NuGetClient::from_source_as`], with an explicit HTTP cache
+    /// instead of whatever [`cache::enable`] set up.
+    pub(crate) async fn connect(
+        source: impl AsRef<str>,
+        protocol: SourceProtocol,
+        cache: Option<HttpCache>,
+    ) -> Result<Self, NuGetApiError> {
+        if protocol == SourceProtocol::V2 {
+            return Err(NuGetApiError::UnsupportedProtocol(protocol));
+        }
+        // Scheduling goes first, so time spent waiting for a slot isn't
+        // counted as part of the request anywhere else.
+        let mut client = Client::new().with(Schedule::new(schedule::global()));
+        if let Some(auth) = credentials::authenticator(source.as_ref()) {
+            client = client.with(auth);
+        }
+        if let Some(log) = capture::active() {
+            client = client.with(Capture::new(log));
+        }
+        let client = client.with(Decompression);
         let url: Url = source
             .as_ref()
             .parse()
             .map_err(|_| NuGetApiError::InvalidSource(source.as_ref().into()))?;
-        let req = surf::get(&url);
-        let Index { resources, .. } = serde_json::from_slice(
-            &client
-                .send(req)
-                .await
-                .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?
-                .body_bytes()
-                .await
-                .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?,
-        )
-        .map_err(|_| NuGetApiError::InvalidSource(source.as_ref().into()))?;
-        Ok(NuGetClient {
+        let not_an_index = || match protocol {
+            SourceProtocol::Auto => NuGetApiError::InvalidSource(source.as_ref().into()),
+            pinned => NuGetApiError::ProtocolMismatch {
+                url: source.as_ref().into(),
+                pinned,
+            },
+        };
+        let mut nuget = NuGetClient {
             client,
             key: None,
-            endpoints: NuGetEndpoints::from_resources(resources),
-        })
+            endpoints: NuGetEndpoints::from_resources(&[]),
+            resources: Vec::new(),
+            nupkg_memo: Mutex::new(NupkgMemo::new(DEFAULT_NUPKG_MEMO_SIZE)),
+            inflight: InFlight::default(),
+            retries: RetryPolicy::default(),
+            cache,
+        };
+        // The index goes through the cache like anything else, so offline
+        // clients can still be created.
+        let (status, body) = nuget.get_shared(&url).await?;
+        // Only a successful response that isn't an index says anything about
+        // the protocol. Anything else is the source failing, whatever it is.
+        match status {
+            status if status.is_success() => {}
+            StatusCode::NotFound => {
+                return Err(NuGetApiError::InvalidSource(source.as_ref().into()))
+            }
+            status => return Err(NuGetApiError::BadResponse(status)),
+        }
+        let Index { resources, .. } =
+            from_json_response(&body[..], url.as_str()).map_err(|_| not_an_index())?;
+        nuget.endpoints = NuGetEndpoints::from_resources(&resources);
+        nuget.resources = resources;
+        Ok(nuget)
     }
 
     pub fn get_key(&self) -> Result<String, NuGetApiError> {
@@ -109,4 +248,463 @@ impl NuGetClient {
         self.key = key.map(|k| k.as_ref().into());
         self
     }
+
+    /// GETs `url` and reads the whole body. Identical concurrent requests
+    /// share a single underlying request. A `429` that outlasts the retries
+    /// comes back as [`NuGetApiError::RateLimited`].
+    ///
+    /// With an HTTP cache, cached immutable responses are reused as-is, and
+    /// anything else cached is revalidated by its `ETag`.
+    pub(crate) async fn get_shared(
+        &self,
+        url: &Url,
+    ) -> Result<(StatusCode, Arc<[u8]>), NuGetApiError> {
+        let hit = match &self.cache {
+            Some(cache) => cache.load(url).await,
+            None => None,
+        };
+        if let Some(cache) = &self.cache {
+            if cache.is_offline() || (hit.is_some() && cache::is_immutable(url)) {
+                return hit
+                    .map(|hit| (StatusCode::Ok, hit.body.into()))
+                    .ok_or_else(|| NuGetApiError::Offline(url.to_string()));
+            }
+        }
+        let client = self.client.clone();
+        let req_url = url.clone();
+        let retries = self.retries.clone();
+        let etag = hit.as_ref().and_then(|hit| hit.entry.etag.clone());
+        let fetched = self
+            .inflight
+            .run(url.to_string(), move || {
+                inflight::fetch(client, req_url, retries, etag)
+            })
+            .await
+            .map_err(|e| {
+                NuGetApiError::SurfError(
+                    surf::Error::from_str(e.status(), e.to_string()),
+                    url.clone().into(),
+                )
+            })?;
+        match (fetched.status, hit, &self.cache) {
+            (StatusCode::TooManyRequests, ..) => Err(retry::rate_limited(&fetched, url)),
+            (StatusCode::UnavailableForLegalReasons, ..) => {
+                Err(unavailable::legally_unavailable(&fetched, url))
+            }
+            // Checked before caching, so the page doesn't get kept.
+            (StatusCode::Ok, ..) if unavailable::is_error_page(&fetched, url) => {
+                Err(unavailable::error_page(&fetched, url))
+            }
+            (StatusCode::NotModified, Some(hit), _) => {
+                tracing::debug!("{} hasn't changed. Using the cached copy.", url);
+                Ok((StatusCode::Ok, hit.body.into()))
+            }
+            (StatusCode::Ok, _, Some(cache)) => {
+                cache
+                    .store(url, fetched.etag.clone(), fetched.body.to_vec())
+                    .await;
+                Ok((fetched.status, fetched.body))
+            }
+            _ => Ok((fetched.status, fetched.body)),
+        }
+    }
+
+    /// Fails if this client is offline, for requests that can't be answered
+    /// from the cache.
+    pub(crate) fn ensure_online(&self, url: &Url) -> Result<(), NuGetApiError> {
+        match &self.cache {
+            Some(cache) if cache.is_offline() => Err(NuGetApiError::Offline(url.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets how requests that fail for transient reasons get retried. GETs
+    /// are retried by default; see [`RetryPolicy::retry_non_idempotent`] for
+    /// everything else.
+    pub fn with_retries(mut self, retries: RetryPolicy) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the maximum number of bytes of downloaded .nupkg data this client
+    /// will keep around for reuse. Set to 0 to disable.
+    pub fn with_nupkg_memo_size(self, max_size: usize) -> Self {
+        self.nupkg_memo
+            .lock()
+            .expect("nupkg memo lock poisoned")
+            .set_max_size(max_size);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use turron_common::{serde_json, smol};
+    use turron_testing::{NupkgBuilder, TestServer};
+
+    use super::*;
+
+    const INDEX: &str = r#"{
+        "version": "3.0.0",
+        "resources": [
+            {
+                "@id": "https://example.com/v3/search",
+                "@type": "SearchQueryService/3.5.0",
+                "comment": "Query endpoint of NuGet Search service. Rate limited to 100 requests per minute."
+            },
+            {
+                "@id": "https://example.com/v3/flatcontainer/",
+                "@type": "PackageBaseAddress/3.0.0",
+                "comment": "Base URL of where NuGet packages are stored"
+            },
+            {
+                "@id": "https://example.com/v3/legacy",
+                "@type": "LegacyGallery/2.0.0",
+                "comment": "This endpoint is deprecated."
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn endpoints_keep_comments() {
+        let index: Index = serde_json::from_str(INDEX).unwrap();
+        let endpoints = NuGetEndpoints::from_resources(&index.resources);
+        assert_eq!(
+            endpoints.search.as_ref().map(|u| u.as_str()),
+            Some("https://example.com/v3/search")
+        );
+        assert!(endpoints.registration.is_none());
+        let json = serde_json::to_value(&endpoints).unwrap();
+        assert_eq!(
+            json["provenance"]["package_content"]["comment"],
+            "Base URL of where NuGet packages are stored"
+        );
+        assert_eq!(
+            json["provenance"]["search"]["@type"],
+            "SearchQueryService/3.5.0"
+        );
+    }
+
+    #[test]
+    fn notable_comments() {
+        let index: Index = serde_json::from_str(INDEX).unwrap();
+        let notable = index
+            .resources
+            .iter()
+            .filter(|r| r.has_notable_comment())
+            .map(|r| r.restype.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notable,
+            vec!["SearchQueryService/3.5.0", "LegacyGallery/2.0.0"]
+        );
+    }
+
+    #[test]
+    fn ignores_words_that_merely_contain_rate() {
+        for comment in &[
+            "Generate package IDs",
+            "Separate endpoint for symbols",
+            "Integrated with the search service",
+            "Accurate as of the last catalog commit",
+        ] {
+            let resource = IndexResource {
+                id: "https://example.com/".parse().unwrap(),
+                restype: "SearchQueryService/3.5.0".into(),
+                comment: Some(comment.to_string()),
+            };
+            assert!(!resource.has_notable_comment(), "{}", comment);
+        }
+        let resource = IndexResource {
+            id: "https://example.com/".parse().unwrap(),
+            restype: "SearchQueryService/3.5.0".into(),
+            comment: Some("Subject to rate-limiting".into()),
+        };
+        assert!(resource.has_notable_comment());
+    }
+
+    fn registration_resources(restypes: &[&str]) -> Vec<IndexResource> {
+        restypes
+            .iter()
+            .map(|restype| IndexResource {
+                id: format!("https://example.com/{}/", restype.replace('/', "-"))
+                    .parse()
+                    .unwrap(),
+                restype: restype.to_string(),
+                comment: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prefers_semver2_registration() {
+        let endpoints = NuGetEndpoints::from_resources(&registration_resources(&[
+            "RegistrationsBaseUrl",
+            "RegistrationsBaseUrl/3.4.0",
+            "RegistrationsBaseUrl/3.6.0",
+        ]));
+        assert_eq!(
+            endpoints.registration.as_ref().unwrap().as_str(),
+            "https://example.com/RegistrationsBaseUrl-3.6.0/"
+        );
+        assert!(endpoints.registration_supports_semver2());
+    }
+
+    #[test]
+    fn nuget_org_endpoints() {
+        let index: Index =
+            serde_json::from_value(turron_testing::fixtures::service_index()).unwrap();
+        let endpoints = NuGetEndpoints::from_resources(&index.resources);
+        assert_eq!(
+            endpoints.registration.as_ref().unwrap().as_str(),
+            "https://api.nuget.org/v3/registration5-gz-semver2/"
+        );
+        assert_eq!(
+            endpoints.package_content.as_ref().unwrap().as_str(),
+            "https://api.nuget.org/v3-flatcontainer/"
+        );
+        assert!(endpoints.registration_supports_semver2());
+    }
+
+    #[test]
+    fn falls_back_to_older_registration() {
+        let endpoints = NuGetEndpoints::from_resources(&registration_resources(&[
+            "RegistrationsBaseUrl",
+            "RegistrationsBaseUrl/3.0.0-rc",
+            "RegistrationsBaseUrl/3.4.0",
+        ]));
+        assert_eq!(
+            endpoints.registration.as_ref().unwrap().as_str(),
+            "https://example.com/RegistrationsBaseUrl-3.4.0/"
+        );
+        assert!(!endpoints.registration_supports_semver2());
+
+        let endpoints = NuGetEndpoints::from_resources(&registration_resources(&[
+            "RegistrationsBaseUrl",
+            "RegistrationsBaseUrl/3.0.0-rc",
+        ]));
+        assert_eq!(
+            endpoints.registration.as_ref().unwrap().as_str(),
+            "https://example.com/RegistrationsBaseUrl-3.0.0-rc/"
+        );
+
+        let endpoints =
+            NuGetEndpoints::from_resources(&registration_resources(&["RegistrationsBaseUrl"]));
+        assert_eq!(
+            endpoints.registration.as_ref().unwrap().as_str(),
+            "https://example.com/RegistrationsBaseUrl/"
+        );
+        assert!(!endpoints.registration_supports_semver2());
+    }
+
+    const V2_SERVICE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<service xml:base="https://example.com/api/v2/" xmlns="http://www.w3.org/2007/app">
+  <workspace><collection href="Packages" /></workspace>
+</service>"#;
+
+    #[test]
+    fn pinned_v3_rejects_other_protocols() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.route("/api/v2/", V2_SERVICE);
+            let v2_url = server.url("/api/v2/");
+            match NuGetClient::from_source_as(&v2_url, SourceProtocol::V3).await {
+                Err(NuGetApiError::ProtocolMismatch { url, pinned }) => {
+                    assert_eq!(url, v2_url);
+                    assert_eq!(pinned, SourceProtocol::V3);
+                }
+                other => panic!("expected ProtocolMismatch, got {:?}", other),
+            }
+            assert!(matches!(
+                NuGetClient::from_source(&v2_url).await,
+                Err(NuGetApiError::InvalidSource(_))
+            ));
+            NuGetClient::from_source_as(server.index_url(), SourceProtocol::V3)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn index_errors_are_not_protocol_mismatches() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.respond("/missing/index.json", 404, "").respond(
+                "/forbidden/index.json",
+                403,
+                "",
+            );
+            assert!(matches!(
+                NuGetClient::from_source_as(server.url("/missing/index.json"), SourceProtocol::V3)
+                    .await,
+                Err(NuGetApiError::InvalidSource(_))
+            ));
+            assert!(matches!(
+                NuGetClient::from_source_as(
+                    server.url("/forbidden/index.json"),
+                    SourceProtocol::V3
+                )
+                .await,
+                Err(NuGetApiError::BadResponse(StatusCode::Forbidden))
+            ));
+        });
+    }
+
+    #[test]
+    fn pinned_v2_skips_detection() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            assert!(matches!(
+                NuGetClient::from_source_as(server.index_url(), SourceProtocol::V2).await,
+                Err(NuGetApiError::UnsupportedProtocol(SourceProtocol::V2))
+            ));
+            assert_eq!(server.hits("/v3/index.json"), 0);
+        });
+    }
+
+    #[test]
+    fn caches_responses() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let versions = "/v3-flatcontainer/foo/index.json";
+            let nupkg = "/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg";
+            server
+                .route(versions, r#"{"versions": ["1.0.0"]}"#)
+                .route(nupkg, NupkgBuilder::new("Foo", "1.0.0").build());
+            let dir = tempdir().unwrap();
+            let cache = HttpCache::new(dir.path());
+            let version: Version = "1.0.0".parse().unwrap();
+
+            let client = NuGetClient::connect(
+                server.index_url(),
+                SourceProtocol::Auto,
+                Some(cache.clone()),
+            )
+            .await
+            .unwrap();
+            client.versions("Foo").await.unwrap();
+            client.nupkg("Foo", &version).await.unwrap();
+            let served = server.bytes_served();
+            // Versions get revalidated, and come back unchanged. The nupkg
+            // isn't even asked for.
+            client.versions("Foo").await.unwrap();
+            client.nupkg("Foo", &version).await.unwrap();
+            assert_eq!(server.hits(versions), 2);
+            assert_eq!(server.hits(nupkg), 1);
+            assert_eq!(server.bytes_served(), served);
+
+            let requests = server.requests().len();
+            let offline = NuGetClient::connect(
+                server.index_url(),
+                SourceProtocol::Auto,
+                Some(cache.offline(true)),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                offline.versions("Foo").await.unwrap(),
+                vec![version.clone()]
+            );
+            let mut out = Vec::new();
+            offline
+                .nupkg_to_writer("Foo", &version, &mut out, |_, _| {})
+                .await
+                .unwrap();
+            assert_eq!(&out[..], &client.nupkg("Foo", &version).await.unwrap()[..]);
+            assert!(matches!(
+                offline.versions("Bar").await,
+                Err(NuGetApiError::Offline(url)) if url.ends_with("/bar/index.json")
+            ));
+            assert!(matches!(
+                offline
+                    .with_key(Some("key"))
+                    .push("Foo.1.0.0.nupkg", |_, _| {})
+                    .await,
+                Err(NuGetApiError::Offline(_))
+            ));
+            assert_eq!(server.requests().len(), requests);
+        });
+    }
+
+    #[test]
+    fn refuses_blocked_packages_and_error_pages() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let blocked = "/v3-flatcontainer/foo/1.0.0/foo.1.0.0.nupkg";
+            let mirrored = "/v3-flatcontainer/bar/1.0.0/bar.1.0.0.nupkg";
+            server
+                .respond(blocked, 451, "")
+                .header(
+                    blocked,
+                    "Link",
+                    r#"<https://example.com/notice>; rel="blocked-by""#,
+                )
+                .respond("/v3-flatcontainer/foo/index.json", 451, "")
+                .route(mirrored, "<html><body>Access denied</body></html>")
+                .header(mirrored, "Content-Type", "text/html; charset=utf-8");
+            let dir = tempdir().unwrap();
+            let cache = HttpCache::new(dir.path());
+            let client = NuGetClient::connect(
+                server.index_url(),
+                SourceProtocol::Auto,
+                Some(cache.clone()),
+            )
+            .await
+            .unwrap();
+            let version: Version = "1.0.0".parse().unwrap();
+
+            for result in vec![
+                client.nupkg("Foo", &version).await.map(|_| ()),
+                client
+                    .nupkg_to_writer("Foo", &version, Vec::new(), |_, _| {})
+                    .await
+                    .map(|_| ()),
+            ] {
+                match result {
+                    Err(NuGetApiError::LegallyUnavailable { url, blocked_by }) => {
+                        assert!(url.ends_with(blocked));
+                        assert_eq!(blocked_by.as_deref(), Some("https://example.com/notice"));
+                    }
+                    other => panic!("expected LegallyUnavailable, got {:?}", other),
+                }
+            }
+            assert!(matches!(
+                client.versions("Foo").await,
+                Err(NuGetApiError::LegallyUnavailable {
+                    blocked_by: None,
+                    ..
+                })
+            ));
+
+            assert!(matches!(
+                client.nupkg("Bar", &version).await,
+                Err(NuGetApiError::ErrorPage { content_type, .. })
+                    if content_type.starts_with("text/html")
+            ));
+            let mut out = Vec::new();
+            assert!(matches!(
+                client
+                    .nupkg_to_writer("Bar", &version, &mut out, |_, _| {})
+                    .await,
+                Err(NuGetApiError::ErrorPage { .. })
+            ));
+            assert!(out.is_empty());
+
+            // Neither got cached.
+            let offline = NuGetClient::connect(
+                server.index_url(),
+                SourceProtocol::Auto,
+                Some(cache.offline(true)),
+            )
+            .await
+            .unwrap();
+            for id in &["Foo", "Bar"] {
+                assert!(matches!(
+                    offline.nupkg(id, &version).await,
+                    Err(NuGetApiError::Offline(_))
+                ));
+            }
+        });
+    }
 }
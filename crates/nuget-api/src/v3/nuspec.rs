@@ -0,0 +1,549 @@
+//! Writing [`NuSpec`]s back out as XML, laid out the way `nuget pack` lays
+//! them out.
+
+use std::fmt::Display;
+
+use dotnet_semver::Range;
+
+use crate::errors::NuGetApiError;
+use crate::v3::{
+    NuSpec, NuSpecContentFiles, NuSpecDependencies, NuSpecDependency, NuSpecFiles,
+    NuSpecFrameworkAssemblies, NuSpecMetadata, NuSpecPackageTypes, NuSpecReference,
+    NuSpecReferences, NuSpecRepository,
+};
+
+const NAMESPACE: &str = "http://schemas.microsoft.com/packaging/2013/05/nuspec.xsd";
+
+impl NuSpec {
+    /// This nuspec as a complete XML document.
+    ///
+    /// Everything that's read from a nuspec is written back, so parsing the
+    /// result gives the same `NuSpec`, with one exception: floating
+    /// dependency versions like `2.*` aren't allowed in packed nuspecs, so
+    /// they're written as the range of versions they cover.
+    ///
+    /// Fails if the id, description, or authors are blank, or if any value
+    /// has characters XML can't hold.
+    pub fn to_xml_string(&self) -> Result<String, NuGetApiError> {
+        let mut xml = Xml::default();
+        xml.out
+            .push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.open("package", &[("xmlns", Some(NAMESPACE.into()))])?;
+        write_metadata(&mut xml, &self.metadata)?;
+        if let Some(files) = &self.files {
+            write_files(&mut xml, files)?;
+        }
+        xml.close("package");
+        Ok(xml.out)
+    }
+}
+
+impl NuSpecMetadata {
+    /// The file in the package that `<license>` points to, if it's a file
+    /// rather than an SPDX expression.
+    pub fn license_file(&self) -> Option<&str> {
+        self.license
+            .as_deref()
+            .filter(|license| license_type(license) == "file")
+    }
+}
+
+fn write_metadata(xml: &mut Xml, metadata: &NuSpecMetadata) -> Result<(), NuGetApiError> {
+    for (field, value) in &[
+        ("id", &metadata.id),
+        ("description", &metadata.description),
+        ("authors", &metadata.authors),
+    ] {
+        if value.trim().is_empty() {
+            return Err(NuGetApiError::InvalidNuSpec(format!(
+                "<{}> is required, but it's blank.",
+                field
+            )));
+        }
+    }
+    xml.open(
+        "metadata",
+        &[(
+            "minClientVersion",
+            metadata.min_client_version.as_ref().map(to_string),
+        )],
+    )?;
+    xml.element("id", Some(&metadata.id))?;
+    xml.element("version", Some(&metadata.version))?;
+    xml.element("title", metadata.title.as_ref())?;
+    xml.element("authors", Some(&metadata.authors))?;
+    xml.element("owners", metadata.owners.as_ref())?;
+    xml.element(
+        "requireLicenseAcceptance",
+        metadata.require_license_acceptance.as_ref(),
+    )?;
+    xml.element(
+        "developmentDependency",
+        metadata.development_dependency.as_ref(),
+    )?;
+    if let Some(license) = &metadata.license {
+        xml.element_with(
+            "license",
+            &[("type", Some(license_type(license).into()))],
+            license,
+        )?;
+    }
+    xml.element("licenseUrl", metadata.license_url.as_ref())?;
+    xml.element("icon", metadata.icon.as_ref())?;
+    xml.element("readme", metadata.readme.as_ref())?;
+    xml.element("projectUrl", metadata.project_url.as_ref())?;
+    xml.element("iconUrl", metadata.icon_url.as_ref())?;
+    xml.element("description", Some(&metadata.description))?;
+    xml.element("releaseNotes", metadata.release_notes.as_ref())?;
+    xml.element("copyright", metadata.copyright.as_ref())?;
+    xml.element("language", metadata.language.as_ref())?;
+    xml.element("tags", metadata.tags.as_ref())?;
+    if let Some(repository) = &metadata.repository {
+        write_repository(xml, repository)?;
+    }
+    if let Some(package_types) = &metadata.package_types {
+        write_package_types(xml, package_types)?;
+    }
+    if let Some(dependencies) = &metadata.dependencies {
+        write_dependencies(xml, dependencies)?;
+    }
+    if let Some(assemblies) = &metadata.framework_assemblies {
+        write_framework_assemblies(xml, assemblies)?;
+    }
+    if let Some(references) = &metadata.references {
+        write_references(xml, references)?;
+    }
+    if let Some(content_files) = &metadata.content_files {
+        write_content_files(xml, content_files)?;
+    }
+    xml.close("metadata");
+    Ok(())
+}
+
+fn write_repository(xml: &mut Xml, repository: &NuSpecRepository) -> Result<(), NuGetApiError> {
+    xml.empty(
+        "repository",
+        &[
+            ("type", repository.repo_type.clone()),
+            ("url", repository.url.as_ref().map(to_string)),
+            ("branch", repository.branch.clone()),
+            ("commit", repository.commit.clone()),
+        ],
+    )
+}
+
+fn write_package_types(xml: &mut Xml, types: &NuSpecPackageTypes) -> Result<(), NuGetApiError> {
+    xml.open("packageTypes", &[])?;
+    for package_type in &types.package_types {
+        xml.empty(
+            "packageType",
+            &[
+                ("name", Some(package_type.name.clone())),
+                ("version", package_type.version.clone()),
+            ],
+        )?;
+    }
+    xml.close("packageTypes");
+    Ok(())
+}
+
+fn write_dependencies(
+    xml: &mut Xml,
+    dependencies: &NuSpecDependencies,
+) -> Result<(), NuGetApiError> {
+    xml.open("dependencies", &[])?;
+    for dependency in &dependencies.dependencies {
+        write_dependency(xml, dependency)?;
+    }
+    for group in &dependencies.groups {
+        let attrs = [("targetFramework", group.target_framework.clone())];
+        if group.dependencies.is_empty() {
+            xml.empty("group", &attrs)?;
+        } else {
+            xml.open("group", &attrs)?;
+            for dependency in &group.dependencies {
+                write_dependency(xml, dependency)?;
+            }
+            xml.close("group");
+        }
+    }
+    xml.close("dependencies");
+    Ok(())
+}
+
+fn write_dependency(xml: &mut Xml, dependency: &NuSpecDependency) -> Result<(), NuGetApiError> {
+    xml.empty(
+        "dependency",
+        &[
+            ("id", Some(dependency.id.clone())),
+            ("version", nuspec_range(&dependency.version)),
+            ("include", dependency.include.clone()),
+            ("exclude", dependency.exclude.clone()),
+        ],
+    )
+}
+
+fn write_framework_assemblies(
+    xml: &mut Xml,
+    assemblies: &NuSpecFrameworkAssemblies,
+) -> Result<(), NuGetApiError> {
+    xml.open("frameworkAssemblies", &[])?;
+    for assembly in &assemblies.assemblies {
+        xml.empty(
+            "frameworkAssembly",
+            &[
+                ("assemblyName", assembly.assembly_name.clone()),
+                ("targetFramework", assembly.target_framework.clone()),
+            ],
+        )?;
+    }
+    xml.close("frameworkAssemblies");
+    Ok(())
+}
+
+fn write_references(xml: &mut Xml, references: &NuSpecReferences) -> Result<(), NuGetApiError> {
+    let reference = |xml: &mut Xml, r: &NuSpecReference| {
+        xml.empty("reference", &[("file", Some(r.file.clone()))])
+    };
+    xml.open("references", &[])?;
+    for r in &references.references {
+        reference(xml, r)?;
+    }
+    for group in &references.groups {
+        let attrs = [("targetFramework", group.target_framework.clone())];
+        if group.references.is_empty() {
+            xml.empty("group", &attrs)?;
+        } else {
+            xml.open("group", &attrs)?;
+            for r in &group.references {
+                reference(xml, r)?;
+            }
+            xml.close("group");
+        }
+    }
+    xml.close("references");
+    Ok(())
+}
+
+fn write_content_files(
+    xml: &mut Xml,
+    content_files: &NuSpecContentFiles,
+) -> Result<(), NuGetApiError> {
+    xml.open("contentFiles", &[])?;
+    for files in &content_files.files {
+        xml.empty(
+            "files",
+            &[
+                ("include", Some(files.include.clone())),
+                ("exclude", files.exclude.clone()),
+                ("buildAction", files.build_action.clone()),
+                ("copyToOutput", files.copy_to_output.as_ref().map(to_string)),
+                ("flatten", files.flatten.as_ref().map(to_string)),
+            ],
+        )?;
+    }
+    xml.close("contentFiles");
+    Ok(())
+}
+
+fn write_files(xml: &mut Xml, files: &NuSpecFiles) -> Result<(), NuGetApiError> {
+    xml.open("files", &[])?;
+    for file in &files.files {
+        xml.empty(
+            "file",
+            &[
+                ("src", Some(file.src.clone())),
+                ("target", file.target.clone()),
+                ("exclude", file.exclude.clone()),
+            ],
+        )?;
+    }
+    xml.close("files");
+    Ok(())
+}
+
+/// A dependency's `version` attribute, or `None` to leave it out because
+/// any version will do.
+fn nuspec_range(range: &Range) -> Option<String> {
+    if *range == Range::any() {
+        return None;
+    }
+    let text = range.to_string();
+    match range.plain_version() {
+        // `[1.2.3,)` is what a plain `1.2.3` means, and the way NuGet
+        // writes it.
+        Some(version) if !text.ends_with(']') => Some(version.to_string()),
+        _ => Some(text),
+    }
+}
+
+/// Whether a `<license>` is an SPDX expression or a file in the package.
+/// Nuspecs say which in a `type` attribute, but it isn't kept when they're
+/// read, so this goes by what file names look like: anything with a path
+/// separator, or named like a license file (`LICENSE`, `license.txt`), is a
+/// file.
+fn license_type(license: &str) -> &'static str {
+    let lower = license.trim().to_lowercase();
+    if lower.contains('/')
+        || lower.contains('\\')
+        || lower.starts_with("license")
+        || lower.starts_with("licence")
+        || lower.ends_with(".txt")
+        || lower.ends_with(".md")
+    {
+        "file"
+    } else {
+        "expression"
+    }
+}
+
+fn to_string(value: &impl Display) -> String {
+    value.to_string()
+}
+
+/// Just enough of an XML writer for nuspecs: two-space indents, and
+/// attributes left out when they're `None`.
+#[derive(Default)]
+struct Xml {
+    out: String,
+    depth: usize,
+}
+
+type Attrs<'a> = [(&'a str, Option<String>)];
+
+impl Xml {
+    fn open(&mut self, name: &str, attrs: &Attrs<'_>) -> Result<(), NuGetApiError> {
+        self.start_tag(name, attrs)?;
+        self.out.push_str(">\n");
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn close(&mut self, name: &str) {
+        self.depth -= 1;
+        self.indent();
+        self.out.push_str(&format!("</{}>\n", name));
+    }
+
+    fn empty(&mut self, name: &str, attrs: &Attrs<'_>) -> Result<(), NuGetApiError> {
+        self.start_tag(name, attrs)?;
+        self.out.push_str(" />\n");
+        Ok(())
+    }
+
+    /// `<name>value</name>`, if there's a value.
+    fn element(&mut self, name: &str, value: Option<&impl Display>) -> Result<(), NuGetApiError> {
+        match value {
+            Some(value) => self.element_with(name, &[], &value.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn element_with(
+        &mut self,
+        name: &str,
+        attrs: &Attrs<'_>,
+        text: &str,
+    ) -> Result<(), NuGetApiError> {
+        self.start_tag(name, attrs)?;
+        self.out.push('>');
+        self.out.push_str(&escape(name, text, false)?);
+        self.out.push_str(&format!("</{}>\n", name));
+        Ok(())
+    }
+
+    fn start_tag(&mut self, name: &str, attrs: &Attrs<'_>) -> Result<(), NuGetApiError> {
+        self.indent();
+        self.out.push('<');
+        self.out.push_str(name);
+        for (attr, value) in attrs {
+            if let Some(value) = value {
+                let value = escape(&format!("{}@{}", name, attr), value, true)?;
+                self.out.push_str(&format!(" {}=\"{}\"", attr, value));
+            }
+        }
+        Ok(())
+    }
+
+    fn indent(&mut self) {
+        self.out.push_str(&"  ".repeat(self.depth));
+    }
+}
+
+/// Escapes `text` for use in an element, or an attribute if `attr` is set.
+/// Attributes also get their whitespace escaped, since XML parsers would
+/// turn newlines and tabs in them into plain spaces.
+fn escape(field: &str, text: &str, attr: bool) -> Result<String, NuGetApiError> {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attr => escaped.push_str("&quot;"),
+            '\n' if attr => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' if attr => escaped.push_str("&#9;"),
+            '\t' | '\n' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => {
+                return Err(NuGetApiError::InvalidNuSpec(format!(
+                    "{} has a character XML can't hold: U+{:04X}.",
+                    field, c as u32
+                )));
+            }
+            c => escaped.push(c),
+        }
+    }
+    Ok(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::quick_xml;
+    use turron_testing::fixtures;
+
+    use super::*;
+
+    fn parse(xml: &str) -> NuSpec {
+        quick_xml::de::from_str(xml).unwrap()
+    }
+
+    /// Parses `xml`, writes it back out, and checks that parsing that gives
+    /// the same thing.
+    fn round_trip(xml: &str) -> (NuSpec, String) {
+        let parsed = parse(xml);
+        let written = parsed.to_xml_string().unwrap();
+        assert_eq!(parse(&written), parsed, "wrote:\n{}", written);
+        assert_eq!(parse(&written).to_xml_string().unwrap(), written);
+        (parsed, written)
+    }
+
+    #[test]
+    fn round_trips_real_nuspecs() {
+        let (newtonsoft, written) = round_trip(fixtures::nuspec());
+        assert_eq!(newtonsoft.metadata.title.as_deref(), Some("Json.NET"));
+        assert!(written.starts_with(concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<package xmlns=\"http://schemas.microsoft.com/packaging/2013/05/nuspec.xsd\">\n",
+            "  <metadata minClientVersion=\"2.12.0\">\n",
+            "    <id>Newtonsoft.Json</id>\n",
+        )));
+        assert!(written.contains("    <license type=\"expression\">MIT</license>\n"));
+        assert!(written.contains(
+            "    <repository type=\"git\" url=\"https://github.com/JamesNK/Newtonsoft.Json\" commit=\"ae9fe44e1323e91bcbd185ca1a14099fba7c021f\" />\n"
+        ));
+        assert!(written.contains(concat!(
+            "      <group targetFramework=\".NETFramework2.0\" />\n",
+            "      <group targetFramework=\".NETStandard1.0\">\n",
+            "        <dependency id=\"Microsoft.CSharp\" version=\"4.3.0\" exclude=\"Build,Analyzers\" />\n",
+        )));
+
+        let (xunit, written) = round_trip(fixtures::xunit_nuspec());
+        assert!(xunit.metadata.description.contains("\n\nInstalling"));
+        assert!(written.contains(concat!(
+            "    <dependencies>\n",
+            "      <dependency id=\"xunit.analyzers\" version=\"0.10.0\" />\n",
+            "      <dependency id=\"xunit.assert\" version=\"[2.4.1]\" />\n",
+        )));
+
+        let (tool, written) = round_trip(fixtures::tool_nuspec());
+        let metadata = &tool.metadata;
+        assert_eq!(
+            metadata.package_types.as_ref().unwrap().package_types[0].name,
+            "DotnetTool"
+        );
+        let assemblies = &metadata.framework_assemblies.as_ref().unwrap().assemblies;
+        assert_eq!(assemblies.len(), 2);
+        assert_eq!(
+            assemblies[1].target_framework.as_deref(),
+            Some(".NETFramework4.5")
+        );
+        let references = metadata.references.as_ref().unwrap();
+        assert_eq!(references.groups.len(), 2);
+        assert_eq!(
+            references.groups[0].references[1].file,
+            "Turron.Tool.Core.dll"
+        );
+        let content = &metadata.content_files.as_ref().unwrap().files;
+        assert_eq!(content[0].copy_to_output, Some(true));
+        assert_eq!(content[1].build_action.as_deref(), Some("None"));
+        let files = &tool.files.as_ref().unwrap().files;
+        assert_eq!(files[1].exclude.as_deref(), Some("**/*.pdb"));
+        assert!(written.contains("    <license type=\"file\">LICENSE.txt</license>\n"));
+        assert!(
+            written.contains("    <releaseNotes>Fixes &lt;tool&gt; &amp; more.</releaseNotes>\n")
+        );
+        assert!(written.contains(concat!(
+            "  <files>\n",
+            "    <file src=\"bin/Release/**\" target=\"tools\" />\n",
+        )));
+    }
+
+    #[test]
+    fn writes_dependency_ranges() {
+        let ranges = parse(fixtures::ranges_nuspec());
+        let written = ranges.to_xml_string().unwrap();
+        assert!(written.contains(concat!(
+            "      <group targetFramework=\"net6.0\">\n",
+            "        <dependency id=\"Newtonsoft.Json\" version=\"13.0.1\" />\n",
+            "        <dependency id=\"Serilog\" version=\"[2.0.0,3.0.0)\" />\n",
+            "        <dependency id=\"System.Memory\" version=\"4.5.4\" exclude=\"Build,Analyzers\" />\n",
+            "        <dependency id=\"Polly\" version=\"(7.0.0,8.0.0]\" />\n",
+            "        <dependency id=\"Anything.Goes\" />\n",
+            "        <dependency id=\"Also.Anything\" />\n",
+            "      </group>\n",
+            "      <group targetFramework=\".NETStandard2.0\" />\n",
+        )));
+        let reparsed = parse(&written);
+        let group = &reparsed.metadata.dependencies.unwrap().groups[0];
+        // Everything but the floating range comes back as it was.
+        for (i, dependency) in group.dependencies.iter().enumerate() {
+            let original =
+                &ranges.metadata.dependencies.as_ref().unwrap().groups[0].dependencies[i];
+            if original.version.is_floating() {
+                assert!(!dependency.version.is_floating());
+                assert!(dependency.version.satisfies(&"2.5.0".parse().unwrap()));
+                assert!(!dependency.version.satisfies(&"3.0.0".parse().unwrap()));
+            } else {
+                assert_eq!(dependency, original);
+            }
+        }
+    }
+
+    #[test]
+    fn escapes_what_it_has_to() {
+        let mut nuspec = parse(fixtures::xunit_nuspec());
+        nuspec.metadata.title = Some("Tabs\tand \"quotes\" & <brackets>".into());
+        nuspec.metadata.dependencies.as_mut().unwrap().dependencies[0].exclude =
+            Some("Line\nbreak \"here\"".into());
+        let written = nuspec.to_xml_string().unwrap();
+        assert!(written.contains("<title>Tabs\tand \"quotes\" &amp; &lt;brackets&gt;</title>"));
+        assert!(written.contains("exclude=\"Line&#10;break &quot;here&quot;\""));
+        assert_eq!(parse(&written), nuspec);
+    }
+
+    #[test]
+    fn refuses_what_it_cant_write() {
+        let mut nuspec = parse(fixtures::xunit_nuspec());
+        nuspec.metadata.authors = "  ".into();
+        assert!(matches!(
+            nuspec.to_xml_string(),
+            Err(NuGetApiError::InvalidNuSpec(reason)) if reason.contains("<authors>")
+        ));
+
+        let mut nuspec = parse(fixtures::xunit_nuspec());
+        nuspec.metadata.tags = Some("bell\u{7}".into());
+        assert!(matches!(
+            nuspec.to_xml_string(),
+            Err(NuGetApiError::InvalidNuSpec(reason)) if reason.contains("U+0007")
+        ));
+    }
+
+    #[test]
+    fn tells_license_files_from_expressions() {
+        let mut nuspec = parse(fixtures::tool_nuspec());
+        assert_eq!(nuspec.metadata.license_file(), Some("LICENSE.txt"));
+        nuspec.metadata.license = Some("MIT OR Apache-2.0".into());
+        assert_eq!(nuspec.metadata.license_file(), None);
+    }
+}
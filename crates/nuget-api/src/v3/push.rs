@@ -1,51 +1,318 @@
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
 use turron_common::{
-    smol::io::{AsyncReadExt, Cursor},
-    surf::{self, Body, StatusCode},
+    smol::{
+        fs::{self, File},
+        io::{self, AsyncRead, AsyncReadExt, BufReader, Chain, Cursor},
+    },
+    surf::{self, Body, Response, StatusCode, Url},
 };
 
 use crate::errors::NuGetApiError;
-use crate::v3::NuGetClient;
+use crate::v3::{retry::rate_limited, NuGetClient};
 
-impl NuGetClient {
-    pub async fn push(self, body: Body) -> Result<(), NuGetApiError> {
-        use NuGetApiError::*;
-        let line1 = "--X-BOUNDARY\r\n".as_bytes();
-        let line2 =
-            "Content-Disposition: form-data; name=\"package\";filename=\"package.nupkg\"\r\n\r\n"
-                .as_bytes();
-        let line3 = "\r\n--X-BOUNDARY--\r\n".as_bytes();
-        let len = body
-            .len()
-            .map(|len| len + line1.len() + line2.len() + line3.len());
-        let chain = Cursor::new(line1)
-            .chain(Cursor::new(line2))
-            .chain(body)
-            .chain(Cursor::new(line3));
-        let body = Body::from_reader(chain, len);
+const HEAD: &str =
+    "--X-BOUNDARY\r\nContent-Disposition: form-data; name=\"package\";filename=\"package.nupkg\"\r\n\r\n";
+const TAIL: &str = "\r\n--X-BOUNDARY--\r\n";
+
+/// Told how many bytes of the package have been sent so far, and how many
+/// there are in total.
+type Progress = Arc<dyn Fn(u64, u64) + Send + Sync>;
 
+impl NuGetClient {
+    /// Pushes the package at `path` to the source. The package is streamed
+    /// from disk as it's sent, so it never has to fit in memory.
+    ///
+    /// `progress` is called as the upload goes, with how many of the
+    /// package's bytes have been sent and how many there are. If the push
+    /// gets retried, it starts over from `0`.
+    pub async fn push<P>(&self, path: impl AsRef<Path>, progress: P) -> Result<(), NuGetApiError>
+    where
+        P: Fn(u64, u64) + Send + Sync + 'static,
+    {
         let url = self
             .endpoints
             .publish
             .clone()
-            .ok_or_else(|| UnsupportedEndpoint("PackagePublish/2.0.0".into()))?;
-        let req = surf::put(&url)
-            .header("X-NuGet-ApiKey", self.get_key()?)
-            .header("X-NuGet-Protocol-Version", "4.1.0")
-            .header("Content-Type", "multipart/form-data; boundary=X-BOUNDARY")
-            .body(body);
+            .ok_or_else(|| NuGetApiError::UnsupportedEndpoint("PackagePublish/2.0.0".into()))?;
+        self.push_to(url, Upload::File(path.as_ref().into()), Arc::new(progress))
+            .await
+    }
 
-        let res = self
-            .client
-            .send(req)
+    /// Pushes a symbol package (.snupkg) to the source's symbol server.
+    /// Streams and reports progress just like [`NuGetClient::push`].
+    pub async fn push_symbols<P>(
+        &self,
+        path: impl AsRef<Path>,
+        progress: P,
+    ) -> Result<(), NuGetApiError>
+    where
+        P: Fn(u64, u64) + Send + Sync + 'static,
+    {
+        let url = self.endpoints.symbol_publish.clone().ok_or_else(|| {
+            NuGetApiError::UnsupportedEndpoint("SymbolPackagePublish/4.9.0".into())
+        })?;
+        self.push_to(url, Upload::File(path.as_ref().into()), Arc::new(progress))
             .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.into()))?;
+    }
 
+    /// Sends the publish endpoint an authenticated push whose "package" isn't
+    /// a package at all, and returns the status it answered with. Sources
+    /// check the API key before looking at the upload, so a `400` means the
+    /// key was accepted, without anything actually getting published.
+    pub async fn probe_publish(&self) -> Result<StatusCode, NuGetApiError> {
+        let url = self
+            .endpoints
+            .publish
+            .clone()
+            .ok_or_else(|| NuGetApiError::UnsupportedEndpoint("PackagePublish/2.0.0".into()))?;
+        let res = self
+            .send_push(
+                &url,
+                &Upload::Bytes(b"not a nupkg".to_vec()),
+                Arc::new(|_, _| {}),
+            )
+            .await?;
+        Ok(res.status())
+    }
+
+    async fn push_to(
+        &self,
+        url: Url,
+        upload: Upload,
+        progress: Progress,
+    ) -> Result<(), NuGetApiError> {
+        use NuGetApiError::*;
+        let res = self.send_push(&url, &upload, progress).await?;
         match res.status() {
             s if s.is_success() => Ok(()),
             StatusCode::BadRequest => Err(InvalidPackage),
             StatusCode::Conflict => Err(PackageAlreadyExists),
             StatusCode::Forbidden => Err(BadApiKey(self.get_key()?)),
+            StatusCode::TooManyRequests => Err(rate_limited(&res, &url)),
             code => Err(BadResponse(code)),
         }
     }
+
+    async fn send_push(
+        &self,
+        url: &Url,
+        upload: &Upload,
+        progress: Progress,
+    ) -> Result<Response, NuGetApiError> {
+        self.ensure_online(url)?;
+        let key = self.get_key()?;
+        if let Upload::File(path) = upload {
+            // Fail on a missing or unreadable package up front, instead of
+            // as a connection error.
+            fs::metadata(path).await?;
+        }
+
+        // Every attempt gets a fresh body, read from the start of the
+        // package again.
+        let retries = self.retries.for_request(false);
+        retries
+            .run(url, || {
+                let (key, progress) = (&key, progress.clone());
+                async move {
+                    let body = upload.body(progress).await?;
+                    self.client
+                        .send(
+                            surf::put(url)
+                                .header("X-NuGet-ApiKey", key.as_str())
+                                .header("X-NuGet-Protocol-Version", "4.1.0")
+                                .header("Content-Type", "multipart/form-data; boundary=X-BOUNDARY")
+                                .body(body),
+                        )
+                        .await
+                }
+            })
+            .await
+            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))
+    }
+}
+
+/// What a push sends as its "package" part.
+enum Upload {
+    File(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+impl Upload {
+    /// A `multipart/form-data` body for this upload, with an accurate
+    /// length, that reads the package as it gets sent.
+    async fn body(&self, progress: Progress) -> io::Result<Body> {
+        Ok(match self {
+            Upload::File(path) => {
+                let file = File::open(path).await?;
+                let len = file.metadata().await?.len();
+                Multipart::new(file, len, progress).into_body()
+            }
+            Upload::Bytes(bytes) => {
+                let len = bytes.len() as u64;
+                Multipart::new(Cursor::new(bytes.clone()), len, progress).into_body()
+            }
+        })
+    }
+}
+
+/// The multipart framing around a package that's `len` bytes long, read
+/// from `R` on demand. Counts bytes as they're read, and reports how much of
+/// the package itself has gone out.
+struct Multipart<R> {
+    inner: Chain<Chain<Cursor<&'static [u8]>, R>, Cursor<&'static [u8]>>,
+    read: u64,
+    len: u64,
+    progress: Progress,
+}
+
+impl<R> Multipart<R>
+where
+    R: AsyncRead + Unpin + Send + Sync + 'static,
+{
+    fn new(package: R, len: u64, progress: Progress) -> Self {
+        progress(0, len);
+        Multipart {
+            inner: Cursor::new(HEAD.as_bytes())
+                .chain(package)
+                .chain(Cursor::new(TAIL.as_bytes())),
+            read: 0,
+            len,
+            progress,
+        }
+    }
+
+    fn into_body(self) -> Body {
+        let len = HEAD.len() as u64 + self.len + TAIL.len() as u64;
+        Body::from_reader(BufReader::new(self), Some(len as usize))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Multipart<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(read)) => {
+                self.read += read as u64;
+                let sent = self.read.saturating_sub(HEAD.len() as u64).min(self.len);
+                (self.progress)(sent, self.len);
+                Poll::Ready(Ok(read))
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use turron_common::smol;
+    use turron_testing::TestServer;
+
+    use super::*;
+
+    const PUBLISH: &str = "/api/v2/package";
+
+    async fn push(server: &TestServer) -> Result<(), NuGetApiError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("Foo.1.0.0.nupkg");
+        std::fs::write(&path, "not really a nupkg")?;
+        NuGetClient::from_source(server.index_url())
+            .await?
+            .with_key(Some("key"))
+            .push(&path, |_, _| {})
+            .await
+    }
+
+    #[test]
+    fn pushes_packages() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.respond(PUBLISH, 201, "");
+            push(&server).await.unwrap();
+            assert_eq!(server.hits(PUBLISH), 1);
+        });
+    }
+
+    #[test]
+    fn probes_publish_without_publishing() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .respond_once(PUBLISH, 400, "")
+                .respond_once(PUBLISH, 403, "");
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_key(Some("key"));
+            assert_eq!(
+                client.probe_publish().await.unwrap(),
+                StatusCode::BadRequest
+            );
+            assert_eq!(client.probe_publish().await.unwrap(), StatusCode::Forbidden);
+            assert_eq!(client.probe_publish().await.unwrap(), StatusCode::NotFound);
+        });
+    }
+
+    #[test]
+    fn conflicts_mean_the_package_exists() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.respond(PUBLISH, 409, "");
+            assert!(matches!(
+                push(&server).await,
+                Err(NuGetApiError::PackageAlreadyExists)
+            ));
+        });
+    }
+
+    #[test]
+    fn streams_large_packages() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.respond(PUBLISH, 201, "");
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("Big.1.0.0.nupkg");
+            let package = (0..8 * 1024 * 1024)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<_>>();
+            std::fs::write(&path, &package).unwrap();
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let progress = seen.clone();
+            NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_key(Some("key"))
+                .push(&path, move |sent, total| {
+                    progress.lock().unwrap().push((sent, total))
+                })
+                .await
+                .unwrap();
+
+            let bodies = server.request_bodies(PUBLISH);
+            assert_eq!(bodies.len(), 1);
+            let body = &bodies[0];
+            assert_eq!(body.len(), HEAD.len() + package.len() + TAIL.len());
+            assert_eq!(
+                server.request_headers(PUBLISH, "Content-Length"),
+                vec![Some(body.len().to_string())]
+            );
+            assert!(body.starts_with(HEAD.as_bytes()));
+            assert!(body.ends_with(TAIL.as_bytes()));
+            assert!(body[HEAD.len()..body.len() - TAIL.len()] == package[..]);
+
+            let total = package.len() as u64;
+            let seen = seen.lock().unwrap();
+            assert_eq!(seen.first(), Some(&(0, total)));
+            assert_eq!(seen.last(), Some(&(total, total)));
+            assert!(seen.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        });
+    }
 }
@@ -0,0 +1,534 @@
+use std::convert::TryInto;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use dotnet_semver::Version;
+use turron_common::{
+    smol,
+    surf::{
+        self,
+        http::headers::{ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE},
+        StatusCode, Url,
+    },
+    tracing,
+};
+use zip::ZipArchive;
+
+use crate::errors::NuGetApiError;
+use crate::v3::{retry::rate_limited, unavailable, NuGetClient};
+
+/// How much of the end of a .nupkg to fetch first. The central directory is
+/// almost always in here, along with the end-of-central-directory record and
+/// its (at most 64KiB) comment.
+const TAIL_SIZE: u64 = 64 * 1024;
+
+/// Extra bytes fetched after an entry's local header, in case its extra
+/// field is longer than the central directory's copy.
+const LOCAL_HEADER_SLACK: u64 = 1024;
+
+const LOCAL_HEADER_LEN: u64 = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+
+impl NuGetClient {
+    /// Like [`NuGetClient::get_from_nupkg`], but tries not to download the
+    /// whole package. When the source supports `Range` requests, this reads
+    /// the zip's central directory off the end of the .nupkg, and then just
+    /// the bytes for `filename`. Sources that don't, and packages that can't
+    /// be read that way, fall back to downloading the whole thing.
+    pub async fn nupkg_entry_ranged(
+        &self,
+        package_id: impl AsRef<str>,
+        version: &Version,
+        filename: impl AsRef<str>,
+    ) -> Result<Vec<u8>, NuGetApiError> {
+        let package_id = package_id.as_ref();
+        let filename = filename.as_ref();
+        let memoized = self
+            .nupkg_memo
+            .lock()
+            .expect("nupkg memo lock poisoned")
+            .get(package_id, version)
+            .is_some();
+        if !memoized {
+            if let Some(data) = self.ranged_entry(package_id, version, filename).await? {
+                return Ok(data);
+            }
+            tracing::debug!(
+                "Couldn't read {} out of {}@{} with ranged requests. Downloading the whole package.",
+                filename,
+                package_id,
+                version
+            );
+        }
+        self.get_from_nupkg(package_id, version, filename).await
+    }
+
+    /// The ranged half of [`NuGetClient::nupkg_entry_ranged`]. `None` means
+    /// it's time to fall back to a full download.
+    async fn ranged_entry(
+        &self,
+        package_id: &str,
+        version: &Version,
+        filename: &str,
+    ) -> Result<Option<Vec<u8>>, NuGetApiError> {
+        let url = self.nupkg_url(package_id, version)?;
+        if let Some(cache) = &self.cache {
+            // A full download would come straight out of the cache, or has
+            // to.
+            if cache.is_offline() || cache.load(&url).await.is_some() {
+                return Ok(None);
+            }
+        }
+        let len = match self.rangeable_len(&url).await? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        let mut sparse = Sparse::new(len);
+        let tail_start = len.saturating_sub(TAIL_SIZE);
+        if !self.fetch_range(&url, &mut sparse, tail_start, len).await? {
+            return Ok(None);
+        }
+        let (cd_start, cd_len) = match end_of_central_directory(&sparse) {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        if cd_start < tail_start
+            && !self
+                .fetch_range(&url, &mut sparse, cd_start, tail_start)
+                .await?
+        {
+            return Ok(None);
+        }
+        let entries = match sparse.get(cd_start, cd_len).and_then(central_directory) {
+            Some(entries) => entries,
+            None => return Ok(None),
+        };
+        let wanted = filename.to_lowercase();
+        let entry = match entries
+            .into_iter()
+            .find(|e| e.name.to_lowercase() == wanted)
+        {
+            Some(entry) => entry,
+            None => {
+                return Err(NuGetApiError::FileNotFound(
+                    package_id.into(),
+                    version.clone(),
+                    wanted,
+                ))
+            }
+        };
+        let entry_end = (entry.header_start
+            + LOCAL_HEADER_LEN
+            + entry.name_len
+            + entry.extra_len
+            + entry.compressed_size
+            + LOCAL_HEADER_SLACK)
+            .min(cd_start);
+        if !self
+            .fetch_range(&url, &mut sparse, entry.header_start, entry_end)
+            .await?
+        {
+            return Ok(None);
+        }
+        // The local header's own extra field might not match the central
+        // directory's, so check that everything really got fetched.
+        let local_end = sparse
+            .get(entry.header_start, LOCAL_HEADER_LEN as usize)
+            .map(|header| {
+                entry.header_start
+                    + LOCAL_HEADER_LEN
+                    + u64::from(u16_at(header, 26))
+                    + u64::from(u16_at(header, 28))
+                    + entry.compressed_size
+            });
+        match local_end {
+            Some(local_end) if local_end > entry_end => {
+                if !self
+                    .fetch_range(&url, &mut sparse, entry_end, local_end)
+                    .await?
+                {
+                    return Ok(None);
+                }
+            }
+            Some(_) => {}
+            None => return Ok(None),
+        }
+        let name = entry.name;
+        let read = smol::unblock(move || {
+            let mut zip = ZipArchive::new(sparse)?;
+            let mut file = zip.by_name(&name)?;
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            Ok::<_, NuGetApiError>(buf)
+        })
+        .await;
+        match read {
+            Ok(data) => Ok(Some(data)),
+            Err(err) => {
+                tracing::debug!("Failed to read a ranged nupkg entry: {}", err);
+                Ok(None)
+            }
+        }
+    }
+
+    /// The size of the file at `url`, if the source says it can serve parts
+    /// of it.
+    async fn rangeable_len(&self, url: &Url) -> Result<Option<u64>, NuGetApiError> {
+        use NuGetApiError::*;
+        let res = self
+            .retries
+            .run(url, || self.client.send(surf::head(url)))
+            .await
+            .map_err(|e| SurfError(e, url.clone().into()))?;
+        match res.status() {
+            StatusCode::Ok => {}
+            StatusCode::NotFound => return Err(PackageNotFound),
+            StatusCode::TooManyRequests => return Err(rate_limited(&res, url)),
+            StatusCode::UnavailableForLegalReasons => {
+                return Err(unavailable::legally_unavailable(&res, url))
+            }
+            // Some hosts don't do HEAD. They can still do a full download.
+            _ => return Ok(None),
+        }
+        let ranges = res
+            .header(ACCEPT_RANGES)
+            .map(|values| values.last().as_str().trim().eq_ignore_ascii_case("bytes"))
+            .unwrap_or(false);
+        Ok(res
+            .header(CONTENT_LENGTH)
+            .and_then(|values| values.last().as_str().trim().parse().ok())
+            .filter(|_| ranges))
+    }
+
+    /// Fetches bytes `start..end` of `url` into `sparse`, unless it already
+    /// has them. Returns `false` if the source didn't answer with exactly
+    /// those bytes.
+    async fn fetch_range(
+        &self,
+        url: &Url,
+        sparse: &mut Sparse,
+        start: u64,
+        end: u64,
+    ) -> Result<bool, NuGetApiError> {
+        use NuGetApiError::*;
+        if start >= end || sparse.get(start, (end - start) as usize).is_some() {
+            return Ok(true);
+        }
+        let range = format!("bytes={}-{}", start, end - 1);
+        let mut res = self
+            .retries
+            .run(url, || {
+                self.client.send(
+                    surf::get(url)
+                        .header("Range", range.as_str())
+                        // Byte offsets are into the file as stored, so
+                        // nothing should get compressed on the way.
+                        .header(ACCEPT_ENCODING, "identity"),
+                )
+            })
+            .await
+            .map_err(|e| SurfError(e, url.clone().into()))?;
+        match res.status() {
+            StatusCode::PartialContent => {}
+            StatusCode::NotFound => return Err(PackageNotFound),
+            StatusCode::TooManyRequests => return Err(rate_limited(&res, url)),
+            StatusCode::UnavailableForLegalReasons => {
+                return Err(unavailable::legally_unavailable(&res, url))
+            }
+            _ => return Ok(false),
+        }
+        let expected = format!("bytes {}-{}/{}", start, end - 1, sparse.len);
+        let honored = res
+            .header(CONTENT_RANGE)
+            .map(|values| values.last().as_str().trim() == expected)
+            .unwrap_or(false);
+        if !honored {
+            return Ok(false);
+        }
+        let body = res
+            .body_bytes()
+            .await
+            .map_err(|e| SurfError(e, url.clone().into()))?;
+        if body.len() as u64 != end - start {
+            return Ok(false);
+        }
+        sparse.insert(start, body);
+        Ok(true)
+    }
+}
+
+/// A file of `len` bytes, only parts of which have been fetched. Reading
+/// anywhere else is an error.
+#[derive(Debug)]
+struct Sparse {
+    len: u64,
+    pos: u64,
+    /// Fetched bytes, by offset.
+    parts: Vec<(u64, Vec<u8>)>,
+}
+
+impl Sparse {
+    fn new(len: u64) -> Self {
+        Sparse {
+            len,
+            pos: 0,
+            parts: Vec::new(),
+        }
+    }
+
+    /// Adds fetched bytes. Parts that touch get joined, so reads can span
+    /// them.
+    fn insert(&mut self, start: u64, mut bytes: Vec<u8>) {
+        let end = start + bytes.len() as u64;
+        if let Some(next) = self.parts.iter().position(|(offset, _)| *offset == end) {
+            bytes.extend(self.parts.remove(next).1);
+        }
+        let prev = self
+            .parts
+            .iter_mut()
+            .find(|(offset, part)| offset + part.len() as u64 == start);
+        match prev {
+            Some((_, part)) => part.extend(bytes),
+            None => self.parts.push((start, bytes)),
+        }
+    }
+
+    /// `len` bytes from `start`, if they were all fetched together.
+    fn get(&self, start: u64, len: usize) -> Option<&[u8]> {
+        self.parts.iter().find_map(|(offset, bytes)| {
+            let from = start.checked_sub(*offset)? as usize;
+            bytes.get(from..from.checked_add(len)?)
+        })
+    }
+}
+
+impl Read for Sparse {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let pos = self.pos;
+        let available = self.parts.iter().find_map(|(offset, bytes)| {
+            let from = pos.checked_sub(*offset)? as usize;
+            bytes.get(from..).filter(|rest| !rest.is_empty())
+        });
+        match available {
+            Some(rest) => {
+                let read = rest.len().min(buf.len());
+                buf[..read].copy_from_slice(&rest[..read]);
+                self.pos += read as u64;
+                Ok(read)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("byte {} of the .nupkg wasn't fetched", pos),
+            )),
+        }
+    }
+}
+
+impl Seek for Sparse {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => add_signed(self.len, delta),
+            SeekFrom::Current(delta) => add_signed(self.pos, delta),
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to before the start of the .nupkg",
+            )),
+        }
+    }
+}
+
+fn add_signed(base: u64, delta: i64) -> Option<u64> {
+    if delta < 0 {
+        base.checked_sub(delta.wrapping_neg() as u64)
+    } else {
+        base.checked_add(delta as u64)
+    }
+}
+
+/// An entry in a zip's central directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CentralEntry {
+    name: String,
+    header_start: u64,
+    compressed_size: u64,
+    name_len: u64,
+    extra_len: u64,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+/// Finds the end-of-central-directory record in the fetched end of a zip,
+/// and returns where the central directory starts and how long it is.
+/// Zip64 archives aren't handled.
+fn end_of_central_directory(sparse: &Sparse) -> Option<(u64, usize)> {
+    let search_start = sparse.len.saturating_sub(TAIL_SIZE);
+    let tail = sparse.get(search_start, (sparse.len - search_start) as usize)?;
+    let eocd = (0..=tail.len().checked_sub(END_OF_CENTRAL_DIRECTORY_LEN)?)
+        .rev()
+        .find(|&at| u32_at(tail, at) == 0x0605_4b50)?;
+    let record = &tail[eocd..];
+    let (cd_len, cd_start) = (u32_at(record, 12), u32_at(record, 16));
+    if u16_at(record, 10) == 0xffff || cd_len == 0xffff_ffff || cd_start == 0xffff_ffff {
+        return None;
+    }
+    let cd_start = u64::from(cd_start);
+    if cd_start + u64::from(cd_len) > search_start + eocd as u64 {
+        return None;
+    }
+    Some((cd_start, cd_len as usize))
+}
+
+/// Parses a zip's central directory. `None` if it's malformed, or uses
+/// Zip64 sizes or offsets.
+fn central_directory(cd: &[u8]) -> Option<Vec<CentralEntry>> {
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while at < cd.len() {
+        let header = cd.get(at..at + CENTRAL_HEADER_LEN)?;
+        if u32_at(header, 0) != 0x0201_4b50 {
+            return None;
+        }
+        let compressed_size = u32_at(header, 20);
+        let header_start = u32_at(header, 42);
+        if compressed_size == 0xffff_ffff || header_start == 0xffff_ffff {
+            return None;
+        }
+        let name_len = usize::from(u16_at(header, 28));
+        let extra_len = usize::from(u16_at(header, 30));
+        let comment_len = usize::from(u16_at(header, 32));
+        let name = cd.get(at + CENTRAL_HEADER_LEN..at + CENTRAL_HEADER_LEN + name_len)?;
+        entries.push(CentralEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            header_start: u64::from(header_start),
+            compressed_size: u64::from(compressed_size),
+            name_len: name_len as u64,
+            extra_len: extra_len as u64,
+        });
+        at += CENTRAL_HEADER_LEN + name_len + extra_len + comment_len;
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use turron_testing::{NupkgBuilder, TestServer};
+
+    use super::*;
+
+    /// Bytes that won't compress, so the package is actually big.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn big_nupkg() -> Vec<u8> {
+        NupkgBuilder::new("Big", "1.0.0")
+            .file("lib/net6.0/Big.dll", noise(512 * 1024))
+            .readme("README.md", "# Big\n\nIt's big.")
+            .file("tools/more.dll", noise(256 * 1024))
+            .build()
+    }
+
+    #[test]
+    fn reads_entries_with_ranges() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let nupkg = big_nupkg();
+            server.route("/v3-flatcontainer/big/1.0.0/big.1.0.0.nupkg", nupkg.clone());
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let version = "1.0.0".parse().unwrap();
+            let served = server.bytes_served();
+
+            let readme = client
+                .nupkg_entry_ranged("Big", &version, "readme.md")
+                .await
+                .unwrap();
+            assert_eq!(readme, b"# Big\n\nIt's big.");
+            assert!(server.bytes_served() - served < nupkg.len() / 4);
+
+            let dll = client
+                .nupkg_entry_ranged("Big", &version, "lib/net6.0/Big.dll")
+                .await
+                .unwrap();
+            assert_eq!(dll, noise(512 * 1024));
+
+            let missing = client.nupkg_entry_ranged("Big", &version, "nope.txt").await;
+            assert!(matches!(missing, Err(NuGetApiError::FileNotFound(..))));
+        })
+    }
+
+    #[test]
+    fn falls_back_without_ranges() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.ignore_ranges();
+            let nupkg = big_nupkg();
+            server.route("/v3-flatcontainer/big/1.0.0/big.1.0.0.nupkg", nupkg.clone());
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_nupkg_memo_size(0);
+            let version = "1.0.0".parse().unwrap();
+            let served = server.bytes_served();
+
+            let readme = client
+                .nupkg_entry_ranged("Big", &version, "README.md")
+                .await
+                .unwrap();
+            assert_eq!(readme, b"# Big\n\nIt's big.");
+            assert!(server.bytes_served() - served >= nupkg.len());
+        })
+    }
+
+    #[test]
+    fn reads_small_central_directories() {
+        let nupkg = NupkgBuilder::new("Foo", "1.0.0")
+            .readme("docs/README.md", "hi")
+            .build();
+        let mut sparse = Sparse::new(nupkg.len() as u64);
+        sparse.insert(0, nupkg.clone());
+        let (start, len) = end_of_central_directory(&sparse).unwrap();
+        let entries = central_directory(sparse.get(start, len).unwrap()).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| &e.name[..]).collect::<Vec<_>>(),
+            vec!["foo.nuspec", "[Content_Types].xml", "docs/README.md"]
+        );
+        let mut zip = ZipArchive::new(Cursor::new(nupkg)).unwrap();
+        assert_eq!(
+            zip.by_name("docs/README.md").unwrap().header_start(),
+            entries[2].header_start
+        );
+
+        // Nothing but the central directory and end record can be read.
+        let mut sparse = Sparse::new(sparse.len);
+        let tail = zip.into_inner().into_inner()[start as usize..].to_vec();
+        sparse.insert(start, tail);
+        let mut zip = ZipArchive::new(sparse).unwrap();
+        assert!(zip.by_name("docs/README.md").is_err());
+    }
+}
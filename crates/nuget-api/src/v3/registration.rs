@@ -1,14 +1,19 @@
+use std::fmt;
+use std::str::FromStr;
+
 use dotnet_semver::{Range, Version};
 pub use turron_common::surf::Body;
 use turron_common::{
     chrono::{DateTime, Utc},
     serde::{Deserialize, Serialize},
-    serde_json, serde_with,
-    surf::{self, StatusCode, Url},
+    serde_with,
+    smol::{channel, Executor},
+    surf::{StatusCode, Url},
+    tracing,
 };
 
 use crate::errors::NuGetApiError;
-use crate::v3::NuGetClient;
+use crate::v3::{encoding::from_json_response, NuGetClient};
 
 impl NuGetClient {
     pub async fn registration_page(
@@ -17,23 +22,10 @@ impl NuGetClient {
     ) -> Result<RegistrationPage, NuGetApiError> {
         use NuGetApiError::*;
         let url = Url::parse(page.as_ref())?;
-        let req = surf::get(url.clone());
-
-        let mut res = self
-            .client
-            .send(req)
-            .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
-
-        match res.status() {
-            StatusCode::Ok => {
-                let body = res
-                    .body_string()
-                    .await
-                    .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
-                Ok(serde_json::from_str(&body)
-                    .map_err(|e| NuGetApiError::from_json_err(e, url.into(), body))?)
-            }
+        let (status, body) = self.get_shared(&url).await?;
+
+        match status {
+            StatusCode::Ok => from_json_response(&body, url.as_str()),
             StatusCode::NotFound => Err(RegistrationPageNotFound),
             code => Err(BadResponse(code)),
         }
@@ -48,33 +40,131 @@ impl NuGetClient {
             .endpoints
             .registration
             .clone()
-            .ok_or_else(|| UnsupportedEndpoint("RegistrationsBaseUrl/3.6.0".into()))?
+            .ok_or_else(|| UnsupportedEndpoint("RegistrationsBaseUrl".into()))?
             .join(&format!(
                 "{}/index.json",
                 &package_id.as_ref().to_lowercase()
             ))?;
 
-        let req = surf::get(url.clone());
-
-        let mut res = self
-            .client
-            .send(req)
-            .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
-
-        match res.status() {
-            StatusCode::Ok => {
-                let body = res
-                    .body_string()
-                    .await
-                    .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
-                Ok(serde_json::from_str(&body)
-                    .map_err(|e| NuGetApiError::from_json_err(e, url.into(), body))?)
-            }
+        let (status, body) = self.get_shared(&url).await?;
+
+        match status {
+            StatusCode::Ok => from_json_response(&body, url.as_str()),
             StatusCode::NotFound => Err(PackageNotFound),
             code => Err(BadResponse(code)),
         }
     }
+
+    /// All of `package_id`'s registration leaves, fetching any pages the
+    /// index doesn't inline.
+    pub async fn registration_leaves(
+        &self,
+        package_id: impl AsRef<str>,
+    ) -> Result<Vec<RegistrationLeaf>, NuGetApiError> {
+        let index = self.registration(package_id).await?;
+        self.leaves_in(&index).await
+    }
+
+    /// Like [`NuGetClient::registration_leaves`], for an index that's
+    /// already been fetched.
+    pub async fn leaves_in(
+        &self,
+        index: &RegistrationIndex,
+    ) -> Result<Vec<RegistrationLeaf>, NuGetApiError> {
+        let mut leaves = Vec::new();
+        for page in &index.items {
+            match &page.items {
+                Some(items) => leaves.extend(items.iter().cloned()),
+                None => leaves.extend(
+                    self.registration_page(&page.id)
+                        .await?
+                        .items
+                        .unwrap_or_default(),
+                ),
+            }
+        }
+        Ok(leaves)
+    }
+
+    /// The registration leaf for `version` of `package_id`, or `None` if
+    /// the registration doesn't have it (yet).
+    pub async fn find_registration_leaf(
+        &self,
+        package_id: impl AsRef<str>,
+        version: &Version,
+    ) -> Result<Option<RegistrationLeaf>, NuGetApiError> {
+        let index = self.registration(package_id).await?;
+        self.find_leaf_in(&index, version).await
+    }
+
+    /// Like [`NuGetClient::find_registration_leaf`], for an index that's
+    /// already been fetched. Only pages whose `[lower, upper]` covers
+    /// `version` are looked at, and any that aren't inlined get fetched all
+    /// at once, with the first one to turn up the leaf winning.
+    pub async fn find_leaf_in(
+        &self,
+        index: &RegistrationIndex,
+        version: &Version,
+    ) -> Result<Option<RegistrationLeaf>, NuGetApiError> {
+        let candidates = index
+            .items
+            .iter()
+            .filter(|page| &page.lower <= version && version <= &page.upper);
+        let mut remote = Vec::new();
+        for page in candidates {
+            match &page.items {
+                Some(items) => {
+                    if let Some(leaf) = leaf_for(items, version) {
+                        return Ok(Some(leaf.clone()));
+                    }
+                }
+                None => remote.push(page.id.clone()),
+            }
+        }
+        if remote.is_empty() {
+            return Ok(None);
+        }
+        // The tasks borrow `self`, so they run on a local executor rather
+        // than being spawned globally. Whatever's still running when a leaf
+        // turns up gets cancelled when `_tasks` is dropped.
+        let ex = Executor::new();
+        let (tx, rx) = channel::unbounded();
+        let _tasks = remote
+            .into_iter()
+            .map(|page| {
+                let tx = tx.clone();
+                ex.spawn(async move {
+                    let _ = tx.send(self.registration_page(&page).await).await;
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(tx);
+        ex.run(async {
+            let mut failed = None;
+            while let Ok(page) = rx.recv().await {
+                match page {
+                    Ok(page) => {
+                        if let Some(leaf) =
+                            leaf_for(page.items.as_deref().unwrap_or_default(), version)
+                        {
+                            return Ok(Some(leaf.clone()));
+                        }
+                    }
+                    Err(err) => {
+                        failed.get_or_insert(err);
+                    }
+                }
+            }
+            failed.map_or(Ok(None), Err)
+        })
+        .await
+    }
+}
+
+fn leaf_for<'a>(leaves: &'a [RegistrationLeaf], version: &Version) -> Option<&'a RegistrationLeaf> {
+    leaves
+        .iter()
+        .find(|leaf| &leaf.catalog_entry.version == version)
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -134,6 +224,15 @@ pub enum Authors {
     Many(Vec<String>),
 }
 
+impl fmt::Display for Authors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Authors::One(author) => write!(f, "{}", author),
+            Authors::Many(authors) => write!(f, "{}", authors.join(", ")),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Tags {
@@ -150,11 +249,74 @@ pub struct DependencyGroup {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-#[serde_with::skip_serializing_none]
+#[serde(from = "RawDependency", into = "RawDependency")]
 pub struct Dependency {
     pub id: String,
+    /// `None` if the source didn't send a range, or sent one that doesn't
+    /// parse. Either way, NuGet treats it as "any version".
     pub range: Option<Range>,
+    /// The range exactly as the source sent it, kept when it couldn't be
+    /// parsed into `range`, so it can still be shown.
+    pub raw_range: Option<String>,
+}
+
+impl Dependency {
+    /// The range for display: the parsed one if there is one, or whatever
+    /// the source sent otherwise.
+    pub fn range_label(&self) -> Option<String> {
+        match (&self.range, &self.raw_range) {
+            (Some(range), _) => Some(range.to_string()),
+            (None, Some(raw)) if !raw.trim().is_empty() => Some(raw.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// [`Dependency`] as it comes over the wire. Feeds in the wild have ranges
+/// like `""` and `"(, )"` in them, and one bad range shouldn't make a whole
+/// registration page unreadable.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde_with::skip_serializing_none]
+struct RawDependency {
+    id: String,
+    range: Option<String>,
+}
+
+impl From<RawDependency> for Dependency {
+    fn from(raw: RawDependency) -> Self {
+        let (range, raw_range) = match raw.range {
+            None => (None, None),
+            Some(text) => match dotnet_semver::range_cache::parse(&text) {
+                Ok(range) => (Some(range), None),
+                Err(err) => {
+                    if !text.trim().is_empty() {
+                        tracing::warn!(
+                            "Ignoring unparseable version range {:?} for dependency {}: {}",
+                            text,
+                            raw.id,
+                            err
+                        );
+                    }
+                    (None, Some(text))
+                }
+            },
+        };
+        Dependency {
+            id: raw.id,
+            range,
+            raw_range,
+        }
+    }
+}
+
+impl From<Dependency> for RawDependency {
+    fn from(dep: Dependency) -> Self {
+        RawDependency {
+            range: dep.range.map(|range| range.to_string()).or(dep.raw_range),
+            id: dep.id,
+        }
+    }
 }
 
 impl PartialOrd for Dependency {
@@ -184,7 +346,8 @@ pub struct Vulnerability {
     pub severity: Severity,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// How bad a vulnerability is. Ordered from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Severity {
     #[serde(rename = "0")]
     Low,
@@ -196,6 +359,31 @@ pub enum Severity {
     Critical,
 }
 
+impl FromStr for Severity {
+    type Err = NuGetApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.trim().to_lowercase()[..] {
+            "low" | "0" => Ok(Severity::Low),
+            "moderate" | "1" => Ok(Severity::Moderate),
+            "high" | "2" => Ok(Severity::High),
+            "critical" | "3" => Ok(Severity::Critical),
+            _ => Err(NuGetApiError::UnknownSeverity(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Low => write!(f, "low"),
+            Severity::Moderate => write!(f, "moderate"),
+            Severity::High => write!(f, "high"),
+            Severity::Critical => write!(f, "critical"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum DeprecationReason {
     Legacy,
@@ -204,3 +392,149 @@ pub enum DeprecationReason {
     #[serde(other)]
     Unknown,
 }
+
+#[cfg(test)]
+mod tests {
+    use turron_common::smol;
+    use turron_testing::{RegistrationBuilder, TestServer};
+
+    use super::*;
+
+    #[test]
+    fn follows_registration_pages() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let reg = RegistrationBuilder::new("Foo")
+                .base_url(server.url("/v3/registration5-gz-semver2/"))
+                .leaves(150)
+                .inline_leaves(false)
+                .build();
+            for (url, body) in reg.documents() {
+                server.json(&url[server.base().len()..], &body);
+            }
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            let index = client.registration("Foo").await.unwrap();
+            assert_eq!(index.count, 3);
+            let mut versions = Vec::new();
+            for page in index.items {
+                assert!(page.items.is_none());
+                let page = client.registration_page(&page.id).await.unwrap();
+                versions.extend(
+                    page.items
+                        .unwrap()
+                        .into_iter()
+                        .map(|leaf| leaf.catalog_entry.version),
+                );
+            }
+            assert_eq!(versions.len(), 150);
+            assert_eq!(versions[149], "1.0.149".parse().unwrap());
+        });
+    }
+
+    #[test]
+    fn collects_leaves_from_every_page() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let reg = RegistrationBuilder::new("Foo")
+                .base_url(server.url("/v3/registration5-gz-semver2/"))
+                .leaves(150)
+                .inline_leaves(false)
+                .build();
+            for (url, body) in reg.documents() {
+                server.json(&url[server.base().len()..], &body);
+            }
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            let leaves = client.registration_leaves("Foo").await.unwrap();
+            assert_eq!(leaves.len(), 150);
+            assert_eq!(
+                leaves[149].catalog_entry.version,
+                "1.0.149".parse().unwrap()
+            );
+        });
+    }
+
+    #[test]
+    fn only_fetches_candidate_pages_for_a_leaf() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let reg = RegistrationBuilder::new("Foo")
+                .base_url(server.url("/v3/registration5-gz-semver2/"))
+                .leaves(150)
+                .inline_leaves(false)
+                .build();
+            for (url, body) in reg.documents() {
+                server.json(&url[server.base().len()..], &body);
+            }
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            let pages = || {
+                server
+                    .requests()
+                    .into_iter()
+                    .filter(|req| req.contains("/page/"))
+                    .collect::<Vec<_>>()
+            };
+
+            let leaf = client
+                .find_registration_leaf("Foo", &"1.0.100".parse().unwrap())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(leaf.catalog_entry.version, "1.0.100".parse().unwrap());
+            let fetched = pages();
+            assert_eq!(fetched.len(), 1);
+            assert!(fetched[0].contains("/page/1.0.64/1.0.127.json"));
+
+            // Nothing covers it, so no pages get fetched at all.
+            let missing = client
+                .find_registration_leaf("Foo", &"2.0.0".parse().unwrap())
+                .await
+                .unwrap();
+            assert!(missing.is_none());
+            assert_eq!(pages().len(), 1);
+        });
+    }
+
+    #[test]
+    fn tolerates_unparseable_dependency_ranges() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let reg = RegistrationBuilder::new("Foo")
+                .base_url(server.url("/v3/registration5-gz-semver2/"))
+                .versions(vec!["1.0.0"])
+                .dependency(Some("net45"), "Empty", "")
+                .dependency(Some("net45"), "Unbounded", "(, )")
+                .dependency(Some("net45"), "Garbage", "1.0.0 or so")
+                .dependency(Some("net45"), "Fine", "[1.0.0, 2.0.0)")
+                .build();
+            for (url, body) in reg.documents() {
+                server.json(&url[server.base().len()..], &body);
+            }
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            let index = client.registration("Foo").await.unwrap();
+            let page = index.items[0].items.as_ref().unwrap();
+            let groups = page[0].catalog_entry.dependency_groups.as_ref().unwrap();
+            let deps = groups[0].dependencies.as_ref().unwrap();
+            let labels = deps
+                .iter()
+                .map(|dep| (&dep.id[..], dep.range_label()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                labels,
+                vec![
+                    ("Empty", None),
+                    ("Unbounded", Some("(, )".into())),
+                    ("Garbage", Some("1.0.0 or so".into())),
+                    ("Fine", Some("[1.0.0,2.0.0)".into())),
+                ]
+            );
+            assert_eq!(deps[2].range, None);
+            assert_eq!(deps[3].raw_range, None);
+            // Bad ranges go back out the way they came in.
+            let json = turron_common::serde_json::to_value(&deps[2]).unwrap();
+            assert_eq!(json["range"], "1.0.0 or so");
+        });
+    }
+}
@@ -1,11 +1,11 @@
 use turron_common::surf::{self, StatusCode, Url};
 
 use crate::errors::NuGetApiError;
-use crate::v3::NuGetClient;
+use crate::v3::{retry::rate_limited, NuGetClient};
 
 impl NuGetClient {
     pub async fn relist(
-        self,
+        &self,
         package_id: impl AsRef<str>,
         version: impl AsRef<str>,
     ) -> Result<(), NuGetApiError> {
@@ -16,21 +16,31 @@ impl NuGetClient {
             .clone()
             .ok_or_else(|| UnsupportedEndpoint("PackagePublish/2.0.0".into()))?;
 
-        let url = Url::parse(&format!("{}/{}/{}", url, package_id.as_ref(), version.as_ref()))?;
-
-        let req = surf::post(url.join(package_id.as_ref())?.join(version.as_ref())?)
-            .header("X-NuGet-ApiKey", self.get_key()?);
+        let url = Url::parse(&format!(
+            "{}/{}/{}",
+            url,
+            package_id.as_ref(),
+            version.as_ref()
+        ))?;
 
+        let req_url = url.join(package_id.as_ref())?.join(version.as_ref())?;
+        self.ensure_online(&req_url)?;
+        let key = self.get_key()?;
         let res = self
-            .client
-            .send(req)
+            .retries
+            .for_request(false)
+            .run(&url, || {
+                self.client
+                    .send(surf::post(&req_url).header("X-NuGet-ApiKey", key.as_str()))
+            })
             .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.into()))?;
+            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
 
         match res.status() {
             StatusCode::Ok => Ok(()),
             StatusCode::NotFound => Err(PackageNotFound),
             StatusCode::Forbidden => Err(BadApiKey(self.get_key()?)),
+            StatusCode::TooManyRequests => Err(rate_limited(&res, &url)),
             code => Err(BadResponse(code)),
         }
     }
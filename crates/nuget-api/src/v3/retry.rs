@@ -0,0 +1,372 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use turron_common::{
+    chrono::{DateTime, Utc},
+    smol::Timer,
+    surf::{self, http::headers::CONTENT_TYPE, Response, StatusCode, Url},
+    tracing,
+};
+
+use crate::errors::NuGetApiError;
+
+/// How [`NuGetClient`](crate::v3::NuGetClient) retries requests that fail
+/// for reasons that might go away on their own: connection errors,
+/// timeouts, `429 Too Many Requests`, and `5xx` responses.
+///
+/// Attempts are spaced out with exponential backoff, starting at
+/// `base_delay` and doubling up to `max_delay`, with some jitter so a burst
+/// of concurrent requests doesn't retry in lockstep. When the source sends
+/// `Retry-After`, that's used instead, unless it's longer than `max_delay`,
+/// in which case the response is returned right away so the user can
+/// decide whether to wait.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. `1` disables
+    /// retries.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Whether to also retry requests that change things on the source
+    /// (pushes, unlists, relists). A retried push whose first attempt
+    /// actually went through will usually fail with a conflict.
+    pub retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Makes every request exactly once.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// The policy to use for a single request. Non-idempotent requests only
+    /// get retried if the policy explicitly asks for it.
+    pub(crate) fn for_request(&self, idempotent: bool) -> Self {
+        if idempotent || self.retry_non_idempotent {
+            self.clone()
+        } else {
+            Self::none()
+        }
+    }
+
+    /// How long to wait before retrying after `attempt` (starting at 1)
+    /// failed. Somewhere between half and all of the exponential delay.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .checked_mul(1 << attempt.saturating_sub(1).min(16))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        exp / 2 + exp.mul_f64(jitter() / 2.0)
+    }
+
+    /// Runs `attempt` until it either succeeds with a non-transient status,
+    /// or this policy runs out of attempts. The last result is returned
+    /// as-is, so a source that's still failing surfaces its actual status.
+    pub(crate) async fn run<T, F, Fut>(&self, url: &Url, mut attempt: F) -> surf::Result<T>
+    where
+        T: Attempt,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = surf::Result<T>>,
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut tries = 1;
+        loop {
+            let res = attempt().await;
+            if tries >= max_attempts {
+                return res;
+            }
+            let (reason, delay) = match &res {
+                Ok(res) if is_transient(res.status()) => match res.retry_after() {
+                    Some(wait) if wait > self.max_delay => return Ok(res),
+                    Some(wait) => (res.status().to_string(), wait),
+                    None => (res.status().to_string(), self.delay(tries)),
+                },
+                Ok(_) => return res,
+                Err(err) => (err.to_string(), self.delay(tries)),
+            };
+            tracing::debug!(
+                "Request to {} failed ({}). Retrying in {:?} (attempt {} of {}).",
+                url,
+                reason,
+                delay,
+                tries + 1,
+                max_attempts
+            );
+            Timer::after(delay).await;
+            tries += 1;
+        }
+    }
+}
+
+/// What the retry loop, and the errors for responses it gives up on, need
+/// to know about a finished request.
+pub(crate) trait Attempt {
+    fn status(&self) -> StatusCode;
+
+    /// How long the source asked us to wait, from `Retry-After`.
+    fn retry_after(&self) -> Option<Duration>;
+
+    /// Every `Link` header, joined with commas.
+    fn link(&self) -> Option<String>;
+
+    fn content_type(&self) -> Option<String>;
+}
+
+impl Attempt for Response {
+    fn status(&self) -> StatusCode {
+        Response::status(self)
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        self.header("Retry-After")
+            .and_then(|values| parse_retry_after(values.last().as_str(), Utc::now()))
+    }
+
+    fn link(&self) -> Option<String> {
+        self.header("Link").map(|values| {
+            values
+                .iter()
+                .map(|value| value.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+    }
+
+    fn content_type(&self) -> Option<String> {
+        self.header(CONTENT_TYPE)
+            .map(|values| values.last().to_string())
+    }
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an
+/// HTTP date. Dates in the past mean "now".
+pub(crate) fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        date.with_timezone(&Utc)
+            .signed_duration_since(now)
+            .to_std()
+            .unwrap_or_else(|_| Duration::from_secs(0)),
+    )
+}
+
+/// The error for a `429` that retrying didn't get past.
+pub(crate) fn rate_limited(res: &impl Attempt, url: &Url) -> NuGetApiError {
+    NuGetApiError::RateLimited {
+        retry_after: res.retry_after(),
+        url: url.to_string(),
+    }
+}
+
+/// Statuses that are worth trying again after a little while.
+pub(crate) fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TooManyRequests || status.is_server_error()
+}
+
+/// A number in `[0, 1)`. Not remotely cryptographic, but `RandomState` is
+/// seeded differently every time, which is all jitter needs.
+fn jitter() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    (hasher.finish() % 1_000_000) as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::smol;
+    use turron_testing::TestServer;
+
+    use turron_common::chrono::TimeZone;
+
+    use super::*;
+    use crate::v3::NuGetClient;
+
+    fn fast() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            retry_non_idempotent: false,
+        }
+    }
+
+    #[test]
+    fn delays_back_off_within_bounds() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            retry_non_idempotent: false,
+        };
+        for &(attempt, full) in &[
+            (1, 100),
+            (2, 200),
+            (3, 400),
+            (4, 800),
+            (5, 1000),
+            (40, 1000),
+        ] {
+            let delay = policy.delay(attempt);
+            let full = Duration::from_millis(full);
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn parses_retry_after_seconds() {
+        let now = Utc::now();
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::from_secs(0)));
+        assert_eq!(parse_retry_after("-5", now), None);
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn parses_retry_after_dates() {
+        let now = Utc.ymd(2015, 10, 21).and_hms(7, 28, 0);
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now),
+            Some(Duration::from_secs(90))
+        );
+        // Dates that already passed mean there's no need to wait.
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::from_secs(0))
+        );
+    }
+
+    #[test]
+    fn rate_limits_become_errors() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let path = "/v3-flatcontainer/foo/index.json";
+            server.respond(path, 429, "");
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_retries(fast());
+
+            let url = Url::parse(&server.url(path)).unwrap();
+            match client.get_shared(&url).await {
+                Err(NuGetApiError::RateLimited { retry_after, url }) => {
+                    assert_eq!(retry_after, None);
+                    assert_eq!(url, server.url(path));
+                }
+                other => panic!("expected RateLimited, got {:?}", other),
+            }
+            assert_eq!(server.hits(path), 3);
+        });
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let path = "/v3-flatcontainer/foo/index.json";
+            server
+                .respond_once(path, 503, "")
+                .respond_once(path, 429, "")
+                .route(path, r#"{"versions": ["1.0.0"]}"#);
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_retries(fast());
+
+            let url = Url::parse(&server.url(path)).unwrap();
+            let (status, body) = client.get_shared(&url).await.unwrap();
+            assert_eq!(status, StatusCode::Ok);
+            assert_eq!(&body[..], br#"{"versions": ["1.0.0"]}"#);
+            assert_eq!(server.hits(path), 3);
+        });
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let path = "/v3-flatcontainer/foo/index.json";
+            server.respond(path, 502, "");
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_retries(fast());
+
+            let url = Url::parse(&server.url(path)).unwrap();
+            let (status, _) = client.get_shared(&url).await.unwrap();
+            assert_eq!(status, StatusCode::BadGateway);
+            assert_eq!(server.hits(path), 3);
+        });
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let path = "/v3-flatcontainer/foo/index.json";
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_retries(fast());
+
+            let url = Url::parse(&server.url(path)).unwrap();
+            let (status, _) = client.get_shared(&url).await.unwrap();
+            assert_eq!(status, StatusCode::NotFound);
+            assert_eq!(server.hits(path), 1);
+        });
+    }
+
+    #[test]
+    fn only_retries_writes_when_asked() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            let path = "/api/v2/package/Foo/1.0.0";
+            server.respond_once(path, 503, "").route(path, "");
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_key(Some("key"))
+                .with_retries(fast());
+            assert!(matches!(
+                client.unlist("Foo", "1.0.0").await,
+                Err(NuGetApiError::BadResponse(StatusCode::ServiceUnavailable))
+            ));
+            assert_eq!(server.hits(path), 1);
+
+            server.respond_once(path, 503, "");
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_key(Some("key"))
+                .with_retries(RetryPolicy {
+                    retry_non_idempotent: true,
+                    ..fast()
+                });
+            client.unlist("Foo", "1.0.0").await.unwrap();
+            assert_eq!(server.hits(path), 3);
+        });
+    }
+}
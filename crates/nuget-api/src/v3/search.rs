@@ -1,25 +1,123 @@
+use std::fmt;
+
+use dotnet_semver::Version;
 use turron_common::{
-    serde::{Deserialize, Serialize},
+    serde::{Deserialize, Deserializer, Serialize, Serializer},
     serde_with,
-    surf::{self, StatusCode},
+    surf::{StatusCode, Url},
 };
 
 use crate::errors::NuGetApiError;
-use crate::v3::NuGetClient;
+use crate::framework::Framework;
+use crate::v3::{encoding::from_json_response, Authors, NuGetClient, Tags};
+
+/// Page size for [`NuGetClient::search_all`] when the query doesn't set one.
+pub const DEFAULT_SEARCH_PAGE_SIZE: usize = 100;
+
+/// The most results nuget.org will return for a single search request.
+pub const MAX_SEARCH_PAGE_SIZE: usize = 1000;
 
 impl NuGetClient {
-    pub async fn search(self, query: SearchQuery) -> Result<SearchResponse, NuGetApiError> {
+    pub async fn search(&self, query: SearchQuery) -> Result<SearchResponse, NuGetApiError> {
+        let (url, local_filter) = self.search_url(&query)?;
+        let mut response = self.fetch_search(&url).await?;
+        if let Some(framework) = local_filter {
+            response
+                .data
+                .retain(|result| framework.supports_any(&result.frameworks));
+            response.client_side_filtered = true;
+        }
+        Ok(response)
+    }
+
+    /// Runs `query` a page at a time, bumping `skip` until the source runs
+    /// out of results or `limit` of them have been collected. `query.take`
+    /// is the page size. After each page, `on_page` gets how many results
+    /// have been fetched so far and how many the source says there are.
+    pub async fn search_all(
+        &self,
+        query: SearchQuery,
+        limit: Option<usize>,
+        mut on_page: impl FnMut(usize, usize),
+    ) -> Result<SearchResponse, NuGetApiError> {
+        let page_size = query
+            .take
+            .unwrap_or(DEFAULT_SEARCH_PAGE_SIZE)
+            .clamp(1, MAX_SEARCH_PAGE_SIZE);
+        let mut skip = query.skip.unwrap_or(0);
+        let mut fetched = 0;
+        let mut previous_ids = Vec::new();
+        let mut all = SearchResponse {
+            total_hits: 0,
+            data: Vec::new(),
+            client_side_filtered: false,
+        };
+        loop {
+            let (url, local_filter) = self.search_url(&SearchQuery {
+                skip: Some(skip),
+                take: Some(page_size),
+                ..query.clone()
+            })?;
+            let mut page = self.fetch_search(&url).await?;
+            all.total_hits = page.total_hits;
+            if page.data.is_empty() {
+                break;
+            }
+            // A source that ignores `skip` would otherwise be paged through
+            // forever.
+            let ids = page
+                .data
+                .iter()
+                .map(|result| result.id.to_lowercase())
+                .collect::<Vec<_>>();
+            if ids == previous_ids {
+                return Err(NuGetApiError::SearchStalled(url.to_string(), skip));
+            }
+            fetched += page.data.len();
+            skip += page.data.len();
+            if let Some(framework) = local_filter {
+                page.data
+                    .retain(|result| framework.supports_any(&result.frameworks));
+                all.client_side_filtered = true;
+            }
+            all.data.extend(page.data);
+            on_page(fetched, all.total_hits);
+            if let Some(limit) = limit {
+                if all.data.len() >= limit {
+                    all.data.truncate(limit);
+                    break;
+                }
+            }
+            if skip >= all.total_hits {
+                break;
+            }
+            previous_ids = ids;
+        }
+        Ok(all)
+    }
+
+    /// The URL for `query`, plus the framework to filter results by
+    /// afterwards if the source can't do it.
+    fn search_url(&self, query: &SearchQuery) -> Result<(Url, Option<Framework>), NuGetApiError> {
         use NuGetApiError::*;
         let mut url = self
             .endpoints
             .search
-            .ok_or_else(|| UnsupportedEndpoint("SearchQueryService/3.5.0".into()))?
-            .clone();
+            .clone()
+            .ok_or_else(|| UnsupportedEndpoint("SearchQueryService/3.5.0".into()))?;
+        // Sources that can't filter by framework get filtered after the
+        // fact instead, which needs a framework we can actually compare.
+        let local_filter = match &query.framework {
+            Some(tfm) if !self.endpoints.search_supports_frameworks() => {
+                Some(Framework::parse(tfm).ok_or_else(|| UnknownFramework(tfm.clone()))?)
+            }
+            _ => None,
+        };
         {
             let mut pairs = url.query_pairs_mut();
             pairs.append_pair("semVerLevel", "2.0.0");
-            if let Some(query) = query.query {
-                pairs.append_pair("q", &query);
+            if let Some(query) = &query.query {
+                pairs.append_pair("q", query);
             }
             if let Some(skip) = query.skip {
                 pairs.append_pair("skip", &skip.to_string());
@@ -30,37 +128,36 @@ impl NuGetClient {
             if let Some(prerelease) = query.prerelease {
                 pairs.append_pair("prerelease", &prerelease.to_string());
             }
-            if let Some(package_type) = query.package_type {
-                pairs.append_pair("packageType", &package_type);
+            if let Some(package_type) = &query.package_type {
+                pairs.append_pair("packageType", package_type);
+            }
+            if let Some(framework) = query.framework.as_ref().filter(|_| local_filter.is_none()) {
+                pairs.append_pair("supportedFramework", framework);
             }
         }
+        Ok((url, local_filter))
+    }
 
-        let req = surf::get(&url);
-
-        let mut res = self
-            .client
-            .send(req)
-            .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
-
-        match res.status() {
-            StatusCode::Ok => Ok(res
-                .body_json()
-                .await
-                .map_err(|e| NuGetApiError::SurfError(e, url.into()))?),
+    async fn fetch_search(&self, url: &Url) -> Result<SearchResponse, NuGetApiError> {
+        use NuGetApiError::*;
+        let (status, body) = self.get_shared(url).await?;
+        match status {
+            StatusCode::Ok => from_json_response(&body, url.as_str()),
             StatusCode::NotFound => Err(PackageNotFound),
             code => Err(BadResponse(code)),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SearchQuery {
     pub query: Option<String>,
     pub skip: Option<usize>,
     pub take: Option<usize>,
     pub prerelease: Option<bool>,
     pub package_type: Option<String>,
+    /// Only return packages that can be used from this target framework.
+    pub framework: Option<String>,
 }
 
 impl SearchQuery {
@@ -71,6 +168,7 @@ impl SearchQuery {
             take: None,
             prerelease: None,
             package_type: None,
+            framework: None,
         }
     }
 }
@@ -80,14 +178,339 @@ pub struct SearchResponse {
     #[serde(rename = "totalHits")]
     pub total_hits: usize,
     pub data: Vec<SearchResult>,
+    /// Set when the source couldn't filter by framework, so turron filtered
+    /// `data` itself. Only the requested page gets looked at, so compatible
+    /// packages on later pages are missing, and `total_hits` still counts
+    /// the incompatible ones.
+    #[serde(
+        default,
+        rename = "clientSideFiltered",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub client_side_filtered: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde_with::skip_serializing_none]
+#[serde(rename_all = "camelCase")]
 pub struct SearchResult {
     pub id: String,
-    pub version: String,
+    pub version: LenientVersion,
     pub description: Option<String>,
-    // TODO: there's a lot more of these fields, but they're a pain to add.
-    // https://docs.microsoft.com/en-us/nuget/api/search-query-service-resource#search-result
+    pub authors: Option<Authors>,
+    pub total_downloads: Option<u64>,
+    /// Whether the package id has a reserved prefix. Only some sources, like
+    /// nuget.org, know about prefix reservations.
+    pub verified: Option<bool>,
+    pub icon_url: Option<String>,
+    pub tags: Option<Tags>,
+    pub project_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub package_types: Vec<SearchResultPackageType>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<SearchResultVersion>,
+    /// Target frameworks the package ships assets for, on sources that
+    /// report them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frameworks: Vec<String>,
+}
+
+/// A version as a search result reports it. Sources should only ever send
+/// valid versions, but one that doesn't shouldn't make the rest of the page
+/// unreadable, so anything that fails to parse is kept as it was written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LenientVersion {
+    Parsed(Version),
+    Unparseable(String),
+}
+
+impl LenientVersion {
+    pub fn parse(input: impl AsRef<str>) -> Self {
+        let input = input.as_ref();
+        Version::parse(input)
+            .map(LenientVersion::Parsed)
+            .unwrap_or_else(|_| LenientVersion::Unparseable(input.into()))
+    }
+
+    pub fn parsed(&self) -> Option<&Version> {
+        match self {
+            LenientVersion::Parsed(version) => Some(version),
+            LenientVersion::Unparseable(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for LenientVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LenientVersion::Parsed(version) => write!(f, "{}", version),
+            LenientVersion::Unparseable(raw) => write!(f, "{}", raw),
+        }
+    }
+}
+
+impl Serialize for LenientVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for LenientVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(LenientVersion::parse(String::deserialize(deserializer)?))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResultPackageType {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResultVersion {
+    pub version: String,
+    pub downloads: Option<u64>,
+    #[serde(rename = "@id")]
+    pub id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::{
+        serde_json::{self, json, Value},
+        smol,
+    };
+    use turron_testing::{fixtures, TestServer};
+
+    use super::*;
+
+    fn query(framework: &str) -> SearchQuery {
+        SearchQuery {
+            framework: Some(framework.into()),
+            ..SearchQuery::from_query("json")
+        }
+    }
+
+    fn results() -> Value {
+        json!({
+            "totalHits": 3,
+            "data": [
+                { "id": "Modern", "version": "1.0.0", "frameworks": ["netstandard2.0"] },
+                { "id": "Legacy", "version": "1.0.0", "frameworks": ["net48"] },
+                { "id": "Mystery", "version": "1.0.0" },
+            ]
+        })
+    }
+
+    #[test]
+    fn sends_framework_to_sources_that_support_it() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .json(
+                    "/v3/index.json",
+                    &json!({
+                        "version": "3.0.0",
+                        "resources": [{
+                            "@id": server.url("/query"),
+                            "@type": "SearchQueryService/3.6.0",
+                        }],
+                    }),
+                )
+                .json("/query", &fixtures::search());
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            assert!(client.endpoints.search_supports_frameworks());
+
+            let response = client.search(query("net6.0")).await.unwrap();
+            assert!(!response.client_side_filtered);
+            assert_eq!(response.data.len(), 2);
+            assert!(
+                server
+                    .requests()
+                    .iter()
+                    .any(|req| req.starts_with("/query?")
+                        && req.contains("supportedFramework=net6.0"))
+            );
+        });
+    }
+
+    #[test]
+    fn filters_locally_when_the_source_cannot() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.json("/query", &results());
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            assert!(!client.endpoints.search_supports_frameworks());
+
+            let response = client.search(query("net6.0")).await.unwrap();
+            assert!(response.client_side_filtered);
+            let ids = response.data.iter().map(|r| &r.id[..]).collect::<Vec<_>>();
+            assert_eq!(ids, vec!["Modern"]);
+            assert!(!server
+                .requests()
+                .iter()
+                .any(|req| req.contains("supportedFramework")));
+
+            let response = client.search(query("net48")).await.unwrap();
+            let ids = response.data.iter().map(|r| &r.id[..]).collect::<Vec<_>>();
+            assert_eq!(ids, vec!["Modern", "Legacy"]);
+        });
+    }
+
+    #[test]
+    fn parses_versions_leniently() {
+        let response: SearchResponse = serde_json::from_value(json!({
+            "totalHits": 3,
+            "data": [
+                { "id": "Good", "version": "1.2.3-beta.1" },
+                { "id": "Short", "version": "1.0" },
+                { "id": "Weird", "version": "not.a.version" },
+            ]
+        }))
+        .unwrap();
+        let versions = response
+            .data
+            .iter()
+            .map(|r| r.version.parsed().map(|v| v.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            vec![Some("1.2.3-beta.1".into()), Some("1.0.0".into()), None]
+        );
+        assert_eq!(
+            response.data[2].version,
+            LenientVersion::Unparseable("not.a.version".into())
+        );
+        assert_eq!(
+            serde_json::to_value(&response.data[2]).unwrap()["version"],
+            json!("not.a.version")
+        );
+    }
+
+    #[test]
+    fn reads_recorded_results() {
+        let response: SearchResponse = serde_json::from_value(fixtures::search()).unwrap();
+        let newtonsoft = &response.data[0];
+        assert_eq!(newtonsoft.id, "Newtonsoft.Json");
+        assert!(
+            matches!(&newtonsoft.authors, Some(Authors::Many(a)) if a == &["James Newton-King"])
+        );
+        assert!(matches!(&newtonsoft.tags, Some(Tags::Many(t)) if t == &["json"]));
+        assert_eq!(newtonsoft.total_downloads, Some(1_591_356_093));
+        assert_eq!(newtonsoft.verified, Some(true));
+        assert_eq!(
+            newtonsoft.icon_url.as_deref(),
+            Some("https://api.nuget.org/v3-flatcontainer/newtonsoft.json/13.0.1/icon")
+        );
+        assert_eq!(
+            newtonsoft.project_url.as_deref(),
+            Some("https://www.newtonsoft.com/json")
+        );
+        assert_eq!(newtonsoft.package_types[0].name, "Dependency");
+        assert_eq!(newtonsoft.versions.len(), 2);
+        assert_eq!(newtonsoft.versions[1].version, "13.0.1");
+        assert_eq!(newtonsoft.versions[1].downloads, Some(264_520_316));
+
+        // Everything past id and version is optional.
+        let bare: SearchResult =
+            serde_json::from_value(json!({ "id": "Bare", "version": "1.0.0" })).unwrap();
+        assert!(bare.authors.is_none() && bare.verified.is_none());
+        assert!(bare.package_types.is_empty() && bare.versions.is_empty());
+    }
+
+    fn page(total: usize, ids: &[&str]) -> String {
+        let data = ids
+            .iter()
+            .map(|id| json!({ "id": id, "version": "1.0.0" }))
+            .collect::<Vec<_>>();
+        json!({ "totalHits": total, "data": data }).to_string()
+    }
+
+    fn paged(take: usize) -> SearchQuery {
+        SearchQuery {
+            take: Some(take),
+            ..SearchQuery::from_query("json")
+        }
+    }
+
+    #[test]
+    fn pages_through_every_result() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .respond_once("/query", 200, page(5, &["A", "B"]))
+                .respond_once("/query", 200, page(5, &["C", "D"]))
+                .respond_once("/query", 200, page(5, &["E"]));
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            let mut progress = Vec::new();
+            let response = client
+                .search_all(paged(2), None, |fetched, total| {
+                    progress.push((fetched, total))
+                })
+                .await
+                .unwrap();
+            let ids = response.data.iter().map(|r| &r.id[..]).collect::<Vec<_>>();
+            assert_eq!(ids, vec!["A", "B", "C", "D", "E"]);
+            assert_eq!(response.total_hits, 5);
+            assert_eq!(progress, vec![(2, 5), (4, 5), (5, 5)]);
+            let skips = server
+                .requests()
+                .iter()
+                .filter_map(|req| {
+                    req.split('&')
+                        .find(|pair| pair.starts_with("skip="))
+                        .map(String::from)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(skips, vec!["skip=0", "skip=2", "skip=4"]);
+        });
+    }
+
+    #[test]
+    fn stops_at_the_limit() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .respond_once("/query", 200, page(100, &["A", "B"]))
+                .respond_once("/query", 200, page(100, &["C", "D"]));
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            let response = client
+                .search_all(paged(2), Some(3), |_, _| {})
+                .await
+                .unwrap();
+            let ids = response.data.iter().map(|r| &r.id[..]).collect::<Vec<_>>();
+            assert_eq!(ids, vec!["A", "B", "C"]);
+            assert_eq!(server.hits("/query"), 2);
+        });
+    }
+
+    #[test]
+    fn bails_when_pages_repeat() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.route("/query", page(100, &["A", "B"]));
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+
+            assert!(matches!(
+                client.search_all(paged(2), None, |_, _| {}).await,
+                Err(NuGetApiError::SearchStalled(_, 2))
+            ));
+            assert_eq!(server.hits("/query"), 2);
+        });
+    }
+
+    #[test]
+    fn local_filtering_needs_a_known_framework() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server.json("/query", &results());
+            let client = NuGetClient::from_source(server.index_url()).await.unwrap();
+            assert!(matches!(
+                client.search(query("monoandroid10")).await,
+                Err(NuGetApiError::UnknownFramework(tfm)) if tfm == "monoandroid10"
+            ));
+        });
+    }
 }
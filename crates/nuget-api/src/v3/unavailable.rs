@@ -0,0 +1,81 @@
+//! Responses meaning a package can't be had from this source at all, even
+//! though they aren't an ordinary NuGet error: `451`s, and mirrors that
+//! answer with a web page instead of the .nupkg.
+
+use turron_common::surf::Url;
+
+use crate::errors::NuGetApiError;
+use crate::v3::retry::Attempt;
+
+/// The error for a `451 Unavailable For Legal Reasons`. Per RFC 7725, the
+/// `Link` header can say who's blocking it, with `rel="blocked-by"`.
+pub(crate) fn legally_unavailable(res: &impl Attempt, url: &Url) -> NuGetApiError {
+    NuGetApiError::LegallyUnavailable {
+        url: url.to_string(),
+        blocked_by: res.link().as_deref().and_then(blocked_by),
+    }
+}
+
+/// Whether `res`, a `200` for `url`, is actually an HTML error page. Some
+/// mirrors and CDNs send one, with a `200`, instead of the .nupkg that was
+/// asked for.
+pub(crate) fn is_error_page(res: &impl Attempt, url: &Url) -> bool {
+    url.path().to_lowercase().ends_with(".nupkg")
+        && res.content_type().map_or(false, |ty| {
+            ty.trim().to_lowercase().starts_with("text/html")
+        })
+}
+
+pub(crate) fn error_page(res: &impl Attempt, url: &Url) -> NuGetApiError {
+    NuGetApiError::ErrorPage {
+        url: url.to_string(),
+        content_type: res.content_type().unwrap_or_default(),
+    }
+}
+
+/// Finds the `rel="blocked-by"` target in a `Link` header, like
+/// `<https://example.com/notice>; rel="blocked-by"`.
+fn blocked_by(link: &str) -> Option<String> {
+    link.split(',').find_map(|link| {
+        let mut parts = link.split(';');
+        let target = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+        let blocked = parts.any(|param| {
+            let mut param = param.splitn(2, '=');
+            let (name, value) = (
+                param.next().unwrap_or("").trim(),
+                param.next().unwrap_or(""),
+            );
+            name.eq_ignore_ascii_case("rel")
+                && value
+                    .trim()
+                    .trim_matches('"')
+                    .split_whitespace()
+                    .any(|rel| rel.eq_ignore_ascii_case("blocked-by"))
+        });
+        if blocked {
+            Some(target.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_who_is_blocking() {
+        assert_eq!(
+            blocked_by(r#"<https://example.com/notice>; rel="blocked-by""#).as_deref(),
+            Some("https://example.com/notice")
+        );
+        assert_eq!(
+            blocked_by(r#"<https://a.example/>; rel="next", <https://b.example/>; REL=blocked-by"#)
+                .as_deref(),
+            Some("https://b.example/")
+        );
+        assert_eq!(blocked_by(r#"<https://a.example/>; rel="next""#), None);
+        assert_eq!(blocked_by("garbage"), None);
+    }
+}
@@ -1,11 +1,11 @@
 use turron_common::surf::{self, StatusCode, Url};
 
 use crate::errors::NuGetApiError;
-use crate::v3::NuGetClient;
+use crate::v3::{retry::rate_limited, NuGetClient};
 
 impl NuGetClient {
     pub async fn unlist(
-        self,
+        &self,
         package_id: impl AsRef<str>,
         version: impl AsRef<str>,
     ) -> Result<(), NuGetApiError> {
@@ -16,20 +16,60 @@ impl NuGetClient {
             .clone()
             .ok_or_else(|| UnsupportedEndpoint("PackagePublish/2.0.0".into()))?;
 
-        let url = Url::parse(&format!("{}/{}/{}", url, package_id.as_ref(), version.as_ref()))?;
-
-        let req = surf::delete(&url).header("X-NuGet-ApiKey", self.get_key()?);
+        let url = Url::parse(&format!(
+            "{}/{}/{}",
+            url,
+            package_id.as_ref(),
+            version.as_ref()
+        ))?;
 
+        self.ensure_online(&url)?;
+        let key = self.get_key()?;
         let res = self
-            .client
-            .send(req)
+            .retries
+            .for_request(false)
+            .run(&url, || {
+                self.client
+                    .send(surf::delete(&url).header("X-NuGet-ApiKey", key.as_str()))
+            })
             .await
-            .map_err(|e| NuGetApiError::SurfError(e, url.into()))?;
+            .map_err(|e| NuGetApiError::SurfError(e, url.clone().into()))?;
         match res.status() {
             StatusCode::Ok | StatusCode::NoContent => Ok(()),
             StatusCode::NotFound => Err(PackageNotFound),
             StatusCode::Forbidden => Err(BadApiKey(self.get_key()?)),
+            StatusCode::TooManyRequests => Err(rate_limited(&res, &url)),
             code => Err(BadResponse(code)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use turron_common::smol;
+    use turron_testing::TestServer;
+
+    use super::*;
+
+    #[test]
+    fn unlists_many_versions_with_one_client() {
+        smol::block_on(async {
+            let server = TestServer::start().await;
+            server
+                .route("/api/v2/package/Foo/1.0.0", "")
+                .route("/api/v2/package/Foo/1.0.1", "");
+            let client = NuGetClient::from_source(server.index_url())
+                .await
+                .unwrap()
+                .with_key(Some("key"));
+            client.unlist("Foo", "1.0.0").await.unwrap();
+            client.unlist("Foo", "1.0.1").await.unwrap();
+            assert!(matches!(
+                client.unlist("Foo", "1.0.2").await,
+                Err(NuGetApiError::PackageNotFound)
+            ));
+            assert_eq!(server.hits("/v3/index.json"), 1);
+            assert_eq!(server.hits("/api/v2/package/Foo/1.0.1"), 1);
+        });
+    }
+}
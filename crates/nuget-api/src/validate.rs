@@ -0,0 +1,670 @@
+//! Stricter checks on API responses, for `--validate-responses`.
+//!
+//! Deserializing a response only makes sure turron can use it. Sources being
+//! tested for conformance also get held to the protocol docs: required fields
+//! have to be there, URLs have to parse, counts have to add up, and versions
+//! have to be normalized. Anything that doesn't is recorded as a
+//! [`Violation`] instead of failing the request.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use dotnet_semver::Version;
+use once_cell::sync::OnceCell;
+use turron_common::{
+    serde::Serialize,
+    serde_json::{self, Value},
+    surf::Url,
+};
+
+use crate::v3::{
+    AutocompleteResponse, CatalogEntry, Dependency, DependencyGroup, Index, IndexResource,
+    LenientVersion, PackageVersions, RegistrationIndex, RegistrationLeaf, RegistrationPage,
+    SearchResponse, SearchResult, SearchResultVersion, Vulnerability,
+};
+
+/// Fields the protocol documents but turron has no use for, so they're never
+/// reported as unknown. Anything starting with `@` is JSON-LD and is skipped
+/// too.
+const DOCUMENTED: &[&str] = &[
+    "commitId",
+    "commitTimeStamp",
+    "deprecation",
+    "language",
+    "licenseUrl",
+    "minClientVersion",
+    "owners",
+    "packageContent",
+    "readmeUrl",
+    "registration",
+    "summary",
+    "title",
+    "vulnerabilities",
+];
+
+static ACTIVE: OnceCell<Arc<ValidationLog>> = OnceCell::new();
+
+/// Starts validating every API response from here on, for the rest of the
+/// process. Calling it again returns the same log.
+pub fn start() -> Arc<ValidationLog> {
+    ACTIVE
+        .get_or_init(|| Arc::new(ValidationLog::default()))
+        .clone()
+}
+
+/// Checks `parsed`, which was deserialized from `body`, if [`start`] has been
+/// called.
+pub(crate) fn check<T: Validate + Serialize>(url: &str, body: &[u8], parsed: &T) {
+    if let Some(log) = ACTIVE.get() {
+        // Bodies that were compressed without saying so don't parse here.
+        // They've already been warned about.
+        if let Ok(raw) = serde_json::from_slice::<Value>(body) {
+            log.extend(violations(url, &raw, parsed));
+        }
+    }
+}
+
+/// Everything about `parsed` that doesn't follow the protocol. `raw` is the
+/// JSON it came from, which is what unknown fields and unnormalized versions
+/// are looked for in.
+pub(crate) fn violations<T: Validate + Serialize>(
+    url: &str,
+    raw: &Value,
+    parsed: &T,
+) -> Vec<Violation> {
+    let mut validator = Validator {
+        raw,
+        path: Vec::new(),
+        found: Vec::new(),
+    };
+    parsed.validate(&mut validator);
+    if let Ok(typed) = serde_json::to_value(parsed) {
+        unknown_fields(raw, &typed, &mut validator);
+    }
+    validator
+        .found
+        .into_iter()
+        .map(|(path, rule, message)| Violation {
+            url: url.into(),
+            path,
+            rule,
+            message,
+        })
+        .collect()
+}
+
+/// Violations found so far, in the order their responses came in.
+#[derive(Debug, Default)]
+pub struct ValidationLog {
+    violations: Mutex<Vec<Violation>>,
+}
+
+impl ValidationLog {
+    pub fn violations(&self) -> Vec<Violation> {
+        self.violations.lock().unwrap().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.violations.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn extend(&self, violations: Vec<Violation>) {
+        self.violations.lock().unwrap().extend(violations);
+    }
+}
+
+/// Something a response got wrong.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    /// The response's URL.
+    pub url: String,
+    /// Where in the response, like `$.items[0].count`.
+    pub path: String,
+    pub rule: Rule,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} ({})",
+            self.url, self.path, self.message, self.rule
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// A field the protocol requires is missing or empty.
+    Required,
+    /// A URL field isn't an absolute URL.
+    Url,
+    /// A count doesn't match what it counts.
+    Count,
+    /// A version isn't written in its normalized form.
+    NormalizedVersion,
+    /// A field neither the protocol nor turron knows about.
+    UnknownField,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Required => write!(f, "required"),
+            Rule::Url => write!(f, "url"),
+            Rule::Count => write!(f, "count"),
+            Rule::NormalizedVersion => write!(f, "normalized-version"),
+            Rule::UnknownField => write!(f, "unknown-field"),
+        }
+    }
+}
+
+/// Implemented by response types, to walk themselves with a [`Validator`].
+pub(crate) trait Validate {
+    fn validate(&self, v: &mut Validator<'_>);
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl From<&str> for Segment {
+    fn from(key: &str) -> Self {
+        Segment::Key(key.into())
+    }
+}
+
+impl From<usize> for Segment {
+    fn from(index: usize) -> Self {
+        Segment::Index(index)
+    }
+}
+
+/// Keeps track of where in a response a [`Validate`] impl is, and what it's
+/// found wrong so far.
+#[derive(Debug)]
+pub(crate) struct Validator<'a> {
+    raw: &'a Value,
+    path: Vec<Segment>,
+    found: Vec<(String, Rule, String)>,
+}
+
+impl<'a> Validator<'a> {
+    /// Validates `value`, found at `key`.
+    pub(crate) fn object<T: Validate>(&mut self, key: impl Into<Segment>, value: &T) {
+        self.path.push(key.into());
+        value.validate(self);
+        self.path.pop();
+    }
+
+    /// Validates each of `items`, found at `key`.
+    pub(crate) fn each<T: Validate>(&mut self, key: &str, items: &[T]) {
+        self.path.push(key.into());
+        for (i, item) in items.iter().enumerate() {
+            self.object(i, item);
+        }
+        self.path.pop();
+    }
+
+    pub(crate) fn required(&mut self, key: &str, present: bool) {
+        if !present {
+            self.report(
+                key,
+                Rule::Required,
+                format!("`{}` is required, but is missing or empty", key),
+            );
+        }
+    }
+
+    /// Like [`Validator::required`], for fields turron doesn't deserialize.
+    pub(crate) fn required_raw(&mut self, key: &str) {
+        let present = self.raw_at(key).map_or(false, |value| {
+            !is_empty(value) && value.as_str().map_or(true, |value| !blank(value))
+        });
+        self.required(key, present);
+    }
+
+    /// Blank URLs count as missing, which isn't this rule's business.
+    pub(crate) fn url(&mut self, key: &str, url: Option<&str>) {
+        if let Some(url) = url.filter(|url| !url.trim().is_empty()) {
+            if Url::parse(url).is_err() {
+                self.report(key, Rule::Url, format!("`{}` isn't an absolute URL", url));
+            }
+        }
+    }
+
+    pub(crate) fn count(&mut self, key: &str, stated: usize, actual: usize) {
+        if stated != actual {
+            self.report(
+                key,
+                Rule::Count,
+                format!("says {}, but there are {}", stated, actual),
+            );
+        }
+    }
+
+    /// For counts of everything there is, of which `actual` were sent.
+    pub(crate) fn count_at_least(&mut self, key: &str, stated: usize, actual: usize) {
+        if stated < actual {
+            self.report(
+                key,
+                Rule::Count,
+                format!("says {}, but {} were sent", stated, actual),
+            );
+        }
+    }
+
+    /// Checks that the version at `key` was written the way `version` would
+    /// normalize it. Casing is up to the source.
+    pub(crate) fn version(&mut self, key: impl Into<Segment>, version: &Version) {
+        self.normalized(key, &version.to_string(), false);
+    }
+
+    /// Checks that the version at `key` was written exactly as `expected`.
+    pub(crate) fn normalized(&mut self, key: impl Into<Segment>, expected: &str, exact: bool) {
+        let key = key.into();
+        let raw = match self.raw_at(key.clone()).and_then(Value::as_str) {
+            Some(raw) => raw.to_string(),
+            None => return,
+        };
+        let matches = if exact {
+            raw == expected
+        } else {
+            raw.eq_ignore_ascii_case(expected)
+        };
+        if !matches {
+            self.report(
+                key,
+                Rule::NormalizedVersion,
+                format!("`{}` should be written as `{}`", raw, expected),
+            );
+        }
+    }
+
+    fn report(&mut self, key: impl Into<Segment>, rule: Rule, message: String) {
+        self.path.push(key.into());
+        let path = render_path(&self.path);
+        self.path.pop();
+        self.found.push((path, rule, message));
+    }
+
+    /// The raw JSON at `key` under the current path.
+    fn raw_at(&self, key: impl Into<Segment>) -> Option<&'a Value> {
+        let mut value = self.raw;
+        for segment in self.path.iter().chain(std::iter::once(&key.into())) {
+            value = match segment {
+                Segment::Key(key) => value.get(key)?,
+                Segment::Index(i) => value.get(i)?,
+            };
+        }
+        Some(value)
+    }
+}
+
+fn render_path(path: &[Segment]) -> String {
+    let mut rendered = String::from("$");
+    for segment in path {
+        match segment {
+            Segment::Key(key) => {
+                rendered.push('.');
+                rendered.push_str(key);
+            }
+            Segment::Index(i) => rendered.push_str(&format!("[{}]", i)),
+        }
+    }
+    rendered
+}
+
+/// Whether serializing would have left `value` out, so its absence from the
+/// typed side doesn't mean it's unknown.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
+}
+
+/// Reports fields in `raw` that didn't survive being deserialized and
+/// serialized again as `typed`, which means turron's types don't have them.
+fn unknown_fields(raw: &Value, typed: &Value, v: &mut Validator<'_>) {
+    match (raw, typed) {
+        (Value::Object(raw), Value::Object(typed)) => {
+            for (key, raw) in raw {
+                if key.starts_with('@') || DOCUMENTED.contains(&key.as_str()) {
+                    continue;
+                }
+                match typed.get(key) {
+                    Some(typed) => {
+                        v.path.push(key.as_str().into());
+                        unknown_fields(raw, typed, v);
+                        v.path.pop();
+                    }
+                    None if !is_empty(raw) => v.report(
+                        key.as_str(),
+                        Rule::UnknownField,
+                        format!("`{}` isn't part of the protocol", key),
+                    ),
+                    None => {}
+                }
+            }
+        }
+        (Value::Array(raw), Value::Array(typed)) => {
+            for (i, (raw, typed)) in raw.iter().zip(typed).enumerate() {
+                v.path.push(i.into());
+                unknown_fields(raw, typed, v);
+                v.path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn blank(value: &str) -> bool {
+    value.trim().is_empty()
+}
+
+impl Validate for Index {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.version("version", &self.version);
+        v.required("resources", !self.resources.is_empty());
+        v.each("resources", &self.resources);
+    }
+}
+
+impl Validate for IndexResource {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.required("@type", !blank(&self.restype));
+    }
+}
+
+impl Validate for RegistrationIndex {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.count("count", self.count, self.items.len());
+        v.each("items", &self.items);
+    }
+}
+
+impl Validate for RegistrationPage {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.url("@id", Some(&self.id));
+        v.url("parent", self.parent.as_deref());
+        v.version("lower", &self.lower);
+        v.version("upper", &self.upper);
+        if let Some(items) = &self.items {
+            v.count("count", self.count, items.len());
+            v.each("items", items);
+        }
+    }
+}
+
+impl Validate for RegistrationLeaf {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.required_raw("@id");
+        v.url("packageContent", Some(&self.package_content));
+        v.object("catalogEntry", &self.catalog_entry);
+    }
+}
+
+impl Validate for CatalogEntry {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.required("id", !blank(&self.id));
+        v.version("version", &self.version);
+        v.url("iconUrl", self.icon_url.as_deref());
+        v.url("licenseUrl", self.license_url.as_deref());
+        v.url("projectUrl", self.project_url.as_deref());
+        if let Some(groups) = &self.dependency_groups {
+            v.each("dependencyGroups", groups);
+        }
+        if let Some(vulnerabilities) = &self.vulnerabilities {
+            v.each("vulnerabilities", vulnerabilities);
+        }
+    }
+}
+
+impl Validate for DependencyGroup {
+    fn validate(&self, v: &mut Validator<'_>) {
+        if let Some(dependencies) = &self.dependencies {
+            v.each("dependencies", dependencies);
+        }
+    }
+}
+
+impl Validate for Dependency {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.required("id", !blank(&self.id));
+    }
+}
+
+impl Validate for Vulnerability {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.url("advisoryUrl", Some(&self.advisory_url));
+    }
+}
+
+impl Validate for PackageVersions {
+    fn validate(&self, v: &mut Validator<'_>) {
+        // The flat container is stricter than everything else: versions are
+        // lowercased, and build metadata is dropped.
+        v.path.push("versions".into());
+        for (i, version) in self.versions.iter().enumerate() {
+            let mut expected = version.clone();
+            expected.build.clear();
+            v.normalized(i, &expected.to_string().to_lowercase(), true);
+        }
+        v.path.pop();
+    }
+}
+
+impl Validate for SearchResponse {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.count_at_least("totalHits", self.total_hits, self.data.len());
+        v.each("data", &self.data);
+    }
+}
+
+impl Validate for SearchResult {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.required("id", !blank(&self.id));
+        match &self.version {
+            LenientVersion::Parsed(version) => v.version("version", version),
+            LenientVersion::Unparseable(raw) => v.report(
+                "version",
+                Rule::NormalizedVersion,
+                format!("`{}` isn't a valid version", raw),
+            ),
+        }
+        v.url("iconUrl", self.icon_url.as_deref());
+        v.url("projectUrl", self.project_url.as_deref());
+        v.required("packageTypes", !self.package_types.is_empty());
+        v.required("versions", !self.versions.is_empty());
+        v.each("versions", &self.versions);
+    }
+}
+
+impl Validate for SearchResultVersion {
+    fn validate(&self, v: &mut Validator<'_>) {
+        v.required("@id", self.id.is_some());
+        v.url("@id", self.id.as_deref());
+        v.required("downloads", self.downloads.is_some());
+        match Version::parse(&self.version) {
+            Ok(version) => v.version("version", &version),
+            Err(_) => v.report(
+                "version",
+                Rule::NormalizedVersion,
+                format!("`{}` isn't a valid version", self.version),
+            ),
+        }
+    }
+}
+
+impl Validate for AutocompleteResponse {
+    fn validate(&self, v: &mut Validator<'_>) {
+        if let Some(total_hits) = self.total_hits {
+            v.count_at_least("totalHits", total_hits, self.data.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_common::serde::de::DeserializeOwned;
+    use turron_common::serde_json::json;
+    use turron_testing::{fixtures, RegistrationBuilder};
+
+    use super::*;
+
+    const URL: &str = "https://example.com/response.json";
+
+    fn check<T: Validate + Serialize + DeserializeOwned>(raw: Value) -> Vec<(Rule, String)> {
+        let parsed: T = serde_json::from_value(raw.clone()).unwrap();
+        violations(URL, &raw, &parsed)
+            .into_iter()
+            .map(|violation| (violation.rule, violation.path))
+            .collect()
+    }
+
+    #[test]
+    fn conforming_responses() {
+        assert!(check::<Index>(fixtures::service_index()).is_empty());
+        assert!(check::<SearchResponse>(fixtures::search()).is_empty());
+        assert!(check::<PackageVersions>(fixtures::flatcontainer_versions()).is_empty());
+        let registration = RegistrationBuilder::new("Foo")
+            .versions(vec!["1.0.0", "2.0.0-beta.1"])
+            .dependency(None, "Bar", "[1.0.0, )")
+            .build();
+        assert!(check::<RegistrationIndex>(registration.index).is_empty());
+    }
+
+    #[test]
+    fn required_fields() {
+        let mut search = fixtures::search();
+        search["data"][0]["versions"] = json!([]);
+        search["data"][1]["versions"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("downloads");
+        assert_eq!(
+            check::<SearchResponse>(search),
+            vec![
+                (Rule::Required, "$.data[0].versions".into()),
+                (Rule::Required, "$.data[1].versions[0].downloads".into()),
+            ]
+        );
+
+        let mut registration = RegistrationBuilder::new("Foo")
+            .versions(vec!["1.0.0"])
+            .build()
+            .index;
+        registration["items"][0]["items"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("@id");
+        assert_eq!(
+            check::<RegistrationIndex>(registration),
+            vec![(Rule::Required, "$.items[0].items[0].@id".into())]
+        );
+    }
+
+    #[test]
+    fn url_fields() {
+        let mut registration = RegistrationBuilder::new("Foo")
+            .versions(vec!["1.0.0"])
+            .build()
+            .index;
+        registration["items"][0]["items"][0]["packageContent"] = json!("foo.1.0.0.nupkg");
+        registration["items"][0]["items"][0]["catalogEntry"]["projectUrl"] =
+            json!("www.example.com");
+        // Blank is the same as missing.
+        registration["items"][0]["items"][0]["catalogEntry"]["iconUrl"] = json!("");
+        assert_eq!(
+            check::<RegistrationIndex>(registration),
+            vec![
+                (Rule::Url, "$.items[0].items[0].packageContent".into()),
+                (
+                    Rule::Url,
+                    "$.items[0].items[0].catalogEntry.projectUrl".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts() {
+        let mut registration = RegistrationBuilder::new("Foo")
+            .versions(vec!["1.0.0", "1.1.0"])
+            .build()
+            .index;
+        registration["count"] = json!(2);
+        registration["items"][0]["count"] = json!(1);
+        assert_eq!(
+            check::<RegistrationIndex>(registration),
+            vec![
+                (Rule::Count, "$.count".into()),
+                (Rule::Count, "$.items[0].count".into()),
+            ]
+        );
+
+        let mut search = fixtures::search();
+        search["totalHits"] = json!(1);
+        assert_eq!(
+            check::<SearchResponse>(search),
+            vec![(Rule::Count, "$.totalHits".into())]
+        );
+    }
+
+    #[test]
+    fn normalized_versions() {
+        assert_eq!(
+            check::<PackageVersions>(json!({
+                "versions": ["1.0.0", "1.0.0.0", "2.0.0-Beta", "3.0.0+sha.abc"]
+            })),
+            vec![
+                (Rule::NormalizedVersion, "$.versions[1]".into()),
+                (Rule::NormalizedVersion, "$.versions[2]".into()),
+                (Rule::NormalizedVersion, "$.versions[3]".into()),
+            ]
+        );
+
+        // Registrations can keep their casing and build metadata.
+        let registration = RegistrationBuilder::new("Foo")
+            .versions(vec!["2.0.0-Beta+sha.abc", "2.0.0.0"])
+            .build()
+            .index;
+        assert_eq!(
+            check::<RegistrationIndex>(registration),
+            vec![
+                (Rule::NormalizedVersion, "$.items[0].upper".into()),
+                (
+                    Rule::NormalizedVersion,
+                    "$.items[0].items[1].catalogEntry.version".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let mut search = fixtures::search();
+        search["data"][0]["popularity"] = json!(11);
+        search["data"][0]["packageTypes"][0]["kind"] = json!("lib");
+        // Nothing to report about empty fields, or ones from the docs.
+        search["data"][0]["related"] = json!([]);
+        search["data"][0]["owners"] = json!("someone");
+        assert_eq!(
+            check::<SearchResponse>(search),
+            vec![
+                (Rule::UnknownField, "$.data[0].packageTypes[0].kind".into()),
+                (Rule::UnknownField, "$.data[0].popularity".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! What the terminal can display, for deciding how fancy rendering gets.
+//!
+//! Some probes are slow (graphics protocol detection can mean waiting on a
+//! terminal that never answers), so results are cached per terminal for a
+//! little while. Anything odd about the cache just means probing again, and
+//! anything odd about a probe result means falling back to
+//! [`Capabilities::safest`].
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use turron_common::{
+    dirs,
+    serde::{Deserialize, Serialize},
+    serde_json, tracing,
+};
+
+/// Name of the cache file, inside turron's cache directory.
+pub const CACHE_FILE: &str = "terminal-capabilities.json";
+
+/// How long probe results are trusted.
+pub const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How images get drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Graphics {
+    /// Colored half-block characters. Works anywhere colors do.
+    Blocks,
+    Kitty,
+    Iterm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorDepth {
+    None,
+    Basic,
+    Ansi256,
+    TrueColor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub graphics: Graphics,
+    pub unicode: bool,
+    pub color: ColorDepth,
+}
+
+impl Capabilities {
+    /// What to assume when nothing can be trusted.
+    pub fn safest() -> Self {
+        Capabilities {
+            graphics: Graphics::Blocks,
+            unicode: false,
+            color: ColorDepth::None,
+        }
+    }
+
+    /// Whether these could all be true of one terminal. Every graphics mode
+    /// needs color.
+    pub fn is_consistent(&self) -> bool {
+        self.color != ColorDepth::None || self.graphics == Graphics::Blocks
+    }
+}
+
+/// The functions that actually ask the terminal. Swappable for tests, and
+/// so crates that know about a particular graphics protocol can supply the
+/// check for it.
+pub struct Probes {
+    pub graphics: Box<dyn Fn() -> Graphics>,
+    pub unicode: Box<dyn Fn() -> bool>,
+    pub color: Box<dyn Fn() -> ColorDepth>,
+}
+
+impl Default for Probes {
+    /// Environment-based guesses, with no graphics protocol detection.
+    fn default() -> Self {
+        Probes {
+            graphics: Box::new(|| Graphics::Blocks),
+            unicode: Box::new(unicode_from_env),
+            color: Box::new(color_from_env),
+        }
+    }
+}
+
+impl Probes {
+    pub fn with_graphics(mut self, probe: impl Fn() -> Graphics + 'static) -> Self {
+        self.graphics = Box::new(probe);
+        self
+    }
+
+    fn run(&self) -> Capabilities {
+        let caps = Capabilities {
+            graphics: (self.graphics)(),
+            unicode: (self.unicode)(),
+            color: (self.color)(),
+        };
+        if caps.is_consistent() {
+            caps
+        } else {
+            tracing::debug!(
+                "Inconsistent terminal capabilities {:?}, playing it safe",
+                caps
+            );
+            Capabilities::safest()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    capabilities: Capabilities,
+    /// Seconds since the Unix epoch.
+    probed_at: u64,
+}
+
+/// Detects the current terminal's capabilities, using turron's cache
+/// directory. `refresh` skips the cache.
+pub fn detect(probes: &Probes, refresh: bool) -> Capabilities {
+    let cache = dirs::cache_dir().ok().map(|dir| dir.join(CACHE_FILE));
+    detect_cached(
+        cache.as_deref(),
+        &terminal_key(),
+        probes,
+        refresh,
+        SystemTime::now(),
+    )
+}
+
+/// Like [`detect`], but with everything spelled out. Never fails: cache
+/// problems are logged and otherwise ignored.
+pub fn detect_cached(
+    cache: Option<&Path>,
+    key: &str,
+    probes: &Probes,
+    refresh: bool,
+    now: SystemTime,
+) -> Capabilities {
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut entries: BTreeMap<String, CacheEntry> = cache
+        .and_then(|file| fs::read_to_string(file).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+    if !refresh {
+        if let Some(entry) = entries.get(key) {
+            let fresh = entry.probed_at <= now && now - entry.probed_at < CACHE_TTL.as_secs();
+            if fresh && entry.capabilities.is_consistent() {
+                return entry.capabilities;
+            }
+        }
+    }
+    let capabilities = probes.run();
+    if let Some(file) = cache {
+        entries.retain(|_, entry| {
+            entry.probed_at <= now && now - entry.probed_at < CACHE_TTL.as_secs()
+        });
+        entries.insert(
+            key.into(),
+            CacheEntry {
+                capabilities,
+                probed_at: now,
+            },
+        );
+        let written = file
+            .parent()
+            .map(fs::create_dir_all)
+            .unwrap_or(Ok(()))
+            .and_then(|_| {
+                fs::write(
+                    file,
+                    serde_json::to_vec(&entries)
+                        .expect("TURRON BUG: capabilities should always serialize"),
+                )
+            });
+        if let Err(err) = written {
+            tracing::debug!("Failed to cache terminal capabilities: {}", err);
+        }
+    }
+    capabilities
+}
+
+/// Identifies the terminal the results apply to: `$TERM`, `$TERM_PROGRAM`,
+/// and, where it can be found, the tty device.
+pub fn terminal_key() -> String {
+    let var = |name| env::var(name).unwrap_or_default();
+    let tty = fs::read_link("/proc/self/fd/1")
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    format!("{}|{}|{}", var("TERM"), var("TERM_PROGRAM"), tty)
+}
+
+fn unicode_from_env() -> bool {
+    if env::var_os("WT_SESSION").is_some() {
+        return true;
+    }
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|val| !val.is_empty())
+        .map(|val| {
+            let val = val.to_lowercase();
+            val.contains("utf-8") || val.contains("utf8")
+        })
+        .unwrap_or(false)
+}
+
+fn color_from_env() -> ColorDepth {
+    let term = env::var("TERM").unwrap_or_default();
+    if env::var_os("NO_COLOR").is_some() || term == "dumb" {
+        return ColorDepth::None;
+    }
+    match env::var("COLORTERM").as_deref() {
+        Ok("truecolor") | Ok("24bit") => ColorDepth::TrueColor,
+        _ if term.contains("256color") => ColorDepth::Ansi256,
+        _ => ColorDepth::Basic,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use tempfile::tempdir;
+
+    const KITTY: Capabilities = Capabilities {
+        graphics: Graphics::Kitty,
+        unicode: true,
+        color: ColorDepth::TrueColor,
+    };
+
+    /// Probes that report `caps` and count how often they're asked.
+    fn fake(caps: Capabilities) -> (Probes, Rc<Cell<usize>>) {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let probes = Probes {
+            graphics: Box::new(move || {
+                counter.set(counter.get() + 1);
+                caps.graphics
+            }),
+            unicode: Box::new(move || caps.unicode),
+            color: Box::new(move || caps.color),
+        };
+        (probes, calls)
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn caches_probe_results() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("cache").join(CACHE_FILE);
+        let (probes, calls) = fake(KITTY);
+
+        assert_eq!(
+            detect_cached(Some(&file), "kitty", &probes, false, at(1000)),
+            KITTY
+        );
+        assert_eq!(
+            detect_cached(Some(&file), "kitty", &probes, false, at(1001)),
+            KITTY
+        );
+        assert_eq!(calls.get(), 1);
+
+        // Refreshing, a different terminal, and an expired entry all probe.
+        detect_cached(Some(&file), "kitty", &probes, true, at(1002));
+        assert_eq!(calls.get(), 2);
+        detect_cached(Some(&file), "xterm", &probes, false, at(1002));
+        assert_eq!(calls.get(), 3);
+        let later = 1002 + CACHE_TTL.as_secs();
+        detect_cached(Some(&file), "kitty", &probes, false, at(later));
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn bad_caches_mean_probing_again() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join(CACHE_FILE);
+        let (probes, calls) = fake(KITTY);
+
+        fs::write(&file, "{\"kitty\": {\"graphics\": \"sixel\"").unwrap();
+        assert_eq!(
+            detect_cached(Some(&file), "kitty", &probes, false, at(1000)),
+            KITTY
+        );
+        assert_eq!(calls.get(), 1);
+
+        // Graphics without color can't be right.
+        fs::write(
+            &file,
+            r#"{"kitty": {"graphics": "iterm", "unicode": true, "color": "none", "probed_at": 1000}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            detect_cached(Some(&file), "kitty", &probes, false, at(1000)),
+            KITTY
+        );
+        assert_eq!(calls.get(), 2);
+
+        // Neither can an entry from the future.
+        fs::write(
+            &file,
+            r#"{"kitty": {"graphics": "blocks", "unicode": false, "color": "basic", "probed_at": 5000}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            detect_cached(Some(&file), "kitty", &probes, false, at(1000)),
+            KITTY
+        );
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn unwritable_caches_are_ignored() {
+        let dir = tempdir().unwrap();
+        // A directory where the file should be can be neither read nor
+        // written.
+        let (probes, calls) = fake(KITTY);
+        assert_eq!(
+            detect_cached(Some(dir.path()), "kitty", &probes, false, at(1000)),
+            KITTY
+        );
+        assert_eq!(
+            detect_cached(None, "kitty", &probes, false, at(1000)),
+            KITTY
+        );
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn inconsistent_probes_fall_back_to_safest() {
+        let (probes, _) = fake(Capabilities {
+            graphics: Graphics::Kitty,
+            unicode: true,
+            color: ColorDepth::None,
+        });
+        assert_eq!(
+            detect_cached(None, "kitty", &probes, false, at(1000)),
+            Capabilities::safest()
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Numbers in human-facing output. They're humanized by default, like `1.2M`
+//! or `18.30MiB`, which is nice to read but not to paste into a spreadsheet.
+//! `--raw-numbers` (or `raw_numbers` in config) switches all of them to plain
+//! integers instead.
+//!
+//! JSON output always has plain numbers, and doesn't go through here.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use indicatif::HumanBytes;
+
+static RAW: AtomicBool = AtomicBool::new(false);
+
+/// How numbers get formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numbers {
+    /// `1.2M`, `18.30MiB`.
+    Human,
+    /// `1234567`, `19189350`. No separators or units.
+    Raw,
+}
+
+/// Sets how numbers get formatted for the rest of the run.
+pub fn set_numbers(numbers: Numbers) {
+    RAW.store(numbers == Numbers::Raw, Ordering::Relaxed);
+}
+
+impl Numbers {
+    /// The mode set with [`set_numbers`]. Commands should use this, and
+    /// pass it down to anything they want to test in both modes.
+    pub fn current() -> Self {
+        if RAW.load(Ordering::Relaxed) {
+            Numbers::Raw
+        } else {
+            Numbers::Human
+        }
+    }
+
+    /// A count of things, like downloads.
+    pub fn count(self, count: u64) -> String {
+        const UNITS: &[(u64, &str)] = &[(1_000_000_000, "B"), (1_000_000, "M"), (1_000, "K")];
+        if self == Numbers::Human {
+            for (size, suffix) in UNITS {
+                if count >= *size {
+                    let scaled = count as f64 / *size as f64;
+                    return if scaled < 100.0 {
+                        format!("{:.1}{}", scaled, suffix)
+                    } else {
+                        format!("{:.0}{}", scaled, suffix)
+                    };
+                }
+            }
+        }
+        count.to_string()
+    }
+
+    /// A size, in bytes.
+    pub fn bytes(self, bytes: u64) -> String {
+        match self {
+            Numbers::Human => HumanBytes(bytes).to_string(),
+            Numbers::Raw => bytes.to_string(),
+        }
+    }
+
+    /// The part of an indicatif template that shows how much of a transfer
+    /// is done.
+    pub fn transfer_template(self) -> &'static str {
+        match self {
+            Numbers::Human => "{bytes}/{total_bytes} ({bytes_per_sec})",
+            Numbers::Raw => "{pos}/{len} bytes",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn humanizes_counts() {
+        let human = [
+            0,
+            999,
+            1_000,
+            1_240,
+            99_949,
+            123_456,
+            1_234_567,
+            4_200_000_000,
+        ]
+        .iter()
+        .map(|count| Numbers::Human.count(*count))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            human,
+            vec!["0", "999", "1.0K", "1.2K", "99.9K", "123K", "1.2M", "4.2B"]
+        );
+        assert_eq!(Numbers::Raw.count(1_234_567), "1234567");
+    }
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(Numbers::Human.bytes(19_189_350), "18.30MiB");
+        assert_eq!(Numbers::Raw.bytes(19_189_350), "19189350");
+        assert_eq!(Numbers::Human.bytes(12), "12B");
+    }
+}
@@ -4,11 +4,14 @@ use turron_common::miette::Result;
 pub use async_trait;
 pub use clap;
 pub use dialoguer;
-pub use directories;
 pub use indicatif;
 pub use owo_colors;
 pub use turron_config;
 
+pub mod capabilities;
+pub mod fmt;
+pub mod render;
+
 #[async_trait::async_trait]
 pub trait TurronCommand {
     async fn execute(self) -> Result<()>;
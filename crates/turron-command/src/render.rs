@@ -0,0 +1,296 @@
+//! Helpers for getting server-provided text onto the terminal in one piece.
+
+use std::collections::HashMap;
+
+use nu_table::{draw_table, StyledString, Table, TextStyle, Theme};
+
+/// Default maximum width, in characters, of a single table cell.
+pub const DEFAULT_MAX_CELL_WIDTH: usize = 80;
+
+/// Makes `text` safe to put in a single table cell: it's [`sanitize`]d,
+/// whitespace runs (including newlines and tabs) are collapsed to a single
+/// space, and anything longer than `max_width` characters is truncated with
+/// an ellipsis.
+pub fn sanitize_cell(text: &str, max_width: usize) -> String {
+    truncate(&collapse_whitespace(&sanitize(text)), max_width)
+}
+
+/// Renders a list of `key: value` pairs as a block, with values wrapped to
+/// fit in `width` columns and continuation lines aligned under the first.
+pub fn record(fields: &[(&str, String)], width: usize) -> String {
+    let key_width = fields
+        .iter()
+        .map(|(k, _)| k.chars().count())
+        .max()
+        .unwrap_or(0);
+    // "key: " plus at least a little room for the value.
+    let value_width = width.saturating_sub(key_width + 2).max(20);
+    let mut out = String::new();
+    for (key, value) in fields {
+        let value = collapse_whitespace(&sanitize(value));
+        let lines = wrap(&value, value_width);
+        for (i, line) in lines.iter().enumerate() {
+            if i == 0 {
+                out.push_str(&format!("{:>width$}: {}", key, line, width = key_width));
+            } else {
+                out.push_str(&format!("{:width$}  {}", "", line, width = key_width));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Draws a table with rounded borders, squeezed into `width` columns if
+/// need be. Cells are used as-is, so they should already be
+/// [`sanitize_cell`]d.
+pub fn table(headers: &[String], rows: &[Vec<String>], width: usize) -> String {
+    let headers = headers
+        .iter()
+        .map(|h| StyledString::new(h.clone(), TextStyle::default_header()))
+        .collect();
+    let rows = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| StyledString::new(cell.clone(), TextStyle::basic_left()))
+                .collect()
+        })
+        .collect();
+    let table = Table::new(headers, rows, Theme::rounded());
+    draw_table(&table, width, &HashMap::new())
+}
+
+/// How many columns [`table`] needs to draw these cells without wrapping
+/// any of them.
+pub fn table_width(headers: &[String], rows: &[Vec<String>]) -> usize {
+    let mut widths = headers
+        .iter()
+        .map(|h| h.chars().count())
+        .collect::<Vec<_>>();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            let len = cell.chars().count();
+            match widths.get_mut(i) {
+                Some(width) => *width = (*width).max(len),
+                None => widths.push(len),
+            }
+        }
+    }
+    // Each column gets a border and a space of padding on either side, and
+    // there's one more border at the end.
+    widths.iter().map(|w| w + 3).sum::<usize>() + 1
+}
+
+/// Word-wraps `text` to lines of at most `width` characters. Words longer
+/// than `width` get a line of their own.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let len = current.chars().count();
+        if len > 0 && len + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn truncate(text: &str, max_width: usize) -> String {
+    if text.chars().count() <= max_width {
+        text.into()
+    } else {
+        let mut truncated = text
+            .chars()
+            .take(max_width.saturating_sub(1))
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+        truncated.push('…');
+        truncated
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Makes server-provided text safe to print to a terminal. Package
+/// descriptions, deprecation messages, and the like come from untrusted
+/// publishers, and printing escape sequences verbatim would let them do
+/// anything from recoloring output to rewriting the user's clipboard.
+///
+/// Removes entire escape sequences (CSI, OSC, DCS, and friends, in both their
+/// 7-bit and 8-bit forms), any stray ESC, and every other C0 or C1 control
+/// character except for newlines and tabs. JSON output should not go through
+/// this.
+pub fn sanitize(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.peek().copied() {
+                Some('[') => {
+                    chars.next();
+                    skip_csi(&mut chars);
+                }
+                Some(']') | Some('P') | Some('X') | Some('^') | Some('_') => {
+                    chars.next();
+                    skip_string(&mut chars);
+                }
+                Some(c) if ('\x20'..='\x2f').contains(&c) => {
+                    // nF escapes: intermediates, then one final byte.
+                    for c in chars.by_ref() {
+                        if !('\x20'..='\x2f').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                Some(c) if ('\x30'..='\x7e').contains(&c) => {
+                    chars.next();
+                }
+                // A bare ESC. Just drop it.
+                _ => {}
+            },
+            '\u{9b}' => skip_csi(&mut chars),
+            '\u{90}' | '\u{98}' | '\u{9d}' | '\u{9e}' | '\u{9f}' => skip_string(&mut chars),
+            '\n' | '\t' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Skips the rest of a CSI sequence: parameters and intermediates, then a
+/// final byte.
+fn skip_csi(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    for c in chars {
+        if ('\x40'..='\x7e').contains(&c) {
+            break;
+        }
+    }
+}
+
+/// Skips the rest of a string-type sequence (OSC, DCS, SOS, PM, APC), which
+/// runs until BEL or ST.
+fn skip_string(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\x07' | '\u{9c}' => break,
+            '\x1b' if chars.peek() == Some(&'\\') => {
+                chars.next();
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: &str =
+        "A package.\r\n\n\tIt does \x1b[31mred\x1b[0m things.\x1b]0;pwned\x07  Really.";
+
+    #[test]
+    fn cells() {
+        assert_eq!(
+            sanitize_cell(DESCRIPTION, 80),
+            "A package. It does red things. Really."
+        );
+        assert_eq!(sanitize_cell(DESCRIPTION, 15), "A package. It…");
+        assert_eq!(sanitize_cell("short", 5), "short");
+    }
+
+    #[test]
+    fn records() {
+        let fields = vec![
+            ("id", "Foo.Bar".to_string()),
+            ("version", "1.2.3".to_string()),
+            ("description", DESCRIPTION.to_string()),
+        ];
+        assert_eq!(
+            record(&fields, 34),
+            concat!(
+                "         id: Foo.Bar\n",
+                "    version: 1.2.3\n",
+                "description: A package. It does\n",
+                "             red things. Really.\n",
+            )
+        );
+    }
+
+    #[test]
+    fn table_widths() {
+        let headers = vec!["id".to_string(), "version".to_string()];
+        let rows = vec![
+            vec!["Newtonsoft.Json".to_string(), "13.0.1".to_string()],
+            vec!["A".to_string(), "1.0.0-beta.1".to_string()],
+        ];
+        // │ Newtonsoft.Json │ 1.0.0-beta.1 │
+        assert_eq!(table_width(&headers, &rows), 34);
+        assert_eq!(table_width(&headers, &[]), 16);
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(wrap("", 10), vec![""]);
+        assert_eq!(wrap("aaaa bbbb cccc", 9), vec!["aaaa bbbb", "cccc"]);
+        assert_eq!(
+            wrap("short waytoolongforthis", 5),
+            vec!["short", "waytoolongforthis"]
+        );
+    }
+
+    #[test]
+    fn keeps_newlines_and_tabs() {
+        assert_eq!(sanitize("one\n\ttwo\r\nthree"), "one\n\ttwo\nthree");
+        assert_eq!(sanitize("héllo wörld ✨"), "héllo wörld ✨");
+    }
+
+    #[test]
+    fn strips_csi() {
+        assert_eq!(sanitize("\x1b[1;31mred\x1b[0m"), "red");
+        assert_eq!(sanitize("\x1b[2J\x1b[Hcleared"), "cleared");
+        assert_eq!(sanitize("\u{9b}31mred\u{9b}0m"), "red");
+    }
+
+    #[test]
+    fn strips_osc() {
+        // Window title, terminated by BEL.
+        assert_eq!(sanitize("\x1b]0;pwned\x07after"), "after");
+        // OSC 52 clipboard write, terminated by ST.
+        assert_eq!(sanitize("a\x1b]52;c;Y3VybCBldmlsLnNoIHwgc2g=\x1b\\b"), "ab");
+        // 8-bit OSC.
+        assert_eq!(sanitize("a\u{9d}8;;https://evil.example\u{9c}b"), "ab");
+        // Hyperlinks.
+        assert_eq!(
+            sanitize("\x1b]8;;https://evil.example\x1b\\click\x1b]8;;\x1b\\"),
+            "click"
+        );
+    }
+
+    #[test]
+    fn strips_bare_esc_and_controls() {
+        assert_eq!(sanitize("a\x1bb"), "a");
+        assert_eq!(sanitize("a\x1b"), "a");
+        assert_eq!(sanitize("\x1bcreset"), "reset");
+        assert_eq!(sanitize("\x1b(Bascii"), "ascii");
+        assert_eq!(
+            sanitize("bell\x07 back\x08space \u{85}next"),
+            "bell backspace next"
+        );
+        // Unterminated sequences eat the rest of the string rather than
+        // leaking partial payloads.
+        assert_eq!(sanitize("ok\x1b]0;never ends"), "ok");
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs;
+
+use turron_command::{
+    clap::{self, Clap, FromArgMatches, IntoApp},
+    turron_config::{TurronConfig, TurronConfigLayer, TurronConfigOptions},
+};
+use turron_common::miette::Result;
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "test"]
+struct TestCmd {
+    #[clap(long)]
+    source: Option<String>,
+    #[clap(long)]
+    dry_run: bool,
+    #[clap(long)]
+    tag: Vec<String>,
+    #[clap(long, default_value = "10")]
+    max_width: usize,
+}
+
+fn config(kdl: &str) -> TurronConfig {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("turron.kdl");
+    fs::write(&file, kdl).unwrap();
+    TurronConfigOptions::new()
+        .env(false)
+        .global_config_file(Some(file))
+        .load()
+        .unwrap()
+}
+
+fn layered(args: &[&str], kdl: &str) -> Result<TestCmd> {
+    let matches = TestCmd::into_app().get_matches_from(args);
+    let mut cmd = TestCmd::from_arg_matches(&matches);
+    cmd.layer_config(&matches, &config(kdl))?;
+    Ok(cmd)
+}
+
+const CONFIG: &str = r#"
+commands {
+    test {
+        source "https://example.com/v3/index.json"
+    }
+}
+source "https://api.nuget.org/v3/index.json"
+dry_run true
+tag "a" "b"
+max_width 40
+"#;
+
+#[test]
+fn config_fills_in_missing_args() -> Result<()> {
+    let cmd = layered(&["test"], CONFIG)?;
+    assert_eq!(
+        cmd.source.as_deref(),
+        Some("https://example.com/v3/index.json")
+    );
+    assert!(cmd.dry_run);
+    assert_eq!(cmd.tag, vec!["a", "b"]);
+    assert_eq!(cmd.max_width, 40);
+    Ok(())
+}
+
+#[test]
+fn unscoped_keys_are_a_fallback() -> Result<()> {
+    let cmd = layered(&["test"], "source \"https://other.example\"\ntag \"solo\"")?;
+    assert_eq!(cmd.source.as_deref(), Some("https://other.example"));
+    assert_eq!(cmd.tag, vec!["solo"]);
+    assert!(!cmd.dry_run);
+    assert_eq!(cmd.max_width, 10);
+    Ok(())
+}
+
+#[test]
+fn args_override_config() -> Result<()> {
+    let cmd = layered(
+        &["test", "--source", "cli", "--tag", "c", "--max-width", "5"],
+        CONFIG,
+    )?;
+    assert_eq!(cmd.source.as_deref(), Some("cli"));
+    assert_eq!(cmd.tag, vec!["c"]);
+    assert_eq!(cmd.max_width, 5);
+    // Not passed, so it still comes from config.
+    assert!(cmd.dry_run);
+    Ok(())
+}
+
+#[test]
+fn bad_config_values_name_the_key() {
+    let err = layered(&["test"], "max_width \"wide\"").unwrap_err();
+    assert!(format!("{}", err).contains("max_width"));
+}
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "keyed"]
+struct KeyedCmd {
+    #[clap(long, default_value = "https://api.nuget.org/v3/index.json")]
+    source: String,
+    #[clap(long)]
+    #[config_layer(api_key_for = "source")]
+    api_key: Option<String>,
+}
+
+const KEYED_CONFIG: &str = r#"
+api_key "fallback"
+source "https://api.nuget.org/v3/index.json" {
+    api_key "nuget"
+}
+source "https://example.com/v3/index.json" {
+    api_key "example"
+}
+"#;
+
+fn keyed(args: &[&str]) -> Result<KeyedCmd> {
+    let matches = KeyedCmd::into_app().get_matches_from(args);
+    let mut cmd = KeyedCmd::from_arg_matches(&matches);
+    cmd.layer_config(&matches, &config(KEYED_CONFIG))?;
+    Ok(cmd)
+}
+
+#[test]
+fn api_keys_follow_the_source() -> Result<()> {
+    assert_eq!(keyed(&["keyed"])?.api_key.as_deref(), Some("nuget"));
+    assert_eq!(
+        keyed(&["keyed", "--source", "https://example.com/v3/index.json"])?
+            .api_key
+            .as_deref(),
+        Some("example")
+    );
+    assert_eq!(
+        keyed(&["keyed", "--source", "https://other.example/index.json"])?
+            .api_key
+            .as_deref(),
+        Some("fallback")
+    );
+    assert_eq!(
+        keyed(&["keyed", "--api-key", "cli"])?.api_key.as_deref(),
+        Some("cli")
+    );
+    Ok(())
+}
+
+#[derive(Debug, Clap, TurronConfigLayer)]
+#[config_layer = "pinned"]
+struct PinnedCmd {
+    #[clap(long, default_value = "https://api.nuget.org/v3/index.json")]
+    source: String,
+    #[clap(long)]
+    #[config_layer(protocol_for = "source")]
+    assume_source_version: Option<String>,
+}
+
+fn pinned(args: &[&str]) -> Result<PinnedCmd> {
+    let matches = PinnedCmd::into_app().get_matches_from(args);
+    let mut cmd = PinnedCmd::from_arg_matches(&matches);
+    cmd.layer_config(
+        &matches,
+        &config(
+            r#"
+assume_source_version "auto"
+source "https://example.com/api/v2" {
+    protocol "v2"
+}
+"#,
+        ),
+    )?;
+    Ok(cmd)
+}
+
+#[test]
+fn protocols_follow_the_source() -> Result<()> {
+    assert_eq!(
+        pinned(&["pinned", "--source", "https://example.com/api/v2/"])?
+            .assume_source_version
+            .as_deref(),
+        Some("v2")
+    );
+    assert_eq!(
+        pinned(&["pinned"])?.assume_source_version.as_deref(),
+        Some("auto")
+    );
+    assert_eq!(
+        pinned(&[
+            "pinned",
+            "--source",
+            "https://example.com/api/v2",
+            "--assume-source-version",
+            "v3"
+        ])?
+        .assume_source_version
+        .as_deref(),
+        Some("v3")
+    );
+    Ok(())
+}
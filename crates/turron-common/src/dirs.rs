@@ -0,0 +1,315 @@
+//! Where turron keeps its configuration and cache.
+//!
+//! Each directory is the first of these that turron could write to:
+//!
+//! 1. `TURRON_CONFIG_DIR` or `TURRON_CACHE_DIR`. These are used as-is, even
+//!    if they aren't writable, since someone asked for them specifically.
+//! 2. The platform's usual place, like `~/.config/turron` on Linux or
+//!    `%APPDATA%\turron\config` on Windows.
+//! 3. `$XDG_CONFIG_HOME/turron` or `$XDG_CACHE_HOME/turron`. The platform
+//!    directories can't be found without a home directory, which some
+//!    containers don't have, but these might still be set.
+//! 4. `.turron/config` or `.turron/cache` in the current directory.
+
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use directories::{BaseDirs, ProjectDirs};
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::explain::Explanation;
+
+/// Name of the global config file, inside the config directory.
+pub const CONFIG_FILE: &str = "turron.kdl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirKind {
+    Config,
+    Cache,
+}
+
+impl DirKind {
+    fn env_var(&self) -> &'static str {
+        match self {
+            DirKind::Config => "TURRON_CONFIG_DIR",
+            DirKind::Cache => "TURRON_CACHE_DIR",
+        }
+    }
+
+    fn xdg_var(&self) -> &'static str {
+        match self {
+            DirKind::Config => "XDG_CONFIG_HOME",
+            DirKind::Cache => "XDG_CACHE_HOME",
+        }
+    }
+}
+
+impl fmt::Display for DirKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DirKind::Config => write!(f, "config"),
+            DirKind::Cache => write!(f, "cache"),
+        }
+    }
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum DirsError {
+    #[error("Couldn't find a writable {0} directory. Tried: {}", join_paths(.1))]
+    #[diagnostic(
+        code(turron::dirs::not_writable),
+        help("Set TURRON_CONFIG_DIR or TURRON_CACHE_DIR to a directory you can write to.")
+    )]
+    NotWritable(DirKind, Vec<PathBuf>),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[Explanation {
+    code: "turron::dirs::not_writable",
+    cause: "turron needed somewhere to keep its config or cache files, but every place it knows to look was either missing or read-only. This usually happens in containers that have no home directory.",
+    fixes: &[
+        "Set TURRON_CONFIG_DIR (for config) or TURRON_CACHE_DIR (for the cache) to a writable directory.",
+        "Set HOME, or XDG_CONFIG_HOME and XDG_CACHE_HOME, to somewhere writable.",
+        "Run turron from a directory you can write to, so it can fall back to `.turron` there.",
+    ],
+    config: &[],
+}];
+
+/// Directory for turron's config files.
+pub fn config_dir() -> Result<PathBuf, DirsError> {
+    resolve_from_env(DirKind::Config)
+}
+
+/// Directory for turron's caches.
+pub fn cache_dir() -> Result<PathBuf, DirsError> {
+    resolve_from_env(DirKind::Cache)
+}
+
+/// The global config file, which may not exist yet.
+pub fn config_file() -> Result<PathBuf, DirsError> {
+    Ok(config_dir()?.join(CONFIG_FILE))
+}
+
+/// NuGet's global packages folder, where `dotnet restore` expands packages:
+/// `NUGET_PACKAGES`, or `~/.nuget/packages` if that isn't set. `None` if
+/// there's no home directory to find it in.
+pub fn nuget_global_packages() -> Option<PathBuf> {
+    env::var_os("NUGET_PACKAGES")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| BaseDirs::new().map(|dirs| dirs.home_dir().join(".nuget").join("packages")))
+}
+
+fn resolve_from_env(kind: DirKind) -> Result<PathBuf, DirsError> {
+    let platform = ProjectDirs::from("", "", "turron").map(|dirs| match kind {
+        DirKind::Config => dirs.config_dir().to_path_buf(),
+        DirKind::Cache => dirs.cache_dir().to_path_buf(),
+    });
+    resolve(
+        kind,
+        |name| env::var_os(name),
+        platform,
+        env::current_dir().ok(),
+    )
+}
+
+/// Picks a directory for `kind` out of the places listed in the module
+/// docs. `var` looks up environment variables, `platform` is the platform
+/// directory if there is one, and `cwd` is the current directory.
+pub fn resolve(
+    kind: DirKind,
+    var: impl Fn(&str) -> Option<OsString>,
+    platform: Option<PathBuf>,
+    cwd: Option<PathBuf>,
+) -> Result<PathBuf, DirsError> {
+    let var = |name| var(name).filter(|val| !val.is_empty()).map(PathBuf::from);
+    if let Some(dir) = var(kind.env_var()) {
+        return Ok(dir);
+    }
+    // The XDG spec says relative paths should be ignored.
+    let xdg = var(kind.xdg_var())
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join("turron"));
+    let local = cwd.map(|cwd| cwd.join(".turron").join(kind.to_string()));
+    let candidates = platform
+        .into_iter()
+        .chain(xdg)
+        .chain(local)
+        .collect::<Vec<_>>();
+    candidates
+        .iter()
+        .find(|dir| is_writable(dir))
+        .cloned()
+        .ok_or(DirsError::NotWritable(kind, candidates))
+}
+
+/// Whether `dir`, or the closest parent of it that exists, is a directory
+/// that isn't read-only. Nothing gets created to find out.
+fn is_writable(dir: &Path) -> bool {
+    dir.ancestors()
+        .find_map(|path| fs::metadata(path).ok())
+        .map_or(false, |meta| {
+            meta.is_dir() && !meta.permissions().readonly()
+        })
+}
+
+fn join_paths(paths: &[PathBuf]) -> String {
+    if paths.is_empty() {
+        return "nowhere".into();
+    }
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use tempfile::tempdir;
+
+    fn env(vars: &[(&str, &Path)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars = vars
+            .iter()
+            .map(|(name, val)| (name.to_string(), val.as_os_str().to_owned()))
+            .collect::<HashMap<_, _>>();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn read_only(dir: &Path) {
+        let mut perms = fs::metadata(dir).unwrap().permissions();
+        perms.set_readonly(true);
+        fs::set_permissions(dir, perms).unwrap();
+    }
+
+    #[test]
+    fn turron_vars_win() {
+        let dir = tempdir().unwrap();
+        let wanted = dir.path().join("not").join("made").join("yet");
+        let resolved = resolve(
+            DirKind::Cache,
+            env(&[
+                ("TURRON_CACHE_DIR", wanted.as_path()),
+                ("XDG_CACHE_HOME", dir.path()),
+            ]),
+            Some(dir.path().join("platform")),
+            Some(dir.path().into()),
+        )
+        .unwrap();
+        assert_eq!(resolved, wanted);
+
+        // Even when turron couldn't write there.
+        read_only(dir.path());
+        let resolved = resolve(
+            DirKind::Config,
+            env(&[("TURRON_CONFIG_DIR", dir.path())]),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(resolved, dir.path());
+    }
+
+    #[test]
+    fn platform_dirs_come_next() {
+        let dir = tempdir().unwrap();
+        let platform = dir.path().join("home").join(".config").join("turron");
+        let resolved = resolve(
+            DirKind::Config,
+            env(&[("XDG_CONFIG_HOME", dir.path().join("xdg").as_path())]),
+            Some(platform.clone()),
+            Some(dir.path().into()),
+        )
+        .unwrap();
+        assert_eq!(resolved, platform);
+    }
+
+    #[test]
+    fn xdg_vars_without_a_home() {
+        let dir = tempdir().unwrap();
+        let xdg = dir.path().join("xdg");
+        let resolved = resolve(
+            DirKind::Cache,
+            env(&[
+                ("XDG_CACHE_HOME", xdg.as_path()),
+                ("XDG_CONFIG_HOME", dir.path()),
+            ]),
+            None,
+            Some(dir.path().into()),
+        )
+        .unwrap();
+        assert_eq!(resolved, xdg.join("turron"));
+
+        // Relative ones don't count.
+        let resolved = resolve(
+            DirKind::Cache,
+            env(&[("XDG_CACHE_HOME", Path::new("relative"))]),
+            None,
+            Some(dir.path().into()),
+        )
+        .unwrap();
+        assert_eq!(resolved, dir.path().join(".turron").join("cache"));
+    }
+
+    #[test]
+    fn falls_back_to_the_current_directory() {
+        let dir = tempdir().unwrap();
+        let resolved = resolve(DirKind::Config, env(&[]), None, Some(dir.path().into())).unwrap();
+        assert_eq!(resolved, dir.path().join(".turron").join("config"));
+
+        // Empty variables are treated as unset.
+        let resolved = resolve(
+            DirKind::Config,
+            env(&[("TURRON_CONFIG_DIR", Path::new(""))]),
+            None,
+            Some(dir.path().into()),
+        )
+        .unwrap();
+        assert_eq!(resolved, dir.path().join(".turron").join("config"));
+    }
+
+    #[test]
+    fn skips_read_only_dirs() {
+        let dir = tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        let open = dir.path().join("open");
+        fs::create_dir(&locked).unwrap();
+        fs::create_dir(&open).unwrap();
+        read_only(&locked);
+
+        let resolved = resolve(
+            DirKind::Cache,
+            env(&[("XDG_CACHE_HOME", open.as_path())]),
+            Some(locked.join("turron")),
+            None,
+        )
+        .unwrap();
+        assert_eq!(resolved, open.join("turron"));
+
+        let err = resolve(
+            DirKind::Cache,
+            env(&[]),
+            Some(locked.join("turron")),
+            Some(locked.clone()),
+        )
+        .unwrap_err();
+        let DirsError::NotWritable(kind, tried) = err;
+        assert_eq!(kind, DirKind::Cache);
+        assert_eq!(
+            tried,
+            vec![locked.join("turron"), locked.join(".turron").join("cache")]
+        );
+
+        assert!(matches!(
+            resolve(DirKind::Config, env(&[]), None, None),
+            Err(DirsError::NotWritable(DirKind::Config, tried)) if tried.is_empty()
+        ));
+    }
+}
@@ -0,0 +1,19 @@
+//! Long-form documentation for diagnostic codes, as printed by
+//! `turron explain <code>`.
+//!
+//! Every crate that defines diagnostics keeps a static list of
+//! [`Explanation`]s right next to its error types. The `turron-cmd-explain`
+//! crate collects them all and has a test making sure none are missing.
+
+/// Extended documentation for a single diagnostic code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Explanation {
+    /// The diagnostic code, as it appears in `#[diagnostic(code(...))]`.
+    pub code: &'static str,
+    /// What usually causes this diagnostic to show up.
+    pub cause: &'static str,
+    /// Things worth trying, in rough order of likelihood.
+    pub fixes: &'static [&'static str],
+    /// Configuration keys that affect this diagnostic, if any.
+    pub config: &'static [&'static str],
+}
@@ -10,3 +10,8 @@ pub use smol;
 pub use surf;
 pub use thiserror;
 pub use tracing;
+
+pub mod dirs;
+pub mod explain;
+pub mod paths;
+pub mod resume;
@@ -0,0 +1,269 @@
+//! Helpers for safely building filesystem paths out of untrusted input, such
+//! as archive entry names, package IDs, and configured directories.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::explain::Explanation;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum PathError {
+    #[error("Path `{0}` is absolute, but a relative path was expected.")]
+    #[diagnostic(
+        code(turron::paths::absolute_path),
+        help("Drive prefixes (`C:`), UNC paths (`\\\\server\\share`), and leading slashes are not allowed here.")
+    )]
+    AbsolutePath(String),
+
+    #[error("Path `{0}` escapes its base directory.")]
+    #[diagnostic(code(turron::paths::path_traversal))]
+    PathTraversal(String),
+
+    #[error("Path `{}` goes through a symlink that points outside its base directory.", .0.display())]
+    #[diagnostic(code(turron::paths::symlink_escape))]
+    SymlinkEscape(PathBuf),
+
+    #[error(transparent)]
+    #[diagnostic(code(turron::paths::io_error))]
+    IoError(#[from] io::Error),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::paths::absolute_path",
+        cause: "A path that should have been relative to some base directory was absolute. This usually comes from an archive entry or a configured directory that starts with `/`, a drive letter, or a UNC prefix.",
+        fixes: &[
+            "If the path came from a config file, rewrite it relative to the project root.",
+            "If it came from a .nupkg, the package is malformed or malicious and should not be extracted.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::paths::path_traversal",
+        cause: "A path used enough `..` components to climb out of the directory it was supposed to stay in. turron refuses to read or write outside that directory.",
+        fixes: &[
+            "Remove the leading `..` components from the configured path.",
+            "If it came from a .nupkg, report the package to the source's maintainers.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::paths::symlink_escape",
+        cause: "Part of the target path already exists on disk as a symlink that points outside the base directory. Writing through it would put files somewhere unexpected.",
+        fixes: &[
+            "Remove or retarget the symlink.",
+            "Extract into a fresh, empty directory instead.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::paths::io_error",
+        cause: "The filesystem returned an error while turron was checking a path, for example while resolving a symlink.",
+        fixes: &["Check that the directory exists and that you have permission to read it."],
+        config: &[],
+    },
+];
+
+/// Joins `relative` onto `base`, making sure the result stays inside `base`.
+///
+/// Both `/` and `\` are treated as separators regardless of platform, since
+/// archive entries and config values are often written on a different OS
+/// than the one reading them. Absolute paths, drive prefixes, UNC paths, and
+/// `..` components that climb above `base` are rejected. If any part of the
+/// joined path already exists on disk as a symlink, it must resolve to
+/// somewhere inside `base`.
+pub fn join_checked(
+    base: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<PathBuf, PathError> {
+    let base = base.as_ref();
+    let relative = relative.as_ref().to_string_lossy();
+    if is_absolute_like(&relative) {
+        return Err(PathError::AbsolutePath(relative.into()));
+    }
+    let mut parts = Vec::new();
+    for part in relative.split(|c| c == '/' || c == '\\') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(PathError::PathTraversal(relative.into()));
+                }
+            }
+            // Something like `foo/C:bar` would turn into a drive-relative
+            // path on Windows.
+            part if has_drive_prefix(part) => {
+                return Err(PathError::AbsolutePath(relative.into()));
+            }
+            part => parts.push(part),
+        }
+    }
+    let mut joined = base.to_path_buf();
+    for part in parts {
+        joined.push(part);
+    }
+    check_symlinks(base, &joined)?;
+    Ok(joined)
+}
+
+/// Turns an arbitrary string (usually a package ID or version) into
+/// something that's safe to use as a single file or directory name on every
+/// platform turron supports.
+pub fn sanitize_filename(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+    let mut sanitized = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    // Windows silently drops trailing dots and spaces.
+    while sanitized.ends_with('.') || sanitized.ends_with(' ') {
+        sanitized.pop();
+    }
+    let stem = sanitized.split('.').next().unwrap_or("");
+    if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        sanitized.insert(0, '_');
+    }
+    if sanitized.is_empty() {
+        sanitized.push('_');
+    }
+    sanitized
+}
+
+fn is_absolute_like(path: &str) -> bool {
+    path.starts_with('/') || path.starts_with('\\') || has_drive_prefix(path)
+}
+
+fn has_drive_prefix(path: &str) -> bool {
+    let mut chars = path.chars();
+    matches!(
+        (chars.next(), chars.next()),
+        (Some(c), Some(':')) if c.is_ascii_alphabetic()
+    )
+}
+
+fn check_symlinks(base: &Path, joined: &Path) -> Result<(), PathError> {
+    let relative = match joined.strip_prefix(base) {
+        Ok(relative) => relative,
+        Err(_) => return Ok(()),
+    };
+    let mut current = base.to_path_buf();
+    let mut canonical_base = None;
+    for component in relative.components() {
+        current.push(component);
+        match current.symlink_metadata() {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let canonical_base = match &canonical_base {
+                    Some(base) => base,
+                    None => canonical_base.get_or_insert(base.canonicalize()?),
+                };
+                let target = current.canonicalize()?;
+                if !target.starts_with(canonical_base) {
+                    return Err(PathError::SymlinkEscape(current));
+                }
+            }
+            Ok(_) => {}
+            // Nothing further down can be a symlink if this doesn't exist.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_normal_paths() {
+        let base = Path::new("base");
+        assert_eq!(
+            join_checked(base, "lib/net5.0/Foo.dll").unwrap(),
+            base.join("lib").join("net5.0").join("Foo.dll")
+        );
+        assert_eq!(
+            join_checked(base, "lib\\net5.0\\.\\Foo.dll").unwrap(),
+            base.join("lib").join("net5.0").join("Foo.dll")
+        );
+        assert_eq!(
+            join_checked(base, "lib/../Foo.dll").unwrap(),
+            base.join("Foo.dll")
+        );
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        let base = Path::new("base");
+        for bad in &["..", "../foo", "foo/../../bar", "foo\\..\\..\\bar"] {
+            assert!(
+                matches!(join_checked(base, bad), Err(PathError::PathTraversal(_))),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_absolute() {
+        let base = Path::new("base");
+        for bad in &[
+            "/etc/passwd",
+            "\\Windows\\System32",
+            "C:\\Windows",
+            "c:foo",
+            "\\\\server\\share\\foo",
+            "//server/share/foo",
+            "foo/C:bar",
+        ] {
+            assert!(
+                matches!(join_checked(base, bad), Err(PathError::AbsolutePath(_))),
+                "{} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_escaping_symlinks() {
+        let outside = tempfile::tempdir().unwrap();
+        let base = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), base.path().join("link")).unwrap();
+        std::fs::create_dir(base.path().join("dir")).unwrap();
+        std::os::unix::fs::symlink(base.path().join("dir"), base.path().join("inner")).unwrap();
+
+        assert!(matches!(
+            join_checked(base.path(), "link/foo"),
+            Err(PathError::SymlinkEscape(_))
+        ));
+        assert_eq!(
+            join_checked(base.path(), "inner/foo").unwrap(),
+            base.path().join("inner").join("foo")
+        );
+    }
+
+    #[test]
+    fn sanitizes_filenames() {
+        assert_eq!(sanitize_filename("Foo.Bar"), "Foo.Bar");
+        assert_eq!(sanitize_filename("1.0.0+build:1"), "1.0.0+build_1");
+        assert_eq!(sanitize_filename("a/b\\c"), "a_b_c");
+        assert_eq!(sanitize_filename("what?*<>|\""), "what______");
+        assert_eq!(sanitize_filename("bell\u{7}"), "bell_");
+        assert_eq!(sanitize_filename("trailing. . "), "trailing");
+        assert_eq!(sanitize_filename(".."), "_");
+        assert_eq!(sanitize_filename(""), "_");
+        assert_eq!(sanitize_filename("CON"), "_CON");
+        assert_eq!(sanitize_filename("com1.txt"), "_com1.txt");
+        assert_eq!(sanitize_filename("Console"), "Console");
+    }
+}
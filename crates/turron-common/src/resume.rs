@@ -0,0 +1,387 @@
+//! Resumable state for long-running jobs, like `turron cache
+//! import-global-packages`, that work through a list of items and would
+//! rather not start over after an interruption.
+//!
+//! State lives in `<cache>/jobs/<hash>.json`, where the hash is taken from
+//! the job's spec, so running the same job again finds where the last run
+//! left off. The file is rewritten atomically after every item.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use miette::Diagnostic;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::explain::Explanation;
+
+/// Bumped when a state file changes in a way older versions can't read.
+/// Purely additive changes don't need a bump: unknown fields are kept when
+/// the file is rewritten.
+pub const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ResumeError {
+    #[error("Failed to access job state at {}.", .0.display())]
+    #[diagnostic(code(turron::resume::io_error))]
+    IoError(PathBuf, #[source] io::Error),
+
+    #[error("Job state at {} is corrupted.", .0.display())]
+    #[diagnostic(
+        code(turron::resume::bad_state),
+        help("Start the job over from scratch to replace it.")
+    )]
+    BadState(PathBuf, #[source] serde_json::Error),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::resume::io_error",
+        cause: "turron keeps track of long-running jobs in a state file in its cache directory, so they can be resumed. That file could not be read or written.",
+        fixes: &[
+            "Make sure the cache directory exists and is writable.",
+            "Check that the disk isn't full.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::resume::bad_state",
+        cause: "A job state file exists for this job, but it isn't valid JSON. It was probably truncated by a crash or edited by hand.",
+        fixes: &["Restart the job from scratch, which replaces the state file."],
+        config: &[],
+    },
+];
+
+/// What's been done so far, as stored on disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobState {
+    pub version: u32,
+    /// The spec the job was started with.
+    pub spec: String,
+    pub completed: BTreeSet<String>,
+    /// Items that failed, with their error messages.
+    pub failed: BTreeMap<String, String>,
+    /// Items not yet attempted, in order.
+    pub remaining: Vec<String>,
+    /// Fields written by newer versions of turron.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Counts for reporting progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct JobSummary {
+    pub completed: usize,
+    pub failed: usize,
+    pub remaining: usize,
+}
+
+impl JobSummary {
+    pub fn total(&self) -> usize {
+        self.completed + self.failed + self.remaining
+    }
+}
+
+impl fmt::Display for JobSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} done, {} failed, {} remaining",
+            self.completed,
+            self.total(),
+            self.failed,
+            self.remaining
+        )
+    }
+}
+
+impl JobState {
+    pub fn summary(&self) -> JobSummary {
+        JobSummary {
+            completed: self.completed.len(),
+            failed: self.failed.len(),
+            remaining: self.remaining.len(),
+        }
+    }
+}
+
+/// A job whose progress is saved as it goes.
+#[derive(Debug)]
+pub struct ResumableJob {
+    path: PathBuf,
+    state: JobState,
+}
+
+impl ResumableJob {
+    /// Where the state for `spec` is kept under `cache`.
+    pub fn state_path(cache: &Path, spec: &str) -> PathBuf {
+        cache
+            .join("jobs")
+            .join(format!("{:016x}.json", fnv1a(spec)))
+    }
+
+    /// A previous run's state for `spec`, if there's one that can be
+    /// resumed. Use this to decide whether to offer resuming.
+    pub fn existing(cache: &Path, spec: &str) -> Result<Option<JobState>, ResumeError> {
+        let path = Self::state_path(cache, spec);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ResumeError::IoError(path, e)),
+        };
+        let state: JobState =
+            serde_json::from_str(&contents).map_err(|e| ResumeError::BadState(path, e))?;
+        // Hash collisions and incompatible future formats both mean there's
+        // nothing here for this job.
+        if state.spec != spec || state.version > STATE_VERSION {
+            return Ok(None);
+        }
+        Ok(Some(state))
+    }
+
+    /// Starts `spec` over from scratch, replacing any saved state.
+    pub fn start(cache: &Path, spec: &str, items: Vec<String>) -> Result<Self, ResumeError> {
+        let job = ResumableJob {
+            path: Self::state_path(cache, spec),
+            state: JobState {
+                version: STATE_VERSION,
+                spec: spec.into(),
+                completed: BTreeSet::new(),
+                failed: BTreeMap::new(),
+                remaining: items,
+                extra: Map::new(),
+            },
+        };
+        job.save()?;
+        Ok(job)
+    }
+
+    /// Picks `spec` back up where it left off, or starts it if there's
+    /// nothing to resume. Completed items are skipped. Failed items are
+    /// retried after everything that was never attempted, and items that
+    /// weren't part of the job before are added to the end.
+    pub fn resume(cache: &Path, spec: &str, items: Vec<String>) -> Result<Self, ResumeError> {
+        let mut state = match Self::existing(cache, spec)? {
+            Some(state) => state,
+            None => return Self::start(cache, spec, items),
+        };
+        let mut remaining = std::mem::take(&mut state.remaining);
+        remaining.extend(
+            std::mem::take(&mut state.failed)
+                .into_iter()
+                .map(|(item, _)| item),
+        );
+        for item in items {
+            if !state.completed.contains(&item) && !remaining.contains(&item) {
+                remaining.push(item);
+            }
+        }
+        state.remaining = remaining;
+        let job = ResumableJob {
+            path: Self::state_path(cache, spec),
+            state,
+        };
+        job.save()?;
+        Ok(job)
+    }
+
+    /// Items still to be processed, in order.
+    pub fn pending(&self) -> &[String] {
+        &self.state.remaining
+    }
+
+    pub fn state(&self) -> &JobState {
+        &self.state
+    }
+
+    pub fn summary(&self) -> JobSummary {
+        self.state.summary()
+    }
+
+    /// Records `item` as done.
+    pub fn complete(&mut self, item: &str) -> Result<(), ResumeError> {
+        self.state.remaining.retain(|i| i != item);
+        self.state.failed.remove(item);
+        self.state.completed.insert(item.into());
+        self.save()
+    }
+
+    /// Records `item` as failed. It'll be retried next time the job is
+    /// resumed.
+    pub fn fail(&mut self, item: &str, error: impl fmt::Display) -> Result<(), ResumeError> {
+        self.state.remaining.retain(|i| i != item);
+        self.state.failed.insert(item.into(), error.to_string());
+        self.save()
+    }
+
+    /// Wraps up the job. The state file is removed if everything went
+    /// through, and kept so failures can be retried otherwise.
+    pub fn finish(self) -> Result<JobSummary, ResumeError> {
+        let summary = self.summary();
+        if summary.failed == 0 && summary.remaining == 0 {
+            match fs::remove_file(&self.path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(ResumeError::IoError(self.path, e)),
+            }
+        }
+        Ok(summary)
+    }
+
+    fn save(&self) -> Result<(), ResumeError> {
+        let io_err = |e| ResumeError::IoError(self.path.clone(), e);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(io_err)?;
+        }
+        let contents = serde_json::to_vec_pretty(&self.state)
+            .expect("TURRON BUG: job state should always serialize");
+        let tmp = self.path.with_extension("json.tmp");
+        let mut out = File::create(&tmp).map_err(io_err)?;
+        out.write_all(&contents).map_err(io_err)?;
+        out.sync_all().map_err(io_err)?;
+        drop(out);
+        fs::rename(&tmp, &self.path).map_err(io_err)
+    }
+}
+
+/// 64-bit FNV-1a. Unlike std's hashers, its output is guaranteed not to
+/// change between Rust versions, which matters for file names that have to
+/// be found again later.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    const SPEC: &str = r#"{"from":"https://api.nuget.org/v3/index.json","to":"./mirror"}"#;
+
+    fn items(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn resumes_after_an_interruption() {
+        let cache = tempdir().unwrap();
+        let all = items(&["a", "b", "c", "d", "e"]);
+        {
+            let mut job = ResumableJob::start(cache.path(), SPEC, all.clone()).unwrap();
+            job.complete("a").unwrap();
+            job.fail("b", "500 Internal Server Error").unwrap();
+            job.complete("c").unwrap();
+            // Dropped here without finishing, like a Ctrl-C.
+        }
+
+        let saved = ResumableJob::existing(cache.path(), SPEC).unwrap().unwrap();
+        assert_eq!(
+            saved.summary(),
+            JobSummary {
+                completed: 2,
+                failed: 1,
+                remaining: 2
+            }
+        );
+        assert_eq!(saved.failed["b"], "500 Internal Server Error");
+
+        let mut job = ResumableJob::resume(cache.path(), SPEC, all).unwrap();
+        assert_eq!(job.pending(), &items(&["d", "e", "b"])[..]);
+        for item in job.pending().to_vec() {
+            job.complete(&item).unwrap();
+        }
+        assert_eq!(
+            job.finish().unwrap(),
+            JobSummary {
+                completed: 5,
+                failed: 0,
+                remaining: 0
+            }
+        );
+        assert!(ResumableJob::existing(cache.path(), SPEC)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn failures_keep_the_state_around() {
+        let cache = tempdir().unwrap();
+        let mut job = ResumableJob::start(cache.path(), SPEC, items(&["a", "b"])).unwrap();
+        job.complete("a").unwrap();
+        job.fail("b", "nope").unwrap();
+        assert_eq!(
+            job.finish().unwrap().to_string(),
+            "1 of 2 done, 1 failed, 0 remaining"
+        );
+        assert!(ResumableJob::existing(cache.path(), SPEC)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn new_items_and_restarts() {
+        let cache = tempdir().unwrap();
+        let mut job = ResumableJob::start(cache.path(), SPEC, items(&["a", "b"])).unwrap();
+        job.complete("a").unwrap();
+        drop(job);
+
+        let job = ResumableJob::resume(cache.path(), SPEC, items(&["a", "b", "c"])).unwrap();
+        assert_eq!(job.pending(), &items(&["b", "c"])[..]);
+
+        let job = ResumableJob::start(cache.path(), SPEC, items(&["a", "b"])).unwrap();
+        assert_eq!(job.pending(), &items(&["a", "b"])[..]);
+
+        let other = ResumableJob::resume(cache.path(), "{}", items(&["x"])).unwrap();
+        assert_eq!(other.pending(), &items(&["x"])[..]);
+        assert_ne!(
+            ResumableJob::state_path(cache.path(), SPEC),
+            ResumableJob::state_path(cache.path(), "{}")
+        );
+    }
+
+    #[test]
+    fn state_format_is_forward_compatible() {
+        let cache = tempdir().unwrap();
+        let path = ResumableJob::state_path(cache.path(), SPEC);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let newer = serde_json::json!({
+            "version": STATE_VERSION,
+            "spec": SPEC,
+            "completed": ["a"],
+            "failed": {},
+            "remaining": ["b"],
+            "checksums": {"a": "abc123"},
+        });
+        fs::write(&path, newer.to_string()).unwrap();
+
+        let mut job = ResumableJob::resume(cache.path(), SPEC, items(&["a", "b"])).unwrap();
+        job.fail("b", "nope").unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["checksums"]["a"], "abc123");
+
+        let incompatible = serde_json::json!({
+            "version": STATE_VERSION + 1,
+            "spec": SPEC,
+            "completed": ["a", "b"],
+            "failed": {},
+            "remaining": [],
+        });
+        fs::write(&path, incompatible.to_string()).unwrap();
+        assert!(ResumableJob::existing(cache.path(), SPEC)
+            .unwrap()
+            .is_none());
+
+        fs::write(&path, "{\"version\": 1, \"spe").unwrap();
+        assert!(matches!(
+            ResumableJob::existing(cache.path(), SPEC),
+            Err(ResumeError::BadState(..))
+        ));
+    }
+}
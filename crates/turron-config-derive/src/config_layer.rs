@@ -14,37 +14,78 @@ pub struct TurronConfigLayer {
 struct ConfigField {
     name: syn::Ident,
     field_type: ConfigFieldType,
+    /// From `#[config_layer(api_key_for = "source")]` and friends: the
+    /// `source` block setting to use, and the field holding the source URL
+    /// to look it up for.
+    per_source: Option<(&'static str, syn::Ident)>,
 }
 
+/// What a `#[config_layer(...)]` field attribute asked for.
+enum FieldAttr {
+    PerSource(&'static str, syn::Ident),
+    SourcePolicy,
+}
+
+/// Field attributes that look a setting up in the `source` block for the
+/// source named by another field, and the setting each one reads.
+const PER_SOURCE_ATTRS: &[(&str, &str)] = &[
+    ("api_key_for", "api_key"),
+    ("protocol_for", "protocol"),
+    ("credential_store_for", "credential_store"),
+];
+
 #[derive(Debug)]
 enum ConfigFieldType {
-    // OptionOption,
-    // OptionVec,
+    OptionOption,
+    OptionVec,
     Option,
     Plain,
-    // Vec,
+    Vec,
+    /// From `#[config_layer(source_policy)]`: filled in with the package
+    /// patterns from every `source` block. These fields aren't args.
+    SourcePolicy,
 }
 
 impl ConfigField {
     fn from_field(_i: usize, field: syn::Field) -> Result<Option<Self>, syn::Error> {
+        let per_source = match field_attr(&field)? {
+            Some(FieldAttr::PerSource(key, source)) => Some((key, source)),
+            Some(FieldAttr::SourcePolicy) => {
+                return match field.ident.clone() {
+                    Some(name) => Ok(Some(ConfigField {
+                        name,
+                        field_type: ConfigFieldType::SourcePolicy,
+                        per_source: None,
+                    })),
+                    None => Err(syn::Error::new(
+                        field.span(),
+                        "Only named structs are supported.",
+                    )),
+                };
+            }
+            None => None,
+        };
         if let Some(attr) = field.attrs.iter().find(|attr| attr.path.is_ident("clap")) {
             let meta = attr.parse_meta()?;
             if let syn::Meta::List(list) = meta {
-                if list.nested.iter().any(|x| {
-                    if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = x {
-                        let p = &name_value.path;
-                        p.is_ident("from_global") || p.is_ident("long") || p.is_ident("short")
-                    } else if let syn::NestedMeta::Meta(syn::Meta::Path(path)) = x {
-                        let p = path;
-                        p.is_ident("from_global") || p.is_ident("long") || p.is_ident("short")
-                    } else if let syn::NestedMeta::Meta(syn::Meta::List(list)) = x {
-                        let p = &list.path;
-                        p.is_ident("from_global") || p.is_ident("long") || p.is_ident("short")
-                    } else {
-                        false
-                    }
-                }) {
-                    // TODO
+                // Per-source settings may be config-only, `#[clap(skip)]`
+                // fields.
+                if per_source.is_some()
+                    || list.nested.iter().any(|x| {
+                        if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = x {
+                            let p = &name_value.path;
+                            p.is_ident("from_global") || p.is_ident("long") || p.is_ident("short")
+                        } else if let syn::NestedMeta::Meta(syn::Meta::Path(path)) = x {
+                            let p = path;
+                            p.is_ident("from_global") || p.is_ident("long") || p.is_ident("short")
+                        } else if let syn::NestedMeta::Meta(syn::Meta::List(list)) = x {
+                            let p = &list.path;
+                            p.is_ident("from_global") || p.is_ident("long") || p.is_ident("short")
+                        } else {
+                            false
+                        }
+                    })
+                {
                     let ty = &field.ty;
                     let member = if let Some(ident) = field.ident.clone() {
                         ident
@@ -55,43 +96,36 @@ impl ConfigField {
                         ));
                     };
                     if is_generic_ty(ty, "Vec") {
-                        return Err(syn::Error::new(
-                            field.span(),
-                            "Vec<_> types are not supported (yet).",
-                        ));
-                        // return Ok(Some(ConfigField {
-                        //     name: member,
-                        //     field_type: ConfigFieldType::Vec,
-                        // }));
+                        return Ok(Some(ConfigField {
+                            name: member,
+                            field_type: ConfigFieldType::Vec,
+                            per_source,
+                        }));
                     } else if let Some(subty) = subty_if_name(ty, "Option") {
                         if is_generic_ty(subty, "Option") {
-                            return Err(syn::Error::new(
-                                field.span(),
-                                "Option<Option<_>> types are not supported (yet).",
-                            ));
-                            // return Ok(Some(ConfigField {
-                            //     name: member,
-                            //     field_type: ConfigFieldType::OptionOption,
-                            // }));
+                            return Ok(Some(ConfigField {
+                                name: member,
+                                field_type: ConfigFieldType::OptionOption,
+                                per_source,
+                            }));
                         } else if is_generic_ty(subty, "Vec") {
-                            return Err(syn::Error::new(
-                                field.span(),
-                                "Option<Vec<_>> types are not supported (yet).",
-                            ));
-                            // return Ok(Some(ConfigField {
-                            //     name: member,
-                            //     field_type: ConfigFieldType::OptionVec,
-                            // }));
+                            return Ok(Some(ConfigField {
+                                name: member,
+                                field_type: ConfigFieldType::OptionVec,
+                                per_source,
+                            }));
                         } else {
                             return Ok(Some(ConfigField {
                                 name: member,
                                 field_type: ConfigFieldType::Option,
+                                per_source,
                             }));
                         }
                     } else {
                         return Ok(Some(ConfigField {
                             name: member,
                             field_type: ConfigFieldType::Plain,
+                            per_source,
                         }));
                     }
                 }
@@ -103,6 +137,56 @@ impl ConfigField {
     }
 }
 
+/// Reads `#[config_layer(api_key_for = "field")]` (or another of
+/// [`PER_SOURCE_ATTRS`]) or `#[config_layer(source_policy)]` off a field.
+fn field_attr(field: &syn::Field) -> Result<Option<FieldAttr>, syn::Error> {
+    let attr = match field
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("config_layer"))
+    {
+        Some(attr) => attr,
+        None => return Ok(None),
+    };
+    if let syn::Meta::List(list) = attr.parse_meta()? {
+        for nested in list.nested {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    lit: syn::Lit::Str(lit_str),
+                    ..
+                })) => {
+                    let (attr_name, key) = match PER_SOURCE_ATTRS
+                        .iter()
+                        .find(|(attr_name, _)| path.is_ident(attr_name))
+                    {
+                        Some(found) => *found,
+                        None => continue,
+                    };
+                    if subty_if_name(&field.ty, "Option").is_none() {
+                        return Err(syn::Error::new(
+                            field.span(),
+                            format!("`{}` fields must be `Option`s.", attr_name),
+                        ));
+                    }
+                    return Ok(Some(FieldAttr::PerSource(
+                        key,
+                        syn::Ident::new(&lit_str.value(), lit_str.span()),
+                    )));
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("source_policy") => {
+                    return Ok(Some(FieldAttr::SourcePolicy));
+                }
+                _ => {}
+            }
+        }
+    }
+    Err(syn::Error::new(
+        attr.span(),
+        "Expected `#[config_layer(api_key_for = \"field\")]`, `#[config_layer(protocol_for = \"field\")]`, `#[config_layer(credential_store_for = \"field\")]`, or `#[config_layer(source_policy)]`.",
+    ))
+}
+
 impl TurronConfigLayer {
     pub fn from_derive_input(input: syn::DeriveInput) -> Result<Self, syn::Error> {
         match input.data {
@@ -157,36 +241,90 @@ impl TurronConfigLayer {
     pub fn gen(&self) -> TokenStream {
         let ident = &self.ident;
         let generics = &self.generics;
-        let sections = self.fields.iter().map(|field| {
+        // Per-source settings depend on the source field having been
+        // layered already.
+        let fields = self
+            .fields
+            .iter()
+            .filter(|field| field.per_source.is_none())
+            .chain(
+                self.fields
+                    .iter()
+                    .filter(|field| field.per_source.is_some()),
+            );
+        let sections = fields.map(|field| {
             let ident = &field.name;
             let field_str = syn::LitStr::new(&format!("{}", field.name), field.name.span());
+            // clap names args after the kebab-cased field name.
+            let arg_str = syn::LitStr::new(
+                &format!("{}", field.name).replace('_', "-"),
+                field.name.span(),
+            );
             let scoped_field_str = syn::LitStr::new(
                 &format!("commands.{}.{}", self.command.value(), field.name),
                 field.name.span(),
             );
+            let keys = quote! { &[#scoped_field_str, #field_str] };
             use ConfigFieldType::*;
-            match field.field_type {
-                Plain => {
-                    quote! {
-                        if !matches.is_present(#field_str) {
-                            if let Ok(val) = config.get_str(#scoped_field_str) {
-                                self.#ident = val.parse().into_diagnostic()?;
-                            } else if let Ok(val) = config.get_str(#field_str) {
-                                self.#ident = val.parse().into_diagnostic()?;
-                            }
-                        }
+            let assign = match field.field_type {
+                Plain => quote! {
+                    if let Some(val) = turron_command::turron_config::config_value(config, #keys)? {
+                        self.#ident = val;
                     }
+                },
+                Option => quote! {
+                    if let Some(val) = turron_command::turron_config::config_value(config, #keys)? {
+                        self.#ident = Some(val);
+                    }
+                },
+                OptionOption => quote! {
+                    if let Some(val) = turron_command::turron_config::config_value(config, #keys)? {
+                        self.#ident = Some(Some(val));
+                    }
+                },
+                Vec => quote! {
+                    if let Some(vals) = turron_command::turron_config::config_values(config, #keys)? {
+                        self.#ident = vals;
+                    }
+                },
+                OptionVec => quote! {
+                    if let Some(vals) = turron_command::turron_config::config_values(config, #keys)? {
+                        self.#ident = Some(vals);
+                    }
+                },
+                SourcePolicy => {
+                    return quote! {
+                        self.#ident = turron_command::turron_config::SourceConfig::source_policy(config);
+                    };
                 }
-                Option => {
+            };
+            let assign = if let Some((key, source)) = &field.per_source {
+                // API keys can also live in the OS keychain. A key kept for
+                // this source there still beats a top-level one.
+                let keychain = if *key == "api_key" {
                     quote! {
-                        if !matches.is_present(#field_str) {
-                            if let Ok(val) = config.get_str(#scoped_field_str) {
-                                self.#ident = Some(val.parse().into_diagnostic()?);
-                            } else if let Ok(val) = config.get_str(#field_str) {
-                                self.#ident = Some(val.parse().into_diagnostic()?);
-                            }
+                        else if let Some(val) = turron_command::turron_config::keychain::keychain_api_key_for(config, self.#source.as_ref()) {
+                            self.#ident = Some(val);
                         }
                     }
+                } else {
+                    quote! {}
+                };
+                quote! {
+                    if let Some(val) = turron_command::turron_config::SourceConfig::source_value(config, self.#source.as_ref(), #key)? {
+                        self.#ident = Some(val);
+                    } #keychain else {
+                        #assign
+                    }
+                }
+            } else {
+                assign
+            };
+            // `is_present` is true for defaulted args too, so check whether
+            // the arg was actually passed.
+            quote! {
+                if matches.occurrences_of(#arg_str) == 0 {
+                    #assign
                 }
             }
         });
@@ -197,7 +335,6 @@ impl TurronConfigLayer {
                     matches: &turron_command::turron_config::ArgMatches,
                     config: &turron_command::turron_config::TurronConfig,
                 ) -> turron_common::miette::Result<()> {
-                    use turron_common::miette::IntoDiagnostic;
                     #(#sections)*
                     Ok(())
                 }
@@ -0,0 +1,419 @@
+//! Command aliases. A few are built in (see [`BUILTIN`]), and more can be
+//! configured with an `alias` block:
+//!
+//! ```kdl
+//! alias {
+//!     up "outdated --update"
+//!     nj "view summary Newtonsoft.Json"
+//! }
+//! ```
+//!
+//! Expansions are split into arguments the way a shell would: quotes group
+//! words, and backslashes escape. An alias stands in for the command name,
+//! and whatever follows it is passed along after the expansion. Aliases can
+//! expand to other aliases, but not back to themselves.
+//!
+//! Aliases named after real commands are ignored, so config can't change
+//! what an existing command does.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+
+use turron_common::miette::{self, Diagnostic};
+use turron_common::thiserror::{self, Error};
+
+use crate::TurronConfig;
+
+/// Aliases every turron has. Configured aliases with the same name replace
+/// these. Aliases that don't cross into a subcommand, like `rm-listing` for
+/// `unlist`, are clap aliases instead.
+pub const BUILTIN: &[(&str, &str)] = &[("info", "view summary"), ("ls", "view versions")];
+
+#[derive(Debug, Diagnostic, Error, PartialEq)]
+pub enum AliasError {
+    #[error("The alias `{name}` can't be expanded: {reason}.")]
+    #[diagnostic(
+        code(config::alias::invalid),
+        help("Fix the alias in the `alias` block of your turron.kdl.")
+    )]
+    Invalid { name: String, reason: &'static str },
+
+    #[error("The alias `{name}` expands back to itself: {chain}.")]
+    #[diagnostic(
+        code(config::alias::recursive),
+        help("Change one of these aliases so they don't refer to each other.")
+    )]
+    Recursive { name: String, chain: String },
+}
+
+/// Every alias turron knows about, by name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aliases {
+    aliases: BTreeMap<String, String>,
+}
+
+impl Default for Aliases {
+    fn default() -> Self {
+        Aliases {
+            aliases: BUILTIN
+                .iter()
+                .map(|(name, expansion)| (name.to_string(), expansion.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Aliases {
+    /// Just the [`BUILTIN`] aliases.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in aliases, plus the ones in `config`'s `alias` block.
+    /// Entries that aren't a single string are skipped.
+    pub fn from_config(config: &TurronConfig) -> Self {
+        let mut aliases = Self::new();
+        for (name, expansion) in config.get_table("alias").unwrap_or_default() {
+            if let Ok(expansion) = expansion.into_str() {
+                aliases = aliases.alias(name, expansion);
+            }
+        }
+        aliases
+    }
+
+    /// Adds an alias, replacing any other by the same name.
+    pub fn alias(mut self, name: impl Into<String>, expansion: impl Into<String>) -> Self {
+        self.aliases.insert(name.into(), expansion.into());
+        self
+    }
+
+    /// What `name` expands to, unexpanded.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// Replaces the alias in `args`, if there is one, with its expansion, over
+    /// and over until what's left is a real command.
+    ///
+    /// `args` is the whole command line, program name included. The alias has
+    /// to be the first argument that isn't an option, so options listed in
+    /// `value_options` (like `--root`) have their values skipped, and nothing
+    /// after a `--` is looked at. Names for which `is_command` is true are
+    /// never expanded.
+    pub fn expand(
+        &self,
+        mut args: Vec<OsString>,
+        value_options: &[String],
+        is_command: impl Fn(&str) -> bool,
+    ) -> Result<Vec<OsString>, AliasError> {
+        let mut chain: Vec<String> = Vec::new();
+        while let Some(pos) = command_position(&args, value_options) {
+            let name = match args[pos].to_str() {
+                Some(name) if !is_command(name) => name.to_string(),
+                _ => break,
+            };
+            let expansion = match self.get(&name) {
+                Some(expansion) => expansion,
+                None => break,
+            };
+            if chain.contains(&name) {
+                chain.push(name);
+                return Err(AliasError::Recursive {
+                    name: chain[0].clone(),
+                    chain: chain.join(" -> "),
+                });
+            }
+            let words = split(expansion).map_err(|reason| AliasError::Invalid {
+                name: name.clone(),
+                reason,
+            })?;
+            if words.is_empty() {
+                return Err(AliasError::Invalid {
+                    name,
+                    reason: "it's empty",
+                });
+            }
+            args.splice(pos..=pos, words.into_iter().map(OsString::from));
+            chain.push(name);
+        }
+        Ok(args)
+    }
+
+    /// A listing of the aliases for `--help`, skipping any that are shadowed
+    /// by a real command.
+    pub fn help(&self, is_command: impl Fn(&str) -> bool) -> String {
+        let active = self
+            .aliases
+            .iter()
+            .filter(|(name, _)| !is_command(name.as_str()))
+            .collect::<Vec<_>>();
+        let width = active.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let mut out = String::from("ALIASES:\n");
+        for (name, expansion) in active {
+            out.push_str(&format!(
+                "    {:width$}    {}\n",
+                name,
+                expansion,
+                width = width
+            ));
+        }
+        out
+    }
+}
+
+/// Where the command name is in `args`: the first argument after the program
+/// name that isn't an option or an option's value.
+fn command_position(args: &[OsString], value_options: &[String]) -> Option<usize> {
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].to_string_lossy();
+        if arg == "--" {
+            return None;
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            // `--root=dir` and `-vdebug` carry their own values.
+            if value_options.iter().any(|option| *option == arg) {
+                i += 1;
+            }
+        } else {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Splits `text` into arguments like a POSIX shell would, minus expansions.
+/// Single quotes keep everything literally, double quotes let a backslash
+/// escape `"`, `\`, `$` and `` ` ``, and outside of quotes a backslash
+/// escapes anything.
+pub fn split(text: &str) -> Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("it has an unclosed single quote"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("it has an unclosed double quote"),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("it has an unclosed double quote"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("it ends with a backslash"),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::TurronConfigOptions;
+
+    const COMMANDS: &[&str] = &["outdated", "publish", "search", "unlist", "view"];
+
+    fn is_command(name: &str) -> bool {
+        COMMANDS.contains(&name)
+    }
+
+    fn value_options() -> Vec<String> {
+        vec!["--root".into(), "-v".into()]
+    }
+
+    fn expand(aliases: &Aliases, line: &str) -> Result<Vec<String>, AliasError> {
+        let args = std::iter::once("turron")
+            .chain(line.split(' '))
+            .map(OsString::from)
+            .collect();
+        Ok(aliases
+            .expand(args, &value_options(), is_command)?
+            .into_iter()
+            .skip(1)
+            .map(|arg| arg.into_string().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn splits_like_a_shell() {
+        assert_eq!(
+            split("outdated --update").unwrap(),
+            vec!["outdated", "--update"]
+        );
+        assert_eq!(split("  spaced \t out  ").unwrap(), vec!["spaced", "out"]);
+        assert_eq!(
+            split(r#"search "Json.NET serializer" --take 5"#).unwrap(),
+            vec!["search", "Json.NET serializer", "--take", "5"]
+        );
+        assert_eq!(
+            split(r#"view 'it''s "quoted"'"#).unwrap(),
+            vec!["view", r#"its "quoted""#]
+        );
+        assert_eq!(split(r#"a" b "c"#).unwrap(), vec!["a b c"]);
+        assert_eq!(split(r#""" ''"#).unwrap(), vec!["", ""]);
+        assert_eq!(
+            split(r#""say \"hi\" \$HOME \n""#).unwrap(),
+            vec![r#"say "hi" $HOME \n"#]
+        );
+        assert_eq!(split(r#"one\ word \"x"#).unwrap(), vec!["one word", "\"x"]);
+        assert_eq!(split("").unwrap(), Vec::<String>::new());
+
+        assert_eq!(split("view 'oops"), Err("it has an unclosed single quote"));
+        assert_eq!(
+            split(r#"view "oops\""#),
+            Err("it has an unclosed double quote")
+        );
+        assert_eq!(split(r"view oops\"), Err("it ends with a backslash"));
+    }
+
+    #[test]
+    fn expands_aliases_in_place() {
+        let aliases = Aliases::new()
+            .alias("up", "outdated --update")
+            .alias("find", r#"search "--take" 5"#);
+        assert_eq!(
+            expand(&aliases, "up --prerelease").unwrap(),
+            vec!["outdated", "--update", "--prerelease"]
+        );
+        assert_eq!(
+            expand(&aliases, "--root dir -q -vdebug find json").unwrap(),
+            vec!["--root", "dir", "-q", "-vdebug", "search", "--take", "5", "json"]
+        );
+        assert_eq!(
+            expand(&aliases, "info Newtonsoft.Json").unwrap(),
+            vec!["view", "summary", "Newtonsoft.Json"]
+        );
+        // Option values and anything after `--` aren't commands.
+        assert_eq!(
+            expand(&aliases, "--root up view").unwrap(),
+            vec!["--root", "up", "view"]
+        );
+        assert_eq!(expand(&aliases, "-- up").unwrap(), vec!["--", "up"]);
+        // Only the command name is looked up.
+        assert_eq!(expand(&aliases, "view up").unwrap(), vec!["view", "up"]);
+        assert_eq!(expand(&aliases, "nope").unwrap(), vec!["nope"]);
+    }
+
+    #[test]
+    fn aliases_can_use_other_aliases() {
+        let aliases = Aliases::new()
+            .alias("nj", "info Newtonsoft.Json")
+            .alias("njq", "-q nj");
+        assert_eq!(
+            expand(&aliases, "njq --json").unwrap(),
+            vec!["-q", "view", "summary", "Newtonsoft.Json", "--json"]
+        );
+    }
+
+    #[test]
+    fn commands_win_over_aliases() {
+        let aliases = Aliases::new().alias("publish", "unlist");
+        assert_eq!(expand(&aliases, "publish x").unwrap(), vec!["publish", "x"]);
+        assert!(!aliases.help(is_command).contains("publish"));
+    }
+
+    #[test]
+    fn catches_recursive_aliases() {
+        let aliases = Aliases::new()
+            .alias("a", "b --one")
+            .alias("b", "-q c")
+            .alias("c", "a")
+            .alias("me", "me");
+        assert_eq!(
+            expand(&aliases, "a"),
+            Err(AliasError::Recursive {
+                name: "a".into(),
+                chain: "a -> b -> c -> a".into(),
+            })
+        );
+        assert_eq!(
+            expand(&aliases, "me"),
+            Err(AliasError::Recursive {
+                name: "me".into(),
+                chain: "me -> me".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_broken_expansions() {
+        let aliases = Aliases::new()
+            .alias("quote", "search 'json")
+            .alias("blank", "  ");
+        assert_eq!(
+            expand(&aliases, "quote"),
+            Err(AliasError::Invalid {
+                name: "quote".into(),
+                reason: "it has an unclosed single quote",
+            })
+        );
+        assert_eq!(
+            expand(&aliases, "blank"),
+            Err(AliasError::Invalid {
+                name: "blank".into(),
+                reason: "it's empty",
+            })
+        );
+        // Broken aliases only matter when they're used.
+        assert_eq!(expand(&aliases, "view x").unwrap(), vec!["view", "x"]);
+    }
+
+    #[test]
+    fn reads_alias_blocks() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("turron.kdl");
+        fs::write(
+            &file,
+            "alias {\n    up \"outdated --update\"\n    info \"view summary --json\"\n}\n",
+        )
+        .unwrap();
+        let config = TurronConfigOptions::new()
+            .env(false)
+            .global_config_file(Some(file))
+            .load()
+            .unwrap();
+        let aliases = Aliases::from_config(&config);
+        assert_eq!(aliases.get("up"), Some("outdated --update"));
+        assert_eq!(aliases.get("info"), Some("view summary --json"));
+        assert_eq!(aliases.get("ls"), Some("view versions"));
+        assert_eq!(
+            aliases.help(is_command),
+            "ALIASES:\n    info    view summary --json\n    ls      view versions\n    up      outdated --update\n"
+        );
+    }
+}
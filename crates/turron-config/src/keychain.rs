@@ -0,0 +1,198 @@
+//! API keys kept in the operating system's credential store (the macOS
+//! Keychain, Windows Credential Manager, or the Secret Service on Linux)
+//! instead of in plain text in turron.kdl.
+//!
+//! This is opt-in. `turron login --keychain` stores the key and leaves a
+//! marker in the source's block:
+//!
+//! ```kdl
+//! source "https://api.nuget.org/v3/index.json" {
+//!     credential_store "keyring"
+//! }
+//! ```
+//!
+//! A top-level `credential_store "keyring"` does the same for every source.
+//! Keys are looked up in this order: `--api-key`, then an `api_key` in the
+//! source's block, then the keychain, and only then a top-level `api_key`.
+
+use turron_common::{
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+    tracing,
+};
+
+use crate::{SourceConfig, TurronConfig};
+
+/// The `credential_store` value that keeps keys in the OS keychain.
+pub const KEYRING: &str = "keyring";
+
+/// Every key is stored under this account name, in a service named after
+/// its source. See [`service_name`].
+#[cfg(feature = "keychain")]
+const ACCOUNT: &str = "api_key";
+
+#[derive(Debug, Diagnostic, Error)]
+pub enum KeychainError {
+    #[error("The OS keychain isn't available: {0}")]
+    #[diagnostic(
+        code(config::keychain::unavailable),
+        help("Headless machines and containers often have no keychain to talk to. Run `turron login` without --keychain to keep the key in turron.kdl instead.")
+    )]
+    Unavailable(String),
+}
+
+/// The keychain service the key for `source` is stored under. URLs that
+/// [`same_source`](crate::same_source) considers the same share one.
+pub fn service_name(source: &str) -> String {
+    format!(
+        "turron:{}",
+        source.trim().trim_end_matches('/').to_lowercase()
+    )
+}
+
+/// Whether the config says to keep the key for `source` in the keychain.
+pub fn uses_keychain(config: &TurronConfig, source: &str) -> bool {
+    let store = config
+        .source_value::<String>(source, "credential_store")
+        .ok()
+        .flatten()
+        .or_else(|| config.get_str("credential_store").ok());
+    matches!(store.as_deref(), Some(KEYRING))
+}
+
+/// The key for `source` from the keychain, if the config opted in to one.
+/// A keychain that can't be reached is only a warning here, since the
+/// command may not even need a key.
+pub fn keychain_api_key_for(config: &TurronConfig, source: &str) -> Option<String> {
+    if !uses_keychain(config, source) {
+        return None;
+    }
+    match load_api_key(source) {
+        Ok(key) => key,
+        Err(err) => {
+            tracing::warn!(
+                "Couldn't read the API key for {} from the keychain: {}",
+                source,
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(feature = "keychain")]
+fn entry(source: &str) -> keyring::Entry {
+    keyring::Entry::new(&service_name(source), ACCOUNT)
+}
+
+#[cfg(feature = "keychain")]
+fn unavailable(err: keyring::Error) -> KeychainError {
+    KeychainError::Unavailable(err.to_string())
+}
+
+/// Reads the key for `source` from the keychain. `Ok(None)` means the
+/// keychain works, but has no key for it.
+#[cfg(feature = "keychain")]
+pub fn load_api_key(source: &str) -> Result<Option<String>, KeychainError> {
+    match entry(source).get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(unavailable(err)),
+    }
+}
+
+/// Stores `key` for `source` in the keychain, replacing any key already
+/// there.
+#[cfg(feature = "keychain")]
+pub fn store_api_key(source: &str, key: &str) -> Result<(), KeychainError> {
+    entry(source).set_password(key).map_err(unavailable)
+}
+
+/// Removes the key for `source` from the keychain. Returns whether there
+/// was one.
+#[cfg(feature = "keychain")]
+pub fn delete_api_key(source: &str) -> Result<bool, KeychainError> {
+    match entry(source).delete_password() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(err) => Err(unavailable(err)),
+    }
+}
+
+#[cfg(not(feature = "keychain"))]
+const NOT_BUILT: &str = "this build of turron doesn't include keychain support";
+
+#[cfg(not(feature = "keychain"))]
+pub fn load_api_key(_source: &str) -> Result<Option<String>, KeychainError> {
+    Err(KeychainError::Unavailable(NOT_BUILT.into()))
+}
+
+#[cfg(not(feature = "keychain"))]
+pub fn store_api_key(_source: &str, _key: &str) -> Result<(), KeychainError> {
+    Err(KeychainError::Unavailable(NOT_BUILT.into()))
+}
+
+#[cfg(not(feature = "keychain"))]
+pub fn delete_api_key(_source: &str) -> Result<bool, KeychainError> {
+    Err(KeychainError::Unavailable(NOT_BUILT.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::Path;
+
+    use anyhow::Result;
+    use tempfile::tempdir;
+
+    use crate::TurronConfigOptions;
+
+    fn load(file: &Path) -> Result<TurronConfig> {
+        Ok(TurronConfigOptions::new()
+            .env(false)
+            .global_config_file(Some(file.to_path_buf()))
+            .load()?)
+    }
+
+    #[test]
+    fn names_services_after_sources() {
+        assert_eq!(
+            service_name("https://API.nuget.org/v3/index.json/"),
+            "turron:https://api.nuget.org/v3/index.json"
+        );
+        assert_eq!(
+            service_name("https://api.nuget.org/v3/index.json"),
+            service_name(" https://api.nuget.org/v3/index.json/")
+        );
+    }
+
+    #[test]
+    fn opts_in_per_source_or_everywhere() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        fs::write(
+            &file,
+            r#"
+source "https://example.com/v3/index.json" {
+    credential_store "keyring"
+}
+"#,
+        )?;
+        let config = load(&file)?;
+        assert!(uses_keychain(&config, "https://example.com/v3/index.json/"));
+        assert!(!uses_keychain(
+            &config,
+            "https://api.nuget.org/v3/index.json"
+        ));
+
+        fs::write(&file, "credential_store \"keyring\"\n")?;
+        let config = load(&file)?;
+        assert!(uses_keychain(
+            &config,
+            "https://api.nuget.org/v3/index.json"
+        ));
+        Ok(())
+    }
+}
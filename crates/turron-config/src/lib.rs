@@ -1,16 +1,29 @@
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 pub use clap::ArgMatches;
 pub use config::Config as TurronConfig;
 pub use config::Value as ConfigValue;
 use config::{ConfigError, Environment, Source};
 use kdl::{KdlNode, KdlValue};
+use turron_common::explain::Explanation;
 use turron_common::miette::{self, Diagnostic, Result};
 use turron_common::thiserror::{self, Error};
 
+pub use aliases::{AliasError, Aliases};
+pub use keychain::KeychainError;
+pub use policy::*;
+pub use sources::*;
 pub use turron_config_derive::*;
+pub use write::update_config;
+
+pub mod aliases;
+pub mod keychain;
+mod policy;
+mod sources;
+mod write;
 
 pub trait TurronConfigLayer {
     fn layer_config(&mut self, _matches: &ArgMatches, _config: &TurronConfig) -> Result<()> {
@@ -24,9 +37,137 @@ pub enum TurronConfigError {
     #[diagnostic(code(config::error))]
     ConfigError(#[from] ConfigError),
 
-    #[error("Error while parsing config file at {1}:\n\t{0}")]
+    #[error("Error while parsing config at {1}:\n\t{0}")]
     #[diagnostic(code(config::parse_error))]
     ConfigParseError(Box<dyn std::error::Error + Send + Sync>, String),
+
+    #[error("Failed to access config file at {1}:\n\t{0}")]
+    #[diagnostic(code(config::io_error))]
+    ConfigIoError(std::io::Error, String),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "config::error",
+        cause: "The configuration system failed to load or merge a config source. This covers things like a config file that could not be read, or an environment variable that could not be converted.",
+        fixes: &[
+            "Run with `--verbosity debug` to see which file was being loaded.",
+            "Pass `--config <file>` to rule out problems with the global config file.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "config::parse_error",
+        cause: "A config value was found, but it could not be converted to the type the command expected, such as a word where a number was needed.",
+        fixes: &[
+            "Look up the key named in the error in your turron.kdl and fix its value.",
+            "Check `TURRON_CONFIG_*` environment variables, which override config files.",
+        ],
+        config: &["commands.<command>.<option>", "<option>"],
+    },
+    Explanation {
+        code: "config::io_error",
+        cause: "turron could not read or write a config file, for example while saving an API key with `turron login`.",
+        fixes: &[
+            "Make sure the config directory exists and is writable.",
+            "Pass `--config <file>` to use a different file.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "config::source_policy::pinned",
+        cause: "The package id matches an `allow` pattern in the `package_patterns` of another source. Pinned ids are refused from every other source, so a public package can't stand in for an internal one with the same name.",
+        fixes: &[
+            "Fetch the package from the source named in the error with `--source`.",
+            "If the pattern is too broad, narrow it in that source's `package_patterns` block.",
+            "Pass `--ignore-source-policy` to skip the check for a single run.",
+        ],
+        config: &["source \"<url>\" { package_patterns { allow ... } }"],
+    },
+    Explanation {
+        code: "config::source_policy::denied",
+        cause: "The package id matches a `deny` pattern in the `package_patterns` of the source it was requested from.",
+        fixes: &[
+            "Fetch the package from a different source with `--source`.",
+            "Pass `--ignore-source-policy` to skip the check for a single run.",
+        ],
+        config: &["source \"<url>\" { package_patterns { deny ... } }"],
+    },
+    Explanation {
+        code: "config::alias::invalid",
+        cause: "An alias from the `alias` block couldn't be turned into arguments. Its expansion is split like a shell would split it, so every quote needs a closing quote, a backslash at the very end has nothing to escape, and an expansion with no words at all doesn't name a command.",
+        fixes: &[
+            "Fix the alias's quoting in your turron.kdl. Quotes inside a KDL string need a backslash, like `up \"search \\\"two words\\\"\"`.",
+            "Use single quotes inside the expansion to avoid escaping, like `up \"search 'two words'\"`.",
+        ],
+        config: &["alias { <name> \"<expansion>\" }"],
+    },
+    Explanation {
+        code: "config::alias::recursive",
+        cause: "An alias expands to itself, either directly or through other aliases, so expanding it would never end. The error lists the whole chain.",
+        fixes: &[
+            "Change one of the aliases in the chain to use the command it means instead of another alias.",
+        ],
+        config: &["alias { <name> \"<expansion>\" }"],
+    },
+    Explanation {
+        code: "config::keychain::unavailable",
+        cause: "The source is set up to keep its API key in the operating system's credential store, but turron couldn't reach it. Headless machines, containers, and SSH sessions often have no keychain service running, and some builds of turron leave keychain support out entirely.",
+        fixes: &[
+            "Unlock the keychain, or start a Secret Service provider like gnome-keyring on Linux.",
+            "Run `turron login` without `--keychain` to keep the key in turron.kdl instead.",
+            "Pass `--api-key` to skip the lookup for a single run.",
+        ],
+        config: &["credential_store", "source \"<url>\" { credential_store \"keyring\" }"],
+    },
+];
+
+/// Looks up the first of `keys` that's set in `config` and parses it. Used
+/// by `#[derive(TurronConfigLayer)]`.
+pub fn config_value<T>(config: &TurronConfig, keys: &[&str]) -> Result<Option<T>, TurronConfigError>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    for key in keys {
+        if let Ok(val) = config.get_str(key) {
+            return parse_value(&val, key).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Like [`config_value`], but for list values. A single value is treated
+/// as a list of one.
+pub fn config_values<T>(
+    config: &TurronConfig,
+    keys: &[&str],
+) -> Result<Option<Vec<T>>, TurronConfigError>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    for key in keys {
+        if let Ok(vals) = config.get_array(key) {
+            return vals
+                .into_iter()
+                .map(|val| parse_value(&val.into_str()?, key))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Some);
+        } else if let Ok(val) = config.get_str(key) {
+            return parse_value(&val, key).map(|val| Some(vec![val]));
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn parse_value<T>(val: &str, key: &str) -> Result<T, TurronConfigError>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    val.parse()
+        .map_err(|e| TurronConfigError::ConfigParseError(Box::new(e), key.into()))
 }
 
 pub struct TurronConfigOptions {
@@ -98,8 +239,9 @@ impl TurronConfigOptions {
                     .map_err(TurronConfigError::ConfigError)?;
             }
             if let Ok(str) = fs::read_to_string(&root.join(".turron.kdl")) {
-                let src = kdl::parse_document(str)
-                    .map_err(|e| TurronConfigError::ConfigParseError(Box::new(e), root.display().to_string()))?;
+                let src = kdl::parse_document(str).map_err(|e| {
+                    TurronConfigError::ConfigParseError(Box::new(e), root.display().to_string())
+                })?;
                 c.merge(KdlDocument(src))
                     .map_err(TurronConfigError::ConfigError)?;
             }
@@ -145,6 +287,16 @@ impl KdlDocument {
         }
     }
 
+    /// `source "url" { ... }` blocks become tables with the URL under
+    /// `url`, collected into a `sources` array.
+    fn source_block(node: &KdlNode) -> ConfigValue {
+        let mut table = KdlDocument::children_table(&node.children);
+        if let Some(url) = node.values.first() {
+            table.insert("url".into(), KdlDocument::read_kdl_val(url));
+        }
+        table.into()
+    }
+
     fn children_table(children: &[KdlNode]) -> HashMap<String, ConfigValue> {
         let mut table = HashMap::new();
         for child in children {
@@ -161,8 +313,16 @@ impl Source for KdlDocument {
 
     fn collect(&self) -> Result<HashMap<String, ConfigValue>, ConfigError> {
         let mut hash = HashMap::new();
+        let mut sources = Vec::new();
         for node in &self.0 {
-            hash.insert(node.name.clone(), KdlDocument::node_value(node));
+            if node.name == "source" && !node.children.is_empty() {
+                sources.push(KdlDocument::source_block(node));
+            } else {
+                hash.insert(node.name.clone(), KdlDocument::node_value(node));
+            }
+        }
+        if !sources.is_empty() {
+            hash.insert("sources".into(), sources.into());
         }
         Ok(hash)
     }
@@ -0,0 +1,350 @@
+//! Package id patterns that pin packages to particular sources, configured
+//! inside `source` blocks:
+//!
+//! ```kdl
+//! source "https://pkgs.contoso.com/v3/index.json" {
+//!     package_patterns {
+//!         allow "Contoso.*" "Fabrikam.Internal"
+//!         deny "Contoso.Legacy.*"
+//!     }
+//! }
+//! ```
+//!
+//! An id that matches an `allow` pattern may only come from the source(s)
+//! that allow it; every other source is implicitly denied. A `deny` pattern
+//! keeps a source from providing matching ids at all. Ids that match no
+//! pattern can come from anywhere.
+//!
+//! Patterns are matched against the whole id, ignoring case. `*` matches
+//! any run of characters and `?` matches exactly one.
+
+use turron_common::miette::{self, Diagnostic};
+use turron_common::thiserror::{self, Error};
+use turron_common::tracing;
+
+use crate::{same_source, ConfigValue};
+
+#[derive(Debug, Diagnostic, Error, PartialEq)]
+pub enum SourcePolicyError {
+    #[error("{id} can't come from {requested}: the package pattern \"{pattern}\" pins it to {pinned_to}.")]
+    #[diagnostic(
+        code(config::source_policy::pinned),
+        help("Use that source instead, or pass --ignore-source-policy if you're sure.")
+    )]
+    Pinned {
+        id: String,
+        requested: String,
+        pattern: String,
+        pinned_to: String,
+    },
+
+    #[error(
+        "{id} can't come from {requested}: that source denies the package pattern \"{pattern}\"."
+    )]
+    #[diagnostic(
+        code(config::source_policy::denied),
+        help("Use another source, or pass --ignore-source-policy if you're sure.")
+    )]
+    Denied {
+        id: String,
+        requested: String,
+        pattern: String,
+    },
+}
+
+/// Which package ids each source may provide. See the module docs for how
+/// it's configured.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourcePolicy {
+    rules: Vec<SourceRule>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct SourceRule {
+    source: String,
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl SourcePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pins ids matching `pattern` to `source`.
+    pub fn allow(mut self, source: impl AsRef<str>, pattern: impl Into<String>) -> Self {
+        self.rule(source.as_ref()).allow.push(pattern.into());
+        self
+    }
+
+    /// Keeps `source` from providing ids matching `pattern`.
+    pub fn deny(mut self, source: impl AsRef<str>, pattern: impl Into<String>) -> Self {
+        self.rule(source.as_ref()).deny.push(pattern.into());
+        self
+    }
+
+    /// Whether any patterns are configured at all.
+    pub fn is_empty(&self) -> bool {
+        self.rules
+            .iter()
+            .all(|rule| rule.allow.is_empty() && rule.deny.is_empty())
+    }
+
+    /// Checks whether `id` may be fetched from `source`.
+    pub fn check(&self, id: &str, source: &str) -> Result<(), SourcePolicyError> {
+        let here = self
+            .rules
+            .iter()
+            .find(|rule| same_source(&rule.source, source));
+        if let Some(pattern) = here.and_then(|rule| first_match(&rule.deny, id)) {
+            return Err(SourcePolicyError::Denied {
+                id: id.into(),
+                requested: source.into(),
+                pattern: pattern.into(),
+            });
+        }
+        if here.and_then(|rule| first_match(&rule.allow, id)).is_some() {
+            return Ok(());
+        }
+        match self
+            .rules
+            .iter()
+            .find_map(|rule| first_match(&rule.allow, id).map(|pattern| (rule, pattern)))
+        {
+            Some((rule, pattern)) => Err(SourcePolicyError::Pinned {
+                id: id.into(),
+                requested: source.into(),
+                pattern: pattern.into(),
+                pinned_to: rule.source.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Like [`SourcePolicy::check`], but with an escape hatch: when `ignore`
+    /// is set, violations are logged as warnings instead.
+    pub fn enforce(&self, id: &str, source: &str, ignore: bool) -> Result<(), SourcePolicyError> {
+        match self.check(id, source) {
+            Err(err) if ignore => {
+                tracing::warn!("IGNORING SOURCE POLICY (--ignore-source-policy): {}", err);
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    /// Reads `package_patterns` out of the `sources` config array.
+    pub(crate) fn from_sources(sources: Vec<ConfigValue>) -> Self {
+        let mut policy = SourcePolicy::new();
+        for block in sources {
+            let mut block = match block.into_table() {
+                Ok(block) => block,
+                Err(_) => continue,
+            };
+            let url = match block.remove("url").and_then(|url| url.into_str().ok()) {
+                Some(url) => url,
+                None => continue,
+            };
+            let mut patterns = match block
+                .remove("package_patterns")
+                .and_then(|patterns| patterns.into_table().ok())
+            {
+                Some(patterns) => patterns,
+                None => continue,
+            };
+            let rule = policy.rule(&url);
+            rule.allow.extend(strings(patterns.remove("allow")));
+            rule.deny.extend(strings(patterns.remove("deny")));
+        }
+        policy
+    }
+
+    fn rule(&mut self, source: &str) -> &mut SourceRule {
+        let index = match self
+            .rules
+            .iter()
+            .position(|rule| same_source(&rule.source, source))
+        {
+            Some(index) => index,
+            None => {
+                self.rules.push(SourceRule {
+                    source: source.into(),
+                    allow: Vec::new(),
+                    deny: Vec::new(),
+                });
+                self.rules.len() - 1
+            }
+        };
+        &mut self.rules[index]
+    }
+}
+
+/// A config value that's either a single string or a list of them.
+fn strings(value: Option<ConfigValue>) -> Vec<String> {
+    match value {
+        None => Vec::new(),
+        Some(value) => match value.clone().into_array() {
+            Ok(values) => values
+                .into_iter()
+                .filter_map(|value| value.into_str().ok())
+                .collect(),
+            Err(_) => value.into_str().into_iter().collect(),
+        },
+    }
+}
+
+fn first_match<'a>(patterns: &'a [String], id: &str) -> Option<&'a str> {
+    patterns
+        .iter()
+        .find(|pattern| matches_pattern(pattern, id))
+        .map(|pattern| &pattern[..])
+}
+
+/// Whether `id` matches the package id `pattern`, ignoring case.
+pub fn matches_pattern(pattern: &str, id: &str) -> bool {
+    let pattern = pattern.trim().to_lowercase().chars().collect::<Vec<_>>();
+    let id = id.to_lowercase().chars().collect::<Vec<_>>();
+    // Classic wildcard matching, backtracking to the most recent `*`.
+    let (mut p, mut i) = (0, 0);
+    let mut star = None;
+    while i < id.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == id[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, i));
+            p += 1;
+        } else if let Some((star_p, star_i)) = star {
+            p = star_p + 1;
+            i = star_i + 1;
+            star = Some((star_p, star_i + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    use crate::{SourceConfig, TurronConfigOptions};
+
+    const INTERNAL: &str = "https://pkgs.contoso.com/v3/index.json";
+    const NUGET: &str = "https://api.nuget.org/v3/index.json";
+
+    #[test]
+    fn patterns() {
+        assert!(matches_pattern("Contoso.*", "Contoso.Core"));
+        assert!(matches_pattern("contoso.*", "CONTOSO.Core.Extensions"));
+        assert!(!matches_pattern("Contoso.*", "Contoso"));
+        assert!(!matches_pattern("Contoso.*", "NotContoso.Core"));
+        assert!(matches_pattern("Contoso", "contoso"));
+        assert!(!matches_pattern("Contoso", "Contoso.Core"));
+        assert!(matches_pattern("*", "Anything.At.All"));
+        assert!(matches_pattern("*.Internal.*", "Fabrikam.Internal.Logging"));
+        assert!(matches_pattern("Foo.?", "Foo.X"));
+        assert!(!matches_pattern("Foo.?", "Foo.XY"));
+        assert!(matches_pattern("a*b*c", "aXXbYYbc"));
+    }
+
+    #[test]
+    fn pins_ids_to_sources() {
+        let policy = SourcePolicy::new().allow(INTERNAL, "Contoso.*");
+        assert_eq!(policy.check("Contoso.Core", INTERNAL), Ok(()));
+        assert_eq!(
+            policy.check("contoso.core", &format!("{}/", INTERNAL)),
+            Ok(())
+        );
+        assert_eq!(
+            policy.check("Contoso.Core", NUGET),
+            Err(SourcePolicyError::Pinned {
+                id: "Contoso.Core".into(),
+                requested: NUGET.into(),
+                pattern: "Contoso.*".into(),
+                pinned_to: INTERNAL.into(),
+            })
+        );
+        // Unpinned ids can come from anywhere.
+        assert_eq!(policy.check("Newtonsoft.Json", NUGET), Ok(()));
+        assert_eq!(policy.check("Newtonsoft.Json", INTERNAL), Ok(()));
+    }
+
+    #[test]
+    fn ids_allowed_on_several_sources() {
+        let mirror = "https://mirror.contoso.com/v3/index.json";
+        let policy = SourcePolicy::new()
+            .allow(INTERNAL, "Contoso.*")
+            .allow(mirror, "Contoso.*");
+        assert_eq!(policy.check("Contoso.Core", INTERNAL), Ok(()));
+        assert_eq!(policy.check("Contoso.Core", mirror), Ok(()));
+        assert!(policy.check("Contoso.Core", NUGET).is_err());
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let policy = SourcePolicy::new()
+            .allow(INTERNAL, "Contoso.*")
+            .deny(INTERNAL, "Contoso.Legacy.*");
+        assert_eq!(
+            policy.check("Contoso.Legacy.Thing", INTERNAL),
+            Err(SourcePolicyError::Denied {
+                id: "Contoso.Legacy.Thing".into(),
+                requested: INTERNAL.into(),
+                pattern: "Contoso.Legacy.*".into(),
+            })
+        );
+        assert!(policy.check("Contoso.Legacy.Thing", NUGET).is_err());
+        assert_eq!(policy.check("Contoso.Core", INTERNAL), Ok(()));
+    }
+
+    #[test]
+    fn empty_policy_allows_everything() {
+        let policy = SourcePolicy::new();
+        assert!(policy.is_empty());
+        assert_eq!(policy.check("Contoso.Core", NUGET), Ok(()));
+    }
+
+    #[test]
+    fn reads_policy_from_config() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        fs::write(
+            &file,
+            r#"
+source "https://pkgs.contoso.com/v3/index.json" {
+    api_key "key"
+    package_patterns {
+        allow "Contoso.*" "Fabrikam.Internal"
+        deny "Contoso.Legacy.*"
+    }
+}
+source "https://api.nuget.org/v3/index.json" {
+    package_patterns {
+        deny "Internal"
+    }
+}
+"#,
+        )?;
+        let config = TurronConfigOptions::new()
+            .env(false)
+            .global_config_file(Some(file))
+            .load()?;
+        assert_eq!(
+            config.source_policy(),
+            SourcePolicy::new()
+                .allow(INTERNAL, "Contoso.*")
+                .allow(INTERNAL, "Fabrikam.Internal")
+                .deny(INTERNAL, "Contoso.Legacy.*")
+                .deny(NUGET, "Internal")
+        );
+        Ok(())
+    }
+}
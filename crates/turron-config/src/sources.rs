@@ -0,0 +1,437 @@
+//! Per-source settings, configured with `source` blocks:
+//!
+//! ```kdl
+//! source "https://api.nuget.org/v3/index.json" {
+//!     api_key "..."
+//! }
+//! ```
+//!
+//! A `source` node with no children keeps its old meaning: it sets the
+//! default source. Blocks can also pin the `protocol` the source speaks,
+//! hold `package_patterns` (see [`SourcePolicy`]), and say how to log in to
+//! the source: with a `username` and `password`, or with a
+//! `credential_provider` plugin, like Azure Artifacts'.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use kdl::{KdlNode, KdlValue};
+
+use crate::write::{node, update_config};
+use crate::{parse_value, ConfigValue, SourcePolicy, TurronConfig, TurronConfigError};
+
+/// Lookups for settings that depend on which source is being used.
+pub trait SourceConfig {
+    /// The API key configured for `source`, if any.
+    fn api_key_for(&self, source: &str) -> Option<String>;
+
+    /// Any other setting from the `source` block for `source`, parsed.
+    fn source_value<T>(&self, source: &str, key: &str) -> Result<Option<T>, TurronConfigError>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static;
+
+    /// The package id patterns configured across all sources.
+    fn source_policy(&self) -> SourcePolicy;
+
+    /// The URL of every `source` block, in the order they were configured.
+    fn configured_sources(&self) -> Vec<String>;
+}
+
+impl SourceConfig for TurronConfig {
+    fn api_key_for(&self, source: &str) -> Option<String> {
+        source_setting(self, source, "api_key")?.into_str().ok()
+    }
+
+    fn source_value<T>(&self, source: &str, key: &str) -> Result<Option<T>, TurronConfigError>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        match source_setting(self, source, key) {
+            Some(val) => {
+                parse_value(&val.into_str()?, &format!("source \"{}\" {}", source, key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn source_policy(&self) -> SourcePolicy {
+        self.get_array("sources")
+            .map(SourcePolicy::from_sources)
+            .unwrap_or_default()
+    }
+
+    fn configured_sources(&self) -> Vec<String> {
+        self.get_array("sources")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|block| block.into_table().ok()?.remove("url")?.into_str().ok())
+            .collect()
+    }
+}
+
+/// The raw `key` node from the `source` block for `source`.
+fn source_setting(config: &TurronConfig, source: &str, key: &str) -> Option<ConfigValue> {
+    config
+        .get_array("sources")
+        .ok()?
+        .into_iter()
+        .filter_map(|block| block.into_table().ok())
+        .find(|block| {
+            block
+                .get("url")
+                .and_then(|url| url.clone().into_str().ok())
+                .map(|url| same_source(&url, source))
+                .unwrap_or(false)
+        })?
+        .remove(key)
+}
+
+/// Whether two source URLs refer to the same source. Comparison ignores case
+/// and trailing slashes.
+pub fn same_source(a: &str, b: &str) -> bool {
+    let normalize = |url: &str| url.trim().trim_end_matches('/').to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+/// Writes `key` into the `source` block for `source` in the KDL config file
+/// at `file`, creating the file, the block, or the `api_key` node as needed.
+/// An existing key is replaced in place, and duplicate `api_key` nodes for
+/// the same source are removed.
+///
+/// The file is re-serialized, so comments and formatting are not kept. See
+/// [`update_config`] for the backup this may make, and the path it returns.
+pub fn save_api_key(
+    file: &Path,
+    source: &str,
+    key: &str,
+) -> Result<Option<PathBuf>, TurronConfigError> {
+    update_config(file, |nodes| set_setting(nodes, source, "api_key", key))
+}
+
+/// Marks `source` as keeping its API key in the OS keychain (see
+/// [`keychain`](crate::keychain)), and removes any key saved in plain text
+/// for it, in the KDL config file at `file`.
+pub fn save_keychain_marker(
+    file: &Path,
+    source: &str,
+) -> Result<Option<PathBuf>, TurronConfigError> {
+    update_config(file, |nodes| {
+        remove_settings(nodes, source, &["api_key"]);
+        set_setting(nodes, source, "credential_store", crate::keychain::KEYRING);
+    })
+}
+
+/// Removes the API key, and any keychain marker, for `source` from the KDL
+/// config file at `file`. Blocks left empty are removed too.
+pub fn forget_api_key(file: &Path, source: &str) -> Result<Option<PathBuf>, TurronConfigError> {
+    update_config(file, |nodes| {
+        remove_settings(nodes, source, &["api_key", "credential_store"])
+    })
+}
+
+/// Sets `name` to `value` in the `source` block for `source`, replacing an
+/// existing setting in place and dropping any duplicates.
+fn set_setting(nodes: &mut Vec<KdlNode>, source: &str, name: &str, value: &str) {
+    let mut setting = Some(node(name, vec![KdlValue::String(value.into())], Vec::new()));
+    let mut i = 0;
+    while i < nodes.len() {
+        if is_source_block(&nodes[i], source) {
+            let block = &mut nodes[i];
+            // The first one is replaced where it is, so the block's other
+            // settings stay in the order they were written.
+            let first = block.children.iter().position(|c| c.name == name);
+            let mut index = 0;
+            block.children.retain(|c| {
+                index += 1;
+                c.name != name || (setting.is_some() && Some(index - 1) == first)
+            });
+            if let Some(setting) = setting.take() {
+                match first {
+                    Some(first) => block.children[first] = setting,
+                    None => block.children.push(setting),
+                }
+            } else if block.children.is_empty() {
+                // A childless `source` node means something else entirely,
+                // so duplicate blocks that only held this go away.
+                nodes.remove(i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+    if let Some(setting) = setting {
+        nodes.push(node(
+            "source",
+            vec![KdlValue::String(source.into())],
+            vec![setting],
+        ));
+    }
+}
+
+/// Removes every one of `names` from the `source` blocks for `source`.
+fn remove_settings(nodes: &mut Vec<KdlNode>, source: &str, names: &[&str]) {
+    let mut i = 0;
+    while i < nodes.len() {
+        if is_source_block(&nodes[i], source) {
+            let block = &mut nodes[i];
+            block.children.retain(|c| !names.contains(&c.name.as_str()));
+            if block.children.is_empty() {
+                nodes.remove(i);
+                continue;
+            }
+        }
+        i += 1;
+    }
+}
+
+fn is_source_block(node: &KdlNode, source: &str) -> bool {
+    node.name == "source"
+        && !node.children.is_empty()
+        && matches!(node.values.first(), Some(KdlValue::String(url)) if same_source(url, source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use tempfile::tempdir;
+
+    use crate::TurronConfigOptions;
+
+    fn load(file: &Path) -> Result<TurronConfig> {
+        Ok(TurronConfigOptions::new()
+            .env(false)
+            .global_config_file(Some(file.to_path_buf()))
+            .load()?)
+    }
+
+    #[test]
+    fn keys_per_source() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        fs::write(
+            &file,
+            r#"
+source "https://api.nuget.org/v3/index.json"
+api_key "global-key"
+source "https://api.nuget.org/v3/index.json" {
+    api_key "nuget-key"
+}
+source "https://pkgs.dev.azure.com/me/_packaging/feed/nuget/v3/index.json/" {
+    api_key "azure-key"
+}
+"#,
+        )?;
+        let config = load(&file)?;
+        assert_eq!(
+            config.api_key_for("https://api.nuget.org/v3/index.json"),
+            Some("nuget-key".into())
+        );
+        assert_eq!(
+            config.api_key_for("https://pkgs.dev.azure.com/me/_packaging/feed/nuget/v3/index.json"),
+            Some("azure-key".into())
+        );
+        assert_eq!(
+            config.api_key_for("https://example.com/v3/index.json"),
+            None
+        );
+        // Source blocks don't clobber the default source or the global key.
+        assert_eq!(
+            config.get_str("source")?,
+            "https://api.nuget.org/v3/index.json"
+        );
+        assert_eq!(config.get_str("api_key")?, "global-key");
+        Ok(())
+    }
+
+    #[test]
+    fn other_settings_per_source() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        fs::write(
+            &file,
+            r#"
+source "https://example.com/v3/index.json" {
+    protocol "v3"
+    timeout "soon"
+}
+"#,
+        )?;
+        let config = load(&file)?;
+        assert_eq!(
+            config.source_value::<String>("https://example.com/v3/index.json/", "protocol")?,
+            Some("v3".into())
+        );
+        assert_eq!(
+            config.source_value::<String>("https://api.nuget.org/v3/index.json", "protocol")?,
+            None
+        );
+        let err = config
+            .source_value::<u32>("https://example.com/v3/index.json", "timeout")
+            .unwrap_err();
+        assert!(format!("{}", err).contains("timeout"));
+        assert_eq!(
+            config.configured_sources(),
+            vec!["https://example.com/v3/index.json"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn saves_keys_into_source_blocks() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        fs::write(&file, "source \"https://api.nuget.org/v3/index.json\"\n")?;
+
+        save_api_key(&file, "https://api.nuget.org/v3/index.json", "one")?;
+        save_api_key(&file, "https://example.com/v3/index.json", "two")?;
+        save_api_key(&file, "https://api.nuget.org/v3/index.json/", "three")?;
+
+        assert_eq!(
+            fs::read_to_string(&file)?,
+            concat!(
+                "source \"https://api.nuget.org/v3/index.json\"\n",
+                "source \"https://api.nuget.org/v3/index.json\" {\n",
+                "    api_key \"three\"\n",
+                "}\n",
+                "source \"https://example.com/v3/index.json\" {\n",
+                "    api_key \"two\"\n",
+                "}\n",
+            )
+        );
+        let config = load(&file)?;
+        assert_eq!(
+            config.api_key_for("https://api.nuget.org/v3/index.json"),
+            Some("three".into())
+        );
+        assert_eq!(
+            config.api_key_for("https://example.com/v3/index.json"),
+            Some("two".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dedupes_keys_for_a_source() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        fs::write(
+            &file,
+            r#"
+source "https://example.com/v3/index.json" {
+    api_key "one"
+    api_key "two"
+}
+source "https://example.com/v3/index.json/" {
+    api_key "three"
+}
+"#,
+        )?;
+        save_api_key(&file, "https://example.com/v3/index.json", "four")?;
+        assert_eq!(
+            fs::read_to_string(&file)?,
+            concat!(
+                "source \"https://example.com/v3/index.json\" {\n",
+                "    api_key \"four\"\n",
+                "}\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn replaces_keys_in_place() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        fs::write(
+            &file,
+            r#"
+source "https://example.com/v3/index.json" {
+    protocol "v3"
+    api_key "one"
+    timeout 30
+}
+"#,
+        )?;
+        save_api_key(&file, "https://example.com/v3/index.json", "two")?;
+        assert_eq!(
+            fs::read_to_string(&file)?,
+            concat!(
+                "source \"https://example.com/v3/index.json\" {\n",
+                "    protocol \"v3\"\n",
+                "    api_key \"two\"\n",
+                "    timeout 30\n",
+                "}\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn moves_keys_to_the_keychain_and_forgets_them() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        fs::write(
+            &file,
+            r#"
+source "https://example.com/v3/index.json" {
+    api_key "one"
+    protocol "v3"
+}
+"#,
+        )?;
+        save_keychain_marker(&file, "https://example.com/v3/index.json")?;
+        assert_eq!(
+            fs::read_to_string(&file)?,
+            concat!(
+                "source \"https://example.com/v3/index.json\" {\n",
+                "    protocol \"v3\"\n",
+                "    credential_store \"keyring\"\n",
+                "}\n",
+            )
+        );
+        assert_eq!(
+            load(&file)?.api_key_for("https://example.com/v3/index.json"),
+            None
+        );
+
+        forget_api_key(&file, "https://example.com/v3/index.json/")?;
+        assert_eq!(
+            fs::read_to_string(&file)?,
+            concat!(
+                "source \"https://example.com/v3/index.json\" {\n",
+                "    protocol \"v3\"\n",
+                "}\n",
+            )
+        );
+        save_api_key(&file, "https://example.com/v3/index.json", "two")?;
+        forget_api_key(&file, "https://example.com/v3/index.json")?;
+        assert_eq!(
+            fs::read_to_string(&file)?,
+            concat!(
+                "source \"https://example.com/v3/index.json\" {\n",
+                "    protocol \"v3\"\n",
+                "}\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn creates_missing_config() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        save_api_key(&file, "https://example.com/v3/index.json", "we\"ird")?;
+        assert_eq!(
+            load(&file)?.api_key_for("https://example.com/v3/index.json"),
+            Some("we\"ird".into())
+        );
+        Ok(())
+    }
+}
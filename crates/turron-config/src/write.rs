@@ -0,0 +1,255 @@
+//! Safe read-modify-write access to KDL config files.
+//!
+//! Several turron processes may try to update the same config at once (think
+//! CI matrix jobs all running `turron login`). Every write goes through
+//! [`update_config`], which serializes writers with an advisory lock on a
+//! `.lock` file next to the config, and replaces the config atomically so
+//! readers never see a half-written file.
+//!
+//! Writes re-serialize the whole file, which loses comments and formatting,
+//! so the first rewrite of a hand-written config keeps a copy of it in
+//! `turron.kdl.bak`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use fs2::FileExt;
+use kdl::{KdlNode, KdlValue};
+
+use crate::TurronConfigError;
+
+/// Parses the KDL config at `file`, lets `edit` change its nodes, and writes
+/// the result back. A missing file (or missing parent directories) is treated
+/// as an empty config.
+///
+/// The lock is held for the whole read-modify-write, so concurrent callers
+/// never lose each other's changes. The file is re-serialized, so comments
+/// and formatting are not kept. Before the first time an existing file is
+/// changed, its original contents are copied to a `.bak` file next to it.
+/// Returns the path of that backup if this call made it.
+pub fn update_config<F>(file: &Path, edit: F) -> Result<Option<PathBuf>, TurronConfigError>
+where
+    F: FnOnce(&mut Vec<KdlNode>),
+{
+    let path = file.display().to_string();
+    let io_err = |e: io::Error| TurronConfigError::ConfigIoError(e, path.clone());
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).map_err(io_err)?;
+    }
+    // The config itself gets replaced on every write, so locking it directly
+    // would leave waiting writers holding a lock on the old file.
+    let lock = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(sidecar(file, "lock"))
+        .map_err(io_err)?;
+    lock.lock_exclusive().map_err(io_err)?;
+
+    let result = (|| {
+        let original = match fs::read_to_string(file) {
+            Ok(contents) => Some(contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(io_err(e)),
+        };
+        let mut nodes = match &original {
+            Some(contents) => kdl::parse_document(contents)
+                .map_err(|e| TurronConfigError::ConfigParseError(Box::new(e), path.clone()))?,
+            None => Vec::new(),
+        };
+        edit(&mut nodes);
+        let updated = to_kdl_string(&nodes);
+        let mut backup = None;
+        if let Some(original) = original.filter(|original| original != &updated) {
+            let bak = sidecar(file, "bak");
+            if !bak.exists() {
+                fs::write(&bak, original).map_err(io_err)?;
+                backup = Some(bak);
+            }
+        }
+        write_atomic(file, updated.as_bytes()).map_err(io_err)?;
+        Ok(backup)
+    })();
+
+    lock.unlock().map_err(io_err)?;
+    result
+}
+
+/// Writes `contents` to a temporary file next to `file`, then renames it into
+/// place. Only call this while holding the config lock, since the temporary
+/// file name is fixed.
+fn write_atomic(file: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = sidecar(file, "tmp");
+    let mut out = File::create(&tmp)?;
+    out.write_all(contents)?;
+    out.sync_all()?;
+    drop(out);
+    fs::rename(&tmp, file)
+}
+
+fn sidecar(file: &Path, ext: &str) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(ext);
+    file.with_file_name(name)
+}
+
+pub(crate) fn node(name: &str, values: Vec<KdlValue>, children: Vec<KdlNode>) -> KdlNode {
+    KdlNode {
+        name: name.into(),
+        values,
+        properties: Default::default(),
+        children,
+    }
+}
+
+fn to_kdl_string(nodes: &[KdlNode]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        write_node(&mut out, node, 0);
+    }
+    out
+}
+
+fn write_node(out: &mut String, node: &KdlNode, depth: usize) {
+    out.push_str(&"    ".repeat(depth));
+    out.push_str(&identifier(&node.name));
+    for value in &node.values {
+        out.push(' ');
+        out.push_str(&value_string(value));
+    }
+    let mut properties = node.properties.iter().collect::<Vec<_>>();
+    properties.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, value) in properties {
+        out.push(' ');
+        out.push_str(&identifier(name));
+        out.push('=');
+        out.push_str(&value_string(value));
+    }
+    if !node.children.is_empty() {
+        out.push_str(" {\n");
+        for child in &node.children {
+            write_node(out, child, depth + 1);
+        }
+        out.push_str(&"    ".repeat(depth));
+        out.push('}');
+    }
+    out.push('\n');
+}
+
+fn identifier(name: &str) -> String {
+    let bare = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if bare {
+        name.into()
+    } else {
+        quoted(name)
+    }
+}
+
+fn value_string(value: &KdlValue) -> String {
+    match value {
+        KdlValue::Int(x) => x.to_string(),
+        KdlValue::Float(x) => format!("{:?}", x),
+        KdlValue::String(x) => quoted(x),
+        KdlValue::Boolean(x) => x.to_string(),
+        KdlValue::Null => "null".into(),
+    }
+}
+
+fn quoted(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use turron_common::smol;
+
+    use crate::save_api_key;
+
+    fn source(i: usize) -> String {
+        format!("https://source{}.example.com/v3/index.json", i)
+    }
+
+    #[test]
+    fn concurrent_writers_keep_every_change() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("nested").join("turron.kdl");
+        smol::block_on(async {
+            let tasks = (0..16)
+                .map(|i| {
+                    let file = file.clone();
+                    smol::unblock(move || save_api_key(&file, &source(i % 8), &format!("key{}", i)))
+                })
+                .collect::<Vec<_>>();
+            for task in tasks {
+                task.await?;
+            }
+            Ok::<_, TurronConfigError>(())
+        })?;
+
+        let nodes = kdl::parse_document(fs::read_to_string(&file)?)?;
+        assert_eq!(nodes.len(), 8);
+        for i in 0..8 {
+            let url = source(i);
+            let block = nodes
+                .iter()
+                .find(|node| matches!(node.values.first(), Some(KdlValue::String(u)) if u == &url))
+                .unwrap_or_else(|| panic!("missing block for {}", url));
+            assert_eq!(block.children.len(), 1);
+            assert_eq!(block.children[0].name, "api_key");
+            let key = match &block.children[0].values[..] {
+                [KdlValue::String(key)] => key.clone(),
+                other => panic!("unexpected api_key values: {:?}", other),
+            };
+            assert!(key == format!("key{}", i) || key == format!("key{}", i + 8));
+        }
+        assert!(!dir.path().join("nested").join("turron.kdl.tmp").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn backs_up_hand_written_configs_once() -> Result<()> {
+        let dir = tempdir()?;
+        let file = dir.path().join("turron.kdl");
+        let bak = dir.path().join("turron.kdl.bak");
+
+        // A brand new config has nothing to back up.
+        assert_eq!(save_api_key(&file, &source(0), "one")?, None);
+        assert!(!bak.exists());
+
+        let hand_written = r#"// Sources I publish to.
+source "https://example.com/v3/index.json" {
+    api_key "x"
+}
+"#;
+        fs::write(&file, hand_written)?;
+        assert_eq!(save_api_key(&file, &source(0), "two")?, Some(bak.clone()));
+        assert_eq!(fs::read_to_string(&bak)?, hand_written);
+
+        // The first backup is the one worth keeping.
+        assert_eq!(save_api_key(&file, &source(0), "three")?, None);
+        assert_eq!(fs::read_to_string(&bak)?, hand_written);
+        Ok(())
+    }
+}
@@ -0,0 +1,473 @@
+//! Checking and fixing the things that make two packs of the same project
+//! come out different.
+//!
+//! `dotnet pack` stamps every zip entry with the current time, names the
+//! package's core properties part (`*.psmdcp`) after a random GUID, gives
+//! the relationships in `_rels/.rels` random ids, and doesn't promise any
+//! particular entry order. [`normalize`] rewrites all of those based only on
+//! what's in the package.
+
+use std::fmt;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::PathBuf;
+
+use turron_common::{regex::Regex, smol, tracing};
+use zip::{write::FileOptions, CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use crate::{errors::DotnetError, PackOptions, PackReport};
+
+const CORE_PROPERTIES_DIR: &str = "package/services/metadata/core-properties/";
+const RELS: &str = "_rels/.rels";
+
+/// Something in a .nupkg that would change between two packs of the same
+/// inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Nondeterminism {
+    /// The entry's timestamp is the time it was packed.
+    Timestamp { entry: String },
+    /// The core properties part is named after a random GUID.
+    PartName { entry: String },
+    /// `_rels/.rels` uses random relationship ids.
+    RelationshipIds,
+    /// Entries aren't sorted by name.
+    EntryOrder,
+}
+
+impl fmt::Display for Nondeterminism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Nondeterminism::*;
+        match self {
+            Timestamp { entry } => write!(f, "{} has an unnormalized timestamp", entry),
+            PartName { entry } => write!(f, "{} has a randomly generated name", entry),
+            RelationshipIds => write!(f, "{} has random relationship ids", RELS),
+            EntryOrder => write!(f, "entries are not sorted"),
+        }
+    }
+}
+
+/// How an entry differs between two packages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffKind {
+    OnlyInFirst,
+    OnlyInSecond,
+    Contents,
+    Timestamp,
+    Position,
+}
+
+/// An entry that differs between two packages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryDiff {
+    pub entry: String,
+    pub kind: DiffKind,
+}
+
+impl fmt::Display for EntryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use DiffKind::*;
+        let what = match self.kind {
+            OnlyInFirst => "only in the first pack",
+            OnlyInSecond => "only in the second pack",
+            Contents => "contents differ",
+            Timestamp => "timestamps differ",
+            Position => "position in the archive differs",
+        };
+        write!(f, "{} ({})", self.entry, what)
+    }
+}
+
+struct Entry {
+    name: String,
+    contents: Vec<u8>,
+    modified: (u16, u16),
+}
+
+/// Lists everything in `nupkg` that [`normalize`] would change.
+pub fn inspect(nupkg: &[u8]) -> Result<Vec<Nondeterminism>, DotnetError> {
+    let entries = read_entries(nupkg)?;
+    let mut found = Vec::new();
+    let fixed = fixed_time();
+    for entry in &entries {
+        if entry.modified != (fixed.datepart(), fixed.timepart()) {
+            found.push(Nondeterminism::Timestamp {
+                entry: entry.name.clone(),
+            });
+        }
+    }
+    if let Some(part) = entries.iter().find(|entry| is_core_properties(&entry.name)) {
+        if part.name != core_properties_name(&part.contents) {
+            found.push(Nondeterminism::PartName {
+                entry: part.name.clone(),
+            });
+        }
+    }
+    if let Some(rels) = entries.iter().find(|entry| entry.name == RELS) {
+        let contents = String::from_utf8_lossy(&rels.contents);
+        if normalize_relationship_ids(&contents) != contents {
+            found.push(Nondeterminism::RelationshipIds);
+        }
+    }
+    if entries.windows(2).any(|pair| pair[0].name > pair[1].name) {
+        found.push(Nondeterminism::EntryOrder);
+    }
+    Ok(found)
+}
+
+/// Rewrites `nupkg` so its bytes only depend on its contents: entries are
+/// sorted, every timestamp is 1980-01-01, the core properties part is named
+/// after a hash of its contents, and relationship ids are derived from their
+/// targets.
+pub fn normalize(nupkg: &[u8]) -> Result<Vec<u8>, DotnetError> {
+    let mut entries = read_entries(nupkg)?;
+    if let Some(index) = entries
+        .iter()
+        .position(|entry| is_core_properties(&entry.name))
+    {
+        let old = entries[index].name.clone();
+        let new = core_properties_name(&entries[index].contents);
+        entries[index].name = new.clone();
+        if let Some(rels) = entries.iter_mut().find(|entry| entry.name == RELS) {
+            let contents = String::from_utf8_lossy(&rels.contents)
+                .replace(&format!("/{}", old), &format!("/{}", new));
+            rels.contents = contents.into_bytes();
+        }
+    }
+    if let Some(rels) = entries.iter_mut().find(|entry| entry.name == RELS) {
+        let contents = String::from_utf8_lossy(&rels.contents).into_owned();
+        rels.contents = normalize_relationship_ids(&contents).into_bytes();
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(fixed_time())
+        .unix_permissions(0o644);
+    for entry in entries {
+        zip.start_file(entry.name, options)?;
+        zip.write_all(&entry.contents)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Compares two packages entry by entry.
+pub fn diff(first: &[u8], second: &[u8]) -> Result<Vec<EntryDiff>, DotnetError> {
+    let first = read_entries(first)?;
+    let second = read_entries(second)?;
+    let mut diffs = Vec::new();
+    for (i, a) in first.iter().enumerate() {
+        let kind = match second.iter().position(|b| b.name == a.name) {
+            None => Some(DiffKind::OnlyInFirst),
+            Some(j) if second[j].contents != a.contents => Some(DiffKind::Contents),
+            Some(j) if second[j].modified != a.modified => Some(DiffKind::Timestamp),
+            Some(j) if i != j => Some(DiffKind::Position),
+            Some(_) => None,
+        };
+        if let Some(kind) = kind {
+            diffs.push(EntryDiff {
+                entry: a.name.clone(),
+                kind,
+            });
+        }
+    }
+    for b in &second {
+        if !first.iter().any(|a| a.name == b.name) {
+            diffs.push(EntryDiff {
+                entry: b.name.clone(),
+                kind: DiffKind::OnlyInSecond,
+            });
+        }
+    }
+    Ok(diffs)
+}
+
+/// Runs `dotnet pack` and normalizes every package it produced. With
+/// `twice`, packs a second time and fails if the normalized packages don't
+/// match byte for byte.
+pub async fn pack_deterministic(
+    opts: &PackOptions,
+    twice: bool,
+) -> Result<PackReport, DotnetError> {
+    let report = crate::pack(opts).await?;
+    let first = normalize_all(&report.nupkgs).await?;
+    if twice {
+        tracing::info!("Packing a second time to check for reproducibility");
+        let again = crate::pack(opts).await?;
+        let second = normalize_all(&again.nupkgs).await?;
+        for (path, a) in &first {
+            let diffs = match second.iter().find(|(other, _)| other == path) {
+                Some((_, b)) if a == b => continue,
+                Some((_, b)) => diff(a, b)?,
+                // Not producing the package at all the second time around
+                // isn't something entries can describe.
+                None => Vec::new(),
+            };
+            return Err(DotnetError::NotReproducible {
+                path: path.clone(),
+                diffs,
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Normalizes each of `nupkgs` in place, returning the new bytes.
+async fn normalize_all(nupkgs: &[PathBuf]) -> Result<Vec<(PathBuf, Vec<u8>)>, DotnetError> {
+    let mut normalized = Vec::new();
+    for nupkg in nupkgs {
+        let path = nupkg.clone();
+        let bytes = smol::unblock(move || -> Result<Vec<u8>, DotnetError> {
+            let bytes = normalize(&fs::read(&path)?)?;
+            let remaining = inspect(&bytes)?;
+            if !remaining.is_empty() {
+                return Err(DotnetError::NotNormalized(path, remaining));
+            }
+            let mut tmp = path.clone().into_os_string();
+            tmp.push(".tmp");
+            fs::write(&tmp, &bytes)?;
+            fs::rename(&tmp, &path)?;
+            Ok(bytes)
+        })
+        .await?;
+        tracing::info!("Normalized {}", nupkg.display());
+        normalized.push((nupkg.clone(), bytes));
+    }
+    Ok(normalized)
+}
+
+fn read_entries(nupkg: &[u8]) -> Result<Vec<Entry>, DotnetError> {
+    let mut zip = ZipArchive::new(Cursor::new(nupkg))?;
+    let mut entries = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if !file.is_file() {
+            continue;
+        }
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        let modified = file.last_modified();
+        entries.push(Entry {
+            name: file.name().into(),
+            contents,
+            modified: (modified.datepart(), modified.timepart()),
+        });
+    }
+    Ok(entries)
+}
+
+fn fixed_time() -> DateTime {
+    DateTime::default()
+}
+
+fn is_core_properties(name: &str) -> bool {
+    name.starts_with(CORE_PROPERTIES_DIR) && name.ends_with(".psmdcp")
+}
+
+fn core_properties_name(contents: &[u8]) -> String {
+    format!("{}{}.psmdcp", CORE_PROPERTIES_DIR, content_hash(contents))
+}
+
+/// Replaces each relationship's `Id` with one derived from its `Target`.
+fn normalize_relationship_ids(rels: &str) -> String {
+    let relationship = Regex::new(r"<Relationship\b[^>]*>").expect("TURRON BUG: oops, bad regex?");
+    let target = Regex::new(r#"\bTarget="([^"]*)""#).expect("TURRON BUG: oops, bad regex?");
+    let id = Regex::new(r#"\bId="[^"]*""#).expect("TURRON BUG: oops, bad regex?");
+    relationship
+        .replace_all(rels, |caps: &turron_common::regex::Captures| {
+            let element = &caps[0];
+            match target.captures(element) {
+                Some(target) => {
+                    let new_id = format!("Id=\"R{}\"", &content_hash(target[1].as_bytes())[..16]);
+                    id.replace(element, new_id.as_str()).into_owned()
+                }
+                None => element.into(),
+            }
+        })
+        .into_owned()
+}
+
+/// A stable 128-bit hex digest. Two FNV-1a passes with different offset
+/// bases: not cryptographic, but stable across platforms and releases,
+/// which is what matters for naming things.
+fn content_hash(bytes: &[u8]) -> String {
+    let fnv = |offset: u64| {
+        bytes.iter().fold(offset, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    };
+    format!(
+        "{:016x}{:016x}",
+        fnv(0xcbf2_9ce4_8422_2325),
+        fnv(0x6c62_272e_07bb_0142)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUSPEC: &str = "<?xml version=\"1.0\"?><package><metadata><id>Turron.Test</id><version>1.0.0</version></metadata></package>";
+    const PSMDCP: &str = "<coreProperties><identifier>Turron.Test</identifier><version>1.0.0</version></coreProperties>";
+
+    /// Packs a fixed set of inputs the way `dotnet pack` does: with a
+    /// timestamp, a GUID-named core properties part, random relationship
+    /// ids, and whatever entry order it likes.
+    fn pack_like_dotnet(
+        stamp: (u16, u8, u8, u8, u8, u8),
+        guid: &str,
+        rel_ids: (&str, &str),
+        reverse: bool,
+    ) -> Vec<u8> {
+        let psmdcp = format!("{}{}.psmdcp", CORE_PROPERTIES_DIR, guid);
+        let rels = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+                "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+                "<Relationship Type=\"http://schemas.microsoft.com/packaging/2010/07/manifest\" Target=\"/Turron.Test.nuspec\" Id=\"{}\" />",
+                "<Relationship Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" Target=\"/{}\" Id=\"{}\" />",
+                "</Relationships>"
+            ),
+            rel_ids.0, psmdcp, rel_ids.1
+        );
+        let mut files = vec![
+            (RELS.to_string(), rels.into_bytes()),
+            ("Turron.Test.nuspec".to_string(), NUSPEC.as_bytes().to_vec()),
+            (
+                "lib/netstandard2.0/Turron.Test.dll".to_string(),
+                b"MZ not really a dll".to_vec(),
+            ),
+            (psmdcp, PSMDCP.as_bytes().to_vec()),
+            ("[Content_Types].xml".to_string(), b"<Types />".to_vec()),
+        ];
+        if reverse {
+            files.reverse();
+        }
+        let (year, month, day, hour, minute, second) = stamp;
+        let options = FileOptions::default().last_modified_time(
+            DateTime::from_date_and_time(year, month, day, hour, minute, second).unwrap(),
+        );
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(&contents).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn first_pack() -> Vec<u8> {
+        pack_like_dotnet(
+            (2021, 9, 1, 12, 30, 10),
+            "a1b2c3d4e5f60718293a4b5c6d7e8f90",
+            ("R3f1c2d0a9b8e7f6a", "R0a1b2c3d4e5f6071"),
+            false,
+        )
+    }
+
+    fn second_pack() -> Vec<u8> {
+        pack_like_dotnet(
+            (2021, 9, 1, 12, 31, 44),
+            "0f9e8d7c6b5a49382716051a2b3c4d5e",
+            ("Rd4c3b2a1f0e9d8c7", "R7f6e5d4c3b2a1908"),
+            true,
+        )
+    }
+
+    #[test]
+    fn two_packs_normalize_to_identical_bytes() {
+        let (first, second) = (first_pack(), second_pack());
+        assert_ne!(first, second);
+        assert_eq!(normalize(&first).unwrap(), normalize(&second).unwrap());
+    }
+
+    #[test]
+    fn finds_every_source_of_nondeterminism() {
+        let found = inspect(&second_pack()).unwrap();
+        assert_eq!(
+            found
+                .iter()
+                .filter(|n| matches!(n, Nondeterminism::Timestamp { .. }))
+                .count(),
+            5
+        );
+        assert!(found.contains(&Nondeterminism::PartName {
+            entry: format!(
+                "{}0f9e8d7c6b5a49382716051a2b3c4d5e.psmdcp",
+                CORE_PROPERTIES_DIR
+            )
+        }));
+        assert!(found.contains(&Nondeterminism::RelationshipIds));
+        assert!(found.contains(&Nondeterminism::EntryOrder));
+    }
+
+    #[test]
+    fn normalized_packages_are_clean() {
+        let normalized = normalize(&first_pack()).unwrap();
+        assert_eq!(inspect(&normalized).unwrap(), Vec::new());
+        assert_eq!(normalize(&normalized).unwrap(), normalized);
+
+        // The renamed part is still what the relationships point at.
+        let entries = read_entries(&normalized).unwrap();
+        let part = entries
+            .iter()
+            .find(|entry| is_core_properties(&entry.name))
+            .unwrap();
+        let rels = entries.iter().find(|entry| entry.name == RELS).unwrap();
+        let rels = String::from_utf8(rels.contents.clone()).unwrap();
+        assert!(rels.contains(&format!("Target=\"/{}\"", part.name)));
+        assert!(!rels.contains("R3f1c2d0a9b8e7f6a"));
+    }
+
+    #[test]
+    fn diffs_report_exact_entries() {
+        let first = pack_like_dotnet(
+            (2021, 9, 1, 12, 30, 10),
+            "a1b2c3d4e5f60718293a4b5c6d7e8f90",
+            ("R1", "R2"),
+            false,
+        );
+        assert_eq!(
+            diff(&first_pack(), &second_pack()).unwrap(),
+            vec![
+                EntryDiff {
+                    entry: RELS.into(),
+                    kind: DiffKind::Contents,
+                },
+                EntryDiff {
+                    entry: "Turron.Test.nuspec".into(),
+                    kind: DiffKind::Timestamp,
+                },
+                EntryDiff {
+                    entry: "lib/netstandard2.0/Turron.Test.dll".into(),
+                    kind: DiffKind::Timestamp,
+                },
+                EntryDiff {
+                    entry: format!(
+                        "{}a1b2c3d4e5f60718293a4b5c6d7e8f90.psmdcp",
+                        CORE_PROPERTIES_DIR
+                    ),
+                    kind: DiffKind::OnlyInFirst,
+                },
+                EntryDiff {
+                    entry: "[Content_Types].xml".into(),
+                    kind: DiffKind::Timestamp,
+                },
+                EntryDiff {
+                    entry: format!(
+                        "{}0f9e8d7c6b5a49382716051a2b3c4d5e.psmdcp",
+                        CORE_PROPERTIES_DIR
+                    ),
+                    kind: DiffKind::OnlyInSecond,
+                },
+            ]
+        );
+        assert_eq!(
+            diff(&first_pack(), &first).unwrap(),
+            vec![EntryDiff {
+                entry: RELS.into(),
+                kind: DiffKind::Contents,
+            }]
+        );
+        assert_eq!(diff(&first, &first).unwrap(), Vec::new());
+    }
+}
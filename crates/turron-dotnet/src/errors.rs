@@ -1,5 +1,12 @@
+use std::path::PathBuf;
+
+use dotnet_semver::{SemverError, Version};
+
+use crate::deterministic::{EntryDiff, Nondeterminism};
 use turron_common::{
+    explain::Explanation,
     miette::{self, Diagnostic, LabeledSpan, NamedSource, Severity, SourceSpan},
+    quick_xml,
     thiserror::{self, Error},
 };
 
@@ -12,6 +19,13 @@ pub enum DotnetError {
     )]
     DotnetNotFound(#[from] which::Error),
 
+    #[error("dotnet SDK {found} is too old. Turron needs {required} or newer.")]
+    #[diagnostic(
+        code(turron::dotnet::sdk_too_old),
+        help("Install a newer .NET SDK from https://dotnet.microsoft.com/download. If a global.json pins an older SDK, update its `sdk.version`.")
+    )]
+    SdkTooOld { found: String, required: String },
+
     #[error("Failed to execute dotnet CLI.")]
     #[diagnostic(code(turron::dotnet::cli_failed))]
     DotnetFailed(#[from] std::io::Error),
@@ -19,8 +33,303 @@ pub enum DotnetError {
     #[error("Pack failed.")]
     #[diagnostic(code(turron::dotnet::pack_failed))]
     PackFailed(#[related] Vec<MsBuildError>),
+
+    #[error("Failed to get a version from `git describe --tags`: {0}")]
+    #[diagnostic(
+        code(turron::dotnet::git_describe_failed),
+        help("--version-from-git requires a git repository with at least one tag reachable from HEAD.")
+    )]
+    GitDescribeFailed(String),
+
+    #[error("Git tag `{0}` is not a valid package version.")]
+    #[diagnostic(
+        code(turron::dotnet::invalid_git_tag),
+        help("Tags should look like `v1.2.3` or `1.2.3-beta.1`.")
+    )]
+    InvalidGitTag(String, #[source] SemverError),
+
+    #[error("Packed {} with version {}, but {} was requested.", .path.display(), .found, .expected)]
+    #[diagnostic(
+        code(turron::dotnet::version_mismatch),
+        help("Something in your project is overriding PackageVersion. Check your Directory.Build.props and .csproj files.")
+    )]
+    VersionMismatch {
+        path: PathBuf,
+        expected: Version,
+        found: Version,
+    },
+
+    #[error("`dotnet pack` reported creating {}, but it isn't there.", .0.display())]
+    #[diagnostic(code(turron::dotnet::package_missing))]
+    PackageMissing(PathBuf),
+
+    #[error("Failed to read project file {}.", .0.display())]
+    #[diagnostic(code(turron::dotnet::bad_project))]
+    BadProject(PathBuf, #[source] quick_xml::Error),
+
+    #[error("No packable projects found under {}.", .0.display())]
+    #[diagnostic(
+        code(turron::dotnet::no_projects),
+        help("`--all` looks for .csproj files that don't set IsPackable to false, skipping anything git ignores and anything matching --exclude.")
+    )]
+    NoProjects(PathBuf),
+
+    #[error("No project file found in {} or any directory above it.", .0.display())]
+    #[diagnostic(
+        code(turron::dotnet::project_not_found),
+        help("Run this from inside a project's directory, or pass --root.")
+    )]
+    ProjectNotFound(PathBuf),
+
+    #[error("Found more than one project file in {}:{}", .0.display(), list_paths(.1))]
+    #[diagnostic(
+        code(turron::dotnet::ambiguous_project),
+        help("Pass --root with the path of the project file to use.")
+    )]
+    AmbiguousProject(PathBuf, Vec<PathBuf>),
+
+    #[error("No solution file found in {} or any directory above it.", .0.display())]
+    #[diagnostic(
+        code(turron::dotnet::solution_not_found),
+        help("--all-projects works through the projects in a .sln. Run this from inside the solution's directory, or pass --root.")
+    )]
+    SolutionNotFound(PathBuf),
+
+    #[error("{id} is referenced in {} without a version, so its version is set somewhere else.", .project.display())]
+    #[diagnostic(
+        code(turron::dotnet::versionless_reference),
+        help("This usually means central package management sets it in Directory.Packages.props. Change the version there instead.")
+    )]
+    VersionlessReference { project: PathBuf, id: String },
+
+    #[error("{} manages package versions centrally, in {}.", .project.display(), .props.display())]
+    #[diagnostic(
+        code(turron::dotnet::centrally_managed),
+        help("Add or update the package's `<PackageVersion>` in Directory.Packages.props instead. A `Version` on the project's `<PackageReference>` would fail the build with NU1008.")
+    )]
+    CentrallyManaged { project: PathBuf, props: PathBuf },
+
+    #[error("Found more than one solution file in {}:{}", .0.display(), list_paths(.1))]
+    #[diagnostic(
+        code(turron::dotnet::ambiguous_solution),
+        help("Pass --root with the path of the solution file to use.")
+    )]
+    AmbiguousSolution(PathBuf, Vec<PathBuf>),
+
+    #[error("Failed to read solution file {}.", .0.display())]
+    #[diagnostic(code(turron::dotnet::bad_solution))]
+    BadSolution(PathBuf, #[source] std::io::Error),
+
+    #[error("Could not find a .nuspec in {}.", .0.display())]
+    #[diagnostic(code(turron::dotnet::nuspec_not_found))]
+    NuSpecNotFound(PathBuf),
+
+    #[error("Failed to parse the .nuspec in {}.", .0.display())]
+    #[diagnostic(code(turron::dotnet::bad_nuspec))]
+    BadNuSpec(PathBuf, #[source] quick_xml::DeError),
+
+    #[error(transparent)]
+    #[diagnostic(code(turron::dotnet::zip_error))]
+    ZipError(#[from] zip::result::ZipError),
+
+    #[error("Packing twice gave different results for {}:{}", .path.display(), list(.diffs))]
+    #[diagnostic(
+        code(turron::dotnet::not_reproducible),
+        help("Something in the build embeds per-build data, like a timestamp, an absolute path, or a random id. Look at how the listed entries are produced.")
+    )]
+    NotReproducible {
+        path: PathBuf,
+        diffs: Vec<EntryDiff>,
+    },
+
+    #[error("{} still isn't deterministic after normalizing:{}", .0.display(), list(.1))]
+    #[diagnostic(code(turron::dotnet::not_normalized))]
+    NotNormalized(PathBuf, Vec<Nondeterminism>),
 }
 
+fn list_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| format!("\n\t{}", path.display()))
+        .collect()
+}
+
+fn list<T: std::fmt::Display>(items: &[T]) -> String {
+    if items.is_empty() {
+        " the second pack didn't produce it.".into()
+    } else {
+        items.iter().map(|item| format!("\n\t{}", item)).collect()
+    }
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::dotnet::cli_not_found",
+        cause: "Packing goes through the `dotnet` CLI, and no `dotnet` executable was found on $PATH.",
+        fixes: &[
+            "Install the .NET SDK from https://dotnet.microsoft.com/download.",
+            "If it's already installed, make sure its directory is on $PATH for the shell running turron.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::sdk_too_old",
+        cause: "`dotnet --version` reported an SDK older than the oldest one turron supports. Older SDKs don't understand some of the arguments turron passes to `dotnet pack`.",
+        fixes: &[
+            "Install a current .NET SDK from https://dotnet.microsoft.com/download.",
+            "If a global.json in this directory or a parent pins the SDK, raise its `sdk.version` or add `\"rollForward\": \"latestMajor\"`.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::cli_failed",
+        cause: "`dotnet` was found but could not be started, or its output could not be read.",
+        fixes: &["Try running `dotnet --info` directly to see whether the SDK itself works."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::pack_failed",
+        cause: "`dotnet pack` ran but reported build errors. Each MSBuild error is shown below the main one, pointing at the offending file.",
+        fixes: &["Fix the build errors listed, then pack again."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::git_describe_failed",
+        cause: "`--version-from-git` runs `git describe --tags`, which failed. Usually the directory isn't a git repository or no tag is reachable from HEAD.",
+        fixes: &[
+            "Create a tag, e.g. `git tag v1.0.0`.",
+            "In shallow CI clones, fetch tags with `git fetch --tags --unshallow`.",
+        ],
+        config: &["commands.pack.version_from_git"],
+    },
+    Explanation {
+        code: "turron::dotnet::invalid_git_tag",
+        cause: "The tag found by `git describe --tags` could not be turned into a package version.",
+        fixes: &["Name release tags like `v1.2.3` or `1.2.3-beta.1`."],
+        config: &["commands.pack.version_from_git"],
+    },
+    Explanation {
+        code: "turron::dotnet::version_mismatch",
+        cause: "turron asked `dotnet pack` for one version, but the produced package has another. Something in the project overrides PackageVersion after the command line sets it.",
+        fixes: &[
+            "Look for PackageVersion or Version in Directory.Build.props and the .csproj.",
+            "Make the property conditional, e.g. `Condition=\"'$(PackageVersion)' == ''\"`.",
+        ],
+        config: &["commands.pack.set_version"],
+    },
+    Explanation {
+        code: "turron::dotnet::package_missing",
+        cause: "`dotnet pack` printed that it created a package, but no file exists at that path once it finished. Something deleted or moved it in the meantime.",
+        fixes: &["Look for build targets that run after packing and move or clean up .nupkg files."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::bad_project",
+        cause: "`--all` reads each .csproj it finds to check IsPackable and project references, and `turron add` reads the project it's adding a reference to. One of them couldn't be read or isn't well-formed XML.",
+        fixes: &[
+            "Check that the project builds with `dotnet build`.",
+            "Leave the project out with `--exclude <glob>`.",
+        ],
+        config: &["commands.pack.exclude", "commands.publish.exclude"],
+    },
+    Explanation {
+        code: "turron::dotnet::no_projects",
+        cause: "`--all` walked the directory tree looking for packable projects and found none. Projects that set `<IsPackable>false</IsPackable>`, live in git-ignored paths, or match `--exclude` are skipped.",
+        fixes: &[
+            "Pass `--root` to point at the repository you meant.",
+            "Loosen any `--exclude` patterns.",
+        ],
+        config: &["commands.pack.exclude", "commands.publish.exclude"],
+    },
+    Explanation {
+        code: "turron::dotnet::project_not_found",
+        cause: "`turron add` and `turron remove` look for a .csproj, .fsproj, or .vbproj in the current directory (or --root), then in each directory above it, and didn't find one.",
+        fixes: &[
+            "Run turron from inside the project's directory.",
+            "Pass `--root` with the project file or its directory.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::ambiguous_project",
+        cause: "The closest directory with a project file has more than one, and turron won't guess which one to edit.",
+        fixes: &["Pass `--root` with the path of the project file itself."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::versionless_reference",
+        cause: "The project's `<PackageReference>` for this package has no `Version`, so something else, usually Directory.Packages.props under central package management, decides which version gets used. Giving the reference a `Version` of its own would break that.",
+        fixes: &[
+            "Change the package's `<PackageVersion>` in Directory.Packages.props.",
+            "To pin just this project, add a `VersionOverride` to its `<PackageReference>` by hand.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::centrally_managed",
+        cause: "The project turns on central package management with `ManagePackageVersionsCentrally`, so its `<PackageReference>`s can't have versions (NuGet fails the restore with NU1008). Versions go in `<PackageVersion>` items in Directory.Packages.props, which `turron add` doesn't edit.",
+        fixes: &[
+            "Add `<PackageVersion Include=\"<id>\" Version=\"<version>\" />` to Directory.Packages.props, and a `<PackageReference Include=\"<id>\" />` to the project.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::solution_not_found",
+        cause: "`--all-projects` edits every project listed in a .sln, and no .sln was found in the current directory (or --root), or in any directory above it.",
+        fixes: &[
+            "Run turron from inside the solution's directory.",
+            "Pass `--root` with the solution file or its directory.",
+            "Drop `--all-projects` to only edit the closest project.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::ambiguous_solution",
+        cause: "The closest directory with a solution file has more than one, and turron won't guess which one to use.",
+        fixes: &["Pass `--root` with the path of the solution file itself."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::bad_solution",
+        cause: "The solution file couldn't be read.",
+        fixes: &["Check that the file exists and that you have permission to read it."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::nuspec_not_found",
+        cause: "A package produced by `dotnet pack` didn't contain a .nuspec at its root, so turron can't tell what it contains.",
+        fixes: &["Check for custom packaging targets that replace the default .nupkg layout."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::bad_nuspec",
+        cause: "The .nuspec inside a produced package is not valid XML, or doesn't follow the nuspec schema.",
+        fixes: &["Check any hand-written .nuspec template used by the project."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::zip_error",
+        cause: "A produced .nupkg could not be opened as a zip archive.",
+        fixes: &["Delete the output directory and pack again."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::dotnet::not_reproducible",
+        cause: "`turron pack --deterministic --pack-twice` packed the project twice and normalized both results, but they still differ. Whatever is left comes from the build itself rather than from how the .nupkg was zipped.",
+        fixes: &[
+            "Set `<Deterministic>true</Deterministic>` and `<ContinuousIntegrationBuild>true</ContinuousIntegrationBuild>` so compiled assemblies don't embed paths or timestamps.",
+            "Look for generated files (version info, build dates, git SHAs) among the listed entries.",
+        ],
+        config: &["commands.pack.deterministic", "commands.pack.pack_twice"],
+    },
+    Explanation {
+        code: "turron::dotnet::not_normalized",
+        cause: "turron rewrote a package to strip timestamps, random part names and entry order, but checking it afterwards still found some. This is a turron bug.",
+        fixes: &["Please report it, along with the .nupkg if you can share it."],
+        config: &["commands.pack.deterministic"],
+    },
+];
+
 #[derive(Error, Debug)]
 #[error("{message}")]
 pub struct MsBuildError {
@@ -0,0 +1,103 @@
+use dotnet_semver::{Identifier, Version};
+use turron_common::{
+    regex::Regex,
+    smol::{self, process::Command},
+};
+
+use crate::errors::DotnetError;
+
+/// Runs `git describe --tags` in the current directory and converts its
+/// output into a package version. See [`version_from_git_describe`].
+pub async fn version_from_git() -> Result<Version, DotnetError> {
+    let git = smol::unblock(|| which::which("git"))
+        .await
+        .map_err(|_| DotnetError::GitDescribeFailed("git was not found in $PATH".into()))?;
+    let output = Command::new(git)
+        .arg("describe")
+        .arg("--tags")
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(DotnetError::GitDescribeFailed(
+            String::from_utf8_lossy(&output.stderr).trim().into(),
+        ));
+    }
+    version_from_git_describe(String::from_utf8_lossy(&output.stdout).trim())
+}
+
+/// Converts `git describe --tags` output into a package version.
+///
+/// A leading `v` is stripped from the tag, and the rest of the tag must be a
+/// valid version:
+///
+/// * `v1.2.3` (HEAD is tagged) becomes `1.2.3`.
+/// * `v1.2.3-4-gabcdef` (4 commits past the tag) becomes
+///   `1.2.3-dev.4+abcdef`.
+/// * `v1.2.3-beta.1-4-gabcdef` (a prerelease tag) becomes
+///   `1.2.3-beta.1.dev.4+abcdef`.
+///
+/// Any build metadata on the tag itself is replaced by the commit hash.
+pub fn version_from_git_describe(describe: &str) -> Result<Version, DotnetError> {
+    let regex = Regex::new(r"^(?P<tag>.+)-(?P<count>\d+)-g(?P<hash>[0-9a-fA-F]+)$")
+        .expect("TURRON BUG: oops, bad regex?");
+    let describe = describe.trim();
+    let (tag, dev) = if let Some(captures) = regex.captures(describe) {
+        let count = captures["count"]
+            .parse::<u64>()
+            .map_err(|_| DotnetError::GitDescribeFailed(describe.into()))?;
+        (
+            captures.name("tag").unwrap().as_str(),
+            Some((count, captures["hash"].to_lowercase())),
+        )
+    } else {
+        (describe, None)
+    };
+    let tag = tag.strip_prefix('v').unwrap_or(tag);
+    let mut version = Version::parse(tag).map_err(|e| DotnetError::InvalidGitTag(tag.into(), e))?;
+    if let Some((count, hash)) = dev {
+        version
+            .pre_release
+            .push(Identifier::AlphaNumeric("dev".into()));
+        version.pre_release.push(Identifier::Numeric(count));
+        version.build = vec![Identifier::AlphaNumeric(hash)];
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(input: &str) -> String {
+        version_from_git_describe(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn exact_tag() {
+        assert_eq!(describe("v1.2.3"), "1.2.3");
+        assert_eq!(describe("1.2.3"), "1.2.3");
+        assert_eq!(describe("v1.2.3-beta.1"), "1.2.3-beta.1");
+    }
+
+    #[test]
+    fn commits_past_tag() {
+        assert_eq!(describe("v1.2.3-4-gabcdef"), "1.2.3-dev.4+abcdef");
+        assert_eq!(describe("1.2.3-12-gABCDEF0\n"), "1.2.3-dev.12+abcdef0");
+    }
+
+    #[test]
+    fn commits_past_prerelease_tag() {
+        assert_eq!(
+            describe("v1.2.3-beta.1-4-gabcdef"),
+            "1.2.3-beta.1.dev.4+abcdef"
+        );
+    }
+
+    #[test]
+    fn bad_tag() {
+        assert!(matches!(
+            version_from_git_describe("release-4-gabcdef"),
+            Err(DotnetError::InvalidGitTag(..))
+        ));
+    }
+}
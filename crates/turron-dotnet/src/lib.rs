@@ -1,3 +1,9 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use dotnet_semver::Version;
 use turron_common::{
     miette::{NamedSource, Severity, SourceOffset},
     regex::Regex,
@@ -5,55 +11,362 @@ use turron_common::{
     tracing,
 };
 
-pub use errors::{DotnetError, MsBuildError};
+pub use deterministic::{
+    diff, inspect, normalize, pack_deterministic, DiffKind, EntryDiff, Nondeterminism,
+};
+pub use errors::{DotnetError, MsBuildError, EXPLANATIONS};
+pub use git::{version_from_git, version_from_git_describe};
+pub use references::{
+    package_references, remove_package_reference, set_package_reference, set_reference_metadata,
+    PackageReference, ReferenceChange, RemovedReference,
+};
+pub use sdk::{dotnet_cli, MIN_SDK_VERSION};
+pub use workspace::{
+    built_nupkg, central_package_versions, discover, find_project, find_solution, pack_order,
+    project_files, solution_projects, Project,
+};
 
+mod deterministic;
 mod errors;
+mod git;
+mod nuspec;
+mod references;
+mod sdk;
+mod workspace;
+
+/// What a successful `dotnet pack` produced.
+#[derive(Debug)]
+pub struct PackReport {
+    /// Paths to the .nupkg files that were created.
+    pub nupkgs: Vec<PathBuf>,
+    /// Package version, as read back from the packed .nuspec.
+    pub version: Option<Version>,
+    /// Warnings MSBuild reported along the way.
+    pub warnings: Vec<MsBuildError>,
+}
+
+/// What to pass along to `dotnet pack`.
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+    /// Project or solution to pack. `dotnet pack` looks in the current
+    /// directory if this isn't given.
+    pub project: Option<PathBuf>,
+    /// Build configuration, like `Release`.
+    pub configuration: Option<String>,
+    /// Directory to put the packages in.
+    pub output: Option<PathBuf>,
+    /// Passed along as `PackageVersion`. The produced packages are checked to
+    /// make sure it actually took.
+    pub version: Option<Version>,
+    /// Any other MSBuild properties to set, as `-p:Name=Value`.
+    pub extra_props: Vec<(String, String)>,
+}
+
+impl PackOptions {
+    fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["pack".into(), "--nologo".into()];
+        if let Some(project) = &self.project {
+            args.push(project.into());
+        }
+        if let Some(configuration) = &self.configuration {
+            args.push("-c".into());
+            args.push(configuration.into());
+        }
+        if let Some(output) = &self.output {
+            args.push("-o".into());
+            args.push(output.into());
+        }
+        if let Some(version) = &self.version {
+            args.push(format!("-p:PackageVersion={}", version).into());
+        }
+        for (name, value) in &self.extra_props {
+            args.push(format!("-p:{}={}", name, value).into());
+        }
+        args
+    }
+
+    /// Where to look for packages when `dotnet pack` doesn't say what it
+    /// made: the output directory if one was given, or else everything under
+    /// the project.
+    fn search_pattern(&self) -> String {
+        if let Some(output) = &self.output {
+            return format!(
+                "{}/*.nupkg",
+                glob::Pattern::escape(&output.to_string_lossy())
+            );
+        }
+        let dir = match &self.project {
+            Some(project) if project.is_file() => {
+                project.parent().map(Path::to_path_buf).unwrap_or_default()
+            }
+            Some(project) => project.clone(),
+            None => PathBuf::new(),
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            ".".into()
+        } else {
+            dir.to_string_lossy().into_owned()
+        };
+        format!("{}/**/*.nupkg", glob::Pattern::escape(&dir))
+    }
+}
 
-pub async fn pack() -> Result<(), DotnetError> {
-    let cli_path = smol::unblock(|| which::which("dotnet")).await?;
-    let output = Command::new(cli_path)
-        .arg("pack")
-        .arg("--nologo")
-        .output()
-        .await?;
+/// .nupkg files matching `pattern` that were written after `since`. Some
+/// filesystems only keep mtimes to the second or two, so there's a little
+/// slack.
+fn nupkgs_since(pattern: &str, since: SystemTime) -> Vec<PathBuf> {
+    let since = since - Duration::from_secs(2);
+    let mut found = glob::glob(pattern)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|path| {
+            path.metadata()
+                .and_then(|meta| meta.modified())
+                .map(|modified| modified >= since)
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    found.sort();
+    found
+}
+
+/// Runs `dotnet pack` with `opts`.
+pub async fn pack(opts: &PackOptions) -> Result<PackReport, DotnetError> {
+    let cli_path = dotnet_cli().await?;
+    let mut cmd = Command::new(cli_path);
+    cmd.args(opts.args());
+    let started = SystemTime::now();
+    let output = cmd.output().await?;
     // TODO: handle bad utf8 errors
     let stdout = String::from_utf8(output.stdout).unwrap_or_else(|_| "".into());
+    let (mut nupkgs, diagnostics) = parse_output(&stdout);
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for diagnostic in diagnostics {
+        // Some diagnostics come from tools rather than files (`CSC : warning
+        // ...`), so there isn't always anything to read.
+        let contents = fs::read_to_string(&diagnostic.file)
+            .await
+            .unwrap_or_default();
+        let err_offset = SourceOffset::from_location(&contents, diagnostic.line, diagnostic.column);
+        let severity = match &diagnostic.severity[..] {
+            "warning" => Severity::Warning,
+            "info" => Severity::Advice,
+            _ => Severity::Error,
+        };
+        let err = MsBuildError {
+            file: NamedSource::new(diagnostic.file, contents),
+            span: (err_offset, 0.into()).into(),
+            code: diagnostic.code,
+            message: diagnostic.message,
+            severity,
+        };
+        match severity {
+            Severity::Error => errors.push(err),
+            _ => warnings.push(err),
+        }
+    }
+    if !output.status.success() {
+        errors.extend(warnings);
+        return Err(DotnetError::PackFailed(errors));
+    }
+    for nupkg in &nupkgs {
+        if fs::metadata(nupkg).await.is_err() {
+            return Err(DotnetError::PackageMissing(nupkg.clone()));
+        }
+    }
+    if nupkgs.is_empty() {
+        // Localized SDKs don't say "Successfully created package", so fall
+        // back to looking for whatever showed up while we were packing.
+        let pattern = opts.search_pattern();
+        nupkgs = smol::unblock(move || nupkgs_since(&pattern, started)).await;
+        for nupkg in &nupkgs {
+            tracing::info!("Found {} after packing", nupkg.display());
+        }
+    }
+
+    let mut packed_version = None;
+    for nupkg in &nupkgs {
+        let path = nupkg.clone();
+        let found = smol::unblock(move || nuspec::nupkg_version(&path)).await?;
+        if let Some(expected) = &opts.version {
+            if &found != expected {
+                return Err(DotnetError::VersionMismatch {
+                    path: nupkg.clone(),
+                    expected: expected.clone(),
+                    found,
+                });
+            }
+        }
+        packed_version.get_or_insert(found);
+    }
+    Ok(PackReport {
+        nupkgs,
+        version: packed_version,
+        warnings,
+    })
+}
+
+/// One MSBuild diagnostic line, before its file has been read.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RawDiagnostic {
+    file: String,
+    line: usize,
+    column: usize,
+    severity: String,
+    code: String,
+    message: String,
+}
+
+/// Picks the created packages and diagnostics out of `dotnet pack` output,
+/// logging everything else. MSBuild repeats diagnostics once per target
+/// framework, tagged with the project and framework, so those are
+/// collapsed into one.
+fn parse_output(stdout: &str) -> (Vec<PathBuf>, Vec<RawDiagnostic>) {
     let regex = Regex::new(
             r"^\s*(?P<file>.*?)(\((?P<line>\d+),(?P<column>\d+)\))?\s*:\s+(?P<severity>.*?)\s+(?P<code>.*):\s+(?P<message>.*)$",
         ).expect("TURRON BUG: oops, bad regex?");
-    let mut errors = Vec::new();
+    let created_regex = Regex::new(r"Successfully created package '(?P<path>.*\.nupkg)'")
+        .expect("TURRON BUG: oops, bad regex?");
+    let project_regex = Regex::new(r"\s+\[[^\[\]]*\]$").expect("TURRON BUG: oops, bad regex?");
+    let mut nupkgs = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut seen = HashSet::new();
 
     for line in stdout.lines() {
-        if let Some(captures) = regex.captures(line) {
-            let filename: String = captures.name("file").unwrap().as_str().trim().into();
-            let contents = fs::read_to_string(&filename).await?;
-            let line = captures
-                .name("line")
-                .map(|x| x.as_str().parse::<usize>().unwrap())
-                .unwrap_or(0);
-            let column = captures
-                .name("column")
-                .map(|x| x.as_str().parse::<usize>().unwrap())
-                .unwrap_or(0);
-            let err_offset = SourceOffset::from_location(&contents, line, column);
-            errors.push(MsBuildError {
-                file: NamedSource::new(filename, contents),
-                span: (err_offset, 0.into()).into(),
+        if let Some(captures) = created_regex.captures(line) {
+            nupkgs.push(PathBuf::from(&captures["path"]));
+            tracing::info!("{}", line);
+        } else if let Some(captures) = regex.captures(line) {
+            let position = |name| {
+                captures
+                    .name(name)
+                    .map(|x| x.as_str().parse::<usize>().unwrap())
+                    .unwrap_or(0)
+            };
+            let message = captures.name("message").unwrap().as_str().trim();
+            let diagnostic = RawDiagnostic {
+                file: captures.name("file").unwrap().as_str().trim().into(),
+                line: position("line"),
+                column: position("column"),
+                severity: captures.name("severity").unwrap().as_str().trim().into(),
                 code: captures.name("code").unwrap().as_str().trim().into(),
-                message: captures.name("message").unwrap().as_str().trim().into(),
-                severity: match captures.name("severity").unwrap().as_str().trim() {
-                    "warning" => Severity::Warning,
-                    "info" => Severity::Advice,
-                    _ => Severity::Error,
-                },
-            });
+                message: project_regex.replace(message, "").into_owned(),
+            };
+            if seen.insert(diagnostic.clone()) {
+                diagnostics.push(diagnostic);
+            }
         } else {
             tracing::info!("{}", line);
         }
     }
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(DotnetError::PackFailed(errors))
+    (nupkgs, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_packages_and_deduped_diagnostics() {
+        let stdout = concat!(
+            "  Determining projects to restore...\n",
+            "/src/Foo/Foo.cs(10,5): warning CS0168: The variable 'e' is declared but never used [/src/Foo/Foo.csproj::TargetFramework=net5.0]\n",
+            "/src/Foo/Foo.cs(10,5): warning CS0168: The variable 'e' is declared but never used [/src/Foo/Foo.csproj::TargetFramework=netstandard2.0]\n",
+            "CSC : warning CS8032: An instance of analyzer Foo cannot be created [/src/Foo/Foo.csproj]\n",
+            "/src/Foo/Foo.cs(12,1): error CS1002: ; expected [/src/Foo/Foo.csproj::TargetFramework=net5.0]\n",
+            "  Successfully created package '/src/Foo/bin/Debug/Foo.1.0.0.nupkg'.\n",
+        );
+        let (nupkgs, diagnostics) = parse_output(stdout);
+        assert_eq!(
+            nupkgs,
+            vec![PathBuf::from("/src/Foo/bin/Debug/Foo.1.0.0.nupkg")]
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                RawDiagnostic {
+                    file: "/src/Foo/Foo.cs".into(),
+                    line: 10,
+                    column: 5,
+                    severity: "warning".into(),
+                    code: "CS0168".into(),
+                    message: "The variable 'e' is declared but never used".into(),
+                },
+                RawDiagnostic {
+                    file: "CSC".into(),
+                    line: 0,
+                    column: 0,
+                    severity: "warning".into(),
+                    code: "CS8032".into(),
+                    message: "An instance of analyzer Foo cannot be created".into(),
+                },
+                RawDiagnostic {
+                    file: "/src/Foo/Foo.cs".into(),
+                    line: 12,
+                    column: 1,
+                    severity: "error".into(),
+                    code: "CS1002".into(),
+                    message: "; expected".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pack_args() {
+        assert_eq!(PackOptions::default().args(), vec!["pack", "--nologo"]);
+        let opts = PackOptions {
+            project: Some("src/Foo/Foo.csproj".into()),
+            configuration: Some("Release".into()),
+            output: Some("./artifacts".into()),
+            version: Some("1.2.3-beta.1".parse().unwrap()),
+            extra_props: vec![("ContinuousIntegrationBuild".into(), "true".into())],
+        };
+        assert_eq!(
+            opts.args(),
+            vec![
+                "pack",
+                "--nologo",
+                "src/Foo/Foo.csproj",
+                "-c",
+                "Release",
+                "-o",
+                "./artifacts",
+                "-p:PackageVersion=1.2.3-beta.1",
+                "-p:ContinuousIntegrationBuild=true",
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_packages_written_since_pack_started() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("bin").join("Release");
+        std::fs::create_dir_all(&nested).unwrap();
+        let nupkg = nested.join("Foo.1.0.0.nupkg");
+        std::fs::write(&nupkg, b"").unwrap();
+        std::fs::write(nested.join("Foo.1.0.0.snupkg"), b"").unwrap();
+
+        let opts = PackOptions {
+            project: Some(dir.path().into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            nupkgs_since(&opts.search_pattern(), SystemTime::now()),
+            vec![nupkg]
+        );
+        let later = SystemTime::now() + Duration::from_secs(60);
+        assert!(nupkgs_since(&opts.search_pattern(), later).is_empty());
+
+        let opts = PackOptions {
+            output: Some(nested.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            opts.search_pattern(),
+            format!("{}/*.nupkg", nested.display())
+        );
     }
 }
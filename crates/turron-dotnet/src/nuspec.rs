@@ -0,0 +1,38 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use dotnet_semver::Version;
+use turron_common::{quick_xml, serde::Deserialize};
+use zip::ZipArchive;
+
+use crate::errors::DotnetError;
+
+#[derive(Deserialize)]
+#[serde(rename = "package")]
+struct NuSpec {
+    metadata: NuSpecMetadata,
+}
+
+#[derive(Deserialize)]
+struct NuSpecMetadata {
+    #[serde(rename = "$unflatten=version")]
+    version: Version,
+}
+
+/// Reads the package version out of the .nuspec inside a .nupkg.
+pub(crate) fn nupkg_version(nupkg: &Path) -> Result<Version, DotnetError> {
+    let mut zip = ZipArchive::new(File::open(nupkg)?)?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        // The nuspec always lives at the root of the package.
+        if file.is_file() && !file.name().contains('/') && file.name().ends_with(".nuspec") {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            let nuspec: NuSpec = quick_xml::de::from_str(&contents)
+                .map_err(|e| DotnetError::BadNuSpec(nupkg.into(), e))?;
+            return Ok(nuspec.metadata.version);
+        }
+    }
+    Err(DotnetError::NuSpecNotFound(nupkg.into()))
+}
@@ -0,0 +1,815 @@
+//! Adding, updating, and removing `<PackageReference>`s in project files.
+//!
+//! Project files are edited as text, using the XML parser only to find where
+//! things are, so comments, indentation, attribute order, and line endings
+//! all survive. Conditions and imported props are ignored, like in
+//! [`Project::parse`](crate::Project::parse).
+
+use std::ops::Range;
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+use turron_common::{
+    quick_xml::{events::Event, Reader},
+    regex::{self, Regex},
+};
+
+use crate::errors::DotnetError;
+
+static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\b(Include|Version)\s*=\s*("[^"]*"|'[^']*')"#)
+        .expect("TURRON BUG: bad attribute regex")
+});
+
+/// What [`set_package_reference`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferenceChange {
+    /// There was no reference to the package, so one was added.
+    Added,
+    /// The package was already referenced, at `from`.
+    Updated { from: String },
+    /// The package was already referenced at exactly this version.
+    Unchanged,
+}
+
+/// A `<PackageReference>`, as written in the project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageReference {
+    pub id: String,
+    /// The version or range, from either a `Version` attribute or a
+    /// `<Version>` child. Centrally managed versions don't have one.
+    pub version: Option<String>,
+}
+
+/// A `<PackageReference>` that [`remove_package_reference`] took out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemovedReference {
+    /// The package id, spelled the way the project had it.
+    pub id: String,
+    pub version: Option<String>,
+}
+
+/// A `<PackageReference>` that's already in the project.
+#[derive(Debug)]
+struct Existing {
+    /// The `Include` attribute's value, trimmed.
+    id: String,
+    /// The whole element, from `<PackageReference` to the end of its end tag.
+    span: Range<usize>,
+    /// Where the start tag ends.
+    tag_end: usize,
+    /// Where the `Include` attribute's value ends, quote and all.
+    include_end: usize,
+    /// The `Version` attribute's value, or the text of a `<Version>` child,
+    /// without quotes.
+    version: Option<Range<usize>>,
+    /// The `<ItemGroup>` the reference is in, if it has nothing else in it.
+    lone_group: Option<Range<usize>>,
+}
+
+/// Where things are in a project file.
+#[derive(Debug, Default)]
+struct Scan {
+    /// The first reference to the package being looked for.
+    existing: Option<Existing>,
+    /// Every package reference, in order.
+    references: Vec<PackageReference>,
+    last_reference_end: Option<usize>,
+    last_reference_indent: Option<String>,
+    /// The indentation of the `<Project>`'s first child.
+    child_indent: Option<String>,
+    /// Where `</Project>` starts.
+    project_end: Option<usize>,
+}
+
+/// Points the project in `contents` at `version` of `id`, updating its
+/// existing reference to the package if there is one, or adding a new one
+/// after the other package references otherwise. `path` is only used in
+/// errors. References without a version are left alone, and are an error,
+/// since their version comes from somewhere else, like central package
+/// management.
+///
+/// Returns the new contents of the project, and what changed.
+pub fn set_package_reference(
+    path: &Path,
+    contents: &str,
+    id: &str,
+    version: &str,
+) -> Result<(String, ReferenceChange), DotnetError> {
+    let scan = scan(path, contents, Some(id))?;
+    let nl = if contents.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let escaped = escape(version);
+    let mut edited = contents.to_string();
+    let change = if let Some(existing) = scan.existing {
+        match existing.version {
+            Some(span) if contents[span.clone()].trim() == version => ReferenceChange::Unchanged,
+            Some(span) => {
+                let from = contents[span.clone()].trim().to_string();
+                edited.replace_range(span, &escaped);
+                ReferenceChange::Updated { from }
+            }
+            // Giving it a version would fight whatever sets it now, usually
+            // central package management.
+            None => {
+                return Err(DotnetError::VersionlessReference {
+                    project: path.into(),
+                    id: existing.id,
+                })
+            }
+        }
+    } else {
+        let reference = format!(
+            "<PackageReference Include=\"{}\" Version=\"{}\" />",
+            escape(id),
+            escaped
+        );
+        if let (Some(end), Some(indent)) = (scan.last_reference_end, scan.last_reference_indent) {
+            edited.insert_str(end, &format!("{}{}{}", nl, indent, reference));
+        } else {
+            let project_end = scan.project_end.ok_or_else(|| {
+                DotnetError::BadProject(
+                    path.into(),
+                    turron_common::quick_xml::Error::UnexpectedEof("Project".into()),
+                )
+            })?;
+            let indent = scan.child_indent.unwrap_or_else(|| "  ".into());
+            let group = format!(
+                "{indent}<ItemGroup>{nl}{indent}{indent}{reference}{nl}{indent}</ItemGroup>{nl}",
+                nl = nl,
+                indent = indent,
+                reference = reference,
+            );
+            // Put the new group on its own lines, right before `</Project>`,
+            // with a blank line after it like the SDK templates have.
+            let line_start = contents[..project_end]
+                .rfind('\n')
+                .map(|i| i + 1)
+                .filter(|&i| contents[i..project_end].trim().is_empty());
+            match line_start {
+                Some(line_start) => edited.insert_str(line_start, &format!("{}{}", group, nl)),
+                None => edited.insert_str(project_end, &format!("{}{}", nl, group)),
+            }
+        }
+        ReferenceChange::Added
+    };
+    Ok((edited, change))
+}
+
+/// Sets or removes metadata, like `PrivateAssets`, on the project's
+/// reference to `id`. `Some` values are written as attributes, or into the
+/// matching child element if the reference already has one. `None` removes
+/// the metadata in either form. Everything else about the reference is left
+/// as it was. `path` is only used in errors.
+///
+/// Returns the new contents of the project and whether anything changed, or
+/// `None` if the project doesn't reference `id`.
+pub fn set_reference_metadata(
+    path: &Path,
+    contents: &str,
+    id: &str,
+    metadata: &[(&str, Option<&str>)],
+) -> Result<Option<(String, bool)>, DotnetError> {
+    let mut edited = contents.to_string();
+    for (name, value) in metadata {
+        // Edits move everything after them around, so look again each time.
+        let existing = match scan(path, &edited, Some(id))?.existing {
+            Some(existing) => existing,
+            None => return Ok(None),
+        };
+        let pattern = regex::escape(name);
+        let attribute = Regex::new(&format!(r#"(?i)\s+{}\s*=\s*("[^"]*"|'[^']*')"#, pattern))
+            .expect("TURRON BUG: bad metadata attribute regex");
+        let child = Regex::new(&format!(r"(?is)<{0}\s*>(.*?)</{0}\s*>", pattern))
+            .expect("TURRON BUG: bad metadata element regex");
+        let tag = existing.span.start..existing.tag_end;
+        let body = existing.tag_end..existing.span.end;
+        if let Some(found) = attribute.captures(&edited[tag.clone()]) {
+            let whole = found.get(0).expect("TURRON BUG: no match").range();
+            let quoted = found.get(1).expect("TURRON BUG: no value").range();
+            match value {
+                Some(value) => edited.replace_range(
+                    tag.start + quoted.start + 1..tag.start + quoted.end - 1,
+                    &escape(value),
+                ),
+                None => edited.replace_range(tag.start + whole.start..tag.start + whole.end, ""),
+            }
+        } else if let Some(found) = child.captures(&edited[body.clone()]) {
+            let whole = found.get(0).expect("TURRON BUG: no match").range();
+            let text = found.get(1).expect("TURRON BUG: no text").range();
+            match value {
+                Some(value) => edited.replace_range(
+                    body.start + text.start..body.start + text.end,
+                    &escape(value),
+                ),
+                None => {
+                    let span =
+                        whole_lines(&edited, body.start + whole.start..body.start + whole.end);
+                    edited.replace_range(span, "");
+                }
+            }
+        } else if let Some(value) = value {
+            // Right after the last attribute, before any `/>` or `>`.
+            let tag_text = &edited[tag.clone()];
+            let close = if tag_text.ends_with("/>") { 2 } else { 1 };
+            let at = tag.start + tag_text[..tag_text.len() - close].trim_end().len();
+            edited.insert_str(at, &format!(" {}=\"{}\"", name, escape(value)));
+        }
+    }
+    let changed = edited != contents;
+    Ok(Some((edited, changed)))
+}
+
+/// Takes every reference to `id` out of the project in `contents`, like the
+/// ones in per-framework `<ItemGroup Condition="...">`s, along with the lines
+/// they were on, and their `<ItemGroup>`s too if nothing else is left in
+/// them. `path` is only used in errors.
+///
+/// Returns the new contents of the project and what was removed, in the
+/// order it was in, or `None` if the project doesn't reference `id`.
+pub fn remove_package_reference(
+    path: &Path,
+    contents: &str,
+    id: &str,
+) -> Result<Option<(String, Vec<RemovedReference>)>, DotnetError> {
+    let mut edited = contents.to_string();
+    let mut removed = Vec::new();
+    while let Some((rest, reference)) = remove_first_reference(path, &edited, id)? {
+        edited = rest;
+        removed.push(reference);
+    }
+    if removed.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some((edited, removed)))
+    }
+}
+
+/// Takes the first reference to `id` out of the project in `contents`.
+fn remove_first_reference(
+    path: &Path,
+    contents: &str,
+    id: &str,
+) -> Result<Option<(String, RemovedReference)>, DotnetError> {
+    let existing = match scan(path, contents, Some(id))?.existing {
+        Some(existing) => existing,
+        None => return Ok(None),
+    };
+    let mut span = whole_lines(
+        contents,
+        existing
+            .lone_group
+            .clone()
+            .unwrap_or_else(|| existing.span.clone()),
+    );
+    if existing.lone_group.is_some() && follows_blank_line(contents, span.start) {
+        // Don't leave two blank lines where the group used to be.
+        span.end += rest_of_line(&contents[span.end..]).unwrap_or(0);
+    }
+    let mut edited = contents.to_string();
+    edited.replace_range(span, "");
+    let removed = RemovedReference {
+        id: existing.id,
+        version: existing
+            .version
+            .map(|version| contents[version].trim().to_string()),
+    };
+    Ok(Some((edited, removed)))
+}
+
+/// Every package the project in `contents` references, in the order they
+/// show up. `path` is only used in errors.
+pub fn package_references(
+    path: &Path,
+    contents: &str,
+) -> Result<Vec<PackageReference>, DotnetError> {
+    Ok(scan(path, contents, None)?.references)
+}
+
+/// Reads through a project file, noting where things are, including the
+/// reference to `id` if there is one.
+fn scan(path: &Path, contents: &str, id: Option<&str>) -> Result<Scan, DotnetError> {
+    let mut reader = Reader::from_str(contents);
+    let mut buf = Vec::new();
+    let mut scan = Scan::default();
+    let mut depth = 0;
+    // Inside a reference, where a `<Version>` child sets its version.
+    let mut in_reference = false;
+    let mut in_existing = false;
+    let mut version_text_start = None;
+    // The `<ItemGroup>` being read: where it starts, and whether it has
+    // anything besides the reference to `id` in it.
+    let mut group: Option<(usize, bool)> = None;
+    let mut existing_group = None;
+    loop {
+        let start = reader.buffer_position();
+        let event = reader
+            .read_event(&mut buf)
+            .map_err(|e| DotnetError::BadProject(path.into(), e))?;
+        let end = reader.buffer_position();
+        let direct_child = depth == 2;
+        let mut is_existing = false;
+        match event {
+            Event::Start(ref tag) | Event::Empty(ref tag) => {
+                let empty = matches!(event, Event::Empty(_));
+                if depth == 1 && scan.child_indent.is_none() {
+                    scan.child_indent = Some(indent_at(contents, start));
+                }
+                if depth == 1 && tag.name() == b"ItemGroup" && !empty {
+                    group = Some((start, false));
+                }
+                if tag.name() == b"PackageReference" {
+                    let found = parse_reference(&contents[start..end], start);
+                    scan.last_reference_indent = Some(indent_at(contents, start));
+                    if empty {
+                        scan.last_reference_end = Some(end);
+                    }
+                    if let Some(found) = found {
+                        in_reference = !empty;
+                        scan.references.push(PackageReference {
+                            id: found.id.clone(),
+                            version: found
+                                .version
+                                .clone()
+                                .map(|version| contents[version].trim().to_string()),
+                        });
+                        let wanted = id.map_or(false, |id| found.id.eq_ignore_ascii_case(id));
+                        if wanted && scan.existing.is_none() {
+                            is_existing = true;
+                            in_existing = !empty;
+                            existing_group = group.map(|(group_start, _)| group_start);
+                            scan.existing = Some(found);
+                        }
+                    }
+                } else if tag.name() == b"Version" && in_reference && !empty {
+                    version_text_start = Some(end);
+                }
+                if !empty {
+                    depth += 1;
+                }
+            }
+            Event::End(ref tag) => {
+                depth = depth.saturating_sub(1);
+                if tag.name() == b"PackageReference" {
+                    scan.last_reference_end = Some(end);
+                    if in_existing {
+                        if let Some(existing) = &mut scan.existing {
+                            existing.span.end = end;
+                        }
+                    }
+                    in_reference = false;
+                    in_existing = false;
+                } else if tag.name() == b"Version" && in_reference {
+                    if let Some(text_start) = version_text_start.take() {
+                        if let Some(reference) = scan.references.last_mut() {
+                            reference
+                                .version
+                                .get_or_insert_with(|| contents[text_start..start].trim().into());
+                        }
+                        if in_existing {
+                            if let Some(existing) = &mut scan.existing {
+                                existing.version.get_or_insert(text_start..start);
+                            }
+                        }
+                    }
+                } else if tag.name() == b"ItemGroup" && depth == 1 {
+                    if let (Some((group_start, false)), Some(existing)) =
+                        (group.take(), &mut scan.existing)
+                    {
+                        if existing_group == Some(group_start) {
+                            existing.lone_group = Some(group_start..end);
+                        }
+                    }
+                } else if tag.name() == b"Project" && depth == 0 {
+                    scan.project_end = Some(start);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        // Anything directly in the group, other than whitespace and the
+        // reference to `id`, means the group has to stay.
+        let is_content = match event {
+            Event::Start(_) | Event::Empty(_) => !is_existing,
+            Event::Text(_) => !contents[start..end].trim().is_empty(),
+            Event::End(_) => false,
+            _ => true,
+        };
+        if direct_child && is_content {
+            if let Some((_, other)) = &mut group {
+                *other = true;
+            }
+        }
+        buf.clear();
+    }
+    Ok(scan)
+}
+
+/// Reads the start tag of a `<PackageReference>` at `offset`. Returns
+/// `None` if it doesn't have an `Include`.
+fn parse_reference(tag: &str, offset: usize) -> Option<Existing> {
+    let mut include = None;
+    let mut version = None;
+    for attr in ATTRIBUTE.captures_iter(tag) {
+        let value = attr.get(2)?;
+        let inner = offset + value.start() + 1..offset + value.end() - 1;
+        if attr[1].eq_ignore_ascii_case("Include") {
+            let id = &value.as_str()[1..value.as_str().len() - 1];
+            include = Some((id.trim().to_string(), offset + value.end()));
+        } else {
+            version = Some(inner);
+        }
+    }
+    let (id, include_end) = include?;
+    Some(Existing {
+        id,
+        span: offset..offset + tag.len(),
+        tag_end: offset + tag.len(),
+        include_end,
+        version,
+        lone_group: None,
+    })
+}
+
+/// The whitespace between the start of the line and `pos`.
+fn indent_at(contents: &str, pos: usize) -> String {
+    let line_start = contents[..pos].rfind('\n').map_or(0, |i| i + 1);
+    contents[line_start..pos]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect()
+}
+
+/// `span`, widened to cover the whole lines it's on, line ending and all,
+/// if there's nothing else on them.
+fn whole_lines(contents: &str, span: Range<usize>) -> Range<usize> {
+    let line_start = contents[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    match rest_of_line(&contents[span.end..]) {
+        Some(rest) if contents[line_start..span.start].trim().is_empty() => {
+            line_start..span.end + rest
+        }
+        _ => span,
+    }
+}
+
+/// The length of the line at the start of `text`, line ending included, if
+/// it's blank.
+fn rest_of_line(text: &str) -> Option<usize> {
+    let line = text.find('\n').map_or(text, |i| &text[..=i]);
+    if line.trim().is_empty() {
+        Some(line.len())
+    } else {
+        None
+    }
+}
+
+/// Whether the line before the one starting at `line_start` is blank.
+fn follows_blank_line(contents: &str, line_start: usize) -> bool {
+    match contents[..line_start].strip_suffix('\n') {
+        Some(before) => before[before.rfind('\n').map_or(0, |i| i + 1)..]
+            .trim()
+            .is_empty(),
+        None => false,
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(contents: &str, id: &str, version: &str) -> (String, ReferenceChange) {
+        set_package_reference(Path::new("Test.csproj"), contents, id, version).unwrap()
+    }
+
+    const PROJECT: &str = r#"<Project Sdk="Microsoft.NET.Sdk">
+
+  <PropertyGroup>
+    <TargetFramework>net6.0</TargetFramework>
+  </PropertyGroup>
+
+  <ItemGroup>
+    <!-- Logging -->
+    <PackageReference Include="Serilog"   Version="2.10.0" />
+    <PackageReference Include="Newtonsoft.Json">
+      <Version>12.0.3</Version>
+    </PackageReference>
+    <PackageReference Include="Polly" PrivateAssets="all" />
+  </ItemGroup>
+
+</Project>
+"#;
+
+    #[test]
+    fn updates_version_attributes() {
+        let (edited, change) = set(PROJECT, "serilog", "[2.11.0, )");
+        assert_eq!(
+            change,
+            ReferenceChange::Updated {
+                from: "2.10.0".into()
+            }
+        );
+        assert_eq!(
+            edited,
+            PROJECT.replace(
+                r#"Include="Serilog"   Version="2.10.0""#,
+                r#"Include="Serilog"   Version="[2.11.0, )""#
+            )
+        );
+        assert_eq!(
+            set(PROJECT, "Serilog", "2.10.0").1,
+            ReferenceChange::Unchanged
+        );
+    }
+
+    #[test]
+    fn updates_version_elements() {
+        let (edited, change) = set(PROJECT, "Newtonsoft.Json", "13.0.1");
+        assert_eq!(
+            change,
+            ReferenceChange::Updated {
+                from: "12.0.3".into()
+            }
+        );
+        assert_eq!(
+            edited,
+            PROJECT.replace("<Version>12.0.3</Version>", "<Version>13.0.1</Version>")
+        );
+    }
+
+    #[test]
+    fn leaves_versionless_references_alone() {
+        let err =
+            set_package_reference(Path::new("Test.csproj"), PROJECT, "polly", "7.2.2").unwrap_err();
+        assert!(matches!(
+            err,
+            DotnetError::VersionlessReference { id, .. } if id == "Polly"
+        ));
+    }
+
+    #[test]
+    fn adds_after_other_references() {
+        let (edited, change) = set(PROJECT, "Turron.Test", "1.0.0");
+        assert_eq!(change, ReferenceChange::Added);
+        assert_eq!(
+            edited,
+            PROJECT.replace(
+                "    <PackageReference Include=\"Polly\" PrivateAssets=\"all\" />\n",
+                concat!(
+                    "    <PackageReference Include=\"Polly\" PrivateAssets=\"all\" />\n",
+                    "    <PackageReference Include=\"Turron.Test\" Version=\"1.0.0\" />\n",
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn adds_an_item_group() {
+        let project = "<Project Sdk=\"Microsoft.NET.Sdk\">\r\n\r\n\t<PropertyGroup>\r\n\t\t<TargetFramework>net6.0</TargetFramework>\r\n\t</PropertyGroup>\r\n\r\n</Project>\r\n";
+        let (edited, change) = set(project, "Turron.Test", "1.0.0");
+        assert_eq!(change, ReferenceChange::Added);
+        assert_eq!(
+            edited,
+            concat!(
+                "<Project Sdk=\"Microsoft.NET.Sdk\">\r\n\r\n",
+                "\t<PropertyGroup>\r\n\t\t<TargetFramework>net6.0</TargetFramework>\r\n\t</PropertyGroup>\r\n\r\n",
+                "\t<ItemGroup>\r\n",
+                "\t\t<PackageReference Include=\"Turron.Test\" Version=\"1.0.0\" />\r\n",
+                "\t</ItemGroup>\r\n\r\n",
+                "</Project>\r\n",
+            )
+        );
+    }
+
+    fn set_metadata(contents: &str, id: &str, metadata: &[(&str, Option<&str>)]) -> String {
+        let (edited, changed) =
+            set_reference_metadata(Path::new("Test.csproj"), contents, id, metadata)
+                .unwrap()
+                .unwrap();
+        assert_eq!(changed, edited != contents);
+        edited
+    }
+
+    #[test]
+    fn adds_metadata_attributes() {
+        let edited = set_metadata(
+            PROJECT,
+            "serilog",
+            &[
+                ("PrivateAssets", Some("all")),
+                ("IncludeAssets", Some("runtime; build")),
+            ],
+        );
+        assert_eq!(
+            edited,
+            PROJECT.replace(
+                r#"Version="2.10.0" />"#,
+                r#"Version="2.10.0" PrivateAssets="all" IncludeAssets="runtime; build" />"#
+            )
+        );
+        let edited = set_metadata(
+            PROJECT,
+            "Newtonsoft.Json",
+            &[("ExcludeAssets", Some("compile"))],
+        );
+        assert_eq!(
+            edited,
+            PROJECT.replace(
+                r#"<PackageReference Include="Newtonsoft.Json">"#,
+                r#"<PackageReference Include="Newtonsoft.Json" ExcludeAssets="compile">"#
+            )
+        );
+    }
+
+    #[test]
+    fn updates_metadata_in_place() {
+        let edited = set_metadata(PROJECT, "Polly", &[("privateassets", Some("none"))]);
+        assert_eq!(
+            edited,
+            PROJECT.replace(r#"PrivateAssets="all""#, r#"PrivateAssets="none""#)
+        );
+        let project = PROJECT.replace(
+            "      <Version>12.0.3</Version>\n",
+            "      <Version>12.0.3</Version>\n      <PrivateAssets>all</PrivateAssets>\n",
+        );
+        let edited = set_metadata(
+            &project,
+            "Newtonsoft.Json",
+            &[("PrivateAssets", Some("compile"))],
+        );
+        assert_eq!(
+            edited,
+            project.replace(
+                "<PrivateAssets>all</PrivateAssets>",
+                "<PrivateAssets>compile</PrivateAssets>"
+            )
+        );
+        assert_eq!(
+            set_reference_metadata(
+                Path::new("Test.csproj"),
+                PROJECT,
+                "Polly",
+                &[("PrivateAssets", Some("all"))]
+            )
+            .unwrap(),
+            Some((PROJECT.to_string(), false))
+        );
+    }
+
+    #[test]
+    fn removes_metadata() {
+        let edited = set_metadata(PROJECT, "Polly", &[("PrivateAssets", None)]);
+        assert_eq!(
+            edited,
+            PROJECT.replace(
+                r#"Include="Polly" PrivateAssets="all" />"#,
+                r#"Include="Polly" />"#
+            )
+        );
+        let project = PROJECT.replace(
+            "      <Version>12.0.3</Version>\n",
+            "      <Version>12.0.3</Version>\n      <PrivateAssets>all</PrivateAssets>\n",
+        );
+        assert_eq!(
+            set_metadata(&project, "Newtonsoft.Json", &[("PrivateAssets", None)]),
+            PROJECT
+        );
+        // Nothing to remove.
+        assert_eq!(
+            set_metadata(PROJECT, "Serilog", &[("ExcludeAssets", None)]),
+            PROJECT
+        );
+        assert!(set_reference_metadata(
+            Path::new("Test.csproj"),
+            PROJECT,
+            "Turron.Test",
+            &[("PrivateAssets", Some("all"))]
+        )
+        .unwrap()
+        .is_none());
+    }
+
+    fn remove(contents: &str, id: &str) -> Option<(String, RemovedReference)> {
+        let (edited, mut removed) =
+            remove_package_reference(Path::new("Test.csproj"), contents, id).unwrap()?;
+        assert_eq!(removed.len(), 1);
+        Some((edited, removed.remove(0)))
+    }
+
+    #[test]
+    fn removes_attribute_references() {
+        let (edited, removed) = remove(PROJECT, "serilog").unwrap();
+        assert_eq!(
+            removed,
+            RemovedReference {
+                id: "Serilog".into(),
+                version: Some("2.10.0".into())
+            }
+        );
+        assert_eq!(
+            edited,
+            PROJECT.replace(
+                "    <PackageReference Include=\"Serilog\"   Version=\"2.10.0\" />\n",
+                ""
+            )
+        );
+    }
+
+    #[test]
+    fn removes_element_references() {
+        let (edited, removed) = remove(PROJECT, "Newtonsoft.Json").unwrap();
+        assert_eq!(removed.version.as_deref(), Some("12.0.3"));
+        assert_eq!(
+            edited,
+            PROJECT.replace(
+                concat!(
+                    "    <PackageReference Include=\"Newtonsoft.Json\">\n",
+                    "      <Version>12.0.3</Version>\n",
+                    "    </PackageReference>\n",
+                ),
+                ""
+            )
+        );
+        let (edited, removed) = remove(PROJECT, "Polly").unwrap();
+        assert_eq!(removed.version, None);
+        assert!(!edited.contains("Polly"));
+        assert!(edited.contains("<!-- Logging -->"));
+    }
+
+    #[test]
+    fn removes_groups_it_empties() {
+        let project = "<Project Sdk=\"Microsoft.NET.Sdk\">\r\n\r\n\t<PropertyGroup>\r\n\t\t<TargetFramework>net6.0</TargetFramework>\r\n\t</PropertyGroup>\r\n\r\n</Project>\r\n";
+        let (added, _) = set(project, "Turron.Test", "1.0.0");
+        let (edited, _) = remove(&added, "Turron.Test").unwrap();
+        assert_eq!(edited, project);
+    }
+
+    #[test]
+    fn removes_every_conditional_reference() {
+        let project = r#"<Project Sdk="Microsoft.NET.Sdk">
+
+  <PropertyGroup>
+    <TargetFrameworks>net472;net6.0</TargetFrameworks>
+  </PropertyGroup>
+
+  <ItemGroup>
+    <PackageReference Include="Polly" Version="7.2.2" />
+  </ItemGroup>
+
+  <ItemGroup Condition="'$(TargetFramework)' == 'net472'">
+    <PackageReference Include="System.Text.Json" Version="5.0.2" />
+  </ItemGroup>
+
+  <ItemGroup Condition="'$(TargetFramework)' == 'net6.0'">
+    <PackageReference Include="System.Text.Json" Version="6.0.0" />
+  </ItemGroup>
+
+</Project>
+"#;
+        let (edited, removed) =
+            remove_package_reference(Path::new("Test.csproj"), project, "system.text.json")
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            removed
+                .iter()
+                .map(|reference| reference.version.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("5.0.2"), Some("6.0.0")]
+        );
+        assert!(!edited.contains("System.Text.Json"));
+        assert!(!edited.contains("Condition"));
+        assert!(edited.contains("<PackageReference Include=\"Polly\" Version=\"7.2.2\" />"));
+    }
+
+    #[test]
+    fn lists_references() {
+        assert!(remove(PROJECT, "Turron.Test").is_none());
+        let references = package_references(Path::new("Test.csproj"), PROJECT).unwrap();
+        assert_eq!(
+            references
+                .iter()
+                .map(|r| (&r.id[..], r.version.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Serilog", Some("2.10.0")),
+                ("Newtonsoft.Json", Some("12.0.3")),
+                ("Polly", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_things_that_arent_projects() {
+        assert!(set_package_reference(Path::new("x"), "<Nope>", "A", "1.0.0").is_err());
+    }
+}
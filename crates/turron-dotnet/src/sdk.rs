@@ -0,0 +1,96 @@
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use dotnet_semver::Version;
+use once_cell::sync::OnceCell;
+use turron_common::{
+    smol::{self, process::Command},
+    tracing,
+};
+
+use crate::errors::DotnetError;
+
+/// Oldest .NET SDK turron knows how to drive. `dotnet pack --nologo` showed
+/// up in 3.0, and 3.1 is the oldest LTS release.
+pub const MIN_SDK_VERSION: &str = "3.1.100";
+
+static DOTNET_CLI: OnceCell<PathBuf> = OnceCell::new();
+
+/// Finds the `dotnet` CLI and makes sure its SDK is new enough. Only the
+/// first successful check in a process actually runs anything.
+pub async fn dotnet_cli() -> Result<PathBuf, DotnetError> {
+    if let Some(cli) = DOTNET_CLI.get() {
+        return Ok(cli.clone());
+    }
+    let paths = env::var_os("PATH").unwrap_or_default();
+    let cwd = env::current_dir()?;
+    let cli = smol::unblock(move || find_dotnet_in(paths, cwd)).await?;
+    check_sdk(&cli).await?;
+    Ok(DOTNET_CLI.get_or_init(|| cli).clone())
+}
+
+/// Looks for `dotnet` in `paths`, which is formatted like $PATH.
+fn find_dotnet_in(paths: impl AsRef<OsStr>, cwd: impl AsRef<Path>) -> Result<PathBuf, DotnetError> {
+    Ok(which::which_in("dotnet", Some(paths), cwd)?)
+}
+
+async fn check_sdk(cli: &Path) -> Result<(), DotnetError> {
+    let output = Command::new(cli).arg("--version").output().await?;
+    if !output.status.success() {
+        // Usually a global.json pinning an SDK that isn't installed. `dotnet
+        // pack` will say as much in its own words, so let it.
+        tracing::debug!(
+            "`dotnet --version` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Ok(());
+    }
+    check_sdk_version(String::from_utf8_lossy(&output.stdout).trim())
+}
+
+/// Compares `dotnet --version` output against [`MIN_SDK_VERSION`]. Output
+/// that doesn't look like a version is let through, since the SDK is
+/// probably fine and just printing something unexpected.
+fn check_sdk_version(found: &str) -> Result<(), DotnetError> {
+    let required =
+        Version::parse(MIN_SDK_VERSION).expect("TURRON BUG: MIN_SDK_VERSION should parse");
+    match Version::parse(found) {
+        Ok(version) if version < required => Err(DotnetError::SdkTooOld {
+            found: found.into(),
+            required: MIN_SDK_VERSION.into(),
+        }),
+        Ok(_) => Ok(()),
+        Err(err) => {
+            tracing::debug!("Couldn't parse dotnet SDK version {:?}: {}", found, err);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_cli() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = find_dotnet_in(dir.path(), dir.path()).unwrap_err();
+        assert!(matches!(err, DotnetError::DotnetNotFound(_)));
+    }
+
+    #[test]
+    fn sdk_versions() {
+        assert!(check_sdk_version("6.0.100").is_ok());
+        assert!(check_sdk_version("3.1.100").is_ok());
+        assert!(check_sdk_version("7.0.100-preview.1.22110.4").is_ok());
+        assert!(check_sdk_version("not a version").is_ok());
+        match check_sdk_version("2.1.818") {
+            Err(DotnetError::SdkTooOld { found, required }) => {
+                assert_eq!(found, "2.1.818");
+                assert_eq!(required, MIN_SDK_VERSION);
+            }
+            other => panic!("expected SdkTooOld, got {:?}", other),
+        }
+    }
+}
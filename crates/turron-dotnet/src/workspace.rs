@@ -0,0 +1,692 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use dotnet_semver::Version;
+use turron_common::{
+    quick_xml::{events::Event, Reader},
+    regex::Regex,
+    tracing,
+};
+
+use crate::errors::DotnetError;
+
+/// What `--all` needs to know about a project file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    pub path: PathBuf,
+    /// `false` when the project sets `<IsPackable>false</IsPackable>`.
+    pub packable: bool,
+    /// `<PackageId>`, falling back on `<AssemblyName>` and then the project
+    /// file's name, like MSBuild does.
+    pub package_id: String,
+    /// `<PackageVersion>` or `<Version>`, if the project file sets one to a
+    /// literal version. Versions set with properties, or from a
+    /// `Directory.Build.props`, aren't seen.
+    pub version: Option<Version>,
+    /// Projects this one has a `<ProjectReference>` to, resolved against its
+    /// directory.
+    pub references: Vec<PathBuf>,
+}
+
+impl Project {
+    pub fn read(path: &Path) -> Result<Self, DotnetError> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| DotnetError::BadProject(path.into(), e.into()))?;
+        Self::parse(path, &contents)
+    }
+
+    /// Reads the relevant bits out of a project file's contents. Anything
+    /// MSBuild would work out on its own, like conditions or imported props,
+    /// is ignored.
+    pub fn parse(path: &Path, contents: &str) -> Result<Self, DotnetError> {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut reader = Reader::from_str(contents);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+        // The property element we're inside of, if it's one we care about.
+        let mut property = None;
+        let mut properties = BTreeMap::new();
+        let mut references = Vec::new();
+        loop {
+            let event = reader
+                .read_event(&mut buf)
+                .map_err(|e| DotnetError::BadProject(path.into(), e))?;
+            match event {
+                Event::Start(tag) if PROPERTIES.contains(&tag.name()) => {
+                    property = Some(tag.name().to_vec())
+                }
+                Event::End(_) => property = None,
+                Event::Text(text) if property.is_some() => {
+                    let text = text
+                        .unescaped()
+                        .map_err(|e| DotnetError::BadProject(path.into(), e))?;
+                    properties.insert(
+                        property.clone().unwrap_or_default(),
+                        String::from_utf8_lossy(&text).trim().to_string(),
+                    );
+                }
+                Event::Start(tag) | Event::Empty(tag) if tag.name() == b"ProjectReference" => {
+                    for attr in tag.attributes().filter_map(Result::ok) {
+                        if attr.key == b"Include" {
+                            // Project files are usually written on Windows.
+                            let include = String::from_utf8_lossy(&attr.value).replace('\\', "/");
+                            references.push(normalize(&dir.join(include)));
+                        }
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        let get = |name: &[u8]| {
+            properties
+                .get(name)
+                .filter(|value: &&String| !value.is_empty())
+                .cloned()
+        };
+        let packable =
+            !get(b"IsPackable").map_or(false, |value| value.eq_ignore_ascii_case("false"));
+        let package_id = get(b"PackageId")
+            .or_else(|| get(b"AssemblyName"))
+            .unwrap_or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+        let version = get(b"PackageVersion")
+            .or_else(|| get(b"Version"))
+            .and_then(|version| Version::parse(version).ok());
+        Ok(Project {
+            path: path.into(),
+            packable,
+            package_id,
+            version,
+            references,
+        })
+    }
+}
+
+/// The project properties [`Project::parse`] reads.
+const PROPERTIES: &[&[u8]] = &[
+    b"IsPackable",
+    b"PackageId",
+    b"AssemblyName",
+    b"PackageVersion",
+    b"Version",
+];
+
+/// Finds every packable project under `root`, skipping anything ignored by
+/// git, `bin`/`obj` directories, and projects whose path relative to `root`
+/// (or whose name) matches one of `exclude`. Sorted by path.
+pub fn discover(root: &Path, exclude: &[glob::Pattern]) -> Result<Vec<Project>, DotnetError> {
+    let mut projects = Vec::new();
+    for path in walk(root, |path| {
+        path.extension().map_or(false, |ext| ext == "csproj")
+    }) {
+        if is_excluded(root, &path, exclude) {
+            continue;
+        }
+        let project = Project::read(&path)?;
+        if project.packable {
+            projects.push(project);
+        } else {
+            tracing::debug!("Skipping {}, since it isn't packable", path.display());
+        }
+    }
+    Ok(projects)
+}
+
+/// Every .csproj, .fsproj, and .vbproj under `root`, packable or not,
+/// skipping the same things [`discover`] does. Sorted by path.
+pub fn project_files(root: &Path) -> Vec<PathBuf> {
+    walk(root, is_project_file)
+}
+
+/// Files under `root` that are `matching`, skipping anything ignored by git
+/// and `bin`/`obj` directories. Sorted by path.
+fn walk(root: &Path, matching: fn(&Path) -> bool) -> Vec<PathBuf> {
+    let walker = ignore::WalkBuilder::new(root)
+        .require_git(false)
+        .filter_entry(|entry| !matches!(entry.file_name().to_str(), Some("bin") | Some("obj")))
+        .build();
+    let mut found = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::warn!("Skipping part of {}: {}", root.display(), err);
+                continue;
+            }
+        };
+        if entry.file_type().map_or(false, |ty| ty.is_file()) && matching(entry.path()) {
+            found.push(normalize(entry.path()));
+        }
+    }
+    found.sort();
+    found
+}
+
+/// The project file to work on, starting from `start`: `start` itself if
+/// it's a file, or else the only project file in `start` or the closest
+/// directory above it that has any. Pass an absolute `start` to search all
+/// the way up.
+pub fn find_project(start: &Path) -> Result<PathBuf, DotnetError> {
+    if start.is_file() {
+        return Ok(start.into());
+    }
+    match closest(start, is_project_file) {
+        Some((_, mut found)) if found.len() == 1 => Ok(found.remove(0)),
+        Some((dir, found)) => Err(DotnetError::AmbiguousProject(dir, found)),
+        None => Err(DotnetError::ProjectNotFound(start.into())),
+    }
+}
+
+/// The `Directory.Packages.props` that sets package versions for
+/// `project`, if it uses central package management. That's on when
+/// `ManagePackageVersionsCentrally` is `true` in the project itself, or in
+/// the closest `Directory.Build.props` or `Directory.Packages.props` above
+/// it. Like [`Project::parse`], conditions are ignored.
+pub fn central_package_versions(project: &Path) -> Option<PathBuf> {
+    let dir = project.parent().unwrap_or_else(|| Path::new(""));
+    let (_, mut props) = closest(dir, is_packages_props)?;
+    let props = props.remove(0);
+    let build_props = closest(dir, is_build_props).map(|(_, mut found)| found.remove(0));
+    let regex = Regex::new(r"(?i)<ManagePackageVersionsCentrally>\s*true\s*</")
+        .expect("TURRON BUG: oops, bad regex?");
+    let enabled = [Some(project), build_props.as_deref(), Some(props.as_path())]
+        .iter()
+        .flatten()
+        .any(|file| fs::read_to_string(file).map_or(false, |contents| regex.is_match(&contents)));
+    if enabled {
+        Some(props)
+    } else {
+        None
+    }
+}
+
+/// Like [`find_project`], but for a `.sln`.
+pub fn find_solution(start: &Path) -> Result<PathBuf, DotnetError> {
+    if start.is_file() && is_solution_file(start) {
+        return Ok(start.into());
+    }
+    match closest(start, is_solution_file) {
+        Some((_, mut found)) if found.len() == 1 => Ok(found.remove(0)),
+        Some((dir, found)) => Err(DotnetError::AmbiguousSolution(dir, found)),
+        None => Err(DotnetError::SolutionNotFound(start.into())),
+    }
+}
+
+/// The project files a solution lists, resolved against its directory, in
+/// the order it lists them. Solution folders are skipped.
+pub fn solution_projects(path: &Path) -> Result<Vec<PathBuf>, DotnetError> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| DotnetError::BadSolution(path.into(), e))?;
+    Ok(parse_solution(path, &contents))
+}
+
+fn parse_solution(path: &Path, contents: &str) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let regex = Regex::new(r#"^\s*Project\("[^"]*"\)\s*=\s*"[^"]*"\s*,\s*"([^"]*)""#)
+        .expect("TURRON BUG: oops, bad regex?");
+    contents
+        .lines()
+        .filter_map(|line| regex.captures(line))
+        // Solutions are usually written on Windows.
+        .map(|captures| PathBuf::from(captures[1].replace('\\', "/")))
+        .filter(|project| is_project_file(project))
+        .map(|project| normalize(&dir.join(project)))
+        .collect()
+}
+
+/// The files in `start` or the closest directory above it that has any
+/// `matching`, sorted, along with that directory.
+fn closest(start: &Path, matching: fn(&Path) -> bool) -> Option<(PathBuf, Vec<PathBuf>)> {
+    for dir in start.ancestors() {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::debug!("Skipping {}: {}", dir.display(), err);
+                continue;
+            }
+        };
+        let mut found = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && matching(path))
+            .collect::<Vec<_>>();
+        if !found.is_empty() {
+            found.sort();
+            return Some((dir.into(), found));
+        }
+    }
+    None
+}
+
+fn is_packages_props(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| {
+            name.eq_ignore_ascii_case("Directory.Packages.props")
+        })
+}
+
+fn is_build_props(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| {
+            name.eq_ignore_ascii_case("Directory.Build.props")
+        })
+}
+
+fn is_solution_file(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("sln"))
+}
+
+fn is_project_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            ["csproj", "fsproj", "vbproj"]
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+fn is_excluded(root: &Path, path: &Path, exclude: &[glob::Pattern]) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let name = path.file_stem().map(Path::new);
+    exclude.iter().any(|pattern| {
+        pattern.matches_path(relative) || name.map_or(false, |name| pattern.matches_path(name))
+    })
+}
+
+/// Orders `projects` so each comes after the projects it references, so
+/// packages built from project references already exist by the time
+/// something depends on them. References to projects outside the list are
+/// ignored. Otherwise, projects stay in path order.
+pub fn pack_order(projects: &[Project]) -> Vec<&Project> {
+    let paths = projects
+        .iter()
+        .map(|project| &project.path)
+        .collect::<BTreeSet<_>>();
+    let mut pending = projects
+        .iter()
+        .map(|project| {
+            let deps = project
+                .references
+                .iter()
+                .filter(|reference| paths.contains(reference) && *reference != &project.path)
+                .collect::<BTreeSet<_>>();
+            (&project.path, (project, deps))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut ordered = Vec::with_capacity(projects.len());
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .filter(|(_, (_, deps))| deps.is_empty())
+            .map(|(path, _)| *path)
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            tracing::warn!(
+                "Project references form a cycle. Packing the remaining {} projects in path order.",
+                pending.len()
+            );
+            ordered.extend(pending.values().map(|(project, _)| *project));
+            break;
+        }
+        for path in ready {
+            if let Some((project, _)) = pending.remove(path) {
+                ordered.push(project);
+            }
+            for (_, deps) in pending.values_mut() {
+                deps.remove(path);
+            }
+        }
+    }
+    ordered
+}
+
+/// The package `dotnet pack` built for `project`, under its `bin` directory,
+/// which is where it goes unless told otherwise. Only packages named after
+/// the project's package id and version count, so leftovers from older
+/// versions, or from other projects, are never picked. If the version
+/// isn't known (see [`Project::version`]), the newest package with the
+/// right id is used. Either way, when there's more than one match, like a
+/// Debug and a Release build, the most recently written one wins.
+pub fn built_nupkg(project: &Project) -> Option<PathBuf> {
+    let dir = project.path.parent().unwrap_or_else(|| Path::new(""));
+    let pattern = format!(
+        "{}/bin/**/*.nupkg",
+        glob::Pattern::escape(&dir.to_string_lossy())
+    );
+    glob::glob(&pattern)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|path| is_package_for(project, path))
+        .filter_map(|path| {
+            let modified = path.metadata().and_then(|meta| meta.modified()).ok()?;
+            Some((modified, path))
+        })
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+/// Whether `path` is named like a package of `project`:
+/// `<id>.<version>.nupkg`, ignoring case.
+fn is_package_for(project: &Project, path: &Path) -> bool {
+    let name = match path.file_stem() {
+        Some(name) => name.to_string_lossy().to_lowercase(),
+        None => return false,
+    };
+    let prefix = format!("{}.", project.package_id.to_lowercase());
+    let version = match name
+        .strip_prefix(&prefix)
+        .and_then(|version| Version::parse(version).ok())
+    {
+        Some(version) => version,
+        None => return false,
+    };
+    match &project.version {
+        // Pre-release labels are compared ignoring case, like NuGet does.
+        // Build metadata never makes it into the file name, and versions
+        // compare equal without it.
+        Some(expected) => Version::parse(expected.to_string().to_lowercase()).ok() == Some(version),
+        None => true,
+    }
+}
+
+/// Gets rid of `.` and `..` in `path` without touching the filesystem, so
+/// references written relative to different projects compare equal.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(path: &str, references: &[&str]) -> Project {
+        Project {
+            path: path.into(),
+            packable: true,
+            package_id: Path::new(path)
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            version: None,
+            references: references.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn reads_packable_and_references() {
+        let contents = r#"<Project Sdk="Microsoft.NET.Sdk">
+  <PropertyGroup>
+    <TargetFramework>net6.0</TargetFramework>
+    <IsPackable>False</IsPackable>
+  </PropertyGroup>
+  <ItemGroup>
+    <ProjectReference Include="..\Core\Core.csproj" />
+    <ProjectReference Include="./Util/Util.csproj"></ProjectReference>
+    <PackageReference Include="Newtonsoft.Json" Version="13.0.1" />
+  </ItemGroup>
+</Project>"#;
+        let project = Project::parse(Path::new("src/App/App.csproj"), contents).unwrap();
+        assert!(!project.packable);
+        assert_eq!(
+            project.references,
+            vec![
+                PathBuf::from("src/Core/Core.csproj"),
+                PathBuf::from("src/App/Util/Util.csproj")
+            ]
+        );
+
+        assert_eq!(project.package_id, "App");
+        assert_eq!(project.version, None);
+
+        let project = Project::parse(Path::new("Lib.csproj"), "<Project />").unwrap();
+        assert!(project.packable);
+        assert!(project.references.is_empty());
+
+        let contents = r#"<Project Sdk="Microsoft.NET.Sdk">
+  <PropertyGroup>
+    <AssemblyName>Contoso.Lib</AssemblyName>
+    <PackageId>Contoso.Utilities</PackageId>
+    <Version>2.1</Version>
+  </PropertyGroup>
+</Project>"#;
+        let project = Project::parse(Path::new("Lib.csproj"), contents).unwrap();
+        assert_eq!(project.package_id, "Contoso.Utilities");
+        assert_eq!(project.version, Some("2.1.0".parse().unwrap()));
+
+        // Versions built out of properties can't be known ahead of time.
+        let contents =
+            "<Project><PropertyGroup><Version>$(VersionPrefix)-ci</Version></PropertyGroup></Project>";
+        let project = Project::parse(Path::new("Lib.csproj"), contents).unwrap();
+        assert_eq!(project.version, None);
+    }
+
+    #[test]
+    fn finds_the_package_for_the_project_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let touch = |path: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, b"").unwrap();
+            path
+        };
+        let project_path = touch("Foo/Foo.csproj");
+        let current = touch("Foo/bin/Release/Foo.1.0.0-Beta.nupkg");
+        // Written later, but for an older version, another package, or a
+        // package whose id only starts with this one's.
+        touch("Foo/bin/Debug/Foo.0.9.0.nupkg");
+        touch("Foo/bin/Debug/Foo.Extras.1.0.0-beta.nupkg");
+        touch("Foo/bin/Debug/Bar.1.0.0-beta.nupkg");
+
+        let mut project = Project {
+            path: project_path,
+            packable: true,
+            package_id: "foo".into(),
+            version: Some("1.0.0-beta".parse().unwrap()),
+            references: Vec::new(),
+        };
+        assert_eq!(built_nupkg(&project), Some(current));
+
+        project.version = Some("2.0.0".parse().unwrap());
+        assert_eq!(built_nupkg(&project), None);
+
+        project.package_id = "Bar".into();
+        project.version = None;
+        assert_eq!(
+            built_nupkg(&project),
+            Some(dir.path().join("Foo/bin/Debug/Bar.1.0.0-beta.nupkg"))
+        );
+    }
+
+    #[test]
+    fn orders_by_project_references() {
+        let projects = vec![
+            project("a/A.csproj", &["c/C.csproj", "outside/X.csproj"]),
+            project("b/B.csproj", &[]),
+            project("c/C.csproj", &["b/B.csproj"]),
+        ];
+        let order = pack_order(&projects)
+            .into_iter()
+            .map(|p| p.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["b/B.csproj", "c/C.csproj", "a/A.csproj"]);
+
+        let cycle = vec![
+            project("a/A.csproj", &["b/B.csproj"]),
+            project("b/B.csproj", &["a/A.csproj"]),
+            project("c/C.csproj", &[]),
+        ];
+        let order = pack_order(&cycle)
+            .into_iter()
+            .map(|p| p.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["c/C.csproj", "a/A.csproj", "b/B.csproj"]);
+    }
+
+    #[test]
+    fn discovers_packable_projects() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("src/Lib/Lib.csproj", "<Project />");
+        write("src/App/App.csproj", "<Project />");
+        write(
+            "src/Internal/Internal.csproj",
+            "<Project><PropertyGroup><IsPackable>false</IsPackable></PropertyGroup></Project>",
+        );
+        write("tests/Lib.Tests/Lib.Tests.csproj", "<Project />");
+        write("src/Lib/obj/Stale.csproj", "<Project />");
+        write("vendor/Other/Other.csproj", "<Project />");
+        write(".gitignore", "vendor/\n");
+
+        let exclude = vec![glob::Pattern::new("*.Tests").unwrap()];
+        let found = discover(dir.path(), &exclude)
+            .unwrap()
+            .into_iter()
+            .map(|p| {
+                p.path
+                    .strip_prefix(dir.path())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec!["src/App/App.csproj", "src/Lib/Lib.csproj"]);
+
+        let exclude = vec![glob::Pattern::new("src/App/*").unwrap()];
+        assert_eq!(discover(dir.path(), &exclude).unwrap().len(), 2);
+
+        write("src/Lib.FSharp/Lib.FSharp.fsproj", "<Project />");
+        let found = project_files(dir.path())
+            .into_iter()
+            .map(|p| {
+                p.strip_prefix(dir.path())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                "src/App/App.csproj",
+                "src/Internal/Internal.csproj",
+                "src/Lib/Lib.csproj",
+                "src/Lib.FSharp/Lib.FSharp.fsproj",
+                "tests/Lib.Tests/Lib.Tests.csproj",
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_the_closest_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("src").join("App");
+        fs::create_dir_all(app.join("Models")).unwrap();
+        fs::write(app.join("App.csproj"), "<Project />").unwrap();
+        fs::write(app.join("Program.cs"), "").unwrap();
+
+        let found = find_project(&app.join("Models")).unwrap();
+        assert_eq!(found, app.join("App.csproj"));
+        assert_eq!(find_project(&found).unwrap(), found);
+
+        fs::write(app.join("App.Tests.fsproj"), "<Project />").unwrap();
+        assert!(matches!(
+            find_project(&app),
+            Err(DotnetError::AmbiguousProject(_, found)) if found.len() == 2
+        ));
+        assert!(matches!(
+            find_project(&dir.path().join("src")),
+            Err(DotnetError::ProjectNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn detects_central_package_management() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = dir.path().join("src").join("App");
+        fs::create_dir_all(&app).unwrap();
+        let project = app.join("App.csproj");
+        fs::write(&project, "<Project />").unwrap();
+        assert_eq!(central_package_versions(&project), None);
+
+        // A props file on its own doesn't turn it on.
+        let props = dir.path().join("Directory.Packages.props");
+        fs::write(&props, "<Project><ItemGroup /></Project>").unwrap();
+        assert_eq!(central_package_versions(&project), None);
+
+        fs::write(
+            dir.path().join("Directory.Build.props"),
+            "<Project><PropertyGroup><ManagePackageVersionsCentrally>true</ManagePackageVersionsCentrally></PropertyGroup></Project>",
+        )
+        .unwrap();
+        assert_eq!(central_package_versions(&project), Some(props.clone()));
+
+        fs::remove_file(dir.path().join("Directory.Build.props")).unwrap();
+        fs::write(
+            &props,
+            "<Project>\n  <PropertyGroup>\n    <ManagePackageVersionsCentrally> True </ManagePackageVersionsCentrally>\n  </PropertyGroup>\n</Project>\n",
+        )
+        .unwrap();
+        assert_eq!(central_package_versions(&project), Some(props));
+    }
+
+    #[test]
+    fn reads_solutions() {
+        let sln = r#"
+Microsoft Visual Studio Solution File, Format Version 12.00
+# Visual Studio Version 17
+Project("{FAE04EC0-301F-11D3-BF4B-00C04F79EFBC}") = "App", "src\App\App.csproj", "{6F1D6B4C-0000-0000-0000-000000000001}"
+EndProject
+Project("{2150E333-8FDC-42A3-9474-1A3956D46DE8}") = "tests", "tests", "{6F1D6B4C-0000-0000-0000-000000000002}"
+EndProject
+Project("{F2A71F9B-5D33-465A-A702-920D77279786}") = "Lib", "src\..\lib\Lib.fsproj", "{6F1D6B4C-0000-0000-0000-000000000003}"
+EndProject
+Global
+EndGlobal
+"#;
+        assert_eq!(
+            parse_solution(Path::new("repo/All.sln"), sln),
+            vec![
+                PathBuf::from("repo/src/App/App.csproj"),
+                PathBuf::from("repo/lib/Lib.fsproj")
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("src").join("App");
+        fs::create_dir_all(&nested).unwrap();
+        assert!(matches!(
+            find_solution(&nested),
+            Err(DotnetError::SolutionNotFound(_))
+        ));
+        fs::write(dir.path().join("All.sln"), sln).unwrap();
+        assert_eq!(find_solution(&nested).unwrap(), dir.path().join("All.sln"));
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+use nuget_api::NuGetApiError;
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    quick_xml,
+    thiserror::{self, Error},
+};
+use turron_dotnet::DotnetError;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum NupkgError {
+    #[error("Failed to read {}.", .0.display())]
+    #[diagnostic(code(turron::nupkg::io_error))]
+    IoError(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to write {}.", .0.display())]
+    #[diagnostic(code(turron::nupkg::write_failed))]
+    WriteFailed(PathBuf, #[source] std::io::Error),
+
+    #[error("{} isn't a valid nuspec.", .0.display())]
+    #[diagnostic(
+        code(turron::nupkg::bad_nuspec),
+        help("Check that it has a <package> element with <metadata> inside, and that <id>, <version>, <authors>, and <description> are all there.")
+    )]
+    BadNuSpec(PathBuf, #[source] quick_xml::DeError),
+
+    #[error(transparent)]
+    #[diagnostic(code(turron::nupkg::invalid_nuspec))]
+    InvalidNuSpec(#[from] NuGetApiError),
+
+    #[error("`{0}` isn't a valid package id.")]
+    #[diagnostic(
+        code(turron::nupkg::invalid_id),
+        help("Package ids are at most 100 characters of letters, numbers, and underscores, separated by single dots or dashes.")
+    )]
+    InvalidId(String),
+
+    #[error("`{src}` in the nuspec's <files> isn't a valid pattern.")]
+    #[diagnostic(code(turron::nupkg::bad_pattern))]
+    BadPattern {
+        src: String,
+        #[source]
+        source: glob::PatternError,
+    },
+
+    #[error("Can't put a file at `{target}` in the package: {reason}.")]
+    #[diagnostic(
+        code(turron::nupkg::bad_target),
+        help("Targets are relative paths inside the package, like `lib/net6.0/Foo.dll`.")
+    )]
+    BadTarget {
+        target: String,
+        reason: &'static str,
+    },
+
+    #[error("More than one file would go at `{0}` in the package.")]
+    #[diagnostic(
+        code(turron::nupkg::duplicate_target),
+        help("Paths inside a package are compared ignoring case. Give one of the files a different target, or exclude it.")
+    )]
+    DuplicateTarget(String),
+
+    #[error("The nuspec's <{field}> is `{path}`, but the package has no such file.")]
+    #[diagnostic(
+        code(turron::nupkg::missing_file),
+        help("Add the file to the nuspec's <files>, or fix the path.")
+    )]
+    MissingFile { field: &'static str, path: String },
+
+    #[error(transparent)]
+    #[diagnostic(code(turron::nupkg::zip_error))]
+    ZipError(#[from] zip::result::ZipError),
+
+    #[error(transparent)]
+    #[diagnostic(code(turron::nupkg::normalize_failed))]
+    NormalizeFailed(#[from] DotnetError),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::nupkg::io_error",
+        cause: "A file that should go into the package, or the nuspec itself, couldn't be read.",
+        fixes: &[
+            "Check that the file exists and is readable. `<file src>` paths are relative to the nuspec's directory.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::write_failed",
+        cause: "The package was built, but couldn't be written to the output directory.",
+        fixes: &[
+            "Check that the output directory exists and is writable, or pick another one with --output.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::bad_nuspec",
+        cause: "The file passed to `turron pack --nuspec` couldn't be read as a nuspec.",
+        fixes: &[
+            "Make sure it's well-formed XML with a <package> root and a <metadata> element.",
+            "Make sure <id>, <version>, <authors>, and <description> are all there, and that <version> is a valid version.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::invalid_nuspec",
+        cause: "The nuspec was read, but can't be written into a package as it is, usually because a required field is blank or a value has characters XML can't hold.",
+        fixes: &["Fix the field the error names."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::invalid_id",
+        cause: "The nuspec's <id> isn't one nuget.org would accept. Ids are at most 100 characters, made of letters, numbers, and underscores, with single dots or dashes between them.",
+        fixes: &["Rename the package to something like `Contoso.Utilities`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::bad_pattern",
+        cause: "A `src` or `exclude` in the nuspec's <files> isn't a valid wildcard pattern.",
+        fixes: &[
+            "Use `*` to match within a directory, `**` to match any number of directories, and `?` to match a single character.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::bad_target",
+        cause: "A file would be put somewhere a package can't have one: outside of the package, at an absolute path, or on top of the parts every package uses for its own bookkeeping (`_rels/`, `package/`, `[Content_Types].xml`, and the nuspec).",
+        fixes: &[
+            "Give the file a relative `target` inside the package, like `lib/net6.0` or `content/`.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::duplicate_target",
+        cause: "Two files would end up at the same path in the package. Package paths are compared ignoring case, so `README.md` and `readme.md` collide too.",
+        fixes: &[
+            "Change one of the `target`s in the nuspec's <files>.",
+            "Add an `exclude` to the <file> that picks up the extra copy.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::missing_file",
+        cause: "The nuspec's <readme>, <icon>, or file <license> points at a file that isn't in the package. nuget.org rejects packages like that.",
+        fixes: &[
+            "Add the file to the nuspec's <files>, with a `target` matching the path the metadata uses.",
+            "Fix the path in the metadata.",
+        ],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::zip_error",
+        cause: "Something went wrong writing the package's zip archive.",
+        fixes: &["This is a bug in turron, and should be reported."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::nupkg::normalize_failed",
+        cause: "The package was built, but normalizing it so that the same inputs always give the same bytes failed.",
+        fixes: &["This is a bug in turron, and should be reported."],
+        config: &[],
+    },
+];
@@ -0,0 +1,673 @@
+//! Building .nupkgs straight from a nuspec, without the dotnet CLI. Good for
+//! packages that are just content, tool manifests, or the output of
+//! toolchains other than MSBuild.
+//!
+//! Packages are laid out the way `nuget pack` lays them out: the nuspec at
+//! the root, every file at its target path, and the OPC parts every package
+//! has (`[Content_Types].xml`, `_rels/.rels`, and a core properties part).
+//! They're then run through [`turron_dotnet::normalize`], so the same inputs
+//! always give the same bytes.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use nuget_api::v3::{NuSpec, NuSpecFile, NuSpecMetadata};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use turron_common::{paths::sanitize_filename, quick_xml, regex::Regex};
+use zip::{write::FileOptions, ZipWriter};
+
+pub use errors::{NupkgError, EXPLANATIONS};
+
+mod errors;
+
+/// Where the core properties part starts out. [`turron_dotnet::normalize`]
+/// renames it after a hash of its contents.
+const CORE_PROPERTIES: &str = "package/services/metadata/core-properties/core.psmdcp";
+const RELS: &str = "_rels/.rels";
+const CONTENT_TYPES: &str = "[Content_Types].xml";
+
+/// What gets escaped in entry names: everything but unreserved characters
+/// and `/`, like `Uri.EscapeDataString` on each path segment.
+const ENTRY_NAME: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Reads a nuspec from disk, `<files>` and all.
+pub fn read_nuspec(path: &Path) -> Result<NuSpec, NupkgError> {
+    let xml = fs::read_to_string(path).map_err(|e| NupkgError::IoError(path.into(), e))?;
+    quick_xml::de::from_str(&xml).map_err(|e| NupkgError::BadNuSpec(path.into(), e))
+}
+
+/// What `nuget pack` names the package: `<id>.<version>.nupkg`, without the
+/// version's build metadata.
+pub fn nupkg_file_name(nuspec: &NuSpec) -> String {
+    let mut version = nuspec.metadata.version.clone();
+    version.build.clear();
+    sanitize_filename(&format!("{}.{}.nupkg", nuspec.metadata.id, version))
+}
+
+/// The files a nuspec's `<files>` picks out, as `(path on disk, target in
+/// the package)` pairs. `src` and `exclude` patterns are relative to `base`,
+/// which is normally the nuspec's directory.
+///
+/// Like `nuget pack`, a wildcard `src` keeps each match's path below the
+/// part of the pattern before the first wildcard, under `target`. A plain
+/// `src` goes inside `target`, unless `target` has the same extension, in
+/// which case it's the file's new name. With no `target`, files keep their
+/// path relative to `base`. And without a `<files>` at all, everything
+/// under `base` is packed, except hidden files, .nupkgs, and nuspecs.
+pub fn resolve_files(nuspec: &NuSpec, base: &Path) -> Result<Vec<(PathBuf, String)>, NupkgError> {
+    // `Path::parent` gives an empty path for bare file names.
+    let base = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base
+    };
+    let mut found = Vec::new();
+    match &nuspec.files {
+        Some(files) => {
+            for file in &files.files {
+                found.extend(resolve_file(file, base)?);
+            }
+        }
+        None => {
+            walk(base, base, &mut found)?;
+            found.sort_by(|a, b| a.1.cmp(&b.1));
+        }
+    }
+    Ok(found)
+}
+
+fn resolve_file(file: &NuSpecFile, base: &Path) -> Result<Vec<(PathBuf, String)>, NupkgError> {
+    let src = file.src.replace('\\', "/");
+    let target = file
+        .target
+        .as_deref()
+        .unwrap_or_default()
+        .replace('\\', "/");
+    let target = target.trim_end_matches('/');
+    let is_wildcard = |segment: &str| segment.contains(|c| c == '*' || c == '?');
+    if !is_wildcard(&src) {
+        let name = src.rsplit('/').next().unwrap_or(&src);
+        let target = if target.is_empty() {
+            src.clone()
+        } else if extension(target).is_some() && extension(target) == extension(name) {
+            target.into()
+        } else {
+            format!("{}/{}", target, name)
+        };
+        return Ok(vec![(base.join(&src), target)]);
+    }
+
+    let excludes = file
+        .exclude
+        .iter()
+        .flat_map(|exclude| exclude.split(';'))
+        .map(str::trim)
+        .filter(|exclude| !exclude.is_empty())
+        .map(|exclude| {
+            glob::Pattern::new(&exclude.replace('\\', "/")).map_err(|source| {
+                NupkgError::BadPattern {
+                    src: exclude.into(),
+                    source,
+                }
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let literal = src
+        .split('/')
+        .take_while(|segment| !is_wildcard(segment))
+        .collect::<Vec<_>>()
+        .join("/");
+    // A trailing `**` means every file below, not just directories.
+    let pattern = if src.ends_with("**") {
+        format!("{}/*", src)
+    } else {
+        src.clone()
+    };
+    let pattern = format!(
+        "{}/{}",
+        glob::Pattern::escape(&base.to_string_lossy()),
+        pattern
+    );
+    let paths = glob::glob(&pattern).map_err(|source| NupkgError::BadPattern {
+        src: file.src.clone(),
+        source,
+    })?;
+    let mut found = Vec::new();
+    for path in paths {
+        let path = path.map_err(|e| NupkgError::IoError(e.path().into(), e.into_error()))?;
+        if !path.is_file() {
+            continue;
+        }
+        let relative = relative(base, &path);
+        if excludes.iter().any(|exclude| exclude.matches(&relative)) {
+            continue;
+        }
+        let target = if target.is_empty() {
+            relative
+        } else {
+            let below = relative
+                .strip_prefix(&literal)
+                .unwrap_or(&relative)
+                .trim_start_matches('/');
+            format!("{}/{}", target, below)
+        };
+        found.push((path, target));
+    }
+    Ok(found)
+}
+
+/// Everything under `dir` that `nuget pack` would pack without a `<files>`.
+fn walk(base: &Path, dir: &Path, found: &mut Vec<(PathBuf, String)>) -> Result<(), NupkgError> {
+    let entries = fs::read_dir(dir).map_err(|e| NupkgError::IoError(dir.into(), e))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| NupkgError::IoError(dir.into(), e))?
+            .path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.starts_with('.') || name.ends_with(".nupkg") || name.ends_with(".nuspec") {
+            continue;
+        }
+        if path.is_dir() {
+            walk(base, &path, found)?;
+        } else {
+            found.push((path.clone(), relative(base, &path)));
+        }
+    }
+    Ok(())
+}
+
+/// `path` relative to `base`, with `/` separators.
+fn relative(base: &Path, path: &Path) -> String {
+    path.strip_prefix(base)
+        .unwrap_or(path)
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn extension(name: &str) -> Option<String> {
+    let name = name.rsplit('/').next().unwrap_or(name);
+    name.rfind('.')
+        .filter(|dot| *dot > 0)
+        .map(|dot| name[dot + 1..].to_lowercase())
+}
+
+/// Builds a package out of `nuspec` and `files`, and writes it to `out`.
+/// `files` are `(path on disk, target in the package)` pairs, like the ones
+/// [`resolve_files`] returns.
+pub fn build_nupkg(
+    nuspec: &NuSpec,
+    files: &[(PathBuf, String)],
+    out: &Path,
+) -> Result<(), NupkgError> {
+    let mut contents = Vec::with_capacity(files.len());
+    for (path, target) in files {
+        let data = fs::read(path).map_err(|e| NupkgError::IoError(path.clone(), e))?;
+        contents.push((target.clone(), data));
+    }
+    let nupkg = nupkg_bytes(nuspec, contents)?;
+    fs::write(out, nupkg).map_err(|e| NupkgError::WriteFailed(out.into(), e))
+}
+
+/// Like [`build_nupkg`], for files that are already in memory. Returns the
+/// package's bytes.
+///
+/// Fails on anything nuget.org would reject: an invalid id, files outside
+/// of the package or on top of its packaging parts, two files at the same
+/// path (ignoring case), and a `<readme>`, `<icon>`, or file `<license>`
+/// that isn't in the package.
+pub fn nupkg_bytes(nuspec: &NuSpec, files: Vec<(String, Vec<u8>)>) -> Result<Vec<u8>, NupkgError> {
+    let metadata = &nuspec.metadata;
+    check_id(&metadata.id)?;
+    // Nuspecs inside packages don't say what to pack.
+    let manifest = NuSpec {
+        metadata: metadata.clone(),
+        files: None,
+    }
+    .to_xml_string()?;
+    let manifest_name = format!("{}.nuspec", metadata.id);
+
+    let mut seen = HashSet::new();
+    seen.insert(manifest_name.to_lowercase());
+    let mut parts = Vec::with_capacity(files.len() + 4);
+    for (target, data) in files {
+        let target = check_target(&target)?;
+        if !seen.insert(target.to_lowercase()) {
+            return Err(NupkgError::DuplicateTarget(target));
+        }
+        parts.push((target, data));
+    }
+    check_referenced(metadata, &seen)?;
+
+    parts.push((manifest_name.clone(), manifest.into_bytes()));
+    parts.push((
+        CORE_PROPERTIES.into(),
+        core_properties(metadata).into_bytes(),
+    ));
+    let names = parts
+        .iter()
+        .map(|(name, _)| entry_name(name))
+        .collect::<Vec<_>>();
+    parts.push((RELS.into(), rels(&manifest_name).into_bytes()));
+    parts.push((CONTENT_TYPES.into(), content_types(&names).into_bytes()));
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in parts {
+        zip.start_file(entry_name(&name), FileOptions::default())?;
+        zip.write_all(&data).map_err(zip::result::ZipError::from)?;
+    }
+    Ok(turron_dotnet::normalize(&zip.finish()?.into_inner())?)
+}
+
+/// nuget.org's rules for ids: at most 100 characters, made of word
+/// characters with single dots or dashes between them.
+fn check_id(id: &str) -> Result<(), NupkgError> {
+    let valid = Regex::new(r"^\w+([.-]\w+)*$").expect("TURRON BUG: oops, bad regex?");
+    if id.chars().count() > 100 || !valid.is_match(id) {
+        return Err(NupkgError::InvalidId(id.into()));
+    }
+    Ok(())
+}
+
+/// `target` with `/` separators, if a file can go there.
+fn check_target(target: &str) -> Result<String, NupkgError> {
+    let normalized = target.replace('\\', "/");
+    let lower = normalized.to_lowercase();
+    let reason = if normalized.is_empty() {
+        Some("it's empty")
+    } else if normalized.starts_with('/') || normalized.get(1..2) == Some(":") {
+        Some("it's an absolute path")
+    } else if normalized
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        Some("it has an empty, `.`, or `..` path segment")
+    } else if lower.starts_with("_rels/")
+        || lower.starts_with("package/")
+        || lower == CONTENT_TYPES.to_lowercase()
+    {
+        Some("that's where the package's own packaging files go")
+    } else if !lower.contains('/') && lower.ends_with(".nuspec") {
+        Some("the package's own nuspec is the only one allowed at the root")
+    } else {
+        None
+    };
+    match reason {
+        Some(reason) => Err(NupkgError::BadTarget {
+            target: target.into(),
+            reason,
+        }),
+        None => Ok(normalized),
+    }
+}
+
+/// Makes sure the files the metadata points at are in the package. `seen`
+/// holds every path in the package, lowercased.
+fn check_referenced(metadata: &NuSpecMetadata, seen: &HashSet<String>) -> Result<(), NupkgError> {
+    let referenced = [
+        ("readme", metadata.readme.as_deref()),
+        ("icon", metadata.icon.as_deref()),
+        ("license", metadata.license_file()),
+    ];
+    for &(field, path) in referenced.iter() {
+        if let Some(path) = path {
+            let normalized = path.replace('\\', "/").to_lowercase();
+            if !seen.contains(normalized.trim_start_matches("./")) {
+                return Err(NupkgError::MissingFile {
+                    field,
+                    path: path.into(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn entry_name(path: &str) -> String {
+    utf8_percent_encode(path, ENTRY_NAME).to_string()
+}
+
+/// Minimal XML text escaping. Anything that can't be escaped has already
+/// been turned away by [`NuSpec::to_xml_string`].
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn core_properties(metadata: &NuSpecMetadata) -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<coreProperties xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:dcterms=\"http://purl.org/dc/terms/\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xmlns=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\">",
+    ));
+    let mut element = |name: &str, value: &str| {
+        xml.push_str(&format!("<{0}>{1}</{0}>", name, escape(value)));
+    };
+    element("dc:creator", &metadata.authors);
+    element("dc:description", &metadata.description);
+    element("dc:identifier", &metadata.id);
+    element("version", &metadata.version.to_string());
+    if let Some(title) = &metadata.title {
+        element("dc:title", title);
+    }
+    if let Some(tags) = &metadata.tags {
+        element("keywords", tags);
+    }
+    element("lastModifiedBy", "turron");
+    xml.push_str("</coreProperties>");
+    xml
+}
+
+fn rels(manifest_name: &str) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+            "<Relationship Type=\"http://schemas.microsoft.com/packaging/2010/07/manifest\" Target=\"/{}\" Id=\"R1\" />",
+            "<Relationship Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" Target=\"/{}\" Id=\"R2\" />",
+            "</Relationships>"
+        ),
+        entry_name(manifest_name),
+        CORE_PROPERTIES
+    )
+}
+
+/// A `Default` for every extension in `names`, and an `Override` for each
+/// name without one.
+fn content_types(names: &[String]) -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+        "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">",
+        "<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\" />",
+        "<Default Extension=\"psmdcp\" ContentType=\"application/vnd.openxmlformats-package.core-properties+xml\" />",
+    ));
+    let mut extensions = vec!["rels".to_string(), "psmdcp".to_string()];
+    for name in names {
+        match extension(name) {
+            Some(ext) if extensions.contains(&ext) => {}
+            Some(ext) => {
+                xml.push_str(&format!(
+                    "<Default Extension=\"{}\" ContentType=\"application/octet\" />",
+                    ext
+                ));
+                extensions.push(ext);
+            }
+            None => xml.push_str(&format!(
+                "<Override PartName=\"/{}\" ContentType=\"application/octet\" />",
+                name
+            )),
+        }
+    }
+    xml.push_str("</Types>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tempfile::tempdir;
+    use turron_testing::fixtures;
+    use zip::ZipArchive;
+
+    use super::*;
+
+    fn tool_nuspec() -> NuSpec {
+        quick_xml::de::from_str(fixtures::tool_nuspec()).unwrap()
+    }
+
+    /// Everything the tool nuspec's metadata points at.
+    fn referenced_files() -> Vec<(String, Vec<u8>)> {
+        vec![
+            ("LICENSE.txt".into(), b"MIT".to_vec()),
+            ("images/icon.png".into(), b"\x89PNG".to_vec()),
+            ("docs/README.md".into(), b"# Turron.Tool".to_vec()),
+        ]
+    }
+
+    fn entries(nupkg: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut zip = ZipArchive::new(Cursor::new(nupkg)).unwrap();
+        (0..zip.len())
+            .map(|i| {
+                let mut file = zip.by_index(i).unwrap();
+                let mut data = Vec::new();
+                file.read_to_end(&mut data).unwrap();
+                (file.name().to_string(), data)
+            })
+            .collect()
+    }
+
+    fn text<'a>(entries: &'a [(String, Vec<u8>)], name: &str) -> &'a str {
+        let (_, data) = entries.iter().find(|(n, _)| n == name).unwrap();
+        std::str::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn builds_packages_we_can_read_back() {
+        let mut files = referenced_files();
+        files.push(("tools/net6.0/any/Turron.Tool.dll".into(), b"MZ".to_vec()));
+        files.push(("content/My File#1.txt".into(), b"hi".to_vec()));
+        files.push(("tools\\NOTICE".into(), b"notice".to_vec()));
+        let nupkg = nupkg_bytes(&tool_nuspec(), files.clone()).unwrap();
+        assert!(turron_dotnet::inspect(&nupkg).unwrap().is_empty());
+        assert_eq!(nupkg_bytes(&tool_nuspec(), files).unwrap(), nupkg);
+
+        let entries = entries(&nupkg);
+        let names = entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !name.starts_with("package/"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "LICENSE.txt",
+                "Turron.Tool.nuspec",
+                "[Content_Types].xml",
+                "_rels/.rels",
+                "content/My%20File%231.txt",
+                "docs/README.md",
+                "images/icon.png",
+                "tools/NOTICE",
+                "tools/net6.0/any/Turron.Tool.dll",
+            ]
+        );
+
+        let nuspec: NuSpec = quick_xml::de::from_str(text(&entries, "Turron.Tool.nuspec")).unwrap();
+        assert_eq!(nuspec.metadata, tool_nuspec().metadata);
+        assert!(nuspec.files.is_none());
+
+        let content_types = text(&entries, "[Content_Types].xml");
+        for ext in &["nuspec", "txt", "png", "md", "dll"] {
+            assert!(
+                content_types.contains(&format!("<Default Extension=\"{}\"", ext)),
+                "{}",
+                content_types
+            );
+        }
+        assert!(content_types.contains("<Override PartName=\"/tools/NOTICE\""));
+
+        let psmdcp = entries
+            .iter()
+            .find(|(name, _)| name.ends_with(".psmdcp"))
+            .unwrap();
+        let core_properties = std::str::from_utf8(&psmdcp.1).unwrap();
+        assert!(core_properties.contains("<dc:identifier>Turron.Tool</dc:identifier>"));
+        assert!(core_properties.contains("<keywords>tool cli &quot;quoted&quot;</keywords>"));
+        let rels = text(&entries, "_rels/.rels");
+        assert!(rels.contains("Target=\"/Turron.Tool.nuspec\""));
+        assert!(rels.contains(&format!("Target=\"/{}\"", psmdcp.0)));
+    }
+
+    #[test]
+    fn resolves_nuspec_files() {
+        let dir = tempdir().unwrap();
+        for file in &[
+            "bin/Release/Turron.Tool.dll",
+            "bin/Release/runtimes/win/native.dll",
+            "src/Program.cs",
+            "src/Commands/Run.cs",
+            "src/Commands/Run.pdb",
+            "LICENSE.txt",
+        ] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+        let found = resolve_files(&tool_nuspec(), dir.path()).unwrap();
+        let mut targets = found
+            .iter()
+            .map(|(path, target)| (relative(dir.path(), path), target.as_str()))
+            .collect::<Vec<_>>();
+        targets.sort();
+        assert_eq!(
+            targets,
+            vec![
+                ("LICENSE.txt".into(), "LICENSE.txt"),
+                (
+                    "bin/Release/Turron.Tool.dll".into(),
+                    "tools/Turron.Tool.dll"
+                ),
+                (
+                    "bin/Release/runtimes/win/native.dll".into(),
+                    "tools/runtimes/win/native.dll"
+                ),
+                ("src/Commands/Run.cs".into(), "src/Commands/Run.cs"),
+                ("src/Program.cs".into(), "src/Program.cs"),
+            ]
+        );
+
+        // Plain files go inside targets that look like directories, and
+        // are renamed by ones that look like files.
+        let file = |src: &str, target: &str| NuSpecFile {
+            src: src.into(),
+            target: Some(target.into()),
+            exclude: None,
+        };
+        let resolved = |file: NuSpecFile| resolve_file(&file, dir.path()).unwrap()[0].1.clone();
+        assert_eq!(resolved(file("LICENSE.txt", "docs")), "docs/LICENSE.txt");
+        assert_eq!(resolved(file("LICENSE.txt", "docs\\")), "docs/LICENSE.txt");
+        assert_eq!(
+            resolved(file("LICENSE.txt", "docs/COPYING.txt")),
+            "docs/COPYING.txt"
+        );
+    }
+
+    #[test]
+    fn packs_everything_without_a_files_element() {
+        let dir = tempdir().unwrap();
+        for file in &[
+            "Foo.nuspec",
+            "Foo.1.0.0.nupkg",
+            ".git/config",
+            "content/a.txt",
+            "tools/install.ps1",
+        ] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+        let mut nuspec = tool_nuspec();
+        nuspec.files = None;
+        let targets = resolve_files(&nuspec, dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|(_, target)| target)
+            .collect::<Vec<_>>();
+        assert_eq!(targets, vec!["content/a.txt", "tools/install.ps1"]);
+    }
+
+    #[test]
+    fn writes_packages_to_disk() {
+        let dir = tempdir().unwrap();
+        let license = dir.path().join("LICENSE.txt");
+        fs::write(&license, "MIT").unwrap();
+        let mut nuspec = tool_nuspec();
+        nuspec.metadata.readme = None;
+        nuspec.metadata.icon = None;
+        let name = nupkg_file_name(&nuspec);
+        assert_eq!(name, "Turron.Tool.1.2.0-beta.1.nupkg");
+        let out = dir.path().join(&name);
+        build_nupkg(&nuspec, &[(license, "LICENSE.txt".into())], &out).unwrap();
+        let entries = entries(&fs::read(&out).unwrap());
+        assert_eq!(text(&entries, "LICENSE.txt"), "MIT");
+
+        let missing = dir.path().join("nope.txt");
+        assert!(matches!(
+            build_nupkg(&nuspec, &[(missing.clone(), "nope.txt".into())], &out),
+            Err(NupkgError::IoError(path, _)) if path == missing
+        ));
+    }
+
+    #[test]
+    fn rejects_what_nuget_org_would() {
+        let build = |nuspec: &NuSpec, extra: &[&str]| {
+            let mut files = referenced_files();
+            files.extend(extra.iter().map(|name| (name.to_string(), Vec::new())));
+            nupkg_bytes(nuspec, files)
+        };
+        let long = "a".repeat(101);
+        for id in &["Turron..Tool", "-Turron", "Turron Tool", long.as_str()] {
+            let mut nuspec = tool_nuspec();
+            nuspec.metadata.id = id.to_string();
+            assert!(
+                matches!(build(&nuspec, &[]), Err(NupkgError::InvalidId(_))),
+                "{}",
+                id
+            );
+        }
+
+        let nuspec = tool_nuspec();
+        for target in &[
+            "../escape.txt",
+            "/etc/passwd",
+            "C:\\Windows\\evil.dll",
+            "lib//Foo.dll",
+            "_rels/extra.rels",
+            "package/services/x.psmdcp",
+            "[content_types].xml",
+            "Other.nuspec",
+        ] {
+            assert!(
+                matches!(
+                    build(&nuspec, &[*target]),
+                    Err(NupkgError::BadTarget { .. })
+                ),
+                "{}",
+                target
+            );
+        }
+        // Nuspecs further down are just content.
+        assert!(build(&nuspec, &["content/Other.nuspec"]).is_ok());
+
+        assert!(matches!(
+            build(&nuspec, &["docs/readme.md"]),
+            Err(NupkgError::DuplicateTarget(target)) if target == "docs/readme.md"
+        ));
+        assert!(matches!(
+            build(&nuspec, &["turron.tool.nuspec"]),
+            Err(NupkgError::BadTarget { .. })
+        ));
+
+        let files = referenced_files()
+            .into_iter()
+            .filter(|(name, _)| name != "images/icon.png")
+            .collect();
+        assert!(matches!(
+            nupkg_bytes(&nuspec, files),
+            Err(NupkgError::MissingFile { field: "icon", path }) if path == "images\\icon.png"
+        ));
+    }
+}
@@ -1,6 +1,7 @@
 use dotnet_semver::SemverError;
 use nom::error::{ContextError, ErrorKind, FromExternalError, ParseError};
 use turron_common::{
+    explain::Explanation,
     miette::{self, Diagnostic},
     thiserror::{self, Error},
 };
@@ -114,6 +115,57 @@ pub enum SpecErrorKind {
     Other,
 }
 
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::spec::invalid_chars",
+        cause: "The package spec contained characters that can't appear in a package id or version, such as spaces or URL-encoded sequences.",
+        fixes: &["Package specs look like `Newtonsoft.Json`, `Newtonsoft.Json@13.0.1`, or `Newtonsoft.Json@[12,13)`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::spec::invalid_drive_letter",
+        cause: "The spec looked like a Windows path, but the drive letter wasn't a letter.",
+        fixes: &["Use a path like `C:\\path\\to\\package`, or a relative path."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::spec::invalid_git_host",
+        cause: "The spec used a git shorthand with a host turron doesn't know about, or the rest of the shorthand could not be parsed.",
+        fixes: &["Supported shorthands are `github:`, `gitlab:`, `gist:`, and `bitbucket:`. Use a full git URL for anything else."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::spec::invalid_semver",
+        cause: "The version or range after `@` in the spec is not a valid NuGet version or range. The nested error has the details.",
+        fixes: &["Versions look like `1.2.3`, and ranges like `[1.0,2.0)` or `1.*`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::spec::invalid_url",
+        cause: "The spec looked like a URL but could not be parsed as one.",
+        fixes: &["Include the scheme, e.g. `https://`, and check for typos."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::spec::invalid_semver_component",
+        cause: "A specific part of the version in the spec was malformed. The error names the part that failed.",
+        fixes: &["Versions look like `1.2.3`, and ranges like `[1.0,2.0)` or `1.*`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::spec::incomplete_semver",
+        cause: "The spec ended in the middle of a version, like `Foo@1.` or `Foo@[1.0,`.",
+        fixes: &["Finish the version, or close the range bracket."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::spec::other",
+        cause: "The spec parser failed without saying why.",
+        fixes: &["Please report the spec that caused it, since this error should be more specific."],
+        config: &[],
+    },
+];
+
 #[derive(Debug)]
 pub(crate) struct SpecParseError<I> {
     pub(crate) input: I,
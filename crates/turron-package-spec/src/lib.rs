@@ -6,7 +6,7 @@ use dotnet_semver::Range;
 use nom::combinator::all_consuming;
 use nom::Err;
 
-pub use crate::error::{PackageSpecError, SpecErrorKind};
+pub use crate::error::{PackageSpecError, SpecErrorKind, EXPLANATIONS};
 pub use crate::gitinfo::{GitHost, GitInfo};
 use crate::parsers::package;
 
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+
+use turron_common::{
+    explain::Explanation,
+    miette::{self, Diagnostic},
+    thiserror::{self, Error},
+};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum SuppressionsError {
+    #[error("Failed to read {}.", .0.display())]
+    #[diagnostic(code(turron::suppressions::io_error))]
+    IoError(PathBuf, #[source] std::io::Error),
+
+    #[error("Failed to parse turron-suppressions.kdl.")]
+    #[diagnostic(code(turron::suppressions::parse_error))]
+    ParseError(#[source] kdl::KdlError),
+
+    #[error("Unknown node `{0}` in turron-suppressions.kdl.")]
+    #[diagnostic(
+        code(turron::suppressions::unknown_node),
+        help("Top-level nodes are `advisory` and `license`. Inside them, use `package`, `allow`, `expires`, and `reason`.")
+    )]
+    UnknownNode(String),
+
+    #[error("`{0}` in turron-suppressions.kdl needs a string value.")]
+    #[diagnostic(code(turron::suppressions::missing_value))]
+    MissingValue(String),
+
+    #[error("The suppression for `{0}` has no `reason`.")]
+    #[diagnostic(
+        code(turron::suppressions::missing_reason),
+        help("Every suppression needs a `reason` explaining why the finding is acceptable.")
+    )]
+    MissingReason(String),
+
+    #[error("The license exception for `{0}` doesn't `allow` any licenses.")]
+    #[diagnostic(
+        code(turron::suppressions::no_licenses),
+        help("List the accepted licenses with `allow`, like `allow \"GPL-3.0-only\"`.")
+    )]
+    NoLicenses(String),
+
+    #[error("`{0}` is not a valid expiry date.")]
+    #[diagnostic(
+        code(turron::suppressions::bad_date),
+        help("Expiry dates look like `2024-12-31`.")
+    )]
+    BadDate(String),
+}
+
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "turron::suppressions::io_error",
+        cause: "turron-suppressions.kdl exists in the project root, but turron could not read it.",
+        fixes: &["Check the file's permissions."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::suppressions::parse_error",
+        cause: "turron-suppressions.kdl is not valid KDL.",
+        fixes: &["Look for unbalanced braces or unquoted strings."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::suppressions::unknown_node",
+        cause: "turron-suppressions.kdl contains a node turron doesn't recognize. Unknown nodes are errors rather than being skipped, so a typo can't silently drop a suppression.",
+        fixes: &["Fix the node name. The file is made of `advisory` and `license` blocks, which hold `package`, `allow`, `expires`, and `reason` nodes."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::suppressions::missing_value",
+        cause: "A node in turron-suppressions.kdl that needs a string, like the advisory id after `advisory` or the date after `expires`, doesn't have one.",
+        fixes: &["Add the missing value, quoted, right after the node name."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::suppressions::missing_reason",
+        cause: "An `advisory` or `license` block has no `reason`. Suppressions hide findings from security and license checks, so each one has to say why.",
+        fixes: &["Add a `reason \"...\"` node to the block explaining why the finding is acceptable."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::suppressions::no_licenses",
+        cause: "A `license` block names a package but doesn't say which of its licenses are accepted.",
+        fixes: &["Add an `allow` node listing the accepted license expressions, like `allow \"GPL-3.0-only\"`."],
+        config: &[],
+    },
+    Explanation {
+        code: "turron::suppressions::bad_date",
+        cause: "An `expires` node holds something that isn't a calendar date.",
+        fixes: &["Write the date as `YYYY-MM-DD`, like `expires \"2024-12-31\"`."],
+        config: &[],
+    },
+];
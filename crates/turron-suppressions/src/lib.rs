@@ -0,0 +1,479 @@
+//! Acknowledged security advisories and license exceptions, read from
+//! `turron-suppressions.kdl` in the project root:
+//!
+//! ```kdl
+//! advisory "GHSA-5crp-9r3c-p9vr" {
+//!     package "Newtonsoft.Json"
+//!     expires "2024-12-31"
+//!     reason "We never deserialize untrusted input."
+//! }
+//!
+//! license "Some.Package" {
+//!     allow "GPL-3.0-only"
+//!     reason "Only used by internal build tooling."
+//! }
+//! ```
+//!
+//! Advisories can be named by GHSA id, CVE id, or advisory URL. Leaving out
+//! `package` suppresses the advisory for every package it affects. A
+//! suppression stops counting once its `expires` date has passed, so
+//! findings come back on their own instead of being ignored forever.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use kdl::{KdlNode, KdlValue};
+use turron_common::{chrono::NaiveDate, serde::Serialize};
+
+pub use errors::{SuppressionsError, EXPLANATIONS};
+
+mod errors;
+
+/// Name of the suppressions file, relative to the project root.
+pub const SUPPRESSIONS_FILE: &str = "turron-suppressions.kdl";
+
+/// Everything listed in a suppressions file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Suppressions {
+    pub advisories: Vec<AdvisorySuppression>,
+    pub licenses: Vec<LicenseException>,
+}
+
+/// An `advisory` block.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdvisorySuppression {
+    /// GHSA id, CVE id, or advisory URL, as written.
+    pub advisory: String,
+    /// Only suppress the advisory for this package.
+    pub package: Option<String>,
+    pub expires: Option<NaiveDate>,
+    pub reason: String,
+}
+
+/// A `license` block.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LicenseException {
+    pub package: String,
+    /// License expressions accepted for `package`.
+    pub licenses: Vec<String>,
+    pub expires: Option<NaiveDate>,
+    pub reason: String,
+}
+
+/// Whether a finding was suppressed, and by what.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FindingStatus<'a, T> {
+    /// Nothing matched.
+    Active,
+    Suppressed {
+        suppression: &'a T,
+    },
+    /// A suppression matched, but its expiry date has passed.
+    Expired {
+        suppression: &'a T,
+    },
+}
+
+impl<'a, T> FindingStatus<'a, T> {
+    /// Whether the finding should still fail the command.
+    pub fn counts(&self) -> bool {
+        !matches!(self, FindingStatus::Suppressed { .. })
+    }
+
+    /// The matching suppression, expired or not.
+    pub fn suppression(&self) -> Option<&'a T> {
+        match self {
+            FindingStatus::Active => None,
+            FindingStatus::Suppressed { suppression } | FindingStatus::Expired { suppression } => {
+                Some(suppression)
+            }
+        }
+    }
+}
+
+impl Suppressions {
+    /// Reads `turron-suppressions.kdl` from `root`. A missing file means
+    /// nothing is suppressed.
+    pub fn load(root: &Path) -> Result<Self, SuppressionsError> {
+        let path = root.join(SUPPRESSIONS_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(SuppressionsError::IoError(path, e)),
+        }
+    }
+
+    /// Parses the contents of a suppressions file.
+    pub fn parse(contents: &str) -> Result<Self, SuppressionsError> {
+        let nodes = kdl::parse_document(contents).map_err(SuppressionsError::ParseError)?;
+        let mut suppressions = Self::default();
+        for node in &nodes {
+            match &node.name[..] {
+                "advisory" => suppressions.advisories.push(advisory_block(node)?),
+                "license" => suppressions.licenses.push(license_block(node)?),
+                _ => return Err(SuppressionsError::UnknownNode(node.name.clone())),
+            }
+        }
+        Ok(suppressions)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.advisories.is_empty() && self.licenses.is_empty()
+    }
+
+    /// Looks up an advisory affecting `package`. `ids` are the advisory's
+    /// GHSA and CVE ids, and `url` its advisory page, if known; a
+    /// suppression matching any of them applies.
+    pub fn advisory(
+        &self,
+        package: &str,
+        ids: &[&str],
+        url: Option<&str>,
+        today: NaiveDate,
+    ) -> FindingStatus<'_, AdvisorySuppression> {
+        let finding = advisory_keys(ids.iter().copied().chain(url));
+        status(
+            self.advisories.iter().filter(|s| {
+                s.package
+                    .as_ref()
+                    .map(|p| p.eq_ignore_ascii_case(package))
+                    .unwrap_or(true)
+                    && advisory_keys(Some(&s.advisory[..]))
+                        .iter()
+                        .any(|key| finding.contains(key))
+            }),
+            |s| s.expires,
+            today,
+        )
+    }
+
+    /// Looks up an exception for `package` being licensed under `license`.
+    pub fn license(
+        &self,
+        package: &str,
+        license: &str,
+        today: NaiveDate,
+    ) -> FindingStatus<'_, LicenseException> {
+        status(
+            self.licenses.iter().filter(|s| {
+                s.package.eq_ignore_ascii_case(package)
+                    && s.licenses
+                        .iter()
+                        .any(|l| l.trim().eq_ignore_ascii_case(license.trim()))
+            }),
+            |s| s.expires,
+            today,
+        )
+    }
+}
+
+/// Picks the best of the matching suppressions: any live one wins over an
+/// expired one. Expiry dates are inclusive.
+fn status<'a, T>(
+    matching: impl Iterator<Item = &'a T>,
+    expires: impl Fn(&T) -> Option<NaiveDate>,
+    today: NaiveDate,
+) -> FindingStatus<'a, T> {
+    let mut found = FindingStatus::Active;
+    for suppression in matching {
+        match expires(suppression) {
+            Some(date) if date < today => {
+                if let FindingStatus::Active = found {
+                    found = FindingStatus::Expired { suppression };
+                }
+            }
+            _ => return FindingStatus::Suppressed { suppression },
+        }
+    }
+    found
+}
+
+/// The ways an advisory can be referred to, normalized for comparison. URLs
+/// also count as the GHSA or CVE id they end in, so
+/// `https://github.com/advisories/GHSA-...` matches a plain `GHSA-...`.
+fn advisory_keys<'a>(ids: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut keys = Vec::new();
+    for id in ids {
+        let id = id.trim().trim_end_matches('/').to_lowercase();
+        if id.starts_with("http://") || id.starts_with("https://") {
+            if let Some(last) = id.rsplit('/').next() {
+                if last.starts_with("ghsa-") || last.starts_with("cve-") {
+                    keys.push(last.to_string());
+                }
+            }
+        }
+        keys.push(id);
+    }
+    keys
+}
+
+fn advisory_block(node: &KdlNode) -> Result<AdvisorySuppression, SuppressionsError> {
+    let advisory = string_value(node)?;
+    let mut package = None;
+    let mut expires = None;
+    let mut reason = None;
+    for child in &node.children {
+        match &child.name[..] {
+            "package" => package = Some(string_value(child)?),
+            "expires" => expires = Some(date_value(child)?),
+            "reason" => reason = Some(string_value(child)?),
+            _ => return Err(SuppressionsError::UnknownNode(child.name.clone())),
+        }
+    }
+    Ok(AdvisorySuppression {
+        reason: reason.ok_or_else(|| SuppressionsError::MissingReason(advisory.clone()))?,
+        advisory,
+        package,
+        expires,
+    })
+}
+
+fn license_block(node: &KdlNode) -> Result<LicenseException, SuppressionsError> {
+    let package = string_value(node)?;
+    let mut licenses = Vec::new();
+    let mut expires = None;
+    let mut reason = None;
+    for child in &node.children {
+        match &child.name[..] {
+            "allow" => {
+                for value in &child.values {
+                    match value {
+                        KdlValue::String(license) => licenses.push(license.clone()),
+                        _ => return Err(SuppressionsError::MissingValue(child.name.clone())),
+                    }
+                }
+            }
+            "expires" => expires = Some(date_value(child)?),
+            "reason" => reason = Some(string_value(child)?),
+            _ => return Err(SuppressionsError::UnknownNode(child.name.clone())),
+        }
+    }
+    if licenses.is_empty() {
+        return Err(SuppressionsError::NoLicenses(package));
+    }
+    Ok(LicenseException {
+        reason: reason.ok_or_else(|| SuppressionsError::MissingReason(package.clone()))?,
+        package,
+        licenses,
+        expires,
+    })
+}
+
+fn string_value(node: &KdlNode) -> Result<String, SuppressionsError> {
+    match node.values.first() {
+        Some(KdlValue::String(s)) if !s.trim().is_empty() => Ok(s.trim().into()),
+        _ => Err(SuppressionsError::MissingValue(node.name.clone())),
+    }
+}
+
+fn date_value(node: &KdlNode) -> Result<NaiveDate, SuppressionsError> {
+    let date = string_value(node)?;
+    NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|_| SuppressionsError::BadDate(date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    const FILE: &str = r#"
+advisory "GHSA-5crp-9r3c-p9vr" {
+    package "Newtonsoft.Json"
+    expires "2024-12-31"
+    reason "We never deserialize untrusted input."
+}
+advisory "https://nvd.nist.gov/vuln/detail/CVE-2024-0001" {
+    reason "Not reachable."
+}
+license "Some.Package" {
+    allow "GPL-3.0-only" "LGPL-2.1-only"
+    reason "Only used by internal build tooling."
+}
+"#;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn parses_suppressions() {
+        let parsed = Suppressions::parse(FILE).unwrap();
+        assert_eq!(
+            parsed.advisories[0],
+            AdvisorySuppression {
+                advisory: "GHSA-5crp-9r3c-p9vr".into(),
+                package: Some("Newtonsoft.Json".into()),
+                expires: Some(date("2024-12-31")),
+                reason: "We never deserialize untrusted input.".into(),
+            }
+        );
+        assert_eq!(parsed.advisories[1].package, None);
+        assert_eq!(
+            parsed.licenses,
+            vec![LicenseException {
+                package: "Some.Package".into(),
+                licenses: vec!["GPL-3.0-only".into(), "LGPL-2.1-only".into()],
+                expires: None,
+                reason: "Only used by internal build tooling.".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let err = |src| Suppressions::parse(src).unwrap_err();
+        assert!(matches!(
+            err("advisory \"GHSA-1\" {\n  expires \"2024-12-31\"\n}"),
+            SuppressionsError::MissingReason(id) if id == "GHSA-1"
+        ));
+        assert!(matches!(
+            err("advisory \"GHSA-1\" {\n  reason \"x\"\n  expires \"next tuesday\"\n}"),
+            SuppressionsError::BadDate(d) if d == "next tuesday"
+        ));
+        assert!(matches!(
+            err("advisory {\n  reason \"x\"\n}"),
+            SuppressionsError::MissingValue(node) if node == "advisory"
+        ));
+        assert!(matches!(
+            err("license \"Pkg\" {\n  reason \"x\"\n}"),
+            SuppressionsError::NoLicenses(pkg) if pkg == "Pkg"
+        ));
+        assert!(matches!(
+            err("advisory \"GHSA-1\" {\n  reason \"x\"\n  pacakge \"Pkg\"\n}"),
+            SuppressionsError::UnknownNode(node) if node == "pacakge"
+        ));
+        assert!(matches!(
+            err("advisories \"GHSA-1\""),
+            SuppressionsError::UnknownNode(node) if node == "advisories"
+        ));
+    }
+
+    #[test]
+    fn suppressions_expire() {
+        let parsed = Suppressions::parse(FILE).unwrap();
+        let lookup =
+            |today| parsed.advisory("Newtonsoft.Json", &["GHSA-5crp-9r3c-p9vr"], None, today);
+
+        let status = lookup(date("2024-12-31"));
+        assert!(matches!(status, FindingStatus::Suppressed { .. }));
+        assert!(!status.counts());
+
+        let status = lookup(date("2025-01-01"));
+        assert!(matches!(status, FindingStatus::Expired { .. }));
+        assert!(status.counts());
+        assert_eq!(status.suppression(), Some(&parsed.advisories[0]));
+    }
+
+    #[test]
+    fn live_suppressions_beat_expired_ones() {
+        let parsed = Suppressions::parse(
+            r#"
+advisory "GHSA-1" {
+    expires "2020-01-01"
+    reason "old"
+}
+advisory "ghsa-1" {
+    reason "new"
+}
+"#,
+        )
+        .unwrap();
+        let status = parsed.advisory("Pkg", &["GHSA-1"], None, date("2024-01-01"));
+        assert_eq!(
+            status,
+            FindingStatus::Suppressed {
+                suppression: &parsed.advisories[1]
+            }
+        );
+    }
+
+    #[test]
+    fn matches_advisories() {
+        let parsed = Suppressions::parse(FILE).unwrap();
+        let today = date("2024-01-01");
+
+        // Ids match case-insensitively, but only for the named package.
+        assert!(!parsed
+            .advisory("newtonsoft.json", &["ghsa-5crp-9r3c-p9vr"], None, today)
+            .counts());
+        assert!(parsed
+            .advisory("Other.Package", &["GHSA-5crp-9r3c-p9vr"], None, today)
+            .counts());
+
+        // A GHSA suppression matches an advisory only known by URL.
+        assert!(!parsed
+            .advisory(
+                "Newtonsoft.Json",
+                &[],
+                Some("https://github.com/advisories/GHSA-5crp-9r3c-p9vr"),
+                today
+            )
+            .counts());
+
+        // A URL suppression matches the CVE it points at, for any package.
+        assert!(!parsed
+            .advisory("Anything", &["GHSA-2", "CVE-2024-0001"], None, today)
+            .counts());
+        assert!(!parsed
+            .advisory(
+                "Anything",
+                &[],
+                Some("https://nvd.nist.gov/vuln/detail/CVE-2024-0001/"),
+                today
+            )
+            .counts());
+
+        assert_eq!(
+            parsed.advisory("Anything", &["CVE-2024-0002"], None, today),
+            FindingStatus::Active
+        );
+    }
+
+    #[test]
+    fn matches_licenses() {
+        let parsed = Suppressions::parse(FILE).unwrap();
+        let today = date("2024-01-01");
+        assert!(!parsed
+            .license("some.package", "gpl-3.0-only", today)
+            .counts());
+        assert!(!parsed
+            .license("Some.Package", "LGPL-2.1-only", today)
+            .counts());
+        assert!(parsed
+            .license("Some.Package", "AGPL-3.0-only", today)
+            .counts());
+        assert!(parsed
+            .license("Other.Package", "GPL-3.0-only", today)
+            .counts());
+    }
+
+    #[test]
+    fn loads_from_project_root() {
+        let dir = tempdir().unwrap();
+        assert!(Suppressions::load(dir.path()).unwrap().is_empty());
+        fs::write(dir.path().join(SUPPRESSIONS_FILE), FILE).unwrap();
+        let loaded = Suppressions::load(dir.path()).unwrap();
+        assert_eq!(loaded, Suppressions::parse(FILE).unwrap());
+    }
+
+    #[test]
+    fn serializes_statuses() {
+        let parsed = Suppressions::parse(FILE).unwrap();
+        let status = parsed.license("Some.Package", "GPL-3.0-only", date("2024-01-01"));
+        let json = turron_common::serde_json::to_value(&status).unwrap();
+        assert_eq!(json["status"], "suppressed");
+        assert_eq!(json["suppression"]["package"], "Some.Package");
+        assert_eq!(
+            json["suppression"]["expires"],
+            turron_common::serde_json::Value::Null
+        );
+        let status: FindingStatus<'_, LicenseException> = FindingStatus::Active;
+        assert_eq!(
+            turron_common::serde_json::to_value(&status).unwrap()["status"],
+            "active"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Responses recorded from nuget.org, trimmed down to a manageable size.
+
+use turron_common::serde_json::{self, Value};
+
+use crate::NupkgBuilder;
+
+/// Hosts that show up in recorded nuget.org responses.
+const NUGET_ORG_HOSTS: &[&str] = &[
+    "https://azuresearch-usnc.nuget.org",
+    "https://api.nuget.org",
+    "https://www.nuget.org",
+];
+
+/// The nuget.org service index (`https://api.nuget.org/v3/index.json`).
+pub fn service_index() -> Value {
+    parse(include_str!("../fixtures/service_index.json"))
+}
+
+/// The nuget.org service index, with every resource moved under `base`. See
+/// [`rebase`].
+pub fn service_index_at(base: &str) -> Value {
+    parse(&rebase(
+        include_str!("../fixtures/service_index.json"),
+        base,
+    ))
+}
+
+/// Search results for `q=newtonsoft.json&take=2`.
+pub fn search() -> Value {
+    parse(include_str!("../fixtures/search.json"))
+}
+
+/// Flat container version list for `Newtonsoft.Json`.
+pub fn flatcontainer_versions() -> Value {
+    parse(include_str!("../fixtures/flatcontainer_versions.json"))
+}
+
+/// The `Newtonsoft.Json` 13.0.1 nuspec.
+pub fn nuspec() -> &'static str {
+    include_str!("../fixtures/newtonsoft.json.nuspec")
+}
+
+/// The `xunit` 2.4.1 nuspec, trimmed down. Its dependencies have no
+/// target framework, and mix exact (`[2.4.1]`) and plain versions.
+pub fn xunit_nuspec() -> &'static str {
+    include_str!("../fixtures/xunit.nuspec")
+}
+
+/// A nuspec whose dependencies use every form of version NuGet accepts:
+/// open and half-open ranges, floating versions, plain versions, and none
+/// at all.
+pub fn ranges_nuspec() -> &'static str {
+    include_str!("../fixtures/ranges.nuspec")
+}
+
+/// A nuspec for a made-up .NET tool that uses every part of the format
+/// turron reads: package types, framework assemblies, reference groups,
+/// content files, and a `<files>` section for packing.
+pub fn tool_nuspec() -> &'static str {
+    include_str!("../fixtures/turron.tool.nuspec")
+}
+
+/// A small but valid .nupkg for `Turron.Test` 1.0.0, containing a nuspec, a
+/// README, and an empty `lib/netstandard2.0/Turron.Test.dll`.
+pub fn nupkg_minimal() -> Vec<u8> {
+    NupkgBuilder::new("Turron.Test", "1.0.0")
+        .description("A package for testing turron.")
+        .readme("README.md", "# Turron.Test\n\nHello, world.\n")
+        .file("lib/netstandard2.0/Turron.Test.dll", Vec::new())
+        .build()
+}
+
+/// `Turron.Test` 1.0.0 the way `dotnet pack` lays it out: [`nupkg_minimal`],
+/// plus OPC packaging files and a file whose name had to be escaped.
+pub fn nupkg_packed() -> Vec<u8> {
+    NupkgBuilder::new("Turron.Test", "1.0.0")
+        .description("A package for testing turron.")
+        .readme("README.md", "# Turron.Test\n\nHello, world.\n")
+        .file("lib/netstandard2.0/Turron.Test.dll", Vec::new())
+        .file("content/Read%20Me.txt", "Hello, world.\n")
+        .file("_rels/.rels", RELS)
+        .file(
+            "package/services/metadata/core-properties/0123456789abcdef.psmdcp",
+            "<coreProperties />",
+        )
+        .build()
+}
+
+/// Every file `dotnet restore` puts in the global packages folder for
+/// [`nupkg_packed`], one path per line, sorted.
+pub fn global_packages_listing() -> &'static str {
+    include_str!("../fixtures/turron.test.global-packages.txt")
+}
+
+/// A `.nupkg.metadata` laid out the way `dotnet restore` on Windows writes
+/// it: indented, with CRLF line endings. The hash isn't of any real package.
+pub fn nupkg_metadata() -> &'static str {
+    include_str!("../fixtures/nupkg.metadata")
+}
+
+const RELS: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+    "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    "<Relationship Type=\"http://schemas.microsoft.com/packaging/2010/07/manifest\" ",
+    "Target=\"/turron.test.nuspec\" Id=\"R0\" />",
+    "</Relationships>\n",
+);
+
+/// Replaces the scheme and host of every nuget.org URL in `text` with
+/// `base`, so `https://api.nuget.org/v3-flatcontainer/` becomes
+/// `{base}/v3-flatcontainer/`. Paths are left alone, so the rebased
+/// resources don't collide with each other.
+pub fn rebase(text: &str, base: &str) -> String {
+    let base = base.trim_end_matches('/');
+    NUGET_ORG_HOSTS
+        .iter()
+        .fold(text.to_string(), |text, host| text.replace(host, base))
+}
+
+fn parse(json: &str) -> Value {
+    serde_json::from_str(json).expect("TURRON BUG: fixture is not valid JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebases_every_resource() {
+        let index = service_index_at("http://127.0.0.1:1234/");
+        for resource in index["resources"].as_array().unwrap() {
+            let id = resource["@id"].as_str().unwrap();
+            assert!(id.starts_with("http://127.0.0.1:1234/"), "{}", id);
+        }
+        assert_eq!(
+            service_index()["resources"].as_array().unwrap().len(),
+            index["resources"].as_array().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn fixtures_load() {
+        assert_eq!(search()["totalHits"], 2);
+        assert_eq!(flatcontainer_versions()["versions"][3], "13.0.1");
+        assert!(nuspec().contains("<id>Newtonsoft.Json</id>"));
+        assert!(xunit_nuspec().contains("<id>xunit</id>"));
+        assert!(ranges_nuspec().contains("<id>Turron.Ranges</id>"));
+        assert!(tool_nuspec().contains("<id>Turron.Tool</id>"));
+    }
+}
@@ -0,0 +1,15 @@
+//! Test fixtures shared across the turron workspace: recorded nuget.org
+//! responses, builders for synthesizing registrations and nupkgs, a tiny
+//! local HTTP server to serve them from, and snapshots of rendered output.
+//!
+//! This crate is only meant to be used as a dev-dependency.
+
+pub mod fixtures;
+pub mod nupkg;
+pub mod registration;
+pub mod server;
+pub mod snapshot;
+
+pub use nupkg::NupkgBuilder;
+pub use registration::{Registration, RegistrationBuilder};
+pub use server::TestServer;
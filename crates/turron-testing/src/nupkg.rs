@@ -0,0 +1,151 @@
+//! Builds .nupkg files at test time, so binaries don't have to be committed.
+
+use std::io::{Cursor, Write};
+
+use zip::{write::FileOptions, ZipWriter};
+
+/// Builds a .nupkg in memory.
+#[derive(Debug, Clone)]
+pub struct NupkgBuilder {
+    id: String,
+    version: String,
+    description: String,
+    authors: String,
+    readme: Option<String>,
+    dependencies: Vec<(String, String)>,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl NupkgBuilder {
+    pub fn new(id: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            version: version.into(),
+            description: "Test package.".into(),
+            authors: "turron".into(),
+            readme: None,
+            dependencies: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    pub fn authors(mut self, authors: impl Into<String>) -> Self {
+        self.authors = authors.into();
+        self
+    }
+
+    /// Adds a dependency, with no target framework.
+    pub fn dependency(mut self, id: impl Into<String>, range: impl Into<String>) -> Self {
+        self.dependencies.push((id.into(), range.into()));
+        self
+    }
+
+    /// Adds a file and points the nuspec's `<readme>` at it.
+    pub fn readme(self, path: impl Into<String>, contents: impl Into<Vec<u8>>) -> Self {
+        let path = path.into();
+        let mut builder = self.file(path.clone(), contents);
+        builder.readme = Some(path);
+        builder
+    }
+
+    /// Adds an arbitrary file to the package.
+    pub fn file(mut self, path: impl Into<String>, contents: impl Into<Vec<u8>>) -> Self {
+        self.files.push((path.into(), contents.into()));
+        self
+    }
+
+    /// The nuspec that will be included in the package.
+    pub fn nuspec(&self) -> String {
+        let mut nuspec = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+                "<package xmlns=\"http://schemas.microsoft.com/packaging/2013/05/nuspec.xsd\">\n",
+                "  <metadata>\n",
+                "    <id>{}</id>\n",
+                "    <version>{}</version>\n",
+                "    <authors>{}</authors>\n",
+                "    <description>{}</description>\n",
+            ),
+            self.id, self.version, self.authors, self.description
+        );
+        if let Some(readme) = &self.readme {
+            nuspec.push_str(&format!("    <readme>{}</readme>\n", readme));
+        }
+        if !self.dependencies.is_empty() {
+            nuspec.push_str("    <dependencies>\n");
+            for (id, range) in &self.dependencies {
+                nuspec.push_str(&format!(
+                    "      <dependency id=\"{}\" version=\"{}\" />\n",
+                    id, range
+                ));
+            }
+            nuspec.push_str("    </dependencies>\n");
+        }
+        nuspec.push_str("  </metadata>\n</package>\n");
+        nuspec
+    }
+
+    /// Zips everything up.
+    pub fn build(&self) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let mut add = |path: &str, contents: &[u8]| {
+            zip.start_file(path, FileOptions::default())
+                .expect("TURRON BUG: failed to add file to test nupkg");
+            zip.write_all(contents)
+                .expect("TURRON BUG: failed to write file to test nupkg");
+        };
+        add(
+            &format!("{}.nuspec", self.id.to_lowercase()),
+            self.nuspec().as_bytes(),
+        );
+        add("[Content_Types].xml", CONTENT_TYPES.as_bytes());
+        for (path, contents) in &self.files {
+            add(path, contents);
+        }
+        zip.finish()
+            .expect("TURRON BUG: failed to finish test nupkg")
+            .into_inner()
+    }
+}
+
+const CONTENT_TYPES: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+    "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">",
+    "<Default Extension=\"nuspec\" ContentType=\"application/octet\" />",
+    "<Default Extension=\"dll\" ContentType=\"application/octet\" />",
+    "<Default Extension=\"md\" ContentType=\"application/octet\" />",
+    "</Types>\n",
+);
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    #[test]
+    fn builds_a_readable_zip() {
+        let nupkg = NupkgBuilder::new("Foo.Bar", "1.2.3")
+            .dependency("Baz", "[1.0.0, )")
+            .readme("docs/README.md", "hi")
+            .build();
+        let mut zip = ZipArchive::new(Cursor::new(nupkg)).unwrap();
+        let mut nuspec = String::new();
+        zip.by_name("foo.bar.nuspec")
+            .unwrap()
+            .read_to_string(&mut nuspec)
+            .unwrap();
+        assert!(nuspec.contains("<version>1.2.3</version>"));
+        assert!(nuspec.contains("<readme>docs/README.md</readme>"));
+        assert!(nuspec.contains("<dependency id=\"Baz\" version=\"[1.0.0, )\" />"));
+        assert!(zip.by_name("docs/README.md").is_ok());
+        assert!(zip.by_name("[Content_Types].xml").is_ok());
+    }
+}
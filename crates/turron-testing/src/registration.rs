@@ -0,0 +1,236 @@
+//! Synthesizes registration indexes, so pagination and resolution tests can
+//! generate their cases instead of hand-writing JSON.
+
+use turron_common::serde_json::{json, Value};
+
+/// Builds a registration index and its pages.
+#[derive(Debug, Clone)]
+pub struct RegistrationBuilder {
+    id: String,
+    versions: Vec<String>,
+    base: String,
+    content_base: String,
+    page_size: usize,
+    inline: bool,
+    dependencies: Vec<(Option<String>, String, String)>,
+}
+
+/// A generated registration.
+#[derive(Debug, Clone)]
+pub struct Registration {
+    /// URL of the index itself.
+    pub url: String,
+    /// The registration index.
+    pub index: Value,
+    /// URLs and bodies of pages that aren't inlined in the index. Empty when
+    /// leaves are inlined.
+    pub pages: Vec<(String, Value)>,
+}
+
+impl RegistrationBuilder {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            versions: Vec::new(),
+            base: "https://api.nuget.org/v3/registration5-gz-semver2/".into(),
+            content_base: "https://api.nuget.org/v3-flatcontainer/".into(),
+            page_size: 64,
+            inline: true,
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Base URL of the registration resource.
+    pub fn base_url(mut self, base: impl Into<String>) -> Self {
+        self.base = base.into();
+        self
+    }
+
+    /// Base URL of the flat container, for `packageContent` links.
+    pub fn content_base_url(mut self, base: impl Into<String>) -> Self {
+        self.content_base = base.into();
+        self
+    }
+
+    /// Adds specific versions. Leaves are emitted in the order given.
+    pub fn versions<I, S>(mut self, versions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.versions.extend(versions.into_iter().map(Into::into));
+        self
+    }
+
+    /// Adds `count` versions, `1.0.0` through `1.0.{count - 1}`.
+    pub fn leaves(self, count: usize) -> Self {
+        self.versions((0..count).map(|patch| format!("1.0.{}", patch)))
+    }
+
+    /// How many leaves go on each page. nuget.org uses 64.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Whether leaves are inlined into the index, or pages have to be
+    /// fetched separately. nuget.org inlines them for packages with fewer
+    /// than 128 versions.
+    pub fn inline_leaves(mut self, inline: bool) -> Self {
+        self.inline = inline;
+        self
+    }
+
+    /// Adds a dependency on `id` in `range` to every version, in the group
+    /// for `framework` (or the framework-agnostic group, if `None`).
+    pub fn dependency(
+        mut self,
+        framework: Option<&str>,
+        id: impl Into<String>,
+        range: impl Into<String>,
+    ) -> Self {
+        self.dependencies
+            .push((framework.map(String::from), id.into(), range.into()));
+        self
+    }
+
+    pub fn build(&self) -> Registration {
+        let base = format!(
+            "{}/{}",
+            self.base.trim_end_matches('/'),
+            self.id.to_lowercase()
+        );
+        let url = format!("{}/index.json", base);
+        let mut items = Vec::new();
+        let mut pages = Vec::new();
+        for chunk in self.versions.chunks(self.page_size) {
+            let lower = &chunk[0];
+            let upper = &chunk[chunk.len() - 1];
+            let page_url = format!("{}/page/{}/{}.json", base, lower, upper);
+            let leaves = chunk
+                .iter()
+                .map(|version| self.leaf(&base, version))
+                .collect::<Vec<_>>();
+            let mut page = json!({
+                "@id": page_url,
+                "count": chunk.len(),
+                "lower": lower,
+                "upper": upper,
+            });
+            if self.inline {
+                page["parent"] = json!(url);
+                page["items"] = json!(leaves);
+            } else {
+                let mut full = page.clone();
+                full["parent"] = json!(url);
+                full["items"] = json!(leaves);
+                pages.push((page_url, full));
+            }
+            items.push(page);
+        }
+        Registration {
+            index: json!({
+                "@id": url,
+                "count": items.len(),
+                "items": items,
+            }),
+            url,
+            pages,
+        }
+    }
+
+    fn leaf(&self, base: &str, version: &str) -> Value {
+        let id_lower = self.id.to_lowercase();
+        let version_lower = version.to_lowercase();
+        json!({
+            "@id": format!("{}/{}.json", base, version_lower),
+            "catalogEntry": {
+                "id": self.id,
+                "version": version,
+                "description": format!("{} {}", self.id, version),
+                "listed": true,
+                "dependencyGroups": self.dependency_groups(),
+            },
+            "packageContent": format!(
+                "{}/{}/{}/{}.{}.nupkg",
+                self.content_base.trim_end_matches('/'),
+                id_lower,
+                version_lower,
+                id_lower,
+                version_lower
+            ),
+        })
+    }
+
+    fn dependency_groups(&self) -> Vec<Value> {
+        let mut groups: Vec<(Option<&String>, Vec<Value>)> = Vec::new();
+        for (framework, id, range) in &self.dependencies {
+            let dep = json!({ "id": id, "range": range });
+            match groups.iter_mut().find(|(fw, _)| *fw == framework.as_ref()) {
+                Some((_, deps)) => deps.push(dep),
+                None => groups.push((framework.as_ref(), vec![dep])),
+            }
+        }
+        groups
+            .into_iter()
+            .map(|(framework, deps)| match framework {
+                Some(framework) => json!({ "targetFramework": framework, "dependencies": deps }),
+                None => json!({ "dependencies": deps }),
+            })
+            .collect()
+    }
+}
+
+impl Registration {
+    /// Every `(url, body)` pair that makes up this registration: the index,
+    /// then any separate pages.
+    pub fn documents(&self) -> Vec<(String, Value)> {
+        let mut documents = vec![(self.url.clone(), self.index.clone())];
+        documents.extend(self.pages.iter().cloned());
+        documents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inlined_pages() {
+        let reg = RegistrationBuilder::new("Foo")
+            .leaves(5)
+            .page_size(2)
+            .build();
+        assert_eq!(reg.index["count"], 3);
+        assert!(reg.pages.is_empty());
+        let last = &reg.index["items"][2];
+        assert_eq!(last["lower"], "1.0.4");
+        assert_eq!(last["items"][0]["catalogEntry"]["version"], "1.0.4");
+        assert_eq!(
+            last["items"][0]["packageContent"],
+            "https://api.nuget.org/v3-flatcontainer/foo/1.0.4/foo.1.0.4.nupkg"
+        );
+    }
+
+    #[test]
+    fn separate_pages() {
+        let reg = RegistrationBuilder::new("Foo")
+            .base_url("http://localhost/reg/")
+            .versions(vec!["1.0.0", "2.0.0-beta", "2.0.0"])
+            .page_size(2)
+            .inline_leaves(false)
+            .build();
+        assert_eq!(reg.url, "http://localhost/reg/foo/index.json");
+        assert!(reg.index["items"][0].get("items").is_none());
+        assert_eq!(reg.pages.len(), 2);
+        assert_eq!(
+            reg.pages[0].0,
+            "http://localhost/reg/foo/page/1.0.0/2.0.0-beta.json"
+        );
+        assert_eq!(
+            reg.pages[1].1["items"][0]["catalogEntry"]["version"],
+            "2.0.0"
+        );
+        assert_eq!(reg.documents().len(), 3);
+    }
+}
@@ -0,0 +1,378 @@
+//! A very small HTTP/1.1 server for tests that need to talk to a "source".
+
+use std::collections::{hash_map::DefaultHasher, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use turron_common::smol::{
+    self,
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    Timer,
+};
+
+use crate::fixtures;
+
+#[derive(Debug, Clone)]
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    routes: HashMap<String, Response>,
+    queued: HashMap<String, VecDeque<Response>>,
+    headers: HashMap<String, Vec<(String, String)>>,
+    delays: HashMap<String, Duration>,
+    requests: Vec<String>,
+    received_headers: Vec<Vec<(String, String)>>,
+    received_bodies: Vec<Vec<u8>>,
+    ignore_ranges: bool,
+    bytes_served: usize,
+}
+
+/// Serves canned responses on a random local port. Unknown paths get a 404.
+///
+/// Out of the box, `/v3/index.json` serves the recorded nuget.org service
+/// index with every resource pointed back at this server, so a client built
+/// from [`TestServer::index_url`] will send `/v3-flatcontainer/...`,
+/// `/v3/registration5-gz-semver2/...`, `/query`, and so on right back here.
+///
+/// Like a static file host, 200s come with an `ETag` (and a `304` for an
+/// `If-None-Match` that matches it), `HEAD` requests get headers only, and
+/// single `Range: bytes=...` requests get a `206` with just those bytes. See
+/// [`TestServer::ignore_ranges`] for hosts that don't do ranges.
+#[derive(Debug, Clone)]
+pub struct TestServer {
+    base: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TURRON BUG: failed to bind test server");
+        let base = format!(
+            "http://{}",
+            listener
+                .local_addr()
+                .expect("TURRON BUG: test server has no address")
+        );
+        let server = Self {
+            base,
+            state: Arc::new(Mutex::new(State::default())),
+        };
+        server.json("/v3/index.json", &fixtures::service_index_at(&server.base));
+        let state = server.state.clone();
+        smol::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                smol::spawn(handle(stream, state.clone())).detach();
+            }
+        })
+        .detach();
+        server
+    }
+
+    /// Base URL of the server, without a trailing slash.
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// Full URL for `path`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    /// URL of the service index.
+    pub fn index_url(&self) -> String {
+        self.url("/v3/index.json")
+    }
+
+    /// Serves `body` with a 200 at `path`. Query strings are ignored when
+    /// matching.
+    pub fn route(&self, path: impl Into<String>, body: impl Into<Vec<u8>>) -> &Self {
+        self.respond(path, 200, body)
+    }
+
+    /// Serves `body` as JSON at `path`.
+    pub fn json(&self, path: impl Into<String>, body: &impl ToString) -> &Self {
+        self.route(path, body.to_string())
+    }
+
+    /// Serves an arbitrary status at `path`.
+    pub fn respond(&self, path: impl Into<String>, status: u16, body: impl Into<Vec<u8>>) -> &Self {
+        self.state.lock().unwrap().routes.insert(
+            path.into(),
+            Response {
+                status,
+                body: body.into(),
+            },
+        );
+        self
+    }
+
+    /// Serves `status` at `path` for a single request, before falling back to
+    /// whatever else is set up there. Queued responses are served in the
+    /// order they were added.
+    pub fn respond_once(
+        &self,
+        path: impl Into<String>,
+        status: u16,
+        body: impl Into<Vec<u8>>,
+    ) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .queued
+            .entry(path.into())
+            .or_default()
+            .push_back(Response {
+                status,
+                body: body.into(),
+            });
+        self
+    }
+
+    /// Sends an extra header with every response at `path`, whatever its
+    /// status.
+    pub fn header(
+        &self,
+        path: impl Into<String>,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .headers
+            .entry(path.into())
+            .or_default()
+            .push((name.into(), value.into()));
+        self
+    }
+
+    /// Waits `delay` before answering each request to `path`, so tests can
+    /// have several requests for it in flight at once.
+    pub fn delay(&self, path: impl Into<String>, delay: Duration) -> &Self {
+        self.state.lock().unwrap().delays.insert(path.into(), delay);
+        self
+    }
+
+    /// Paths (with query strings) of every request received so far, in
+    /// order.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The value of the `name` header on each request to `path` so far, in
+    /// order, ignoring query strings. Requests without one get `None`.
+    pub fn request_headers(&self, path: &str, name: &str) -> Vec<Option<String>> {
+        let state = self.state.lock().unwrap();
+        state
+            .requests
+            .iter()
+            .zip(&state.received_headers)
+            .filter(|(req, _)| strip_query(req) == path)
+            .map(|(_, headers)| {
+                headers
+                    .iter()
+                    .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.clone())
+            })
+            .collect()
+    }
+
+    /// The body of each request to `path` so far, in order, ignoring query
+    /// strings.
+    pub fn request_bodies(&self, path: &str) -> Vec<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        state
+            .requests
+            .iter()
+            .zip(&state.received_bodies)
+            .filter(|(req, _)| strip_query(req) == path)
+            .map(|(_, body)| body.clone())
+            .collect()
+    }
+
+    /// Stops advertising and honoring `Range` requests, so everything gets
+    /// the whole body.
+    pub fn ignore_ranges(&self) -> &Self {
+        self.state.lock().unwrap().ignore_ranges = true;
+        self
+    }
+
+    /// Total response body bytes sent so far, across every request.
+    pub fn bytes_served(&self) -> usize {
+        self.state.lock().unwrap().bytes_served
+    }
+
+    /// How many requests were made to `path`, ignoring query strings.
+    pub fn hits(&self, path: &str) -> usize {
+        self.requests()
+            .iter()
+            .filter(|req| strip_query(req) == path)
+            .count()
+    }
+}
+
+async fn handle(stream: TcpStream, state: Arc<Mutex<State>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.clone());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut line = String::new();
+    let mut content_length = 0;
+    let mut range = None;
+    let mut if_none_match = None;
+    let mut received = Vec::new();
+    while reader.read_line(&mut line).await? > 2 {
+        let mut header = line.splitn(2, ':');
+        if let (Some(name), Some(value)) = (header.next(), header.next()) {
+            received.push((name.trim().to_string(), value.trim().to_string()));
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("range") {
+                range = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("if-none-match") {
+                if_none_match = Some(value.trim().to_string());
+            }
+        }
+        line.clear();
+    }
+    // Read the whole body (e.g. a push), both so tests can look at it and so
+    // closing the connection doesn't reset it before the client reads the
+    // response.
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+    let mut request_line = request_line.split(' ');
+    let method = request_line.next().unwrap_or("GET").to_string();
+    let path = request_line.next().unwrap_or("/").to_string();
+    let (response, headers, delay, ranges) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(path.clone());
+        state.received_headers.push(received);
+        state.received_bodies.push(body);
+        let key = strip_query(&path);
+        let response = state
+            .queued
+            .get_mut(key)
+            .and_then(|queue| queue.pop_front())
+            .or_else(|| state.routes.get(key).cloned());
+        let headers = state.headers.get(key).cloned().unwrap_or_default();
+        let delay = state.delays.get(key).copied();
+        (response, headers, delay, !state.ignore_ranges)
+    };
+    if let Some(delay) = delay {
+        Timer::after(delay).await;
+    }
+    let Response { mut status, body } = response.unwrap_or(Response {
+        status: 404,
+        body: Vec::new(),
+    });
+    let mut extra = String::new();
+    for (name, value) in headers {
+        extra.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    let mut body = &body[..];
+    if status == 200 {
+        let etag = etag(body);
+        extra.push_str(&format!("ETag: {}\r\n", etag));
+        if if_none_match.as_deref() == Some(&etag[..]) {
+            status = 304;
+            body = &[];
+        }
+    }
+    if status == 200 && ranges {
+        extra.push_str("Accept-Ranges: bytes\r\n");
+        if let Some(range) = range {
+            match parse_range(&range, body.len()) {
+                Some((start, end)) => {
+                    status = 206;
+                    extra.push_str(&format!(
+                        "Content-Range: bytes {}-{}/{}\r\n",
+                        start,
+                        end,
+                        body.len()
+                    ));
+                    body = &body[start..=end];
+                }
+                None => {
+                    status = 416;
+                    extra.push_str(&format!("Content-Range: bytes */{}\r\n", body.len()));
+                    body = &[];
+                }
+            }
+        }
+    }
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        status,
+        reason(status),
+        body.len(),
+        extra
+    );
+    let mut stream = stream;
+    stream.write_all(head.as_bytes()).await?;
+    if method != "HEAD" {
+        stream.write_all(body).await?;
+        state.lock().unwrap().bytes_served += body.len();
+    }
+    stream.flush().await
+}
+
+/// A strong ETag for `body`. Only needs to be stable within a test run.
+fn etag(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Parses a single `bytes=start-end`, `bytes=start-`, or `bytes=-suffix`
+/// range into inclusive offsets into a body `len` bytes long.
+fn parse_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let mut bounds = range.strip_prefix("bytes=")?.splitn(2, '-');
+    let (start, end) = (bounds.next()?.trim(), bounds.next()?.trim());
+    let (start, end) = match (start.parse::<usize>().ok(), end.parse::<usize>().ok()) {
+        (Some(start), Some(end)) => (start, end.min(len.checked_sub(1)?)),
+        (Some(start), None) if end.is_empty() => (start, len.checked_sub(1)?),
+        (None, Some(suffix)) if start.is_empty() && suffix > 0 => {
+            (len.saturating_sub(suffix), len.checked_sub(1)?)
+        }
+        _ => return None,
+    };
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+fn strip_query(path: &str) -> &str {
+    path.split('?').next().unwrap_or(path)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        416 => "Range Not Satisfiable",
+        429 => "Too Many Requests",
+        451 => "Unavailable For Legal Reasons",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,183 @@
+//! Golden-file snapshots of terminal output, in the style of `insta`.
+//!
+//! Commands render into a buffer at a fixed [`WIDTH`], colors are stripped,
+//! and anything that changes from run to run (timestamps, "3 days ago",
+//! `12.3ms`) is redacted. The result is compared against
+//! `snapshots/<name>.snap` in the calling crate.
+//!
+//! When a snapshot doesn't match, the new output is written next to it as
+//! `<name>.snap.new` and the test fails. Run the tests again with
+//! `TURRON_UPDATE_SNAPSHOTS=1` to accept the changes, then review the diff
+//! like any other.
+
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+use turron_common::regex::Regex;
+
+/// How many columns output gets to render into, whatever the terminal
+/// running the tests is.
+pub const WIDTH: usize = 80;
+
+/// Set to `1` to record snapshots instead of checking them.
+pub const UPDATE_VAR: &str = "TURRON_UPDATE_SNAPSHOTS";
+
+/// Compares `$actual` against `snapshots/$name.snap` in the crate the test
+/// is in. See the [module docs](crate::snapshot).
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($name:expr, $actual:expr) => {
+        $crate::snapshot::assert_snapshot(env!("CARGO_MANIFEST_DIR"), $name, &$actual)
+    };
+}
+
+/// Calls `render` with a buffer and [`WIDTH`], and cleans up what it wrote
+/// for a snapshot: colors are stripped, and timestamps and durations are
+/// redacted.
+pub fn render<F, E>(render: F) -> String
+where
+    F: FnOnce(&mut Vec<u8>, usize) -> Result<(), E>,
+    E: Debug,
+{
+    let mut out = Vec::new();
+    render(&mut out, WIDTH).expect("Failed to render into a buffer");
+    let text = String::from_utf8(out).expect("Rendered output wasn't UTF-8");
+    redact_durations(&redact_timestamps(&strip_ansi(&text)))
+}
+
+/// Removes ANSI color and style sequences.
+pub fn strip_ansi(text: &str) -> String {
+    let regex = Regex::new(r"\x1b\[[0-9;]*[A-Za-z]").expect("TURRON BUG: oops, bad regex?");
+    regex.replace_all(text, "").into_owned()
+}
+
+/// Replaces RFC 3339 timestamps, like `2021-03-22T20:13:32.123Z`, with
+/// `[timestamp]`.
+pub fn redact_timestamps(text: &str) -> String {
+    let regex = Regex::new(r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:?\d{2})?")
+        .expect("TURRON BUG: oops, bad regex?");
+    regex.replace_all(text, "[timestamp]").into_owned()
+}
+
+/// Replaces relative times, like `5 years ago` or `in a day`, with `[time]`,
+/// and elapsed times, like `12.3ms` or `2s`, with `[duration]`.
+pub fn redact_durations(text: &str) -> String {
+    let span = r"(a few seconds|(a|an|\d+) (second|minute|hour|day|week|month|year)s?)";
+    let relative = Regex::new(&format!(r"\b(in {span}|{span} ago)\b", span = span))
+        .expect("TURRON BUG: oops, bad regex?");
+    let elapsed =
+        Regex::new(r"\b\d+(\.\d+)?(ns|µs|us|ms|s|m|h)\b").expect("TURRON BUG: oops, bad regex?");
+    let text = relative.replace_all(text, "[time]");
+    elapsed.replace_all(&text, "[duration]").into_owned()
+}
+
+/// Checks `actual` against the snapshot called `name` under
+/// `manifest_dir`. Use [`assert_snapshot!`] instead, which fills in the
+/// directory.
+pub fn assert_snapshot(manifest_dir: &str, name: &str, actual: &str) {
+    let dir = Path::new(manifest_dir).join("snapshots");
+    let path = dir.join(format!("{}.snap", name));
+    let pending = dir.join(format!("{}.snap.new", name));
+    // Git might have given the snapshot Windows line endings.
+    let expected = fs::read_to_string(&path)
+        .ok()
+        .map(|expected| expected.replace("\r\n", "\n"));
+    if expected.as_deref() == Some(actual) {
+        fs::remove_file(&pending).ok();
+        return;
+    }
+    let update = std::env::var(UPDATE_VAR).map_or(false, |var| var == "1");
+    let target = if update { &path } else { &pending };
+    fs::create_dir_all(&dir).expect("Failed to create snapshots directory");
+    fs::write(target, actual)
+        .unwrap_or_else(|err| panic!("Failed to write {}: {}", target.display(), err));
+    if update {
+        fs::remove_file(&pending).ok();
+        return;
+    }
+    match expected {
+        Some(expected) => panic!(
+            "Snapshot `{}` doesn't match. New output is in {}.\nRerun with {}=1 to accept it.\n\n{}",
+            name,
+            pending.display(),
+            UPDATE_VAR,
+            line_diff(&expected, actual)
+        ),
+        None => panic!(
+            "No snapshot named `{}` yet. Output is in {}.\nRerun with {}=1 to record it.\n\n{}",
+            name,
+            pending.display(),
+            UPDATE_VAR,
+            actual
+        ),
+    }
+}
+
+/// Lines that differ between `expected` and `actual`, `-`/`+` style. Lines
+/// are compared by position, which is plenty for telling what moved.
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(old), Some(new)) if old == new => out.push_str(&format!(" {}\n", old)),
+            (old, new) => {
+                if let Some(old) = old {
+                    out.push_str(&format!("-{}\n", old));
+                }
+                if let Some(new) = new {
+                    out.push_str(&format!("+{}\n", new));
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_colors() {
+        assert_eq!(
+            strip_ansi("\x1b[32mok\x1b[39m \x1b[1;4mbold\x1b[0m"),
+            "ok bold"
+        );
+    }
+
+    #[test]
+    fn redacts_timestamps_and_durations() {
+        assert_eq!(
+            redact_timestamps(
+                "published 2021-03-22T20:13:32.123Z, edited 2021-03-23 01:02:03+00:00"
+            ),
+            "published [timestamp], edited [timestamp]"
+        );
+        assert_eq!(
+            redact_durations("Published 5 years ago, next in a day. pong: 12.345ms, took 2s"),
+            "Published [time], next [time]. pong: [duration], took [duration]"
+        );
+        // Versions and counts aren't durations.
+        assert_eq!(
+            redact_durations("13.0.1 | deps: 2 | 1.6B"),
+            "13.0.1 | deps: 2 | 1.6B"
+        );
+    }
+
+    #[test]
+    fn renders_at_a_fixed_width() {
+        let out = render(|out: &mut Vec<u8>, width| {
+            use std::io::Write;
+            writeln!(out, "{} \x1b[33mwide\x1b[39m, 1.5s", width)
+        });
+        assert_eq!(out, "80 wide, [duration]\n");
+    }
+
+    #[test]
+    fn diffs_by_line() {
+        assert_eq!(line_diff("a\nb\nc\n", "a\nx\n"), " a\n-b\n+x\n-c\n");
+    }
+}
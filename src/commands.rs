@@ -0,0 +1,188 @@
+//! What `turron --list` shows, and suggestions for mistyped commands.
+
+use std::fmt;
+
+use turron_command::clap::{App, AppSettings};
+use turron_common::serde_json::{json, Value};
+
+/// What a command is for, so `--list` can group related ones together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    PackageInfo,
+    Publishing,
+    Project,
+    Maintenance,
+}
+
+impl Category {
+    /// Every category, in the order `--list` shows them.
+    pub const ALL: [Category; 4] = [
+        Category::PackageInfo,
+        Category::Publishing,
+        Category::Project,
+        Category::Maintenance,
+    ];
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Category::PackageInfo => write!(f, "Package info"),
+            Category::Publishing => write!(f, "Publishing"),
+            Category::Project => write!(f, "Project"),
+            Category::Maintenance => write!(f, "Maintenance"),
+        }
+    }
+}
+
+/// Which category each command is in. Every command in
+/// [`TurronCmd`](crate::TurronCmd) that isn't hidden needs to be listed
+/// here; the tests below will complain if one is missed.
+const CATEGORIES: &[(&str, Category)] = &[
+    ("add", Category::Project),
+    ("audit", Category::Project),
+    ("bisect", Category::Project),
+    ("cache", Category::Maintenance),
+    ("download", Category::PackageInfo),
+    ("explain", Category::Maintenance),
+    ("login", Category::Publishing),
+    ("logout", Category::Publishing),
+    ("outdated", Category::Project),
+    ("pack", Category::Publishing),
+    ("ping", Category::Maintenance),
+    ("publish", Category::Publishing),
+    ("relist", Category::Publishing),
+    ("remove", Category::Project),
+    ("search", Category::PackageInfo),
+    ("source", Category::Maintenance),
+    ("stats", Category::PackageInfo),
+    ("unlist", Category::Publishing),
+    ("unpublish-check", Category::Publishing),
+    ("verify", Category::Publishing),
+    ("view", Category::PackageInfo),
+];
+
+pub fn category(command: &str) -> Option<Category> {
+    CATEGORIES
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map(|(_, category)| *category)
+}
+
+/// `app`'s subcommands, minus hidden ones like `__complete`.
+fn visible<'a, 'help>(app: &'a App<'help>) -> impl Iterator<Item = &'a App<'help>> + 'a {
+    app.get_subcommands()
+        .filter(|command| !command.is_set(AppSettings::Hidden))
+}
+
+/// `app`'s commands under their categories, each with its description.
+pub fn list(app: &App) -> String {
+    let width = visible(app)
+        .map(|command| command.get_name().len())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for category in Category::ALL.iter() {
+        let commands = visible(app)
+            .filter(|command| self::category(command.get_name()) == Some(*category))
+            .collect::<Vec<_>>();
+        if commands.is_empty() {
+            continue;
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("{}:\n", category));
+        for command in commands {
+            out.push_str(&format!(
+                "    {:width$}  {}\n",
+                command.get_name(),
+                command.get_about().unwrap_or_default(),
+                width = width
+            ));
+        }
+    }
+    out
+}
+
+/// [`list`], for `--json`.
+pub fn list_json(app: &App) -> Value {
+    visible(app)
+        .map(|command| {
+            json!({
+                "name": command.get_name(),
+                "category": category(command.get_name()).map(|c| c.to_string()),
+                "about": command.get_about(),
+                "aliases": command.get_all_aliases().collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// The command whose name or alias is closest to `typo`, if any is close
+/// enough to be a plausible typo. Unlike clap's own suggestions, swapped
+/// letters only count as one mistake, so `pbulish` finds `publish`.
+pub fn suggest(app: &App, typo: &str) -> Option<String> {
+    let typo = typo.to_lowercase();
+    let threshold = std::cmp::max(2, typo.chars().count() / 3);
+    visible(app)
+        .flat_map(|command| {
+            std::iter::once(command.get_name())
+                .chain(command.get_all_aliases())
+                .map(move |name| (name, command.get_name()))
+        })
+        .map(|(name, command)| (strsim::damerau_levenshtein(&typo, name), command))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, command)| command.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use turron_command::clap::IntoApp;
+
+    use super::*;
+    use crate::Turron;
+
+    #[test]
+    fn every_command_is_categorized() {
+        let app = Turron::into_app();
+        for command in visible(&app) {
+            assert!(
+                category(command.get_name()).is_some(),
+                "`{}` needs a category in src/commands.rs",
+                command.get_name()
+            );
+            assert!(
+                command.get_about().map_or(false, |about| !about.is_empty()),
+                "`{}` needs an `about`",
+                command.get_name()
+            );
+        }
+        for (name, _) in CATEGORIES {
+            assert!(
+                visible(&app).any(|command| command.get_name() == *name),
+                "`{}` is categorized, but isn't a command",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn lists_commands_by_category() {
+        let listed = list(&Turron::into_app());
+        assert!(listed.starts_with("Package info:\n"));
+        assert!(listed.contains("\nProject:\n    add "));
+        assert!(!listed.contains("__complete"));
+    }
+
+    #[test]
+    fn suggests_commands_for_typos() {
+        let app = Turron::into_app();
+        assert_eq!(suggest(&app, "pbulish").as_deref(), Some("publish"));
+        assert_eq!(suggest(&app, "REMVOE").as_deref(), Some("remove"));
+        assert_eq!(suggest(&app, "rm-lsiting").as_deref(), Some("unlist"));
+        assert_eq!(suggest(&app, "xyzzy"), None);
+    }
+}
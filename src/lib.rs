@@ -1,26 +1,58 @@
+use std::ffi::OsString;
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use nuget_api::{
+    cache::HttpCache,
+    credentials::{self, Credentials, StaticCredentials},
+    schedule::Limits,
+    validate::Violation,
+};
+use tracing_subscriber::EnvFilter;
 use turron_command::TurronCommand;
 use turron_command::{
     async_trait::async_trait,
-    clap::{self, ArgMatches, Clap, FromArgMatches, IntoApp},
-    directories::ProjectDirs,
-    turron_config::{TurronConfig, TurronConfigLayer, TurronConfigOptions},
+    clap::{self, App, ArgMatches, ArgSettings, Clap, ErrorKind, FromArgMatches, IntoApp},
+    fmt::{self, Numbers},
+    owo_colors::OwoColorize,
+    render::sanitize,
+    turron_config::{
+        self, Aliases, SourceConfig, TurronConfig, TurronConfigLayer, TurronConfigOptions,
+    },
 };
 use turron_common::{
-    miette::{Context, Result},
+    dirs,
+    miette::{Context, IntoDiagnostic, Result},
+    serde_json,
+    surf::Url,
     tracing,
 };
 
+use turron_cmd_add::AddCmd;
+use turron_cmd_audit::AuditCmd;
+use turron_cmd_bisect::BisectCmd;
+use turron_cmd_cache::CacheCmd;
+use turron_cmd_complete::CompleteCmd;
+use turron_cmd_download::DownloadCmd;
+use turron_cmd_explain::ExplainCmd;
 use turron_cmd_login::LoginCmd;
+use turron_cmd_logout::LogoutCmd;
+use turron_cmd_outdated::OutdatedCmd;
 use turron_cmd_pack::PackCmd;
 use turron_cmd_ping::PingCmd;
 use turron_cmd_publish::PublishCmd;
 use turron_cmd_relist::RelistCmd;
+use turron_cmd_remove::RemoveCmd;
 use turron_cmd_search::SearchCmd;
+use turron_cmd_source::SourceCmd;
+use turron_cmd_stats::StatsCmd;
 use turron_cmd_unlist::UnlistCmd;
+use turron_cmd_unpublish_check::UnpublishCheckCmd;
+use turron_cmd_verify::VerifyCmd;
 use turron_cmd_view::ViewCmd;
 
+pub mod commands;
+
 #[derive(Debug, Clap)]
 #[clap(
     author = "Kat Marchán <kzm@zkat.tech>",
@@ -30,6 +62,7 @@ use turron_cmd_view::ViewCmd;
     setting = clap::AppSettings::DisableHelpSubcommand,
     setting = clap::AppSettings::DeriveDisplayOrder,
     setting = clap::AppSettings::InferSubcommands,
+    setting = clap::AppSettings::ArgRequiredElseHelp,
 )]
 pub struct Turron {
     #[clap(global = true, long = "root", about = "Package path to operate on.")]
@@ -38,12 +71,12 @@ pub struct Turron {
     config: Option<PathBuf>,
     #[clap(
         global = true,
-        about = "Log verbosity level (off, error, warn, info, debug, trace)",
+        about = "Log verbosity: a level (off, error, warn, info, debug, trace), or per-module levels like `nuget_api=trace,turron=warn`",
         long,
         short,
         default_value = "warn"
     )]
-    verbosity: tracing::Level,
+    verbosity: EnvFilter,
     #[clap(global = true, about = "Disable all output", long, short = 'q')]
     quiet: bool,
     #[clap(global = true, long, about = "Format output as JSON.")]
@@ -55,20 +88,67 @@ pub struct Turron {
         about = "NuGet API key for the targeted NuGet source."
     )]
     api_key: Option<String>,
+    #[clap(
+        global = true,
+        long,
+        about = "NuGet protocol to assume sources speak (auto, v3, v2), instead of detecting it. Only v3 is supported so far.",
+        possible_values = &["auto", "v3", "v2"]
+    )]
+    assume_source_version: Option<String>,
+    #[clap(
+        global = true,
+        long,
+        about = "Detect what the terminal can display again, instead of using cached results."
+    )]
+    refresh_capabilities: bool,
+    #[clap(
+        global = true,
+        long,
+        about = "Show numbers as plain integers, like 1234567 instead of 1.2M, and sizes in bytes."
+    )]
+    raw_numbers: bool,
+    #[clap(
+        global = true,
+        long,
+        about = "Write every HTTP request and response to this file, as HAR-like JSON. Credentials are redacted."
+    )]
+    capture_http: Option<PathBuf>,
+    #[clap(
+        global = true,
+        long,
+        about = "Check every response from sources against the NuGet protocol, and report anything that doesn't conform at the end. For testing sources."
+    )]
+    validate_responses: bool,
+    #[clap(
+        global = true,
+        long,
+        about = "Cache HTTP responses on disk, and revalidate them instead of downloading them again."
+    )]
+    cache: bool,
+    #[clap(
+        global = true,
+        long,
+        about = "Only use cached HTTP responses, and fail if something isn't cached. Implies --cache."
+    )]
+    offline: bool,
+    #[clap(long, about = "List every command, grouped by what it's for.")]
+    list: bool,
     #[clap(subcommand)]
-    subcommand: TurronCmd,
+    subcommand: Option<TurronCmd>,
 }
 
 impl Turron {
-    fn setup_logging(&self) -> Result<()> {
-        let mut collector = tracing_subscriber::fmt()
+    fn setup_logging(&mut self) -> Result<()> {
+        // Filters can't be cloned, and nothing else needs this one.
+        let verbosity = std::mem::replace(&mut self.verbosity, EnvFilter::new("off"));
+        let collector = tracing_subscriber::fmt()
             .with_writer(std::io::stderr)
-            .without_time();
-        if self.quiet {
-            collector = collector.with_max_level(tracing_subscriber::filter::LevelFilter::OFF);
-        } else {
-            collector = collector.with_max_level(self.verbosity);
-        }
+            .without_time()
+            .with_env_filter(if self.quiet {
+                EnvFilter::new("off")
+            } else {
+                verbosity
+            });
         // TODO: Switch to try_init (ugh, `Box<dyn Error>` issues)
         if self.json {
             collector.json().init();
@@ -81,32 +161,259 @@ impl Turron {
 
     pub async fn load() -> Result<()> {
         let start = std::time::Instant::now();
-        let clp = Turron::into_app();
-        let matches = clp.get_matches();
+        // Config has to be read before clap sees the arguments, since
+        // configured aliases change what they are.
+        let args = std::env::args_os().collect::<Vec<_>>();
+        let (config, root) = (early_value(&args, "config"), early_value(&args, "root"));
+        let cfg = load_config(config.clone(), root.clone())?;
+        let aliases = Aliases::from_config(&cfg);
+        let app = Turron::into_app();
+        let args = aliases.expand(args, &value_options(&app), |name| is_command(&app, name))?;
+        // Only built once per run, so leaking it to satisfy clap is fine.
+        let help: &'static str =
+            Box::leak(aliases.help(|name| is_command(&app, name)).into_boxed_str());
+        let matches = app
+            .after_help(help)
+            .try_get_matches_from(args)
+            .unwrap_or_else(|err| suggest_command(err).exit());
         let mut turron = Turron::from_arg_matches(&matches);
-        let cfg = if let Some(file) = &turron.config {
-            TurronConfigOptions::new()
-                .global_config_file(Some(file.clone()))
-                .load()?
+        if turron.list {
+            let app = Turron::into_app();
+            if turron.json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&commands::list_json(&app))
+                        .into_diagnostic()
+                        .context("Failed to serialize command list into JSON")?
+                );
+            } else {
+                print!("{}", commands::list(&app));
+            }
+            return Ok(());
+        }
+        if turron.subcommand.is_none() {
+            clap::Error::with_description(
+                "A command is required. Run `turron --list` to see them all.".into(),
+                ErrorKind::MissingSubcommand,
+            )
+            .exit();
+        }
+        // Aliases can pass `--config` or `--root` too.
+        let cfg = if turron.config != config || turron.root != root {
+            load_config(turron.config.clone(), turron.root.clone())?
         } else {
-            TurronConfigOptions::new()
-                .global_config_file(
-                    ProjectDirs::from("", "", "turron")
-                        .map(|d| d.config_dir().to_owned().join("turron.kdl")),
-                )
-                .pkg_root(turron.root.clone())
-                .load()?
+            cfg
         };
         turron.layer_config(&matches, &cfg)?;
         turron.setup_logging().context("Failed to set up logging")?;
-        turron.execute().await?;
+        nuget_api::schedule::configure(request_limits(&cfg)?);
+        register_credentials(&cfg)?;
+        let raw_numbers = turron.raw_numbers
+            || turron_config::config_value(&cfg, &["raw_numbers"])?.unwrap_or(false);
+        fmt::set_numbers(if raw_numbers {
+            Numbers::Raw
+        } else {
+            Numbers::Human
+        });
+        let capture = turron
+            .capture_http
+            .clone()
+            .map(|file| (file, nuget_api::capture::start()));
+        // Nothing would be reported anyway.
+        let validation = if turron.validate_responses && !turron.quiet {
+            Some(nuget_api::validate::start())
+        } else {
+            None
+        };
+        if turron.cache || turron.offline {
+            let dir = HttpCache::default_dir()?;
+            nuget_api::cache::enable(HttpCache::new(dir).offline(turron.offline));
+        }
+        let result = turron.execute().await;
+        // Failed runs are usually the ones worth capturing.
+        if let Some((file, log)) = capture {
+            log.write(&file)
+                .into_diagnostic()
+                .with_context(|| format!("Failed to write HTTP capture to {}", file.display()))?;
+        }
+        if let Some(log) = validation {
+            report_violations(&log.violations());
+        }
+        result?;
         tracing::info!("Ran in {}s", start.elapsed().as_millis() as f32 / 1000.0);
         Ok(())
     }
 }
 
+/// Reads `file`, or else the global config file and `root`'s.
+fn load_config(file: Option<PathBuf>, root: Option<PathBuf>) -> Result<TurronConfig> {
+    let options = if let Some(file) = file {
+        TurronConfigOptions::new().global_config_file(Some(file))
+    } else {
+        TurronConfigOptions::new()
+            // Nowhere to keep config just means there's no global
+            // config to read. Anything that needs to write it will
+            // complain on its own.
+            .global_config_file(dirs::config_file().ok())
+            .pkg_root(root)
+    };
+    Ok(options.load()?)
+}
+
+/// The value of the global `--<long>` option in `args`, found without clap,
+/// which can't parse them until aliases are expanded.
+fn early_value(args: &[OsString], long: &str) -> Option<PathBuf> {
+    let flag = format!("--{}", long);
+    let prefix = format!("--{}=", long);
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let text = arg.to_string_lossy();
+        if text == "--" {
+            break;
+        } else if text == flag {
+            return args.next().map(PathBuf::from);
+        } else if let Some(value) = text.strip_prefix(&prefix) {
+            return Some(PathBuf::from(value));
+        }
+    }
+    None
+}
+
+/// The `--long` and `-s` spellings of every top-level option that takes a
+/// value, so alias expansion can skip over their values.
+fn value_options(app: &App) -> Vec<String> {
+    app.get_arguments()
+        .filter(|arg| arg.is_set(ArgSettings::TakesValue))
+        .flat_map(|arg| {
+            arg.get_long()
+                .map(|long| format!("--{}", long))
+                .into_iter()
+                .chain(arg.get_short().map(|short| format!("-{}", short)))
+        })
+        .collect()
+}
+
+/// Whether `name` is a real command, or one of clap's aliases for one.
+/// Those always win over configured aliases.
+fn is_command(app: &App, name: &str) -> bool {
+    app.find_subcommand(name).is_some()
+}
+
+/// How many requests can be in flight at once, from the `max_requests` and
+/// `max_requests_per_host` config values.
+fn request_limits(cfg: &TurronConfig) -> Result<Limits> {
+    let defaults = Limits::default();
+    Ok(Limits {
+        max_requests: turron_config::config_value(cfg, &["max_requests"])?
+            .unwrap_or(defaults.max_requests),
+        max_requests_per_host: turron_config::config_value(cfg, &["max_requests_per_host"])?
+            .unwrap_or(defaults.max_requests_per_host),
+    })
+}
+
+/// Registers the `username`/`password` and `credential_provider` from each
+/// `source` block, in that order.
+fn register_credentials(cfg: &TurronConfig) -> Result<()> {
+    for source in cfg.configured_sources() {
+        let username = cfg.source_value::<String>(&source, "username")?;
+        let password = cfg.source_value::<String>(&source, "password")?;
+        let provider = cfg.source_value::<String>(&source, "credential_provider")?;
+        if username.is_none() && password.is_none() && provider.is_none() {
+            continue;
+        }
+        let url: Url = source
+            .parse()
+            .into_diagnostic()
+            .with_context(|| format!("Invalid URL in `source` block: {}", source))?;
+        match (username, password) {
+            (Some(username), Some(password)) => credentials::register(
+                &source,
+                Arc::new(StaticCredentials::new(
+                    &url,
+                    Credentials::new(username, password),
+                )),
+            ),
+            (None, None) => {}
+            _ => tracing::warn!(
+                "The `source` block for {} needs both a username and a password. Ignoring them.",
+                source
+            ),
+        }
+        if let Some(provider) = provider {
+            register_plugin(&source, url, provider);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "credential-plugins")]
+fn register_plugin(source: &str, url: Url, path: String) {
+    use turron_command::dialoguer::console;
+    credentials::register(
+        source,
+        Arc::new(credentials::PluginProvider::new(path, url).interactive(console::user_attended())),
+    );
+}
+
+#[cfg(not(feature = "credential-plugins"))]
+fn register_plugin(source: &str, _url: Url, _path: String) {
+    tracing::warn!(
+        "Ignoring the credential_provider for {}: this turron was built without credential plugin support.",
+        source
+    );
+}
+
 #[derive(Debug, Clap)]
 pub enum TurronCmd {
+    #[clap(
+        about = "Add a package reference to a project",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Add(AddCmd),
+    #[clap(
+        about = "Check dependencies for known vulnerabilities",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Audit(AuditCmd),
+    #[clap(
+        about = "Find the first version of a package that breaks a test",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Bisect(BisectCmd),
+    #[clap(
+        about = "Manage turron's HTTP cache",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Cache(CacheCmd),
+    #[clap(
+        name = "__complete",
+        about = "Print dynamic completion candidates for shell completion scripts",
+        setting = clap::AppSettings::Hidden,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+    )]
+    Complete(CompleteCmd),
+    #[clap(
+        about = "Download a package",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Download(DownloadCmd),
+    #[clap(
+        about = "Explain a diagnostic code in detail",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Explain(ExplainCmd),
     #[clap(
         about = "Log in to nuget.org",
         setting = clap::AppSettings::ColoredHelp,
@@ -114,6 +421,20 @@ pub enum TurronCmd {
         setting = clap::AppSettings::DeriveDisplayOrder,
     )]
     Login(LoginCmd),
+    #[clap(
+        about = "Forget the API key saved for a source",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Logout(LogoutCmd),
+    #[clap(
+        about = "Check project references for newer versions",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Outdated(OutdatedCmd),
     #[clap(
         about = "Pack a project",
         setting = clap::AppSettings::ColoredHelp,
@@ -142,6 +463,13 @@ pub enum TurronCmd {
         setting = clap::AppSettings::DeriveDisplayOrder,
     )]
     Relist(RelistCmd),
+    #[clap(
+        about = "Remove a package reference from a project",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Remove(RemoveCmd),
     #[clap(
         about = "Search for packages",
         setting = clap::AppSettings::ColoredHelp,
@@ -149,13 +477,42 @@ pub enum TurronCmd {
         setting = clap::AppSettings::DeriveDisplayOrder,
     )]
     Search(SearchCmd),
+    #[clap(
+        about = "Check and manage sources",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Source(SourceCmd),
+    #[clap(
+        about = "Show download and release stats for a package, or a whole family of them",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Stats(StatsCmd),
     #[clap(
         about = "Unlist a package version",
         setting = clap::AppSettings::ColoredHelp,
         setting = clap::AppSettings::DisableHelpSubcommand,
+        visible_alias = "rm-listing",
         setting = clap::AppSettings::DeriveDisplayOrder,
     )]
     Unlist(UnlistCmd),
+    #[clap(
+        about = "Report how risky unlisting a package version would be",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    UnpublishCheck(UnpublishCheckCmd),
+    #[clap(
+        about = "Check a .nupkg for problems before publishing it",
+        setting = clap::AppSettings::ColoredHelp,
+        setting = clap::AppSettings::DisableHelpSubcommand,
+        setting = clap::AppSettings::DeriveDisplayOrder,
+    )]
+    Verify(VerifyCmd),
     #[clap(
         about = "View package info",
         setting = clap::AppSettings::ColoredHelp,
@@ -170,14 +527,29 @@ impl TurronCommand for Turron {
     async fn execute(self) -> Result<()> {
         tracing::debug!("Running command: {:#?}", self.subcommand);
         match self.subcommand {
-            TurronCmd::Login(login) => login.execute().await,
-            TurronCmd::Pack(pack) => pack.execute().await,
-            TurronCmd::Ping(ping) => ping.execute().await,
-            TurronCmd::Publish(publish) => publish.execute().await,
-            TurronCmd::Relist(relist) => relist.execute().await,
-            TurronCmd::Search(search) => search.execute().await,
-            TurronCmd::Unlist(unlist) => unlist.execute().await,
-            TurronCmd::View(view) => view.execute().await,
+            Some(TurronCmd::Add(add)) => add.execute().await,
+            Some(TurronCmd::Audit(audit)) => audit.execute().await,
+            Some(TurronCmd::Bisect(bisect)) => bisect.execute().await,
+            Some(TurronCmd::Cache(cache)) => cache.execute().await,
+            Some(TurronCmd::Complete(complete)) => complete.execute().await,
+            Some(TurronCmd::Download(download)) => download.execute().await,
+            Some(TurronCmd::Explain(explain)) => explain.execute().await,
+            Some(TurronCmd::Login(login)) => login.execute().await,
+            Some(TurronCmd::Logout(logout)) => logout.execute().await,
+            Some(TurronCmd::Outdated(outdated)) => outdated.execute().await,
+            Some(TurronCmd::Pack(pack)) => pack.execute().await,
+            Some(TurronCmd::Ping(ping)) => ping.execute().await,
+            Some(TurronCmd::Publish(publish)) => publish.execute().await,
+            Some(TurronCmd::Relist(relist)) => relist.execute().await,
+            Some(TurronCmd::Remove(remove)) => remove.execute().await,
+            Some(TurronCmd::Search(search)) => search.execute().await,
+            Some(TurronCmd::Source(source)) => source.execute().await,
+            Some(TurronCmd::Stats(stats)) => stats.execute().await,
+            Some(TurronCmd::Unlist(unlist)) => unlist.execute().await,
+            Some(TurronCmd::UnpublishCheck(check)) => check.execute().await,
+            Some(TurronCmd::Verify(verify)) => verify.execute().await,
+            Some(TurronCmd::View(view)) => view.execute().await,
+            None => Ok(()),
         }
     }
 }
@@ -185,30 +557,111 @@ impl TurronCommand for Turron {
 impl TurronConfigLayer for Turron {
     fn layer_config(&mut self, args: &ArgMatches, conf: &TurronConfig) -> Result<()> {
         match self.subcommand {
-            TurronCmd::Login(ref mut login) => {
+            Some(TurronCmd::Add(ref mut add)) => {
+                add.layer_config(args.subcommand_matches("add").unwrap(), conf)
+            }
+            Some(TurronCmd::Audit(ref mut audit)) => {
+                audit.layer_config(args.subcommand_matches("audit").unwrap(), conf)
+            }
+            Some(TurronCmd::Bisect(ref mut bisect)) => {
+                bisect.layer_config(args.subcommand_matches("bisect").unwrap(), conf)
+            }
+            Some(TurronCmd::Cache(ref mut cache)) => {
+                cache.layer_config(args.subcommand_matches("cache").unwrap(), conf)
+            }
+            Some(TurronCmd::Complete(ref mut complete)) => {
+                complete.layer_config(args.subcommand_matches("__complete").unwrap(), conf)
+            }
+            Some(TurronCmd::Download(ref mut download)) => {
+                download.layer_config(args.subcommand_matches("download").unwrap(), conf)
+            }
+            Some(TurronCmd::Explain(ref mut explain)) => {
+                explain.layer_config(args.subcommand_matches("explain").unwrap(), conf)
+            }
+            Some(TurronCmd::Login(ref mut login)) => {
                 login.layer_config(args.subcommand_matches("login").unwrap(), conf)
             }
-            TurronCmd::Pack(ref mut pack) => {
+            Some(TurronCmd::Logout(ref mut logout)) => {
+                logout.layer_config(args.subcommand_matches("logout").unwrap(), conf)
+            }
+            Some(TurronCmd::Outdated(ref mut outdated)) => {
+                outdated.layer_config(args.subcommand_matches("outdated").unwrap(), conf)
+            }
+            Some(TurronCmd::Pack(ref mut pack)) => {
                 pack.layer_config(args.subcommand_matches("pack").unwrap(), conf)
             }
-            TurronCmd::Ping(ref mut ping) => {
+            Some(TurronCmd::Ping(ref mut ping)) => {
                 ping.layer_config(args.subcommand_matches("ping").unwrap(), conf)
             }
-            TurronCmd::Publish(ref mut publish) => {
+            Some(TurronCmd::Publish(ref mut publish)) => {
                 publish.layer_config(args.subcommand_matches("publish").unwrap(), conf)
             }
-            TurronCmd::Relist(ref mut relist) => {
+            Some(TurronCmd::Relist(ref mut relist)) => {
                 relist.layer_config(args.subcommand_matches("relist").unwrap(), conf)
             }
-            TurronCmd::Search(ref mut search) => {
+            Some(TurronCmd::Remove(ref mut remove)) => {
+                remove.layer_config(args.subcommand_matches("remove").unwrap(), conf)
+            }
+            Some(TurronCmd::Search(ref mut search)) => {
                 search.layer_config(args.subcommand_matches("search").unwrap(), conf)
             }
-            TurronCmd::Unlist(ref mut unlist) => {
+            Some(TurronCmd::Source(ref mut source)) => {
+                source.layer_config(args.subcommand_matches("source").unwrap(), conf)
+            }
+            Some(TurronCmd::Stats(ref mut stats)) => {
+                stats.layer_config(args.subcommand_matches("stats").unwrap(), conf)
+            }
+            Some(TurronCmd::Unlist(ref mut unlist)) => {
                 unlist.layer_config(args.subcommand_matches("unlist").unwrap(), conf)
             }
-            TurronCmd::View(ref mut view) => {
+            Some(TurronCmd::UnpublishCheck(ref mut check)) => {
+                check.layer_config(args.subcommand_matches("unpublish-check").unwrap(), conf)
+            }
+            Some(TurronCmd::Verify(ref mut verify)) => {
+                verify.layer_config(args.subcommand_matches("verify").unwrap(), conf)
+            }
+            Some(TurronCmd::View(ref mut view)) => {
                 view.layer_config(args.subcommand_matches("view").unwrap(), conf)
             }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Prints what `--validate-responses` found. This goes to stderr, after
+/// everything else, so it doesn't get mixed up with a command's output.
+fn report_violations(violations: &[Violation]) {
+    if violations.is_empty() {
+        eprintln!(
+            "{} Every response followed the NuGet protocol.",
+            "validate:".green()
+        );
+        return;
+    }
+    eprintln!(
+        "{} Found {} violations of the NuGet protocol:",
+        "validate:".yellow(),
+        violations.len()
+    );
+    for violation in violations {
+        eprintln!("    {}", sanitize(&violation.to_string()));
+    }
+}
+
+/// Replaces clap's error for a mistyped command with one that suggests
+/// [`commands::suggest`]'s pick instead. Other errors, like `--help`, pass
+/// through untouched.
+fn suggest_command(err: clap::Error) -> clap::Error {
+    match err.kind {
+        ErrorKind::InvalidSubcommand | ErrorKind::UnrecognizedSubcommand => {
+            let typo = err.info.first().cloned().unwrap_or_default();
+            let mut message = format!("The command '{}' wasn't recognized.", typo);
+            if let Some(command) = commands::suggest(&Turron::into_app(), &typo) {
+                message.push_str(&format!(" Did you mean '{}'?", command));
+            }
+            message.push_str("\n\nRun `turron --list` to see every command.");
+            clap::Error::with_description(message, err.kind)
         }
+        _ => err,
     }
 }